@@ -35,7 +35,7 @@
 //! ```
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::atomic::Ordering;
 use std::sync::{mpsc, Arc, Condvar, Mutex, RwLock};
@@ -44,7 +44,10 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
 use clap::Arg;
+use compute_tools::lfc_prewarm::launch_lfc_prewarm;
+use compute_tools::logical_slots::launch_logical_slots_persister;
 use compute_tools::lsn_lease::launch_lsn_lease_bg_task_for_static;
+use compute_tools::pageserver_failover::launch_pageserver_failover;
 use signal_hook::consts::{SIGQUIT, SIGTERM};
 use signal_hook::{consts::SIGINT, iterator::Signals};
 use tracing::{error, info, warn};
@@ -57,12 +60,13 @@
     forward_termination_signal, ComputeNode, ComputeState, ParsedSpec, PG_PID,
 };
 use compute_tools::configurator::launch_configurator;
-use compute_tools::extension_server::get_pg_version;
+use compute_tools::extension_server::{get_pg_version, ExtensionIntegrity};
 use compute_tools::http::api::launch_http_server;
 use compute_tools::logger::*;
 use compute_tools::monitor::launch_monitor;
 use compute_tools::params::*;
 use compute_tools::spec::*;
+use compute_tools::sql_exporter::{launch_sql_exporter, SqlExporterConfig};
 use compute_tools::swap::resize_swap;
 use rlimit::{setrlimit, Resource};
 
@@ -139,6 +143,18 @@ fn process_cli(matches: &clap::ArgMatches) -> Result<ProcessCliResult> {
             }
         });
 
+    let ext_public_key_path = matches.get_one::<String>("remote-ext-public-key-path");
+    let ext_cache_dir = matches.get_one::<String>("remote-ext-cache-dir");
+
+    let sql_exporter_config = matches
+        .get_one::<String>("sql-exporter-config")
+        .map(|path| {
+            SqlExporterConfig::load(Path::new(path))
+                .with_context(|| format!("cannot load SQL exporter config {path}"))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
     let http_port = *matches
         .get_one::<u16>("http-port")
         .expect("http-port is required");
@@ -157,6 +173,9 @@ fn process_cli(matches: &clap::ArgMatches) -> Result<ProcessCliResult> {
         pgdata,
         pgbin,
         ext_remote_storage,
+        ext_public_key_path,
+        ext_cache_dir,
+        sql_exporter_config,
         http_port,
         spec_json,
         spec_path,
@@ -169,6 +188,9 @@ struct ProcessCliResult<'clap> {
     pgdata: &'clap str,
     pgbin: &'clap str,
     ext_remote_storage: Option<&'clap str>,
+    ext_public_key_path: Option<&'clap String>,
+    ext_cache_dir: Option<&'clap String>,
+    sql_exporter_config: SqlExporterConfig,
     http_port: u16,
     spec_json: Option<&'clap String>,
     spec_path: Option<&'clap String>,
@@ -238,6 +260,7 @@ fn try_spec_from_cli(
 
     let spec;
     let mut live_config_allowed = false;
+    let mut control_plane = None;
     match spec_json {
         // First, try to get cluster spec from the cli argument
         Some(json) => {
@@ -254,6 +277,7 @@ fn try_spec_from_cli(
             } else if let Some(id) = compute_id {
                 if let Some(cp_base) = control_plane_uri {
                     live_config_allowed = true;
+                    control_plane = Some((cp_base.clone(), id.clone()));
                     spec = match get_spec_from_control_plane(cp_base, id) {
                         Ok(s) => s,
                         Err(e) => {
@@ -276,6 +300,7 @@ fn try_spec_from_cli(
     Ok(CliSpecParams {
         spec,
         live_config_allowed,
+        control_plane,
     })
 }
 
@@ -283,6 +308,8 @@ struct CliSpecParams {
     /// If a spec was provided via CLI or file, the [`ComputeSpec`]
     spec: Option<ComputeSpec>,
     live_config_allowed: bool,
+    /// The control plane URI and compute ID, if the spec comes from the control plane
+    control_plane: Option<(String, String)>,
 }
 
 fn wait_spec(
@@ -292,6 +319,9 @@ fn wait_spec(
         pgdata,
         pgbin,
         ext_remote_storage,
+        ext_public_key_path,
+        ext_cache_dir,
+        sql_exporter_config,
         resize_swap_on_bind,
         http_port,
         ..
@@ -299,6 +329,7 @@ fn wait_spec(
     CliSpecParams {
         spec,
         live_config_allowed,
+        control_plane,
     }: CliSpecParams,
 ) -> Result<WaitSpecResult> {
     let mut new_state = ComputeState::new();
@@ -312,16 +343,25 @@ fn wait_spec(
     } else {
         spec_set = false;
     }
+    let ext_integrity = ext_public_key_path
+        .map(|key_path| {
+            ExtensionIntegrity::new(Path::new(key_path), ext_cache_dir.map(PathBuf::from))
+                .context("cannot load extension public keys")
+        })
+        .transpose()?;
     let compute_node = ComputeNode {
         connstr: Url::parse(connstr).context("cannot parse connstr as a URL")?,
         pgdata: pgdata.to_string(),
         pgbin: pgbin.to_string(),
         pgversion: get_pg_version(pgbin),
         live_config_allowed,
+        control_plane,
         state: Mutex::new(new_state),
         state_changed: Condvar::new(),
         ext_remote_storage: ext_remote_storage.map(|s| s.to_string()),
+        ext_integrity,
         ext_download_progress: RwLock::new(HashMap::new()),
+        sql_exporter: Mutex::new(None),
         build_tag,
     };
     let compute = Arc::new(compute_node);
@@ -373,6 +413,7 @@ fn wait_spec(
         compute,
         http_port,
         resize_swap_on_bind,
+        sql_exporter_config,
     })
 }
 
@@ -381,6 +422,7 @@ struct WaitSpecResult {
     // passed through from ProcessCliResult
     http_port: u16,
     resize_swap_on_bind: bool,
+    sql_exporter_config: SqlExporterConfig,
 }
 
 fn start_postgres(
@@ -390,6 +432,7 @@ fn start_postgres(
         compute,
         http_port,
         resize_swap_on_bind,
+        sql_exporter_config,
     }: WaitSpecResult,
 ) -> Result<(Option<PostgresHandle>, StartPostgresResult)> {
     // We got all we need, update the state.
@@ -408,6 +451,10 @@ fn start_postgres(
     // Launch remaining service threads
     let _monitor_handle = launch_monitor(&compute);
     let _configurator_handle = launch_configurator(&compute);
+    launch_logical_slots_persister(&compute);
+    launch_lfc_prewarm(&compute);
+    launch_pageserver_failover(&compute);
+    let _sql_exporter_handle = launch_sql_exporter(&compute, sql_exporter_config.metric_queries);
 
     let mut prestartup_failed = false;
     let mut delay_exit = false;
@@ -479,6 +526,13 @@ fn start_postgres(
                 .expect("--vm-monitor-addr should always be set because it has a default arg");
             let file_cache_connstr = matches.get_one::<String>("filecache-connstr");
             let cgroup = matches.get_one::<String>("cgroup");
+            let memory_scaling = compute
+                .state
+                .lock()
+                .unwrap()
+                .pspec
+                .as_ref()
+                .and_then(|pspec| pspec.spec.memory_scaling.clone());
 
             // Only make a runtime if we need to.
             // Note: it seems like you can make a runtime in an inner scope and
@@ -505,6 +559,7 @@ fn start_postgres(
                         cgroup: cgroup.cloned(),
                         pgconnstr: file_cache_connstr.cloned(),
                         addr: vm_monitor_addr.clone(),
+                        memory_scaling,
                     })),
                     token.clone(),
                 ))
@@ -722,6 +777,24 @@ fn cli() -> clap::Command {
                 .long("remote-ext-config")
                 .value_name("REMOTE_EXT_CONFIG"),
         )
+        .arg(
+            Arg::new("remote-ext-public-key-path")
+                .long("remote-ext-public-key-path")
+                .value_name("REMOTE_EXT_PUBLIC_KEY_PATH")
+                .help("Ed25519 public key, or directory of keys, that extension indexes must be signed with"),
+        )
+        .arg(
+            Arg::new("remote-ext-cache-dir")
+                .long("remote-ext-cache-dir")
+                .value_name("REMOTE_EXT_CACHE_DIR")
+                .help("Where to keep verified extension archives across restarts"),
+        )
+        .arg(
+            Arg::new("sql-exporter-config")
+                .long("sql-exporter-config")
+                .value_name("SQL_EXPORTER_CONFIG")
+                .help("JSON file with metric queries to serve on /metrics, in addition to the spec's metric_queries"),
+        )
         // TODO(fprasx): we currently have default arguments because the cloud PR
         // to pass them in hasn't been merged yet. We should get rid of them once
         // the PR is merged.
@@ -1,10 +1,17 @@
+use anyhow::anyhow;
 use compute_api::{
-    responses::CatalogObjects,
-    spec::{Database, Role},
+    responses::{CatalogObjects, DatabaseDrift, SpecDriftResponse},
+    spec::{Database, DefaultPrivilegesTarget, GrantTarget, Role},
 };
 use futures::Stream;
 use postgres::{Client, NoTls};
-use std::{path::Path, process::Stdio, result::Result, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    process::Stdio,
+    result::Result,
+    sync::Arc,
+};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::Command,
@@ -16,6 +23,10 @@
 
 use crate::{
     compute::ComputeNode,
+    db_objects::{
+        default_privileges, grant_privileges, plan_objects, plan_privileges, ExistingExtension,
+        ExistingObjects,
+    },
     pg_helpers::{get_existing_dbs, get_existing_roles},
 };
 
@@ -35,6 +46,217 @@ pub async fn get_dbs_and_roles(compute: &Arc<ComputeNode>) -> anyhow::Result<Cat
     .await?
 }
 
+/// Read the schemas and extensions of `db`, which `client` is connected to, and which of the
+/// privileges that its spec declares are already held.
+pub fn get_existing_objects(client: &mut Client, db: &Database) -> anyhow::Result<ExistingObjects> {
+    let objects = &db.objects;
+
+    let schemas: HashMap<_, _> = client
+        .query(
+            "SELECT nspname, pg_get_userbyid(nspowner) AS owner FROM pg_catalog.pg_namespace",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get("nspname"), row.get("owner")))
+        .collect();
+
+    let extensions: HashMap<_, _> = client
+        .query(
+            "SELECT e.extname, e.extversion, n.nspname
+            FROM pg_catalog.pg_extension e
+            JOIN pg_catalog.pg_namespace n ON n.oid = e.extnamespace",
+            &[],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get("extname"),
+                ExistingExtension {
+                    version: row.get("extversion"),
+                    schema: row.get("nspname"),
+                },
+            )
+        })
+        .collect();
+
+    let mut grants = Vec::new();
+    for grant in &objects.grants {
+        let mut held = HashSet::new();
+        if role_exists(client, &grant.role)? {
+            for privilege in grant_privileges(grant)? {
+                let check = if grant.with_grant_option {
+                    format!("{privilege} WITH GRANT OPTION")
+                } else {
+                    privilege.to_string()
+                };
+                // The checks on all objects in a schema hold vacuously if the schema is empty or
+                // does not exist.
+                let row = match &grant.on {
+                    GrantTarget::Database => client.query_one(
+                        "SELECT has_database_privilege($1::name, current_database()::text, $2::text)",
+                        &[&grant.role, &check],
+                    )?,
+                    GrantTarget::Schema { schema } => client.query_one(
+                        "SELECT coalesce((
+                            SELECT has_schema_privilege($1::name, n.oid, $3::text)
+                            FROM pg_catalog.pg_namespace n
+                            WHERE n.nspname = $2::name
+                        ), false)",
+                        &[&grant.role, schema, &check],
+                    )?,
+                    GrantTarget::AllTablesInSchema { schema } => client.query_one(
+                        "SELECT NOT EXISTS (
+                            SELECT 1
+                            FROM pg_catalog.pg_class c
+                            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+                            WHERE n.nspname = $2::name
+                                AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+                                AND NOT has_table_privilege($1::name, c.oid, $3::text)
+                        )",
+                        &[&grant.role, schema, &check],
+                    )?,
+                    GrantTarget::AllSequencesInSchema { schema } => client.query_one(
+                        "SELECT NOT EXISTS (
+                            SELECT 1
+                            FROM pg_catalog.pg_class c
+                            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+                            WHERE n.nspname = $2::name
+                                AND c.relkind = 'S'
+                                AND NOT has_sequence_privilege($1::name, c.oid, $3::text)
+                        )",
+                        &[&grant.role, schema, &check],
+                    )?,
+                    GrantTarget::AllFunctionsInSchema { schema } => client.query_one(
+                        "SELECT NOT EXISTS (
+                            SELECT 1
+                            FROM pg_catalog.pg_proc p
+                            JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+                            WHERE n.nspname = $2::name
+                                AND p.prokind <> 'p'
+                                AND NOT has_function_privilege($1::name, p.oid, $3::text)
+                        )",
+                        &[&grant.role, schema, &check],
+                    )?,
+                };
+                if row.get::<_, bool>(0) {
+                    held.insert(privilege);
+                }
+            }
+        }
+        grants.push(held);
+    }
+
+    let mut default_privileges_held = Vec::new();
+    for defaults in &objects.default_privileges {
+        let privileges = default_privileges(defaults)?;
+        let objtype = match defaults.on {
+            DefaultPrivilegesTarget::Tables => "r",
+            DefaultPrivilegesTarget::Sequences => "S",
+            DefaultPrivilegesTarget::Functions => "f",
+            DefaultPrivilegesTarget::Types => "T",
+            DefaultPrivilegesTarget::Schemas => "n",
+        };
+        let held: HashSet<_> = client
+            .query(
+                "SELECT a.privilege_type, a.is_grantable
+                FROM pg_catalog.pg_default_acl d
+                LEFT JOIN pg_catalog.pg_namespace n ON n.oid = d.defaclnamespace,
+                LATERAL aclexplode(d.defaclacl) a
+                WHERE d.defaclrole = (SELECT oid FROM pg_catalog.pg_roles WHERE rolname = $1::name)
+                    AND coalesce(n.nspname::text, '') = $2::text
+                    AND d.defaclobjtype::text = $3::text
+                    AND a.grantee = (SELECT oid FROM pg_catalog.pg_roles WHERE rolname = $4::name)",
+                &[
+                    defaults.for_role.as_ref().unwrap_or(&db.owner),
+                    &defaults.schema.as_deref().unwrap_or(""),
+                    &objtype,
+                    &defaults.role,
+                ],
+            )?
+            .iter()
+            .filter(|row| !defaults.with_grant_option || row.get::<_, bool>("is_grantable"))
+            .filter_map(|row| {
+                let privilege_type: String = row.get("privilege_type");
+                privileges.iter().find(|p| **p == privilege_type).copied()
+            })
+            .collect();
+        default_privileges_held.push(held);
+    }
+
+    Ok(ExistingObjects {
+        schemas,
+        extensions,
+        grants,
+        default_privileges: default_privileges_held,
+    })
+}
+
+fn role_exists(client: &mut Client, role: &str) -> anyhow::Result<bool> {
+    Ok(!client
+        .query(
+            "SELECT 1 FROM pg_catalog.pg_roles WHERE rolname = $1::name",
+            &[&role],
+        )?
+        .is_empty())
+}
+
+/// Compare the catalog of every database with the schemas, extensions and privileges that the
+/// current spec declares for it.
+pub async fn get_spec_drift(compute: &Arc<ComputeNode>) -> anyhow::Result<SpecDriftResponse> {
+    let spec = compute
+        .state
+        .lock()
+        .unwrap()
+        .pspec
+        .as_ref()
+        .map(|pspec| pspec.spec.clone())
+        .ok_or_else(|| anyhow!("compute has no spec"))?;
+    let connstr = compute.connstr.clone();
+
+    task::spawn_blocking(move || {
+        let mut client = Client::connect(connstr.as_str(), NoTls)?;
+        let existing_dbs = get_existing_dbs(&mut client)?;
+
+        let mut databases = Vec::new();
+        for db in spec
+            .cluster
+            .databases
+            .iter()
+            .filter(|db| !db.objects.is_empty())
+        {
+            let connectable = existing_dbs
+                .get(&db.name)
+                .is_some_and(|pg_db| !pg_db.restrict_conn && !pg_db.invalid);
+            if !connectable {
+                databases.push(DatabaseDrift {
+                    name: db.name.clone(),
+                    missing: true,
+                    statements: Vec::new(),
+                });
+                continue;
+            }
+
+            let mut db_connstr = connstr.clone();
+            db_connstr.set_path(&db.name);
+            let mut db_client = Client::connect(db_connstr.as_str(), NoTls)?;
+            let existing = get_existing_objects(&mut db_client, db)?;
+
+            let mut statements = plan_objects(db, &existing);
+            statements.extend(plan_privileges(db, &existing)?);
+            if !statements.is_empty() {
+                databases.push(DatabaseDrift {
+                    name: db.name.clone(),
+                    missing: false,
+                    statements,
+                });
+            }
+        }
+
+        Ok(SpecDriftResponse { databases })
+    })
+    .await?
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SchemaDumpError {
     #[error("Database does not exist.")]
@@ -8,7 +8,7 @@
 use std::str::FromStr;
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
-use std::sync::{Condvar, Mutex, RwLock};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
 use std::time::Instant;
 
@@ -24,7 +24,9 @@
 use utils::id::{TenantId, TimelineId};
 use utils::lsn::Lsn;
 
-use compute_api::responses::{ComputeMetrics, ComputeStatus};
+use compute_api::responses::{
+    ComputeMetrics, ComputeStatus, LfcPrewarmStatus, LogicalSlotStatus, PromotionStatus,
+};
 use compute_api::spec::{ComputeFeature, ComputeMode, ComputeSpec};
 use utils::measured_stream::MeasuredReader;
 
@@ -36,8 +38,8 @@
 use crate::logger::inlinify;
 use crate::pg_helpers::*;
 use crate::spec::*;
-use crate::sync_sk::{check_if_synced, ping_safekeeper};
-use crate::{config, extension_server};
+use crate::sync_sk::{check_if_synced, ping_safekeeper, TimelineStatusResponse};
+use crate::{config, extension_server, logical_slots, sql_exporter, suspend_snapshot, tenant_wake};
 
 pub static SYNC_SAFEKEEPERS_PID: AtomicU32 = AtomicU32::new(0);
 pub static PG_PID: AtomicU32 = AtomicU32::new(0);
@@ -62,6 +64,9 @@ pub struct ComputeNode {
     /// - we push spec and it does configuration
     /// - but then it is restarted without any spec again
     pub live_config_allowed: bool,
+    /// The control plane URI and the ID of this compute, if the spec comes from the control
+    /// plane, so that it can be fetched again
+    pub control_plane: Option<(String, String)>,
     /// Volatile part of the `ComputeNode`, which should be used under `Mutex`.
     /// To allow HTTP API server to serving status requests, while configuration
     /// is in progress, lock should be held only for short periods of time to do
@@ -71,9 +76,13 @@ pub struct ComputeNode {
     pub state_changed: Condvar,
     /// the address of extension storage proxy gateway
     pub ext_remote_storage: Option<String>,
+    /// integrity checks of downloaded extensions, if configured
+    pub ext_integrity: Option<extension_server::ExtensionIntegrity>,
     // key: ext_archive_name, value: started download time, download_completed?
     pub ext_download_progress: RwLock<HashMap<String, (DateTime<Utc>, bool)>>,
     pub build_tag: String,
+    /// Runs the metric queries while the compute is running, see [`crate::sql_exporter`]
+    pub sql_exporter: Mutex<Option<Arc<sql_exporter::SqlExporter>>>,
 }
 
 // store some metrics about download size that might impact startup time
@@ -94,6 +103,12 @@ pub struct ComputeState {
     pub error: Option<String>,
     pub pspec: Option<ParsedSpec>,
     pub metrics: ComputeMetrics,
+    /// Logical replication slots, as checked on startup. See [`crate::logical_slots`].
+    pub logical_slots: Vec<LogicalSlotStatus>,
+    /// Progress of prewarming the local file cache. See [`crate::lfc_prewarm`].
+    pub lfc_prewarm: Option<LfcPrewarmStatus>,
+    /// Progress of promoting a replica to a primary. See [`crate::promote`].
+    pub promotion: Option<PromotionStatus>,
 }
 
 impl ComputeState {
@@ -105,6 +120,9 @@ pub fn new() -> Self {
             error: None,
             pspec: None,
             metrics: ComputeMetrics::default(),
+            logical_slots: Vec::new(),
+            lfc_prewarm: None,
+            promotion: None,
         }
     }
 }
@@ -435,75 +453,16 @@ pub async fn check_safekeepers_synced_async(
         &self,
         compute_state: &ComputeState,
     ) -> Result<Option<Lsn>> {
-        // Construct a connection config for each safekeeper
-        let pspec: ParsedSpec = compute_state
-            .pspec
-            .as_ref()
-            .expect("spec must be set")
-            .clone();
-        let sk_connstrs: Vec<String> = pspec.safekeeper_connstrings.clone();
-        let sk_configs = sk_connstrs.into_iter().map(|connstr| {
-            // Format connstr
-            let id = connstr.clone();
-            let connstr = format!("postgresql://no_user@{}", connstr);
-            let options = format!(
-                "-c timeline_id={} tenant_id={}",
-                pspec.timeline_id, pspec.tenant_id
-            );
-
-            // Construct client
-            let mut config = tokio_postgres::Config::from_str(&connstr).unwrap();
-            config.options(&options);
-            if let Some(storage_auth_token) = pspec.storage_auth_token.clone() {
-                config.password(storage_auth_token);
-            }
-
-            (id, config)
-        });
-
-        // Create task set to query all safekeepers
-        let mut tasks = FuturesUnordered::new();
-        let quorum = sk_configs.len() / 2 + 1;
-        for (id, config) in sk_configs {
-            let timeout = tokio::time::Duration::from_millis(100);
-            let task = tokio::time::timeout(timeout, ping_safekeeper(id, config));
-            tasks.push(tokio::spawn(task));
-        }
-
-        // Get a quorum of responses or errors
-        let mut responses = Vec::new();
-        let mut join_errors = Vec::new();
-        let mut task_errors = Vec::new();
-        let mut timeout_errors = Vec::new();
-        while let Some(response) = tasks.next().await {
-            match response {
-                Ok(Ok(Ok(r))) => responses.push(r),
-                Ok(Ok(Err(e))) => task_errors.push(e),
-                Ok(Err(e)) => timeout_errors.push(e),
-                Err(e) => join_errors.push(e),
-            };
-            if responses.len() >= quorum {
-                break;
-            }
-            if join_errors.len() + task_errors.len() + timeout_errors.len() >= quorum {
-                break;
-            }
-        }
+        let pspec = compute_state.pspec.as_ref().expect("spec must be set");
 
-        // In case of error, log and fail the check, but don't crash.
-        // We're playing it safe because these errors could be transient
-        // and we don't yet retry. Also being careful here allows us to
-        // be backwards compatible with safekeepers that don't have the
-        // TIMELINE_STATUS API yet.
-        if responses.len() < quorum {
-            error!(
-                "failed sync safekeepers check {:?} {:?} {:?}",
-                join_errors, task_errors, timeout_errors
-            );
-            return Ok(None);
+        // In case of error, don't crash. We're playing it safe because these
+        // errors could be transient and we don't yet retry. Also being careful
+        // here allows us to be backwards compatible with safekeepers that don't
+        // have the TIMELINE_STATUS API yet.
+        match ping_safekeepers(pspec).await {
+            Some(responses) => Ok(check_if_synced(responses)),
+            None => Ok(None),
         }
-
-        Ok(check_if_synced(responses))
     }
 
     // Fast path for sync_safekeepers. If they're already synced we get the lsn
@@ -531,6 +490,18 @@ pub fn check_safekeepers_synced(&self, compute_state: &ComputeState) -> Result<O
     // and return the reported LSN back to the caller.
     #[instrument(skip_all)]
     pub fn sync_safekeepers(&self, storage_auth_token: Option<String>) -> Result<Lsn> {
+        self.run_sync_safekeepers(storage_auth_token, false)
+    }
+
+    // Like `sync_safekeepers`, but also establish the new term on the safekeepers
+    // if they are synced already, so that the walproposer of a compute elected
+    // before won't get elected again. Used to promote a replica.
+    #[instrument(skip_all)]
+    pub fn fence_safekeepers(&self, storage_auth_token: Option<String>) -> Result<Lsn> {
+        self.run_sync_safekeepers(storage_auth_token, true)
+    }
+
+    fn run_sync_safekeepers(&self, storage_auth_token: Option<String>, fence: bool) -> Result<Lsn> {
         let start_time = Utc::now();
 
         let mut sync_handle = maybe_cgexec(&self.pgbin)
@@ -541,6 +512,11 @@ pub fn sync_safekeepers(&self, storage_auth_token: Option<String>) -> Result<Lsn
             } else {
                 vec![]
             })
+            .envs(if fence {
+                vec![("NEON_SYNC_SAFEKEEPERS_FENCE", "1")]
+            } else {
+                vec![]
+            })
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -689,7 +665,14 @@ pub fn prepare_pgdata(
         symlink("/dev/shm/", pgdata_path.join("pg_dynshmem"))?;
 
         match spec.mode {
-            ComputeMode::Primary => {}
+            ComputeMode::Primary => {
+                // A broken record shouldn't keep the compute from starting, the slots
+                // that aren't restored will show up as missing.
+                if let Err(e) = logical_slots::restore_logical_slots(pgdata_path, pspec.timeline_id)
+                {
+                    error!("failed to restore logical replication slots: {e:#}");
+                }
+            }
             ComputeMode::Replica | ComputeMode::Static(..) => {
                 add_standby_signal(pgdata_path)?;
             }
@@ -870,6 +853,8 @@ pub fn apply_config(&self, compute_state: &ComputeState) -> Result<()> {
             self.has_feature(ComputeFeature::AnonExtension),
         )
         .context("apply_config handle_grants")?;
+        handle_database_objects(spec, &mut client, connstr.as_str())
+            .context("apply_config handle_database_objects")?;
         handle_extensions(spec, &mut client).context("apply_config handle_extensions")?;
         handle_extension_neon(&mut client).context("apply_config handle_extension_neon")?;
         create_availability_check_data(&mut client)
@@ -954,6 +939,7 @@ pub fn reconfigure(&self) -> Result<()> {
                     self.connstr.as_str(),
                     self.has_feature(ComputeFeature::AnonExtension),
                 )?;
+                handle_database_objects(&spec, &mut client, self.connstr.as_str())?;
                 handle_extensions(&spec, &mut client)?;
                 handle_extension_neon(&mut client)?;
                 // We can skip handle_migrations here because a new migration can only appear
@@ -980,11 +966,46 @@ pub fn reconfigure(&self) -> Result<()> {
         Ok(())
     }
 
+    /// Point Postgres at other pageservers without a restart, by rewriting `postgresql.conf`
+    /// and reloading it, like [`Self::reconfigure`] does. The compute is in `Configuration`
+    /// status meanwhile, so that it doesn't race with a `/configure` request.
+    #[instrument(skip_all)]
+    pub fn switch_pageservers(&self, pageserver_connstr: &str) -> Result<()> {
+        let spec = {
+            let mut state = self.state.lock().unwrap();
+            if state.status != ComputeStatus::Running {
+                anyhow::bail!(
+                    "cannot switch pageservers, compute status is {:?}",
+                    state.status
+                );
+            }
+            let pspec = state.pspec.as_mut().expect("spec must be set");
+            pspec.pageserver_connstr = pageserver_connstr.to_string();
+            pspec.spec.pageserver_connstring = Some(pageserver_connstr.to_string());
+            let spec = pspec.spec.clone();
+            state.status = ComputeStatus::Configuration;
+            self.state_changed.notify_all();
+            spec
+        };
+
+        let postgresql_conf_path = Path::new(&self.pgdata).join("postgresql.conf");
+        let result = config::write_postgres_conf(&postgresql_conf_path, &spec, None)
+            .and_then(|()| self.pg_reload_conf());
+        self.set_status(ComputeStatus::Running);
+        result
+    }
+
     #[instrument(skip_all)]
     pub fn start_compute(
         &self,
         extension_server_port: u16,
     ) -> Result<(std::process::Child, std::thread::JoinHandle<()>)> {
+        // The storage controller may have hibernated the tenant while it had no compute. If it
+        // can't be woken here, getting the basebackup will fail, or retry until it is.
+        if let Err(e) = tenant_wake::wake_tenant(self) {
+            warn!("failed to wake tenant: {e:#}");
+        }
+
         let compute_state = self.state.lock().unwrap().clone();
         let pspec = compute_state.pspec.as_ref().expect("spec must be set");
         info!(
@@ -1067,6 +1088,18 @@ pub fn start_compute(
             self.pg_reload_conf()?;
         }
 
+        if pspec.spec.mode == ComputeMode::Primary {
+            if let Err(e) = logical_slots::check_logical_slots(self) {
+                warn!("failed to check logical replication slots: {e:#}");
+            }
+            if let Err(e) = suspend_snapshot::restore_suspend_snapshot(
+                self,
+                pspec.spec.suspend_snapshot.as_ref(),
+            ) {
+                warn!("failed to restore suspend snapshot: {e:#}");
+            }
+        }
+
         let startup_end_time = Utc::now();
         {
             let mut state = self.state.lock().unwrap();
@@ -1284,6 +1317,7 @@ pub async fn download_extension(
             &real_ext_name,
             &ext_path,
             ext_remote_storage,
+            self.ext_integrity.as_ref(),
             &self.pgbin,
         )
         .await
@@ -1388,6 +1422,70 @@ pub async fn prepare_preload_libraries(
     }
 }
 
+/// Query TIMELINE_STATUS from the safekeepers, until a quorum of them respond. `None` if a
+/// quorum failed instead.
+pub async fn ping_safekeepers(pspec: &ParsedSpec) -> Option<Vec<TimelineStatusResponse>> {
+    // Construct a connection config for each safekeeper
+    let sk_connstrs: Vec<String> = pspec.safekeeper_connstrings.clone();
+    let sk_configs = sk_connstrs.into_iter().map(|connstr| {
+        // Format connstr
+        let id = connstr.clone();
+        let connstr = format!("postgresql://no_user@{}", connstr);
+        let options = format!(
+            "-c timeline_id={} tenant_id={}",
+            pspec.timeline_id, pspec.tenant_id
+        );
+
+        // Construct client
+        let mut config = tokio_postgres::Config::from_str(&connstr).unwrap();
+        config.options(&options);
+        if let Some(storage_auth_token) = pspec.storage_auth_token.clone() {
+            config.password(storage_auth_token);
+        }
+
+        (id, config)
+    });
+
+    // Create task set to query all safekeepers
+    let mut tasks = FuturesUnordered::new();
+    let quorum = sk_configs.len() / 2 + 1;
+    for (id, config) in sk_configs {
+        let timeout = tokio::time::Duration::from_millis(100);
+        let task = tokio::time::timeout(timeout, ping_safekeeper(id, config));
+        tasks.push(tokio::spawn(task));
+    }
+
+    // Get a quorum of responses or errors
+    let mut responses = Vec::new();
+    let mut join_errors = Vec::new();
+    let mut task_errors = Vec::new();
+    let mut timeout_errors = Vec::new();
+    while let Some(response) = tasks.next().await {
+        match response {
+            Ok(Ok(Ok(r))) => responses.push(r),
+            Ok(Ok(Err(e))) => task_errors.push(e),
+            Ok(Err(e)) => timeout_errors.push(e),
+            Err(e) => join_errors.push(e),
+        };
+        if responses.len() >= quorum {
+            break;
+        }
+        if join_errors.len() + task_errors.len() + timeout_errors.len() >= quorum {
+            break;
+        }
+    }
+
+    if responses.len() < quorum {
+        error!(
+            "failed to get TIMELINE_STATUS from a quorum of safekeepers {:?} {:?} {:?}",
+            join_errors, task_errors, timeout_errors
+        );
+        return None;
+    }
+
+    Some(responses)
+}
+
 pub fn forward_termination_signal() {
     let ss_pid = SYNC_SAFEKEEPERS_PID.load(Ordering::SeqCst);
     if ss_pid != 0 {
@@ -1,7 +1,7 @@
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 
@@ -114,6 +114,18 @@ pub fn write_postgres_conf(
         writeln!(file, "# Managed by compute_ctl: end")?;
     }
 
+    // The memory-dependent settings come after the spec settings, so that they override them.
+    // The vm-monitor keeps them up to date while the compute is scaled.
+    #[cfg(target_os = "linux")]
+    if let Some(policy) = &spec.memory_scaling {
+        let memory = available_memory();
+        writeln!(file, "# Scaled with memory by compute_ctl: begin")?;
+        for setting in policy.settings.iter().filter(|s| s.has_valid_name()) {
+            writeln!(file, "{}={}", setting.name, setting.value_for(memory))?;
+        }
+        writeln!(file, "# Scaled with memory by compute_ctl: end")?;
+    }
+
     if let Some(port) = extension_server_port {
         writeln!(file, "neon.extension_server_port={}", port)?;
     }
@@ -125,6 +137,56 @@ pub fn write_postgres_conf(
     Ok(())
 }
 
+/// Memory available to Postgres: the limit of the cgroup that we run in, if there is one, as
+/// in a container, or else the memory of the system
+#[cfg(target_os = "linux")]
+fn available_memory() -> u64 {
+    let system_memory = vm_monitor::get_total_system_memory();
+    match cgroup_memory_limit() {
+        Some(limit) => limit.min(system_memory),
+        None => system_memory,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_memory_limit() -> Option<u64> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let (path, limit_file) = cgroup_memory_limit_path(&cgroups)?;
+    parse_cgroup_memory_limit(&std::fs::read_to_string(path.join(limit_file)).ok()?)
+}
+
+/// Where to read the memory limit of our cgroup, given the contents of `/proc/self/cgroup`:
+/// the directory of the cgroup, and the name of the file in it. The memory controller of
+/// cgroup v1 takes precedence, as v2 controllers are then only used if v1 doesn't have them.
+pub fn cgroup_memory_limit_path(cgroups: &str) -> Option<(PathBuf, &'static str)> {
+    let mut unified = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(_id), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let path = path.trim_start_matches('/');
+        if controllers.split(',').any(|c| c == "memory") {
+            let dir = Path::new("/sys/fs/cgroup/memory").join(path);
+            return Some((dir, "memory.limit_in_bytes"));
+        }
+        if controllers.is_empty() {
+            unified = Some((Path::new("/sys/fs/cgroup").join(path), "memory.max"));
+        }
+    }
+    unified
+}
+
+/// Parse `memory.max` or `memory.limit_in_bytes`. None if there is no limit.
+pub fn parse_cgroup_memory_limit(contents: &str) -> Option<u64> {
+    match contents.trim() {
+        "max" => None,
+        limit => limit.parse().ok(),
+    }
+}
+
 pub fn with_compute_ctl_tmp_override<F>(pgdata_path: &Path, options: &str, exec: F) -> Result<()>
 where
     F: FnOnce() -> Result<()>,
@@ -0,0 +1,252 @@
+//! Declarative management of the schemas, extensions and privileges that the spec lists in each
+//! database's [`DatabaseObjects`].
+//!
+//! The relevant parts of a database's catalog are read into [`ExistingObjects`] by
+//! [`crate::catalog::get_existing_objects`], and compared with the spec to get the statements
+//! that bring the database in line with it. Those statements are run by
+//! [`crate::spec::handle_database_objects`], or reported as drift by the `/spec_drift` API.
+//!
+//! Nothing is ever dropped or revoked: objects and privileges that the spec does not mention
+//! are left alone.
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use compute_api::spec::{
+    Database, DefaultPrivileges, DefaultPrivilegesTarget, Grant, GrantTarget, PgIdent,
+};
+
+use crate::pg_helpers::{escape_literal, Escaping};
+
+/// The state of a database's catalog, limited to what its [`DatabaseObjects`] declares.
+///
+/// [`DatabaseObjects`]: compute_api::spec::DatabaseObjects
+#[derive(Clone, Debug, Default)]
+pub struct ExistingObjects {
+    /// Owner of each schema, by name
+    pub schemas: HashMap<PgIdent, PgIdent>,
+    pub extensions: HashMap<PgIdent, ExistingExtension>,
+    /// For each of the spec's grants, in order, the privileges that are already held
+    pub grants: Vec<HashSet<&'static str>>,
+    /// For each of the spec's default privileges, in order, the privileges that are already held
+    pub default_privileges: Vec<HashSet<&'static str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExistingExtension {
+    pub version: String,
+    pub schema: PgIdent,
+}
+
+const DATABASE_PRIVILEGES: &[&str] = &["CREATE", "CONNECT", "TEMPORARY"];
+const SCHEMA_PRIVILEGES: &[&str] = &["USAGE", "CREATE"];
+const TABLE_PRIVILEGES: &[&str] = &[
+    "SELECT",
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+    "TRUNCATE",
+    "REFERENCES",
+    "TRIGGER",
+];
+const SEQUENCE_PRIVILEGES: &[&str] = &["USAGE", "SELECT", "UPDATE"];
+const FUNCTION_PRIVILEGES: &[&str] = &["EXECUTE"];
+const TYPE_PRIVILEGES: &[&str] = &["USAGE"];
+
+/// Check the privilege keywords of a spec against those that apply to its target, and expand
+/// `ALL`. Privileges are interpolated into SQL, so anything unknown is an error.
+fn normalize_privileges(
+    requested: &[String],
+    allowed: &[&'static str],
+) -> Result<Vec<&'static str>> {
+    let mut privileges = Vec::new();
+    for privilege in requested {
+        let privilege = privilege.to_uppercase();
+        if privilege == "ALL" || privilege == "ALL PRIVILEGES" {
+            return Ok(allowed.to_vec());
+        }
+        let privilege = if privilege == "TEMP" {
+            "TEMPORARY".to_string()
+        } else {
+            privilege
+        };
+        match allowed.iter().find(|p| **p == privilege) {
+            Some(p) if !privileges.contains(p) => privileges.push(*p),
+            Some(_) => {}
+            None => bail!(
+                "privilege {privilege} does not apply here (expected one of {allowed:?} or ALL)"
+            ),
+        }
+    }
+    if privileges.is_empty() {
+        bail!("no privileges given");
+    }
+    Ok(privileges)
+}
+
+/// The privileges of a grant, validated and with `ALL` expanded
+pub fn grant_privileges(grant: &Grant) -> Result<Vec<&'static str>> {
+    let allowed = match grant.on {
+        GrantTarget::Database => DATABASE_PRIVILEGES,
+        GrantTarget::Schema { .. } => SCHEMA_PRIVILEGES,
+        GrantTarget::AllTablesInSchema { .. } => TABLE_PRIVILEGES,
+        GrantTarget::AllSequencesInSchema { .. } => SEQUENCE_PRIVILEGES,
+        GrantTarget::AllFunctionsInSchema { .. } => FUNCTION_PRIVILEGES,
+    };
+    normalize_privileges(&grant.privileges, allowed)
+}
+
+/// The privileges of a default privileges entry, validated and with `ALL` expanded
+pub fn default_privileges(defaults: &DefaultPrivileges) -> Result<Vec<&'static str>> {
+    let allowed = match defaults.on {
+        DefaultPrivilegesTarget::Tables => TABLE_PRIVILEGES,
+        DefaultPrivilegesTarget::Sequences => SEQUENCE_PRIVILEGES,
+        DefaultPrivilegesTarget::Functions => FUNCTION_PRIVILEGES,
+        DefaultPrivilegesTarget::Types => TYPE_PRIVILEGES,
+        DefaultPrivilegesTarget::Schemas => SCHEMA_PRIVILEGES,
+    };
+    if defaults.on == DefaultPrivilegesTarget::Schemas && defaults.schema.is_some() {
+        bail!("default privileges on schemas cannot be limited to a schema");
+    }
+    normalize_privileges(&defaults.privileges, allowed)
+}
+
+/// Statements that create or alter the schemas and extensions of `db`. Schemas come first, as
+/// extensions may be created in them.
+pub fn plan_objects(db: &Database, existing: &ExistingObjects) -> Vec<String> {
+    let mut statements = Vec::new();
+
+    for schema in &db.objects.schemas {
+        let owner = schema.owner.as_ref().unwrap_or(&db.owner);
+        match existing.schemas.get(&schema.name) {
+            None => statements.push(format!(
+                "CREATE SCHEMA IF NOT EXISTS {} AUTHORIZATION {}",
+                schema.name.pg_quote(),
+                owner.pg_quote()
+            )),
+            Some(existing_owner) if existing_owner != owner => statements.push(format!(
+                "ALTER SCHEMA {} OWNER TO {}",
+                schema.name.pg_quote(),
+                owner.pg_quote()
+            )),
+            Some(_) => {}
+        }
+    }
+
+    for ext in &db.objects.extensions {
+        match existing.extensions.get(&ext.name) {
+            None => {
+                let mut query = format!("CREATE EXTENSION IF NOT EXISTS {}", ext.name.pg_quote());
+                if let Some(schema) = &ext.schema {
+                    query.push_str(&format!(" SCHEMA {}", schema.pg_quote()));
+                }
+                if let Some(version) = &ext.version {
+                    query.push_str(&format!(" VERSION {}", escape_literal(version)));
+                }
+                query.push_str(" CASCADE");
+                statements.push(query);
+            }
+            Some(existing_ext) => {
+                if let Some(version) = ext.version.as_ref() {
+                    if *version != existing_ext.version {
+                        statements.push(format!(
+                            "ALTER EXTENSION {} UPDATE TO {}",
+                            ext.name.pg_quote(),
+                            escape_literal(version)
+                        ));
+                    }
+                }
+                if let Some(schema) = ext.schema.as_ref() {
+                    if *schema != existing_ext.schema {
+                        statements.push(format!(
+                            "ALTER EXTENSION {} SET SCHEMA {}",
+                            ext.name.pg_quote(),
+                            schema.pg_quote()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    statements
+}
+
+/// Statements that grant the privileges and default privileges of `db` that are not held yet.
+/// Only meaningful once the objects of [`plan_objects`] exist: grants on all tables in a schema
+/// only cover the tables that exist when they run.
+pub fn plan_privileges(db: &Database, existing: &ExistingObjects) -> Result<Vec<String>> {
+    let mut statements = Vec::new();
+
+    for (idx, grant) in db.objects.grants.iter().enumerate() {
+        let held = existing.grants.get(idx);
+        let missing: Vec<_> = grant_privileges(grant)?
+            .into_iter()
+            .filter(|p| !held.is_some_and(|held| held.contains(p)))
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+
+        let on = match &grant.on {
+            GrantTarget::Database => format!("DATABASE {}", db.name.pg_quote()),
+            GrantTarget::Schema { schema } => format!("SCHEMA {}", schema.pg_quote()),
+            GrantTarget::AllTablesInSchema { schema } => {
+                format!("ALL TABLES IN SCHEMA {}", schema.pg_quote())
+            }
+            GrantTarget::AllSequencesInSchema { schema } => {
+                format!("ALL SEQUENCES IN SCHEMA {}", schema.pg_quote())
+            }
+            GrantTarget::AllFunctionsInSchema { schema } => {
+                format!("ALL FUNCTIONS IN SCHEMA {}", schema.pg_quote())
+            }
+        };
+        let mut query = format!(
+            "GRANT {} ON {} TO {}",
+            missing.join(", "),
+            on,
+            grant.role.pg_quote()
+        );
+        if grant.with_grant_option {
+            query.push_str(" WITH GRANT OPTION");
+        }
+        statements.push(query);
+    }
+
+    for (idx, defaults) in db.objects.default_privileges.iter().enumerate() {
+        let held = existing.default_privileges.get(idx);
+        let missing: Vec<_> = default_privileges(defaults)?
+            .into_iter()
+            .filter(|p| !held.is_some_and(|held| held.contains(p)))
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+
+        let mut query = format!(
+            "ALTER DEFAULT PRIVILEGES FOR ROLE {}",
+            defaults.for_role.as_ref().unwrap_or(&db.owner).pg_quote()
+        );
+        if let Some(schema) = &defaults.schema {
+            query.push_str(&format!(" IN SCHEMA {}", schema.pg_quote()));
+        }
+        let on = match defaults.on {
+            DefaultPrivilegesTarget::Tables => "TABLES",
+            DefaultPrivilegesTarget::Sequences => "SEQUENCES",
+            DefaultPrivilegesTarget::Functions => "FUNCTIONS",
+            DefaultPrivilegesTarget::Types => "TYPES",
+            DefaultPrivilegesTarget::Schemas => "SCHEMAS",
+        };
+        query.push_str(&format!(
+            " GRANT {} ON {} TO {}",
+            missing.join(", "),
+            on,
+            defaults.role.pg_quote()
+        ));
+        if defaults.with_grant_option {
+            query.push_str(" WITH GRANT OPTION");
+        }
+        statements.push(query);
+    }
+
+    Ok(statements)
+}
@@ -6,20 +6,26 @@
 use std::thread;
 
 use crate::catalog::SchemaDumpError;
-use crate::catalog::{get_database_schema, get_dbs_and_roles};
+use crate::catalog::{get_database_schema, get_dbs_and_roles, get_spec_drift};
 use crate::compute::forward_termination_signal;
 use crate::compute::{ComputeNode, ComputeState, ParsedSpec};
+use crate::promote::{check_promotable, promote_replica};
+use crate::suspend_snapshot::capture_suspend_snapshot;
 use compute_api::requests::ConfigurationRequest;
-use compute_api::responses::{ComputeStatus, ComputeStatusResponse, GenericAPIError};
+use compute_api::responses::{
+    ComputeStatus, ComputeStatusResponse, GenericAPIError, PromotionState, PromotionStatus,
+};
+use compute_api::spec::ComputeMode;
 
 use anyhow::Result;
 use hyper::header::CONTENT_TYPE;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use metrics::{Encoder, TextEncoder};
 use tokio::task;
 use tracing::{debug, error, info, warn};
 use tracing_utils::http::OtelName;
-use utils::http::request::must_get_query_param;
+use utils::http::request::{get_query_param, must_get_query_param};
 
 fn status_response_from_state(state: &ComputeState) -> ComputeStatusResponse {
     ComputeStatusResponse {
@@ -35,6 +41,9 @@ fn status_response_from_state(state: &ComputeState) -> ComputeStatusResponse {
         status: state.status,
         last_active: state.last_active,
         error: state.error.clone(),
+        logical_slots: state.logical_slots.clone(),
+        lfc_prewarm: state.lfc_prewarm.clone(),
+        promotion: state.promotion.clone(),
     }
 }
 
@@ -54,8 +63,30 @@ async fn routes(req: Request<Body>, compute: &Arc<ComputeNode>) -> Response<Body
             Response::new(Body::from(serde_json::to_string(&status_response).unwrap()))
         }
 
-        // Startup metrics in JSON format. Keep /metrics reserved for a possible
-        // future use for Prometheus metrics format.
+        // Results of the metric queries in Prometheus format. Not found if the compute
+        // isn't running or has no metric queries.
+        (&Method::GET, "/metrics") => {
+            debug!("serving /metrics GET request");
+            let exporter = compute.sql_exporter.lock().unwrap().clone();
+            let Some(exporter) = exporter else {
+                return render_json_error("no SQL exporter is running", StatusCode::NOT_FOUND);
+            };
+            match task::spawn_blocking(move || exporter.scrape())
+                .await
+                .unwrap()
+            {
+                Ok(body) => Response::builder()
+                    .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                    .body(Body::from(body))
+                    .unwrap(),
+                Err(e) => {
+                    error!("failed to collect metrics: {e:#}");
+                    render_json_error(&format!("{e:#}"), StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            }
+        }
+
+        // Startup metrics in JSON format.
         (&Method::GET, "/metrics.json") => {
             info!("serving /metrics.json GET request");
             let metrics = compute.state.lock().unwrap().metrics.clone();
@@ -126,9 +157,31 @@ async fn routes(req: Request<Body>, compute: &Arc<ComputeNode>) -> Response<Body
             }
         }
 
+        // Promote a replica to a primary, without restarting it. Blocks until the
+        // promotion is over, the progress is reported in /status meanwhile.
+        (&Method::POST, "/promote") => {
+            info!("serving /promote POST request");
+            match handle_promote_request(compute).await {
+                Ok(msg) => render_json(Body::from(msg)),
+                Err((msg, code)) => {
+                    error!("error handling /promote request: {msg}");
+                    render_json_error(&msg, code)
+                }
+            }
+        }
+
         (&Method::POST, "/terminate") => {
-            info!("serving /terminate POST request");
-            match handle_terminate_request(compute).await {
+            let suspend = match get_query_param(&req, "mode") {
+                Err(e) => return e.into_response(),
+                Ok(None) => false,
+                Ok(Some(mode)) if mode == "suspend" => true,
+                Ok(Some(mode)) => {
+                    let msg = format!("unknown termination mode {mode:?}");
+                    return render_json_error(&msg, StatusCode::BAD_REQUEST);
+                }
+            };
+            info!("serving /terminate POST request, suspend: {suspend}");
+            match handle_terminate_request(compute, suspend).await {
                 Ok(()) => Response::new(Body::empty()),
                 Err((msg, code)) => {
                     error!("error handling /terminate request: {msg}");
@@ -147,6 +200,29 @@ async fn routes(req: Request<Body>, compute: &Arc<ComputeNode>) -> Response<Body
             }
         }
 
+        // Differences between the catalog and the schemas, extensions and privileges that
+        // the spec declares
+        (&Method::GET, "/spec_drift") => {
+            info!("serving /spec_drift GET request");
+            let status = compute.get_status();
+            if status != ComputeStatus::Running {
+                let msg = format!("compute is not running, current status: {:?}", status);
+                error!(msg);
+                return render_json_error(&msg, StatusCode::PRECONDITION_FAILED);
+            }
+
+            match get_spec_drift(compute).await {
+                Ok(res) => render_json(Body::from(serde_json::to_string(&res).unwrap())),
+                Err(e) => {
+                    error!("can't get spec drift: {e:#}");
+                    render_json_error(
+                        &format!("can't get spec drift: {e:#}"),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                }
+            }
+        }
+
         (&Method::GET, "/database_schema") => {
             let database = match must_get_query_param(&req, "database") {
                 Err(e) => return e.into_response(),
@@ -287,6 +363,14 @@ async fn handle_configure_request(
                 );
                 return Err((msg, StatusCode::PRECONDITION_FAILED));
             }
+            if state
+                .promotion
+                .as_ref()
+                .is_some_and(|p| p.state.is_in_progress())
+            {
+                let msg = "compute is being promoted to primary".to_string();
+                return Err((msg, StatusCode::PRECONDITION_FAILED));
+            }
             state.pspec = Some(parsed_spec);
             state.status = ComputeStatus::ConfigurationPending;
             compute.state_changed.notify_all();
@@ -329,6 +413,36 @@ async fn handle_configure_request(
     }
 }
 
+async fn handle_promote_request(
+    compute: &Arc<ComputeNode>,
+) -> Result<String, (String, StatusCode)> {
+    {
+        let mut state = compute.state.lock().unwrap();
+        if let Err(msg) = check_promotable(&state) {
+            return Err((msg, StatusCode::PRECONDITION_FAILED));
+        }
+        // Set it under the lock, so that concurrent requests see that it's in progress
+        state.promotion = Some(PromotionStatus {
+            state: PromotionState::CatchingUp,
+            ..Default::default()
+        });
+    }
+
+    let c = compute.clone();
+    let promotion = task::spawn_blocking(move || promote_replica(&c))
+        .await
+        .unwrap();
+    if promotion.state == PromotionState::Failed {
+        let err = promotion.error.as_deref().unwrap_or("unknown error");
+        let msg = format!("promotion failed: {err}");
+        return Err((msg, StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let state = compute.state.lock().unwrap().clone();
+    let status_response = status_response_from_state(&state);
+    Ok(serde_json::to_string(&status_response).unwrap())
+}
+
 fn render_json_error(e: &str, status: StatusCode) -> Response<Body> {
     let error = GenericAPIError {
         error: e.to_string(),
@@ -354,8 +468,11 @@ fn render_plain(body: Body) -> Response<Body> {
         .unwrap()
 }
 
-async fn handle_terminate_request(compute: &Arc<ComputeNode>) -> Result<(), (String, StatusCode)> {
-    {
+async fn handle_terminate_request(
+    compute: &Arc<ComputeNode>,
+    suspend: bool,
+) -> Result<(), (String, StatusCode)> {
+    let capture_snapshot = {
         let mut state = compute.state.lock().unwrap();
         if state.status == ComputeStatus::Terminated {
             return Ok(());
@@ -367,10 +484,29 @@ async fn handle_terminate_request(compute: &Arc<ComputeNode>) -> Result<(), (Str
             );
             return Err((msg, StatusCode::PRECONDITION_FAILED));
         }
+        let capture_snapshot = suspend
+            && state.status == ComputeStatus::Running
+            && state.pspec.as_ref().is_some_and(|pspec| {
+                pspec.spec.mode == ComputeMode::Primary && pspec.spec.suspend_snapshot.is_some()
+            });
         state.status = ComputeStatus::TerminationPending;
         compute.state_changed.notify_all();
         drop(state);
+        capture_snapshot
+    };
+
+    // Postgres is still up, as nothing else shuts it down while termination is pending. Not
+    // being able to capture the snapshot must not prevent the suspend.
+    if capture_snapshot {
+        let c = compute.clone();
+        let result = task::spawn_blocking(move || capture_suspend_snapshot(&c))
+            .await
+            .unwrap();
+        if let Err(e) = result {
+            warn!("failed to capture suspend snapshot: {e:#}");
+        }
     }
+
     forward_termination_signal();
     info!("sent signal and notified waiters");
 
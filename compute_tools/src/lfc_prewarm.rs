@@ -0,0 +1,329 @@
+//! Prewarming of the local file cache (LFC) after restart.
+//!
+//! The LFC starts cold whenever the compute restarts, so a primary periodically saves the set
+//! of cached blocks, as returned by `neon.get_local_cache_state()` in LRU order, in the
+//! `neon/lfc_state.json` aux file of its timeline, with a `neon-file:` logical message like
+//! [`crate::logical_slots`] does. It is only written again when it has changed.
+//!
+//! The record comes back with the basebackup. Once Postgres is running, the blocks are fetched
+//! into the cache with `neon.prewarm_local_cache()`, most recently used first and no faster
+//! than the configured bandwidth, so that prewarming doesn't compete too much with the
+//! workload. Only as many blocks as fit in the cache are fetched. The progress is reported in
+//! the `/status` API and the totals in `/metrics.json`.
+use std::cmp::min;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use compute_api::responses::{ComputeStatus, LfcPrewarmState, LfcPrewarmStatus};
+use compute_api::spec::{ComputeMode, LfcPrewarmSpec};
+use postgres::{Client, NoTls};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::compute::ComputeNode;
+
+/// Path of the record, relative to pgdata and in the aux files of the timeline
+pub const LFC_STATE_AUX_PATH: &str = "neon/lfc_state.json";
+
+const BLCKSZ: u64 = 8192;
+
+/// The contents of the `neon/lfc_state.json` aux file
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LfcState {
+    /// Most recently used first
+    pub runs: Vec<CachedRun>,
+}
+
+/// Consecutive blocks of a relation fork that were cached
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedRun {
+    pub reltablespace: u32,
+    pub reldatabase: u32,
+    pub relfilenode: u32,
+    pub forknum: i16,
+    pub blkno: i64,
+    pub nblocks: i32,
+}
+
+/// The runs to prewarm, so that at most `max_pages` pages are fetched: prewarming more than
+/// fits in the cache would only evict the most recently used pages that were fetched first.
+pub fn runs_to_prewarm(runs: &[CachedRun], max_pages: u64) -> Vec<CachedRun> {
+    let mut result = Vec::new();
+    let mut pages = 0;
+    for run in runs {
+        if pages >= max_pages {
+            break;
+        }
+        let nblocks = min(run.nblocks as u64, max_pages - pages);
+        result.push(CachedRun {
+            nblocks: nblocks as i32,
+            ..*run
+        });
+        pages += nblocks;
+    }
+    result
+}
+
+/// How long to wait so that fetching `bytes` since `elapsed` ago stays within the bandwidth
+pub fn throttle_delay(bytes: u64, elapsed: Duration, bandwidth_mb_per_sec: u32) -> Duration {
+    if bandwidth_mb_per_sec == 0 {
+        return Duration::ZERO;
+    }
+    let target = Duration::from_secs_f64(bytes as f64 / (bandwidth_mb_per_sec as f64 * 1e6));
+    target.saturating_sub(elapsed)
+}
+
+/// Cache size in pages, 0 if the cache is disabled
+fn cache_size_pages(client: &mut Client) -> Result<u64> {
+    let row = client.query_one(
+        "SELECT pg_catalog.pg_size_bytes(pg_catalog.current_setting('neon.file_cache_size_limit'))",
+        &[],
+    )?;
+    let bytes: i64 = row.get(0);
+    Ok(bytes.max(0) as u64 / BLCKSZ)
+}
+
+fn update_status(compute: &ComputeNode, status: &LfcPrewarmStatus) {
+    compute.state.lock().unwrap().lfc_prewarm = Some(status.clone());
+}
+
+/// Whether prewarming should go on. It waits through a reconfiguration, but stops for good
+/// when the compute stops running.
+fn keep_prewarming(compute: &ComputeNode) -> bool {
+    matches!(
+        compute.get_status(),
+        ComputeStatus::Running | ComputeStatus::ConfigurationPending | ComputeStatus::Configuration
+    )
+}
+
+fn prewarm(
+    compute: &ComputeNode,
+    client: &mut Client,
+    spec: &LfcPrewarmSpec,
+    saved: &LfcState,
+    status: &mut LfcPrewarmStatus,
+) -> Result<()> {
+    let runs = runs_to_prewarm(&saved.runs, cache_size_pages(client)?);
+    status.state = LfcPrewarmState::Prewarming;
+    status.total_pages = runs.iter().map(|run| run.nblocks as u64).sum();
+    update_status(compute, status);
+    info!(
+        "prewarming local file cache with {} pages, at {} MB/s",
+        status.total_pages, spec.bandwidth_mb_per_sec
+    );
+
+    let start = Instant::now();
+    for run in runs {
+        if !keep_prewarming(compute) {
+            status.state = LfcPrewarmState::Cancelled;
+            return Ok(());
+        }
+
+        match client.query_one(
+            "SELECT neon.prewarm_local_cache($1, $2, $3, $4, $5, $6)",
+            &[
+                &run.reltablespace,
+                &run.reldatabase,
+                &run.relfilenode,
+                &run.forknum,
+                &run.blkno,
+                &run.nblocks,
+            ],
+        ) {
+            Ok(row) => status.fetched_pages += row.get::<_, i32>(0) as u64,
+            Err(e) if client.is_closed() => return Err(e.into()),
+            Err(e) => {
+                // The relation may have been dropped or truncated since the state was saved
+                debug!("could not prewarm {run:?}: {e}");
+                status.failed_pages += run.nblocks as u64;
+            }
+        }
+        status.done_pages += run.nblocks as u64;
+        update_status(compute, status);
+
+        thread::sleep(throttle_delay(
+            status.fetched_pages * BLCKSZ,
+            start.elapsed(),
+            spec.bandwidth_mb_per_sec,
+        ));
+    }
+
+    status.state = LfcPrewarmState::Completed;
+    let mut state = compute.state.lock().unwrap();
+    state.metrics.lfc_prewarm_ms = start.elapsed().as_millis() as u64;
+    state.metrics.lfc_prewarm_pages = status.fetched_pages;
+    Ok(())
+}
+
+/// Load the record that came with the basebackup, if any
+pub fn load_lfc_state(pgdata: &Path) -> Result<Option<LfcState>> {
+    match fs::read(pgdata.join(LFC_STATE_AUX_PATH)) {
+        Ok(contents) => Ok(Some(
+            serde_json::from_slice(&contents).context("invalid LFC state record")?,
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Save the state of the cache, unless it is the same as `last_saved`, so that an idle compute
+/// doesn't keep writing WAL
+fn save_lfc_state(
+    client: &mut Client,
+    spec: &LfcPrewarmSpec,
+    last_saved: &mut Option<LfcState>,
+) -> Result<()> {
+    let max_chunks = spec.max_chunks.map(|n| n as i32);
+    let runs: Vec<CachedRun> = client
+        .query(
+            "SELECT reltablespace, reldatabase, relfilenode, relforknumber, relblocknumber, nblocks \
+             FROM neon.get_local_cache_state($1)",
+            &[&max_chunks],
+        )?
+        .iter()
+        .map(|row| CachedRun {
+            reltablespace: row.get(0),
+            reldatabase: row.get(1),
+            relfilenode: row.get(2),
+            forknum: row.get(3),
+            blkno: row.get(4),
+            nblocks: row.get(5),
+        })
+        .collect();
+    // Don't replace the record of a warm cache with that of an empty or disabled one
+    if runs.is_empty() {
+        return Ok(());
+    }
+
+    let state = LfcState { runs };
+    if last_saved.as_ref() == Some(&state) {
+        debug!("local file cache state is unchanged");
+        return Ok(());
+    }
+
+    let pages: i64 = state.runs.iter().map(|run| run.nblocks as i64).sum();
+    let contents = serde_json::to_vec(&state)?;
+    let prefix = format!("neon-file:{LFC_STATE_AUX_PATH}");
+    client.execute(
+        "SELECT pg_catalog.pg_logical_emit_message(false, $1, $2::bytea)",
+        &[&prefix, &contents],
+    )?;
+    debug!("saved local file cache state of {pages} pages");
+    *last_saved = Some(state);
+    Ok(())
+}
+
+fn lfc_prewarm_main(compute: &ComputeNode) {
+    // Wait for Postgres to start
+    {
+        let mut state = compute.state.lock().unwrap();
+        while state.status != ComputeStatus::Running {
+            if matches!(
+                state.status,
+                ComputeStatus::Failed | ComputeStatus::Terminated
+            ) {
+                return;
+            }
+            state = compute.state_changed.wait(state).unwrap();
+        }
+    }
+
+    let mut connstr = compute.connstr.clone();
+    connstr
+        .query_pairs_mut()
+        .append_pair("application_name", "compute_ctl:lfc_prewarm");
+    let connect = || Client::connect(connstr.as_str(), NoTls);
+
+    let spec = compute
+        .state
+        .lock()
+        .unwrap()
+        .pspec
+        .as_ref()
+        .and_then(|pspec| pspec.spec.lfc_prewarm.clone());
+    if let Some(spec) = &spec {
+        let mut status = LfcPrewarmStatus::default();
+        let result = load_lfc_state(Path::new(&compute.pgdata)).and_then(|saved| match saved {
+            Some(saved) => {
+                let mut client = connect()?;
+                prewarm(compute, &mut client, spec, &saved, &mut status)
+            }
+            None => {
+                info!("no saved local file cache state to prewarm with");
+                status.state = LfcPrewarmState::Completed;
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            error!("failed to prewarm local file cache: {e:#}");
+            status.state = LfcPrewarmState::Failed;
+            status.error = Some(format!("{e:#}"));
+        }
+        info!("local file cache prewarm is {:?}", status.state);
+        update_status(compute, &status);
+    }
+
+    let mut client: Option<Client> = None;
+    let mut last_saved = None;
+    loop {
+        let (status, mode, spec) = {
+            let state = compute.state.lock().unwrap();
+            let pspec = state.pspec.as_ref();
+            (
+                state.status,
+                pspec.map(|pspec| pspec.spec.mode),
+                pspec.and_then(|pspec| pspec.spec.lfc_prewarm.clone()),
+            )
+        };
+        match status {
+            ComputeStatus::Failed | ComputeStatus::Terminated => break,
+            ComputeStatus::Running => {}
+            _ => {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        }
+        // Only a primary can write the record, and it's only useful while the spec asks for
+        // prewarming
+        let (Some(ComputeMode::Primary), Some(spec)) = (mode, spec) else {
+            break;
+        };
+        thread::sleep(Duration::from_secs(spec.save_interval_secs.get()));
+        if compute.get_status() != ComputeStatus::Running {
+            continue;
+        }
+
+        // Reuse the connection while it's alive
+        if !matches!(&client, Some(cli) if !cli.is_closed()) {
+            match connect() {
+                Ok(cli) => client = Some(cli),
+                Err(e) => {
+                    warn!("could not connect to Postgres to save local file cache state: {e}");
+                    continue;
+                }
+            }
+        }
+        if let Err(e) = save_lfc_state(client.as_mut().unwrap(), &spec, &mut last_saved) {
+            warn!("failed to save local file cache state: {e:#}");
+        }
+    }
+}
+
+/// Spawns a background thread that prewarms the local file cache once Postgres is running,
+/// and then keeps the saved state up to date. Does nothing unless the spec enables it.
+pub fn launch_lfc_prewarm(compute: &Arc<ComputeNode>) {
+    let compute = Arc::clone(compute);
+
+    thread::Builder::new()
+        .name("lfc-prewarm".into())
+        .spawn(move || {
+            lfc_prewarm_main(&compute);
+            info!("local file cache prewarm thread is exited");
+        })
+        .expect("cannot launch local file cache prewarm thread");
+}
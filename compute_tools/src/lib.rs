@@ -10,12 +10,20 @@
 pub mod logger;
 pub mod catalog;
 pub mod compute;
+pub mod db_objects;
 pub mod extension_server;
+pub mod lfc_prewarm;
+pub mod logical_slots;
 pub mod lsn_lease;
 mod migration;
 pub mod monitor;
+pub mod pageserver_failover;
 pub mod params;
 pub mod pg_helpers;
+pub mod promote;
 pub mod spec;
+pub mod sql_exporter;
+pub mod suspend_snapshot;
 pub mod swap;
 pub mod sync_sk;
+pub mod tenant_wake;
@@ -0,0 +1,412 @@
+//! Persistence of logical replication slots across compute restarts.
+//!
+//! Postgres keeps the state of each slot in `pg_replslot/<name>/state`, and those files are
+//! WAL-logged to the pageserver as aux files, but only when Postgres saves them, which is not
+//! on every advance of a slot. So compute_ctl keeps its own record of the logical slots of a
+//! primary in the `neon/logical_slots.json` aux file, and rewrites it with a `neon-file:`
+//! logical message whenever a slot is created, dropped or advanced.
+//!
+//! The record comes back with the basebackup. Before Postgres starts, the state file of each
+//! recorded slot that's missing from `pg_replslot` is written back, unless the record was
+//! inherited from the parent of a branch, so that Postgres loads the
+//! slot before it accepts connections. The state file in the record was saved when Postgres last
+//! saved the slot, so the slot's position in it is brought up to the recorded one, and so is the
+//! position in a state file that came with the basebackup but is older than the record. Once it's running, the `restart_lsn` of each slot is
+//! checked against the oldest WAL that the safekeepers can still stream: a slot that needs WAL
+//! which is gone can't be used, and is reported with an error in the `/status` API.
+use std::cmp::min;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use compute_api::responses::{ComputeStatus, LogicalSlotStatus};
+use compute_api::spec::ComputeMode;
+use postgres::{Client, NoTls};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use crate::compute::{ping_safekeepers, ComputeNode};
+use crate::sync_sk::wal_available_from;
+
+/// Path of the record, relative to pgdata and in the aux files of the timeline
+pub const LOGICAL_SLOTS_AUX_PATH: &str = "neon/logical_slots.json";
+
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Offset of `restart_lsn` in Postgres' `ReplicationSlotOnDisk`, the contents of a slot's
+/// state file. Same as `postgres_ffi::pg_constants::REPL_SLOT_ON_DISK_OFFSETOF_RESTART_LSN`.
+const REPL_SLOT_ON_DISK_OFFSETOF_RESTART_LSN: usize = 4 * 4 + 64 + 4 * 4;
+
+/// Offset of `confirmed_flush` in `ReplicationSlotOnDisk`: it follows `restart_lsn` and
+/// `invalidated_at` (since Postgres 16, `invalidated` and padding), 8 bytes each.
+const REPL_SLOT_ON_DISK_OFFSETOF_CONFIRMED_FLUSH: usize =
+    REPL_SLOT_ON_DISK_OFFSETOF_RESTART_LSN + 16;
+
+/// Offset of the CRC-32C checksum in `ReplicationSlotOnDisk`, and of the data it covers, which
+/// runs to the end of the file
+const REPL_SLOT_ON_DISK_OFFSETOF_CHECKSUM: usize = 4;
+const REPL_SLOT_ON_DISK_NOT_CHECKSUMMED_SIZE: usize = 8;
+
+/// The contents of the `neon/logical_slots.json` aux file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedSlots {
+    /// Timeline the record was written on. Branches don't inherit slots.
+    pub timeline_id: TimelineId,
+    pub slots: Vec<PersistedSlot>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedSlot {
+    pub name: String,
+    pub plugin: String,
+    pub database: String,
+    pub restart_lsn: Option<Lsn>,
+    pub confirmed_flush_lsn: Option<Lsn>,
+    /// Hex-encoded contents of `pg_replslot/<name>/state`
+    pub state: String,
+}
+
+/// The `restart_lsn` stored in the contents of a slot's state file
+pub fn slot_state_restart_lsn(state: &[u8]) -> Result<Lsn> {
+    read_lsn(state, REPL_SLOT_ON_DISK_OFFSETOF_RESTART_LSN)
+}
+
+fn read_lsn(state: &[u8], offs: usize) -> Result<Lsn> {
+    match state.get(offs..offs + 8) {
+        Some(bytes) => Ok(Lsn(u64::from_le_bytes(bytes.try_into().unwrap()))),
+        None => bail!("slot state file is too short ({} bytes)", state.len()),
+    }
+}
+
+/// The `confirmed_flush` LSN stored in the contents of a slot's state file
+pub fn slot_state_confirmed_flush_lsn(state: &[u8]) -> Result<Lsn> {
+    read_lsn(state, REPL_SLOT_ON_DISK_OFFSETOF_CONFIRMED_FLUSH)
+}
+
+/// Move the `restart_lsn` and `confirmed_flush` stored in the contents of a slot's state file
+/// forward to the given positions, if they are behind, and update its checksum. Returns whether
+/// anything changed.
+pub fn advance_slot_state(
+    state: &mut [u8],
+    restart_lsn: Option<Lsn>,
+    confirmed_flush_lsn: Option<Lsn>,
+) -> Result<bool> {
+    let mut changed = false;
+    for (offs, lsn) in [
+        (REPL_SLOT_ON_DISK_OFFSETOF_RESTART_LSN, restart_lsn),
+        (
+            REPL_SLOT_ON_DISK_OFFSETOF_CONFIRMED_FLUSH,
+            confirmed_flush_lsn,
+        ),
+    ] {
+        let Some(lsn) = lsn else {
+            continue;
+        };
+        if read_lsn(state, offs)? < lsn {
+            state[offs..offs + 8].copy_from_slice(&lsn.0.to_le_bytes());
+            changed = true;
+        }
+    }
+
+    if changed {
+        let checksum = crc32c::crc32c(&state[REPL_SLOT_ON_DISK_NOT_CHECKSUMMED_SIZE..]);
+        state[REPL_SLOT_ON_DISK_OFFSETOF_CHECKSUM..REPL_SLOT_ON_DISK_OFFSETOF_CHECKSUM + 4]
+            .copy_from_slice(&checksum.to_le_bytes());
+    }
+    Ok(changed)
+}
+
+/// Slot names become directory names, so only accept what Postgres allows in them
+fn check_slot_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!("invalid replication slot name {name:?}");
+    }
+    Ok(())
+}
+
+/// Write back the state file of each recorded slot that's missing from `pg_replslot`, bring the
+/// position of each slot up to the recorded one, and move `restart.lsn` back so that Postgres can
+/// read the WAL the restored slots need. Must run after the basebackup is unpacked and before
+/// Postgres starts. Returns the names of the restored slots.
+pub fn restore_logical_slots(pgdata: &Path, timeline_id: TimelineId) -> Result<Vec<String>> {
+    let persisted: PersistedSlots = match fs::read(pgdata.join(LOGICAL_SLOTS_AUX_PATH)) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("failed to parse {LOGICAL_SLOTS_AUX_PATH}"))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    if persisted.timeline_id != timeline_id {
+        info!(
+            "not restoring logical replication slots of timeline {}",
+            persisted.timeline_id
+        );
+        return Ok(Vec::new());
+    }
+
+    let mut restored = Vec::new();
+    let mut min_restart_lsn = Lsn::MAX;
+    for slot in &persisted.slots {
+        check_slot_name(&slot.name)?;
+        let slot_dir = pgdata.join("pg_replslot").join(&slot.name);
+        let state_path = slot_dir.join("state");
+        if state_path.exists() {
+            let mut state = fs::read(&state_path)?;
+            if advance_slot_state(&mut state, slot.restart_lsn, slot.confirmed_flush_lsn)
+                .with_context(|| format!("invalid state of replication slot {}", slot.name))?
+            {
+                fs::write(&state_path, &state)?;
+                info!(
+                    "advanced logical replication slot {} to restart LSN {}",
+                    slot.name,
+                    slot_state_restart_lsn(&state)?
+                );
+            }
+            continue;
+        }
+
+        let mut state = hex::decode(&slot.state)
+            .with_context(|| format!("invalid state of replication slot {}", slot.name))?;
+        advance_slot_state(&mut state, slot.restart_lsn, slot.confirmed_flush_lsn)
+            .with_context(|| format!("invalid state of replication slot {}", slot.name))?;
+        let restart_lsn = slot_state_restart_lsn(&state)?;
+        fs::create_dir_all(&slot_dir)?;
+        fs::write(state_path, &state)?;
+
+        info!(
+            "restored logical replication slot {} with restart LSN {}",
+            slot.name, restart_lsn
+        );
+        min_restart_lsn = min(min_restart_lsn, restart_lsn);
+        restored.push(slot.name.clone());
+    }
+
+    // The basebackup only covers the slots that came with it
+    if min_restart_lsn != Lsn::MAX {
+        let restart_lsn_path = pgdata.join("restart.lsn");
+        if let Ok(contents) = fs::read(&restart_lsn_path) {
+            if let Ok(bytes) = <[u8; 8]>::try_from(contents.as_slice()) {
+                min_restart_lsn = min(min_restart_lsn, Lsn(u64::from_le_bytes(bytes)));
+            }
+        }
+        fs::write(restart_lsn_path, min_restart_lsn.0.to_le_bytes())?;
+    }
+
+    Ok(restored)
+}
+
+/// Set an error on each slot that needs WAL from before `wal_start_lsn`
+pub fn check_wal_available(slots: &mut [LogicalSlotStatus], wal_start_lsn: Lsn) {
+    for slot in slots {
+        match slot.restart_lsn {
+            None => {
+                slot.error = Some("slot has been invalidated by Postgres".to_string());
+            }
+            Some(restart_lsn) if restart_lsn < wal_start_lsn => {
+                slot.error = Some(format!(
+                    "slot needs WAL from {restart_lsn}, but safekeepers only have WAL from {wal_start_lsn}"
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+fn parse_lsn(lsn: Option<String>) -> Result<Option<Lsn>> {
+    Ok(lsn.as_deref().map(Lsn::from_str).transpose()?)
+}
+
+/// A row of `pg_replication_slots`
+struct SlotRow {
+    name: String,
+    plugin: String,
+    database: String,
+    restart_lsn: Option<Lsn>,
+    confirmed_flush_lsn: Option<Lsn>,
+}
+
+fn get_logical_slots(client: &mut Client) -> Result<Vec<SlotRow>> {
+    let query = "SELECT slot_name, plugin, database, restart_lsn::text, confirmed_flush_lsn::text
+                 FROM pg_catalog.pg_replication_slots
+                 WHERE slot_type = 'logical' AND NOT temporary
+                 ORDER BY slot_name";
+    client
+        .query(query, &[])?
+        .into_iter()
+        .map(|row| {
+            Ok(SlotRow {
+                name: row.get(0),
+                plugin: row.get(1),
+                database: row.get(2),
+                restart_lsn: parse_lsn(row.get(3))?,
+                confirmed_flush_lsn: parse_lsn(row.get(4))?,
+            })
+        })
+        .collect()
+}
+
+/// Check that the WAL each logical slot needs can still be streamed from the safekeepers, and
+/// keep the result in the compute state for the `/status` API
+pub fn check_logical_slots(compute: &ComputeNode) -> Result<()> {
+    let pspec = compute
+        .state
+        .lock()
+        .unwrap()
+        .pspec
+        .clone()
+        .expect("spec must be set");
+
+    let mut client = Client::connect(compute.connstr.as_str(), NoTls)?;
+    let mut slots: Vec<LogicalSlotStatus> = get_logical_slots(&mut client)?
+        .into_iter()
+        .map(|slot| LogicalSlotStatus {
+            name: slot.name,
+            plugin: slot.plugin,
+            restart_lsn: slot.restart_lsn,
+            error: None,
+        })
+        .collect();
+    if slots.is_empty() {
+        return Ok(());
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create rt");
+    match rt.block_on(ping_safekeepers(&pspec)) {
+        Some(responses) => match wal_available_from(&responses) {
+            Some(wal_start_lsn) => check_wal_available(&mut slots, wal_start_lsn),
+            None => warn!("safekeepers don't report where their WAL starts, not checking slots"),
+        },
+        None => warn!("could not reach safekeepers, not checking logical replication slots"),
+    }
+
+    for slot in &slots {
+        if let Some(error) = &slot.error {
+            error!(
+                "logical replication slot {} is unusable: {}",
+                slot.name, error
+            );
+        }
+    }
+    compute.state.lock().unwrap().logical_slots = slots;
+    Ok(())
+}
+
+/// Write the record of the current logical slots, if it differs from `last_persisted`
+fn persist_logical_slots(
+    client: &mut Client,
+    pgdata: &Path,
+    timeline_id: TimelineId,
+    last_persisted: &mut Option<PersistedSlots>,
+) -> Result<()> {
+    let mut persisted = PersistedSlots {
+        timeline_id,
+        slots: Vec::new(),
+    };
+    for slot in get_logical_slots(client)? {
+        let state = match fs::read(pgdata.join("pg_replslot").join(&slot.name).join("state")) {
+            Ok(state) => state,
+            // The slot is being created or dropped, the next round will see it
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        persisted.slots.push(PersistedSlot {
+            name: slot.name,
+            plugin: slot.plugin,
+            database: slot.database,
+            restart_lsn: slot.restart_lsn,
+            confirmed_flush_lsn: slot.confirmed_flush_lsn,
+            state: hex::encode(state),
+        });
+    }
+
+    let unchanged = match last_persisted {
+        Some(last) => *last == persisted,
+        None => persisted.slots.is_empty(),
+    };
+    if unchanged {
+        return Ok(());
+    }
+
+    let contents = serde_json::to_vec(&persisted)?;
+    let prefix = format!("neon-file:{LOGICAL_SLOTS_AUX_PATH}");
+    client.execute(
+        "SELECT pg_catalog.pg_logical_emit_message(false, $1, $2::bytea)",
+        &[&prefix, &contents],
+    )?;
+    debug!(
+        "persisted {} logical replication slots",
+        persisted.slots.len()
+    );
+    *last_persisted = Some(persisted);
+    Ok(())
+}
+
+fn persist_logical_slots_loop(compute: &ComputeNode, timeline_id: TimelineId) {
+    let pgdata = Path::new(&compute.pgdata);
+    // Only a record of this timeline is up to date, so that a branch writes its own
+    let mut last_persisted = fs::read(pgdata.join(LOGICAL_SLOTS_AUX_PATH))
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<PersistedSlots>(&contents).ok())
+        .filter(|persisted| persisted.timeline_id == timeline_id);
+
+    let mut connstr = compute.connstr.clone();
+    connstr
+        .query_pairs_mut()
+        .append_pair("application_name", "compute_ctl:logical_slots");
+
+    let mut client: Option<Client> = None;
+    loop {
+        thread::sleep(PERSIST_INTERVAL);
+        if compute.get_status() != ComputeStatus::Running {
+            continue;
+        }
+
+        // Reuse the connection while it's alive
+        if !matches!(&client, Some(cli) if !cli.is_closed()) {
+            match Client::connect(connstr.as_str(), NoTls) {
+                Ok(cli) => client = Some(cli),
+                Err(e) => {
+                    warn!(
+                        "could not connect to Postgres to persist logical replication slots: {e}"
+                    );
+                    continue;
+                }
+            }
+        }
+        let cli = client.as_mut().unwrap();
+        if let Err(e) = persist_logical_slots(cli, pgdata, timeline_id, &mut last_persisted) {
+            warn!("failed to persist logical replication slots: {e:#}");
+        }
+    }
+}
+
+/// Spawns a background thread to keep the record of logical replication slots up to date.
+/// Do nothing if the compute is not a primary.
+pub fn launch_logical_slots_persister(compute: &Arc<ComputeNode>) {
+    let timeline_id = {
+        let state = compute.state.lock().unwrap();
+        let spec = state.pspec.as_ref().expect("Spec must be set");
+        if spec.spec.mode != ComputeMode::Primary {
+            return;
+        }
+        spec.timeline_id
+    };
+    let compute = compute.clone();
+
+    thread::Builder::new()
+        .name("logical-slots-persister".into())
+        .spawn(move || persist_logical_slots_loop(&compute, timeline_id))
+        .expect("cannot launch logical slots persister thread");
+}
@@ -222,7 +222,9 @@ fn wait_for_postgres_start(compute: &ComputeNode) {
 // (or open any sessions) yet.
 fn get_database_stats(cli: &mut Client) -> anyhow::Result<(f64, i64)> {
     // Filter out `postgres` database as `compute_ctl` and other monitoring tools
-    // like `postgres_exporter` use it to query Postgres statistics.
+    // like `postgres_exporter` use it to query Postgres statistics. The metric queries
+    // of `compute_ctl` that run in other databases turn off `track_activities`, so they
+    // don't add to `active_time`.
     // Use explicit 8 bytes type casts to match Rust types.
     let stats = cli.query_one(
         "SELECT coalesce(sum(active_time), 0.0)::float8 AS total_active_time,
@@ -0,0 +1,354 @@
+//! Online failover to another pageserver.
+//!
+//! When the storage controller migrates a tenant, the control plane reconfigures the compute
+//! through `/configure`. If the pageserver becomes unreachable before that happens, Postgres
+//! keeps retrying it, so compute_ctl watches the statistics of the page requests to each
+//! shard, as returned by `neon.pageserver_stats()`. When a shard keeps failing, stops
+//! responding or responds too slowly, it asks the storage controller where the tenant is
+//! attached, with its `locate` API, and if that's elsewhere it writes the new
+//! `neon.pageserver_connstring` to `postgresql.conf` and reloads it, like a reconfiguration
+//! would. Postgres then reconnects all backends to the new pageservers. If the tenant was
+//! split or merged, or its stripe size changed, only switching pageservers isn't enough, so
+//! compute_ctl fetches the latest spec from the control plane and reconfigures with it instead.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use compute_api::responses::ComputeStatus;
+use compute_api::spec::PageserverFailoverSpec;
+use postgres::{Client, NoTls};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use url::Url;
+use utils::id::TenantId;
+
+use crate::compute::{ComputeNode, ParsedSpec};
+use crate::spec::get_spec_from_control_plane;
+
+/// Cumulative statistics of the page requests to a shard, as returned by
+/// `neon.pageserver_stats()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShardStats {
+    pub requests: u64,
+    pub responses: u64,
+    pub errors: u64,
+    pub wait_us: u64,
+}
+
+/// Whether a shard looks degraded, given its statistics at the start and at the end of a
+/// check interval: requests failed, or none were answered, or on average they waited for
+/// longer than `max_wait`.
+pub fn is_degraded(prev: &ShardStats, cur: &ShardStats, max_wait: Duration) -> bool {
+    let requests = cur.requests.saturating_sub(prev.requests);
+    let responses = cur.responses.saturating_sub(prev.responses);
+    let errors = cur.errors.saturating_sub(prev.errors);
+    let wait_us = cur.wait_us.saturating_sub(prev.wait_us);
+
+    if errors > 0 || (requests > 0 && responses == 0) {
+        return true;
+    }
+    responses > 0 && wait_us / responses > max_wait.as_micros() as u64
+}
+
+/// The part of the storage controller's `locate` response that we need
+#[derive(Deserialize)]
+struct TenantLocateResponse {
+    shards: Vec<TenantLocateResponseShard>,
+    shard_params: TenantLocateResponseShardParams,
+}
+
+#[derive(Deserialize)]
+struct TenantLocateResponseShard {
+    listen_pg_addr: String,
+    listen_pg_port: u16,
+}
+
+#[derive(Deserialize)]
+struct TenantLocateResponseShardParams {
+    stripe_size: u32,
+}
+
+/// Where the storage controller says the tenant is attached
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TenantLocation {
+    /// Address of the pageserver of each shard
+    pub shards: Vec<(String, u16)>,
+    pub stripe_size: u32,
+}
+
+/// Whether the tenant was split or merged, or its stripe size changed, since Postgres was
+/// configured with `current_connstr` and `current_stripe_size`. The pageserver connection
+/// strings can then not simply be replaced.
+pub fn shard_layout_changed(
+    current_connstr: &str,
+    current_stripe_size: Option<usize>,
+    location: &TenantLocation,
+) -> bool {
+    let shard_count = current_connstr.split(',').count();
+    if location.shards.len() != shard_count {
+        return true;
+    }
+    // The stripe size doesn't matter to an unsharded tenant
+    shard_count > 1 && current_stripe_size != Some(location.stripe_size as usize)
+}
+
+/// The `pageserver_connstring` for pageservers at the given addresses, one per shard. Keeps
+/// the user and options of the current connection strings, if they are URLs.
+pub fn connstr_for_locations(current: &str, locations: &[(String, u16)]) -> String {
+    let templates: Vec<Option<Url>> = current
+        .split(',')
+        .map(|connstr| Url::parse(connstr).ok().filter(|url| url.has_host()))
+        .collect();
+
+    locations
+        .iter()
+        .enumerate()
+        .map(|(shard, (host, port))| {
+            let template = templates
+                .get(shard)
+                .or(templates.first())
+                .cloned()
+                .flatten();
+            if let Some(mut url) = template {
+                if url.set_host(Some(host)).is_ok() && url.set_port(Some(*port)).is_ok() {
+                    return url.to_string();
+                }
+            }
+            format!("postgresql://no_user@{host}:{port}")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Ask the storage controller where the tenant is attached
+fn locate_tenant(
+    spec: &PageserverFailoverSpec,
+    tenant_id: TenantId,
+    auth_token: Option<&str>,
+) -> Result<TenantLocation> {
+    let url = format!(
+        "{}/debug/v1/tenant/{tenant_id}/locate",
+        spec.storage_controller_url.trim_end_matches('/')
+    );
+    let request = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(&url);
+    request_location(request, tenant_id, auth_token)
+}
+
+/// Send a request to the storage controller that is answered with where the tenant is attached:
+/// its `locate` or `wake` API.
+pub(crate) fn request_location(
+    mut request: reqwest::blocking::RequestBuilder,
+    tenant_id: TenantId,
+    auth_token: Option<&str>,
+) -> Result<TenantLocation> {
+    if let Some(token) = auth_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send()?;
+    if !response.status().is_success() {
+        bail!("storage controller responded with {}", response.status());
+    }
+    let located: TenantLocateResponse = response
+        .json()
+        .context("invalid locate response from storage controller")?;
+    let location = TenantLocation {
+        shards: located
+            .shards
+            .into_iter()
+            .map(|shard| (shard.listen_pg_addr, shard.listen_pg_port))
+            .collect(),
+        stripe_size: located.shard_params.stripe_size,
+    };
+    if location.shards.is_empty() {
+        bail!("storage controller doesn't know where tenant {tenant_id} is");
+    }
+    Ok(location)
+}
+
+/// Fetch the latest spec from the control plane, for when the tenant's shards changed in a way
+/// that only a full reconfiguration handles
+pub(crate) fn fetch_spec(compute: &ComputeNode) -> Result<ParsedSpec> {
+    let Some((control_plane_uri, compute_id)) = &compute.control_plane else {
+        bail!("the spec doesn't come from the control plane, waiting for a reconfiguration");
+    };
+    let spec = get_spec_from_control_plane(control_plane_uri, compute_id)?
+        .context("control plane has no spec for the compute")?;
+    ParsedSpec::try_from(spec).map_err(|msg| anyhow!(msg))
+}
+
+fn query_stats(client: &mut Client) -> Result<HashMap<i32, ShardStats>> {
+    Ok(client
+        .query(
+            "SELECT shard_no, requests, responses, errors, wait_us FROM neon.pageserver_stats()",
+            &[],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get::<_, i32>(0),
+                ShardStats {
+                    requests: row.get::<_, i64>(1) as u64,
+                    responses: row.get::<_, i64>(2) as u64,
+                    errors: row.get::<_, i64>(3) as u64,
+                    wait_us: row.get::<_, i64>(4) as u64,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Ask the storage controller where the tenant is, and switch to it if it moved
+fn fail_over(compute: &ComputeNode, spec: &PageserverFailoverSpec) -> Result<bool> {
+    let (tenant_id, current, stripe_size, auth_token) = {
+        let state = compute.state.lock().unwrap();
+        let pspec = state.pspec.as_ref().expect("spec must be set");
+        (
+            pspec.tenant_id,
+            pspec.pageserver_connstr.clone(),
+            pspec.spec.shard_stripe_size,
+            pspec.storage_auth_token.clone(),
+        )
+    };
+
+    let location = locate_tenant(spec, tenant_id, auth_token.as_deref())?;
+    if shard_layout_changed(&current, stripe_size, &location) {
+        warn!("shards of tenant {tenant_id} changed, reloading the spec");
+        let pspec = fetch_spec(compute)?;
+        let mut state = compute.state.lock().unwrap();
+        if state.status != ComputeStatus::Running {
+            bail!(
+                "cannot reload the spec, compute status is {:?}",
+                state.status
+            );
+        }
+        // The configurator applies it like a spec sent with `/configure`
+        state.pspec = Some(pspec);
+        state.status = ComputeStatus::ConfigurationPending;
+        compute.state_changed.notify_all();
+        return Ok(true);
+    }
+    let connstr = connstr_for_locations(&current, &location.shards);
+    if connstr == current {
+        info!("tenant {tenant_id} has not moved, waiting for the storage controller");
+        return Ok(false);
+    }
+
+    info!("switching pageservers from {current} to {connstr}");
+    compute.switch_pageservers(&connstr)?;
+    compute.state.lock().unwrap().metrics.pageserver_failovers += 1;
+    Ok(true)
+}
+
+fn pageserver_failover_main(compute: &ComputeNode) {
+    let mut connstr = compute.connstr.clone();
+    connstr
+        .query_pairs_mut()
+        .append_pair("application_name", "compute_ctl:pageserver_failover");
+
+    let mut client: Option<Client> = None;
+    let mut prev_stats: HashMap<i32, ShardStats> = HashMap::new();
+    let mut degraded_checks = 0;
+    loop {
+        let (status, spec) = {
+            let state = compute.state.lock().unwrap();
+            (
+                state.status,
+                state
+                    .pspec
+                    .as_ref()
+                    .filter(|pspec| pspec.spec.pageserver_connstring.is_some())
+                    .and_then(|pspec| pspec.spec.pageserver_failover.clone()),
+            )
+        };
+        match status {
+            ComputeStatus::Failed | ComputeStatus::Terminated => break,
+            ComputeStatus::Running => {}
+            _ => {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        }
+        // The spec may enable it on reconfiguration
+        let Some(spec) = spec else {
+            thread::sleep(Duration::from_secs(10));
+            continue;
+        };
+        thread::sleep(Duration::from_millis(spec.check_interval_ms));
+
+        // Reuse the connection while it's alive, so that reading the stats needs nothing
+        // from the pageserver
+        if !matches!(&client, Some(cli) if !cli.is_closed()) {
+            // Don't get stuck if the stats can't be read
+            let connect = || {
+                let mut cli = Client::connect(connstr.as_str(), NoTls)?;
+                cli.simple_query("SET statement_timeout = '5s'")?;
+                Ok::<_, postgres::Error>(cli)
+            };
+            match connect() {
+                Ok(cli) => client = Some(cli),
+                Err(e) => {
+                    warn!("could not connect to Postgres to check pageservers: {e}");
+                    continue;
+                }
+            }
+        }
+        let stats = match query_stats(client.as_mut().unwrap()) {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("failed to read pageserver request statistics: {e:#}");
+                continue;
+            }
+        };
+
+        let max_wait = Duration::from_millis(spec.max_wait_ms);
+        let degraded: Vec<i32> = stats
+            .iter()
+            .filter(|(shard, cur)| {
+                prev_stats
+                    .get(shard)
+                    .is_some_and(|prev| is_degraded(prev, cur, max_wait))
+            })
+            .map(|(shard, _)| *shard)
+            .collect();
+        prev_stats = stats;
+
+        if degraded.is_empty() {
+            degraded_checks = 0;
+            continue;
+        }
+        degraded_checks += 1;
+        debug!("pageserver shards {degraded:?} degraded for {degraded_checks} checks");
+        if degraded_checks < spec.degraded_checks {
+            continue;
+        }
+
+        warn!("pageserver shards {degraded:?} are degraded, locating the tenant");
+        match fail_over(compute, &spec) {
+            Ok(true) => {
+                degraded_checks = 0;
+                prev_stats.clear();
+            }
+            Ok(false) => {}
+            Err(e) => warn!("pageserver failover failed: {e:#}"),
+        }
+    }
+}
+
+/// Spawns a background thread that switches to other pageservers when the current ones
+/// degrade, if the spec enables it.
+pub fn launch_pageserver_failover(compute: &Arc<ComputeNode>) {
+    let compute = Arc::clone(compute);
+
+    thread::Builder::new()
+        .name("pageserver-failover".into())
+        .spawn(move || {
+            pageserver_failover_main(&compute);
+            info!("pageserver failover thread is exited");
+        })
+        .expect("cannot launch pageserver failover thread");
+}
@@ -18,7 +18,7 @@
 use tokio_postgres::NoTls;
 use tracing::{debug, error, info, instrument};
 
-use compute_api::spec::{Database, GenericOption, GenericOptions, PgIdent, Role};
+use compute_api::spec::{Database, GenericOption, GenericOptions, NextPassword, PgIdent, Role};
 
 const POSTGRES_WAIT_TIMEOUT: Duration = Duration::from_millis(60 * 1000); // milliseconds
 
@@ -132,6 +132,25 @@ fn find_ref(&self, name: &str) -> Option<&GenericOption> {
 
 pub trait RoleExt {
     fn to_pg_options(&self) -> String;
+    fn to_previous_password_pg_options(&self, next: &NextPassword) -> String;
+}
+
+/// Append the `PASSWORD` option for an encrypted password from the spec.
+fn push_password_option(params: &mut String, password: Option<&String>) {
+    if let Some(pass) = password {
+        // Some time ago we supported only md5 and treated all encrypted_password as md5.
+        // Now we also support SCRAM-SHA-256 and to preserve compatibility
+        // we treat all encrypted_password as md5 unless they starts with SCRAM-SHA-256.
+        if pass.starts_with("SCRAM-SHA-256") {
+            write!(params, " PASSWORD '{pass}'")
+                .expect("String is documented to not to error during write operations");
+        } else {
+            write!(params, " PASSWORD 'md5{pass}'")
+                .expect("String is documented to not to error during write operations");
+        }
+    } else {
+        params.push_str(" PASSWORD NULL");
+    }
 }
 
 impl RoleExt for Role {
@@ -141,21 +160,22 @@ fn to_pg_options(&self) -> String {
         // XXX: consider putting LOGIN as a default option somewhere higher, e.g. in control-plane.
         let mut params: String = self.options.as_pg_options();
         params.push_str(" LOGIN");
+        push_password_option(&mut params, self.effective_password());
 
-        if let Some(pass) = &self.encrypted_password {
-            // Some time ago we supported only md5 and treated all encrypted_password as md5.
-            // Now we also support SCRAM-SHA-256 and to preserve compatibility
-            // we treat all encrypted_password as md5 unless they starts with SCRAM-SHA-256.
-            if pass.starts_with("SCRAM-SHA-256") {
-                write!(params, " PASSWORD '{pass}'")
-                    .expect("String is documented to not to error during write operations");
-            } else {
-                write!(params, " PASSWORD 'md5{pass}'")
-                    .expect("String is documented to not to error during write operations");
-            }
-        } else {
-            params.push_str(" PASSWORD NULL");
-        }
+        params
+    }
+
+    /// Options of the role that keeps the current password while the password is rotated
+    /// to `next`. It stops accepting the password at the end of the overlap.
+    fn to_previous_password_pg_options(&self, next: &NextPassword) -> String {
+        let mut params = String::from(" LOGIN");
+        push_password_option(&mut params, self.encrypted_password.as_ref());
+        write!(
+            params,
+            " VALID UNTIL '{}'",
+            next.overlap_until.format("%Y-%m-%d %H:%M:%S+00")
+        )
+        .expect("String is documented to not to error during write operations");
 
         params
     }
@@ -204,6 +224,7 @@ pub fn get_existing_roles(xact: &mut Transaction<'_>) -> Result<Vec<Role>> {
         .map(|row| Role {
             name: row.get("rolname"),
             encrypted_password: row.get("rolpassword"),
+            next_password: None,
             options: None,
         })
         .collect();
@@ -234,6 +255,7 @@ pub fn get_existing_dbs(client: &mut Client) -> Result<HashMap<String, Database>
             restrict_conn: row.get("restrict_conn"),
             invalid: row.get("invalid"),
             options: None,
+            objects: Default::default(),
         })
         .collect();
 
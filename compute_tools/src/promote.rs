@@ -0,0 +1,192 @@
+//! Promotion of a replica to a primary, in place.
+//!
+//! A `Replica` compute streams WAL from the safekeepers in hot standby. To promote it,
+//! compute_ctl first waits for it to replay what the safekeepers have committed so far, so that
+//! little is left to replay once the old primary is cut off. It then fences the old primary by
+//! running `postgres --sync-safekeepers`, like a primary does on start: that establishes a new
+//! term on the safekeepers, and returns the LSN where the old primary's WAL ends. Once replay
+//! has reached that too, Postgres is promoted with `pg_promote()`. The walproposer, which only
+//! starts when recovery is over, then streams the WAL of the new timeline to the safekeepers.
+//!
+//! The fence sticks: the old primary's walproposer is rejected by the new term and restarts,
+//! and then refuses to get elected again, as another compute's term was established after its
+//! own, which takes its Postgres down. A primary that is just starting and wasn't elected yet
+//! isn't fenced that way, so as a last check, promotion fails if the safekeepers have committed
+//! anything past the end of the old primary's WAL by the time replay got there.
+//!
+//! This needs the replica to be started with the safekeepers in its spec. The progress is
+//! reported in the `/status` API.
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use compute_api::responses::{ComputeStatus, PromotionState, PromotionStatus};
+use compute_api::spec::ComputeMode;
+use postgres::{Client, NoTls};
+use tracing::{error, info, warn};
+use utils::lsn::Lsn;
+
+use crate::compute::{ping_safekeepers, ComputeNode, ComputeState};
+use crate::sync_sk::max_commit_lsn;
+
+/// How long to wait for replay to reach the LSN of the safekeepers
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(300);
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long Postgres may take to finish recovery after `pg_promote()`
+const PROMOTE_TIMEOUT_SECS: i32 = 60;
+
+/// Check that a compute can be promoted: it has to be a running replica that knows the
+/// safekeepers, and not being promoted already.
+pub fn check_promotable(state: &ComputeState) -> Result<(), String> {
+    if state
+        .promotion
+        .as_ref()
+        .is_some_and(|p| p.state.is_in_progress())
+    {
+        return Err("promotion is already in progress".to_string());
+    }
+    if state.status != ComputeStatus::Running {
+        return Err(format!(
+            "invalid compute status for promotion request: {:?}",
+            state.status
+        ));
+    }
+    let pspec = state.pspec.as_ref().expect("spec must be set");
+    if pspec.spec.mode != ComputeMode::Replica {
+        return Err(format!(
+            "only a replica can be promoted, not {:?}",
+            pspec.spec.mode
+        ));
+    }
+    if pspec.safekeeper_connstrings.is_empty() {
+        return Err("replica was started without safekeepers".to_string());
+    }
+    Ok(())
+}
+
+fn update_status(compute: &ComputeNode, status: &PromotionStatus) {
+    compute.state.lock().unwrap().promotion = Some(status.clone());
+}
+
+fn replay_lsn(client: &mut Client) -> Result<Lsn> {
+    let row = client.query_one("SELECT pg_last_wal_replay_lsn()::text", &[])?;
+    let lsn: Option<String> = row.get(0);
+    match lsn {
+        Some(lsn) => Ok(Lsn::from_str(&lsn)?),
+        None => bail!("Postgres is not in recovery"),
+    }
+}
+
+fn wait_for_replay(client: &mut Client, target_lsn: Lsn) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        let lsn = replay_lsn(client)?;
+        if lsn >= target_lsn {
+            info!("replayed up to {lsn}, target was {target_lsn}");
+            return Ok(());
+        }
+        if start.elapsed() > REPLAY_TIMEOUT {
+            bail!("replay is at {lsn}, did not reach {target_lsn} in {REPLAY_TIMEOUT:?}");
+        }
+        thread::sleep(REPLAY_POLL_INTERVAL);
+    }
+}
+
+fn promote(compute: &ComputeNode, status: &mut PromotionStatus) -> Result<()> {
+    let pspec = compute
+        .state
+        .lock()
+        .unwrap()
+        .pspec
+        .clone()
+        .expect("spec must be set");
+    let mut client = Client::connect(compute.connstr.as_str(), NoTls)?;
+
+    // Catch up while the old primary may still be running. Not knowing where the safekeepers
+    // are is fine, fencing tells us anyway.
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create rt");
+    let commit_lsn = || {
+        rt.block_on(ping_safekeepers(&pspec))
+            .as_deref()
+            .and_then(max_commit_lsn)
+    };
+    match commit_lsn() {
+        Some(commit_lsn) => {
+            status.target_lsn = Some(commit_lsn);
+            update_status(compute, status);
+            wait_for_replay(&mut client, commit_lsn)?;
+        }
+        None => warn!("could not get the commit LSN of the safekeepers, fencing right away"),
+    }
+
+    status.state = PromotionState::Fencing;
+    update_status(compute, status);
+    let fence_lsn = compute
+        .fence_safekeepers(pspec.storage_auth_token.clone())
+        .context("failed to fence the old primary")?;
+    info!("fenced the old primary, its WAL ends at {fence_lsn}");
+
+    status.state = PromotionState::FinalReplay;
+    status.target_lsn = Some(fence_lsn);
+    update_status(compute, status);
+    wait_for_replay(&mut client, fence_lsn)?;
+
+    // Nothing but an old primary that got elected before it was ever fenced writes to the
+    // timeline until we're promoted
+    match commit_lsn() {
+        Some(commit_lsn) if commit_lsn > fence_lsn => {
+            bail!(
+                "the old primary was not fenced: the safekeepers committed up to {commit_lsn}, past {fence_lsn}"
+            )
+        }
+        Some(_) => {}
+        None => bail!("could not check the commit LSN of the safekeepers before promoting"),
+    }
+
+    status.state = PromotionState::Promoting;
+    update_status(compute, status);
+    // Commits have to wait for the safekeepers, like on any primary
+    client.simple_query("ALTER SYSTEM SET synchronous_standby_names = 'walproposer'")?;
+    client.simple_query("SELECT pg_reload_conf()")?;
+    let promoted: bool = client
+        .query_one("SELECT pg_promote(true, $1)", &[&PROMOTE_TIMEOUT_SECS])?
+        .get(0);
+    if !promoted {
+        bail!("Postgres did not finish promotion in {PROMOTE_TIMEOUT_SECS}s");
+    }
+
+    let mut state = compute.state.lock().unwrap();
+    if let Some(pspec) = state.pspec.as_mut() {
+        pspec.spec.mode = ComputeMode::Primary;
+    }
+    Ok(())
+}
+
+/// Promote the replica, reporting the progress in the compute state. The caller has checked
+/// it with [`check_promotable`]. Returns the final status.
+pub fn promote_replica(compute: &ComputeNode) -> PromotionStatus {
+    let mut status = PromotionStatus {
+        state: PromotionState::CatchingUp,
+        ..Default::default()
+    };
+    update_status(compute, &status);
+
+    let start = Instant::now();
+    match promote(compute, &mut status) {
+        Ok(()) => {
+            status.state = PromotionState::Completed;
+            info!("promoted to primary in {:?}", start.elapsed());
+        }
+        Err(e) => {
+            error!("failed to promote to primary: {e:#}");
+            status.state = PromotionState::Failed;
+            status.error = Some(format!("{e:#}"));
+        }
+    }
+    update_status(compute, &status);
+    status
+}
@@ -3,19 +3,22 @@
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
 use postgres::config::Config;
-use postgres::{Client, NoTls};
+use postgres::{Client, NoTls, Transaction};
 use reqwest::StatusCode;
 use tracing::{error, info, info_span, instrument, span_enabled, warn, Level};
 
+use crate::catalog::get_existing_objects;
 use crate::config;
+use crate::db_objects::{plan_objects, plan_privileges};
 use crate::logger::inlinify;
 use crate::migration::MigrationRunner;
 use crate::params::PG_HBA_ALL_MD5;
 use crate::pg_helpers::*;
 
 use compute_api::responses::{ControlPlaneComputeStatus, ControlPlaneSpecResponse};
-use compute_api::spec::{ComputeSpec, PgIdent, Role};
+use compute_api::spec::{previous_password_role, ComputeSpec, NextPassword, PgIdent, Role};
 
 // Do control plane request and return response if any. In case of error it
 // returns a bool flag indicating whether it makes sense to retry the request
@@ -186,6 +189,13 @@ pub fn cleanup_instance(client: &mut Client) -> Result<()> {
 /// deletion and update.
 #[instrument(skip_all)]
 pub fn handle_roles(spec: &ComputeSpec, client: &mut Client) -> Result<()> {
+    // Don't switch any role to its next password if the previous one couldn't be accepted
+    // alongside it.
+    let now = Utc::now();
+    for role in &spec.cluster.roles {
+        role.check_rotation(now).map_err(|msg| anyhow!(msg))?;
+    }
+
     let mut xact = client.transaction()?;
     let existing_roles: Vec<Role> = get_existing_roles(&mut xact)?;
 
@@ -224,6 +234,11 @@ pub fn handle_roles(spec: &ComputeSpec, client: &mut Client) -> Result<()> {
 
                     // XXX: with a limited number of roles it is fine, but consider making it a HashMap
                     if existing_roles.iter().any(|r| r.name == op.name) {
+                        // The password is gone, and so is the rotation
+                        let previous = previous_password_role(&op.name);
+                        let query = format!("DROP ROLE IF EXISTS {}", previous.pg_quote());
+                        xact.execute(query.as_str(), &[])?;
+
                         let query: String = format!(
                             "ALTER ROLE {} RENAME TO {}",
                             op.name.pg_quote(),
@@ -250,6 +265,8 @@ pub fn handle_roles(spec: &ComputeSpec, client: &mut Client) -> Result<()> {
         let name = &role.name;
         // XXX: with a limited number of roles it is fine, but consider making it a HashMap
         let pg_role = existing_roles.iter().find(|r| r.name == *name);
+        // During a password rotation, the role already has the next password
+        let password = role.effective_password();
 
         enum RoleAction {
             None,
@@ -257,8 +274,8 @@ enum RoleAction {
             Create,
         }
         let action = if let Some(r) = pg_role {
-            if (r.encrypted_password.is_none() && role.encrypted_password.is_some())
-                || (r.encrypted_password.is_some() && role.encrypted_password.is_none())
+            if (r.encrypted_password.is_none() && password.is_some())
+                || (r.encrypted_password.is_some() && password.is_none())
             {
                 RoleAction::Update
             } else if let Some(pg_pwd) = &r.encrypted_password {
@@ -275,7 +292,7 @@ enum RoleAction {
                 } else {
                     pg_pwd
                 };
-                if pg_pwd != *role.encrypted_password.as_ref().unwrap() {
+                if pg_pwd != *password.unwrap() {
                     RoleAction::Update
                 } else {
                     RoleAction::None
@@ -329,11 +346,73 @@ enum RoleAction {
         }
     }
 
+    handle_previous_password_roles(spec, &mut xact)?;
+
     xact.commit()?;
 
     Ok(())
 }
 
+/// Maximum length of Postgres identifiers, plus one
+const NAMEDATALEN: usize = 64;
+
+/// Create the roles that keep the current passwords of the roles whose password is being
+/// rotated, see [`NextPassword`], and drop them once the rotation is over.
+fn handle_previous_password_roles(spec: &ComputeSpec, xact: &mut Transaction<'_>) -> Result<()> {
+    let now = Utc::now();
+    let existing_roles: Vec<Role> = get_existing_roles(xact)?;
+
+    for role in &spec.cluster.roles {
+        let previous = previous_password_role(&role.name);
+        let exists = existing_roles.iter().any(|r| r.name == previous);
+
+        let Some(next) = role.rotation_at(now) else {
+            if exists {
+                info!("password rotation of role '{}' is over", role.name);
+                let query = format!("DROP ROLE {}", previous.pg_quote());
+                xact.execute(query.as_str(), &[])?;
+            }
+            continue;
+        };
+
+        // Postgres would truncate the name, and it could then clash with another role
+        if previous.len() >= NAMEDATALEN {
+            warn!(
+                "role name '{}' is too long to keep its previous password",
+                role.name
+            );
+            continue;
+        }
+
+        info!(
+            "keeping previous password of role '{}' until {}",
+            role.name, next.overlap_until
+        );
+        let query = if exists {
+            format!("ALTER ROLE {}", previous.pg_quote())
+        } else {
+            format!(
+                "CREATE ROLE {} IN ROLE {}",
+                previous.pg_quote(),
+                role.name.pg_quote()
+            )
+        };
+        xact.execute(
+            &format!("{query}{}", role.to_previous_password_pg_options(next)),
+            &[],
+        )?;
+        // Whoever logs in with the previous password acts as the role itself
+        let query = format!(
+            "ALTER ROLE {} SET role = {}",
+            previous.pg_quote(),
+            escape_literal(&role.name)
+        );
+        xact.execute(query.as_str(), &[])?;
+    }
+
+    Ok(())
+}
+
 /// Reassign all dependent objects and delete requested roles.
 #[instrument(skip_all)]
 pub fn handle_role_deletions(spec: &ComputeSpec, connstr: &str, client: &mut Client) -> Result<()> {
@@ -365,6 +444,10 @@ pub fn handle_role_deletions(spec: &ComputeSpec, connstr: &str, client: &mut Cli
             // We do not check either role exists or not,
             // Postgres will take care of it for us
             if op.action == "delete_role" {
+                let previous = previous_password_role(&op.name);
+                let query: String = format!("DROP ROLE IF EXISTS {}", previous.pg_quote());
+                xact.execute(query.as_str(), &[])?;
+
                 let query: String = format!("DROP ROLE IF EXISTS {}", &op.name.pg_quote());
 
                 warn!("deleting role '{}'", &op.name);
@@ -707,6 +790,74 @@ pub fn handle_grants(
     Ok(())
 }
 
+/// Create and alter the schemas, extensions and privileges that the spec declares in each
+/// database. Schemas and extensions go first, in their own transaction: privileges are then
+/// compared with the catalog again, so that grants on all tables in a schema also cover the
+/// tables that the extensions created.
+#[instrument(skip_all)]
+pub fn handle_database_objects(
+    spec: &ComputeSpec,
+    client: &mut Client,
+    connstr: &str,
+) -> Result<()> {
+    let existing_dbs = get_existing_dbs(client)?;
+
+    for db in spec
+        .cluster
+        .databases
+        .iter()
+        .filter(|db| !db.objects.is_empty())
+    {
+        match existing_dbs.get(&db.name) {
+            Some(pg_db) if pg_db.restrict_conn || pg_db.invalid => {
+                info!(
+                    "skipping database objects for db {} (invalid: {}, connections not allowed: {})",
+                    db.name, pg_db.invalid, pg_db.restrict_conn
+                );
+                continue;
+            }
+            Some(_) => {}
+            None => {
+                bail!(
+                    "database {} doesn't exist in Postgres after handle_databases()",
+                    db.name
+                );
+            }
+        }
+
+        let mut conf = Config::from_str(connstr)?;
+        conf.dbname(&db.name);
+        let mut db_client = conf.connect(NoTls)?;
+
+        let existing = get_existing_objects(&mut db_client, db)?;
+        apply_database_statements(&mut db_client, &db.name, &plan_objects(db, &existing))?;
+
+        let existing = get_existing_objects(&mut db_client, db)?;
+        apply_database_statements(&mut db_client, &db.name, &plan_privileges(db, &existing)?)?;
+    }
+
+    Ok(())
+}
+
+fn apply_database_statements(
+    db_client: &mut Client,
+    dbname: &str,
+    statements: &[String],
+) -> Result<()> {
+    if statements.is_empty() {
+        return Ok(());
+    }
+
+    let mut xact = db_client.transaction()?;
+    for query in statements {
+        info!("database {}: {}", dbname, query);
+        xact.simple_query(query)?;
+    }
+    xact.commit()?;
+
+    Ok(())
+}
+
 /// Create required system extensions
 #[instrument(skip_all)]
 pub fn handle_extensions(spec: &ComputeSpec, client: &mut Client) -> Result<()> {
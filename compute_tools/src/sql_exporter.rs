@@ -0,0 +1,370 @@
+//! Query-based Prometheus metrics, served on the `/metrics` endpoint.
+//!
+//! The queries come from the `metric_queries` of the spec and from the `--sql-exporter-config`
+//! file. An [`SqlExporter`] runs them on scrape, or reuses their results for the query's
+//! `cache_interval_ms`. It exists while the compute is `Running`: [`launch_sql_exporter`]
+//! creates it from the current spec when the compute starts running or is reconfigured, and
+//! drops it, closing its connections, when the compute leaves that state.
+//!
+//! Scrapes are serialized, and each database that the queries run in has at most one
+//! connection, so that frequent or concurrent scrapes can't pile up connections to Postgres.
+//! The connections disable `track_activities`, so that the queries don't add to the
+//! `active_time` of `pg_stat_database`, which the activity monitor takes for user activity.
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use compute_api::responses::ComputeStatus;
+use compute_api::spec::{MetricQuery, MetricType};
+use metrics::core::Collector;
+use metrics::proto::MetricFamily;
+use metrics::{opts, CounterVec, Encoder, GaugeVec, IntCounterVec, TextEncoder};
+use postgres::{Client, NoTls, SimpleQueryMessage};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use url::Url;
+
+use crate::compute::ComputeNode;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Contents of the `--sql-exporter-config` file
+#[derive(Debug, Default, Deserialize)]
+pub struct SqlExporterConfig {
+    #[serde(default)]
+    pub metric_queries: Vec<MetricQuery>,
+}
+
+impl SqlExporterConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path)?;
+        let config: Self = serde_json::from_slice(&contents)?;
+        check_unique_metric_names(&config.metric_queries)?;
+        Ok(config)
+    }
+}
+
+/// Two queries with the same metric name would render conflicting metric families
+pub fn check_unique_metric_names(queries: &[MetricQuery]) -> Result<()> {
+    let mut names = HashSet::new();
+    for query in queries {
+        if !names.insert(&query.metric_name) {
+            bail!("duplicate metric name {}", query.metric_name);
+        }
+    }
+    Ok(())
+}
+
+/// Check a query definition before it's used, so that a bad one is reported once instead of
+/// on every scrape
+pub fn validate_query(query: &MetricQuery) -> Result<()> {
+    if query.values.is_empty() {
+        bail!("no value columns");
+    }
+    if query.values.len() > 1 && query.value_label.is_none() {
+        bail!("value_label is required with several value columns");
+    }
+    // Checks the metric and label names
+    GaugeVec::new(
+        opts!(query.metric_name.clone(), query.help.clone()),
+        &label_names(query),
+    )?;
+    Ok(())
+}
+
+fn label_names(query: &MetricQuery) -> Vec<&str> {
+    let mut names: Vec<&str> = query.key_labels.iter().map(|l| l.as_str()).collect();
+    if let Some(value_label) = &query.value_label {
+        names.push(value_label);
+    }
+    names
+}
+
+/// Turn the rows of a query into metrics. Rows are given as the text of each of the `columns`,
+/// as returned by the simple query protocol. NULL values give no sample, NULL labels are empty.
+pub fn rows_to_metrics(
+    query: &MetricQuery,
+    columns: &[String],
+    rows: &[Vec<Option<String>>],
+) -> Result<Vec<MetricFamily>> {
+    // Column names come with the rows, so there are none to check without rows
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let column_idx = |name: &str| {
+        columns
+            .iter()
+            .position(|c| c == name)
+            .with_context(|| format!("query has no column {name}"))
+    };
+    let label_columns = query
+        .key_labels
+        .iter()
+        .map(|l| column_idx(l))
+        .collect::<Result<Vec<_>>>()?;
+    let value_columns = query
+        .values
+        .iter()
+        .map(|v| column_idx(v))
+        .collect::<Result<Vec<_>>>()?;
+
+    let opts = opts!(query.metric_name.clone(), query.help.clone());
+    let label_names = label_names(query);
+    let (gauges, counters) = match query.metric_type {
+        MetricType::Gauge => (Some(GaugeVec::new(opts, &label_names)?), None),
+        MetricType::Counter => (None, Some(CounterVec::new(opts, &label_names)?)),
+    };
+
+    for row in rows {
+        let mut labels: Vec<&str> = label_columns
+            .iter()
+            .map(|&idx| row[idx].as_deref().unwrap_or(""))
+            .collect();
+        for (value_name, &idx) in query.values.iter().zip(&value_columns) {
+            let Some(value) = &row[idx] else {
+                continue;
+            };
+            let value = f64::from_str(value)
+                .with_context(|| format!("column {value_name} is not numeric: {value:?}"))?;
+
+            if query.value_label.is_some() {
+                labels.push(value_name);
+            }
+            if let Some(gauges) = &gauges {
+                gauges.with_label_values(&labels).set(value);
+            }
+            if let Some(counters) = &counters {
+                if value >= 0.0 {
+                    counters.with_label_values(&labels).inc_by(value);
+                } else {
+                    warn!(
+                        "skipping negative value {value} of counter {}",
+                        query.metric_name
+                    );
+                }
+            }
+            if query.value_label.is_some() {
+                labels.pop();
+            }
+        }
+    }
+
+    Ok(match (gauges, counters) {
+        (Some(gauges), _) => gauges.collect(),
+        (_, Some(counters)) => counters.collect(),
+        _ => unreachable!(),
+    })
+}
+
+struct CachedResult {
+    collected_at: Instant,
+    families: Vec<MetricFamily>,
+}
+
+#[derive(Default)]
+struct ExporterState {
+    /// At most one connection per database
+    connections: HashMap<String, Client>,
+    /// Latest results by index of the query
+    cache: HashMap<usize, CachedResult>,
+    /// Failed runs by metric name
+    errors: HashMap<String, u64>,
+    /// Duration of the latest run by metric name
+    durations: HashMap<String, f64>,
+}
+
+pub struct SqlExporter {
+    connstr: Url,
+    queries: Vec<MetricQuery>,
+    state: Mutex<ExporterState>,
+}
+
+impl SqlExporter {
+    /// Invalid queries are logged and left out, as are queries with the same metric name as an
+    /// earlier one
+    pub fn new(connstr: &Url, queries: Vec<MetricQuery>) -> Self {
+        let mut names = HashSet::new();
+        let queries = queries
+            .into_iter()
+            .filter(|query| match validate_query(query) {
+                Ok(()) if !names.insert(query.metric_name.clone()) => {
+                    error!(
+                        "ignoring metric query {}: duplicate metric name",
+                        query.metric_name
+                    );
+                    false
+                }
+                Ok(()) => true,
+                Err(e) => {
+                    error!("ignoring metric query {}: {e:#}", query.metric_name);
+                    false
+                }
+            })
+            .collect();
+        Self {
+            connstr: connstr.clone(),
+            queries,
+            state: Mutex::new(ExporterState::default()),
+        }
+    }
+
+    fn connect(&self, database: &str) -> Result<Client> {
+        let mut config = postgres::Config::from_str(self.connstr.as_str())?;
+        config
+            .dbname(database)
+            .application_name("compute_ctl:sql_exporter")
+            .options("-c track_activities=off")
+            .connect_timeout(CONNECT_TIMEOUT);
+        Ok(config.connect(NoTls)?)
+    }
+
+    fn run_query(
+        &self,
+        connections: &mut HashMap<String, Client>,
+        query: &MetricQuery,
+    ) -> Result<Vec<MetricFamily>> {
+        let database = query.database.as_deref().unwrap_or("postgres");
+        if connections.get(database).map_or(true, |c| c.is_closed()) {
+            connections.insert(database.to_string(), self.connect(database)?);
+        }
+        let client = connections.get_mut(database).unwrap();
+
+        client.batch_execute(&format!("SET statement_timeout = {}", query.timeout_ms))?;
+        let mut columns = Vec::new();
+        let mut rows = Vec::new();
+        for message in client.simple_query(&query.query)? {
+            if let SimpleQueryMessage::Row(row) = message {
+                if columns.is_empty() {
+                    columns = row.columns().iter().map(|c| c.name().to_string()).collect();
+                }
+                rows.push(
+                    (0..row.len())
+                        .map(|i| row.get(i).map(String::from))
+                        .collect(),
+                );
+            }
+        }
+        rows_to_metrics(query, &columns, &rows)
+    }
+
+    /// Run the queries whose cached results are too old, and render all the results in the
+    /// Prometheus text format. Blocks while another scrape is running.
+    pub fn scrape(&self) -> Result<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let mut families = Vec::new();
+        for (idx, query) in self.queries.iter().enumerate() {
+            let cache_interval = Duration::from_millis(query.cache_interval_ms);
+            if let Some(cached) = state.cache.get(&idx) {
+                if cached.collected_at.elapsed() < cache_interval {
+                    families.extend(cached.families.iter().cloned());
+                    continue;
+                }
+            }
+
+            let start = Instant::now();
+            match self.run_query(&mut state.connections, query) {
+                Ok(result) => {
+                    families.extend(result.iter().cloned());
+                    state.cache.insert(
+                        idx,
+                        CachedResult {
+                            collected_at: start,
+                            families: result,
+                        },
+                    );
+                }
+                Err(e) => {
+                    warn!("metric query {} failed: {e:#}", query.metric_name);
+                    *state.errors.entry(query.metric_name.clone()).or_default() += 1;
+                    // Don't serve stale results as if they were fresh
+                    state.cache.remove(&idx);
+                }
+            }
+            state
+                .durations
+                .insert(query.metric_name.clone(), start.elapsed().as_secs_f64());
+        }
+
+        let durations = GaugeVec::new(
+            opts!(
+                "compute_sql_exporter_query_duration_seconds",
+                "Duration of the latest run of each metric query"
+            ),
+            &["metric_name"],
+        )?;
+        for (metric_name, duration) in &state.durations {
+            durations.with_label_values(&[metric_name]).set(*duration);
+        }
+        families.extend(durations.collect());
+
+        let errors = IntCounterVec::new(
+            opts!(
+                "compute_sql_exporter_query_errors_total",
+                "Number of failed runs of each metric query"
+            ),
+            &["metric_name"],
+        )?;
+        for (metric_name, count) in &state.errors {
+            errors.with_label_values(&[metric_name]).inc_by(*count);
+        }
+        families.extend(errors.collect());
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&families, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+fn sql_exporter_main_loop(compute: &ComputeNode, config_queries: Vec<MetricQuery>) {
+    let mut state = compute.state.lock().unwrap();
+    loop {
+        let running = state.status == ComputeStatus::Running;
+        let mut exporter = compute.sql_exporter.lock().unwrap();
+        if running && exporter.is_none() {
+            let mut queries = config_queries.clone();
+            if let Some(pspec) = &state.pspec {
+                queries.extend(pspec.spec.metric_queries.iter().cloned());
+            }
+            if !queries.is_empty() {
+                info!("starting SQL exporter with {} queries", queries.len());
+                *exporter = Some(Arc::new(SqlExporter::new(&compute.connstr, queries)));
+            }
+        } else if !running && exporter.is_some() {
+            // A scrape in progress keeps its reference until it's done, then the connections
+            // are closed
+            info!("compute is {:?}, stopping SQL exporter", state.status);
+            *exporter = None;
+        }
+        drop(exporter);
+
+        if matches!(
+            state.status,
+            ComputeStatus::Failed | ComputeStatus::Terminated
+        ) {
+            break;
+        }
+        state = compute.state_changed.wait(state).unwrap();
+    }
+}
+
+/// Spawns a background thread that keeps the SQL exporter of `compute` in line with its state
+pub fn launch_sql_exporter(
+    compute: &Arc<ComputeNode>,
+    config_queries: Vec<MetricQuery>,
+) -> thread::JoinHandle<()> {
+    let compute = Arc::clone(compute);
+
+    thread::Builder::new()
+        .name("sql-exporter".into())
+        .spawn(move || {
+            sql_exporter_main_loop(&compute, config_queries);
+            info!("SQL exporter thread is exited");
+        })
+        .expect("cannot launch SQL exporter thread")
+}
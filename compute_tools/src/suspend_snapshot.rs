@@ -0,0 +1,418 @@
+//! Carrying the shared state of Postgres over a suspend of the compute.
+//!
+//! A compute that's suspended starts with an empty buffer pool and no cumulative statistics,
+//! so the first queries after it wakes up are slow, and autovacuum has to find out again which
+//! tables need it. When the compute is terminated with `/terminate?mode=suspend`, a primary
+//! captures the pages in the buffer pool, most used first, and the statistics of relations,
+//! as returned by `neon.get_buffer_pool_state()` and `neon.get_relation_stats()`. It writes
+//! them in the `neon/suspend_snapshot.json` aux file of its timeline, with a `neon-file:`
+//! logical message like [`crate::logical_slots`] does, before Postgres shuts down.
+//!
+//! The snapshot comes back with the basebackup. Once Postgres is running, and before the
+//! compute is reported as running, the statistics are restored and the pages are read back
+//! into the buffer pool, until the configured budget runs out. The snapshot is then deleted,
+//! so that it's never restored after a restart that wasn't a suspend. Session state, such as
+//! prepared statements, belongs to connections that don't survive a suspend anyway.
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use compute_api::spec::SuspendSnapshotSpec;
+use postgres::{Client, NoTls};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use url::Url;
+use utils::id::TimelineId;
+
+use crate::compute::ComputeNode;
+use crate::lfc_prewarm::{runs_to_prewarm, CachedRun};
+
+/// Path of the snapshot, relative to pgdata and in the aux files of the timeline
+pub const SUSPEND_SNAPSHOT_AUX_PATH: &str = "neon/suspend_snapshot.json";
+
+/// Upper bound on the pages read with one call, so that the budget is checked often enough
+const PREWARM_BATCH_PAGES: i32 = 64;
+
+/// The contents of the `neon/suspend_snapshot.json` aux file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuspendSnapshot {
+    /// Timeline the snapshot was captured on. Branches don't inherit it.
+    pub timeline_id: TimelineId,
+    /// The statistics can only be restored into the same version of Postgres
+    pub server_version_num: i32,
+    pub relations: Vec<RelationStats>,
+    /// Most used first
+    pub buffers: Vec<CachedRun>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelationStats {
+    /// 0 for shared relations
+    pub reldatabase: u32,
+    pub relid: u32,
+    /// Hex-encoded contents of the shared statistics entry of the relation
+    pub stats: String,
+}
+
+/// A page in the buffer pool, as returned by `neon.get_buffer_pool_state()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PooledBuffer {
+    pub reltablespace: u32,
+    pub reldatabase: u32,
+    pub relfilenode: u32,
+    pub forknum: i16,
+    pub blkno: i64,
+    pub usagecount: i16,
+}
+
+/// Group the pages into runs of consecutive blocks with the same usage count, most used first
+pub fn buffers_to_runs(buffers: &[PooledBuffer]) -> Vec<CachedRun> {
+    let mut buffers = buffers.to_vec();
+    buffers.sort_by_key(|buf| {
+        (
+            std::cmp::Reverse(buf.usagecount),
+            buf.reltablespace,
+            buf.reldatabase,
+            buf.relfilenode,
+            buf.forknum,
+            buf.blkno,
+        )
+    });
+
+    let mut runs: Vec<CachedRun> = Vec::new();
+    let mut prev: Option<PooledBuffer> = None;
+    for buf in buffers {
+        let extends = prev.is_some_and(|prev| {
+            prev.usagecount == buf.usagecount
+                && prev.reltablespace == buf.reltablespace
+                && prev.reldatabase == buf.reldatabase
+                && prev.relfilenode == buf.relfilenode
+                && prev.forknum == buf.forknum
+                && prev.blkno + 1 == buf.blkno
+        });
+        match runs.last_mut() {
+            Some(run) if extends => run.nblocks += 1,
+            _ => runs.push(CachedRun {
+                reltablespace: buf.reltablespace,
+                reldatabase: buf.reldatabase,
+                relfilenode: buf.relfilenode,
+                forknum: buf.forknum,
+                blkno: buf.blkno,
+                nblocks: 1,
+            }),
+        }
+        prev = Some(buf);
+    }
+    runs
+}
+
+/// Load the snapshot that came with the basebackup, if any
+pub fn load_suspend_snapshot(pgdata: &Path) -> Result<Option<SuspendSnapshot>> {
+    match fs::read(pgdata.join(SUSPEND_SNAPSHOT_AUX_PATH)) {
+        Ok(contents) => Ok(Some(
+            serde_json::from_slice(&contents).context("invalid suspend snapshot")?,
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_aux_file(client: &mut Client, contents: &[u8]) -> Result<()> {
+    let prefix = format!("neon-file:{SUSPEND_SNAPSHOT_AUX_PATH}");
+    client.execute(
+        "SELECT pg_catalog.pg_logical_emit_message(false, $1, $2::bytea)",
+        &[&prefix, &contents],
+    )?;
+    Ok(())
+}
+
+fn server_version_num(client: &mut Client) -> Result<i32> {
+    let row = client.query_one(
+        "SELECT pg_catalog.current_setting('server_version_num')::int4",
+        &[],
+    )?;
+    Ok(row.get(0))
+}
+
+fn connect_url(compute: &ComputeNode, dbname: Option<&str>) -> Url {
+    let mut connstr = compute.connstr.clone();
+    if let Some(dbname) = dbname {
+        connstr.set_path(dbname);
+    }
+    connstr
+        .query_pairs_mut()
+        .append_pair("application_name", "compute_ctl:suspend_snapshot");
+    connstr
+}
+
+fn connect(compute: &ComputeNode, dbname: Option<&str>) -> Result<Client> {
+    Ok(Client::connect(
+        connect_url(compute, dbname).as_str(),
+        NoTls,
+    )?)
+}
+
+/// Time left of the restore budget, rounded down to whole milliseconds. `None` once it has run
+/// out.
+pub fn remaining_budget(deadline: Instant, now: Instant) -> Option<Duration> {
+    let left = deadline.checked_duration_since(now)?;
+    let left = Duration::from_millis(left.as_millis() as u64);
+    (!left.is_zero()).then_some(left)
+}
+
+/// Connect for the restore, with what's left of the budget as the connect timeout and the
+/// statement_timeout, so that neither a slow connection nor a slow query can overrun it
+fn connect_within(
+    compute: &ComputeNode,
+    dbname: Option<&str>,
+    deadline: Instant,
+) -> Result<Client> {
+    let left = remaining_budget(deadline, Instant::now())
+        .context("suspend snapshot restore is out of budget")?;
+    let mut connstr = connect_url(compute, dbname);
+    // In whole seconds, and 0 would mean no timeout
+    connstr.query_pairs_mut().append_pair(
+        "connect_timeout",
+        &left.as_secs_f64().ceil().max(1.0).to_string(),
+    );
+    let mut client = Client::connect(connstr.as_str(), NoTls)?;
+
+    let left = remaining_budget(deadline, Instant::now())
+        .context("suspend snapshot restore is out of budget")?;
+    client.simple_query(&format!("SET statement_timeout = {}", left.as_millis()))?;
+    Ok(client)
+}
+
+/// Capture the snapshot and write it to the aux file. Must run while Postgres is still up,
+/// right before it's shut down.
+pub fn capture_suspend_snapshot(compute: &ComputeNode) -> Result<()> {
+    let start = Instant::now();
+    let timeline_id = {
+        let state = compute.state.lock().unwrap();
+        state.pspec.as_ref().expect("spec must be set").timeline_id
+    };
+
+    let mut client = connect(compute, None)?;
+    let server_version_num = server_version_num(&mut client)?;
+    let relations: Vec<RelationStats> = client
+        .query(
+            "SELECT reldatabase, relid, stats FROM neon.get_relation_stats()",
+            &[],
+        )?
+        .iter()
+        .map(|row| RelationStats {
+            reldatabase: row.get(0),
+            relid: row.get(1),
+            stats: hex::encode(row.get::<_, Vec<u8>>(2)),
+        })
+        .collect();
+    let buffers: Vec<PooledBuffer> = client
+        .query(
+            "SELECT reltablespace, reldatabase, relfilenode, relforknumber, relblocknumber, usagecount \
+             FROM neon.get_buffer_pool_state()",
+            &[],
+        )?
+        .iter()
+        .map(|row| PooledBuffer {
+            reltablespace: row.get(0),
+            reldatabase: row.get(1),
+            relfilenode: row.get(2),
+            forknum: row.get(3),
+            blkno: row.get(4),
+            usagecount: row.get(5),
+        })
+        .collect();
+
+    let snapshot = SuspendSnapshot {
+        timeline_id,
+        server_version_num,
+        relations,
+        buffers: buffers_to_runs(&buffers),
+    };
+    write_aux_file(&mut client, &serde_json::to_vec(&snapshot)?)?;
+    info!(
+        "captured suspend snapshot of {} relations and {} pages in {:?}",
+        snapshot.relations.len(),
+        buffers.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// Restore the statistics of the relations that still exist. Returns how many were restored.
+fn restore_relation_stats(
+    compute: &ComputeNode,
+    client: &mut Client,
+    relations: &[RelationStats],
+    deadline: Instant,
+) -> Result<u64> {
+    let mut by_database: HashMap<u32, Vec<&RelationStats>> = HashMap::new();
+    for rel in relations {
+        by_database.entry(rel.reldatabase).or_default().push(rel);
+    }
+    let databases: HashMap<u32, String> = client
+        .query(
+            "SELECT oid, datname FROM pg_catalog.pg_database WHERE datallowconn",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let mut restored = 0;
+    for (dboid, rels) in by_database {
+        if Instant::now() >= deadline {
+            info!("suspend snapshot restore is out of budget");
+            break;
+        }
+        // Shared relations are in the catalog of every database
+        let mut db_client;
+        let catalog_client = if dboid == 0 {
+            &mut *client
+        } else {
+            let Some(dbname) = databases.get(&dboid) else {
+                continue;
+            };
+            db_client = connect_within(compute, Some(dbname), deadline)?;
+            &mut db_client
+        };
+        let relids: Vec<u32> = rels.iter().map(|rel| rel.relid).collect();
+        let existing: Vec<u32> = catalog_client
+            .query(
+                "SELECT oid FROM pg_catalog.pg_class WHERE oid = ANY($1)",
+                &[&relids],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        for rel in rels.iter().filter(|rel| existing.contains(&rel.relid)) {
+            if Instant::now() >= deadline {
+                info!("suspend snapshot restore is out of budget");
+                return Ok(restored);
+            }
+            let stats = hex::decode(&rel.stats)
+                .with_context(|| format!("invalid statistics of relation {}", rel.relid))?;
+            client.execute(
+                "SELECT neon.restore_relation_stats($1, $2, $3)",
+                &[&rel.reldatabase, &rel.relid, &stats],
+            )?;
+            restored += 1;
+        }
+    }
+    Ok(restored)
+}
+
+/// Read the pages back into the buffer pool, as many as fit and until the deadline. Returns
+/// how many were read.
+fn prewarm_buffers(client: &mut Client, buffers: &[CachedRun], deadline: Instant) -> Result<u64> {
+    let row = client.query_one(
+        "SELECT setting::int8 FROM pg_catalog.pg_settings WHERE name = 'shared_buffers'",
+        &[],
+    )?;
+    let shared_buffers: i64 = row.get(0);
+
+    let mut read = 0;
+    for run in runs_to_prewarm(buffers, shared_buffers.max(0) as u64) {
+        let mut blkno = run.blkno;
+        let end = run.blkno + run.nblocks as i64;
+        while blkno < end {
+            if Instant::now() >= deadline {
+                info!("suspend snapshot restore is out of budget");
+                return Ok(read);
+            }
+            let nblocks = PREWARM_BATCH_PAGES.min((end - blkno) as i32);
+            match client.query_one(
+                "SELECT neon.prewarm_buffers($1, $2, $3, $4, $5, $6)",
+                &[
+                    &run.reltablespace,
+                    &run.reldatabase,
+                    &run.relfilenode,
+                    &run.forknum,
+                    &blkno,
+                    &nblocks,
+                ],
+            ) {
+                Ok(row) => read += row.get::<_, i32>(0) as u64,
+                Err(e) if client.is_closed() => return Err(e.into()),
+                Err(e) => debug!("could not prewarm {run:?}: {e}"),
+            }
+            blkno += nblocks as i64;
+        }
+    }
+    Ok(read)
+}
+
+fn restore(
+    compute: &ComputeNode,
+    spec: &SuspendSnapshotSpec,
+    snapshot: &SuspendSnapshot,
+) -> Result<()> {
+    let timeline_id = compute
+        .state
+        .lock()
+        .unwrap()
+        .pspec
+        .as_ref()
+        .expect("spec must be set")
+        .timeline_id;
+    if snapshot.timeline_id != timeline_id {
+        info!(
+            "not restoring suspend snapshot of timeline {}",
+            snapshot.timeline_id
+        );
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(spec.restore_budget_ms);
+    let mut client = connect_within(compute, None, deadline)?;
+
+    let mut relations = 0;
+    if snapshot.server_version_num == server_version_num(&mut client)? {
+        relations = restore_relation_stats(compute, &mut client, &snapshot.relations, deadline)
+            .unwrap_or_else(|e| {
+                warn!("failed to restore relation statistics: {e:#}");
+                0
+            });
+    } else {
+        info!(
+            "not restoring relation statistics of server version {}",
+            snapshot.server_version_num
+        );
+    }
+    let pages = prewarm_buffers(&mut client, &snapshot.buffers, deadline)?;
+
+    let elapsed = start.elapsed();
+    info!("restored suspend snapshot of {relations} relations and {pages} pages in {elapsed:?}");
+    let mut state = compute.state.lock().unwrap();
+    state.metrics.suspend_restore_ms = elapsed.as_millis() as u64;
+    state.metrics.suspend_restored_relations = relations;
+    state.metrics.suspend_restored_pages = pages;
+    Ok(())
+}
+
+/// Restore the snapshot that came with the basebackup, if any and if the spec enables it, and
+/// delete it. Must run once Postgres is running, before the compute is reported as running.
+pub fn restore_suspend_snapshot(
+    compute: &ComputeNode,
+    spec: Option<&SuspendSnapshotSpec>,
+) -> Result<()> {
+    let Some(snapshot) = load_suspend_snapshot(Path::new(&compute.pgdata))? else {
+        return Ok(());
+    };
+
+    let result = match spec {
+        Some(spec) => restore(compute, spec, &snapshot),
+        None => Ok(()),
+    };
+
+    // A new connection, as those of the restore are limited by its budget
+    connect(compute, None)
+        .and_then(|mut client| write_aux_file(&mut client, b""))
+        .context("failed to delete suspend snapshot")?;
+    result
+}
@@ -13,6 +13,8 @@ pub enum TimelineStatusResponse {
 pub struct TimelineStatusOkResponse {
     flush_lsn: Lsn,
     commit_lsn: Lsn,
+    /// Oldest LSN that the safekeeper can stream WAL from. Older safekeepers don't report it.
+    wal_start_lsn: Option<Lsn>,
 }
 
 /// Get a safekeeper's metadata for our timeline. The id is only used for logging
@@ -42,6 +44,10 @@ pub async fn ping_safekeeper(
         let response = TimelineStatusResponse::Ok(TimelineStatusOkResponse {
             flush_lsn: Lsn::from_str(row.get("flush_lsn").unwrap())?,
             commit_lsn: Lsn::from_str(row.get("commit_lsn").unwrap())?,
+            wal_start_lsn: match row.try_get("wal_start_lsn") {
+                Ok(Some(lsn)) => Some(Lsn::from_str(lsn)?),
+                _ => None,
+            },
         });
         Ok(response)
     } else {
@@ -96,3 +102,27 @@ pub fn check_if_synced(responses: Vec<TimelineStatusResponse>) -> Option<Lsn> {
 
     Some(*commit_max)
 }
+
+/// Given a quorum of responses, get the oldest LSN that WAL can be streamed from by at least one
+/// of the safekeepers, if they report it
+pub fn wal_available_from(responses: &[TimelineStatusResponse]) -> Option<Lsn> {
+    responses
+        .iter()
+        .filter_map(|r| match r {
+            TimelineStatusResponse::Ok(ok_response) => ok_response.wal_start_lsn,
+            _ => None,
+        })
+        .min()
+}
+
+/// Given a quorum of responses, get the highest LSN that any of the safekeepers knows to be
+/// committed
+pub fn max_commit_lsn(responses: &[TimelineStatusResponse]) -> Option<Lsn> {
+    responses
+        .iter()
+        .filter_map(|r| match r {
+            TimelineStatusResponse::Ok(ok_response) => Some(ok_response.commit_lsn),
+            _ => None,
+        })
+        .max()
+}
@@ -0,0 +1,69 @@
+//! Waking a hibernated tenant.
+//!
+//! The storage controller hibernates tenants that have had no compute for a while, detaching
+//! them from pageservers. So before getting the basebackup, compute_ctl asks it to wake the
+//! tenant, with its `wake` API, which re-attaches the tenant if needed and responds with where it
+//! is attached, like `locate` does. If that isn't where the spec says, compute_ctl uses those
+//! pageservers instead, or fetches the latest spec if the tenant's shards changed.
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::compute::ComputeNode;
+use crate::pageserver_failover::{
+    connstr_for_locations, fetch_spec, request_location, shard_layout_changed,
+};
+
+/// Wake the tenant, if the spec asks for it, and point the spec at the pageservers it is
+/// attached to
+pub fn wake_tenant(compute: &ComputeNode) -> Result<()> {
+    let (tenant_id, current, stripe_size, auth_token, spec) = {
+        let state = compute.state.lock().unwrap();
+        let pspec = state.pspec.as_ref().expect("spec must be set");
+        let Some(spec) = pspec
+            .spec
+            .tenant_wake
+            .clone()
+            .filter(|_| pspec.spec.pageserver_connstring.is_some())
+        else {
+            return Ok(());
+        };
+        (
+            pspec.tenant_id,
+            pspec.pageserver_connstr.clone(),
+            pspec.spec.shard_stripe_size,
+            pspec.storage_auth_token.clone(),
+            spec,
+        )
+    };
+
+    let start = Instant::now();
+    let url = format!(
+        "{}/upcall/v1/tenant/{tenant_id}/wake",
+        spec.storage_controller_url.trim_end_matches('/')
+    );
+    let request = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_millis(spec.timeout_ms))
+        .build()?
+        .post(&url);
+    let location = request_location(request, tenant_id, auth_token.as_deref());
+    compute.state.lock().unwrap().metrics.tenant_wake_ms = start.elapsed().as_millis() as u64;
+    let location = location?;
+
+    if shard_layout_changed(&current, stripe_size, &location) {
+        info!("shards of tenant {tenant_id} changed, reloading the spec");
+        let pspec = fetch_spec(compute)?;
+        compute.state.lock().unwrap().pspec = Some(pspec);
+        return Ok(());
+    }
+    let connstr = connstr_for_locations(&current, &location.shards);
+    if connstr != current {
+        info!("tenant {tenant_id} is attached elsewhere, using pageservers {connstr}");
+        let mut state = compute.state.lock().unwrap();
+        let pspec = state.pspec.as_mut().expect("spec must be set");
+        pspec.pageserver_connstr = connstr.clone();
+        pspec.spec.pageserver_connstring = Some(connstr);
+    }
+    Ok(())
+}
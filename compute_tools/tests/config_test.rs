@@ -45,4 +45,24 @@ fn test_line_in_file() {
 
         remove_file(path).unwrap();
     }
+
+    #[test]
+    fn test_cgroup_memory_limit() {
+        // cgroup v2, in a container
+        let (dir, file) = cgroup_memory_limit_path("0::/\n").unwrap();
+        assert_eq!(dir.join(file), Path::new("/sys/fs/cgroup/memory.max"));
+
+        // cgroup v1 takes precedence in a hybrid setup
+        let cgroups = "12:cpu,cpuacct:/kubepods/pod1\n4:memory:/kubepods/pod1\n0::/system.slice\n";
+        let (dir, file) = cgroup_memory_limit_path(cgroups).unwrap();
+        assert_eq!(
+            dir.join(file),
+            Path::new("/sys/fs/cgroup/memory/kubepods/pod1/memory.limit_in_bytes")
+        );
+
+        assert_eq!(cgroup_memory_limit_path(""), None);
+
+        assert_eq!(parse_cgroup_memory_limit("max\n"), None);
+        assert_eq!(parse_cgroup_memory_limit("1073741824\n"), Some(1 << 30));
+    }
 }
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod db_objects_tests {
+    use std::collections::HashSet;
+
+    use compute_api::spec::{
+        Database, DatabaseObjects, DefaultPrivileges, DefaultPrivilegesTarget, Extension, Grant,
+        GrantTarget, Schema,
+    };
+    use compute_tools::db_objects::*;
+
+    fn database(objects: DatabaseObjects) -> Database {
+        Database {
+            name: "db".to_string(),
+            owner: "alice".to_string(),
+            options: None,
+            objects,
+            restrict_conn: false,
+            invalid: false,
+        }
+    }
+
+    fn existing(db: &Database) -> ExistingObjects {
+        ExistingObjects {
+            grants: vec![HashSet::new(); db.objects.grants.len()],
+            default_privileges: vec![HashSet::new(); db.objects.default_privileges.len()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plan_objects_creates_missing() {
+        let db = database(DatabaseObjects {
+            schemas: vec![
+                Schema {
+                    name: "app".to_string(),
+                    owner: None,
+                },
+                Schema {
+                    name: "ext".to_string(),
+                    owner: Some("bob".to_string()),
+                },
+            ],
+            extensions: vec![Extension {
+                name: "hstore".to_string(),
+                version: Some("1.8".to_string()),
+                schema: Some("ext".to_string()),
+            }],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            plan_objects(&db, &existing(&db)),
+            vec![
+                "CREATE SCHEMA IF NOT EXISTS \"app\" AUTHORIZATION \"alice\"",
+                "CREATE SCHEMA IF NOT EXISTS \"ext\" AUTHORIZATION \"bob\"",
+                "CREATE EXTENSION IF NOT EXISTS \"hstore\" SCHEMA \"ext\" VERSION '1.8' CASCADE",
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_objects_alters_existing() {
+        let db = database(DatabaseObjects {
+            schemas: vec![
+                Schema {
+                    name: "app".to_string(),
+                    owner: None,
+                },
+                Schema {
+                    name: "ext".to_string(),
+                    owner: None,
+                },
+            ],
+            extensions: vec![
+                Extension {
+                    name: "hstore".to_string(),
+                    version: Some("1.8".to_string()),
+                    schema: Some("ext".to_string()),
+                },
+                Extension {
+                    name: "pg_trgm".to_string(),
+                    version: None,
+                    schema: None,
+                },
+            ],
+            ..Default::default()
+        });
+
+        let mut existing = existing(&db);
+        existing
+            .schemas
+            .insert("app".to_string(), "alice".to_string());
+        existing
+            .schemas
+            .insert("ext".to_string(), "cloud_admin".to_string());
+        existing.extensions.insert(
+            "hstore".to_string(),
+            ExistingExtension {
+                version: "1.7".to_string(),
+                schema: "public".to_string(),
+            },
+        );
+        existing.extensions.insert(
+            "pg_trgm".to_string(),
+            ExistingExtension {
+                version: "1.6".to_string(),
+                schema: "public".to_string(),
+            },
+        );
+
+        assert_eq!(
+            plan_objects(&db, &existing),
+            vec![
+                "ALTER SCHEMA \"ext\" OWNER TO \"alice\"",
+                "ALTER EXTENSION \"hstore\" UPDATE TO '1.8'",
+                "ALTER EXTENSION \"hstore\" SET SCHEMA \"ext\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_privileges_grants_missing() {
+        let db = database(DatabaseObjects {
+            grants: vec![
+                Grant {
+                    role: "reader".to_string(),
+                    privileges: vec!["usage".to_string()],
+                    on: GrantTarget::Schema {
+                        schema: "app".to_string(),
+                    },
+                    with_grant_option: false,
+                },
+                Grant {
+                    role: "reader".to_string(),
+                    privileges: vec!["ALL".to_string()],
+                    on: GrantTarget::AllSequencesInSchema {
+                        schema: "app".to_string(),
+                    },
+                    with_grant_option: true,
+                },
+                Grant {
+                    role: "reader".to_string(),
+                    privileges: vec!["CONNECT".to_string()],
+                    on: GrantTarget::Database,
+                    with_grant_option: false,
+                },
+            ],
+            default_privileges: vec![DefaultPrivileges {
+                for_role: None,
+                schema: Some("app".to_string()),
+                role: "reader".to_string(),
+                privileges: vec!["SELECT".to_string()],
+                on: DefaultPrivilegesTarget::Tables,
+                with_grant_option: false,
+            }],
+            ..Default::default()
+        });
+
+        let mut existing = existing(&db);
+        existing.grants[1].insert("USAGE");
+        existing.grants[2].insert("CONNECT");
+
+        assert_eq!(
+            plan_privileges(&db, &existing).unwrap(),
+            vec![
+                "GRANT USAGE ON SCHEMA \"app\" TO \"reader\"",
+                "GRANT SELECT, UPDATE ON ALL SEQUENCES IN SCHEMA \"app\" TO \"reader\" WITH GRANT OPTION",
+                "ALTER DEFAULT PRIVILEGES FOR ROLE \"alice\" IN SCHEMA \"app\" GRANT SELECT ON TABLES TO \"reader\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_privileges_rejects_unknown() {
+        let db = database(DatabaseObjects {
+            grants: vec![Grant {
+                role: "reader".to_string(),
+                privileges: vec!["SELECT; DROP TABLE t".to_string()],
+                on: GrantTarget::AllTablesInSchema {
+                    schema: "app".to_string(),
+                },
+                with_grant_option: false,
+            }],
+            ..Default::default()
+        });
+        assert!(plan_privileges(&db, &existing(&db)).is_err());
+
+        let db = database(DatabaseObjects {
+            default_privileges: vec![DefaultPrivileges {
+                for_role: None,
+                schema: Some("app".to_string()),
+                role: "reader".to_string(),
+                privileges: vec!["USAGE".to_string()],
+                on: DefaultPrivilegesTarget::Schemas,
+                with_grant_option: false,
+            }],
+            ..Default::default()
+        });
+        assert!(plan_privileges(&db, &existing(&db)).is_err());
+    }
+}
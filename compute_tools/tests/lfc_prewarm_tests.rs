@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod lfc_prewarm_tests {
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use compute_tools::lfc_prewarm::*;
+
+    fn run(relfilenode: u32, blkno: i64, nblocks: i32) -> CachedRun {
+        CachedRun {
+            reltablespace: 1663,
+            reldatabase: 5,
+            relfilenode,
+            forknum: 0,
+            blkno,
+            nblocks,
+        }
+    }
+
+    #[test]
+    fn prewarm_what_fits() {
+        let runs = vec![run(16384, 0, 128), run(16384, 128, 64), run(16390, 0, 10)];
+
+        assert_eq!(runs_to_prewarm(&runs, 1000), runs);
+        assert_eq!(
+            runs_to_prewarm(&runs, 150),
+            vec![run(16384, 0, 128), run(16384, 128, 22)]
+        );
+        assert_eq!(runs_to_prewarm(&runs, 128), vec![run(16384, 0, 128)]);
+        assert!(runs_to_prewarm(&runs, 0).is_empty());
+    }
+
+    #[test]
+    fn throttling() {
+        // 16 MB at 32 MB/s takes half a second
+        let bytes = 16_000_000;
+        assert_eq!(
+            throttle_delay(bytes, Duration::from_millis(100), 32),
+            Duration::from_millis(400)
+        );
+        assert_eq!(
+            throttle_delay(bytes, Duration::from_secs(1), 32),
+            Duration::ZERO
+        );
+        // No limit
+        assert_eq!(throttle_delay(bytes, Duration::ZERO, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn load_saved_state() {
+        let pgdata = Path::new("./tests/tmp/lfc_prewarm_pgdata");
+        let _ = fs::remove_dir_all(pgdata);
+        fs::create_dir_all(pgdata.join("neon")).unwrap();
+
+        assert_eq!(load_lfc_state(pgdata).unwrap(), None);
+
+        let state = LfcState {
+            runs: vec![run(16384, 0, 128)],
+        };
+        fs::write(
+            pgdata.join(LFC_STATE_AUX_PATH),
+            serde_json::to_vec(&state).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(load_lfc_state(pgdata).unwrap(), Some(state));
+
+        fs::write(pgdata.join(LFC_STATE_AUX_PATH), b"not json").unwrap();
+        assert!(load_lfc_state(pgdata).is_err());
+
+        fs::remove_dir_all(pgdata).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+#[cfg(test)]
+mod logical_slots_tests {
+    use std::fs;
+    use std::path::Path;
+
+    use compute_api::responses::LogicalSlotStatus;
+    use compute_tools::logical_slots::*;
+    use utils::id::TimelineId;
+    use utils::lsn::Lsn;
+
+    /// Contents of a slot's state file, with only the slot's position and the checksum filled in
+    fn slot_state(lsn: Lsn) -> Vec<u8> {
+        let mut state = vec![0u8; 200];
+        state[96..104].copy_from_slice(&lsn.0.to_le_bytes());
+        state[112..120].copy_from_slice(&lsn.0.to_le_bytes());
+        let checksum = crc32c::crc32c(&state[8..]);
+        state[4..8].copy_from_slice(&checksum.to_le_bytes());
+        state
+    }
+
+    /// A slot that Postgres last saved at `saved_lsn`, and that was at `recorded_lsn` when
+    /// compute_ctl recorded it
+    fn persisted_slot(name: &str, saved_lsn: Lsn, recorded_lsn: Lsn) -> PersistedSlot {
+        PersistedSlot {
+            name: name.to_string(),
+            plugin: "pgoutput".to_string(),
+            database: "neondb".to_string(),
+            restart_lsn: Some(recorded_lsn),
+            confirmed_flush_lsn: Some(recorded_lsn),
+            state: hex::encode(slot_state(saved_lsn)),
+        }
+    }
+
+    #[test]
+    fn restore_missing_slots() {
+        let pgdata = Path::new("./tests/tmp/logical_slots_pgdata");
+        let _ = fs::remove_dir_all(pgdata);
+        fs::create_dir_all(pgdata.join("neon")).unwrap();
+        fs::create_dir_all(pgdata.join("pg_replslot/present")).unwrap();
+        fs::write(
+            pgdata.join("pg_replslot/present/state"),
+            slot_state(Lsn(0x3000000)),
+        )
+        .unwrap();
+        fs::create_dir_all(pgdata.join("pg_replslot/stale")).unwrap();
+        fs::write(
+            pgdata.join("pg_replslot/stale/state"),
+            slot_state(Lsn(0x1000000)),
+        )
+        .unwrap();
+        fs::write(pgdata.join("restart.lsn"), 0x3000000u64.to_le_bytes()).unwrap();
+
+        let timeline_id = TimelineId::generate();
+
+        // Nothing to do without a record
+        assert!(restore_logical_slots(pgdata, timeline_id)
+            .unwrap()
+            .is_empty());
+
+        let persisted = PersistedSlots {
+            timeline_id,
+            slots: vec![
+                persisted_slot("missing", Lsn(0x1800000), Lsn(0x2000000)),
+                persisted_slot("present", Lsn(0x1000000), Lsn(0x1000000)),
+                persisted_slot("stale", Lsn(0x1000000), Lsn(0x2800000)),
+            ],
+        };
+        fs::write(
+            pgdata.join(LOGICAL_SLOTS_AUX_PATH),
+            serde_json::to_vec(&persisted).unwrap(),
+        )
+        .unwrap();
+
+        // A branch ignores the record of its parent
+        assert!(restore_logical_slots(pgdata, TimelineId::generate())
+            .unwrap()
+            .is_empty());
+        assert!(!pgdata.join("pg_replslot/missing").exists());
+
+        assert_eq!(
+            restore_logical_slots(pgdata, timeline_id).unwrap(),
+            vec!["missing"]
+        );
+        // At the recorded position, not the one it was saved at
+        assert_eq!(
+            fs::read(pgdata.join("pg_replslot/missing/state")).unwrap(),
+            slot_state(Lsn(0x2000000))
+        );
+        // A slot that came with the basebackup is never moved back...
+        assert_eq!(
+            fs::read(pgdata.join("pg_replslot/present/state")).unwrap(),
+            slot_state(Lsn(0x3000000))
+        );
+        // ...but is brought up to the record if it's older
+        assert_eq!(
+            fs::read(pgdata.join("pg_replslot/stale/state")).unwrap(),
+            slot_state(Lsn(0x2800000))
+        );
+        assert_eq!(
+            fs::read(pgdata.join("restart.lsn")).unwrap(),
+            0x2000000u64.to_le_bytes()
+        );
+
+        fs::remove_dir_all(pgdata).unwrap();
+    }
+
+    #[test]
+    fn restore_rejects_bad_names() {
+        let pgdata = Path::new("./tests/tmp/logical_slots_bad_name");
+        let _ = fs::remove_dir_all(pgdata);
+        fs::create_dir_all(pgdata.join("neon")).unwrap();
+
+        let timeline_id = TimelineId::generate();
+        let persisted = PersistedSlots {
+            timeline_id,
+            slots: vec![persisted_slot(
+                "../../escape",
+                Lsn(0x1000000),
+                Lsn(0x1000000),
+            )],
+        };
+        fs::write(
+            pgdata.join(LOGICAL_SLOTS_AUX_PATH),
+            serde_json::to_vec(&persisted).unwrap(),
+        )
+        .unwrap();
+        assert!(restore_logical_slots(pgdata, timeline_id).is_err());
+
+        fs::remove_dir_all(pgdata).unwrap();
+    }
+
+    #[test]
+    fn state_restart_lsn() {
+        assert_eq!(
+            slot_state_restart_lsn(&slot_state(Lsn(0x16B9188))).unwrap(),
+            Lsn(0x16B9188)
+        );
+        assert!(slot_state_restart_lsn(&[0u8; 100]).is_err());
+    }
+
+    #[test]
+    fn advance_state() {
+        let mut state = slot_state(Lsn(0x1000000));
+
+        // Never moved back
+        assert!(!advance_slot_state(&mut state, Some(Lsn(0x800000)), None).unwrap());
+        assert_eq!(state, slot_state(Lsn(0x1000000)));
+
+        assert!(advance_slot_state(&mut state, Some(Lsn(0x2000000)), None).unwrap());
+        assert_eq!(slot_state_restart_lsn(&state).unwrap(), Lsn(0x2000000));
+        assert_eq!(
+            slot_state_confirmed_flush_lsn(&state).unwrap(),
+            Lsn(0x1000000)
+        );
+
+        // The checksum covers everything after it
+        assert!(advance_slot_state(&mut state, None, Some(Lsn(0x2000000))).unwrap());
+        assert_eq!(state, slot_state(Lsn(0x2000000)));
+
+        assert!(advance_slot_state(&mut [0u8; 100], Some(Lsn(1)), None).is_err());
+    }
+
+    #[test]
+    fn wal_availability() {
+        let slot = |name: &str, restart_lsn: Option<Lsn>| LogicalSlotStatus {
+            name: name.to_string(),
+            plugin: "pgoutput".to_string(),
+            restart_lsn,
+            error: None,
+        };
+        let mut slots = vec![
+            slot("ok", Some(Lsn(0x2000000))),
+            slot("behind", Some(Lsn(0x1000000))),
+            slot("invalidated", None),
+        ];
+        check_wal_available(&mut slots, Lsn(0x2000000));
+
+        assert_eq!(slots[0].error, None);
+        assert_eq!(
+            slots[1].error.as_deref(),
+            Some("slot needs WAL from 0/1000000, but safekeepers only have WAL from 0/2000000")
+        );
+        assert!(slots[2].error.is_some());
+    }
+}
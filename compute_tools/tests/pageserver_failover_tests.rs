@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod pageserver_failover_tests {
+    use std::time::Duration;
+
+    use compute_tools::pageserver_failover::*;
+
+    fn stats(requests: u64, responses: u64, errors: u64, wait_us: u64) -> ShardStats {
+        ShardStats {
+            requests,
+            responses,
+            errors,
+            wait_us,
+        }
+    }
+
+    #[test]
+    fn degraded() {
+        let max_wait = Duration::from_millis(100);
+        let prev = stats(100, 100, 1, 50_000);
+
+        // Idle
+        assert!(!is_degraded(&prev, &prev, max_wait));
+        // Fast enough
+        assert!(!is_degraded(&prev, &stats(110, 110, 1, 550_000), max_wait));
+        // Failed to connect or lost the connection
+        assert!(is_degraded(&prev, &stats(100, 100, 2, 50_000), max_wait));
+        // No responses to the requests
+        assert!(is_degraded(&prev, &stats(105, 100, 1, 50_000), max_wait));
+        // Too slow
+        assert!(is_degraded(&prev, &stats(110, 110, 1, 2_050_000), max_wait));
+    }
+
+    #[test]
+    fn connstr() {
+        let locations = vec![("ps-2".to_string(), 6400)];
+        assert_eq!(
+            connstr_for_locations("postgresql://no_user@ps-1:6400", &locations),
+            "postgresql://no_user@ps-2:6400"
+        );
+        assert_eq!(
+            connstr_for_locations("postgresql://no_user@ps-1:6400?sslmode=require", &locations),
+            "postgresql://no_user@ps-2:6400?sslmode=require"
+        );
+        // Not a URL
+        assert_eq!(
+            connstr_for_locations("host=ps-1 port=6400", &locations),
+            "postgresql://no_user@ps-2:6400"
+        );
+
+        // Each shard keeps its own options
+        let locations = vec![("ps-2".to_string(), 6400), ("ps-3".to_string(), 6401)];
+        assert_eq!(
+            connstr_for_locations(
+                "postgresql://no_user@ps-1:6400,postgresql://no_user@ps-1:6401?sslmode=require",
+                &locations
+            ),
+            "postgresql://no_user@ps-2:6400,postgresql://no_user@ps-3:6401?sslmode=require"
+        );
+    }
+
+    #[test]
+    fn shard_layout() {
+        let location = |shards: usize, stripe_size: u32| TenantLocation {
+            shards: (0..shards)
+                .map(|i| ("ps".to_string(), 6400 + i as u16))
+                .collect(),
+            stripe_size,
+        };
+        let unsharded = "postgresql://no_user@ps-1:6400";
+        let sharded = "postgresql://no_user@ps-1:6400,postgresql://no_user@ps-2:6400";
+
+        assert!(!shard_layout_changed(unsharded, None, &location(1, 32768)));
+        assert!(!shard_layout_changed(
+            sharded,
+            Some(32768),
+            &location(2, 32768)
+        ));
+        // Split or merged
+        assert!(shard_layout_changed(unsharded, None, &location(2, 32768)));
+        assert!(shard_layout_changed(
+            sharded,
+            Some(32768),
+            &location(1, 32768)
+        ));
+        // Stripe size changed
+        assert!(shard_layout_changed(
+            sharded,
+            Some(32768),
+            &location(2, 2048)
+        ));
+    }
+}
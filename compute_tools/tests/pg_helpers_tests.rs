@@ -20,6 +20,29 @@ fn params_serialize() {
         );
     }
 
+    #[test]
+    fn rotating_role_serialize() {
+        let file = File::open("../libs/compute_api/tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+        let role = spec
+            .cluster
+            .roles
+            .iter()
+            .find(|r| r.name == "rotating")
+            .unwrap();
+
+        // The role itself gets the next password
+        assert_eq!(
+            role.to_pg_options(),
+            " LOGIN PASSWORD 'SCRAM-SHA-256$4096:QSXCR+Q6sek8bf92$D5h6KTMBlUvDJk2Y8ELfC1Sjtc6k9YHjRyuRZyBNJns=:Pi3QHbcluX//NDfVkKlFl88GGzlJ5LkyPwcdlN/QBvI='"
+        );
+        // and the role keeping the current one expires with the overlap
+        assert_eq!(
+            role.to_previous_password_pg_options(role.next_password.as_ref().unwrap()),
+            " LOGIN PASSWORD 'SCRAM-SHA-256$4096:QSXCR+Q6sek8bf92$FO+9jBb3MUukt6jJnzjPZOWc5ow/Pu6JtPyju0aqaE8=:qxJ1SbmSAi5EcS0J5Ck/cKAm/+Ixa+Kwp63f4OHDgzo=' VALID UNTIL '2024-09-01 00:00:00+00'"
+        );
+    }
+
     #[test]
     fn settings_serialize() {
         let file = File::open("../libs/compute_api/tests/cluster_spec.json").unwrap();
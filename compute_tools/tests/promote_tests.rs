@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod promote_tests {
+    use std::fs::File;
+
+    use compute_api::responses::{ComputeStatus, PromotionState, PromotionStatus};
+    use compute_api::spec::{ComputeMode, ComputeSpec};
+    use compute_tools::compute::{ComputeState, ParsedSpec};
+    use compute_tools::promote::*;
+
+    fn replica_state() -> ComputeState {
+        let file = File::open("../libs/compute_api/tests/cluster_spec.json").unwrap();
+        let mut spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+        spec.mode = ComputeMode::Replica;
+        spec.safekeeper_connstrings = vec!["127.0.0.1:5454".to_string()];
+
+        let mut state = ComputeState::new();
+        state.status = ComputeStatus::Running;
+        state.pspec = Some(ParsedSpec::try_from(spec).unwrap());
+        state
+    }
+
+    fn promotion(state: PromotionState) -> Option<PromotionStatus> {
+        Some(PromotionStatus {
+            state,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn promotable() {
+        let mut state = replica_state();
+        assert_eq!(check_promotable(&state), Ok(()));
+
+        // A failed promotion can be retried, but not one in progress
+        state.promotion = promotion(PromotionState::Failed);
+        assert_eq!(check_promotable(&state), Ok(()));
+        state.promotion = promotion(PromotionState::Fencing);
+        assert!(check_promotable(&state).is_err());
+
+        let mut state = replica_state();
+        state.status = ComputeStatus::ConfigurationPending;
+        assert!(check_promotable(&state).is_err());
+
+        // Already promoted
+        let mut state = replica_state();
+        state.promotion = promotion(PromotionState::Completed);
+        state.pspec.as_mut().unwrap().spec.mode = ComputeMode::Primary;
+        assert!(check_promotable(&state).is_err());
+
+        let mut state = replica_state();
+        state.pspec.as_mut().unwrap().safekeeper_connstrings = Vec::new();
+        assert!(check_promotable(&state).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod sql_exporter_tests {
+    use compute_api::spec::{MetricQuery, MetricType};
+    use compute_tools::sql_exporter::*;
+    use metrics::{Encoder, TextEncoder};
+
+    fn metric_query(metric_type: MetricType, values: &[&str]) -> MetricQuery {
+        MetricQuery {
+            metric_name: "pg_stat_database_xacts".to_string(),
+            metric_type,
+            help: "Transactions by database".to_string(),
+            key_labels: vec!["datname".to_string()],
+            values: values.iter().map(|v| v.to_string()).collect(),
+            value_label: (values.len() > 1).then(|| "kind".to_string()),
+            query: "SELECT datname, xact_commit AS commit, xact_rollback AS rollback FROM pg_stat_database".to_string(),
+            database: None,
+            timeout_ms: 1000,
+            cache_interval_ms: 0,
+        }
+    }
+
+    fn render(query: &MetricQuery, rows: &[Vec<Option<&str>>]) -> String {
+        let columns = ["datname", "commit", "rollback"].map(String::from);
+        let rows: Vec<Vec<Option<String>>> = rows
+            .iter()
+            .map(|row| row.iter().map(|v| v.map(String::from)).collect())
+            .collect();
+        let families = rows_to_metrics(query, &columns, &rows).unwrap();
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&families, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn gauge_with_value_label() {
+        let query = metric_query(MetricType::Gauge, &["commit", "rollback"]);
+        let text = render(
+            &query,
+            &[
+                vec![Some("neondb"), Some("42"), Some("3")],
+                vec![None, Some("7"), None],
+            ],
+        );
+        assert!(text.contains("# TYPE pg_stat_database_xacts gauge"));
+        assert!(text.contains(r#"pg_stat_database_xacts{datname="neondb",kind="commit"} 42"#));
+        assert!(text.contains(r#"pg_stat_database_xacts{datname="neondb",kind="rollback"} 3"#));
+        // NULL labels are empty, NULL values give no sample
+        assert!(text.contains(r#"pg_stat_database_xacts{datname="",kind="commit"} 7"#));
+        assert!(!text.contains(r#"datname="",kind="rollback""#));
+    }
+
+    #[test]
+    fn counter_skips_negative_values() {
+        let query = metric_query(MetricType::Counter, &["commit"]);
+        let text = render(
+            &query,
+            &[
+                vec![Some("neondb"), Some("42"), None],
+                vec![Some("postgres"), Some("-1"), None],
+            ],
+        );
+        assert!(text.contains("# TYPE pg_stat_database_xacts counter"));
+        assert!(text.contains(r#"pg_stat_database_xacts{datname="neondb"} 42"#));
+        assert!(!text.contains("postgres"));
+    }
+
+    #[test]
+    fn bad_rows() {
+        let columns = ["datname", "commit"].map(String::from);
+
+        let query = metric_query(MetricType::Gauge, &["commit"]);
+        let rows = vec![vec![Some("neondb".to_string()), Some("many".to_string())]];
+        assert!(rows_to_metrics(&query, &columns, &rows).is_err());
+
+        // No rows is fine even though the columns can't be checked
+        let query = metric_query(MetricType::Gauge, &["commit", "rollback"]);
+        assert!(rows_to_metrics(&query, &[], &[]).unwrap().is_empty());
+
+        let rows = vec![vec![Some("neondb".to_string()), Some("1".to_string())]];
+        assert!(rows_to_metrics(&query, &columns, &rows).is_err());
+    }
+
+    #[test]
+    fn query_validation() {
+        assert!(validate_query(&metric_query(MetricType::Gauge, &["commit"])).is_ok());
+        assert!(validate_query(&metric_query(MetricType::Gauge, &[])).is_err());
+
+        let mut query = metric_query(MetricType::Gauge, &["commit", "rollback"]);
+        query.value_label = None;
+        assert!(validate_query(&query).is_err());
+
+        let mut query = metric_query(MetricType::Gauge, &["commit"]);
+        query.metric_name = "xacts-total".to_string();
+        assert!(validate_query(&query).is_err());
+    }
+
+    #[test]
+    fn unique_metric_names() {
+        let gauge = metric_query(MetricType::Gauge, &["commit"]);
+        let mut other = metric_query(MetricType::Counter, &["rollback"]);
+        other.metric_name = "pg_stat_database_rollbacks".to_string();
+        assert!(check_unique_metric_names(&[gauge.clone(), other]).is_ok());
+
+        let counter = metric_query(MetricType::Counter, &["commit"]);
+        assert!(check_unique_metric_names(&[gauge, counter]).is_err());
+    }
+}
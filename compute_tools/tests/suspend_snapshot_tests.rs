@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod suspend_snapshot_tests {
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    use compute_tools::lfc_prewarm::CachedRun;
+    use compute_tools::suspend_snapshot::*;
+    use utils::id::TimelineId;
+
+    fn buffer(relfilenode: u32, blkno: i64, usagecount: i16) -> PooledBuffer {
+        PooledBuffer {
+            reltablespace: 1663,
+            reldatabase: 5,
+            relfilenode,
+            forknum: 0,
+            blkno,
+            usagecount,
+        }
+    }
+
+    fn run(relfilenode: u32, blkno: i64, nblocks: i32) -> CachedRun {
+        CachedRun {
+            reltablespace: 1663,
+            reldatabase: 5,
+            relfilenode,
+            forknum: 0,
+            blkno,
+            nblocks,
+        }
+    }
+
+    #[test]
+    fn group_buffers() {
+        let buffers = vec![
+            buffer(16384, 2, 1),
+            buffer(16384, 0, 1),
+            buffer(16390, 7, 5),
+            buffer(16384, 1, 1),
+            buffer(16384, 3, 3),
+            buffer(16390, 8, 5),
+            buffer(16384, 10, 1),
+        ];
+        assert_eq!(
+            buffers_to_runs(&buffers),
+            vec![
+                // Most used first
+                run(16390, 7, 2),
+                run(16384, 3, 1),
+                // Block 3 is used more, so it's not part of this run
+                run(16384, 0, 3),
+                run(16384, 10, 1),
+            ]
+        );
+        assert!(buffers_to_runs(&[]).is_empty());
+    }
+
+    #[test]
+    fn restore_budget() {
+        let now = Instant::now();
+        let deadline = now + Duration::from_millis(1500);
+        assert_eq!(
+            remaining_budget(deadline, now),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            remaining_budget(deadline, now + Duration::from_millis(1499)),
+            Some(Duration::from_millis(1))
+        );
+        // Less than a millisecond left is no budget, as a statement_timeout of 0 disables it
+        assert_eq!(
+            remaining_budget(deadline, now + Duration::from_micros(1_499_500)),
+            None
+        );
+        assert_eq!(remaining_budget(deadline, deadline), None);
+        assert_eq!(
+            remaining_budget(deadline, deadline + Duration::from_secs(1)),
+            None
+        );
+
+        // A tiny budget
+        let deadline = now + Duration::from_millis(1);
+        assert_eq!(
+            remaining_budget(deadline, now),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            remaining_budget(deadline, now + Duration::from_micros(500)),
+            None
+        );
+    }
+
+    #[test]
+    fn load_snapshot() {
+        let pgdata = Path::new("./tests/tmp/suspend_snapshot_pgdata");
+        let _ = fs::remove_dir_all(pgdata);
+        fs::create_dir_all(pgdata.join("neon")).unwrap();
+
+        assert_eq!(load_suspend_snapshot(pgdata).unwrap(), None);
+
+        let snapshot = SuspendSnapshot {
+            timeline_id: TimelineId::from_str("de200bd42b49cc1814412c7e592dd6e9").unwrap(),
+            server_version_num: 160004,
+            relations: vec![RelationStats {
+                reldatabase: 5,
+                relid: 16384,
+                stats: "0a0b0c".to_string(),
+            }],
+            buffers: vec![run(16384, 0, 128)],
+        };
+        fs::write(
+            pgdata.join(SUSPEND_SNAPSHOT_AUX_PATH),
+            serde_json::to_vec(&snapshot).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(load_suspend_snapshot(pgdata).unwrap(), Some(snapshot));
+
+        fs::write(pgdata.join(SUSPEND_SNAPSHOT_AUX_PATH), b"not json").unwrap();
+        assert!(load_suspend_snapshot(pgdata).is_err());
+
+        fs::remove_dir_all(pgdata).unwrap();
+    }
+}
@@ -888,9 +888,9 @@ async fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Re
                 )
             } else {
                 // Look up the currently attached location of the tenant, and its striping metadata,
-                // to pass these on to postgres.
+                // to pass these on to postgres.  This wakes the tenant if it was hibernated.
                 let storage_controller = StorageController::from_env(env);
-                let locate_result = storage_controller.tenant_locate(endpoint.tenant_id).await?;
+                let locate_result = storage_controller.tenant_wake(endpoint.tenant_id).await?;
                 let pageservers = locate_result
                     .shards
                     .into_iter()
@@ -1748,11 +1748,11 @@ fn cli() -> Command {
                     )
                     .arg(
                         Arg::new("mode")
-                            .help("Postgres shutdown mode, passed to \"pg_ctl -m <mode>\"")
+                            .help("Postgres shutdown mode, passed to \"pg_ctl -m <mode>\", or \"suspend\" to shut down through compute_ctl")
                             .long("mode")
                             .action(ArgAction::Set)
                             .required(false)
-                            .value_parser(["smart", "fast", "immediate"])
+                            .value_parser(["smart", "fast", "immediate", "suspend"])
                             .default_value("fast")
                     )
                 )
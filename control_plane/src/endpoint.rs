@@ -62,7 +62,10 @@
 use crate::storage_controller::StorageController;
 
 use compute_api::responses::{ComputeState, ComputeStatus};
-use compute_api::spec::{Cluster, ComputeFeature, ComputeMode, ComputeSpec};
+use compute_api::spec::{
+    Cluster, ComputeFeature, ComputeMode, ComputeSpec, PageserverFailoverSpec, SuspendSnapshotSpec,
+    TenantWakeSpec,
+};
 
 // contents of a endpoint.json file
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -76,6 +79,14 @@ pub struct EndpointConf {
     pg_version: u32,
     skip_pg_catalog_updates: bool,
     features: Vec<ComputeFeature>,
+    #[serde(default)]
+    pageserver_failover: bool,
+    #[serde(default)]
+    tenant_wake: bool,
+    #[serde(default)]
+    suspend_snapshot: bool,
+    #[serde(default)]
+    suspend_restore_budget_ms: Option<u64>,
 }
 
 //
@@ -148,6 +159,10 @@ pub fn new_endpoint(
             // we also skip catalog updates in the cloud.
             skip_pg_catalog_updates,
             features: vec![],
+            pageserver_failover: false,
+            tenant_wake: false,
+            suspend_snapshot: false,
+            suspend_restore_budget_ms: None,
         });
 
         ep.create_endpoint_dir()?;
@@ -163,6 +178,10 @@ pub fn new_endpoint(
                 pg_version,
                 skip_pg_catalog_updates,
                 features: vec![],
+                pageserver_failover: false,
+                tenant_wake: false,
+                suspend_snapshot: false,
+                suspend_restore_budget_ms: None,
             })?,
         )?;
         std::fs::write(
@@ -227,6 +246,18 @@ pub struct Endpoint {
 
     // Feature flags
     features: Vec<ComputeFeature>,
+
+    // Let compute_ctl switch pageservers on its own, through the storage controller
+    pageserver_failover: bool,
+
+    // Wake the tenant through the storage controller before starting, in case it was hibernated
+    tenant_wake: bool,
+
+    // Carry the buffer pool and statistics over `stop --mode suspend`
+    suspend_snapshot: bool,
+
+    // Time the restore of the suspend snapshot may add to the startup, if not the default
+    suspend_restore_budget_ms: Option<u64>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -277,6 +308,10 @@ fn from_dir_entry(entry: std::fs::DirEntry, env: &LocalEnv) -> Result<Endpoint>
             pg_version: conf.pg_version,
             skip_pg_catalog_updates: conf.skip_pg_catalog_updates,
             features: conf.features,
+            pageserver_failover: conf.pageserver_failover,
+            tenant_wake: conf.tenant_wake,
+            suspend_snapshot: conf.suspend_snapshot,
+            suspend_restore_budget_ms: conf.suspend_restore_budget_ms,
         })
     }
 
@@ -499,10 +534,39 @@ fn build_pageserver_connstr(pageservers: &[(Host, u16)]) -> String {
             .join(",")
     }
 
-    /// Map safekeepers ids to the actual connection strings.
+    /// Lets compute_ctl find the tenant's pageservers through the storage controller, if
+    /// enabled in endpoint.json
+    fn pageserver_failover_spec(&self) -> Option<PageserverFailoverSpec> {
+        if !self.pageserver_failover {
+            return None;
+        }
+        let api = self.env.control_plane_api.as_ref()?;
+        Some(PageserverFailoverSpec {
+            storage_controller_url: format!("http://{}:{}", api.host_str()?, api.port()?),
+            check_interval_ms: 500,
+            max_wait_ms: 1000,
+            degraded_checks: 3,
+        })
+    }
+
+    /// Lets compute_ctl wake the tenant through the storage controller, if enabled in
+    /// endpoint.json
+    fn tenant_wake_spec(&self) -> Option<TenantWakeSpec> {
+        if !self.tenant_wake {
+            return None;
+        }
+        let api = self.env.control_plane_api.as_ref()?;
+        Some(TenantWakeSpec {
+            storage_controller_url: format!("http://{}:{}", api.host_str()?, api.port()?),
+            timeout_ms: 60000,
+        })
+    }
+
+    /// Map safekeepers ids to the actual connection strings. Replicas get them too, so that
+    /// they can be promoted in place.
     fn build_safekeepers_connstrs(&self, sk_ids: Vec<NodeId>) -> Result<Vec<String>> {
         let mut safekeeper_connstrings = Vec::new();
-        if self.mode == ComputeMode::Primary {
+        if matches!(self.mode, ComputeMode::Primary | ComputeMode::Replica) {
             for sk_id in sk_ids {
                 let sk = self
                     .env
@@ -569,6 +633,7 @@ pub async fn start(
                     vec![Role {
                         name: PgIdent::from_str("test").unwrap(),
                         encrypted_password: None,
+                        next_password: None,
                         options: None,
                     }]
                 } else {
@@ -579,6 +644,7 @@ pub async fn start(
                         name: PgIdent::from_str("neondb").unwrap(),
                         owner: PgIdent::from_str("test").unwrap(),
                         options: None,
+                        objects: Default::default(),
                         restrict_conn: false,
                         invalid: false,
                     }]
@@ -598,6 +664,14 @@ pub async fn start(
             remote_extensions,
             pgbouncer_settings: None,
             shard_stripe_size: Some(shard_stripe_size),
+            metric_queries: Vec::new(),
+            lfc_prewarm: None,
+            pageserver_failover: self.pageserver_failover_spec(),
+            tenant_wake: self.tenant_wake_spec(),
+            suspend_snapshot: self.suspend_snapshot.then_some(SuspendSnapshotSpec {
+                restore_budget_ms: self.suspend_restore_budget_ms.unwrap_or(2000),
+            }),
+            memory_scaling: None,
         };
         let spec_path = self.endpoint_path().join("spec.json");
         std::fs::write(spec_path, serde_json::to_string_pretty(&spec)?)?;
@@ -818,7 +892,11 @@ pub async fn reconfigure(
     }
 
     pub fn stop(&self, mode: &str, destroy: bool) -> Result<()> {
-        self.pg_ctl(&["-m", mode, "stop"], &None)?;
+        if mode == "suspend" {
+            self.terminate_for_suspend()?;
+        } else {
+            self.pg_ctl(&["-m", mode, "stop"], &None)?;
+        }
 
         // Also wait for the compute_ctl process to die. It might have some
         // cleanup work to do after postgres stops, like syncing safekeepers,
@@ -828,7 +906,10 @@ pub fn stop(&self, mode: &str, destroy: bool) -> Result<()> {
         // waiting. Sometimes we do *not* want this cleanup: tests intentionally
         // do stop when majority of safekeepers is down, so sync-safekeepers
         // would hang otherwise. This could be a separate flag though.
-        let send_sigterm = destroy || mode == "immediate";
+        //
+        // After a suspend, compute_ctl has done its cleanup already, and would
+        // only keep serving HTTP requests for a while.
+        let send_sigterm = destroy || mode == "immediate" || mode == "suspend";
         self.wait_for_compute_ctl_to_exit(send_sigterm)?;
         if destroy {
             println!(
@@ -840,6 +921,28 @@ pub fn stop(&self, mode: &str, destroy: bool) -> Result<()> {
         Ok(())
     }
 
+    /// Stop Postgres through compute_ctl, like the control plane suspends a compute
+    fn terminate_for_suspend(&self) -> Result<()> {
+        let response = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()?
+            .post(format!(
+                "http://{}:{}/terminate?mode=suspend",
+                self.http_address.ip(),
+                self.http_address.port()
+            ))
+            .send()?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "failed to suspend endpoint {}: {status}: {}",
+                self.endpoint_id,
+                response.text().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
     pub fn connstr(&self, user: &str, db_name: &str) -> String {
         format!(
             "postgresql://{}@{}:{}/{}",
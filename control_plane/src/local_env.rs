@@ -166,8 +166,22 @@ pub struct NeonStorageControllerConf {
 
     pub max_secondary_lag_bytes: Option<u64>,
 
+    /// How many shards fail over out of a lost AZ at a time
+    pub az_failover_per_pass: Option<usize>,
+
     #[serde(with = "humantime_serde")]
     pub heartbeat_interval: Duration,
+
+    /// Elect a leader by lease among the instances listening on these addresses, rather than
+    /// through the database
+    pub leader_lease_peers: Option<Vec<SocketAddr>>,
+
+    #[serde(with = "humantime_serde")]
+    pub leader_lease_ttl: Option<Duration>,
+
+    /// How long to keep the operation history for
+    #[serde(with = "humantime_serde")]
+    pub history_retention: Option<Duration>,
 }
 
 impl NeonStorageControllerConf {
@@ -189,7 +203,11 @@ fn default() -> Self {
             database_url: None,
             split_threshold: None,
             max_secondary_lag_bytes: None,
+            az_failover_per_pass: None,
             heartbeat_interval: Self::DEFAULT_HEARTBEAT_INTERVAL,
+            leader_lease_peers: None,
+            leader_lease_ttl: None,
+            history_retention: None,
         }
     }
 }
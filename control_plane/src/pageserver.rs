@@ -618,11 +618,12 @@ pub async fn timeline_import(
         // Import base
         self.http_client
             .import_basebackup(
-                tenant_id,
+                TenantShardId::unsharded(tenant_id),
                 timeline_id,
                 start_lsn,
                 end_lsn,
                 pg_version,
+                false,
                 base_tarfile,
             )
             .await?;
@@ -630,7 +631,14 @@ pub async fn timeline_import(
         // Import wal if necessary
         if let Some(wal_reader) = wal_reader {
             self.http_client
-                .import_wal(tenant_id, timeline_id, start_lsn, end_lsn, wal_reader)
+                .import_wal(
+                    TenantShardId::unsharded(tenant_id),
+                    timeline_id,
+                    start_lsn,
+                    end_lsn,
+                    false,
+                    wal_reader,
+                )
                 .await?;
         }
 
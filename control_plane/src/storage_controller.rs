@@ -450,12 +450,15 @@ pub async fn start(&self, start_args: NeonStorageControllerStartArgs) -> anyhow:
             .listen
             .get()
             .expect("cell is set earlier in this function");
-        let address_for_peers = Uri::builder()
-            .scheme("http")
-            .authority(format!("{}:{}", listen.ip(), listen.port()))
-            .path_and_query("")
-            .build()
-            .unwrap();
+        let peer_uri = |addr: &SocketAddr| {
+            Uri::builder()
+                .scheme("http")
+                .authority(format!("{}:{}", addr.ip(), addr.port()))
+                .path_and_query("")
+                .build()
+                .unwrap()
+        };
+        let address_for_peers = peer_uri(listen);
 
         let mut args = vec![
             "-l",
@@ -510,6 +513,38 @@ pub async fn start(&self, start_args: NeonStorageControllerStartArgs) -> anyhow:
             args.push(format!("--max-secondary-lag-bytes={lag}"))
         }
 
+        if let Some(per_pass) = self.config.az_failover_per_pass.as_ref() {
+            args.push(format!("--az-failover-per-pass={per_pass}"))
+        }
+
+        if let Some(peers) = self.config.leader_lease_peers.as_ref() {
+            for peer in peers {
+                args.push(format!("--leader-lease-peer={}", peer_uri(peer)));
+            }
+            args.push(format!(
+                "--leader-lease-state={}",
+                instance_dir.join("leader_lease.json").display()
+            ));
+            if let Some(ttl) = self.config.leader_lease_ttl {
+                args.push(format!(
+                    "--leader-lease-ttl={}",
+                    humantime::Duration::from(ttl)
+                ));
+            }
+        }
+
+        if let Some(retention) = self.config.history_retention {
+            args.push(format!(
+                "--history-retention={}",
+                humantime::Duration::from(retention)
+            ));
+        }
+
+        args.push(format!(
+            "--import-dir={}",
+            instance_dir.join("imports").display()
+        ));
+
         args.push(format!(
             "--neon-local-repo-dir={}",
             self.env.base_data_dir.display()
@@ -527,7 +562,14 @@ pub async fn start(&self, start_args: NeonStorageControllerStartArgs) -> anyhow:
             background_process::InitialPidFile::Create(self.pid_file(start_args.instance_id)),
             &start_args.start_timeout,
             || async {
-                match self.ready().await {
+                // With leader leases, only one instance can become ready: the others just need to
+                // be up so that they can vote.
+                let result = if self.config.leader_lease_peers.is_some() {
+                    self.status().await
+                } else {
+                    self.ready().await
+                };
+                match result {
                     Ok(_) => Ok(true),
                     Err(_) => Ok(false),
                 }
@@ -632,7 +674,7 @@ fn get_claims_for_path(path: &str) -> anyhow::Result<Option<Claims>> {
 
         match category {
             "status" | "ready" => Ok(None),
-            "control" | "debug" => Ok(Some(Claims::new(None, Scope::Admin))),
+            "control" | "debug" | "upcall" => Ok(Some(Claims::new(None, Scope::Admin))),
             "v1" => Ok(Some(Claims::new(None, Scope::PageServerApi))),
             _ => Err(anyhow::anyhow!("Failed to determine claims for {}", path)),
         }
@@ -771,6 +813,18 @@ pub async fn tenant_locate(&self, tenant_id: TenantId) -> anyhow::Result<TenantL
         .await
     }
 
+    /// Like [`Self::tenant_locate`], but re-attaches the tenant first if the storage controller
+    /// hibernated it.
+    #[instrument(skip(self))]
+    pub async fn tenant_wake(&self, tenant_id: TenantId) -> anyhow::Result<TenantLocateResponse> {
+        self.dispatch::<(), _>(
+            Method::POST,
+            format!("upcall/v1/tenant/{tenant_id}/wake"),
+            None,
+        )
+        .await
+    }
+
     #[instrument(skip(self))]
     pub async fn tenant_migrate(
         &self,
@@ -837,6 +891,12 @@ pub async fn ready(&self) -> anyhow::Result<()> {
             .await
     }
 
+    #[instrument(skip(self))]
+    pub async fn status(&self) -> anyhow::Result<()> {
+        self.dispatch::<(), ()>(Method::GET, "status".to_string(), None)
+            .await
+    }
+
     #[instrument(skip_all, fields(%tenant_id, timeline_id=%req.new_timeline_id))]
     pub async fn tenant_timeline_create(
         &self,
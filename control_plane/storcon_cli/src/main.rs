@@ -8,8 +8,9 @@
         TenantCreateRequest, TenantDescribeResponse, TenantPolicyRequest,
     },
     models::{
-        EvictionPolicy, EvictionPolicyLayerAccessThreshold, LocationConfigSecondary,
-        ShardParameters, TenantConfig, TenantConfigRequest, TenantShardSplitRequest,
+        utilization::PageserverUtilization, EvictionPolicy, EvictionPolicyLayerAccessThreshold,
+        LocationConfigSecondary, ShardParameters, TenantConfig, TenantConfigRequest,
+        TenantShardMergeRequest, TenantShardMergeResponse, TenantShardSplitRequest,
         TenantShardSplitResponse,
     },
     shard::{ShardStripeSize, TenantShardId},
@@ -19,8 +20,10 @@
 use utils::id::{NodeId, TenantId};
 
 use pageserver_api::controller_api::{
-    NodeConfigureRequest, NodeRegisterRequest, NodeSchedulingPolicy, PlacementPolicy,
-    TenantShardMigrateRequest, TenantShardMigrateResponse,
+    NodeConfigureRequest, NodeRegisterRequest, NodeSchedulingPolicy, OperationEvent,
+    PendingComputeNotification, PlacementPolicy, PlanRequest, PlanResponse, RollingOperationHook,
+    RollingOperationRequest, RollingOperationStatus, TenantShardMigrateRequest,
+    TenantShardMigrateResponse,
 };
 use storage_controller_client::control_api::Client;
 
@@ -104,6 +107,14 @@ enum Command {
         #[arg(long)]
         stripe_size: Option<u32>,
     },
+    /// Merge an existing tenant into a lower number of shards than its current shard count.  The
+    /// current shard count must be a power-of-two multiple of the new one.
+    TenantShardMerge {
+        #[arg(long)]
+        tenant_id: TenantId,
+        #[arg(long)]
+        shard_count: u8,
+    },
     /// Migrate the attached location for a tenant shard to a specific pageserver.
     TenantShardMigrate {
         #[arg(long)]
@@ -196,6 +207,172 @@ enum Command {
         #[arg(long)]
         timeout: humantime::Duration,
     },
+    /// Restart pageservers one at a time: each is drained, restarted by the hook, waited for
+    /// until it reports a new version, and then filled.
+    RollingUpgrade {
+        /// Comma-separated list of nodes, in the order they should be restarted
+        #[arg(long, value_delimiter = ',', required = true)]
+        nodes: Vec<NodeId>,
+        /// Program the storage controller runs to restart a node.  It is passed the node in the
+        /// NODE_ID, NODE_HTTP_ADDR, NODE_HTTP_PORT and NODE_AVAILABILITY_ZONE_ID variables.
+        #[arg(
+            long,
+            conflicts_with = "hook_url",
+            required_unless_present = "hook_url"
+        )]
+        hook_command: Option<String>,
+        /// Arguments to `hook_command`
+        #[arg(long, allow_hyphen_values = true)]
+        hook_arg: Vec<String>,
+        /// URL the storage controller POSTs to in order to restart a node
+        #[arg(long)]
+        hook_url: Option<String>,
+        /// Only consider a node restarted once it reports this version
+        #[arg(long)]
+        target_version: Option<String>,
+        /// Restart all the listed nodes in an availability zone at the same time
+        #[arg(long)]
+        by_availability_zone: bool,
+        /// How long to wait for each node to come back after running the hook
+        #[arg(long, default_value = "10m")]
+        restart_timeout: humantime::Duration,
+    },
+    /// Show the progress of rolling operations
+    RollingStatus {},
+    /// Show the controller's operation history: scheduling decisions, reconciles, node state
+    /// changes and API calls, most recent first
+    History {
+        #[arg(long)]
+        tenant_id: Option<TenantId>,
+        #[arg(long)]
+        node_id: Option<NodeId>,
+        /// One of schedule, reconcile, node_state, shard_count, api
+        #[arg(long)]
+        kind: Option<String>,
+        /// Only show events after this time: either a timestamp such as `2024-09-20T03:00:00Z`,
+        /// or a duration such as `2h` meaning that long ago
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show events before this time, in the same format as `since`
+        #[arg(long)]
+        until: Option<String>,
+        #[arg(long, default_value = "100")]
+        limit: usize,
+    },
+    /// List tenants whose computes have not yet been successfully notified of their latest
+    /// pageservers
+    ComputeNotifications {},
+    /// Show what draining a node would do, without doing it
+    PlanDrain {
+        #[arg(long)]
+        node_id: NodeId,
+    },
+    /// Show what adding a node would do, without adding it.  The node is assumed to have the same
+    /// capacity as the existing nodes in its AZ.
+    PlanNodeAdd {
+        #[arg(long)]
+        node_id: NodeId,
+        #[arg(long)]
+        availability_zone_id: String,
+    },
+    /// Show what changing a tenant's placement policy would do, without changing it
+    PlanTenantPolicy {
+        #[arg(long)]
+        tenant_id: TenantId,
+        /// In the same format as for `tenant-policy`
+        #[arg(long)]
+        placement: PlacementPolicyArg,
+    },
+    /// Pause a rolling operation once its current step is complete
+    RollingPause {
+        #[arg(long)]
+        operation_id: String,
+    },
+    /// Resume a paused rolling operation, retrying the step that failed if it was paused by an error
+    RollingResume {
+        #[arg(long)]
+        operation_id: String,
+    },
+    /// Abort a rolling operation, returning the node it is working on to service
+    RollingAbort {
+        #[arg(long)]
+        operation_id: String,
+    },
+}
+
+/// Print the response to one of the `plan-*` commands
+fn print_plan(plan: PlanResponse) {
+    let mut table = comfy_table::Table::new();
+    table.set_header(["Shard", "Change"]);
+    for migration in plan.migrations {
+        let node = |n: Option<NodeId>| n.map(|n| n.to_string()).unwrap_or("-".to_string());
+        table.add_row([
+            migration.tenant_shard_id.to_string(),
+            format!(
+                "migrate attachment {} -> {}",
+                node(migration.from),
+                node(migration.to)
+            ),
+        ]);
+    }
+    for location in plan.secondaries_created {
+        table.add_row([
+            location.tenant_shard_id.to_string(),
+            format!("create secondary on {}", location.node_id),
+        ]);
+    }
+    for location in plan.locations_removed {
+        table.add_row([
+            location.tenant_shard_id.to_string(),
+            format!("remove location on {}", location.node_id),
+        ]);
+    }
+    for issue in plan.issues {
+        table.add_row([issue.tenant_shard_id.to_string(), issue.reason]);
+    }
+    println!("{table}");
+
+    let score = |s: Option<u64>| {
+        s.map(|s| {
+            format!(
+                "{:.1}%",
+                s as f64 * 100.0 / PageserverUtilization::UTILIZATION_FULL as f64
+            )
+        })
+        .unwrap_or("unschedulable".to_string())
+    };
+    let mut table = comfy_table::Table::new();
+    table.set_header(["Node", "Shards", "Attached", "Utilization"]);
+    for node in plan.nodes {
+        table.add_row([
+            node.node_id.to_string(),
+            format!("{} -> {}", node.shard_count_before, node.shard_count_after),
+            format!(
+                "{} -> {}",
+                node.attached_shard_count_before, node.attached_shard_count_after
+            ),
+            format!(
+                "{} -> {}",
+                score(node.utilization_score_before),
+                score(node.utilization_score_after)
+            ),
+        ]);
+    }
+    println!("{table}");
+
+    if !plan.converged {
+        println!("Planning gave up before the scheduler ran out of work: more changes may follow");
+    }
+}
+
+/// Parse a time for the `history` command into the RFC3339 form the controller expects
+fn history_time_arg(arg: &str) -> anyhow::Result<String> {
+    let time = match humantime::parse_duration(arg) {
+        Ok(ago) => std::time::SystemTime::now() - ago,
+        Err(_) => humantime::parse_rfc3339_weak(arg)
+            .map_err(|e| anyhow::anyhow!("Invalid time '{arg}': {e}"))?,
+    };
+    Ok(humantime::format_rfc3339(time).to_string())
 }
 
 #[derive(Parser)]
@@ -518,6 +695,33 @@ async fn main() -> anyhow::Result<()> {
                     .join(",")
             );
         }
+        Command::TenantShardMerge {
+            tenant_id,
+            shard_count,
+        } => {
+            let req = TenantShardMergeRequest {
+                new_shard_count: shard_count,
+            };
+
+            let response = storcon_client
+                .dispatch::<TenantShardMergeRequest, TenantShardMergeResponse>(
+                    Method::PUT,
+                    format!("control/v1/tenant/{tenant_id}/shard_merge"),
+                    Some(req),
+                )
+                .await?;
+            println!(
+                "Merged tenant {} into {} shards: {}",
+                tenant_id,
+                shard_count,
+                response
+                    .new_shards
+                    .iter()
+                    .map(|s| format!("{:?}", s))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+        }
         Command::TenantShardMigrate {
             tenant_shard_id,
             node,
@@ -974,6 +1178,219 @@ struct MigrationMove {
                 "Fill was cancelled for node {node_id}. Schedulling policy is now {final_policy:?}"
             );
         }
+        Command::RollingUpgrade {
+            nodes,
+            hook_command,
+            hook_arg,
+            hook_url,
+            target_version,
+            by_availability_zone,
+            restart_timeout,
+        } => {
+            let hook = match (hook_command, hook_url) {
+                (Some(program), None) => RollingOperationHook::Command {
+                    program,
+                    args: hook_arg,
+                },
+                (None, Some(url)) => RollingOperationHook::Http { url },
+                _ => anyhow::bail!("Exactly one of --hook-command and --hook-url is required"),
+            };
+
+            let status = storcon_client
+                .dispatch::<_, RollingOperationStatus>(
+                    Method::POST,
+                    "control/v1/rolling_operation".to_string(),
+                    Some(RollingOperationRequest {
+                        nodes,
+                        hook,
+                        target_version,
+                        by_availability_zone,
+                        restart_timeout: *restart_timeout,
+                    }),
+                )
+                .await?;
+            println!("Rolling operation {} started", status.id);
+        }
+        Command::RollingStatus {} => {
+            let operations = storcon_client
+                .dispatch::<(), Vec<RollingOperationStatus>>(
+                    Method::GET,
+                    "control/v1/rolling_operation".to_string(),
+                    None,
+                )
+                .await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header(["Id", "State", "Progress", "Updated", "Error"]);
+            for op in operations {
+                let progress = op
+                    .nodes
+                    .iter()
+                    .map(|node| format!("{}:{:?}", node.node_id, node.step))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                table.add_row([
+                    op.id,
+                    String::from(op.state),
+                    progress,
+                    op.updated_at.to_rfc3339(),
+                    op.error.unwrap_or_default(),
+                ]);
+            }
+            println!("{table}");
+        }
+        Command::History {
+            tenant_id,
+            node_id,
+            kind,
+            since,
+            until,
+            limit,
+        } => {
+            let mut query = vec![format!("limit={limit}")];
+            if let Some(tenant_id) = tenant_id {
+                query.push(format!("tenant_id={tenant_id}"));
+            }
+            if let Some(node_id) = node_id {
+                query.push(format!("node_id={node_id}"));
+            }
+            if let Some(kind) = kind {
+                query.push(format!("kind={kind}"));
+            }
+            if let Some(since) = since {
+                query.push(format!("since={}", history_time_arg(&since)?));
+            }
+            if let Some(until) = until {
+                query.push(format!("until={}", history_time_arg(&until)?));
+            }
+
+            let events = storcon_client
+                .dispatch::<(), Vec<OperationEvent>>(
+                    Method::GET,
+                    format!("control/v1/history?{}", query.join("&")),
+                    None,
+                )
+                .await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header(["Time", "Kind", "Actor", "Tenant", "Node", "Detail"]);
+            for event in events {
+                let tenant = match (event.tenant_shard_id, event.tenant_id) {
+                    (Some(tenant_shard_id), _) => tenant_shard_id.to_string(),
+                    (None, Some(tenant_id)) => tenant_id.to_string(),
+                    (None, None) => String::new(),
+                };
+                table.add_row([
+                    event.timestamp.to_rfc3339(),
+                    String::from(event.kind),
+                    event.actor,
+                    tenant,
+                    event.node_id.map(|n| n.to_string()).unwrap_or_default(),
+                    event.detail,
+                ]);
+            }
+            println!("{table}");
+        }
+        Command::ComputeNotifications {} => {
+            let pending = storcon_client
+                .dispatch::<(), Vec<PendingComputeNotification>>(
+                    Method::GET,
+                    "control/v1/compute_notifications".to_string(),
+                    None,
+                )
+                .await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header([
+                "Tenant",
+                "Attempts",
+                "First failed",
+                "Next attempt",
+                "Last error",
+            ]);
+            for notification in pending {
+                table.add_row([
+                    notification.tenant_id.to_string(),
+                    notification.attempts.to_string(),
+                    notification.first_failed_at.to_rfc3339(),
+                    notification.next_attempt_at.to_rfc3339(),
+                    notification.last_error.unwrap_or_default(),
+                ]);
+            }
+            println!("{table}");
+        }
+        Command::PlanDrain { node_id } => {
+            let plan = storcon_client
+                .dispatch::<_, PlanResponse>(
+                    Method::POST,
+                    "control/v1/plan".to_string(),
+                    Some(PlanRequest::NodeDrain { node_id }),
+                )
+                .await?;
+            print_plan(plan);
+        }
+        Command::PlanNodeAdd {
+            node_id,
+            availability_zone_id,
+        } => {
+            let plan = storcon_client
+                .dispatch::<_, PlanResponse>(
+                    Method::POST,
+                    "control/v1/plan".to_string(),
+                    Some(PlanRequest::NodeAdd {
+                        node_id,
+                        availability_zone_id,
+                    }),
+                )
+                .await?;
+            print_plan(plan);
+        }
+        Command::PlanTenantPolicy {
+            tenant_id,
+            placement,
+        } => {
+            let plan = storcon_client
+                .dispatch::<_, PlanResponse>(
+                    Method::POST,
+                    "control/v1/plan".to_string(),
+                    Some(PlanRequest::TenantPolicy {
+                        tenant_id,
+                        placement: placement.0,
+                    }),
+                )
+                .await?;
+            print_plan(plan);
+        }
+        Command::RollingPause { operation_id } => {
+            storcon_client
+                .dispatch::<(), RollingOperationStatus>(
+                    Method::PUT,
+                    format!("control/v1/rolling_operation/{operation_id}/pause"),
+                    None,
+                )
+                .await?;
+            println!("Rolling operation {operation_id} will pause after its current step");
+        }
+        Command::RollingResume { operation_id } => {
+            storcon_client
+                .dispatch::<(), RollingOperationStatus>(
+                    Method::PUT,
+                    format!("control/v1/rolling_operation/{operation_id}/resume"),
+                    None,
+                )
+                .await?;
+            println!("Rolling operation {operation_id} resumed");
+        }
+        Command::RollingAbort { operation_id } => {
+            storcon_client
+                .dispatch::<(), RollingOperationStatus>(
+                    Method::PUT,
+                    format!("control/v1/rolling_operation/{operation_id}/abort"),
+                    None,
+                )
+                .await?;
+            println!("Rolling operation {operation_id} aborted");
+        }
     }
 
     Ok(())
@@ -2,8 +2,9 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize, Serializer};
+use utils::lsn::Lsn;
 
-use crate::spec::{ComputeSpec, Database, Role};
+use crate::spec::{ComputeSpec, Database, PgIdent, Role};
 
 #[derive(Serialize, Debug, Deserialize)]
 pub struct GenericAPIError {
@@ -21,6 +22,85 @@ pub struct ComputeStatusResponse {
     #[serde(serialize_with = "rfc3339_serialize")]
     pub last_active: Option<DateTime<Utc>>,
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logical_slots: Vec<LogicalSlotStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lfc_prewarm: Option<LfcPrewarmStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promotion: Option<PromotionStatus>,
+}
+
+/// A logical replication slot of a primary, as checked by compute_ctl on startup
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogicalSlotStatus {
+    pub name: String,
+    pub plugin: String,
+    /// `None` if Postgres has invalidated the slot
+    pub restart_lsn: Option<Lsn>,
+    /// Why the slot can't be used, if it can't
+    pub error: Option<String>,
+}
+
+/// Progress of prewarming the local file cache with the saved working set
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LfcPrewarmStatus {
+    pub state: LfcPrewarmState,
+    /// Pages in the saved working set that fit in the cache
+    pub total_pages: u64,
+    /// Pages done so far, fetched or found in the cache already
+    pub done_pages: u64,
+    /// Pages fetched from the pageserver
+    pub fetched_pages: u64,
+    /// Pages that could not be fetched, e.g. because the relation was dropped
+    pub failed_pages: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LfcPrewarmState {
+    #[default]
+    NotStarted,
+    Prewarming,
+    Completed,
+    /// Stopped because compute stopped running
+    Cancelled,
+    Failed,
+}
+
+/// Progress of promoting a replica to a primary
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PromotionStatus {
+    pub state: PromotionState,
+    /// The commit LSN of the safekeepers that replay has to reach, once known. After fencing,
+    /// the last LSN of the old primary.
+    pub target_lsn: Option<Lsn>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PromotionState {
+    #[default]
+    NotStarted,
+    /// Replaying what the old primary has committed so far
+    CatchingUp,
+    /// Electing a new term on the safekeepers, so that the old primary can't commit anymore
+    Fencing,
+    /// Replaying the rest of the old primary's WAL
+    FinalReplay,
+    Promoting,
+    Completed,
+    Failed,
+}
+
+impl PromotionState {
+    pub fn is_in_progress(&self) -> bool {
+        !matches!(
+            self,
+            PromotionState::NotStarted | PromotionState::Completed | PromotionState::Failed
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -111,6 +191,25 @@ pub struct ComputeMetrics {
     pub num_ext_downloaded: u64,
     pub largest_ext_size: u64, // these are measured in bytes
     pub total_ext_download_size: u64,
+
+    /// Time spent prewarming the local file cache, in the background after
+    /// startup, and pages fetched while doing so.
+    pub lfc_prewarm_ms: u64,
+    pub lfc_prewarm_pages: u64,
+
+    /// Times compute_ctl switched to other pageservers on its own, because the
+    /// current ones degraded.
+    pub pageserver_failovers: u64,
+
+    /// Time spent asking the storage controller to wake the tenant, before
+    /// getting the basebackup.
+    pub tenant_wake_ms: u64,
+
+    /// Time the restore of the suspend snapshot added to the startup, and what it
+    /// restored: relation statistics entries and pages read into the buffer pool.
+    pub suspend_restore_ms: u64,
+    pub suspend_restored_relations: u64,
+    pub suspend_restored_pages: u64,
 }
 
 #[derive(Clone, Debug, Default, Serialize)]
@@ -119,6 +218,23 @@ pub struct CatalogObjects {
     pub databases: Vec<Database>,
 }
 
+/// Response of the /spec_drift API: the databases whose catalog differs from the schemas,
+/// extensions and privileges that the spec declares for them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpecDriftResponse {
+    pub databases: Vec<DatabaseDrift>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DatabaseDrift {
+    pub name: PgIdent,
+    /// The database does not exist or does not allow connections, so its contents could not be
+    /// compared with the spec
+    pub missing: bool,
+    /// Statements that would bring the database in line with the spec
+    pub statements: Vec<String>,
+}
+
 /// Response of the `/computes/{compute_id}/spec` control-plane API.
 /// This is not actually a compute API response, so consider moving
 /// to a different place.
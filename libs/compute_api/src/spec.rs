@@ -4,7 +4,9 @@
 //! all the information needed to start up the right version of PostgreSQL,
 //! and connect it to the storage nodes.
 use std::collections::HashMap;
+use std::num::NonZeroU64;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utils::id::{TenantId, TimelineId};
 use utils::lsn::Lsn;
@@ -96,6 +98,210 @@ pub struct ComputeSpec {
     // Stripe size for pageserver sharding, in pages
     #[serde(default)]
     pub shard_stripe_size: Option<usize>,
+
+    /// Queries whose results compute_ctl exports as Prometheus metrics on `/metrics`, in
+    /// addition to those of its `--sql-exporter-config` file
+    #[serde(default)]
+    pub metric_queries: Vec<MetricQuery>,
+
+    /// Save the working set of the local file cache periodically, and prewarm the cache with
+    /// it when the compute starts. Disabled if not set.
+    #[serde(default)]
+    pub lfc_prewarm: Option<LfcPrewarmSpec>,
+
+    /// Watch the requests to the pageservers, and when they fail or slow down, ask the storage
+    /// controller where the tenant is attached and switch to it without a restart. Disabled if
+    /// not set.
+    #[serde(default)]
+    pub pageserver_failover: Option<PageserverFailoverSpec>,
+
+    /// Ask the storage controller to wake the tenant when the compute starts, in case it was
+    /// hibernated, and connect to the pageservers it is then attached to. Disabled if not set.
+    #[serde(default)]
+    pub tenant_wake: Option<TenantWakeSpec>,
+
+    /// Capture the buffer pool and relation statistics when the compute is suspended with
+    /// `/terminate?mode=suspend`, and restore them when it starts again. Disabled if not set.
+    #[serde(default)]
+    pub suspend_snapshot: Option<SuspendSnapshotSpec>,
+
+    /// Settings that depend on the memory of the compute, recalculated when it starts and
+    /// whenever vm-monitor scales it. Disabled if not set.
+    #[serde(default)]
+    pub memory_scaling: Option<MemoryScalingPolicy>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct MemoryScalingPolicy {
+    pub settings: Vec<MemoryScaledSetting>,
+}
+
+/// A Postgres setting proportional to the memory of the compute. Settings that can only be
+/// changed with a restart, like `shared_buffers` or `max_connections`, take their new value
+/// on the next start.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct MemoryScaledSetting {
+    /// Name of the setting, e.g. `work_mem`
+    pub name: String,
+    /// Value per GiB of memory, in the unit of the setting in `pg_settings`, e.g. kB for
+    /// `work_mem` or 8kB pages for `shared_buffers`
+    pub per_gib: f64,
+    #[serde(default)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    pub max: Option<i64>,
+}
+
+impl MemoryScaledSetting {
+    /// The value of the setting for the given memory, in bytes
+    pub fn value_for(&self, memory_bytes: u64) -> i64 {
+        let gib = memory_bytes as f64 / (1u64 << 30) as f64;
+        let mut value = (self.per_gib * gib) as i64;
+        if let Some(min) = self.min {
+            value = value.max(min);
+        }
+        if let Some(max) = self.max {
+            value = value.min(max);
+        }
+        value
+    }
+
+    /// Whether the name can be used as is in `postgresql.conf` and `ALTER SYSTEM`
+    pub fn has_valid_name(&self) -> bool {
+        !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SuspendSnapshotSpec {
+    /// Upper bound on the time the restore adds to the startup. Prewarming of the buffer pool
+    /// stops when it runs out.
+    #[serde(default = "SuspendSnapshotSpec::default_restore_budget_ms")]
+    pub restore_budget_ms: u64,
+}
+
+impl SuspendSnapshotSpec {
+    fn default_restore_budget_ms() -> u64 {
+        2000
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PageserverFailoverSpec {
+    /// Base URL of the storage controller API, e.g. `http://storage-controller:1234`. The
+    /// `storage_auth_token` is used to authenticate to it: its `locate` API accepts tokens
+    /// scoped to the tenant.
+    pub storage_controller_url: String,
+    /// How often the request statistics are checked
+    #[serde(default = "PageserverFailoverSpec::default_check_interval_ms")]
+    pub check_interval_ms: u64,
+    /// Average wait for a page response above which a pageserver counts as degraded
+    #[serde(default = "PageserverFailoverSpec::default_max_wait_ms")]
+    pub max_wait_ms: u64,
+    /// Consecutive degraded checks after which the storage controller is consulted
+    #[serde(default = "PageserverFailoverSpec::default_degraded_checks")]
+    pub degraded_checks: u32,
+}
+
+impl PageserverFailoverSpec {
+    fn default_check_interval_ms() -> u64 {
+        2000
+    }
+
+    fn default_max_wait_ms() -> u64 {
+        1000
+    }
+
+    fn default_degraded_checks() -> u32 {
+        3
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TenantWakeSpec {
+    /// Base URL of the storage controller API, e.g. `http://storage-controller:1234`. The
+    /// `storage_auth_token` is used to authenticate to it: its `wake` API accepts tokens scoped
+    /// to the tenant.
+    pub storage_controller_url: String,
+    /// How long to wait for the tenant to be re-attached. The compute starts anyway if it takes
+    /// longer, and getting the basebackup retries until the tenant is attached.
+    #[serde(default = "TenantWakeSpec::default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl TenantWakeSpec {
+    fn default_timeout_ms() -> u64 {
+        60000
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LfcPrewarmSpec {
+    /// How often the working set is saved. Zero is rejected, rather than saving it continuously.
+    #[serde(default = "LfcPrewarmSpec::default_save_interval_secs")]
+    pub save_interval_secs: NonZeroU64,
+    /// Upper bound on the number of 1 MB cache chunks saved, most recently used first
+    #[serde(default)]
+    pub max_chunks: Option<u32>,
+    /// How fast pages are fetched from the pageserver while prewarming, in MB/s
+    #[serde(default = "LfcPrewarmSpec::default_bandwidth_mb_per_sec")]
+    pub bandwidth_mb_per_sec: u32,
+}
+
+impl LfcPrewarmSpec {
+    fn default_save_interval_secs() -> NonZeroU64 {
+        NonZeroU64::new(300).unwrap()
+    }
+
+    fn default_bandwidth_mb_per_sec() -> u32 {
+        32
+    }
+}
+
+/// A query whose results are exported as Prometheus metrics, described like an sql_exporter
+/// metric. Each row of the result gives a sample of each of the `values` columns, labelled
+/// with the `key_labels` columns.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MetricQuery {
+    pub metric_name: String,
+    #[serde(rename = "type", default)]
+    pub metric_type: MetricType,
+    pub help: String,
+    #[serde(default)]
+    pub key_labels: Vec<String>,
+    pub values: Vec<String>,
+    /// Label that holds the name of the value column. Required with several `values`.
+    #[serde(default)]
+    pub value_label: Option<String>,
+    pub query: String,
+    /// Database to run the query in, `postgres` by default
+    #[serde(default)]
+    pub database: Option<String>,
+    /// How long the query may run before it's cancelled
+    #[serde(default = "MetricQuery::default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// How long the results are reused for, so that frequent scrapes don't rerun expensive
+    /// queries. By default every scrape runs the query.
+    #[serde(default)]
+    pub cache_interval_ms: u64,
+}
+
+impl MetricQuery {
+    fn default_timeout_ms() -> u64 {
+        5000
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricType {
+    #[default]
+    Gauge,
+    Counter,
 }
 
 /// Feature flag to signal `compute_ctl` to enable certain experimental functionality.
@@ -129,6 +335,9 @@ pub struct RemoteExtSpec {
 pub struct ExtensionData {
     pub control_data: HashMap<String, String>,
     pub archive_path: String,
+    /// Hex-encoded SHA-256 of the archive. Required in signed extension indexes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
 }
 
 impl RemoteExtSpec {
@@ -236,9 +445,93 @@ pub struct DeltaOp {
 pub struct Role {
     pub name: PgIdent,
     pub encrypted_password: Option<String>,
+    /// The password the role is being rotated to. Not rotating if not set.
+    #[serde(default)]
+    pub next_password: Option<NextPassword>,
     pub options: GenericOptions,
 }
 
+/// The password a role is being rotated to.
+///
+/// Postgres keeps a single password per role, so the role gets the next password right away,
+/// and the current one is kept by a separate login role (see [`previous_password_role`]) that
+/// expires at `overlap_until`. The proxy sends clients that still use the current password to
+/// that role, which switches to the original role on login.
+///
+/// Both must be SCRAM secrets with the same salt and iteration count: those are sent to the
+/// client before it's known which password it has, so the proxy can't accept two independent
+/// secrets. `compute_ctl` refuses to apply a spec with a rotation that breaks this, see
+/// [`Role::check_rotation`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NextPassword {
+    pub encrypted_password: String,
+    /// Until when the current password is accepted as well
+    pub overlap_until: DateTime<Utc>,
+}
+
+/// Suffix of the role that keeps the current password of a role during a rotation.
+///
+/// The proxy has its own copy, the two must match.
+pub const PREVIOUS_PASSWORD_ROLE_SUFFIX: &str = "/previous";
+
+/// Name of the role that keeps the current password of the role `name` during a rotation
+pub fn previous_password_role(name: &str) -> PgIdent {
+    format!("{name}{PREVIOUS_PASSWORD_ROLE_SUFFIX}")
+}
+
+impl Role {
+    /// The password that the role itself has in Postgres: the next one during a rotation.
+    pub fn effective_password(&self) -> Option<&String> {
+        match &self.next_password {
+            Some(next) => Some(&next.encrypted_password),
+            None => self.encrypted_password.as_ref(),
+        }
+    }
+
+    /// The password rotation of the role, if the current password is still accepted at `now`.
+    pub fn rotation_at(&self, now: DateTime<Utc>) -> Option<&NextPassword> {
+        self.next_password
+            .as_ref()
+            .filter(|next| self.encrypted_password.is_some() && now < next.overlap_until)
+    }
+
+    /// Check that both passwords of the password rotation of the role, if any at `now`,
+    /// can be accepted, see [`NextPassword`].
+    pub fn check_rotation(&self, now: DateTime<Utc>) -> Result<(), String> {
+        let Some(next) = self.rotation_at(now) else {
+            return Ok(());
+        };
+        let current = self
+            .encrypted_password
+            .as_deref()
+            .and_then(scram_parameters);
+        let Some(current) = current else {
+            return Err(format!(
+                "can't rotate the password of role '{}': the current password is not a SCRAM secret",
+                self.name
+            ));
+        };
+        match scram_parameters(&next.encrypted_password) {
+            Some(params) if params == current => Ok(()),
+            Some(_) => Err(format!(
+                "can't rotate the password of role '{}': the next password has a different salt or iteration count",
+                self.name
+            )),
+            None => Err(format!(
+                "can't rotate the password of role '{}': the next password is not a SCRAM secret",
+                self.name
+            )),
+        }
+    }
+}
+
+/// The `<iterations>:<salt>` part of a `SCRAM-SHA-256$<iterations>:<salt>$<stored key>:<server key>`
+/// secret, as Postgres stores it.
+fn scram_parameters(secret: &str) -> Option<&str> {
+    let (params, keys) = secret.strip_prefix("SCRAM-SHA-256$")?.split_once('$')?;
+    (params.contains(':') && keys.contains(':')).then_some(params)
+}
+
 /// Rust representation of Postgres database info with only those fields
 /// that matter for us.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -246,6 +539,10 @@ pub struct Database {
     pub name: PgIdent,
     pub owner: PgIdent,
     pub options: GenericOptions,
+    /// Schemas, extensions and privileges that should exist in this database. `compute_ctl`
+    /// creates or alters them to match, but never drops or revokes anything that is not listed.
+    #[serde(default, skip_serializing_if = "DatabaseObjects::is_empty")]
+    pub objects: DatabaseObjects,
     // These are derived flags, not present in the spec file.
     // They are never set by the control plane.
     #[serde(skip_deserializing, default)]
@@ -254,6 +551,91 @@ pub struct Database {
     pub invalid: bool,
 }
 
+/// Objects inside a database that are managed declaratively by `compute_ctl`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DatabaseObjects {
+    #[serde(default)]
+    pub schemas: Vec<Schema>,
+    #[serde(default)]
+    pub extensions: Vec<Extension>,
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+    #[serde(default)]
+    pub default_privileges: Vec<DefaultPrivileges>,
+}
+
+impl DatabaseObjects {
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+            && self.extensions.is_empty()
+            && self.grants.is_empty()
+            && self.default_privileges.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Schema {
+    pub name: PgIdent,
+    /// Defaults to the owner of the database
+    pub owner: Option<PgIdent>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Extension {
+    pub name: PgIdent,
+    /// If set, the extension is updated to this version. Otherwise it is created at its default
+    /// version, and left at whatever version it has.
+    pub version: Option<String>,
+    /// If set, the extension is created in (or moved to) this schema
+    pub schema: Option<PgIdent>,
+}
+
+/// `GRANT <privileges> ON <on> TO <role>`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Grant {
+    pub role: PgIdent,
+    /// Privilege keywords such as `USAGE` or `SELECT`, or `ALL` for every privilege that applies
+    pub privileges: Vec<String>,
+    pub on: GrantTarget,
+    #[serde(default)]
+    pub with_grant_option: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GrantTarget {
+    Database,
+    Schema { schema: PgIdent },
+    AllTablesInSchema { schema: PgIdent },
+    AllSequencesInSchema { schema: PgIdent },
+    AllFunctionsInSchema { schema: PgIdent },
+}
+
+/// `ALTER DEFAULT PRIVILEGES FOR ROLE <for_role> [IN SCHEMA <schema>] GRANT <privileges> ON
+/// <on> TO <role>`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DefaultPrivileges {
+    /// The role whose future objects receive the privileges. Defaults to the owner of the
+    /// database.
+    pub for_role: Option<PgIdent>,
+    pub schema: Option<PgIdent>,
+    pub role: PgIdent,
+    pub privileges: Vec<String>,
+    pub on: DefaultPrivilegesTarget,
+    #[serde(default)]
+    pub with_grant_option: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultPrivilegesTarget {
+    Tables,
+    Sequences,
+    Functions,
+    Types,
+    Schemas,
+}
+
 /// Common type representing both SQL statement params with or without value,
 /// like `LOGIN` or `OWNER username` in the `CREATE/ALTER ROLE`, and config
 /// options like `wal_level = logical`.
@@ -282,6 +664,170 @@ fn parse_spec_file() {
         assert!(spec.features.is_empty());
     }
 
+    #[test]
+    fn parse_database_objects() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        // Databases without objects default to none.
+        assert!(spec.cluster.databases[0].objects.is_empty());
+
+        let objects = &spec.cluster.databases[2].objects;
+        assert_eq!(objects.schemas[0].owner, None);
+        assert_eq!(objects.extensions[0].version.as_deref(), Some("1.6"));
+        assert_eq!(
+            objects.grants[0].on,
+            GrantTarget::Schema {
+                schema: "app".to_string()
+            }
+        );
+        assert!(!objects.grants[0].with_grant_option);
+        assert_eq!(
+            objects.default_privileges[0].on,
+            DefaultPrivilegesTarget::Tables
+        );
+    }
+
+    #[test]
+    fn parse_metric_queries() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let size = &spec.metric_queries[0];
+        assert_eq!(size.metric_type, MetricType::Gauge);
+        assert!(size.key_labels.is_empty());
+        assert_eq!(size.database, None);
+        assert_eq!(size.timeout_ms, 5000);
+        assert_eq!(size.cache_interval_ms, 60000);
+
+        let xacts = &spec.metric_queries[1];
+        assert_eq!(xacts.metric_type, MetricType::Counter);
+        assert_eq!(xacts.value_label.as_deref(), Some("kind"));
+        assert_eq!(xacts.timeout_ms, 1000);
+    }
+
+    #[test]
+    fn parse_lfc_prewarm() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let prewarm = spec.lfc_prewarm.unwrap();
+        assert_eq!(prewarm.save_interval_secs.get(), 300);
+        assert_eq!(prewarm.max_chunks, None);
+        assert_eq!(prewarm.bandwidth_mb_per_sec, 64);
+
+        let zero_interval = serde_json::from_str::<LfcPrewarmSpec>(r#"{"save_interval_secs": 0}"#);
+        assert!(zero_interval.is_err());
+    }
+
+    #[test]
+    fn parse_pageserver_failover() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let failover = spec.pageserver_failover.unwrap();
+        assert_eq!(
+            failover.storage_controller_url,
+            "http://storage-controller:1234"
+        );
+        assert_eq!(failover.check_interval_ms, 2000);
+        assert_eq!(failover.max_wait_ms, 500);
+        assert_eq!(failover.degraded_checks, 3);
+    }
+
+    #[test]
+    fn parse_tenant_wake() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let wake = spec.tenant_wake.unwrap();
+        assert_eq!(
+            wake.storage_controller_url,
+            "http://storage-controller:1234"
+        );
+        assert_eq!(wake.timeout_ms, 60000);
+    }
+
+    #[test]
+    fn parse_suspend_snapshot() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let snapshot = spec.suspend_snapshot.unwrap();
+        assert_eq!(snapshot.restore_budget_ms, 2000);
+    }
+
+    #[test]
+    fn parse_memory_scaling() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let policy = spec.memory_scaling.unwrap();
+        assert_eq!(policy.settings.len(), 2);
+        let work_mem = &policy.settings[0];
+        assert_eq!(work_mem.name, "work_mem");
+        assert!(work_mem.has_valid_name());
+        // 4 MB per GiB, at least 4 MB, at most 256 MB
+        assert_eq!(work_mem.value_for(1 << 28), 4096);
+        assert_eq!(work_mem.value_for(8 << 30), 32768);
+        assert_eq!(work_mem.value_for(1 << 40), 262144);
+        let max_connections = &policy.settings[1];
+        assert_eq!(max_connections.value_for(1 << 30), 100);
+        assert_eq!(max_connections.value_for(1 << 40), 102400);
+
+        let invalid = MemoryScaledSetting {
+            name: "work_mem = 1; --".to_string(),
+            ..work_mem.clone()
+        };
+        assert!(!invalid.has_valid_name());
+    }
+
+    #[test]
+    fn parse_next_password() {
+        let file = File::open("tests/cluster_spec.json").unwrap();
+        let spec: ComputeSpec = serde_json::from_reader(file).unwrap();
+
+        let role = spec
+            .cluster
+            .roles
+            .iter()
+            .find(|r| r.name == "rotating")
+            .unwrap();
+        let next = role.next_password.as_ref().unwrap();
+        assert_eq!(role.effective_password(), Some(&next.encrypted_password));
+
+        let before = "2024-08-31T23:59:59Z".parse().unwrap();
+        let after = "2024-09-01T00:00:00Z".parse().unwrap();
+        assert_eq!(role.rotation_at(before), Some(next));
+        assert_eq!(role.rotation_at(after), None);
+        assert_eq!(previous_password_role(&role.name), "rotating/previous");
+        assert_eq!(role.check_rotation(before), Ok(()));
+
+        // The proxy couldn't accept both passwords with different salts
+        let mut other_salt = role.clone();
+        other_salt
+            .next_password
+            .as_mut()
+            .unwrap()
+            .encrypted_password = next
+            .encrypted_password
+            .replace("QSXCR+Q6sek8bf92", "3Qt2QtfzJ7B3Gd7R");
+        assert!(other_salt.check_rotation(before).is_err());
+        // ... but it doesn't matter once the rotation is over
+        assert_eq!(other_salt.check_rotation(after), Ok(()));
+
+        let mut not_scram = role.clone();
+        not_scram.encrypted_password = Some("5b1d16b78004bbd51fa06af9eda75972".to_string());
+        assert!(not_scram.check_rotation(before).is_err());
+
+        // Roles that aren't rotating keep their password
+        let role = spec.cluster.roles.iter().find(|r| r.name == "zen").unwrap();
+        assert!(role.next_password.is_none());
+        assert_eq!(role.effective_password(), role.encrypted_password.as_ref());
+        assert_eq!(role.rotation_at(before), None);
+        assert_eq!(role.check_rotation(before), Ok(()));
+    }
+
     #[test]
     fn parse_unknown_fields() {
         // Forward compatibility test
@@ -6,12 +6,13 @@
 /// API (`/control/v1` prefix).  Implemented by the server
 /// in [`storage_controller::http`]
 use serde::{Deserialize, Serialize};
-use utils::id::{NodeId, TenantId};
+use utils::id::{NodeId, TenantId, TimelineId};
+use utils::lsn::Lsn;
 
 use crate::models::PageserverUtilization;
 use crate::{
     models::{ShardParameters, TenantConfig},
-    shard::{ShardStripeSize, TenantShardId},
+    shard::{ShardNumber, ShardStripeSize, TenantShardId},
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -351,6 +352,384 @@ pub struct MetadataHealthListOutdatedResponse {
     pub health_records: Vec<MetadataHealthRecord>,
 }
 
+/// How a rolling operation restarts or upgrades a pageserver, once it has been drained.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RollingOperationHook {
+    /// Run a program on the storage controller's host.  The node is described to it in the
+    /// `NODE_ID`, `NODE_HTTP_ADDR`, `NODE_HTTP_PORT` and `NODE_AVAILABILITY_ZONE_ID` environment
+    /// variables, and a non-zero exit status fails the operation.
+    Command { program: String, args: Vec<String> },
+    /// POST a [`RollingOperationHookBody`] to this URL.  A non-2xx status fails the operation.
+    Http { url: String },
+}
+
+/// Body of the request sent by [`RollingOperationHook::Http`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RollingOperationHookBody {
+    pub node_id: NodeId,
+    pub listen_http_addr: String,
+    pub listen_http_port: u16,
+    pub availability_zone_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RollingOperationRequest {
+    /// Nodes to restart, in order.  With `by_availability_zone`, the order of the first node in
+    /// each AZ determines the order of the AZs.
+    pub nodes: Vec<NodeId>,
+    pub hook: RollingOperationHook,
+    /// If set, a node is only considered restarted once it reports this version.  Otherwise,
+    /// any version different from the one it reported before the hook will do.
+    #[serde(default)]
+    pub target_version: Option<String>,
+    /// Restart all the nodes in an availability zone at the same time, rather than one at a time
+    #[serde(default)]
+    pub by_availability_zone: bool,
+    /// How long to wait for a node to come back with its new version, after running the hook
+    #[serde(default = "RollingOperationRequest::default_restart_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub restart_timeout: Duration,
+}
+
+impl RollingOperationRequest {
+    fn default_restart_timeout() -> Duration {
+        Duration::from_secs(600)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RollingOperationState {
+    Running,
+    /// Not progressing, either because it was requested, or because a step failed.  May be resumed.
+    Paused,
+    Aborted,
+    Complete,
+}
+
+impl RollingOperationState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Aborted | Self::Complete)
+    }
+}
+
+impl FromStr for RollingOperationState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(Self::Running),
+            "paused" => Ok(Self::Paused),
+            "aborted" => Ok(Self::Aborted),
+            "complete" => Ok(Self::Complete),
+            _ => Err(anyhow::anyhow!("Unknown rolling operation state '{s}'")),
+        }
+    }
+}
+
+impl From<RollingOperationState> for String {
+    fn from(value: RollingOperationState) -> String {
+        use RollingOperationState::*;
+        match value {
+            Running => "running",
+            Paused => "paused",
+            Aborted => "aborted",
+            Complete => "complete",
+        }
+        .to_string()
+    }
+}
+
+/// How far a rolling operation has got with one node.  Steps are in the order they happen.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RollingNodeStep {
+    Pending,
+    Draining,
+    /// Running the hook.  If the operation is resumed in this step, the hook is run again, so
+    /// hooks should tolerate being called for a node which has already restarted.
+    Restarting {
+        version_before: Option<String>,
+    },
+    /// The hook completed: waiting for the node to come back with a new version.
+    AwaitingVersion {
+        version_before: Option<String>,
+    },
+    Filling,
+    Done,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RollingNodeStatus {
+    pub node_id: NodeId,
+    pub step: RollingNodeStep,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RollingOperationStatus {
+    pub id: String,
+    pub request: RollingOperationRequest,
+    pub state: RollingOperationState,
+    pub nodes: Vec<RollingNodeStatus>,
+    /// Why the operation was paused, if a step failed
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What an entry in the storage controller's operation history describes
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationEventKind {
+    /// The controller changed where a shard should be attached or have secondaries
+    Schedule,
+    /// A reconciler finished, successfully or not
+    Reconcile,
+    /// A node's availability or scheduling policy changed, or it was registered or deleted
+    NodeState,
+    /// A tenant's shards were split or merged
+    ShardCount,
+    /// A mutating call to the controller's HTTP API
+    Api,
+}
+
+impl FromStr for OperationEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "schedule" => Ok(Self::Schedule),
+            "reconcile" => Ok(Self::Reconcile),
+            "node_state" => Ok(Self::NodeState),
+            "shard_count" => Ok(Self::ShardCount),
+            "api" => Ok(Self::Api),
+            _ => Err(anyhow::anyhow!("Unknown operation event kind '{s}'")),
+        }
+    }
+}
+
+impl From<OperationEventKind> for String {
+    fn from(value: OperationEventKind) -> String {
+        use OperationEventKind::*;
+        match value {
+            Schedule => "schedule",
+            Reconcile => "reconcile",
+            NodeState => "node_state",
+            ShardCount => "shard_count",
+            Api => "api",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OperationEvent {
+    pub id: i64,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub kind: OperationEventKind,
+    /// `controller` for decisions the controller took by itself, `api` (with the caller's
+    /// token scope, if auth is enabled) for changes requested through the API
+    pub actor: String,
+    pub tenant_id: Option<TenantId>,
+    pub tenant_shard_id: Option<TenantShardId>,
+    pub node_id: Option<NodeId>,
+    pub detail: String,
+}
+
+/// A hypothetical change, for which the storage controller will work out the resulting
+/// scheduling decisions without carrying any of them out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlanRequest {
+    /// Drain a node's attachments onto their secondaries, as `/control/v1/node/:node_id/drain` would
+    NodeDrain { node_id: NodeId },
+    /// Register a new, empty node, with the same capacity as the existing nodes in its AZ
+    NodeAdd {
+        node_id: NodeId,
+        availability_zone_id: String,
+    },
+    /// Change a tenant's placement policy, as `/control/v1/tenant/:tenant_id/policy` would
+    TenantPolicy {
+        tenant_id: TenantId,
+        placement: PlacementPolicy,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMigration {
+    pub tenant_shard_id: TenantShardId,
+    pub from: Option<NodeId>,
+    pub to: Option<NodeId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedLocation {
+    pub tenant_shard_id: TenantShardId,
+    pub node_id: NodeId,
+}
+
+/// A shard that the change would leave somewhere other than where we would like it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedShardIssue {
+    pub tenant_shard_id: TenantShardId,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedNodeChange {
+    pub node_id: NodeId,
+    pub shard_count_before: usize,
+    pub shard_count_after: usize,
+    pub attached_shard_count_before: usize,
+    pub attached_shard_count_after: usize,
+    /// None if the node may not have shards scheduled on it
+    pub utilization_score_before: Option<u64>,
+    pub utilization_score_after: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanResponse {
+    /// Changes of attached location.  `from` is None for shards that would be attached for the
+    /// first time, and `to` is None for shards that would be detached.
+    pub migrations: Vec<PlannedMigration>,
+    /// New secondary locations
+    pub secondaries_created: Vec<PlannedLocation>,
+    /// Locations, attached or secondary, that would be removed entirely
+    pub locations_removed: Vec<PlannedLocation>,
+    pub issues: Vec<PlannedShardIssue>,
+    pub nodes: Vec<PlannedNodeChange>,
+    /// False if the optimizer was still finding work when planning gave up: the plan is then only
+    /// the first part of what would happen.
+    pub converged: bool,
+}
+
+/// A compute notification that the storage controller has not yet managed to deliver.  It
+/// is retried with backoff until it succeeds, or until a newer notification for the same
+/// tenant is delivered.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingComputeNotification {
+    pub tenant_id: TenantId,
+    /// How many attempts to deliver have failed
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub first_failed_at: chrono::DateTime<chrono::Utc>,
+    pub next_attempt_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Where a tenant import reads a tar archive from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImportSource {
+    /// Uploaded to the storage controller after the import is created, with
+    /// `PUT /control/v1/import/:tenant_id/upload/:archive`
+    Upload,
+    /// A file on the storage controller's local filesystem
+    LocalPath { path: String },
+    /// An HTTP(S) URL, such as a presigned URL for an object in remote storage
+    Url { url: String },
+}
+
+/// Create a new tenant, with a single timeline imported from a vanilla Postgres basebackup, such
+/// as the output of `pg_basebackup --format=tar --wal-method=none`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TenantImportRequest {
+    pub tenant_id: TenantId,
+    pub timeline_id: TimelineId,
+    #[serde(default)]
+    pub shard_parameters: ShardParameters,
+    #[serde(default)]
+    pub placement_policy: Option<PlacementPolicy>,
+    pub pg_version: u32,
+    /// The LSN of the basebackup's checkpoint
+    pub base_lsn: Lsn,
+    /// The LSN that the timeline should end at once the WAL has been imported.  Without `wal`,
+    /// this must equal `base_lsn`.
+    pub end_lsn: Lsn,
+    /// The basebackup, as a tar archive of the data directory (`base.tar`)
+    pub basebackup: ImportSource,
+    /// WAL segments covering `base_lsn` to `end_lsn`, as a tar archive (`pg_wal.tar`)
+    #[serde(default)]
+    pub wal: Option<ImportSource>,
+    /// Compact the imported data into image layers, rather than leaving it in the L0 delta
+    /// layers that ingest writes
+    #[serde(default = "TenantImportRequest::default_image_layers")]
+    pub image_layers: bool,
+}
+
+impl TenantImportRequest {
+    fn default_image_layers() -> bool {
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantImportState {
+    /// Waiting for archives with an [`ImportSource::Upload`] source to be uploaded
+    AwaitingUpload,
+    Running,
+    /// A step failed.  May be resumed, which retries the step.
+    Failed,
+    Complete,
+}
+
+impl FromStr for TenantImportState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "awaiting_upload" => Ok(Self::AwaitingUpload),
+            "running" => Ok(Self::Running),
+            "failed" => Ok(Self::Failed),
+            "complete" => Ok(Self::Complete),
+            _ => Err(anyhow::anyhow!("Unknown tenant import state '{s}'")),
+        }
+    }
+}
+
+impl From<TenantImportState> for String {
+    fn from(value: TenantImportState) -> String {
+        use TenantImportState::*;
+        match value {
+            AwaitingUpload => "awaiting_upload",
+            Running => "running",
+            Failed => "failed",
+            Complete => "complete",
+        }
+        .to_string()
+    }
+}
+
+/// How far a tenant import has got with one shard.  Steps are in the order they happen.
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportShardStep {
+    Pending,
+    /// The timeline exists on the shard, but the WAL has not been imported yet
+    BasebackupImported,
+    Done,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportShardStatus {
+    pub shard_number: ShardNumber,
+    pub step: ImportShardStep,
+    /// How much of the archives has been sent to this shard's pageserver
+    pub bytes_sent: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TenantImportStatus {
+    pub request: TenantImportRequest,
+    pub state: TenantImportState,
+    pub shards: Vec<ImportShardStatus>,
+    /// Why the import failed, if it did
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -250,8 +250,20 @@ pub struct TenantShardSplitResponse {
     pub new_shards: Vec<TenantShardId>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct TenantShardMergeRequest {
+    // Must be a power-of-two fraction of the current shard count.  The stripe size
+    // is left unchanged by a merge, so there is no equivalent of `new_stripe_size`.
+    pub new_shard_count: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TenantShardMergeResponse {
+    pub new_shards: Vec<TenantShardId>,
+}
+
 /// Parameters that apply to all shards in a tenant.  Used during tenant creation.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct ShardParameters {
     pub count: ShardCount,
@@ -587,9 +599,12 @@ pub struct LocationConfigListResponse {
     pub tenant_shards: Vec<(TenantShardId, Option<LocationConfig>)>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct StatusResponse {
     pub id: NodeId,
+    /// Build version of the pageserver binary
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,6 +1,8 @@
 use std::time::SystemTime;
 use utils::{serde_percent::Percent, serde_system_time};
 
+use crate::shard::TenantShardId;
+
 /// Pageserver current utilization and scoring for how good candidate the pageserver would be for
 /// the next tenant.
 ///
@@ -37,6 +39,35 @@ pub struct PageserverUtilization {
     #[serde(default)]
     pub max_shard_count: u32,
 
+    /// WAL ingested by attached shards on this node, summed over timelines.
+    #[serde(serialize_with = "ser_saturating_u63", default)]
+    pub ingest_bytes_per_second: u64,
+
+    /// How much WAL ingest this node should be able to handle at most.  Zero if the node
+    /// does not report load, in which case ingest does not contribute to the score.
+    #[serde(serialize_with = "ser_saturating_u63", default)]
+    pub max_ingest_bytes_per_second: u64,
+
+    /// GetPage requests served by attached shards on this node.
+    #[serde(serialize_with = "ser_saturating_u63", default)]
+    pub getpage_requests_per_second: u64,
+
+    /// How many GetPage requests this node should be able to serve at most.  Zero if the node
+    /// does not report load, in which case GetPage requests do not contribute to the score.
+    #[serde(serialize_with = "ser_saturating_u63", default)]
+    pub max_getpage_requests_per_second: u64,
+
+    /// The most loaded attached shards on this node, most loaded first.  This is not an exhaustive
+    /// list: it is for the storage controller to pick shards to move off busy nodes.
+    #[serde(default)]
+    pub shard_loads: Vec<ShardLoad>,
+
+    /// All the attached shards on this node that ingested WAL or served any GetPage requests since
+    /// the previous utilization report, or that a compute is connected to.  Unlike
+    /// [`Self::shard_loads`], this is exhaustive.  None if the pageserver predates activity reporting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_shards: Option<Vec<TenantShardId>>,
+
     /// Cached result of [`Self::score`]
     pub utilization_score: Option<u64>,
 
@@ -46,6 +77,18 @@ pub struct PageserverUtilization {
     pub captured_at: serde_system_time::SystemTime,
 }
 
+/// Load generated by one attached tenant shard, as reported in [`PageserverUtilization::shard_loads`].
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardLoad {
+    pub id: TenantShardId,
+    #[serde(serialize_with = "ser_saturating_u63")]
+    pub ingest_bytes_per_second: u64,
+    #[serde(serialize_with = "ser_saturating_u63")]
+    pub getpage_requests_per_second: u64,
+    #[serde(serialize_with = "ser_saturating_u63")]
+    pub resident_size: u64,
+}
+
 fn unity_percent() -> Percent {
     Percent::new(0).unwrap()
 }
@@ -53,7 +96,7 @@ fn unity_percent() -> Percent {
 pub type RawScore = u64;
 
 impl PageserverUtilization {
-    const UTILIZATION_FULL: u64 = 1000000;
+    pub const UTILIZATION_FULL: u64 = 1000000;
 
     /// Calculate a utilization score.  The result is to be inrepreted as a fraction of
     /// Self::UTILIZATION_FULL.
@@ -73,7 +116,50 @@ pub fn score(&self) -> RawScore {
 
         let shard_utilization_score =
             self.shard_count as u64 * Self::UTILIZATION_FULL / self.max_shard_count as u64;
-        std::cmp::max(disk_utilization_score, shard_utilization_score)
+        std::cmp::max(
+            std::cmp::max(disk_utilization_score, shard_utilization_score),
+            self.load_score(),
+        )
+    }
+
+    /// The part of [`Self::score`] that comes from the work attached shards are doing, rather than
+    /// from how many of them there are and how much disk they use.  Zero for nodes that do not
+    /// report their load capacity.
+    pub fn load_score(&self) -> RawScore {
+        self.load_score_of(
+            self.ingest_bytes_per_second,
+            self.getpage_requests_per_second,
+        )
+    }
+
+    /// How much [`Self::load_score`] would change if a shard with this load was added to or removed from
+    /// this node.
+    pub fn shard_load_score(&self, load: &ShardLoad) -> RawScore {
+        self.load_score_of(
+            load.ingest_bytes_per_second,
+            load.getpage_requests_per_second,
+        )
+    }
+
+    fn load_score_of(
+        &self,
+        ingest_bytes_per_second: u64,
+        getpage_requests_per_second: u64,
+    ) -> RawScore {
+        let fraction = |value: u64, max: u64| {
+            if max == 0 {
+                0
+            } else {
+                (value as u128 * Self::UTILIZATION_FULL as u128 / max as u128) as u64
+            }
+        };
+        std::cmp::max(
+            fraction(ingest_bytes_per_second, self.max_ingest_bytes_per_second),
+            fraction(
+                getpage_requests_per_second,
+                self.max_getpage_requests_per_second,
+            ),
+        )
     }
 
     pub fn cached_score(&mut self) -> RawScore {
@@ -113,6 +199,46 @@ pub fn adjust_shard_count_max(&mut self, shard_count: u32) {
         }
     }
 
+    /// Account for a shard's load arriving on or leaving this node, ahead of the next heartbeat that
+    /// would tell us the same thing.
+    pub fn adjust_load(&mut self, load: &ShardLoad, arriving: bool) {
+        if arriving {
+            self.ingest_bytes_per_second += load.ingest_bytes_per_second;
+            self.getpage_requests_per_second += load.getpage_requests_per_second;
+        } else {
+            self.ingest_bytes_per_second = self
+                .ingest_bytes_per_second
+                .saturating_sub(load.ingest_bytes_per_second);
+            self.getpage_requests_per_second = self
+                .getpage_requests_per_second
+                .saturating_sub(load.getpage_requests_per_second);
+        }
+
+        // Dirty cache: this will be calculated next time someone retrives the score
+        self.utilization_score = None;
+    }
+
+    /// A utilization structure for an empty node with the same capacity as this one: use this to
+    /// model a node that has not been deployed yet.
+    pub fn empty_like(&self) -> Self {
+        Self {
+            disk_usage_bytes: 0,
+            free_space_bytes: self.disk_usage_bytes + self.free_space_bytes,
+            disk_wanted_bytes: 0,
+            disk_usable_pct: self.disk_usable_pct,
+            shard_count: 0,
+            max_shard_count: self.max_shard_count,
+            ingest_bytes_per_second: 0,
+            max_ingest_bytes_per_second: self.max_ingest_bytes_per_second,
+            getpage_requests_per_second: 0,
+            max_getpage_requests_per_second: self.max_getpage_requests_per_second,
+            shard_loads: Vec::new(),
+            active_shards: None,
+            utilization_score: None,
+            captured_at: serde_system_time::SystemTime(SystemTime::now()),
+        }
+    }
+
     /// A utilization structure that has a full utilization score: use this as a placeholder when
     /// you need a utilization but don't have real values yet.
     pub fn full() -> Self {
@@ -123,6 +249,12 @@ pub fn full() -> Self {
             disk_usable_pct: Percent::new(100).unwrap(),
             shard_count: 1,
             max_shard_count: 1,
+            ingest_bytes_per_second: 0,
+            max_ingest_bytes_per_second: 0,
+            getpage_requests_per_second: 0,
+            max_getpage_requests_per_second: 0,
+            shard_loads: Vec::new(),
+            active_shards: None,
             utilization_score: Some(Self::UTILIZATION_FULL),
             captured_at: serde_system_time::SystemTime(SystemTime::now()),
         }
@@ -141,6 +273,8 @@ pub mod test_utilization {
     // Parameters of the imaginary node used for test utilization instances
     const TEST_DISK_SIZE: u64 = 1024 * 1024 * 1024 * 1024;
     const TEST_SHARDS_MAX: u32 = 1000;
+    const TEST_INGEST_MAX: u64 = 100 * 1024 * 1024;
+    const TEST_GETPAGE_MAX: u64 = 100000;
 
     /// Unit test helper.  Unconditionally compiled because cfg(test) doesn't carry across crates.  Do
     /// not abuse this function from non-test code.
@@ -154,6 +288,12 @@ pub fn simple(shard_count: u32, disk_wanted_bytes: u64) -> PageserverUtilization
             disk_usable_pct: Percent::new(100).unwrap(),
             shard_count,
             max_shard_count: TEST_SHARDS_MAX,
+            ingest_bytes_per_second: 0,
+            max_ingest_bytes_per_second: TEST_INGEST_MAX,
+            getpage_requests_per_second: 0,
+            max_getpage_requests_per_second: TEST_GETPAGE_MAX,
+            shard_loads: Vec::new(),
+            active_shards: None,
             utilization_score: None,
             captured_at: serde_system_time::SystemTime(SystemTime::now()),
         }
@@ -190,6 +330,12 @@ fn u64_max_is_serialized_as_u63_max() {
             disk_usable_pct: Percent::new(90).unwrap(),
             shard_count: 100,
             max_shard_count: 200,
+            ingest_bytes_per_second: 0,
+            max_ingest_bytes_per_second: 0,
+            getpage_requests_per_second: 0,
+            max_getpage_requests_per_second: 0,
+            shard_loads: Vec::new(),
+            active_shards: None,
             captured_at: serde_system_time::SystemTime(
                 std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1708509779),
             ),
@@ -197,8 +343,39 @@ fn u64_max_is_serialized_as_u63_max() {
 
         let s = serde_json::to_string(&doc).unwrap();
 
-        let expected = "{\"disk_usage_bytes\":9223372036854775807,\"free_space_bytes\":0,\"disk_wanted_bytes\":9223372036854775807,\"disk_usable_pct\":90,\"shard_count\":100,\"max_shard_count\":200,\"utilization_score\":13,\"captured_at\":\"2024-02-21T10:02:59.000Z\"}";
+        let expected = "{\"disk_usage_bytes\":9223372036854775807,\"free_space_bytes\":0,\"disk_wanted_bytes\":9223372036854775807,\"disk_usable_pct\":90,\"shard_count\":100,\"max_shard_count\":200,\"ingest_bytes_per_second\":0,\"max_ingest_bytes_per_second\":0,\"getpage_requests_per_second\":0,\"max_getpage_requests_per_second\":0,\"shard_loads\":[],\"utilization_score\":13,\"captured_at\":\"2024-02-21T10:02:59.000Z\"}";
 
         assert_eq!(s, expected);
     }
+
+    #[test]
+    fn load_contributes_to_score() {
+        let mut doc = test_utilization::simple(0, 0);
+        assert_eq!(doc.score(), 0);
+
+        let load = ShardLoad {
+            id: TenantShardId::unsharded(utils::id::TenantId::generate()),
+            ingest_bytes_per_second: doc.max_ingest_bytes_per_second / 2,
+            getpage_requests_per_second: doc.max_getpage_requests_per_second / 4,
+            resident_size: 0,
+        };
+        assert_eq!(
+            doc.shard_load_score(&load),
+            PageserverUtilization::UTILIZATION_FULL / 2
+        );
+
+        doc.adjust_load(&load, true);
+        assert_eq!(
+            doc.cached_score(),
+            PageserverUtilization::UTILIZATION_FULL / 2
+        );
+        doc.adjust_load(&load, false);
+        assert_eq!(doc.cached_score(), 0);
+
+        // Nodes that don't report their capacity are scored on disk and shard count alone
+        doc.max_ingest_bytes_per_second = 0;
+        doc.max_getpage_requests_per_second = 0;
+        doc.adjust_load(&load, true);
+        assert_eq!(doc.cached_score(), 0);
+    }
 }
@@ -590,4 +590,51 @@ fn shard_id_split() {
             ]
         );
     }
+
+    #[test]
+    fn shard_id_merge() {
+        let tenant_id = TenantId::generate();
+
+        // count=8 into count=2: every source maps onto its number modulo the new count
+        for shard_number in 0..8 {
+            let source = TenantShardId {
+                tenant_id,
+                shard_count: ShardCount(8),
+                shard_number: ShardNumber(shard_number),
+            };
+            assert_eq!(
+                source.merge(ShardCount(2)),
+                TenantShardId {
+                    tenant_id,
+                    shard_count: ShardCount(2),
+                    shard_number: ShardNumber(shard_number % 2)
+                }
+            );
+        }
+
+        // Merging is the inverse of splitting
+        let merged = TenantShardId {
+            tenant_id,
+            shard_count: ShardCount(2),
+            shard_number: ShardNumber(1),
+        };
+        for source in merged.split(ShardCount(8)) {
+            assert_eq!(source.merge(ShardCount(2)), merged);
+        }
+
+        // count=4 into count=1
+        let source = TenantShardId {
+            tenant_id,
+            shard_count: ShardCount(4),
+            shard_number: ShardNumber(3),
+        };
+        assert_eq!(
+            source.merge(ShardCount(1)),
+            TenantShardId {
+                tenant_id,
+                shard_count: ShardCount(1),
+                shard_number: ShardNumber(0)
+            }
+        );
+    }
 }
@@ -162,6 +162,22 @@ pub fn split(&self, new_shard_count: ShardCount) -> Vec<TenantShardId> {
 
         child_shards
     }
+
+    /// Calculate the shard that this TenantShardId will be merged into when reducing the
+    /// overall tenant to the given number of shards.  This is the inverse of [`Self::split`]:
+    /// the sources of a merged shard are `merged.split(old_shard_count)`.
+    ///
+    /// The new shard count must divide the current one, otherwise keys would not map
+    /// cleanly onto the merged shards.
+    pub fn merge(&self, new_shard_count: ShardCount) -> TenantShardId {
+        let effective_new_shard_count = std::cmp::max(new_shard_count.0, 1);
+        debug_assert!(std::cmp::max(self.shard_count.0, 1) % effective_new_shard_count == 0);
+        TenantShardId {
+            tenant_id: self.tenant_id,
+            shard_number: ShardNumber(self.shard_number.0 % effective_new_shard_count),
+            shard_count: new_shard_count,
+        }
+    }
 }
 
 impl<'a> std::fmt::Display for ShardSlug<'a> {
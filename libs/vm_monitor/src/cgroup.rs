@@ -1,4 +1,6 @@
 use std::fmt::{self, Debug, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context};
@@ -10,6 +12,9 @@
 use tokio::sync::watch;
 use tracing::{info, warn};
 
+/// Where the unified hierarchy of cgroups v2 is mounted
+const UNIFIED_MOUNTPOINT: &str = "/sys/fs/cgroup";
+
 /// Configuration for a `CgroupWatcher`
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -50,14 +55,28 @@ fn default() -> Self {
 pub struct CgroupWatcher {
     pub config: Config,
 
-    /// The actual cgroup we are watching and managing.
-    cgroup: cgroups_rs::Cgroup,
+    /// The actual cgroup we are watching and managing. `None` if it was given as a directory,
+    /// whose files are then read directly.
+    cgroup: Option<cgroups_rs::Cgroup>,
+
+    /// The directory of the cgroup
+    dir: PathBuf,
 }
 
 impl CgroupWatcher {
-    /// Create a new `CgroupWatcher`.
+    /// Create a new `CgroupWatcher`, for the cgroup with the given name in the unified
+    /// hierarchy, or in the given directory if `name` is an absolute path. The latter lets a
+    /// directory of plain files stand in for a cgroup in tests.
     #[tracing::instrument(skip_all, fields(%name))]
     pub fn new(name: String) -> anyhow::Result<Self> {
+        if Path::new(&name).is_absolute() {
+            return Ok(Self {
+                cgroup: None,
+                dir: PathBuf::from(name),
+                config: Default::default(),
+            });
+        }
+
         // TODO: clarify exactly why we need v2
         // Make sure cgroups v2 (aka unified) are supported
         if !is_cgroup2_unified_mode() {
@@ -66,11 +85,17 @@ pub fn new(name: String) -> anyhow::Result<Self> {
         let cgroup = cgroups_rs::Cgroup::load(hierarchies::auto(), &name);
 
         Ok(Self {
-            cgroup,
+            cgroup: Some(cgroup),
+            dir: Path::new(UNIFIED_MOUNTPOINT).join(&name),
             config: Default::default(),
         })
     }
 
+    /// The directory of the cgroup
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
     /// The entrypoint for the `CgroupWatcher`.
     #[tracing::instrument(skip_all)]
     pub async fn watch(
@@ -84,8 +109,6 @@ pub async fn watch(
         ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         // ticker.reset_immediately(); // FIXME: enable this once updating to tokio >= 1.30.0
 
-        let mem_controller = self.memory()?;
-
         // buffer for samples that will be logged. once full, it remains so.
         let history_log_len = self.config.memory_history_log_interval;
         let max_skip = self.config.memory_history_log_noskip_interval;
@@ -99,7 +122,7 @@ pub async fn watch(
             ticker.tick().await;
 
             let now = Instant::now();
-            let mem = Self::memory_usage(mem_controller);
+            let mem = self.memory_usage()?;
 
             let i = t as usize % history_log_len;
             history_log_buf[i] = mem;
@@ -152,8 +175,8 @@ pub async fn watch(
     }
 
     /// Get a handle on the memory subsystem.
-    fn memory(&self) -> anyhow::Result<&MemController> {
-        self.cgroup
+    fn memory(cgroup: &cgroups_rs::Cgroup) -> anyhow::Result<&MemController> {
+        cgroup
             .subsystems()
             .iter()
             .find_map(|sub| match sub {
@@ -163,15 +186,47 @@ fn memory(&self) -> anyhow::Result<&MemController> {
             .ok_or_else(|| anyhow!("could not find memory subsystem"))
     }
 
-    /// Given a handle on the memory subsystem, returns the current memory information
-    fn memory_usage(mem_controller: &MemController) -> MemoryStatus {
-        let stat = mem_controller.memory_stat().stat;
-        MemoryStatus {
-            non_reclaimable: stat.active_anon + stat.inactive_anon,
-        }
+    /// Returns the current memory information
+    fn memory_usage(&self) -> anyhow::Result<MemoryStatus> {
+        let non_reclaimable = match &self.cgroup {
+            Some(cgroup) => {
+                let stat = Self::memory(cgroup)?.memory_stat().stat;
+                stat.active_anon + stat.inactive_anon
+            }
+            None => {
+                let path = self.dir.join("memory.stat");
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                parse_non_reclaimable(&contents)
+            }
+        };
+        Ok(MemoryStatus { non_reclaimable })
     }
 }
 
+/// The memory limit of the cgroup in the given directory, from its `memory.max`. `None` if
+/// there is no limit.
+pub fn memory_limit(dir: &Path) -> anyhow::Result<Option<u64>> {
+    let path = dir.join("memory.max");
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    match contents.trim() {
+        "max" => Ok(None),
+        limit => Ok(Some(limit.parse().with_context(|| {
+            format!("invalid memory limit {limit:?} in {}", path.display())
+        })?)),
+    }
+}
+
+/// Sum up the anonymous memory in the contents of `memory.stat`, which can't be reclaimed
+fn parse_non_reclaimable(stat: &str) -> u64 {
+    stat.lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(key, _)| *key == "active_anon" || *key == "inactive_anon")
+        .filter_map(|(_, value)| value.trim().parse::<u64>().ok())
+        .sum()
+}
+
 // Helper function for `CgroupWatcher::watch`
 fn ring_buf_recent_values_iter<T>(
     buf: &[T],
@@ -360,4 +415,12 @@ fn check_similarity_behaviour() {
         assert!(small.status_is_close_or_similar(&large));
         assert!(large.status_is_close_or_similar(&small));
     }
+
+    #[test]
+    fn parse_memory_stat() {
+        let stat = "anon 3145728\nfile 1048576\ninactive_anon 2097152\nactive_anon 1048576\n\
+                    inactive_file 524288\nactive_file 524288\n";
+        assert_eq!(super::parse_non_reclaimable(stat), 3 << 20);
+        assert_eq!(super::parse_non_reclaimable(""), 0);
+    }
 }
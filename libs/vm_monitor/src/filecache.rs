@@ -165,9 +165,13 @@ pub async fn new(
     /// Connect to Postgres.
     ///
     /// Aborts the spawned thread if the kill signal is received. This is not
-    /// a method as it is called in [`FileCacheState::new`].
+    /// a method as it is called in [`FileCacheState::new`], and by
+    /// [`crate::pgsettings`] too.
     #[tracing::instrument(skip_all, fields(%conn_str))]
-    async fn connect(conn_str: &str, token: CancellationToken) -> anyhow::Result<Client> {
+    pub(crate) async fn connect(
+        conn_str: &str,
+        token: CancellationToken,
+    ) -> anyhow::Result<Client> {
         let (client, conn) = tokio_postgres::connect(conn_str, NoTls)
             .await
             .context("failed to connect to pg client")?;
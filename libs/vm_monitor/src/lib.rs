@@ -9,6 +9,7 @@
 };
 use axum::{routing::get, Router, Server};
 use clap::Parser;
+use compute_api::spec::MemoryScalingPolicy;
 use futures::Future;
 use std::{fmt::Debug, time::Duration};
 use sysinfo::{RefreshKind, System, SystemExt};
@@ -24,6 +25,7 @@
 
 pub mod cgroup;
 pub mod filecache;
+pub mod pgsettings;
 pub mod runner;
 
 /// The vm-monitor is an autoscaling component started by compute_ctl.
@@ -33,7 +35,8 @@
 #[derive(Debug, Parser)]
 pub struct Args {
     /// The name of the cgroup we should monitor for memory.high events. This
-    /// is the cgroup that postgres should be running in.
+    /// is the cgroup that postgres should be running in. An absolute path is
+    /// taken as the directory of the cgroup.
     #[arg(short, long)]
     pub cgroup: Option<String>,
 
@@ -45,6 +48,15 @@ pub struct Args {
     /// agent, this is 0.0.0.0:10301. For the informant, this is 127.0.0.1:10369.
     #[arg(short, long)]
     pub addr: String,
+
+    /// The policy for scaling memory-dependent Postgres settings, as JSON. Only used
+    /// together with `pgconnstr`.
+    #[arg(long, value_parser = parse_memory_scaling)]
+    pub memory_scaling: Option<MemoryScalingPolicy>,
+}
+
+fn parse_memory_scaling(s: &str) -> anyhow::Result<MemoryScalingPolicy> {
+    serde_json::from_str(s).context("failed to parse memory scaling policy")
 }
 
 impl Args {
@@ -0,0 +1,260 @@
+//! Logic for scaling the memory-dependent Postgres settings, according to the
+//! [`MemoryScalingPolicy`] of the compute spec.
+//!
+//! Settings that Postgres can change with a reload are set with `ALTER SYSTEM` and applied
+//! right away. Those that need a restart are set the same way, so that they show up as
+//! `pending_restart` in `pg_settings`, but only take effect when compute_ctl starts Postgres
+//! again, and writes the values for the memory at that time.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use compute_api::spec::MemoryScalingPolicy;
+use tokio_postgres::Client;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::bytes_to_mebibytes;
+use crate::filecache::FileCacheState;
+
+/// Manages the memory-dependent settings by keeping a connection open.
+#[derive(Debug)]
+pub struct PgSettingsState {
+    client: Client,
+    conn_str: String,
+    policy: MemoryScalingPolicy,
+
+    /// A token for cancelling spawned threads during shutdown.
+    token: CancellationToken,
+}
+
+/// A setting as found in `pg_settings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrentSetting {
+    pub setting: String,
+    pub context: String,
+}
+
+/// The settings to change for some amount of memory
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SettingsChange {
+    /// Settings that take effect on reload
+    pub live: Vec<(String, i64)>,
+    /// Settings that only take effect on restart
+    pub restart: Vec<(String, i64)>,
+}
+
+impl SettingsChange {
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty() && self.restart.is_empty()
+    }
+}
+
+/// Which settings of the policy need to change for `memory` bytes of memory, given their
+/// current values. Settings that Postgres doesn't know or that aren't integers are skipped.
+pub fn plan_settings(
+    policy: &MemoryScalingPolicy,
+    memory: u64,
+    current: &HashMap<String, CurrentSetting>,
+) -> SettingsChange {
+    let mut change = SettingsChange::default();
+    for setting in &policy.settings {
+        if !setting.has_valid_name() {
+            warn!(name = setting.name, "skipping setting with an invalid name");
+            continue;
+        }
+        let Some(cur) = current.get(&setting.name) else {
+            warn!(name = setting.name, "skipping unknown setting");
+            continue;
+        };
+        let Ok(cur_value) = cur.setting.parse::<i64>() else {
+            warn!(name = setting.name, "skipping non-integer setting");
+            continue;
+        };
+
+        let value = setting.value_for(memory);
+        if value == cur_value {
+            continue;
+        }
+        // See PostgreSQL's GucContext: only "postmaster" settings need a restart
+        if cur.context == "postmaster" {
+            change.restart.push((setting.name.clone(), value));
+        } else {
+            change.live.push((setting.name.clone(), value));
+        }
+    }
+    change
+}
+
+impl PgSettingsState {
+    /// Connect to Postgres.
+    #[tracing::instrument(skip_all, fields(%conn_str, ?policy))]
+    pub async fn new(
+        conn_str: &str,
+        policy: MemoryScalingPolicy,
+        token: CancellationToken,
+    ) -> anyhow::Result<Self> {
+        info!(
+            conn_str,
+            "connecting to Postgres to manage memory-dependent settings"
+        );
+        let client = FileCacheState::connect(conn_str, token.clone())
+            .await
+            .context("failed to connect to postgres")?;
+
+        Ok(Self {
+            client,
+            conn_str: conn_str.to_string(),
+            policy,
+            token,
+        })
+    }
+
+    async fn current_settings(&mut self) -> anyhow::Result<HashMap<String, CurrentSetting>> {
+        let names: Vec<String> = self
+            .policy
+            .settings
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        let query =
+            "SELECT name, setting, context FROM pg_catalog.pg_settings WHERE name = ANY($1)";
+        let rows = match self.client.query(query, &[&names]).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!(error = ?e, "postgres error: {e} -> retrying");
+                self.client = FileCacheState::connect(&self.conn_str, self.token.clone())
+                    .await
+                    .context("failed to connect to postgres")?;
+                info!("successfully reconnected to postgres client");
+                self.client
+                    .query(query, &[&names])
+                    .await
+                    .context("failed to query pg for current settings")?
+            }
+        };
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                (
+                    row.get::<_, String>(0),
+                    CurrentSetting {
+                        setting: row.get(1),
+                        context: row.get(2),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Recalculate the settings for `memory` bytes of memory, and apply those that changed.
+    #[tracing::instrument(skip_all, fields(memory = bytes_to_mebibytes(memory)))]
+    pub async fn apply(&mut self, memory: u64) -> anyhow::Result<SettingsChange> {
+        let current = self
+            .current_settings()
+            .await
+            .context("failed to get current settings")?;
+        let change = plan_settings(&self.policy, memory, &current);
+        if change.is_empty() {
+            return Ok(change);
+        }
+
+        for (name, value) in change.live.iter().chain(change.restart.iter()) {
+            // The names are checked by `plan_settings`, and the values are integers
+            self.client
+                .execute(&format!("ALTER SYSTEM SET {name} = {value};"), &[])
+                .await
+                .with_context(|| format!("failed to change {name}"))?;
+        }
+        self.client
+            .execute("SELECT pg_reload_conf();", &[])
+            .await
+            .context("failed to reload config")?;
+
+        info!(live = ?change.live, restart = ?change.restart, "updated memory-dependent settings");
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use compute_api::spec::{MemoryScaledSetting, MemoryScalingPolicy};
+
+    use super::{plan_settings, CurrentSetting, SettingsChange};
+
+    const GIB: u64 = 1 << 30;
+
+    fn policy() -> MemoryScalingPolicy {
+        MemoryScalingPolicy {
+            settings: vec![
+                MemoryScaledSetting {
+                    name: "work_mem".to_string(),
+                    per_gib: 4096.0,
+                    min: Some(4096),
+                    max: None,
+                },
+                MemoryScaledSetting {
+                    name: "max_connections".to_string(),
+                    per_gib: 100.0,
+                    min: Some(25),
+                    max: Some(1000),
+                },
+                MemoryScaledSetting {
+                    name: "no_such_setting".to_string(),
+                    per_gib: 1.0,
+                    min: None,
+                    max: None,
+                },
+            ],
+        }
+    }
+
+    fn current(work_mem: &str, max_connections: &str) -> HashMap<String, CurrentSetting> {
+        HashMap::from([
+            (
+                "work_mem".to_string(),
+                CurrentSetting {
+                    setting: work_mem.to_string(),
+                    context: "user".to_string(),
+                },
+            ),
+            (
+                "max_connections".to_string(),
+                CurrentSetting {
+                    setting: max_connections.to_string(),
+                    context: "postmaster".to_string(),
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn plan() {
+        // Scaled from 0.25 to 8 GiB
+        assert_eq!(
+            plan_settings(&policy(), 8 * GIB, &current("4096", "25")),
+            SettingsChange {
+                live: vec![("work_mem".to_string(), 32768)],
+                restart: vec![("max_connections".to_string(), 800)],
+            }
+        );
+        // And back
+        assert_eq!(
+            plan_settings(&policy(), GIB / 4, &current("32768", "800")),
+            SettingsChange {
+                live: vec![("work_mem".to_string(), 4096)],
+                restart: vec![("max_connections".to_string(), 25)],
+            }
+        );
+        // Nothing to do
+        assert!(plan_settings(&policy(), 8 * GIB, &current("32768", "800")).is_empty());
+        // Not an integer
+        assert_eq!(
+            plan_settings(&policy(), 8 * GIB, &current("4MB", "800")),
+            SettingsChange::default()
+        );
+    }
+}
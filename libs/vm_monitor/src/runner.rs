@@ -5,6 +5,7 @@
 //! all functionality.
 
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context};
@@ -17,6 +18,7 @@
 use crate::cgroup::{self, CgroupWatcher};
 use crate::dispatcher::Dispatcher;
 use crate::filecache::{FileCacheConfig, FileCacheState};
+use crate::pgsettings::PgSettingsState;
 use crate::protocol::{InboundMsg, InboundMsgKind, OutboundMsg, OutboundMsgKind, Resources};
 use crate::{bytes_to_mebibytes, get_total_system_memory, spawn_with_cancel, Args, MiB};
 
@@ -26,6 +28,7 @@
 pub struct Runner {
     config: Config,
     filecache: Option<FileCacheState>,
+    pg_settings: Option<PgSettingsState>,
     cgroup: Option<CgroupState>,
     dispatcher: Dispatcher,
 
@@ -49,6 +52,8 @@ struct CgroupState {
     /// If [`cgroup::MemoryHistory::avg_non_reclaimable`] exceeds `threshold`, we send upscale
     /// requests.
     threshold: u64,
+    /// The directory of the cgroup, to read its memory limit from
+    dir: PathBuf,
 }
 
 /// Configuration for a `Runner`
@@ -140,6 +145,7 @@ pub async fn new(
         let mut state = Runner {
             config,
             filecache: None,
+            pg_settings: None,
             cgroup: None,
             dispatcher,
             counter: 1, // NB: must be odd, see the comment about the field for more.
@@ -186,6 +192,14 @@ pub async fn new(
 
             file_cache_disk_size = actual_size;
             state.filecache = Some(file_cache);
+
+            if let Some(policy) = &args.memory_scaling {
+                info!("initializing memory-dependent settings");
+                let pg_settings = PgSettingsState::new(connstr, policy.clone(), token.clone())
+                    .await
+                    .context("failed to create settings manager")?;
+                state.pg_settings = Some(pg_settings);
+            }
         }
 
         if let Some(name) = &args.cgroup {
@@ -195,6 +209,7 @@ pub async fn new(
 
             let cgroup =
                 CgroupWatcher::new(name.clone()).context("failed to create cgroup manager")?;
+            let dir = cgroup.dir().to_path_buf();
 
             let init_value = cgroup::MemoryHistory {
                 avg_non_reclaimable: 0,
@@ -213,12 +228,38 @@ pub async fn new(
             state.cgroup = Some(CgroupState {
                 watcher: hist_rx,
                 threshold,
+                dir,
             });
         }
 
+        // Once the cgroup is known, as its memory limit caps the memory for the settings
+        let settings_memory = state.settings_memory(mem);
+        if let Some(pg_settings) = &mut state.pg_settings {
+            pg_settings
+                .apply(settings_memory)
+                .await
+                .context("failed to set initial memory-dependent settings")?;
+        }
+
         Ok(state)
     }
 
+    /// The memory to scale the settings with: the usable memory, unless the cgroup is limited
+    /// to less, as in a container
+    fn settings_memory(&self, usable_system_memory: u64) -> u64 {
+        let Some(cgroup) = &self.cgroup else {
+            return usable_system_memory;
+        };
+        match cgroup::memory_limit(&cgroup.dir) {
+            Ok(Some(limit)) => limit.min(usable_system_memory),
+            Ok(None) => usable_system_memory,
+            Err(e) => {
+                warn!("failed to read cgroup memory limit: {e:#}");
+                usable_system_memory
+            }
+        }
+    }
+
     /// Attempt to downscale filecache + cgroup
     #[tracing::instrument(skip_all, fields(?target))]
     pub async fn try_downscale(&mut self, target: Resources) -> anyhow::Result<(bool, String)> {
@@ -280,7 +321,8 @@ pub async fn try_downscale(&mut self, target: Resources) -> anyhow::Result<(bool
             }
         }
 
-        // The downscaling has been approved. Downscale the file cache, then the cgroup.
+        // The downscaling has been approved. Downscale the file cache and the settings, then the
+        // cgroup.
         let mut status = vec![];
         let mut file_cache_disk_size = 0;
         if let Some(file_cache) = &mut self.filecache {
@@ -297,6 +339,23 @@ pub async fn try_downscale(&mut self, target: Resources) -> anyhow::Result<(bool
             status.push(message);
         }
 
+        let settings_memory = self.settings_memory(usable_system_memory);
+        if let Some(pg_settings) = &mut self.pg_settings {
+            let change = pg_settings
+                .apply(settings_memory)
+                .await
+                .context("failed to update memory-dependent settings")?;
+            if !change.is_empty() {
+                let message = format!(
+                    "set {} settings, {} pending restart",
+                    change.live.len(),
+                    change.restart.len()
+                );
+                info!("downscale: {message}");
+                status.push(message);
+            }
+        }
+
         if let Some(cgroup) = &mut self.cgroup {
             let new_threshold = self
                 .config
@@ -353,6 +412,14 @@ pub async fn handle_upscale(&mut self, resources: Resources) -> anyhow::Result<(
             }
         }
 
+        let settings_memory = self.settings_memory(usable_system_memory);
+        if let Some(pg_settings) = &mut self.pg_settings {
+            pg_settings
+                .apply(settings_memory)
+                .await
+                .context("failed to update memory-dependent settings")?;
+        }
+
         if let Some(cgroup) = &mut self.cgroup {
             let new_threshold = self
                 .config
@@ -213,6 +213,7 @@ pub fn new(api: Box<dyn ApiImpl>, config: Config) -> Wrapper {
             safekeeper_connection_timeout: config.safekeeper_connection_timeout,
             wal_segment_size: WAL_SEGMENT_SIZE as i32, // default 16MB
             syncSafekeepers: config.sync_safekeepers,
+            syncSafekeepersFence: false,
             systemId: 0,
             pgTimeline: 1,
             callback_data,
@@ -224,6 +224,15 @@ pub async fn status(&self) -> Result<()> {
         Ok(())
     }
 
+    pub async fn get_status(&self) -> Result<StatusResponse> {
+        let uri = format!("{}/v1/status", self.mgmt_api_endpoint);
+        self.get(&uri)
+            .await?
+            .json()
+            .await
+            .map_err(Error::ReceiveBody)
+    }
+
     /// The tenant deletion API can return 202 if deletion is incomplete, or
     /// 404 if it is complete.  Callers are responsible for checking the status
     /// code and retrying.  Error codes other than 404 will return Err().
@@ -497,6 +506,21 @@ pub async fn tenant_shard_split(
             .await
             .map_err(Error::ReceiveBody)
     }
+    pub async fn tenant_shard_merge(
+        &self,
+        tenant_shard_id: TenantShardId,
+        req: TenantShardMergeRequest,
+    ) -> Result<TenantShardMergeResponse> {
+        let uri = format!(
+            "{}/v1/tenant/{}/shard_merge",
+            self.mgmt_api_endpoint, tenant_shard_id
+        );
+        self.request(Method::PUT, &uri, req)
+            .await?
+            .json()
+            .await
+            .map_err(Error::ReceiveBody)
+    }
 
     pub async fn timeline_list(
         &self,
@@ -688,17 +712,21 @@ pub async fn list_aux_files(
         }
     }
 
+    /// Create a timeline from a basebackup tarball.  Each shard of a sharded tenant keeps the pages
+    /// that it owns.  If `image_layers` is set, the imported data is compacted into image layers.
+    #[allow(clippy::too_many_arguments)]
     pub async fn import_basebackup(
         &self,
-        tenant_id: TenantId,
+        tenant_shard_id: TenantShardId,
         timeline_id: TimelineId,
         base_lsn: Lsn,
         end_lsn: Lsn,
         pg_version: u32,
+        image_layers: bool,
         basebackup_tarball: ReqwestBody,
     ) -> Result<()> {
         let uri = format!(
-            "{}/v1/tenant/{tenant_id}/timeline/{timeline_id}/import_basebackup?base_lsn={base_lsn}&end_lsn={end_lsn}&pg_version={pg_version}",
+            "{}/v1/tenant/{tenant_shard_id}/timeline/{timeline_id}/import_basebackup?base_lsn={base_lsn}&end_lsn={end_lsn}&pg_version={pg_version}&image_layers={image_layers}",
             self.mgmt_api_endpoint,
         );
         self.start_request(Method::PUT, uri)
@@ -715,14 +743,15 @@ pub async fn import_basebackup(
 
     pub async fn import_wal(
         &self,
-        tenant_id: TenantId,
+        tenant_shard_id: TenantShardId,
         timeline_id: TimelineId,
         start_lsn: Lsn,
         end_lsn: Lsn,
+        image_layers: bool,
         wal_tarball: ReqwestBody,
     ) -> Result<()> {
         let uri = format!(
-            "{}/v1/tenant/{tenant_id}/timeline/{timeline_id}/import_wal?start_lsn={start_lsn}&end_lsn={end_lsn}",
+            "{}/v1/tenant/{tenant_shard_id}/timeline/{timeline_id}/import_wal?start_lsn={start_lsn}&end_lsn={end_lsn}&image_layers={image_layers}",
             self.mgmt_api_endpoint,
         );
         self.start_request(Method::PUT, uri)
@@ -345,6 +345,13 @@ fn new(key: Key) -> Option<AuxFileV2> {
                 AuxFileV2::Recognized("pg_logical/replorigin_checkpoint", hash)
             }
             (2, 1) => AuxFileV2::Recognized("pg_replslot/", hash),
+            (3, 1) if hash.0 == EMPTY_HASH => {
+                AuxFileV2::Recognized("neon/logical_slots.json", hash)
+            }
+            (3, 2) if hash.0 == EMPTY_HASH => AuxFileV2::Recognized("neon/lfc_state.json", hash),
+            (3, 3) if hash.0 == EMPTY_HASH => {
+                AuxFileV2::Recognized("neon/suspend_snapshot.json", hash)
+            }
             (1, 0xff) => AuxFileV2::OtherWithPrefix("pg_logical/", hash),
             (0xff, 0xff) => AuxFileV2::Other(hash),
             _ => return None,
@@ -39,6 +39,7 @@ fn aux_hash_to_metadata_key(dir_level1: u8, dir_level2: u8, data: &[u8]) -> Key
 
 const AUX_DIR_PG_LOGICAL: u8 = 0x01;
 const AUX_DIR_PG_REPLSLOT: u8 = 0x02;
+const AUX_DIR_NEON: u8 = 0x03;
 const AUX_DIR_PG_UNKNOWN: u8 = 0xFF;
 
 /// Encode the aux file into a fixed-size key.
@@ -53,6 +54,9 @@ fn aux_hash_to_metadata_key(dir_level1: u8, dir_level2: u8, data: &[u8]) -> Key
 /// * pg_logical/replorigin_checkpoint -> 0x0103
 /// * pg_logical/others -> 0x01FF
 /// * pg_replslot/ -> 0x0201
+/// * neon/logical_slots.json -> 0x0301
+/// * neon/lfc_state.json -> 0x0302
+/// * neon/suspend_snapshot.json -> 0x0303
 /// * others -> 0xFFFF
 ///
 /// If you add new AUX files to this function, please also add a test case to `test_encoding_portable`.
@@ -75,6 +79,12 @@ pub fn encode_aux_file_key(path: &str) -> Key {
         aux_hash_to_metadata_key(AUX_DIR_PG_LOGICAL, 0xFF, fname.as_bytes())
     } else if let Some(fname) = path.strip_prefix("pg_replslot/") {
         aux_hash_to_metadata_key(AUX_DIR_PG_REPLSLOT, 0x01, fname.as_bytes())
+    } else if path == "neon/logical_slots.json" {
+        aux_hash_to_metadata_key(AUX_DIR_NEON, 0x01, b"")
+    } else if path == "neon/lfc_state.json" {
+        aux_hash_to_metadata_key(AUX_DIR_NEON, 0x02, b"")
+    } else if path == "neon/suspend_snapshot.json" {
+        aux_hash_to_metadata_key(AUX_DIR_NEON, 0x03, b"")
     } else {
         if cfg!(debug_assertions) {
             warn!(
@@ -261,6 +271,18 @@ fn test_encoding_portable() {
             "62000002017F8D83D94F7081693471ABFB92",
             encode_aux_file_key("pg_replslot/test3").to_string()
         );
+        assert_eq!(
+            "62000003012E07BB014262B821756295C58D",
+            encode_aux_file_key("neon/logical_slots.json").to_string()
+        );
+        assert_eq!(
+            "62000003022E07BB014262B821756295C58D",
+            encode_aux_file_key("neon/lfc_state.json").to_string()
+        );
+        assert_eq!(
+            "62000003032E07BB014262B821756295C58D",
+            encode_aux_file_key("neon/suspend_snapshot.json").to_string()
+        );
         assert_eq!(
             "620000FFFF2B6ECC8AEF93F643DC44F15E03",
             encode_aux_file_key("other_file_not_supported").to_string(),
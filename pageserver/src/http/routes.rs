@@ -33,6 +33,8 @@
 use pageserver_api::models::TenantScanRemoteStorageResponse;
 use pageserver_api::models::TenantScanRemoteStorageShard;
 use pageserver_api::models::TenantShardLocation;
+use pageserver_api::models::TenantShardMergeRequest;
+use pageserver_api::models::TenantShardMergeResponse;
 use pageserver_api::models::TenantShardSplitRequest;
 use pageserver_api::models::TenantShardSplitResponse;
 use pageserver_api::models::TenantSorting;
@@ -63,8 +65,8 @@
 use crate::tenant::config::{LocationConf, TenantConfOpt};
 use crate::tenant::mgr::GetActiveTenantError;
 use crate::tenant::mgr::{
-    GetTenantError, TenantManager, TenantMapError, TenantMapInsertError, TenantSlotError,
-    TenantSlotUpsertError, TenantStateError,
+    GetTenantError, MergedShardPrefixInUse, TenantManager, TenantMapError, TenantMapInsertError,
+    TenantSlotError, TenantSlotUpsertError, TenantStateError,
 };
 use crate::tenant::mgr::{TenantSlot, UpsertLocationError};
 use crate::tenant::remote_timeline_client;
@@ -122,6 +124,7 @@ pub struct State {
     deletion_queue_client: DeletionQueueClient,
     secondary_controller: SecondaryController,
     latest_utilization: tokio::sync::Mutex<Option<(std::time::Instant, bytes::Bytes)>>,
+    utilization_load_sampler: std::sync::Mutex<crate::utilization::LoadSampler>,
 }
 
 impl State {
@@ -151,6 +154,7 @@ pub fn new(
             deletion_queue_client,
             secondary_controller,
             latest_utilization: Default::default(),
+            utilization_load_sampler: Default::default(),
         })
     }
 }
@@ -484,7 +488,13 @@ async fn status_handler(
 ) -> Result<Response<Body>, ApiError> {
     check_permission(&request, None)?;
     let config = get_config(&request);
-    json_response(StatusCode::OK, StatusResponse { id: config.id })
+    json_response(
+        StatusCode::OK,
+        StatusResponse {
+            id: config.id,
+            version: Some(crate::GIT_VERSION.to_string()),
+        },
+    )
 }
 
 async fn reload_auth_validation_keys_handler(
@@ -1170,6 +1180,42 @@ async fn tenant_shard_split_handler(
     json_response(StatusCode::OK, TenantShardSplitResponse { new_shards })
 }
 
+async fn tenant_shard_merge_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let req: TenantShardMergeRequest = json_request(&mut request).await?;
+
+    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let state = get_state(&request);
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    let new_shards = state
+        .tenant_manager
+        .shard_merge(
+            tenant,
+            ShardCount::new(req.new_shard_count),
+            state.broker_client.clone(),
+            &ctx,
+        )
+        .await
+        .map_err(|e| {
+            // Nothing was written: tell the caller, so that it doesn't clean up the merged shard
+            if e.downcast_ref::<MergedShardPrefixInUse>().is_some() {
+                ApiError::Conflict(e.to_string())
+            } else {
+                ApiError::InternalServerError(e)
+            }
+        })?;
+
+    json_response(StatusCode::OK, TenantShardMergeResponse { new_shards })
+}
+
 async fn layer_map_info_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
@@ -2394,9 +2440,13 @@ async fn get_utilization(
     // regenerate at most 1Hz to allow polling at any rate.
     if !still_valid {
         let path = state.conf.tenants_path();
-        let doc =
-            crate::utilization::regenerate(state.conf, path.as_std_path(), &state.tenant_manager)
-                .map_err(ApiError::InternalServerError)?;
+        let doc = crate::utilization::regenerate(
+            state.conf,
+            path.as_std_path(),
+            &state.tenant_manager,
+            &mut state.utilization_load_sampler.lock().unwrap(),
+        )
+        .map_err(ApiError::InternalServerError)?;
 
         let mut buf = Vec::new();
         serde_json::to_writer(&mut buf, &doc)
@@ -2584,26 +2634,50 @@ fn cmp(&self, other: &Self) -> std::cmp::Ordering {
     )
 }
 
+/// After an import, optionally rewrite the imported data as image layers, rather than leaving it
+/// in the L0 delta layers that ingest produces.
+async fn import_create_image_layers(
+    timeline: &Arc<Timeline>,
+    cancel: &CancellationToken,
+    ctx: &RequestContext,
+) -> Result<(), ApiError> {
+    info!("creating image layers");
+    timeline
+        .compact(
+            cancel,
+            CompactFlags::ForceRepartition | CompactFlags::ForceImageLayerCreation,
+            ctx,
+        )
+        .await
+        .map_err(|e| match e {
+            CompactionError::ShuttingDown => ApiError::ShuttingDown,
+            CompactionError::Other(e) => ApiError::InternalServerError(e),
+        })?;
+    Ok(())
+}
+
 async fn put_tenant_timeline_import_basebackup(
     request: Request<Body>,
-    _cancel: CancellationToken,
+    cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
     let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
     let base_lsn: Lsn = must_parse_query_param(&request, "base_lsn")?;
     let end_lsn: Lsn = must_parse_query_param(&request, "end_lsn")?;
     let pg_version: u32 = must_parse_query_param(&request, "pg_version")?;
+    let image_layers = parse_query_param(&request, "image_layers")?.unwrap_or(false);
 
-    check_permission(&request, Some(tenant_id))?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
 
-    let span = info_span!("import_basebackup", tenant_id=%tenant_id, timeline_id=%timeline_id, base_lsn=%base_lsn, end_lsn=%end_lsn, pg_version=%pg_version);
+    // Each shard of a sharded tenant is sent the whole basebackup, and keeps the pages it owns
+    let span = info_span!("import_basebackup", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), timeline_id=%timeline_id, base_lsn=%base_lsn, end_lsn=%end_lsn, pg_version=%pg_version);
     async move {
         let state = get_state(&request);
         let tenant = state
             .tenant_manager
-            .get_attached_tenant_shard(TenantShardId::unsharded(tenant_id))?;
+            .get_attached_tenant_shard(tenant_shard_id)?;
 
         let broker_client = state.broker_client.clone();
 
@@ -2633,7 +2707,7 @@ async fn put_tenant_timeline_import_basebackup(
         // Import basebackup provided via CopyData
         info!("importing basebackup");
 
-        timeline
+        let timeline = timeline
             .import_basebackup_from_tar(tenant.clone(), &mut body, base_lsn, broker_client, &ctx)
             .await
             .map_err(ApiError::InternalServerError)?;
@@ -2643,6 +2717,10 @@ async fn put_tenant_timeline_import_basebackup(
             .await
             .map_err(ApiError::InternalServerError)?;
 
+        if image_layers {
+            import_create_image_layers(&timeline, &cancel, &ctx).await?;
+        }
+
         // TODO check checksum
         // Meanwhile you can verify client-side by taking fullbackup
         // and checking that it matches in size with what was imported.
@@ -2658,22 +2736,23 @@ async fn put_tenant_timeline_import_basebackup(
 
 async fn put_tenant_timeline_import_wal(
     request: Request<Body>,
-    _cancel: CancellationToken,
+    cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
     let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
     let start_lsn: Lsn = must_parse_query_param(&request, "start_lsn")?;
     let end_lsn: Lsn = must_parse_query_param(&request, "end_lsn")?;
+    let image_layers = parse_query_param(&request, "image_layers")?.unwrap_or(false);
 
-    check_permission(&request, Some(tenant_id))?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
 
-    let span = info_span!("import_wal", tenant_id=%tenant_id, timeline_id=%timeline_id, start_lsn=%start_lsn, end_lsn=%end_lsn);
+    let span = info_span!("import_wal", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), timeline_id=%timeline_id, start_lsn=%start_lsn, end_lsn=%end_lsn);
     async move {
         let state = get_state(&request);
 
-        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, TenantShardId::unsharded(tenant_id), timeline_id).await?;
+        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
 
         let mut body = StreamReader::new(request.into_body().map(|res| {
             res.map_err(|error| {
@@ -2711,6 +2790,10 @@ async fn put_tenant_timeline_import_wal(
             other => ApiError::InternalServerError(anyhow::anyhow!(other)),
         })?;
 
+        if image_layers {
+            import_create_image_layers(&timeline, &cancel, &ctx).await?;
+        }
+
         info!("done");
 
         json_response(StatusCode::OK, ())
@@ -2926,6 +3009,9 @@ pub fn make_router(
         .put("/v1/tenant/:tenant_shard_id/shard_split", |r| {
             api_handler(r, tenant_shard_split_handler)
         })
+        .put("/v1/tenant/:tenant_shard_id/shard_merge", |r| {
+            api_handler(r, tenant_shard_merge_handler)
+        })
         .get("/v1/tenant/:tenant_shard_id/config", |r| {
             api_handler(r, get_tenant_config_handler)
         })
@@ -3077,11 +3163,11 @@ pub fn make_router(
             |r| testing_api_handler("perf_info", r, perf_info),
         )
         .put(
-            "/v1/tenant/:tenant_id/timeline/:timeline_id/import_basebackup",
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/import_basebackup",
             |r| api_handler(r, put_tenant_timeline_import_basebackup),
         )
         .put(
-            "/v1/tenant/:tenant_id/timeline/:timeline_id/import_wal",
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/import_wal",
             |r| api_handler(r, put_tenant_timeline_import_wal),
         )
         .any(handler_404))
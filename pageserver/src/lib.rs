@@ -52,6 +52,8 @@
 
 pub const DEFAULT_PG_VERSION: u32 = 16;
 
+utils::project_git_version!(GIT_VERSION);
+
 // Magic constants used to identify different kinds of files
 pub const IMAGE_FILE_MAGIC: u16 = 0x5A60;
 pub const DELTA_FILE_MAGIC: u16 = 0x5A61;
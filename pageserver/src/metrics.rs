@@ -1339,6 +1339,12 @@ pub(crate) fn new(tenant_shard_id: &TenantShardId, timeline_id: &TimelineId) ->
             per_timeline_getpage,
         }
     }
+
+    /// How many GetPage requests this timeline has served, for calculating request rates.
+    pub(crate) fn getpage_count(&self) -> u64 {
+        self.per_timeline_getpage.get_sample_count()
+    }
+
     pub(crate) fn start_timer<'c: 'a, 'a>(
         &'a self,
         op: SmgrQueryType,
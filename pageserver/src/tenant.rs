@@ -151,6 +151,7 @@
 pub mod size;
 
 mod gc_block;
+mod shard_merge;
 pub(crate) mod throttle;
 
 pub(crate) use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
@@ -846,6 +846,11 @@ async fn shutdown_all_tenants0(tenants: &std::sync::RwLock<TenantsMap>) {
     // caller will log how long we took
 }
 
+/// The sources of a shard merge still read layers stored under the merged shard's remote storage prefix.
+#[derive(thiserror::Error, Debug)]
+#[error("Source shards still read layers stored under the prefix of merged shard {0}")]
+pub(crate) struct MergedShardPrefixInUse(pub(crate) TenantShardId);
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum UpsertLocationError {
     #[error("Bad config request: {0}")]
@@ -1712,6 +1717,125 @@ pub(crate) async fn do_shard_split(
         Ok(child_shards)
     }
 
+    /// Merge the shards that map onto the same shard at `new_shard_count` into one.  `tenant` is the
+    /// lowest-numbered of these, and all the others must also be attached to this pageserver.
+    ///
+    /// The merged shard is written out from the sources' content (see [`Tenant::merge_from_shards`]).
+    /// The sources are left attached and untouched: the caller removes them once it has committed
+    /// the merge, until then they remain the authoritative copy of the tenant's data.
+    ///
+    /// The merged shard has the same remote storage prefix as the shard of the same count that the
+    /// sources were split from, if there ever was one.  The sources may still read layers stored
+    /// there, in which case the merge is refused with [`MergedShardPrefixInUse`] before anything
+    /// is written, and the caller must not delete the merged shard's remote data.  A merged shard left
+    /// behind by any other failure is cleaned up by the caller, as with split children.
+    #[instrument(skip_all, fields(tenant_id=%tenant.get_tenant_shard_id().tenant_id, shard_id=%tenant.get_tenant_shard_id().shard_slug(), new_shard_count=%new_shard_count.literal()))]
+    pub(crate) async fn shard_merge(
+        &self,
+        tenant: Arc<Tenant>,
+        new_shard_count: ShardCount,
+        broker_client: storage_broker::BrokerClientChannel,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<TenantShardId>> {
+        let tenant_shard_id = *tenant.get_tenant_shard_id();
+
+        // Validate the incoming request
+        if new_shard_count.count() >= tenant_shard_id.shard_count.count() {
+            anyhow::bail!("Requested shard count is not a decrease");
+        }
+        let reduction_factor = tenant_shard_id.shard_count.count() / new_shard_count.count();
+        if tenant_shard_id.shard_count.count() % new_shard_count.count() != 0
+            || !reduction_factor.is_power_of_two()
+        {
+            anyhow::bail!("Requested merge is not a power of two");
+        }
+        if tenant_shard_id.shard_number.0 >= new_shard_count.count() {
+            anyhow::bail!("Merge must be requested on the lowest-numbered source shard");
+        }
+
+        // Plan: identify the merged shard and the shards it is made of
+        let merged_shard = tenant_shard_id.merge(new_shard_count);
+        let source_shards = merged_shard.split(tenant_shard_id.shard_count);
+        tracing::info!(
+            "Shards {} merge into: {}",
+            source_shards
+                .iter()
+                .map(|id| format!("{}", id.to_index()))
+                .join(","),
+            merged_shard.to_index()
+        );
+
+        if self.get(merged_shard).is_some() {
+            anyhow::bail!("Merged shard {} already exists", merged_shard.to_index());
+        }
+
+        let mut sources = Vec::with_capacity(source_shards.len());
+        for source_shard in &source_shards {
+            let source = self
+                .get_attached_tenant_shard(*source_shard)
+                .map_err(|e| anyhow::anyhow!("Source shard {}: {e}", source_shard.to_index()))?;
+            source.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+            sources.push(source);
+        }
+        drop(tenant);
+
+        for source in &sources {
+            if source.references_shard(merged_shard.to_index()).await? {
+                anyhow::bail!(MergedShardPrefixInUse(merged_shard));
+            }
+        }
+
+        fail::fail_point!("shard-merge-pre-attach", |_| Err(anyhow::anyhow!(
+            "failpoint"
+        )));
+
+        // Phase 1: Attach the (empty) merged shard, in the newest of the sources' generations
+        let lowest = sources
+            .first()
+            .expect("split always returns at least one shard");
+        let mut merged_shard_identity = lowest.shard_identity;
+        merged_shard_identity.count = merged_shard.shard_count;
+        merged_shard_identity.number = merged_shard.shard_number;
+        let merged_generation = sources
+            .iter()
+            .map(|s| s.generation)
+            .max()
+            .expect("non-empty");
+
+        let merged_location_conf = LocationConf {
+            mode: LocationMode::Attached(AttachedLocationConfig {
+                generation: merged_generation,
+                attach_mode: AttachmentMode::Single,
+            }),
+            shard: merged_shard_identity,
+            tenant_conf: lowest.get_tenant_conf(),
+        };
+        let Some(merged) = self
+            .upsert_location(
+                merged_shard,
+                merged_location_conf,
+                None,
+                SpawnMode::Eager,
+                ctx,
+            )
+            .await?
+        else {
+            anyhow::bail!("Merged shard was not attached");
+        };
+        merged.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+        // Phase 2: Write the sources' content into the merged shard
+        merged
+            .merge_from_shards(&sources, broker_client, ctx)
+            .await?;
+
+        fail::fail_point!("shard-merge-post-copy", |_| Err(anyhow::anyhow!(
+            "failpoint"
+        )));
+
+        Ok(vec![merged_shard])
+    }
+
     /// Part of [`Self::shard_split`]: hard link parent shard layers into child shards, as an optimization
     /// to avoid the children downloading them again.
     ///
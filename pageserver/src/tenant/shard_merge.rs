@@ -0,0 +1,539 @@
+//! Shard merging: the inverse of a shard split.
+//!
+//! A shard split can reference the parent's layers from its children, because each child
+//! holds a subset of its parent's keys.  A merge cannot do that: the merged shard holds the
+//! union of several shards' keys, and layers from each source are only valid for the keys
+//! owned by that source.  Instead, each timeline's content is copied from the source shards,
+//! taking every key only from the source that owns it:
+//! - history after the GC horizon of the sources is copied value by value, tombstones included,
+//!   so that the merged timeline can serve any LSN that the sources could, and
+//! - content at the horizon, and at older LSNs that child timelines branch off at, is written
+//!   as images, which is all that the sources retain of it.
+//!
+//! The merged timelines' GC cutoff is the horizon, so PITR works as it did on the sources.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::Context;
+use bytes::Bytes;
+use pageserver_api::keyspace::KeySpaceAccum;
+use pageserver_api::shard::ShardIndex;
+use storage_broker::BrokerClientChannel;
+use tracing::info;
+use utils::bin_ser::BeSer;
+use utils::id::TimelineId;
+use utils::lsn::{Lsn, RecordLsn};
+
+use super::metadata::MetadataUpdate;
+use super::storage_layer::merge_iterator::MergeIterator;
+use super::storage_layer::split_writer::{SplitDeltaLayerWriter, SplitWriterResult};
+use super::storage_layer::{ResidentLayer, ValuesReconstructState};
+use super::timeline::{Timeline, WaitLsnWaiter};
+use super::{tree_sort_timelines, Tenant};
+use crate::context::RequestContext;
+use crate::keyspace::KeySpace;
+use crate::repository::{Key, Value};
+use crate::ZERO_PAGE;
+
+impl Tenant {
+    /// Whether any of this shard's timelines read layers stored under the remote prefix of `shard`,
+    /// e.g. those inherited from the shard that it was split from.
+    pub(crate) async fn references_shard(&self, shard: ShardIndex) -> anyhow::Result<bool> {
+        let timelines = self
+            .timelines
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for timeline in timelines {
+            let guard = timeline.layers.read().await;
+            let layer_map = guard.layer_map()?;
+            if layer_map
+                .iter_historic_layers()
+                .any(|desc| guard.get_from_desc(&desc).metadata().shard == shard)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Populate this (freshly attached, empty) tenant shard with the content of `sources`, which
+    /// must be all the shards that map onto this shard at the sources' shard count, ordered by
+    /// shard number.  The sources are flushed, but not otherwise modified.
+    ///
+    /// On success, all timelines are durable in remote storage and have been activated.
+    pub(crate) async fn merge_from_shards(
+        self: &Arc<Self>,
+        sources: &[Arc<Tenant>],
+        broker_client: BrokerClientChannel,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_active(), "Cannot merge into an inactive tenant");
+        anyhow::ensure!(
+            self.timelines.lock().unwrap().is_empty(),
+            "Merged shard already has timelines: its remote prefix holds leftovers of an earlier shard"
+        );
+        let Some(lowest) = sources.first() else {
+            anyhow::bail!("No source shards to merge");
+        };
+
+        // The sources must agree on which timelines exist: we rely on the storage controller
+        // not to create or delete timelines while the merge is in progress.
+        let timelines = lowest.timelines.lock().unwrap().clone();
+        for source in &sources[1..] {
+            let source_timelines = source.timelines.lock().unwrap();
+            if source_timelines.len() != timelines.len()
+                || timelines
+                    .keys()
+                    .any(|id| !source_timelines.contains_key(id))
+            {
+                anyhow::bail!(
+                    "Shard {} does not have the same timelines as shard {}",
+                    source.tenant_shard_id.to_index(),
+                    lowest.tenant_shard_id.to_index()
+                );
+            }
+        }
+        let timelines = tree_sort_timelines(timelines, |t| t.get_ancestor_timeline_id())?;
+
+        // Snapshot the point to merge at: the furthest any source has got to.  All shards ingest
+        // the same WAL, so the other sources will catch up to it.
+        let mut targets: HashMap<TimelineId, RecordLsn> = HashMap::new();
+        for (timeline_id, _) in &timelines {
+            for source in sources {
+                let record_lsn = source
+                    .get_timeline(*timeline_id, true)?
+                    .get_last_record_rlsn();
+                let target = targets.entry(*timeline_id).or_insert(record_lsn);
+                if record_lsn.last > target.last {
+                    *target = record_lsn;
+                }
+            }
+        }
+
+        // Points that child timelines branch from, which must be readable on the merged ancestor
+        let mut branch_points: HashMap<TimelineId, BTreeSet<Lsn>> = HashMap::new();
+        for (_, timeline) in &timelines {
+            if let Some(ancestor_id) = timeline.get_ancestor_timeline_id() {
+                branch_points
+                    .entry(ancestor_id)
+                    .or_default()
+                    .insert(timeline.get_ancestor_lsn());
+            }
+        }
+
+        let mut merged: HashMap<TimelineId, Arc<Timeline>> = HashMap::new();
+        let mut horizons: HashMap<TimelineId, Lsn> = HashMap::new();
+        for (timeline_id, lowest_timeline) in timelines {
+            let target = *targets.get(&timeline_id).expect("populated above");
+            let mut source_timelines = Vec::with_capacity(sources.len());
+            for source in sources {
+                let timeline = source.get_timeline(timeline_id, true)?;
+                info!(
+                    "Waiting for shard {}/{} to reach merge lsn {}",
+                    source.tenant_shard_id.to_index(),
+                    timeline_id,
+                    target.last
+                );
+                timeline
+                    .wait_lsn(target.last, WaitLsnWaiter::Tenant, ctx)
+                    .await?;
+                source_timelines.push(timeline);
+            }
+
+            // Branch points at or before our own start are served by our ancestor
+            let start_lsn = match lowest_timeline.get_ancestor_timeline_id() {
+                Some(_) => lowest_timeline.get_ancestor_lsn(),
+                None => Lsn(0),
+            };
+
+            // History below the GC cutoff of any source may be gone from it: from there on,
+            // every source still has all of it.
+            let horizon = source_timelines
+                .iter()
+                .map(|t| *t.get_latest_gc_cutoff_lsn())
+                .max()
+                .expect("non-empty")
+                .clamp(start_lsn, target.last);
+            horizons.insert(timeline_id, horizon);
+
+            // Branch points after the horizon are served by the copied history
+            let mut points: Vec<Lsn> = branch_points
+                .remove(&timeline_id)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|lsn| *lsn > start_lsn && *lsn < horizon)
+                .collect();
+            if horizon > start_lsn {
+                points.push(horizon);
+            }
+
+            fail::fail_point!("shard-merge-timeline", |_| Err(anyhow::anyhow!(
+                "failpoint"
+            )));
+
+            let timeline = match lowest_timeline.get_ancestor_timeline_id() {
+                None => {
+                    let uninit = self
+                        .create_empty_timeline(
+                            timeline_id,
+                            lowest_timeline.initdb_lsn,
+                            lowest_timeline.pg_version,
+                            ctx,
+                        )
+                        .await?;
+                    let raw_timeline = uninit.raw_timeline()?;
+                    raw_timeline
+                        .last_aux_file_policy
+                        .store(lowest_timeline.last_aux_file_policy.load());
+                    raw_timeline.maybe_spawn_flush_loop();
+                    merge_timeline_content(
+                        raw_timeline,
+                        &source_timelines,
+                        &points,
+                        horizon,
+                        target,
+                        ctx,
+                    )
+                    .await?;
+                    uninit.finish_creation()?
+                }
+                Some(ancestor_id) => {
+                    let ancestor = merged
+                        .get(&ancestor_id)
+                        .context("ancestor timeline not merged")?;
+                    let create_guard = self
+                        .create_timeline_create_guard(timeline_id)
+                        .map_err(|e| anyhow::anyhow!("{e}"))?;
+                    let timeline = self
+                        .branch_timeline(
+                            ancestor,
+                            timeline_id,
+                            Some(lowest_timeline.get_ancestor_lsn()),
+                            create_guard,
+                            ctx,
+                        )
+                        .await?;
+                    timeline.maybe_spawn_flush_loop();
+                    if target.last > lowest_timeline.get_ancestor_lsn() {
+                        merge_timeline_content(
+                            &timeline,
+                            &source_timelines,
+                            &points,
+                            horizon,
+                            target,
+                            ctx,
+                        )
+                        .await?;
+                    }
+                    timeline
+                }
+            };
+
+            merged.insert(timeline_id, timeline);
+        }
+
+        // Now that all branches exist, carry over the sources' GC cutoff, below which we only have
+        // the branch points, and persist the result.
+        for (timeline_id, timeline) in &merged {
+            let horizon = horizons.get(timeline_id).expect("populated above");
+            let new_gc_cutoff = std::cmp::max(*timeline.get_latest_gc_cutoff_lsn(), *horizon);
+            let waitlist = timeline
+                .latest_gc_cutoff_lsn
+                .lock_for_write()
+                .store_and_unlock(new_gc_cutoff);
+            waitlist.wait().await;
+
+            let disk_consistent_lsn = timeline.get_disk_consistent_lsn();
+            let record_lsn = timeline.get_last_record_rlsn();
+            let update = MetadataUpdate::new(
+                disk_consistent_lsn,
+                (disk_consistent_lsn == record_lsn.last).then_some(record_lsn.prev),
+                *timeline.latest_gc_cutoff_lsn.read(),
+            );
+            timeline
+                .remote_client
+                .schedule_index_upload_for_metadata_update(&update)?;
+            if let Some(policy) = timeline.last_aux_file_policy.load() {
+                timeline
+                    .remote_client
+                    .schedule_index_upload_for_aux_file_policy_update(Some(policy))?;
+            }
+            timeline
+                .remote_client
+                .wait_completion()
+                .await
+                .context("wait for merged timeline uploads to complete")?;
+        }
+
+        fail::fail_point!("shard-merge-pre-activate", |_| Err(anyhow::anyhow!(
+            "failpoint"
+        )));
+
+        for timeline in merged.values() {
+            timeline.activate(self.clone(), broker_client.clone(), None, ctx);
+        }
+
+        Ok(())
+    }
+}
+
+/// The source whose content is used for `key`.  Keys that are held on every shard (e.g. relation
+/// sizes) are only maintained accurately on shard zero, so they are taken from the lowest-numbered
+/// source, which is shard zero if we are merging into shard zero.
+fn key_owner(sources: &[Arc<Timeline>], key: &Key) -> usize {
+    sources
+        .iter()
+        .position(|s| s.shard_identity.is_key_local(key))
+        .unwrap_or(0)
+}
+
+/// Write the content of a timeline from `sources` into `timeline`: images as of each of `points`
+/// (ascending, the last of which is `horizon` unless that is where `timeline` starts), then the
+/// history after `horizon` up to `target`.  Leaves `timeline` flushed and with its last record
+/// LSN at `target`.
+async fn merge_timeline_content(
+    timeline: &Arc<Timeline>,
+    sources: &[Arc<Timeline>],
+    points: &[Lsn],
+    horizon: Lsn,
+    target: RecordLsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let mut sparse_keys = HashSet::new();
+    for lsn in points {
+        sparse_keys = merge_timeline_content_at(timeline, sources, *lsn, &sparse_keys, ctx).await?;
+        timeline.writer().await.finish_write(*lsn);
+    }
+    timeline
+        .freeze_and_flush()
+        .await
+        .context("flush merged timeline content")?;
+
+    let layers = if target.last > horizon {
+        merge_timeline_history(timeline, sources, horizon, target.last, ctx).await?
+    } else {
+        Vec::new()
+    };
+    timeline.add_merged_history(layers, target).await
+}
+
+/// Copy the content of all of `sources` as of `lsn` into `timeline` as images, each key from the
+/// source that owns it.  `prev_sparse_keys` are the sparse keys written at the previous point:
+/// those that no longer exist get a tombstone, rather than reading through to the older image.
+/// Dense keys don't need this, as their existence is tracked in metadata keys.  Returns the sparse
+/// keys written.
+async fn merge_timeline_content_at(
+    timeline: &Arc<Timeline>,
+    sources: &[Arc<Timeline>],
+    lsn: Lsn,
+    prev_sparse_keys: &HashSet<Key>,
+    ctx: &RequestContext,
+) -> anyhow::Result<HashSet<Key>> {
+    info!(%lsn, "Merging timeline content");
+
+    let mut sparse_keys = HashSet::new();
+    let mut writer = timeline.writer().await;
+    for (i, source) in sources.iter().enumerate() {
+        let (dense, sparse) = source.collect_keyspace(lsn, ctx).await?;
+
+        let mut key_request_accum = KeySpaceAccum::new();
+        for range in &dense.ranges {
+            let mut key = range.start;
+            while key < range.end {
+                if key_owner(sources, &key) == i {
+                    key_request_accum.add_key(key);
+                }
+
+                let last_key_in_range = key.next() == range.end;
+                key = key.next();
+
+                if key_request_accum.raw_size() >= Timeline::MAX_GET_VECTORED_KEYS
+                    || (last_key_in_range && key_request_accum.raw_size() > 0)
+                {
+                    let results = source
+                        .get_vectored(key_request_accum.consume_keyspace(), lsn, ctx)
+                        .await?;
+                    let mut batch = Vec::with_capacity(results.len());
+                    for (img_key, img) in results {
+                        let img = match img {
+                            Ok(img) => img,
+                            // Same tolerance as image layer creation: unreconstructable FSM/VM pages
+                            // may be zeroed without losing user data.
+                            Err(_)
+                                if img_key.is_rel_fsm_block_key()
+                                    || img_key.is_rel_vm_block_key() =>
+                            {
+                                ZERO_PAGE.clone()
+                            }
+                            Err(e) => return Err(e.into()),
+                        };
+                        let value = Value::Image(img);
+                        let size = value.serialized_size()? as usize;
+                        batch.push((img_key.to_compact(), lsn, size, value));
+                    }
+                    writer.put_batch(batch, ctx).await?;
+                }
+            }
+        }
+
+        // Deleted sparse keys are not returned, so we only get the ones that exist
+        let results = source
+            .get_vectored_impl(
+                KeySpace {
+                    ranges: sparse.0.ranges,
+                },
+                lsn,
+                &mut ValuesReconstructState::default(),
+                ctx,
+            )
+            .await?;
+        let mut batch = Vec::with_capacity(results.len());
+        for (key, value) in results {
+            if key_owner(sources, &key) != i {
+                continue;
+            }
+            let value = Value::Image(value?);
+            let size = value.serialized_size()? as usize;
+            batch.push((key.to_compact(), lsn, size, value));
+            sparse_keys.insert(key);
+        }
+        writer.put_batch(batch, ctx).await?;
+    }
+
+    // In the sparse keyspace, an empty image is a tombstone
+    let mut batch = Vec::new();
+    for key in prev_sparse_keys.difference(&sparse_keys) {
+        let value = Value::Image(Bytes::new());
+        let size = value.serialized_size()? as usize;
+        batch.push((key.to_compact(), lsn, size, value));
+    }
+    if !batch.is_empty() {
+        writer.put_batch(batch, ctx).await?;
+    }
+
+    Ok(sparse_keys)
+}
+
+/// Copy the history of `sources` after `horizon`, up to and including `target`, into delta layers
+/// for `timeline`.  Each key's values, tombstones included, come from the source that owns it:
+/// the layers of the other sources may hold stale values of it, e.g. ones inherited from the shard
+/// they were split from.
+async fn merge_timeline_history(
+    timeline: &Arc<Timeline>,
+    sources: &[Arc<Timeline>],
+    horizon: Lsn,
+    target: Lsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<Vec<ResidentLayer>> {
+    info!(%horizon, %target, "Merging timeline history");
+    let lsn_range = Lsn(horizon.0 + 1)..Lsn(target.0 + 1);
+
+    // The sources have reached the target: after a flush, everything up to it is in their layers.
+    let mut resident = Vec::with_capacity(sources.len());
+    for source in sources {
+        source
+            .freeze_and_flush()
+            .await
+            .context("flush source timeline")?;
+        let layers = {
+            let guard = source.layers.read().await;
+            let layer_map = guard.layer_map()?;
+            layer_map
+                .iter_historic_layers()
+                .filter(|desc| {
+                    let layer_lsns = desc.get_lsn_range();
+                    layer_lsns.start < lsn_range.end && layer_lsns.end > lsn_range.start
+                })
+                .map(|desc| guard.get_from_desc(&desc))
+                .collect::<Vec<_>>()
+        };
+        let mut source_resident = Vec::with_capacity(layers.len());
+        for layer in layers {
+            source_resident.push(layer.download_and_keep_resident().await?);
+        }
+        resident.push(source_resident);
+    }
+
+    let mut inner = Vec::with_capacity(resident.len());
+    for source_resident in &resident {
+        let mut deltas = Vec::new();
+        let mut images = Vec::new();
+        for layer in source_resident {
+            if layer.layer_desc().is_delta() {
+                deltas.push(layer.get_as_delta(ctx).await?);
+            } else {
+                images.push(layer.get_as_image(ctx).await?);
+            }
+        }
+        inner.push((deltas, images));
+    }
+    let mut iters = inner
+        .iter()
+        .map(|(deltas, images)| MergeIterator::create(deltas, images, ctx))
+        .collect::<Vec<_>>();
+
+    // The sources own disjoint sets of keys, so merging their values in key order is all it takes
+    // to write them out in the order that delta layers need.
+    let mut heads = Vec::with_capacity(iters.len());
+    for (i, iter) in iters.iter_mut().enumerate() {
+        heads.push(next_owned_value(iter, sources, i, &lsn_range).await?);
+    }
+    let mut writer = SplitDeltaLayerWriter::new(
+        timeline.conf,
+        timeline.timeline_id,
+        timeline.tenant_shard_id,
+        lsn_range.clone(),
+        timeline.get_compaction_target_size(),
+    )
+    .await?;
+    let mut last_written = None;
+    while let Some(i) = heads
+        .iter()
+        .enumerate()
+        .filter_map(|(i, head)| head.as_ref().map(|(key, lsn, _)| (i, (*key, *lsn))))
+        .min_by_key(|(_, key_lsn)| *key_lsn)
+        .map(|(i, _)| i)
+    {
+        let (key, lsn, value) = heads[i].take().expect("picked above");
+        heads[i] = next_owned_value(&mut iters[i], sources, i, &lsn_range).await?;
+
+        // An image layer may repeat a value that is also in a delta layer: either will do
+        if last_written == Some((key, lsn)) {
+            continue;
+        }
+        last_written = Some((key, lsn));
+        writer.put_value(key, lsn, value, timeline, ctx).await?;
+    }
+
+    let layers = writer
+        .finish_with_discard_fn(timeline, ctx, |_| async { false })
+        .await?
+        .into_iter()
+        .filter_map(|result| match result {
+            SplitWriterResult::Produced(layer) => Some(layer),
+            SplitWriterResult::Discarded(_) => None,
+        })
+        .collect();
+    Ok(layers)
+}
+
+/// The next value from `iter`, a merge of the layers of `sources[source]`, that is in `lsn_range`
+/// and for a key that the source owns.
+async fn next_owned_value(
+    iter: &mut MergeIterator<'_>,
+    sources: &[Arc<Timeline>],
+    source: usize,
+    lsn_range: &Range<Lsn>,
+) -> anyhow::Result<Option<(Key, Lsn, Value)>> {
+    while let Some((key, lsn, value)) = iter.next().await? {
+        if lsn_range.contains(&lsn) && key_owner(sources, &key) == source {
+            return Ok(Some((key, lsn, value)));
+        }
+    }
+    Ok(None)
+}
@@ -1990,7 +1990,7 @@ fn get_checkpoint_timeout(&self) -> Duration {
             .unwrap_or(self.conf.default_tenant_conf.checkpoint_timeout)
     }
 
-    fn get_compaction_target_size(&self) -> u64 {
+    pub(crate) fn get_compaction_target_size(&self) -> u64 {
         let tenant_conf = self.tenant_conf.load();
         tenant_conf
             .tenant_conf
@@ -3720,6 +3720,36 @@ fn set_disk_consistent_lsn(&self, new_value: Lsn) -> bool {
         new_value != old_value
     }
 
+    /// Add layers written outside of the ingest path, which hold the history after the last record
+    /// LSN up to `end`, and advance the timeline to `end`.  Only for a shard merge (see
+    /// [`crate::tenant::Tenant::merge_from_shards`]), before the timeline is activated: the flush
+    /// loop is idle, as everything ingested so far has been flushed.
+    pub(crate) async fn add_merged_history(
+        &self,
+        layers: Vec<ResidentLayer>,
+        end: RecordLsn,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.get_disk_consistent_lsn() == self.get_last_record_lsn(),
+            "Timeline has unflushed writes"
+        );
+        {
+            let mut guard = self.layers.write().await;
+            guard
+                .open_mut()?
+                .finish_gc_compaction(&[], &layers, &self.metrics);
+        }
+
+        // Advancing through the prev record LSN makes it our prev record LSN as well, which a
+        // compute needs to start at `end`.
+        if end.prev.is_valid() && end.prev.is_aligned() {
+            self.finish_write(end.prev);
+        }
+        self.finish_write(end.last);
+        self.set_disk_consistent_lsn(end.last);
+        self.schedule_uploads(end.last, layers)
+    }
+
     /// Update metadata file
     fn schedule_uploads(
         &self,
@@ -436,6 +436,16 @@ pub(super) fn shutdown(&self) {
         }
         drop(handles);
     }
+
+    /// Whether any page service connection currently holds a handle to the timeline, i.e. a
+    /// compute is connected to it, even if it isn't sending requests.
+    pub(crate) fn has_handles(&self) -> bool {
+        self.handles
+            .lock()
+            .expect("mutex poisoned")
+            .as_ref()
+            .is_some_and(|handles| !handles.is_empty())
+    }
 }
 
 impl<T: Types> std::ops::Deref for Handle<T> {
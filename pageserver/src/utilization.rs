@@ -4,17 +4,106 @@
 //! truth.
 
 use anyhow::Context;
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::Instant;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
 use utils::serde_percent::Percent;
 
+use pageserver_api::models::utilization::ShardLoad;
 use pageserver_api::models::PageserverUtilization;
+use pageserver_api::shard::TenantShardId;
 
-use crate::{config::PageServerConf, metrics::NODE_UTILIZATION_SCORE, tenant::mgr::TenantManager};
+use crate::{
+    config::PageServerConf,
+    metrics::NODE_UTILIZATION_SCORE,
+    tenant::mgr::{TenantManager, TenantSlot},
+};
+
+/// How many of the most loaded shards to describe in [`PageserverUtilization::shard_loads`]
+const REPORT_SHARD_LOADS: usize = 32;
+
+struct LoadSample {
+    at: Instant,
+    last_record_lsn: Lsn,
+    getpage_count: u64,
+}
+
+/// Load is reported as rates, which we calculate from the difference between cumulative
+/// counters at successive calls to [`regenerate`].
+#[derive(Default)]
+pub(crate) struct LoadSampler {
+    samples: HashMap<(TenantShardId, TimelineId), LoadSample>,
+}
+
+impl LoadSampler {
+    /// Sample the load of all attached shards.  Timelines that we have not seen before report zero
+    /// load until the next call.
+    ///
+    /// Also returns the shards that were active since the previous call: those that ingested WAL
+    /// or served any GetPage requests, however few, or that a compute is connected to.  A compute
+    /// with a warm cache may be connected but send no requests for a long time.
+    fn sample(&mut self, tenant_manager: &TenantManager) -> (Vec<ShardLoad>, Vec<TenantShardId>) {
+        let now = Instant::now();
+        let mut samples = HashMap::with_capacity(self.samples.len());
+        let mut loads = Vec::new();
+        let mut active_shards = Vec::new();
+
+        for (tenant_shard_id, slot) in tenant_manager.list() {
+            let TenantSlot::Attached(tenant) = slot else {
+                continue;
+            };
+
+            let mut load = ShardLoad {
+                id: tenant_shard_id,
+                ingest_bytes_per_second: 0,
+                getpage_requests_per_second: 0,
+                resident_size: 0,
+            };
+            let mut active = false;
+            for timeline in tenant.list_timelines() {
+                let sample = LoadSample {
+                    at: now,
+                    last_record_lsn: timeline.get_last_record_lsn(),
+                    getpage_count: timeline.query_metrics.getpage_count(),
+                };
+                load.resident_size += timeline.metrics.resident_physical_size_gauge.get();
+                active |= timeline.handles.has_handles();
+
+                if let Some(prev) = self.samples.get(&(tenant_shard_id, timeline.timeline_id)) {
+                    let ingested = sample
+                        .last_record_lsn
+                        .0
+                        .saturating_sub(prev.last_record_lsn.0);
+                    let getpages = sample.getpage_count.saturating_sub(prev.getpage_count);
+                    active |= ingested > 0 || getpages > 0;
+
+                    let elapsed = sample.at.duration_since(prev.at).as_secs_f64();
+                    if elapsed > 0.0 {
+                        load.ingest_bytes_per_second += (ingested as f64 / elapsed) as u64;
+                        load.getpage_requests_per_second += (getpages as f64 / elapsed) as u64;
+                    }
+                }
+
+                samples.insert((tenant_shard_id, timeline.timeline_id), sample);
+            }
+            if active {
+                active_shards.push(tenant_shard_id);
+            }
+            loads.push(load);
+        }
+
+        self.samples = samples;
+        (loads, active_shards)
+    }
+}
 
 pub(crate) fn regenerate(
     conf: &PageServerConf,
     tenants_path: &Path,
     tenant_manager: &TenantManager,
+    load_sampler: &mut LoadSampler,
 ) -> anyhow::Result<PageserverUtilization> {
     let statvfs = nix::sys::statvfs::statvfs(tenants_path)
         .map_err(std::io::Error::from)
@@ -51,6 +140,27 @@ pub(crate) fn regenerate(
     // Express a static value for how many shards we may schedule on one node
     const MAX_SHARDS: u32 = 20000;
 
+    // Likewise for the load that attached shards may generate, in aggregate
+    const MAX_INGEST_BYTES_PER_SECOND: u64 = 256 * 1024 * 1024;
+    const MAX_GETPAGE_REQUESTS_PER_SECOND: u64 = 200000;
+
+    let (mut shard_loads, active_shards) = load_sampler.sample(tenant_manager);
+    let ingest_bytes_per_second = shard_loads.iter().map(|l| l.ingest_bytes_per_second).sum();
+    let getpage_requests_per_second = shard_loads
+        .iter()
+        .map(|l| l.getpage_requests_per_second)
+        .sum();
+
+    // Most loaded first: rank shards by whichever dimension is closest to its limit
+    shard_loads.sort_by_key(|l| {
+        std::cmp::Reverse(std::cmp::max(
+            l.ingest_bytes_per_second as u128 * MAX_GETPAGE_REQUESTS_PER_SECOND as u128,
+            l.getpage_requests_per_second as u128 * MAX_INGEST_BYTES_PER_SECOND as u128,
+        ))
+    });
+    shard_loads.retain(|l| l.ingest_bytes_per_second > 0 || l.getpage_requests_per_second > 0);
+    shard_loads.truncate(REPORT_SHARD_LOADS);
+
     let mut doc = PageserverUtilization {
         disk_usage_bytes: used,
         free_space_bytes: free,
@@ -58,6 +168,12 @@ pub(crate) fn regenerate(
         disk_usable_pct,
         shard_count,
         max_shard_count: MAX_SHARDS,
+        ingest_bytes_per_second,
+        max_ingest_bytes_per_second: MAX_INGEST_BYTES_PER_SECOND,
+        getpage_requests_per_second,
+        max_getpage_requests_per_second: MAX_GETPAGE_REQUESTS_PER_SECOND,
+        shard_loads,
+        active_shards: Some(active_shards),
         utilization_score: None,
         captured_at: utils::serde_system_time::SystemTime(captured_at),
     };
@@ -31,11 +31,13 @@
 #include "postmaster/bgworker.h"
 #include RELFILEINFO_HDR
 #include "storage/buf_internals.h"
+#include "storage/bufmgr.h"
 #include "storage/fd.h"
 #include "storage/ipc.h"
 #include "storage/latch.h"
 #include "storage/lwlock.h"
 #include "storage/pg_shmem.h"
+#include "storage/smgr.h"
 #include "utils/builtins.h"
 #include "utils/dynahash.h"
 #include "utils/guc.h"
@@ -1288,3 +1290,230 @@ approximate_working_set_size(PG_FUNCTION_ARGS)
 	}
 	PG_RETURN_NULL();
 }
+
+/*
+ * Function returning the contents of the local file cache in LRU order, most
+ * recently used first, so that it can be saved and used to prewarm the cache
+ * after restart. Each row is a run of consecutive cached blocks of a chunk.
+ * Chunks that are being accessed right now are not in the LRU list and are
+ * left out.
+ */
+PG_FUNCTION_INFO_V1(get_local_cache_state);
+
+typedef struct
+{
+	Oid			reltablespace;
+	Oid			reldatabase;
+	Oid			relfilenode;
+	ForkNumber	forknum;
+	BlockNumber blocknum;
+	uint32		nblocks;
+} LocalCacheStateRec;
+
+typedef struct
+{
+	TupleDesc	tupdesc;
+	LocalCacheStateRec *record;
+} LocalCacheStateContext;
+
+#define NUM_LOCALCACHE_STATE_ELEM	6
+
+/*
+ * Count the runs of consecutive cached blocks of the chunk, and store them in
+ * 'record' unless it's NULL.
+ */
+static uint32
+lfc_chunk_runs(FileCacheEntry *entry, LocalCacheStateRec *record)
+{
+	uint32		n = 0;
+	int			start = -1;
+
+	for (int i = 0; i <= BLOCKS_PER_CHUNK; i++)
+	{
+		bool		cached = i < BLOCKS_PER_CHUNK &&
+			(entry->bitmap[i >> 5] & (1 << (i & 31))) != 0;
+
+		if (cached && start < 0)
+			start = i;
+		else if (!cached && start >= 0)
+		{
+			if (record)
+			{
+				record[n].reltablespace = NInfoGetSpcOid(BufTagGetNRelFileInfo(entry->key));
+				record[n].reldatabase = NInfoGetDbOid(BufTagGetNRelFileInfo(entry->key));
+				record[n].relfilenode = NInfoGetRelNumber(BufTagGetNRelFileInfo(entry->key));
+				record[n].forknum = entry->key.forkNum;
+				record[n].blocknum = entry->key.blockNum + start;
+				record[n].nblocks = i - start;
+			}
+			n += 1;
+			start = -1;
+		}
+	}
+	return n;
+}
+
+Datum
+get_local_cache_state(PG_FUNCTION_ARGS)
+{
+	FuncCallContext *funcctx;
+	LocalCacheStateContext *fctx;
+
+	if (SRF_IS_FIRSTCALL())
+	{
+		MemoryContext oldcontext;
+		TupleDesc	tupledesc;
+		int32		max_chunks = PG_ARGISNULL(0) ? -1 : PG_GETARG_INT32(0);
+		uint32		n_runs = 0;
+
+		funcctx = SRF_FIRSTCALL_INIT();
+		oldcontext = MemoryContextSwitchTo(funcctx->multi_call_memory_ctx);
+
+		fctx = (LocalCacheStateContext *) palloc(sizeof(LocalCacheStateContext));
+
+		tupledesc = CreateTemplateTupleDesc(NUM_LOCALCACHE_STATE_ELEM);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 1, "reltablespace",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 2, "reldatabase",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 3, "relfilenode",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 4, "relforknumber",
+						   INT2OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 5, "relblocknumber",
+						   INT8OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 6, "nblocks",
+						   INT4OID, -1, 0);
+		fctx->tupdesc = BlessTupleDesc(tupledesc);
+		fctx->record = NULL;
+
+		if (lfc_ctl)
+		{
+			LWLockAcquire(lfc_lock, LW_SHARED);
+
+			if (LFC_ENABLED())
+			{
+				dlist_iter	iter;
+				int32		n_chunks = 0;
+
+				dlist_reverse_foreach(iter, &lfc_ctl->lru)
+				{
+					if (max_chunks >= 0 && n_chunks++ >= max_chunks)
+						break;
+					n_runs += lfc_chunk_runs(dlist_container(FileCacheEntry, list_node, iter.cur), NULL);
+				}
+
+				fctx->record = (LocalCacheStateRec *)
+					MemoryContextAllocHuge(CurrentMemoryContext,
+										   sizeof(LocalCacheStateRec) * Max(n_runs, 1));
+
+				n_runs = 0;
+				n_chunks = 0;
+				dlist_reverse_foreach(iter, &lfc_ctl->lru)
+				{
+					if (max_chunks >= 0 && n_chunks++ >= max_chunks)
+						break;
+					n_runs += lfc_chunk_runs(dlist_container(FileCacheEntry, list_node, iter.cur),
+											 &fctx->record[n_runs]);
+				}
+			}
+			LWLockRelease(lfc_lock);
+		}
+
+		funcctx->max_calls = n_runs;
+		funcctx->user_fctx = fctx;
+
+		MemoryContextSwitchTo(oldcontext);
+	}
+
+	funcctx = SRF_PERCALL_SETUP();
+	fctx = funcctx->user_fctx;
+
+	if (funcctx->call_cntr < funcctx->max_calls)
+	{
+		LocalCacheStateRec *rec = &fctx->record[funcctx->call_cntr];
+		Datum		values[NUM_LOCALCACHE_STATE_ELEM];
+		bool		nulls[NUM_LOCALCACHE_STATE_ELEM] = {
+			false, false, false, false, false, false
+		};
+		HeapTuple	tuple;
+
+		values[0] = ObjectIdGetDatum(rec->reltablespace);
+		values[1] = ObjectIdGetDatum(rec->reldatabase);
+		values[2] = ObjectIdGetDatum(rec->relfilenode);
+		values[3] = Int16GetDatum(rec->forknum);
+		values[4] = Int64GetDatum((int64) rec->blocknum);
+		values[5] = Int32GetDatum((int32) rec->nblocks);
+
+		tuple = heap_form_tuple(fctx->tupdesc, values, nulls);
+		SRF_RETURN_NEXT(funcctx, HeapTupleGetDatum(tuple));
+	}
+	else
+		SRF_RETURN_DONE(funcctx);
+}
+
+/*
+ * Fetch the given blocks of a relation from the page server into the local
+ * file cache, skipping those that are cached already. Returns the number of
+ * blocks fetched.
+ *
+ * The blocks are read through shared buffers, like pg_prewarm does, so that
+ * the usual buffer locking keeps a concurrently modified or evicted newer
+ * version of a page from being overwritten in the cache with an older one.
+ * A bulk-read strategy keeps the prewarm from flushing out shared buffers.
+ */
+PG_FUNCTION_INFO_V1(prewarm_local_cache);
+
+Datum
+prewarm_local_cache(PG_FUNCTION_ARGS)
+{
+	NRelFileInfo rinfo;
+	ForkNumber	forknum = (ForkNumber) PG_GETARG_INT16(3);
+	int64		blkno = PG_GETARG_INT64(4);
+	int32		nblocks = PG_GETARG_INT32(5);
+	SMgrRelation reln;
+	BlockNumber relsize;
+	BufferAccessStrategy strategy;
+	int32		fetched = 0;
+
+	if (forknum < 0 || forknum > MAX_FORKNUM)
+		neon_log(ERROR, "invalid fork number %d", forknum);
+	if (blkno < 0 || nblocks < 0 || blkno + nblocks > MaxBlockNumber)
+		neon_log(ERROR, "invalid block range %ld..%ld", (long) blkno, (long) (blkno + nblocks));
+
+	NInfoGetSpcOid(rinfo) = PG_GETARG_OID(0);
+	NInfoGetDbOid(rinfo) = PG_GETARG_OID(1);
+	NInfoGetRelNumber(rinfo) = PG_GETARG_OID(2);
+	if (NInfoGetRelNumber(rinfo) == InvalidRelFileNumber)
+		neon_log(ERROR, "invalid relfilenode");
+
+	/* The relation may have been truncated or dropped since it was saved */
+	reln = smgropen(rinfo, INVALID_PROC_NUMBER);
+	if (!smgrexists(reln, forknum))
+		PG_RETURN_INT32(0);
+	relsize = smgrnblocks(reln, forknum);
+
+	strategy = GetAccessStrategy(BAS_BULKREAD);
+	for (int64 i = blkno; i < blkno + nblocks && i < relsize; i++)
+	{
+		Buffer		buf;
+
+		if (lfc_maybe_disabled())
+			break;
+		if (lfc_cache_contains(rinfo, forknum, (BlockNumber) i))
+			continue;
+
+		CHECK_FOR_INTERRUPTS();
+#if PG_MAJORVERSION_NUM < 15
+		buf = ReadBufferWithoutRelcache(rinfo, forknum, (BlockNumber) i,
+										RBM_NORMAL, strategy);
+#else
+		buf = ReadBufferWithoutRelcache(rinfo, forknum, (BlockNumber) i,
+										RBM_NORMAL, strategy, true);
+#endif
+		ReleaseBuffer(buf);
+		fetched += 1;
+	}
+	FreeAccessStrategy(strategy);
+	PG_RETURN_INT32(fetched);
+}
@@ -14,9 +14,12 @@
  */
 #include "postgres.h"
 
+#include "access/htup_details.h"
 #include "access/xlog.h"
+#include "catalog/pg_type.h"
 #include "common/hashfn.h"
 #include "fmgr.h"
+#include "funcapi.h"
 #include "libpq-fe.h"
 #include "libpq/libpq.h"
 #include "libpq/pqformat.h"
@@ -79,11 +82,25 @@ typedef struct
  * the postmaster changes the value concurrently. (Postmaster doesn't have a
  * PGPROC entry and therefore cannot use LWLocks.)
  */
+/*
+ * Cumulative statistics of the requests to each shard, for compute_ctl to
+ * notice when a pageserver becomes unreachable or slow. See
+ * pageserver_stats().
+ */
+typedef struct
+{
+	pg_atomic_uint64 requests;	/* requests sent */
+	pg_atomic_uint64 responses;	/* responses received */
+	pg_atomic_uint64 errors;	/* failed connection attempts and lost connections */
+	pg_atomic_uint64 wait_us;	/* time spent waiting for the responses */
+} PagestoreShardStats;
+
 typedef struct
 {
 	pg_atomic_uint64 begin_update_counter;
 	pg_atomic_uint64 end_update_counter;
 	ShardMap	shard_map;
+	PagestoreShardStats shard_stats[MAX_SHARDS];
 } PagestoreShmemState;
 
 #if PG_VERSION_NUM >= 150000
@@ -139,6 +156,12 @@ PagestoreShmemIsValid(void)
 	return pagestore_shared && UsedShmemSegAddr;
 }
 
+static inline void
+count_shard_error(shardno_t shard_no)
+{
+	pg_atomic_add_fetch_u64(&pagestore_shared->shard_stats[shard_no].errors, 1);
+}
+
 /*
  * Parse a comma-separated list of connection strings into a ShardMap.
  *
@@ -760,6 +783,7 @@ pageserver_send(shardno_t shard_no, NeonRequest *request)
 	{
 		while (!pageserver_connect(shard_no, shard->n_reconnect_attempts < max_reconnect_attempts ? LOG : ERROR))
 		{
+			count_shard_error(shard_no);
 			HandleMainLoopInterrupts();
 			shard->n_reconnect_attempts += 1;
 		}
@@ -786,6 +810,7 @@ pageserver_send(shardno_t shard_no, NeonRequest *request)
 	{
 		char	   *msg = pchomp(PQerrorMessage(pageserver_conn));
 
+		count_shard_error(shard_no);
 		pageserver_disconnect(shard_no);
 		neon_shard_log(shard_no, LOG, "pageserver_send disconnected: failed to send page request (try to reconnect): %s", msg);
 		pfree(msg);
@@ -794,6 +819,7 @@ pageserver_send(shardno_t shard_no, NeonRequest *request)
 	}
 
 	pfree(req_buff.data);
+	pg_atomic_add_fetch_u64(&pagestore_shared->shard_stats[shard_no].requests, 1);
 
 	if (message_level_is_interesting(PageStoreTrace))
 	{
@@ -815,6 +841,7 @@ pageserver_receive(shardno_t shard_no)
 	PGconn	   *pageserver_conn = shard->conn;
 	/* read response */
 	int			rc;
+	TimestampTz	wait_start;
 
 	if (shard->state != PS_Connected)
 	{
@@ -826,12 +853,19 @@ pageserver_receive(shardno_t shard_no)
 
 	Assert(pageserver_conn);
 
+	wait_start = GetCurrentTimestamp();
 	rc = call_PQgetCopyData(shard_no, &resp_buff.data);
 	if (rc >= 0)
 	{
+		PagestoreShardStats *stats = &pagestore_shared->shard_stats[shard_no];
+
 		/* call_PQgetCopyData handles rc == 0 */
 		Assert(rc > 0);
 
+		pg_atomic_add_fetch_u64(&stats->responses, 1);
+		pg_atomic_add_fetch_u64(&stats->wait_us,
+								Max(GetCurrentTimestamp() - wait_start, 0));
+
 		PG_TRY();
 		{
 			resp_buff.len = rc;
@@ -858,6 +892,7 @@ pageserver_receive(shardno_t shard_no)
 	else if (rc == -1)
 	{
 		neon_shard_log(shard_no, LOG, "pageserver_receive disconnect: psql end of copy data: %s", pchomp(PQerrorMessage(pageserver_conn)));
+		count_shard_error(shard_no);
 		pageserver_disconnect(shard_no);
 		resp = NULL;
 	}
@@ -865,11 +900,13 @@ pageserver_receive(shardno_t shard_no)
 	{
 		char	   *msg = pchomp(PQerrorMessage(pageserver_conn));
 
+		count_shard_error(shard_no);
 		pageserver_disconnect(shard_no);
 		neon_shard_log(shard_no, ERROR, "pageserver_receive disconnect: could not read COPY data: %s", msg);
 	}
 	else
 	{
+		count_shard_error(shard_no);
 		pageserver_disconnect(shard_no);
 		neon_shard_log(shard_no, ERROR, "pageserver_receive disconnect: unexpected PQgetCopyData return value: %d", rc);
 	}
@@ -893,6 +930,7 @@ pageserver_flush(shardno_t shard_no)
 		{
 			char	   *msg = pchomp(PQerrorMessage(pageserver_conn));
 
+			count_shard_error(shard_no);
 			pageserver_disconnect(shard_no);
 			neon_shard_log(shard_no, LOG, "pageserver_flush disconnect because failed to flush page requests: %s", msg);
 			pfree(msg);
@@ -939,6 +977,15 @@ PagestoreShmemInit(void)
 		pg_atomic_init_u64(&pagestore_shared->begin_update_counter, 0);
 		pg_atomic_init_u64(&pagestore_shared->end_update_counter, 0);
 		memset(&pagestore_shared->shard_map, 0, sizeof(ShardMap));
+		for (int i = 0; i < MAX_SHARDS; i++)
+		{
+			PagestoreShardStats *stats = &pagestore_shared->shard_stats[i];
+
+			pg_atomic_init_u64(&stats->requests, 0);
+			pg_atomic_init_u64(&stats->responses, 0);
+			pg_atomic_init_u64(&stats->errors, 0);
+			pg_atomic_init_u64(&stats->wait_us, 0);
+		}
 		AssignPageserverConnstring(page_server_connstring, NULL);
 	}
 	LWLockRelease(AddinShmemInitLock);
@@ -978,6 +1025,69 @@ pagestore_prepare_shmem(void)
 	shmem_startup_hook = pagestore_shmem_startup_hook;
 }
 
+#define NUM_PAGESERVER_STATS_COLS 5
+
+PG_FUNCTION_INFO_V1(pageserver_stats);
+
+/*
+ * Return the statistics of the requests to each shard of the current shard
+ * map. The counters are cumulative, and not reset when the shard map changes.
+ */
+Datum
+pageserver_stats(PG_FUNCTION_ARGS)
+{
+	FuncCallContext *funcctx;
+	shardno_t	shard_no;
+	PagestoreShardStats *stats;
+	Datum		values[NUM_PAGESERVER_STATS_COLS];
+	bool		nulls[NUM_PAGESERVER_STATS_COLS];
+
+	if (SRF_IS_FIRSTCALL())
+	{
+		MemoryContext oldcontext;
+		TupleDesc	tupledesc;
+		shardno_t	num_shards;
+
+		funcctx = SRF_FIRSTCALL_INIT();
+		oldcontext = MemoryContextSwitchTo(funcctx->multi_call_memory_ctx);
+
+		tupledesc = CreateTemplateTupleDesc(NUM_PAGESERVER_STATS_COLS);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 1, "shard_no",
+						   INT4OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 2, "requests",
+						   INT8OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 3, "responses",
+						   INT8OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 4, "errors",
+						   INT8OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 5, "wait_us",
+						   INT8OID, -1, 0);
+		funcctx->tuple_desc = BlessTupleDesc(tupledesc);
+
+		load_shard_map(0, NULL, &num_shards);
+		funcctx->max_calls = num_shards;
+
+		MemoryContextSwitchTo(oldcontext);
+	}
+
+	funcctx = SRF_PERCALL_SETUP();
+
+	if (funcctx->call_cntr >= funcctx->max_calls)
+		SRF_RETURN_DONE(funcctx);
+
+	shard_no = funcctx->call_cntr;
+	stats = &pagestore_shared->shard_stats[shard_no];
+
+	MemSet(nulls, 0, sizeof(nulls));
+	values[0] = Int32GetDatum(shard_no);
+	values[1] = Int64GetDatum(pg_atomic_read_u64(&stats->requests));
+	values[2] = Int64GetDatum(pg_atomic_read_u64(&stats->responses));
+	values[3] = Int64GetDatum(pg_atomic_read_u64(&stats->errors));
+	values[4] = Int64GetDatum(pg_atomic_read_u64(&stats->wait_us));
+
+	SRF_RETURN_NEXT(funcctx, HeapTupleGetDatum(heap_form_tuple(funcctx->tuple_desc, values, nulls)));
+}
+
 /*
  * Module initialization function
  */
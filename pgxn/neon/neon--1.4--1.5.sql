@@ -0,0 +1,16 @@
+\echo Use "ALTER EXTENSION neon UPDATE TO '1.5'" to load this file. \quit
+
+CREATE FUNCTION get_local_cache_state(max_chunks integer default null)
+RETURNS TABLE (reltablespace oid, reldatabase oid, relfilenode oid,
+               relforknumber int2, relblocknumber int8, nblocks int4)
+AS 'MODULE_PATHNAME', 'get_local_cache_state'
+LANGUAGE C PARALLEL SAFE;
+
+CREATE FUNCTION prewarm_local_cache(reltablespace oid, reldatabase oid, relfilenode oid,
+                                    relforknumber int2, relblocknumber int8, nblocks int4)
+RETURNS integer
+AS 'MODULE_PATHNAME', 'prewarm_local_cache'
+LANGUAGE C STRICT PARALLEL UNSAFE;
+
+REVOKE ALL ON FUNCTION get_local_cache_state(integer) FROM PUBLIC;
+REVOKE ALL ON FUNCTION prewarm_local_cache(oid, oid, oid, int2, int8, int4) FROM PUBLIC;
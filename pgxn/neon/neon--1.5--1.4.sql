@@ -0,0 +1,3 @@
+DROP FUNCTION IF EXISTS prewarm_local_cache(oid, oid, oid, int2, int8, int4) CASCADE;
+
+DROP FUNCTION IF EXISTS get_local_cache_state(integer) CASCADE;
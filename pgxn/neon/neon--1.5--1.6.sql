@@ -0,0 +1,8 @@
+\echo Use "ALTER EXTENSION neon UPDATE TO '1.6'" to load this file. \quit
+
+CREATE FUNCTION pageserver_stats()
+RETURNS TABLE (shard_no int4, requests int8, responses int8, errors int8, wait_us int8)
+AS 'MODULE_PATHNAME', 'pageserver_stats'
+LANGUAGE C PARALLEL SAFE;
+
+GRANT EXECUTE ON FUNCTION pageserver_stats() TO pg_monitor;
@@ -0,0 +1,28 @@
+\echo Use "ALTER EXTENSION neon UPDATE TO '1.7'" to load this file. \quit
+
+CREATE FUNCTION get_buffer_pool_state()
+RETURNS TABLE (reltablespace oid, reldatabase oid, relfilenode oid,
+               relforknumber int2, relblocknumber int8, usagecount int2)
+AS 'MODULE_PATHNAME', 'get_buffer_pool_state'
+LANGUAGE C PARALLEL SAFE;
+
+CREATE FUNCTION prewarm_buffers(reltablespace oid, reldatabase oid, relfilenode oid,
+                                relforknumber int2, relblocknumber int8, nblocks int4)
+RETURNS integer
+AS 'MODULE_PATHNAME', 'prewarm_buffers'
+LANGUAGE C STRICT PARALLEL UNSAFE;
+
+CREATE FUNCTION get_relation_stats()
+RETURNS TABLE (reldatabase oid, relid oid, stats bytea)
+AS 'MODULE_PATHNAME', 'get_relation_stats'
+LANGUAGE C PARALLEL UNSAFE;
+
+CREATE FUNCTION restore_relation_stats(reldatabase oid, relid oid, stats bytea)
+RETURNS void
+AS 'MODULE_PATHNAME', 'restore_relation_stats'
+LANGUAGE C STRICT PARALLEL UNSAFE;
+
+REVOKE ALL ON FUNCTION get_buffer_pool_state() FROM PUBLIC;
+REVOKE ALL ON FUNCTION prewarm_buffers(oid, oid, oid, int2, int8, int4) FROM PUBLIC;
+REVOKE ALL ON FUNCTION get_relation_stats() FROM PUBLIC;
+REVOKE ALL ON FUNCTION restore_relation_stats(oid, oid, bytea) FROM PUBLIC;
@@ -0,0 +1,7 @@
+DROP FUNCTION IF EXISTS restore_relation_stats(oid, oid, bytea) CASCADE;
+
+DROP FUNCTION IF EXISTS get_relation_stats() CASCADE;
+
+DROP FUNCTION IF EXISTS prewarm_buffers(oid, oid, oid, int2, int8, int4) CASCADE;
+
+DROP FUNCTION IF EXISTS get_buffer_pool_state() CASCADE;
@@ -0,0 +1,348 @@
+/*-------------------------------------------------------------------------
+ *
+ * suspend_snapshot.c
+ *	  Functions to capture the contents of the buffer pool and the cumulative
+ *	  statistics of relations when the compute is suspended, and to restore
+ *	  them when it starts again. compute_ctl stores the snapshot in between.
+ *
+ * IDENTIFICATION
+ *	  pgxn/neon/suspend_snapshot.c
+ *
+ *-------------------------------------------------------------------------
+ */
+#include "postgres.h"
+
+#include "neon_pgversioncompat.h"
+
+#include "access/htup_details.h"
+#include "catalog/pg_type.h"
+#include "funcapi.h"
+#include "miscadmin.h"
+#include "pgstat.h"
+#include RELFILEINFO_HDR
+#include "storage/buf_internals.h"
+#include "storage/bufmgr.h"
+#include "storage/smgr.h"
+#include "utils/builtins.h"
+#if PG_MAJORVERSION_NUM >= 15
+#include "lib/dshash.h"
+#include "utils/pgstat_internal.h"
+#endif
+
+#include "pagestore_client.h"
+
+PG_FUNCTION_INFO_V1(get_buffer_pool_state);
+PG_FUNCTION_INFO_V1(prewarm_buffers);
+PG_FUNCTION_INFO_V1(get_relation_stats);
+PG_FUNCTION_INFO_V1(restore_relation_stats);
+
+typedef struct
+{
+	Oid			reltablespace;
+	Oid			reldatabase;
+	Oid			relfilenode;
+	ForkNumber	forknum;
+	BlockNumber blocknum;
+	uint32		usagecount;
+} BufferPoolStateRec;
+
+typedef struct
+{
+	TupleDesc	tupdesc;
+	BufferPoolStateRec *record;
+} BufferPoolStateContext;
+
+#define NUM_BUFFER_POOL_STATE_ELEM	6
+
+/*
+ * Function returning the valid pages of permanent relations in the buffer
+ * pool, with their usage count.
+ */
+Datum
+get_buffer_pool_state(PG_FUNCTION_ARGS)
+{
+	FuncCallContext *funcctx;
+	BufferPoolStateContext *fctx;
+
+	if (SRF_IS_FIRSTCALL())
+	{
+		MemoryContext oldcontext;
+		TupleDesc	tupledesc;
+		uint32		n = 0;
+
+		funcctx = SRF_FIRSTCALL_INIT();
+		oldcontext = MemoryContextSwitchTo(funcctx->multi_call_memory_ctx);
+
+		fctx = (BufferPoolStateContext *) palloc(sizeof(BufferPoolStateContext));
+
+		tupledesc = CreateTemplateTupleDesc(NUM_BUFFER_POOL_STATE_ELEM);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 1, "reltablespace",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 2, "reldatabase",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 3, "relfilenode",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 4, "relforknumber",
+						   INT2OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 5, "relblocknumber",
+						   INT8OID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 6, "usagecount",
+						   INT2OID, -1, 0);
+		fctx->tupdesc = BlessTupleDesc(tupledesc);
+
+		fctx->record = (BufferPoolStateRec *)
+			MemoryContextAllocHuge(CurrentMemoryContext,
+								   sizeof(BufferPoolStateRec) * NBuffers);
+
+		/*
+		 * Like pg_buffercache, don't lock the whole buffer pool: the
+		 * snapshot only needs to be roughly consistent.
+		 */
+		for (int i = 0; i < NBuffers; i++)
+		{
+			BufferDesc *bufHdr = GetBufferDescriptor(i);
+			uint32		buf_state = LockBufHdr(bufHdr);
+
+			if ((buf_state & (BM_VALID | BM_PERMANENT)) == (BM_VALID | BM_PERMANENT))
+			{
+				BufferPoolStateRec *rec = &fctx->record[n++];
+
+				rec->reltablespace = NInfoGetSpcOid(BufTagGetNRelFileInfo(bufHdr->tag));
+				rec->reldatabase = NInfoGetDbOid(BufTagGetNRelFileInfo(bufHdr->tag));
+				rec->relfilenode = NInfoGetRelNumber(BufTagGetNRelFileInfo(bufHdr->tag));
+				rec->forknum = bufHdr->tag.forkNum;
+				rec->blocknum = bufHdr->tag.blockNum;
+				rec->usagecount = BUF_STATE_GET_USAGECOUNT(buf_state);
+			}
+			UnlockBufHdr(bufHdr, buf_state);
+		}
+
+		funcctx->max_calls = n;
+		funcctx->user_fctx = fctx;
+
+		MemoryContextSwitchTo(oldcontext);
+	}
+
+	funcctx = SRF_PERCALL_SETUP();
+	fctx = funcctx->user_fctx;
+
+	if (funcctx->call_cntr < funcctx->max_calls)
+	{
+		BufferPoolStateRec *rec = &fctx->record[funcctx->call_cntr];
+		Datum		values[NUM_BUFFER_POOL_STATE_ELEM];
+		bool		nulls[NUM_BUFFER_POOL_STATE_ELEM] = {
+			false, false, false, false, false, false
+		};
+		HeapTuple	tuple;
+
+		values[0] = ObjectIdGetDatum(rec->reltablespace);
+		values[1] = ObjectIdGetDatum(rec->reldatabase);
+		values[2] = ObjectIdGetDatum(rec->relfilenode);
+		values[3] = Int16GetDatum(rec->forknum);
+		values[4] = Int64GetDatum((int64) rec->blocknum);
+		values[5] = Int16GetDatum((int16) rec->usagecount);
+
+		tuple = heap_form_tuple(fctx->tupdesc, values, nulls);
+		SRF_RETURN_NEXT(funcctx, HeapTupleGetDatum(tuple));
+	}
+	else
+		SRF_RETURN_DONE(funcctx);
+}
+
+/*
+ * Read the given blocks of a relation into the buffer pool. Blocks past the
+ * end of the relation are skipped, as the relation may have been truncated or
+ * dropped since they were listed. Returns the number of blocks read.
+ */
+Datum
+prewarm_buffers(PG_FUNCTION_ARGS)
+{
+	NRelFileInfo rinfo;
+	ForkNumber	forknum = (ForkNumber) PG_GETARG_INT16(3);
+	int64		blkno = PG_GETARG_INT64(4);
+	int32		nblocks = PG_GETARG_INT32(5);
+	SMgrRelation reln;
+	BlockNumber relsize;
+	int32		read = 0;
+
+	if (forknum < 0 || forknum > MAX_FORKNUM)
+		neon_log(ERROR, "invalid fork number %d", forknum);
+	if (blkno < 0 || nblocks < 0 || blkno + nblocks > MaxBlockNumber)
+		neon_log(ERROR, "invalid block range %ld..%ld", (long) blkno, (long) (blkno + nblocks));
+
+	NInfoGetSpcOid(rinfo) = PG_GETARG_OID(0);
+	NInfoGetDbOid(rinfo) = PG_GETARG_OID(1);
+	NInfoGetRelNumber(rinfo) = PG_GETARG_OID(2);
+	if (NInfoGetRelNumber(rinfo) == InvalidRelFileNumber)
+		neon_log(ERROR, "invalid relfilenode");
+
+	reln = smgropen(rinfo, INVALID_PROC_NUMBER);
+	if (!smgrexists(reln, forknum))
+		PG_RETURN_INT32(0);
+	relsize = smgrnblocks(reln, forknum);
+
+	for (int64 i = blkno; i < blkno + nblocks && i < relsize; i++)
+	{
+		Buffer		buf;
+
+		CHECK_FOR_INTERRUPTS();
+#if PG_MAJORVERSION_NUM < 15
+		buf = ReadBufferWithoutRelcache(rinfo, forknum, (BlockNumber) i,
+										RBM_NORMAL, NULL);
+#else
+		buf = ReadBufferWithoutRelcache(rinfo, forknum, (BlockNumber) i,
+										RBM_NORMAL, NULL, true);
+#endif
+		ReleaseBuffer(buf);
+		read += 1;
+	}
+	PG_RETURN_INT32(read);
+}
+
+/*
+ * Function returning the cumulative statistics of all relations, as the raw
+ * contents of their shared statistics entries. They can only be restored into
+ * a server of the same version. Not supported before PostgreSQL 15, where the
+ * statistics are not in shared memory.
+ */
+typedef struct
+{
+	Oid			reldatabase;
+	Oid			relid;
+	PgStat_StatTabEntry stats;
+} RelationStatsRec;
+
+typedef struct
+{
+	TupleDesc	tupdesc;
+	RelationStatsRec *record;
+} RelationStatsContext;
+
+#define NUM_RELATION_STATS_ELEM	3
+
+Datum
+get_relation_stats(PG_FUNCTION_ARGS)
+{
+	FuncCallContext *funcctx;
+	RelationStatsContext *fctx;
+
+	if (SRF_IS_FIRSTCALL())
+	{
+		MemoryContext oldcontext;
+		TupleDesc	tupledesc;
+		uint32		n = 0;
+
+		funcctx = SRF_FIRSTCALL_INIT();
+		oldcontext = MemoryContextSwitchTo(funcctx->multi_call_memory_ctx);
+
+		fctx = (RelationStatsContext *) palloc(sizeof(RelationStatsContext));
+
+		tupledesc = CreateTemplateTupleDesc(NUM_RELATION_STATS_ELEM);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 1, "reldatabase",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 2, "relid",
+						   OIDOID, -1, 0);
+		TupleDescInitEntry(tupledesc, (AttrNumber) 3, "stats",
+						   BYTEAOID, -1, 0);
+		fctx->tupdesc = BlessTupleDesc(tupledesc);
+		fctx->record = NULL;
+
+#if PG_MAJORVERSION_NUM >= 15
+		{
+			dshash_seq_status hstat;
+			PgStatShared_HashEntry *p;
+			uint32		max_records = 64;
+
+			fctx->record = (RelationStatsRec *)
+				palloc(sizeof(RelationStatsRec) * max_records);
+
+			dshash_seq_init(&hstat, pgStatLocal.shared_hash, false);
+			while ((p = dshash_seq_next(&hstat)) != NULL)
+			{
+				PgStatShared_Relation *shared;
+
+				if (p->dropped || p->key.kind != PGSTAT_KIND_RELATION)
+					continue;
+
+				if (n == max_records)
+				{
+					max_records *= 2;
+					fctx->record = (RelationStatsRec *)
+						repalloc_huge(fctx->record, sizeof(RelationStatsRec) * max_records);
+				}
+
+				shared = (PgStatShared_Relation *) dsa_get_address(pgStatLocal.dsa, p->body);
+				LWLockAcquire(&shared->header.lock, LW_SHARED);
+				fctx->record[n].reldatabase = p->key.dboid;
+				fctx->record[n].relid = p->key.objoid;
+				memcpy(&fctx->record[n].stats, &shared->stats, sizeof(PgStat_StatTabEntry));
+				LWLockRelease(&shared->header.lock);
+				n += 1;
+			}
+			dshash_seq_term(&hstat);
+		}
+#endif
+
+		funcctx->max_calls = n;
+		funcctx->user_fctx = fctx;
+
+		MemoryContextSwitchTo(oldcontext);
+	}
+
+	funcctx = SRF_PERCALL_SETUP();
+	fctx = funcctx->user_fctx;
+
+	if (funcctx->call_cntr < funcctx->max_calls)
+	{
+		RelationStatsRec *rec = &fctx->record[funcctx->call_cntr];
+		Datum		values[NUM_RELATION_STATS_ELEM];
+		bool		nulls[NUM_RELATION_STATS_ELEM] = {false, false, false};
+		bytea	   *stats = (bytea *) palloc(VARHDRSZ + sizeof(PgStat_StatTabEntry));
+		HeapTuple	tuple;
+
+		SET_VARSIZE(stats, VARHDRSZ + sizeof(PgStat_StatTabEntry));
+		memcpy(VARDATA(stats), &rec->stats, sizeof(PgStat_StatTabEntry));
+
+		values[0] = ObjectIdGetDatum(rec->reldatabase);
+		values[1] = ObjectIdGetDatum(rec->relid);
+		values[2] = PointerGetDatum(stats);
+
+		tuple = heap_form_tuple(fctx->tupdesc, values, nulls);
+		SRF_RETURN_NEXT(funcctx, HeapTupleGetDatum(tuple));
+	}
+	else
+		SRF_RETURN_DONE(funcctx);
+}
+
+/*
+ * Restore the cumulative statistics of a relation, as returned by
+ * get_relation_stats(). The caller checks that the relation still exists:
+ * it may be in another database.
+ */
+Datum
+restore_relation_stats(PG_FUNCTION_ARGS)
+{
+#if PG_MAJORVERSION_NUM >= 15
+	Oid			dboid = PG_GETARG_OID(0);
+	Oid			relid = PG_GETARG_OID(1);
+	bytea	   *stats = PG_GETARG_BYTEA_PP(2);
+	PgStat_EntryRef *entry_ref;
+	PgStatShared_Relation *shared;
+
+	if (VARSIZE_ANY_EXHDR(stats) != sizeof(PgStat_StatTabEntry))
+		neon_log(ERROR, "relation statistics of size %zu, expected %zu",
+				 (size_t) VARSIZE_ANY_EXHDR(stats), sizeof(PgStat_StatTabEntry));
+	if (!OidIsValid(relid))
+		neon_log(ERROR, "invalid relation oid");
+
+	entry_ref = pgstat_get_entry_ref_locked(PGSTAT_KIND_RELATION, dboid, relid, false);
+	shared = (PgStatShared_Relation *) entry_ref->shared_stats;
+	memcpy(&shared->stats, VARDATA_ANY(stats), sizeof(PgStat_StatTabEntry));
+	pgstat_unlock_entry(entry_ref);
+
+	PG_RETURN_VOID();
+#else
+	neon_log(ERROR, "restoring relation statistics requires PostgreSQL 15 or later");
+	PG_RETURN_VOID();
+#endif
+}
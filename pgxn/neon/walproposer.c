@@ -844,7 +844,12 @@ HandleElectedProposer(WalProposer *wp)
 		wp_log(FATAL, "failed to download WAL for logical replicaiton");
 	}
 
-	if (wp->truncateLsn == wp->propEpochStartLsn && wp->config->syncSafekeepers)
+	/*
+	 * When fencing, go on even if the safekeepers are synced already, so that
+	 * our term gets into their term history.
+	 */
+	if (wp->truncateLsn == wp->propEpochStartLsn && wp->config->syncSafekeepers &&
+		!wp->config->syncSafekeepersFence)
 	{
 		/* Sync is not needed: just exit */
 		wp->api.finish_sync_safekeepers(wp, wp->propEpochStartLsn);
@@ -1029,6 +1034,24 @@ DetermineEpochStartLsn(WalProposer *wp)
 	walprop_shared = wp->api.get_shmem_state(wp);
 	if (!wp->config->syncSafekeepers)
 	{
+		term_t		mine = pg_atomic_read_u64(&walprop_shared->mineLastElectedTerm);
+
+		/*
+		 * If we were elected before, and another compute has been elected
+		 * since and established its term in the log, e.g. a replica promoted
+		 * in place, it has taken over the timeline: don't compete with it,
+		 * even if its WAL starts right where ours ends so that the basebackup
+		 * check below passes. A plain term bump doesn't establish a term, so
+		 * it doesn't count.
+		 */
+		if (mine != 0 && dth->n_entries >= 1 && dth->entries[dth->n_entries - 1].term > mine)
+		{
+			disable_core_dump();
+			wp_log(PANIC,
+				   "term " UINT64_FORMAT " of another compute was established after our term " UINT64_FORMAT ", it has taken over the timeline",
+				   dth->entries[dth->n_entries - 1].term, mine);
+		}
+
 		/*
 		 * Basebackup LSN always points to the beginning of the record (not
 		 * the page), as StartupXLOG most probably wants it this way.
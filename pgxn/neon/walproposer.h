@@ -638,6 +638,14 @@ typedef struct WalProposerConfig
 	 */
 	bool		syncSafekeepers;
 
+	/*
+	 * In sync mode, establish the new term on the safekeepers even if they
+	 * are synced already, so that a walproposer elected before knows that it
+	 * has been fenced and doesn't get elected again. Used when a replica is
+	 * promoted.
+	 */
+	bool		syncSafekeepersFence;
+
 	/* Will be passed to safekeepers in greet request. */
 	uint64		systemId;
 
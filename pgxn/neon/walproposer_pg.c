@@ -11,6 +11,7 @@
 #include <signal.h>
 #include <unistd.h>
 #include <sys/stat.h>
+#include "access/timeline.h"
 #include "access/xact.h"
 #include "access/xlog.h"
 #include "access/xlogdefs.h"
@@ -121,6 +122,8 @@ init_walprop_config(bool syncSafekeepers)
 	walprop_config.safekeeper_connection_timeout = wal_acceptor_connection_timeout;
 	walprop_config.wal_segment_size = wal_segment_size;
 	walprop_config.syncSafekeepers = syncSafekeepers;
+	walprop_config.syncSafekeepersFence = syncSafekeepers &&
+		getenv("NEON_SYNC_SAFEKEEPERS_FENCE") != NULL;
 	if (!syncSafekeepers)
 		walprop_config.systemId = GetSystemIdentifier();
 	else
@@ -717,7 +720,14 @@ walprop_pg_init_bgworker(void)
 	/* FIXME pass proper tli to WalProposerInit ? */
 	GetXLogReplayRecPtr(&tli);
 #else
-	GetXLogReplayRecPtr(&ThisTimeLineID);
+
+	/*
+	 * Recovery is over by now, and the first RecoveryInProgress() call sets
+	 * ThisTimeLineID to the timeline WAL is inserted on. That's not the
+	 * timeline of the last replayed record if this is a promoted standby.
+	 */
+	if (RecoveryInProgress())
+		GetXLogReplayRecPtr(&ThisTimeLineID);
 #endif
 }
 
@@ -741,6 +751,14 @@ TimeLineID
 walprop_pg_get_timeline_id(void)
 {
 #if PG_VERSION_NUM >= 150000
+
+	/*
+	 * Neon doesn't switch timelines, except when a standby is promoted in
+	 * place: then the WAL after the switch point is on the new timeline.
+	 * There's no shared memory to look at in --sync-safekeepers mode.
+	 */
+	if (!walprop_config.syncSafekeepers && !RecoveryInProgress())
+		return GetWALInsertionTimeLine();
 	/* FIXME don't use hardcoded timeline id */
 	return 1;
 #else
@@ -1972,6 +1990,23 @@ walprop_pg_process_safekeeper_feedback(WalProposer *wp, Safekeeper *sk)
 static XLogRecPtr
 walprop_pg_get_redo_start_lsn(WalProposer *wp)
 {
+	TimeLineID	tli = walprop_pg_get_timeline_id();
+
+	/*
+	 * A standby promoted in place didn't start from a basebackup: the WAL it
+	 * writes starts where it switched from the timeline it was replaying.
+	 */
+	if (tli > 1)
+	{
+		XLogRecPtr	switchpoint = tliSwitchPoint(tli - 1, readTimeLineHistory(tli), NULL);
+
+		/* Point to the first record, like the basebackup LSN does */
+		if (XLogSegmentOffset(switchpoint, wal_segment_size) == 0)
+			switchpoint += SizeOfXLogLongPHD;
+		else if (switchpoint % XLOG_BLCKSZ == 0)
+			switchpoint += SizeOfXLogShortPHD;
+		return switchpoint;
+	}
 	return GetRedoStartLsn();
 }
 
@@ -184,9 +184,11 @@ NeonOnDemandXLogReaderRoutines(XLogReaderRoutine *xlr)
 {
 	/*
 	 * If safekeepers are not configured, assume we don't need neon_walreader,
-	 * i.e. running neon fork locally.
+	 * i.e. running neon fork locally. A standby has them configured to be
+	 * promoted, but there's no walproposer until then, and the WAL it streams
+	 * is local.
 	 */
-	if (wal_acceptors_list[0] == '\0')
+	if (wal_acceptors_list[0] == '\0' || RecoveryInProgress())
 		return;
 
 	if (!wal_reader)
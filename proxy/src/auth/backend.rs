@@ -1,4 +1,5 @@
 mod classic;
+pub(crate) mod client_cert;
 mod hacks;
 pub mod jwt;
 pub mod local;
@@ -19,7 +20,7 @@
 use crate::auth::{validate_password_and_exchange, AuthError};
 use crate::cache::Cached;
 use crate::console::errors::GetAuthInfoError;
-use crate::console::provider::{CachedRoleSecret, ConsoleBackend};
+use crate::console::provider::{CachedClientCertAuth, CachedRoleSecret, ConsoleBackend};
 use crate::console::{AuthSecret, NodeInfo};
 use crate::context::RequestMonitoring;
 use crate::intern::EndpointIdInt;
@@ -80,6 +81,9 @@ pub(crate) trait TestBackend: Send + Sync + 'static {
     fn get_allowed_ips_and_secret(
         &self,
     ) -> Result<(CachedAllowedIps, Option<CachedRoleSecret>), console::errors::GetAuthInfoError>;
+    fn get_client_cert_auth(
+        &self,
+    ) -> Result<console::provider::CachedClientCertAuth, console::errors::GetAuthInfoError>;
 }
 
 impl std::fmt::Display for Backend<'_, (), ()> {
@@ -89,6 +93,7 @@ fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 ConsoleBackend::Console(endpoint) => {
                     fmt.debug_tuple("Console").field(&endpoint.url()).finish()
                 }
+                ConsoleBackend::File(api) => fmt.debug_tuple("File").field(&api.path()).finish(),
                 #[cfg(any(test, feature = "testing"))]
                 ConsoleBackend::Postgres(endpoint) => {
                     fmt.debug_tuple("Postgres").field(&endpoint.url()).finish()
@@ -165,9 +170,21 @@ pub(crate) fn endpoint_cache_key(&self) -> EndpointCacheKey {
 pub(crate) enum ComputeCredentialKeys {
     Password(Vec<u8>),
     AuthKeys(AuthKeys),
+    /// Keys of the current password of a role whose password is being rotated.
+    /// Only the given role, see [`previous_password_role`], still has that password.
+    PreviousAuthKeys(AuthKeys, RoleName),
     None,
 }
 
+/// Must match `PREVIOUS_PASSWORD_ROLE_SUFFIX` in compute_api.
+const PREVIOUS_PASSWORD_ROLE_SUFFIX: &str = "/previous";
+
+/// The role compute_ctl creates to keep the current password of `user` while it's
+/// being rotated. Sessions of that role run as `user`.
+pub(crate) fn previous_password_role(user: &RoleName) -> RoleName {
+    format!("{user}{PREVIOUS_PASSWORD_ROLE_SUFFIX}").into()
+}
+
 impl TryFrom<ComputeUserInfoMaybeEndpoint> for ComputeUserInfo {
     // user name
     type Error = ComputeUserInfoNoEndpoint;
@@ -298,7 +315,9 @@ async fn auth_quirks(
             ctx.set_endpoint_id(res.info.endpoint.clone());
             let password = match res.keys {
                 ComputeCredentialKeys::Password(p) => p,
-                ComputeCredentialKeys::AuthKeys(_) | ComputeCredentialKeys::None => {
+                ComputeCredentialKeys::AuthKeys(_)
+                | ComputeCredentialKeys::PreviousAuthKeys(..)
+                | ComputeCredentialKeys::None => {
                     unreachable!("password hack should return a password")
                 }
             };
@@ -320,6 +339,22 @@ async fn auth_quirks(
     if !endpoint_rate_limiter.check(info.endpoint.clone().into(), 1) {
         return Err(AuthError::too_many_connections());
     }
+
+    if let Some(cert_auth) = &*api.get_client_cert_auth(ctx, &info).await? {
+        let certs = client.get_ref().client_certificates();
+        if let Some(identity) = cert_auth.authenticate(certs, &info.user)? {
+            info!(identity = &*identity.name, "verified client certificate");
+            if identity.skip_password {
+                ctx.set_auth_method(crate::context::AuthMethod::ClientCertificate);
+                client.write_message_noflush(&pq_proto::BeMessage::AuthenticationOk)?;
+                return Ok(ComputeCredentials {
+                    info,
+                    keys: ComputeCredentialKeys::None,
+                });
+            }
+        }
+    }
+
     let cached_secret = match maybe_secret {
         Some(secret) => secret,
         None => api.get_role_secret(ctx, &info).await?,
@@ -377,7 +412,8 @@ async fn authenticate_with_secret(
         let ep = EndpointIdInt::from(&info.endpoint);
 
         let auth_outcome =
-            validate_password_and_exchange(&config.thread_pool, ep, &password, secret).await?;
+            validate_password_and_exchange(&config.thread_pool, ep, &info.user, &password, secret)
+                .await?;
         let keys = match auth_outcome {
             crate::sasl::Outcome::Success(key) => key,
             crate::sasl::Outcome::Failure(reason) => {
@@ -485,6 +521,17 @@ pub(crate) async fn get_allowed_ips_and_secret(
             Self::Local(_) => Ok((Cached::new_uncached(Arc::new(vec![])), None)),
         }
     }
+
+    pub(crate) async fn get_client_cert_auth(
+        &self,
+        ctx: &RequestMonitoring,
+    ) -> Result<CachedClientCertAuth, GetAuthInfoError> {
+        match self {
+            Self::Console(api, user_info) => api.get_client_cert_auth(ctx, user_info).await,
+            Self::Web(_, ()) => Ok(Cached::new_uncached(None)),
+            Self::Local(_) => Ok(Cached::new_uncached(None)),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -552,7 +599,7 @@ mod tests {
         config::AuthenticationConfig,
         console::{
             self,
-            provider::{self, CachedAllowedIps, CachedRoleSecret},
+            provider::{self, CachedAllowedIps, CachedClientCertAuth, CachedRoleSecret},
             CachedNodeInfo,
         },
         context::RequestMonitoring,
@@ -590,6 +637,14 @@ async fn get_allowed_ips_and_secret(
             ))
         }
 
+        async fn get_client_cert_auth(
+            &self,
+            _ctx: &RequestMonitoring,
+            _user_info: &super::ComputeUserInfo,
+        ) -> Result<CachedClientCertAuth, console::errors::GetAuthInfoError> {
+            Ok(CachedClientCertAuth::new_uncached(None))
+        }
+
         async fn wake_compute(
             &self,
             _ctx: &RequestMonitoring,
@@ -1,7 +1,6 @@
 use super::{ComputeCredentials, ComputeUserInfo};
 use crate::{
-    auth::{self, backend::ComputeCredentialKeys, AuthFlow},
-    compute,
+    auth::{self, AuthFlow},
     config::AuthenticationConfig,
     console::AuthSecret,
     context::RequestMonitoring,
@@ -19,7 +18,7 @@ pub(super) async fn authenticate(
     secret: AuthSecret,
 ) -> auth::Result<ComputeCredentials> {
     let flow = AuthFlow::new(client);
-    let scram_keys = match secret {
+    let keys = match secret {
         #[cfg(any(test, feature = "testing"))]
         AuthSecret::Md5(_) => {
             info!("auth endpoint chooses MD5");
@@ -48,7 +47,7 @@ pub(super) async fn authenticate(
                 auth::AuthError::user_timeout(e)
             })??;
 
-            let client_key = match auth_outcome {
+            let key = match auth_outcome {
                 sasl::Outcome::Success(key) => key,
                 sasl::Outcome::Failure(reason) => {
                     info!("auth backend failed with an error: {reason}");
@@ -56,17 +55,9 @@ pub(super) async fn authenticate(
                 }
             };
 
-            compute::ScramKeys {
-                client_key: client_key.as_bytes(),
-                server_key: secret.server_key.as_bytes(),
-            }
+            auth::scram_compute_keys(&secret, key, &creds.user)
         }
     };
 
-    Ok(ComputeCredentials {
-        info: creds,
-        keys: ComputeCredentialKeys::AuthKeys(tokio_postgres::config::AuthKeys::ScramSha256(
-            scram_keys,
-        )),
-    })
+    Ok(ComputeCredentials { info: creds, keys })
 }
@@ -0,0 +1,446 @@
+//! Client certificate (mTLS) authentication.
+//!
+//! Client certificates are requested during the TLS handshake, long before we
+//! know which endpoint the client wants to connect to. Hence, the handshake only
+//! checks that the client owns the private key of the presented certificate.
+//! The certificate chain is verified later against the CA bundle which the console
+//! returns for the endpoint, see [`ClientCertAuth::authenticate`].
+
+use std::sync::Arc;
+
+use rustls::{
+    client::danger::HandshakeSignatureValid,
+    crypto::WebPkiSupportedAlgorithms,
+    pki_types::{CertificateDer, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme,
+};
+use thiserror::Error;
+use x509_parser::extensions::GeneralName;
+
+use crate::{
+    console::messages::{CertIdentityKind, CertRoleMapping, ClientCertAuthSettings},
+    error::{ReportableError, UserFacingError},
+    RoleName,
+};
+
+/// Accepts any client certificate chain during the handshake,
+/// but still verifies the handshake signature made with the certificate's key.
+#[derive(Debug)]
+pub(crate) struct DeferredClientCertVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl DeferredClientCertVerifier {
+    pub(crate) fn new() -> Self {
+        Self {
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ClientCertVerifier for DeferredClientCertVerifier {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        // Most endpoints don't use client certificates at all.
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        // We can't tell which CAs are acceptable before we know the endpoint.
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        // The chain is verified once the endpoint is known.
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum ClientCertError {
+    #[error("client certificate is required for this endpoint")]
+    Missing,
+
+    #[error("client certificate is not trusted: {0}")]
+    Untrusted(rustls::Error),
+
+    #[error("client certificate is malformed")]
+    Malformed,
+
+    #[error("client certificate does not allow to log in as role '{0}'")]
+    RoleMismatch(RoleName),
+}
+
+impl UserFacingError for ClientCertError {
+    fn to_string_client(&self) -> String {
+        match self {
+            // Don't tell the client why exactly we don't trust the certificate.
+            ClientCertError::Untrusted(_) => "client certificate is not trusted".to_owned(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl ReportableError for ClientCertError {
+    fn get_error_kind(&self) -> crate::error::ErrorKind {
+        crate::error::ErrorKind::User
+    }
+}
+
+/// Parsed client certificate settings of an endpoint.
+pub(crate) struct ClientCertAuth {
+    verifier: Arc<dyn ClientCertVerifier>,
+    required: bool,
+    role_mappings: Vec<CertRoleMapping>,
+}
+
+/// Identity of a client whose certificate has been verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientCertIdentity {
+    /// The certificate identity which matched the role.
+    pub(crate) name: Box<str>,
+    /// Whether SCRAM can be skipped for the role.
+    pub(crate) skip_password: bool,
+}
+
+impl ClientCertAuth {
+    pub(crate) fn new(settings: ClientCertAuthSettings) -> anyhow::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut settings.ca_bundle.as_bytes()) {
+            roots.add(cert?)?;
+        }
+        anyhow::ensure!(!roots.is_empty(), "CA bundle has no certificates");
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots)).build()?;
+
+        Ok(Self {
+            verifier,
+            required: settings.required,
+            role_mappings: settings.role_mappings,
+        })
+    }
+
+    /// Verify the client certificate chain and check that it allows to log in as `role`.
+    ///
+    /// Returns `None` if the client didn't present a certificate and the endpoint doesn't require one.
+    pub(crate) fn authenticate(
+        &self,
+        certs: Option<&[CertificateDer<'_>]>,
+        role: &RoleName,
+    ) -> Result<Option<ClientCertIdentity>, ClientCertError> {
+        let Some((end_entity, intermediates)) = certs.and_then(|c| c.split_first()) else {
+            return if self.required {
+                Err(ClientCertError::Missing)
+            } else {
+                Ok(None)
+            };
+        };
+
+        self.verifier
+            .verify_client_cert(end_entity, intermediates, UnixTime::now())
+            .map_err(ClientCertError::Untrusted)?;
+
+        let (_, cert) = x509_parser::parse_x509_certificate(end_entity)
+            .map_err(|_| ClientCertError::Malformed)?;
+        let identities = CertIdentities::new(&cert)?;
+
+        if self.role_mappings.is_empty() {
+            // Same as `clientcert=verify-full` without a user name map in postgres.
+            return match identities.find(CertIdentityKind::CommonName, role) {
+                Some(name) => Ok(Some(ClientCertIdentity {
+                    name: name.into(),
+                    skip_password: false,
+                })),
+                None => Err(ClientCertError::RoleMismatch(role.clone())),
+            };
+        }
+
+        self.role_mappings
+            .iter()
+            .filter(|mapping| mapping.role == *role)
+            .find_map(|mapping| {
+                let name = identities.find(mapping.identity, &mapping.value)?;
+                Some(ClientCertIdentity {
+                    name: name.into(),
+                    skip_password: mapping.skip_password,
+                })
+            })
+            .map(Some)
+            .ok_or_else(|| ClientCertError::RoleMismatch(role.clone()))
+    }
+}
+
+/// All identities a certificate can be mapped by.
+struct CertIdentities {
+    subject: String,
+    common_names: Vec<String>,
+    alt_names: Vec<(CertIdentityKind, String)>,
+}
+
+impl CertIdentities {
+    fn new(cert: &x509_parser::certificate::X509Certificate<'_>) -> Result<Self, ClientCertError> {
+        let common_names = cert
+            .subject()
+            .iter_common_name()
+            .map(|cn| cn.as_str().map(str::to_owned))
+            .collect::<Result<_, _>>()
+            .map_err(|_| ClientCertError::Malformed)?;
+
+        let mut alt_names = vec![];
+        let san = cert
+            .subject_alternative_name()
+            .map_err(|_| ClientCertError::Malformed)?;
+        for name in san.iter().flat_map(|san| &san.value.general_names) {
+            match name {
+                GeneralName::DNSName(dns) => {
+                    alt_names.push((CertIdentityKind::DnsSan, dns.to_string()));
+                }
+                GeneralName::RFC822Name(email) => {
+                    alt_names.push((CertIdentityKind::EmailSan, email.to_string()));
+                }
+                GeneralName::URI(uri) => {
+                    alt_names.push((CertIdentityKind::UriSan, uri.to_string()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            common_names,
+            alt_names,
+        })
+    }
+
+    fn find(&self, kind: CertIdentityKind, value: &str) -> Option<&str> {
+        match kind {
+            CertIdentityKind::Subject => (self.subject == value).then_some(&*self.subject),
+            CertIdentityKind::CommonName => self
+                .common_names
+                .iter()
+                .find(|cn| *cn == value)
+                .map(|cn| &**cn),
+            kind => self
+                .alt_names
+                .iter()
+                .find(|(k, name)| *k == kind && name == value)
+                .map(|(_, name)| &**name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCa {
+        ca: rcgen::Certificate,
+    }
+
+    impl TestCa {
+        fn new() -> Self {
+            let mut params = rcgen::CertificateParams::default();
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, "test client ca");
+            Self {
+                ca: rcgen::Certificate::from_params(params).unwrap(),
+            }
+        }
+
+        fn pem(&self) -> String {
+            self.ca.serialize_pem().unwrap()
+        }
+
+        fn issue(
+            &self,
+            common_name: &str,
+            alt_names: Vec<rcgen::SanType>,
+        ) -> CertificateDer<'static> {
+            let mut params = rcgen::CertificateParams::default();
+            params.distinguished_name = rcgen::DistinguishedName::new();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, common_name);
+            params.subject_alt_names = alt_names;
+            params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+            let cert = rcgen::Certificate::from_params(params).unwrap();
+            CertificateDer::from(cert.serialize_der_with_signer(&self.ca).unwrap())
+        }
+    }
+
+    fn settings(
+        ca: &TestCa,
+        required: bool,
+        role_mappings: Vec<CertRoleMapping>,
+    ) -> ClientCertAuth {
+        ClientCertAuth::new(ClientCertAuthSettings {
+            ca_bundle: ca.pem().into(),
+            required,
+            role_mappings,
+        })
+        .unwrap()
+    }
+
+    fn mapping(
+        identity: CertIdentityKind,
+        value: &str,
+        role: &str,
+        skip_password: bool,
+    ) -> CertRoleMapping {
+        CertRoleMapping {
+            identity,
+            value: value.into(),
+            role: role.into(),
+            skip_password,
+        }
+    }
+
+    #[test]
+    fn common_name_must_match_role_by_default() {
+        let ca = TestCa::new();
+        let auth = settings(&ca, false, vec![]);
+        let cert = ca.issue("alice", vec![]);
+
+        let identity = auth
+            .authenticate(Some(std::slice::from_ref(&cert)), &"alice".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(&*identity.name, "alice");
+        assert!(!identity.skip_password);
+
+        let err = auth
+            .authenticate(Some(std::slice::from_ref(&cert)), &"bob".into())
+            .unwrap_err();
+        assert!(matches!(err, ClientCertError::RoleMismatch(_)));
+    }
+
+    #[test]
+    fn certificate_from_another_ca_is_rejected() {
+        let ca = TestCa::new();
+        let other_ca = TestCa::new();
+        let auth = settings(&ca, false, vec![]);
+        let cert = other_ca.issue("alice", vec![]);
+
+        let err = auth
+            .authenticate(Some(std::slice::from_ref(&cert)), &"alice".into())
+            .unwrap_err();
+        assert!(matches!(err, ClientCertError::Untrusted(_)));
+    }
+
+    #[test]
+    fn missing_certificate() {
+        let ca = TestCa::new();
+
+        let optional = settings(&ca, false, vec![]);
+        assert_eq!(optional.authenticate(None, &"alice".into()).unwrap(), None);
+        assert_eq!(
+            optional
+                .authenticate(Some(&[][..]), &"alice".into())
+                .unwrap(),
+            None
+        );
+
+        let required = settings(&ca, true, vec![]);
+        let err = required.authenticate(None, &"alice".into()).unwrap_err();
+        assert!(matches!(err, ClientCertError::Missing));
+    }
+
+    #[test]
+    fn role_mappings() {
+        let ca = TestCa::new();
+        let auth = settings(
+            &ca,
+            true,
+            vec![
+                mapping(CertIdentityKind::DnsSan, "app.acme.com", "app", true),
+                mapping(CertIdentityKind::EmailSan, "alice@acme.com", "alice", false),
+                mapping(CertIdentityKind::Subject, "CN=reporting", "readonly", true),
+            ],
+        );
+
+        let app = ca.issue(
+            "some service",
+            vec![rcgen::SanType::DnsName("app.acme.com".into())],
+        );
+        let identity = auth
+            .authenticate(Some(std::slice::from_ref(&app)), &"app".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(&*identity.name, "app.acme.com");
+        assert!(identity.skip_password);
+        // common name is not used when mappings are configured
+        let err = auth
+            .authenticate(Some(std::slice::from_ref(&app)), &"some service".into())
+            .unwrap_err();
+        assert!(matches!(err, ClientCertError::RoleMismatch(_)));
+
+        let alice = ca.issue(
+            "alice",
+            vec![rcgen::SanType::Rfc822Name("alice@acme.com".into())],
+        );
+        let identity = auth
+            .authenticate(Some(std::slice::from_ref(&alice)), &"alice".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(&*identity.name, "alice@acme.com");
+        assert!(!identity.skip_password);
+        let err = auth
+            .authenticate(Some(std::slice::from_ref(&alice)), &"app".into())
+            .unwrap_err();
+        assert!(matches!(err, ClientCertError::RoleMismatch(_)));
+
+        let reporting = ca.issue("reporting", vec![]);
+        let identity = auth
+            .authenticate(Some(std::slice::from_ref(&reporting)), &"readonly".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(&*identity.name, "CN=reporting");
+        assert!(identity.skip_password);
+    }
+
+    #[test]
+    fn bad_ca_bundle() {
+        let res = ClientCertAuth::new(ClientCertAuthSettings {
+            ca_bundle: "not a certificate".into(),
+            required: false,
+            role_mappings: vec![],
+        });
+        assert!(res.is_err());
+    }
+}
@@ -36,6 +36,7 @@ pub(crate) async fn authenticate_cleartext(
         .begin(auth::CleartextPassword {
             secret,
             endpoint: ep,
+            user: info.user.clone(),
             pool: config.thread_pool.clone(),
         })
         .await?;
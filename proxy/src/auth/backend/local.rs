@@ -37,6 +37,7 @@ pub fn new(postgres_addr: SocketAddr) -> Self {
                     project_id: ProjectIdTag::get_interner().get_or_intern("local"),
                     branch_id: BranchIdTag::get_interner().get_or_intern("local"),
                     cold_start_info: ColdStartInfo::WarmCached,
+                    audit_log: None,
                 },
                 allow_self_signed_compute: false,
             },
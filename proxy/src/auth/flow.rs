@@ -1,14 +1,19 @@
 //! Main authentication flow.
 
-use super::{backend::ComputeCredentialKeys, AuthErrorImpl, PasswordHackPayload};
+use super::{
+    backend::{previous_password_role, ComputeCredentialKeys},
+    AuthErrorImpl, PasswordHackPayload,
+};
 use crate::{
     config::TlsServerEndPoint,
     console::AuthSecret,
     context::RequestMonitoring,
     intern::EndpointIdInt,
+    metrics::{Metrics, RotatedSecret},
     sasl,
     scram::{self, threadpool::ThreadPool},
     stream::{PqStream, Stream},
+    RoleName,
 };
 use postgres_protocol::authentication::sasl::{SCRAM_SHA_256, SCRAM_SHA_256_PLUS};
 use pq_proto::{BeAuthenticationSaslMessage, BeMessage, BeMessage as Be};
@@ -61,6 +66,7 @@ fn first_message(&self, _channel_binding: bool) -> BeMessage<'_> {
 pub(crate) struct CleartextPassword {
     pub(crate) pool: Arc<ThreadPool>,
     pub(crate) endpoint: EndpointIdInt,
+    pub(crate) user: RoleName,
     pub(crate) secret: AuthSecret,
 }
 
@@ -138,6 +144,7 @@ pub(crate) async fn authenticate(self) -> super::Result<sasl::Outcome<ComputeCre
         let outcome = validate_password_and_exchange(
             &self.state.pool,
             self.state.endpoint,
+            &self.state.user,
             password,
             self.state.secret,
         )
@@ -154,7 +161,7 @@ pub(crate) async fn authenticate(self) -> super::Result<sasl::Outcome<ComputeCre
 /// Stream wrapper for handling [SCRAM](crate::scram) auth.
 impl<S: AsyncRead + AsyncWrite + Unpin> AuthFlow<'_, S, Scram<'_>> {
     /// Perform user authentication. Raise an error in case authentication failed.
-    pub(crate) async fn authenticate(self) -> super::Result<sasl::Outcome<scram::ScramKey>> {
+    pub(crate) async fn authenticate(self) -> super::Result<sasl::Outcome<scram::VerifiedKey>> {
         let Scram(secret, ctx) = self.state;
 
         // pause the timer while we communicate with the client
@@ -196,6 +203,7 @@ pub(crate) async fn authenticate(self) -> super::Result<sasl::Outcome<scram::Scr
 pub(crate) async fn validate_password_and_exchange(
     pool: &ThreadPool,
     endpoint: EndpointIdInt,
+    user: &RoleName,
     password: &[u8],
     secret: AuthSecret,
 ) -> super::Result<sasl::Outcome<ComputeCredentialKeys>> {
@@ -211,19 +219,45 @@ pub(crate) async fn validate_password_and_exchange(
         AuthSecret::Scram(scram_secret) => {
             let outcome = crate::scram::exchange(pool, endpoint, &scram_secret, password).await?;
 
-            let client_key = match outcome {
-                sasl::Outcome::Success(client_key) => client_key,
+            let key = match outcome {
+                sasl::Outcome::Success(key) => key,
                 sasl::Outcome::Failure(reason) => return Ok(sasl::Outcome::Failure(reason)),
             };
 
-            let keys = crate::compute::ScramKeys {
-                client_key: client_key.as_bytes(),
-                server_key: scram_secret.server_key.as_bytes(),
-            };
-
-            Ok(sasl::Outcome::Success(ComputeCredentialKeys::AuthKeys(
-                tokio_postgres::config::AuthKeys::ScramSha256(keys),
+            Ok(sasl::Outcome::Success(scram_compute_keys(
+                &scram_secret,
+                key,
+                user,
             )))
         }
     }
 }
+
+/// The keys to authenticate to the compute with, for a client whose key matches
+/// one of the secrets of the role.
+pub(crate) fn scram_compute_keys(
+    secret: &scram::ServerSecret,
+    key: scram::VerifiedKey,
+    user: &RoleName,
+) -> ComputeCredentialKeys {
+    let keys = tokio_postgres::config::AuthKeys::ScramSha256(crate::compute::ScramKeys {
+        client_key: key.client_key.as_bytes(),
+        server_key: secret.server_key(key.version).as_bytes(),
+    });
+    if secret.next.is_none() {
+        return ComputeCredentialKeys::AuthKeys(keys);
+    }
+
+    let rotation = &Metrics::get().proxy.password_rotation_auth_total;
+    match key.version {
+        // The role itself has the next password already
+        scram::SecretVersion::Next => {
+            rotation.inc(RotatedSecret::Next);
+            ComputeCredentialKeys::AuthKeys(keys)
+        }
+        scram::SecretVersion::Current => {
+            rotation.inc(RotatedSecret::Current);
+            ComputeCredentialKeys::PreviousAuthKeys(keys, previous_password_role(user))
+        }
+    }
+}
@@ -92,6 +92,14 @@ struct SqlOverHttpArgs {
 
     #[clap(long, default_value_t = 16)]
     sql_over_http_cancel_set_shards: usize,
+
+    /// How long a SQL over HTTP session can stay idle before its transaction is rolled back
+    #[clap(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    sql_over_http_session_idle_timeout: tokio::time::Duration,
+
+    /// How many SQL over HTTP sessions can be open per endpoint
+    #[clap(long, default_value_t = 20)]
+    sql_over_http_max_sessions_per_endpoint: usize,
 }
 
 #[tokio::main]
@@ -208,6 +216,8 @@ fn build_config(args: &LocalProxyCliArgs) -> anyhow::Result<&'static ProxyConfig
         },
         cancel_set: CancelSet::new(args.sql_over_http.sql_over_http_cancel_set_shards),
         client_conn_threshold: args.sql_over_http.sql_over_http_client_conn_threshold,
+        session_idle_timeout: args.sql_over_http.sql_over_http_session_idle_timeout,
+        max_sessions_per_endpoint: args.sql_over_http.sql_over_http_max_sessions_per_endpoint,
     };
 
     Ok(Box::leak(Box::new(ProxyConfig {
@@ -18,6 +18,7 @@
 use proxy::config::HttpConfig;
 use proxy::config::ProjectInfoCacheOptions;
 use proxy::console;
+use proxy::context::audit::AuditLogArgs;
 use proxy::context::parquet::ParquetUploadArgs;
 use proxy::http;
 use proxy::http::health_server::AppMetrics;
@@ -62,6 +63,8 @@
 #[derive(Clone, Debug, ValueEnum)]
 enum AuthBackendType {
     Console,
+    /// Endpoints are read from the file given in `--auth-endpoint`.
+    File,
     #[cfg(feature = "testing")]
     Postgres,
     // clap only shows the name, not the alias, in usage text.
@@ -94,7 +97,7 @@ struct ProxyCliArgs {
     /// redirect unauthenticated users to the given uri in case of web auth
     #[clap(short, long, default_value = "http://localhost:3000/psql_session/")]
     uri: String,
-    /// cloud API endpoint for authenticating users
+    /// cloud API endpoint for authenticating users (or path to the endpoints file for the file backend)
     #[clap(
         short,
         long,
@@ -114,6 +117,10 @@ struct ProxyCliArgs {
     /// path to directory with TLS certificates for client postgres connections
     #[clap(long)]
     certs_dir: Option<String>,
+    /// ask postgres clients for TLS certificates, which are verified against
+    /// the CA bundles configured for endpoints in the console
+    #[clap(long, default_value_t = false, value_parser = clap::builder::BoolishValueParser::new(), action = clap::ArgAction::Set)]
+    tls_client_cert_auth: bool,
     /// timeout for the TLS handshake
     #[clap(long, default_value = "15s", value_parser = humantime::parse_duration)]
     handshake_timeout: tokio::time::Duration,
@@ -205,6 +212,8 @@ struct ProxyCliArgs {
     endpoint_cache_config: String,
     #[clap(flatten)]
     parquet_upload: ParquetUploadArgs,
+    #[clap(flatten)]
+    audit_log: AuditLogArgs,
 
     /// interval for backup metric collection
     #[clap(long, default_value = "10m", value_parser = humantime::parse_duration)]
@@ -268,6 +277,14 @@ struct SqlOverHttpArgs {
 
     #[clap(long, default_value_t = 64)]
     sql_over_http_cancel_set_shards: usize,
+
+    /// How long a SQL over HTTP session can stay idle before its transaction is rolled back
+    #[clap(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    sql_over_http_session_idle_timeout: tokio::time::Duration,
+
+    /// How many SQL over HTTP sessions can be open per endpoint
+    #[clap(long, default_value_t = 20)]
+    sql_over_http_max_sessions_per_endpoint: usize,
 }
 
 #[tokio::main]
@@ -451,6 +468,10 @@ async fn main() -> anyhow::Result<()> {
         cancellation_token.clone(),
         args.parquet_upload,
     ));
+    client_tasks.spawn(proxy::context::audit::worker(
+        cancellation_token.clone(),
+        args.audit_log,
+    ));
 
     // maintenance tasks. these never return unless there's an error
     let mut maintenance_tasks = JoinSet::new();
@@ -511,6 +532,19 @@ async fn main() -> anyhow::Result<()> {
                         .instrument(span),
                 );
             }
+        } else if let proxy::console::provider::ConsoleBackend::File(api) = &**api {
+            maintenance_tasks.spawn(api.watch());
+            let cache = api.caches.project_info.clone();
+            // Only needed for cancellation requests from other proxies.
+            if let Some(client) = redis_notifications_client {
+                maintenance_tasks.spawn(notifications::task_main(
+                    client,
+                    cache.clone(),
+                    cancel_map.clone(),
+                    args.region.clone(),
+                ));
+            }
+            maintenance_tasks.spawn(async move { cache.gc_worker().await });
         }
     }
 
@@ -548,6 +582,7 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
             key_path,
             cert_path,
             args.certs_dir.as_ref(),
+            args.tls_client_cert_auth,
         )?),
         (None, None) => None,
         _ => bail!("either both or neither tls-key and tls-cert must be specified"),
@@ -633,6 +668,23 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
             let api = console::provider::ConsoleBackend::Console(api);
             auth::Backend::Console(MaybeOwned::Owned(api), ())
         }
+        AuthBackendType::File => {
+            let project_info_cache_config: ProjectInfoCacheOptions =
+                args.project_info_cache.parse()?;
+            let endpoint_cache_config: config::EndpointCacheConfig =
+                args.endpoint_cache_config.parse()?;
+
+            info!("Using ProjectInfoCache with options={project_info_cache_config:?}");
+            let caches = Box::leak(Box::new(console::caches::ApiCaches::new(
+                args.wake_compute_cache.parse()?,
+                project_info_cache_config,
+                endpoint_cache_config,
+            )));
+
+            let api = console::provider::file::Api::new(args.auth_endpoint.clone().into(), caches)?;
+            let api = console::provider::ConsoleBackend::File(api);
+            auth::Backend::Console(MaybeOwned::Owned(api), ())
+        }
         #[cfg(feature = "testing")]
         AuthBackendType::Postgres => {
             let url = args.auth_endpoint.parse()?;
@@ -679,6 +731,8 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
         },
         cancel_set: CancelSet::new(args.sql_over_http.sql_over_http_cancel_set_shards),
         client_conn_threshold: args.sql_over_http.sql_over_http_client_conn_threshold,
+        session_idle_timeout: args.sql_over_http.sql_over_http_session_idle_timeout,
+        max_sessions_per_endpoint: args.sql_over_http.sql_over_http_max_sessions_per_endpoint,
     };
     let authentication_config = AuthenticationConfig {
         thread_pool,
@@ -14,7 +14,7 @@
 use tracing::{debug, info};
 
 use crate::{
-    auth::IpPattern,
+    auth::{backend::client_cert::ClientCertAuth, IpPattern},
     config::ProjectInfoCacheOptions,
     console::AuthSecret,
     intern::{EndpointIdInt, ProjectIdInt, RoleNameInt},
@@ -27,6 +27,7 @@
 pub(crate) trait ProjectInfoCache {
     fn invalidate_allowed_ips_for_project(&self, project_id: ProjectIdInt);
     fn invalidate_role_secret_for_project(&self, project_id: ProjectIdInt, role_name: RoleNameInt);
+    fn invalidate_client_cert_auth_for_project(&self, project_id: ProjectIdInt);
     async fn decrement_active_listeners(&self);
     async fn increment_active_listeners(&self);
 }
@@ -55,6 +56,7 @@ fn from(value: T) -> Self {
 struct EndpointInfo {
     secret: std::collections::HashMap<RoleNameInt, Entry<Option<AuthSecret>>>,
     allowed_ips: Option<Entry<Arc<Vec<IpPattern>>>>,
+    client_cert_auth: Option<Entry<Option<Arc<ClientCertAuth>>>>,
 }
 
 impl EndpointInfo {
@@ -96,9 +98,27 @@ pub(crate) fn get_allowed_ips(
         }
         None
     }
+    pub(crate) fn get_client_cert_auth(
+        &self,
+        valid_since: Instant,
+        ignore_cache_since: Option<Instant>,
+    ) -> Option<(Option<Arc<ClientCertAuth>>, bool)> {
+        if let Some(client_cert_auth) = &self.client_cert_auth {
+            if valid_since < client_cert_auth.created_at {
+                return Some((
+                    client_cert_auth.value.clone(),
+                    Self::check_ignore_cache(ignore_cache_since, client_cert_auth.created_at),
+                ));
+            }
+        }
+        None
+    }
     pub(crate) fn invalidate_allowed_ips(&mut self) {
         self.allowed_ips = None;
     }
+    pub(crate) fn invalidate_client_cert_auth(&mut self) {
+        self.client_cert_auth = None;
+    }
     pub(crate) fn invalidate_role_secret(&mut self, role_name: RoleNameInt) {
         self.secret.remove(&role_name);
     }
@@ -153,6 +173,22 @@ fn invalidate_role_secret_for_project(&self, project_id: ProjectIdInt, role_name
             }
         }
     }
+    fn invalidate_client_cert_auth_for_project(&self, project_id: ProjectIdInt) {
+        info!(
+            "invalidating client certificate auth for project `{}`",
+            project_id
+        );
+        let endpoints = self
+            .project2ep
+            .get(&project_id)
+            .map(|kv| kv.value().clone())
+            .unwrap_or_default();
+        for endpoint_id in endpoints {
+            if let Some(mut endpoint_info) = self.cache.get_mut(&endpoint_id) {
+                endpoint_info.invalidate_client_cert_auth();
+            }
+        }
+    }
     async fn decrement_active_listeners(&self) {
         let mut listeners_guard = self.active_listeners_lock.lock().await;
         if *listeners_guard == 0 {
@@ -230,6 +266,24 @@ pub(crate) fn get_allowed_ips(
         }
         Some(Cached::new_uncached(value))
     }
+    pub(crate) fn get_client_cert_auth(
+        &self,
+        endpoint_id: &EndpointId,
+    ) -> Option<Cached<&Self, Option<Arc<ClientCertAuth>>>> {
+        let endpoint_id = EndpointIdInt::get(endpoint_id)?;
+        let (valid_since, ignore_cache_since) = self.get_cache_times();
+        let endpoint_info = self.cache.get(&endpoint_id)?;
+        let value = endpoint_info.get_client_cert_auth(valid_since, ignore_cache_since);
+        let (value, ignore_cache) = value?;
+        if !ignore_cache {
+            let cached = Cached {
+                token: Some((self, CachedLookupInfo::new_client_cert_auth(endpoint_id))),
+                value,
+            };
+            return Some(cached);
+        }
+        Some(Cached::new_uncached(value))
+    }
     pub(crate) fn insert_role_secret(
         &self,
         project_id: ProjectIdInt,
@@ -260,6 +314,19 @@ pub(crate) fn insert_allowed_ips(
         self.insert_project2endpoint(project_id, endpoint_id);
         self.cache.entry(endpoint_id).or_default().allowed_ips = Some(allowed_ips.into());
     }
+    pub(crate) fn insert_client_cert_auth(
+        &self,
+        project_id: ProjectIdInt,
+        endpoint_id: EndpointIdInt,
+        client_cert_auth: Option<Arc<ClientCertAuth>>,
+    ) {
+        if self.cache.len() >= self.config.size {
+            // If there are too many entries, wait until the next gc cycle.
+            return;
+        }
+        self.insert_project2endpoint(project_id, endpoint_id);
+        self.cache.entry(endpoint_id).or_default().client_cert_auth = Some(client_cert_auth.into());
+    }
     fn insert_project2endpoint(&self, project_id: ProjectIdInt, endpoint_id: EndpointIdInt) {
         if let Some(mut endpoints) = self.project2ep.get_mut(&project_id) {
             endpoints.insert(endpoint_id);
@@ -338,11 +405,18 @@ pub(self) fn new_allowed_ips(endpoint_id: EndpointIdInt) -> Self {
             lookup_type: LookupType::AllowedIps,
         }
     }
+    pub(self) fn new_client_cert_auth(endpoint_id: EndpointIdInt) -> Self {
+        Self {
+            endpoint_id,
+            lookup_type: LookupType::ClientCertAuth,
+        }
+    }
 }
 
 enum LookupType {
     RoleSecret(RoleNameInt),
     AllowedIps,
+    ClientCertAuth,
 }
 
 impl Cache for ProjectInfoCacheImpl {
@@ -364,6 +438,11 @@ fn invalidate(&self, key: &Self::LookupInfo<SmolStr>) {
                     endpoint_info.invalidate_allowed_ips();
                 }
             }
+            LookupType::ClientCertAuth => {
+                if let Some(mut endpoint_info) = self.cache.get_mut(&key.endpoint_id) {
+                    endpoint_info.invalidate_client_cert_auth();
+                }
+            }
         }
     }
 }
@@ -100,7 +100,7 @@ pub(crate) fn new() -> Self {
         Self::default()
     }
 
-    /// Reuse password or auth keys from the other config.
+    /// Reuse password or auth keys, and the user they are for, from the other config.
     pub(crate) fn reuse_password(&mut self, other: Self) {
         if let Some(password) = other.get_password() {
             self.password(password);
@@ -109,6 +109,11 @@ pub(crate) fn reuse_password(&mut self, other: Self) {
         if let Some(keys) = other.get_auth_keys() {
             self.auth_keys(keys);
         }
+
+        // The keys may be for another role, see `NodeInfo::set_keys`
+        if let Some(user) = other.get_user() {
+            self.user(user);
+        }
     }
 
     pub(crate) fn get_host(&self) -> Result<Host, WakeComputeError> {
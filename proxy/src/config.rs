@@ -1,5 +1,8 @@
 use crate::{
-    auth::{self, backend::AuthRateLimiter},
+    auth::{
+        self,
+        backend::{client_cert::DeferredClientCertVerifier, AuthRateLimiter},
+    },
     console::locks::ApiLocks,
     rate_limiter::{RateBucketInfo, RateLimitAlgorithm, RateLimiterConfig},
     scram::threadpool::ThreadPool,
@@ -56,6 +59,11 @@ pub struct HttpConfig {
     pub pool_options: GlobalConnPoolOptions,
     pub cancel_set: CancelSet,
     pub client_conn_threshold: u64,
+    /// How long a stateful SQL over HTTP session can stay idle before it's rolled back and closed.
+    pub session_idle_timeout: tokio::time::Duration,
+    /// How many stateful SQL over HTTP sessions can be open per endpoint. Sessions hold their
+    /// connection outside of the pool, so `max_conns_per_endpoint` doesn't limit them.
+    pub max_sessions_per_endpoint: usize,
 }
 
 pub struct AuthenticationConfig {
@@ -71,16 +79,38 @@ impl TlsConfig {
     pub fn to_server_config(&self) -> Arc<rustls::ServerConfig> {
         self.config.clone()
     }
+
+    /// Server config for the HTTP endpoints, which never ask for client certificates.
+    pub fn to_http_server_config(&self) -> rustls::ServerConfig {
+        let mut config = server_config_builder()
+            .with_no_client_auth()
+            .with_cert_resolver(self.cert_resolver.clone());
+        // prefer http2, but support http/1.1
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        config
+    }
 }
 
 /// <https://github.com/postgres/postgres/blob/ca481d3c9ab7bf69ff0c8d71ad3951d407f6a33c/src/include/libpq/pqcomm.h#L159>
 pub const PG_ALPN_PROTOCOL: &[u8] = b"postgresql";
 
+fn server_config_builder() -> rustls::ConfigBuilder<rustls::ServerConfig, rustls::WantsVerifier> {
+    // allow TLS 1.2 to be compatible with older client libraries
+    rustls::ServerConfig::builder_with_protocol_versions(&[
+        &rustls::version::TLS13,
+        &rustls::version::TLS12,
+    ])
+}
+
 /// Configure TLS for the main endpoint.
+///
+/// With `client_cert_auth`, clients are asked for a certificate, which is verified
+/// later against the CA bundle of the endpoint they connect to.
 pub fn configure_tls(
     key_path: &str,
     cert_path: &str,
     certs_dir: Option<&String>,
+    client_cert_auth: bool,
 ) -> anyhow::Result<TlsConfig> {
     let mut cert_resolver = CertResolver::new();
 
@@ -111,13 +141,13 @@ pub fn configure_tls(
 
     let cert_resolver = Arc::new(cert_resolver);
 
-    // allow TLS 1.2 to be compatible with older client libraries
-    let mut config = rustls::ServerConfig::builder_with_protocol_versions(&[
-        &rustls::version::TLS13,
-        &rustls::version::TLS12,
-    ])
-    .with_no_client_auth()
-    .with_cert_resolver(cert_resolver.clone());
+    let builder = server_config_builder();
+    let builder = if client_cert_auth {
+        builder.with_client_cert_verifier(Arc::new(DeferredClientCertVerifier::new()))
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut config = builder.with_cert_resolver(cert_resolver.clone());
 
     config.alpn_protocols = vec![PG_ALPN_PROTOCOL.to_vec()];
 
@@ -668,7 +698,7 @@ fn from_str(options: &str) -> Result<Self, Self::Err> {
 
 #[cfg(test)]
 mod tests {
-    use crate::rate_limiter::Aimd;
+    use crate::rate_limiter::{Aimd, GradientConfig, VegasConfig};
 
     use super::*;
 
@@ -762,4 +792,39 @@ fn test_parse_json_lock_options() -> anyhow::Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_json_latency_based_lock_options() -> anyhow::Result<()> {
+        let ConcurrencyLockOptions { limiter, .. } = r#"{"shards":32,"initial_limit":44,"vegas":{"min":5,"max":500,"alpha":3,"beta":6,"probe_interval":10000},"epoch":"10m","timeout":"1s"}"#
+            .parse()?;
+        assert_eq!(
+            limiter.algorithm,
+            RateLimitAlgorithm::Vegas {
+                conf: VegasConfig {
+                    min: 5,
+                    max: 500,
+                    alpha: 3.0,
+                    beta: 6.0,
+                    probe_interval: 10000,
+                }
+            },
+        );
+
+        let ConcurrencyLockOptions { limiter, .. } = r#"{"shards":32,"initial_limit":44,"gradient":{"min":5,"max":500,"tolerance":1.5,"smoothing":0.2,"long_window":600},"epoch":"10m","timeout":"1s"}"#
+            .parse()?;
+        assert_eq!(
+            limiter.algorithm,
+            RateLimitAlgorithm::Gradient {
+                conf: GradientConfig {
+                    min: 5,
+                    max: 500,
+                    tolerance: 1.5,
+                    smoothing: 0.2,
+                    long_window: 600,
+                }
+            },
+        );
+
+        Ok(())
+    }
 }
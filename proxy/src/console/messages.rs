@@ -226,6 +226,19 @@ pub(crate) struct GetRoleSecret {
     pub(crate) role_secret: Box<str>,
     pub(crate) allowed_ips: Option<Vec<IpPattern>>,
     pub(crate) project_id: Option<ProjectIdInt>,
+    /// Client certificate authentication settings of the endpoint, if enabled.
+    pub(crate) client_cert_auth: Option<ClientCertAuthSettings>,
+    /// The secret the role's password is being rotated to, if any.
+    #[serde(default)]
+    pub(crate) next_role_secret: Option<NextRoleSecret>,
+}
+
+/// The secret of the password a role is being rotated to. Both passwords are
+/// accepted until `overlap_until`.
+#[derive(Deserialize)]
+pub(crate) struct NextRoleSecret {
+    pub(crate) role_secret: Box<str>,
+    pub(crate) overlap_until: chrono::DateTime<chrono::Utc>,
 }
 
 // Manually implement debug to omit sensitive info.
@@ -235,6 +248,59 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Per-endpoint configuration of client certificate (mTLS) authentication.
+#[derive(Deserialize, Clone, PartialEq)]
+pub(crate) struct ClientCertAuthSettings {
+    /// PEM-encoded bundle of CA certificates trusted to issue client certificates.
+    pub(crate) ca_bundle: Box<str>,
+    /// Reject connections which don't present a valid client certificate.
+    #[serde(default)]
+    pub(crate) required: bool,
+    /// Rules which map certificate identities to postgres roles.
+    /// If empty, the certificate's common name must match the role name.
+    #[serde(default)]
+    pub(crate) role_mappings: Vec<CertRoleMapping>,
+}
+
+// Manually implement debug to omit the CA bundle.
+impl fmt::Debug for ClientCertAuthSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCertAuthSettings")
+            .field("required", &self.required)
+            .field("role_mappings", &self.role_mappings)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Maps a certificate identity to the role it is allowed to log in as.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub(crate) struct CertRoleMapping {
+    /// Which part of the certificate to match against.
+    pub(crate) identity: CertIdentityKind,
+    /// Exact value the identity must have.
+    pub(crate) value: Box<str>,
+    pub(crate) role: RoleName,
+    /// Skip SCRAM for this role once the certificate is verified.
+    /// Control plane is responsible for letting such roles into the compute without a password.
+    #[serde(default)]
+    pub(crate) skip_password: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CertIdentityKind {
+    /// Common name of the certificate subject.
+    CommonName,
+    /// Full RFC 4514 string of the certificate subject, e.g. `CN=alice,O=Acme`.
+    Subject,
+    /// DNS name from the subject alternative names.
+    DnsSan,
+    /// Email address from the subject alternative names.
+    EmailSan,
+    /// URI from the subject alternative names.
+    UriSan,
+}
+
 /// Response which holds compute node's `host:port` pair.
 /// Returned by the `/proxy_wake_compute` API method.
 #[derive(Debug, Deserialize)]
@@ -307,6 +373,21 @@ pub(crate) struct MetricsAuxInfo {
     pub(crate) branch_id: BranchIdInt,
     #[serde(default)]
     pub(crate) cold_start_info: ColdStartInfo,
+    /// Statement-level audit logging of the endpoint, if enabled.
+    #[serde(default)]
+    pub(crate) audit_log: Option<AuditLogSettings>,
+}
+
+/// Per-endpoint configuration of the statement audit log.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AuditLogSettings {
+    /// Record the statement text with literals and comments redacted.
+    /// Otherwise, only the hash of the statement is recorded.
+    #[serde(default)]
+    pub(crate) redacted_text: bool,
+    /// Also audit the statements of TCP and WebSocket connections, not only SQL over HTTP.
+    #[serde(default)]
+    pub(crate) tcp: bool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, FixedCardinalityLabel)]
@@ -445,6 +526,25 @@ fn parse_wake_compute() -> anyhow::Result<()> {
         Ok(())
     }
 
+    #[test]
+    fn parse_wake_compute_with_audit_log() -> anyhow::Result<()> {
+        let mut aux = dummy_aux();
+        aux["audit_log"] = json!({ "redacted_text": true });
+        let json = json!({
+            "address": "0.0.0.0",
+            "aux": aux,
+        });
+        let body = serde_json::from_str::<WakeCompute>(&json.to_string())?;
+        assert_eq!(
+            body.aux.audit_log,
+            Some(AuditLogSettings {
+                redacted_text: true,
+                tcp: false,
+            })
+        );
+        Ok(())
+    }
+
     #[test]
     fn parse_get_role_secret() -> anyhow::Result<()> {
         // Empty `allowed_ips` field.
@@ -463,6 +563,35 @@ fn parse_get_role_secret() -> anyhow::Result<()> {
             "project_id": "project",
         });
         serde_json::from_str::<GetRoleSecret>(&json.to_string())?;
+        let json = json!({
+            "role_secret": "secret",
+            "project_id": "project",
+            "client_cert_auth": {
+                "ca_bundle": "-----BEGIN CERTIFICATE-----",
+                "required": true,
+                "role_mappings": [
+                    {"identity": "common_name", "value": "alice", "role": "alice"},
+                    {"identity": "dns_san", "value": "app.acme.com", "role": "app", "skip_password": true},
+                ],
+            },
+        });
+        let body = serde_json::from_str::<GetRoleSecret>(&json.to_string())?;
+        let settings = body.client_cert_auth.unwrap();
+        assert!(settings.required);
+        assert_eq!(settings.role_mappings.len(), 2);
+        assert_eq!(settings.role_mappings[1].identity, CertIdentityKind::DnsSan);
+        assert!(settings.role_mappings[1].skip_password);
+        let json = json!({
+            "role_secret": "secret",
+            "next_role_secret": {
+                "role_secret": "next secret",
+                "overlap_until": "2024-09-01T00:00:00Z",
+            },
+        });
+        let body = serde_json::from_str::<GetRoleSecret>(&json.to_string())?;
+        let next = body.next_role_secret.unwrap();
+        assert_eq!(&*next.role_secret, "next secret");
+        assert_eq!(next.overlap_until.to_rfc3339(), "2024-09-01T00:00:00+00:00");
 
         Ok(())
     }
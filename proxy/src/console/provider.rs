@@ -1,3 +1,4 @@
+pub mod file;
 #[cfg(any(test, feature = "testing"))]
 pub mod mock;
 pub mod neon;
@@ -5,7 +6,7 @@
 use super::messages::{ConsoleError, MetricsAuxInfo};
 use crate::{
     auth::{
-        backend::{ComputeCredentialKeys, ComputeUserInfo},
+        backend::{client_cert::ClientCertAuth, ComputeCredentialKeys, ComputeUserInfo},
         IpPattern,
     },
     cache::{endpoints::EndpointsCache, project_info::ProjectInfoCacheImpl, Cached, TimedLru},
@@ -151,6 +152,9 @@ pub(crate) enum GetAuthInfoError {
         #[error("Console responded with a malformed auth secret")]
         BadSecret,
 
+        #[error("Console responded with a malformed client certificate configuration: {0}")]
+        BadClientCertAuth(anyhow::Error),
+
         #[error(transparent)]
         ApiError(ApiError),
     }
@@ -167,6 +171,7 @@ fn to_string_client(&self) -> String {
             match self {
                 // We absolutely should not leak any secrets!
                 Self::BadSecret => REQUEST_FAILED.to_owned(),
+                Self::BadClientCertAuth(_) => REQUEST_FAILED.to_owned(),
                 // However, API might return a meaningful error.
                 Self::ApiError(e) => e.to_string_client(),
             }
@@ -177,6 +182,7 @@ impl ReportableError for GetAuthInfoError {
         fn get_error_kind(&self) -> crate::error::ErrorKind {
             match self {
                 Self::BadSecret => crate::error::ErrorKind::ControlPlane,
+                Self::BadClientCertAuth(_) => crate::error::ErrorKind::ControlPlane,
                 Self::ApiError(_) => crate::error::ErrorKind::ControlPlane,
             }
         }
@@ -263,6 +269,8 @@ pub(crate) struct AuthInfo {
     pub(crate) allowed_ips: Vec<IpPattern>,
     /// Project ID. This is used for cache invalidation.
     pub(crate) project_id: Option<ProjectIdInt>,
+    /// Client certificate authentication settings, if enabled for the endpoint.
+    pub(crate) client_cert_auth: Option<Arc<ClientCertAuth>>,
 }
 
 /// Info for establishing a connection to a compute node.
@@ -305,6 +313,10 @@ pub(crate) fn set_keys(&mut self, keys: &ComputeCredentialKeys) {
         match keys {
             ComputeCredentialKeys::Password(password) => self.config.password(password),
             ComputeCredentialKeys::AuthKeys(auth_keys) => self.config.auth_keys(*auth_keys),
+            // Set before the startup params, which don't override the user
+            ComputeCredentialKeys::PreviousAuthKeys(auth_keys, role) => {
+                self.config.auth_keys(*auth_keys).user(role)
+            }
             ComputeCredentialKeys::None => &mut self.config,
         };
     }
@@ -314,6 +326,8 @@ pub(crate) fn set_keys(&mut self, keys: &ComputeCredentialKeys) {
 pub(crate) type CachedNodeInfo = Cached<&'static NodeInfoCache, NodeInfo>;
 pub(crate) type CachedRoleSecret = Cached<&'static ProjectInfoCacheImpl, Option<AuthSecret>>;
 pub(crate) type CachedAllowedIps = Cached<&'static ProjectInfoCacheImpl, Arc<Vec<IpPattern>>>;
+pub(crate) type CachedClientCertAuth =
+    Cached<&'static ProjectInfoCacheImpl, Option<Arc<ClientCertAuth>>>;
 
 /// This will allocate per each call, but the http requests alone
 /// already require a few allocations, so it should be fine.
@@ -333,6 +347,13 @@ async fn get_allowed_ips_and_secret(
         user_info: &ComputeUserInfo,
     ) -> Result<(CachedAllowedIps, Option<CachedRoleSecret>), errors::GetAuthInfoError>;
 
+    /// Get the endpoint's client certificate authentication settings.
+    async fn get_client_cert_auth(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedClientCertAuth, errors::GetAuthInfoError>;
+
     /// Wake up the compute node and return the corresponding connection info.
     async fn wake_compute(
         &self,
@@ -345,6 +366,8 @@ async fn wake_compute(
 pub enum ConsoleBackend {
     /// Current Cloud API (V2).
     Console(neon::Api),
+    /// Endpoints read from a local file, without a control plane.
+    File(file::Api),
     /// Local mock of Cloud API (V2).
     #[cfg(any(test, feature = "testing"))]
     Postgres(mock::Api),
@@ -362,6 +385,7 @@ async fn get_role_secret(
     ) -> Result<CachedRoleSecret, errors::GetAuthInfoError> {
         match self {
             Self::Console(api) => api.get_role_secret(ctx, user_info).await,
+            Self::File(api) => api.get_role_secret(ctx, user_info).await,
             #[cfg(any(test, feature = "testing"))]
             Self::Postgres(api) => api.get_role_secret(ctx, user_info).await,
             #[cfg(test)]
@@ -378,6 +402,7 @@ async fn get_allowed_ips_and_secret(
     ) -> Result<(CachedAllowedIps, Option<CachedRoleSecret>), errors::GetAuthInfoError> {
         match self {
             Self::Console(api) => api.get_allowed_ips_and_secret(ctx, user_info).await,
+            Self::File(api) => api.get_allowed_ips_and_secret(ctx, user_info).await,
             #[cfg(any(test, feature = "testing"))]
             Self::Postgres(api) => api.get_allowed_ips_and_secret(ctx, user_info).await,
             #[cfg(test)]
@@ -385,6 +410,21 @@ async fn get_allowed_ips_and_secret(
         }
     }
 
+    async fn get_client_cert_auth(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedClientCertAuth, errors::GetAuthInfoError> {
+        match self {
+            Self::Console(api) => api.get_client_cert_auth(ctx, user_info).await,
+            Self::File(api) => api.get_client_cert_auth(ctx, user_info).await,
+            #[cfg(any(test, feature = "testing"))]
+            Self::Postgres(api) => api.get_client_cert_auth(ctx, user_info).await,
+            #[cfg(test)]
+            Self::Test(api) => api.get_client_cert_auth(),
+        }
+    }
+
     async fn wake_compute(
         &self,
         ctx: &RequestMonitoring,
@@ -392,6 +432,7 @@ async fn wake_compute(
     ) -> Result<CachedNodeInfo, errors::WakeComputeError> {
         match self {
             Self::Console(api) => api.wake_compute(ctx, user_info).await,
+            Self::File(api) => api.wake_compute(ctx, user_info).await,
             #[cfg(any(test, feature = "testing"))]
             Self::Postgres(api) => api.wake_compute(ctx, user_info).await,
             #[cfg(test)]
@@ -0,0 +1,559 @@
+//! Console backend which reads endpoints from a local TOML or JSON file.
+//!
+//! Useful for local development and self-hosted setups without a control plane.
+//! The file is watched for changes and reloaded on the fly.
+//!
+//! ```toml
+//! [[endpoints]]
+//! id = "ep-small-cake-123456"
+//! project_id = "project-1"
+//! branch_id = "br-1"
+//! compute = "127.0.0.1:5432"
+//! allowed_ips = ["10.0.0.0/8"]
+//!
+//! [endpoints.roles]
+//! alice = "SCRAM-SHA-256$4096:..."
+//! ```
+
+use super::{
+    super::messages::{
+        AuditLogSettings, ClientCertAuthSettings, ColdStartInfo, ConsoleError, Details, ErrorInfo,
+        MetricsAuxInfo, Reason, Status,
+    },
+    errors::{ApiError, GetAuthInfoError, WakeComputeError},
+    ApiCaches, AuthInfo, AuthSecret, CachedAllowedIps, CachedClientCertAuth, CachedNodeInfo,
+    CachedRoleSecret, NodeInfo,
+};
+use crate::{
+    auth::{
+        backend::{client_cert::ClientCertAuth, ComputeUserInfo},
+        IpPattern,
+    },
+    cache::{project_info::ProjectInfoCache, Cached},
+    compute,
+    context::RequestMonitoring,
+    intern::ProjectIdInt,
+    scram, BranchId, EndpointId, ProjectId, RoleName,
+};
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use camino::{Utf8Path, Utf8PathBuf};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use tokio_postgres::config::SslMode;
+use tracing::{debug, error, info, warn};
+
+/// How often the file is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    endpoints: Vec<EndpointConfig>,
+}
+
+#[derive(Deserialize, PartialEq)]
+struct EndpointConfig {
+    id: EndpointId,
+    project_id: ProjectId,
+    branch_id: BranchId,
+    /// Address of the compute node, as `host:port`.
+    compute: String,
+    /// If not set, connections are allowed from any address.
+    #[serde(default)]
+    allowed_ips: Vec<IpPattern>,
+    /// SCRAM secrets of the roles, as stored in `pg_authid.rolpassword`.
+    #[serde(default)]
+    roles: HashMap<RoleName, String>,
+    #[serde(default)]
+    client_cert_auth: Option<ClientCertAuthSettings>,
+    #[serde(default)]
+    audit_log: Option<AuditLogSettings>,
+}
+
+/// An endpoint with all of its settings parsed and validated.
+struct Endpoint {
+    conf: EndpointConfig,
+    project_id: ProjectIdInt,
+    secrets: HashMap<RoleName, AuthSecret>,
+    allowed_ips: Arc<Vec<IpPattern>>,
+    client_cert_auth: Option<Arc<ClientCertAuth>>,
+    node: NodeInfo,
+}
+
+impl Endpoint {
+    fn new(conf: EndpointConfig) -> anyhow::Result<Self> {
+        let secrets = conf
+            .roles
+            .iter()
+            .map(|(role, secret)| {
+                let secret = scram::ServerSecret::parse(secret)
+                    .with_context(|| format!("role {role} has a malformed SCRAM secret"))?;
+                Ok((role.clone(), AuthSecret::Scram(secret)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let client_cert_auth = conf
+            .client_cert_auth
+            .clone()
+            .map(ClientCertAuth::new)
+            .transpose()
+            .context("malformed client certificate configuration")?
+            .map(Arc::new);
+
+        let (host, port) = conf
+            .compute
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host.trim_matches(&['[', ']'][..]), port.parse().ok()?)))
+            .with_context(|| format!("malformed compute address {}", conf.compute))?;
+
+        // TLS is not configured on compute nodes.
+        let mut config = compute::ConnCfg::new();
+        config.host(host).port(port).ssl_mode(SslMode::Disable);
+
+        let node = NodeInfo {
+            config,
+            aux: MetricsAuxInfo {
+                endpoint_id: (&conf.id).into(),
+                project_id: (&conf.project_id).into(),
+                branch_id: (&conf.branch_id).into(),
+                cold_start_info: ColdStartInfo::Warm,
+                audit_log: conf.audit_log,
+            },
+            allow_self_signed_compute: false,
+        };
+
+        Ok(Self {
+            project_id: (&conf.project_id).into(),
+            secrets,
+            allowed_ips: Arc::new(conf.allowed_ips.clone()),
+            client_cert_auth,
+            node,
+            conf,
+        })
+    }
+}
+
+type Endpoints = HashMap<EndpointId, Endpoint>;
+
+fn load(path: &Utf8Path) -> anyhow::Result<Endpoints> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    let file: ConfigFile = match path.extension() {
+        Some("json") => serde_json::from_str(&contents)?,
+        _ => toml::from_str(&contents)?,
+    };
+
+    let mut endpoints = HashMap::with_capacity(file.endpoints.len());
+    for conf in file.endpoints {
+        let id = conf.id.clone();
+        let endpoint = Endpoint::new(conf).with_context(|| format!("endpoint {id}"))?;
+        if endpoints.insert(id.clone(), endpoint).is_some() {
+            anyhow::bail!("endpoint {id} is defined more than once");
+        }
+    }
+    Ok(endpoints)
+}
+
+pub struct Api {
+    path: Utf8PathBuf,
+    endpoints: ArcSwap<Endpoints>,
+    /// Number of reloads so far. Lookups that started before a reload don't fill the cache,
+    /// which the reload has invalidated already.
+    epoch: RwLock<u64>,
+    pub caches: &'static ApiCaches,
+}
+
+impl Api {
+    /// Load the endpoints from `path`. Fails if the file is missing or malformed.
+    pub fn new(path: Utf8PathBuf, caches: &'static ApiCaches) -> anyhow::Result<Self> {
+        let endpoints = load(&path)?;
+        info!(%path, endpoints = endpoints.len(), "loaded endpoints");
+        Ok(Self {
+            path,
+            endpoints: ArcSwap::from_pointee(endpoints),
+            epoch: RwLock::new(0),
+            caches,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Reload the file whenever it changes.
+    ///
+    /// If the new file is malformed, the previous version is kept.
+    pub async fn watch(&self) -> anyhow::Result<Infallible> {
+        // We are the only source of changes, so the project info cache doesn't need the TTL.
+        self.caches.project_info.increment_active_listeners().await;
+
+        let mut last_modified = modified(&self.path);
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let modified = modified(&self.path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            match load(&self.path) {
+                Ok(endpoints) => {
+                    info!(path = %self.path, endpoints = endpoints.len(), "reloaded endpoints");
+                    self.reload(endpoints);
+                }
+                Err(e) => {
+                    error!(path = %self.path, "failed to reload endpoints, keeping the previous version: {e:#}");
+                }
+            }
+        }
+    }
+
+    /// Replace the endpoints, and invalidate the cached info of those that changed.
+    fn reload(&self, endpoints: Endpoints) {
+        let old = self.endpoints.swap(Arc::new(endpoints));
+        // Lookups of the old endpoints are either cached already and get invalidated here,
+        // or see the new epoch and skip the cache
+        let mut epoch = self.epoch.write();
+        *epoch += 1;
+        self.invalidate(&old, &self.endpoints.load());
+    }
+
+    /// Invalidate the cached info of all projects with endpoints which differ between `old` and `new`.
+    fn invalidate(&self, old: &Endpoints, new: &Endpoints) {
+        let cache = &self.caches.project_info;
+
+        let changed =
+            old.keys()
+                .chain(new.keys())
+                .filter(|id| match (old.get(*id), new.get(*id)) {
+                    (Some(old), Some(new)) => old.conf != new.conf,
+                    _ => true,
+                });
+        for id in changed {
+            // Both the old and the new version, if present.
+            for endpoint in old.get(id).into_iter().chain(new.get(id)) {
+                cache.invalidate_allowed_ips_for_project(endpoint.project_id);
+                cache.invalidate_client_cert_auth_for_project(endpoint.project_id);
+                for role in endpoint.conf.roles.keys() {
+                    cache.invalidate_role_secret_for_project(endpoint.project_id, role.into());
+                }
+            }
+        }
+    }
+
+    fn get_auth_info(&self, user_info: &ComputeUserInfo) -> Option<(ProjectIdInt, AuthInfo)> {
+        let endpoints = self.endpoints.load();
+        let Some(endpoint) = endpoints.get(&user_info.endpoint.normalize()) else {
+            warn!("endpoint '{}' does not exist", user_info.endpoint);
+            return None;
+        };
+
+        let secret = endpoint.secrets.get(&user_info.user).cloned();
+        if secret.is_none() {
+            warn!("user '{}' does not exist", user_info.user);
+        }
+
+        let auth_info = AuthInfo {
+            secret,
+            allowed_ips: endpoint.allowed_ips.to_vec(),
+            project_id: Some(endpoint.project_id),
+            client_cert_auth: endpoint.client_cert_auth.clone(),
+        };
+        Some((endpoint.project_id, auth_info))
+    }
+
+    /// Like [`Self::get_auth_info`], but also fills the project info cache.
+    fn get_and_cache_auth_info(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> AuthInfo {
+        // Before the endpoints are loaded, see `Self::reload`
+        let epoch = *self.epoch.read();
+        let Some((project_id, auth_info)) = self.get_auth_info(user_info) else {
+            return AuthInfo::default();
+        };
+        ctx.set_project_id(project_id);
+        self.cache_auth_info(epoch, user_info, project_id, &auth_info);
+
+        auth_info
+    }
+
+    /// Fill the project info cache with what was looked up at `epoch`, unless the endpoints
+    /// were reloaded since.
+    fn cache_auth_info(
+        &self,
+        epoch: u64,
+        user_info: &ComputeUserInfo,
+        project_id: ProjectIdInt,
+        auth_info: &AuthInfo,
+    ) {
+        // Held until the entries are inserted, so that a reload can't invalidate them before
+        let current_epoch = self.epoch.read();
+        if *current_epoch != epoch {
+            debug!("endpoints were reloaded during the lookup, not caching it");
+            return;
+        }
+
+        let endpoint_id = user_info.endpoint.normalize_intern();
+        let cache = &self.caches.project_info;
+        cache.insert_role_secret(
+            project_id,
+            endpoint_id,
+            (&user_info.user).into(),
+            auth_info.secret.clone(),
+        );
+        cache.insert_allowed_ips(
+            project_id,
+            endpoint_id,
+            Arc::new(auth_info.allowed_ips.clone()),
+        );
+        cache.insert_client_cert_auth(project_id, endpoint_id, auth_info.client_cert_auth.clone());
+    }
+}
+
+fn modified(path: &Utf8Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl super::Api for Api {
+    #[tracing::instrument(skip_all)]
+    async fn get_role_secret(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedRoleSecret, GetAuthInfoError> {
+        let normalized_ep = &user_info.endpoint.normalize();
+        if let Some(role_secret) = self
+            .caches
+            .project_info
+            .get_role_secret(normalized_ep, &user_info.user)
+        {
+            return Ok(role_secret);
+        }
+        let auth_info = self.get_and_cache_auth_info(ctx, user_info);
+        Ok(Cached::new_uncached(auth_info.secret))
+    }
+
+    async fn get_allowed_ips_and_secret(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<(CachedAllowedIps, Option<CachedRoleSecret>), GetAuthInfoError> {
+        let normalized_ep = &user_info.endpoint.normalize();
+        if let Some(allowed_ips) = self.caches.project_info.get_allowed_ips(normalized_ep) {
+            return Ok((allowed_ips, None));
+        }
+        let auth_info = self.get_and_cache_auth_info(ctx, user_info);
+        Ok((
+            Cached::new_uncached(Arc::new(auth_info.allowed_ips)),
+            Some(Cached::new_uncached(auth_info.secret)),
+        ))
+    }
+
+    async fn get_client_cert_auth(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedClientCertAuth, GetAuthInfoError> {
+        let normalized_ep = &user_info.endpoint.normalize();
+        if let Some(client_cert_auth) = self.caches.project_info.get_client_cert_auth(normalized_ep)
+        {
+            return Ok(client_cert_auth);
+        }
+        let auth_info = self.get_and_cache_auth_info(ctx, user_info);
+        Ok(Cached::new_uncached(auth_info.client_cert_auth))
+    }
+
+    /// Computes are not managed by the proxy, so there's nothing to wake up.
+    /// Compute addresses are read from memory, so they are not cached.
+    #[tracing::instrument(skip_all)]
+    async fn wake_compute(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedNodeInfo, WakeComputeError> {
+        let endpoints = self.endpoints.load();
+        let Some(endpoint) = endpoints.get(&user_info.endpoint.normalize()) else {
+            return Err(WakeComputeError::ApiError(ApiError::Console(
+                ConsoleError {
+                    error: "endpoint not found".into(),
+                    http_status_code: http::StatusCode::NOT_FOUND,
+                    status: Some(Status {
+                        code: "NOT_FOUND".into(),
+                        message: "endpoint not found".into(),
+                        details: Details {
+                            error_info: Some(ErrorInfo {
+                                reason: Reason::EndpointNotFound,
+                            }),
+                            retry_info: None,
+                            user_facing_message: None,
+                        },
+                    }),
+                },
+            )));
+        };
+
+        ctx.set_project(endpoint.node.aux.clone());
+        Ok(Cached::new_uncached(endpoint.node.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::project_info::ProjectInfoCacheImpl,
+        config::{CacheOptions, EndpointCacheConfig, ProjectInfoCacheOptions},
+        intern::EndpointIdInt,
+    };
+
+    const SECRET: &str = "SCRAM-SHA-256$4096:XiWDvxiVBY0wY+Ef8WJD5w==$Y5u+wuL4aowxuEQvdqzVcGSXYTRZyafZTFbKPPP5o3o=:HMhm+7eHu/wy1l5ly1KIY4nRwtHMsnzUqfMRasRGc1s=";
+
+    fn write(path: &Utf8Path, contents: &str) {
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn endpoint_toml(compute: &str) -> String {
+        format!(
+            r#"
+[[endpoints]]
+id = "ep-foo"
+project_id = "project-foo"
+branch_id = "br-foo"
+compute = "{compute}"
+allowed_ips = ["127.0.0.1"]
+
+[endpoints.roles]
+alice = "{SECRET}"
+"#
+        )
+    }
+
+    #[test]
+    fn parse_toml_and_json() -> anyhow::Result<()> {
+        let dir = camino_tempfile::tempdir()?;
+
+        let path = dir.path().join("endpoints.toml");
+        write(&path, &endpoint_toml("[::1]:5432"));
+        let endpoints = load(&path)?;
+        let endpoint = &endpoints[&EndpointId::from("ep-foo")];
+        assert_eq!(endpoint.allowed_ips.len(), 1);
+        assert!(endpoint.secrets.contains_key(&RoleName::from("alice")));
+        assert_eq!(endpoint.node.config.get_host()?.to_string(), "::1");
+
+        let path = dir.path().join("endpoints.json");
+        write(
+            &path,
+            &serde_json::json!({
+                "endpoints": [{
+                    "id": "ep-bar",
+                    "project_id": "project-bar",
+                    "branch_id": "br-bar",
+                    "compute": "compute-bar:5432",
+                }]
+            })
+            .to_string(),
+        );
+        let endpoints = load(&path)?;
+        let endpoint = &endpoints[&EndpointId::from("ep-bar")];
+        assert!(endpoint.allowed_ips.is_empty());
+        assert!(endpoint.secrets.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_malformed() -> anyhow::Result<()> {
+        let dir = camino_tempfile::tempdir()?;
+        let path = dir.path().join("endpoints.toml");
+
+        write(&path, &endpoint_toml("no-port"));
+        assert!(load(&path).is_err());
+
+        write(
+            &path,
+            &endpoint_toml("localhost:5432").replace(SECRET, "md5abc"),
+        );
+        assert!(load(&path).is_err());
+
+        let duplicate = endpoint_toml("localhost:5432").repeat(2);
+        write(&path, &duplicate);
+        assert!(load(&path).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalidate_changed_projects() -> anyhow::Result<()> {
+        let dir = camino_tempfile::tempdir()?;
+        let path = dir.path().join("endpoints.toml");
+        write(&path, &endpoint_toml("localhost:5432"));
+
+        let caches = Box::leak(Box::new(ApiCaches::new(
+            CacheOptions::CACHE_DEFAULT_OPTIONS.parse()?,
+            ProjectInfoCacheOptions::CACHE_DEFAULT_OPTIONS.parse()?,
+            EndpointCacheConfig::CACHE_DEFAULT_OPTIONS.parse()?,
+        )));
+        let api = Api::new(path.clone(), caches)?;
+        let cache: &ProjectInfoCacheImpl = &caches.project_info;
+
+        let endpoint = EndpointId::from("ep-foo");
+        let project_id = ProjectIdInt::from(&ProjectId::from("project-foo"));
+        cache.insert_allowed_ips(project_id, EndpointIdInt::from(&endpoint), Arc::new(vec![]));
+        assert!(cache.get_allowed_ips(&endpoint).is_some());
+
+        // unchanged
+        api.reload(load(&path)?);
+        assert!(cache.get_allowed_ips(&endpoint).is_some());
+
+        // compute address changed
+        write(&path, &endpoint_toml("localhost:5433"));
+        api.reload(load(&path)?);
+        assert!(cache.get_allowed_ips(&endpoint).is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_lookups_older_than_reload() -> anyhow::Result<()> {
+        let dir = camino_tempfile::tempdir()?;
+        let path = dir.path().join("endpoints.toml");
+        write(&path, &endpoint_toml("localhost:5432"));
+
+        let caches = Box::leak(Box::new(ApiCaches::new(
+            CacheOptions::CACHE_DEFAULT_OPTIONS.parse()?,
+            ProjectInfoCacheOptions::CACHE_DEFAULT_OPTIONS.parse()?,
+            EndpointCacheConfig::CACHE_DEFAULT_OPTIONS.parse()?,
+        )));
+        let api = Api::new(path.clone(), caches)?;
+        let cache: &ProjectInfoCacheImpl = &caches.project_info;
+
+        let endpoint = EndpointId::from("ep-foo");
+        let user_info = ComputeUserInfo {
+            endpoint: endpoint.clone(),
+            user: RoleName::from("alice"),
+            options: crate::proxy::NeonOptions::default(),
+        };
+
+        // The lookup started before the reload, and finishes after it
+        let epoch = *api.epoch.read();
+        let (project_id, auth_info) = api.get_auth_info(&user_info).unwrap();
+        write(&path, &endpoint_toml("localhost:5433"));
+        api.reload(load(&path)?);
+        api.cache_auth_info(epoch, &user_info, project_id, &auth_info);
+        assert!(cache.get_allowed_ips(&endpoint).is_none());
+
+        // A lookup after the reload is cached
+        let epoch = *api.epoch.read();
+        let (project_id, auth_info) = api.get_auth_info(&user_info).unwrap();
+        api.cache_auth_info(epoch, &user_info, project_id, &auth_info);
+        assert!(cache.get_allowed_ips(&endpoint).is_some());
+
+        Ok(())
+    }
+}
@@ -2,9 +2,10 @@
 
 use super::{
     errors::{ApiError, GetAuthInfoError, WakeComputeError},
-    AuthInfo, AuthSecret, CachedNodeInfo, NodeInfo,
+    AuthInfo, AuthSecret, CachedClientCertAuth, CachedNodeInfo, NodeInfo,
 };
 use crate::context::RequestMonitoring;
+use crate::{auth::backend::previous_password_role, RoleName};
 use crate::{auth::backend::ComputeUserInfo, compute, error::io_error, scram, url::ApiUrl};
 use crate::{auth::IpPattern, cache::Cached};
 use crate::{
@@ -14,6 +15,7 @@
     },
     BranchId, EndpointId, ProjectId,
 };
+use chrono::{DateTime, Utc};
 use futures::TryFutureExt;
 use std::{str::FromStr, sync::Arc};
 use thiserror::Error;
@@ -73,8 +75,16 @@ async fn do_get_auth_info(
             .await?
             {
                 info!("got a secret: {entry}"); // safe since it's not a prod scenario
-                let secret = scram::ServerSecret::parse(&entry).map(AuthSecret::Scram);
-                secret.or_else(|| parse_md5(&entry).map(AuthSecret::Md5))
+                match scram::ServerSecret::parse(&entry) {
+                    Some(secret) => match get_previous_password(&client, &user_info.user).await? {
+                        Some((current, overlap_until)) => {
+                            Some(current.with_next(&entry, overlap_until))
+                        }
+                        None => Some(secret),
+                    }
+                    .map(AuthSecret::Scram),
+                    None => parse_md5(&entry).map(AuthSecret::Md5),
+                }
             } else {
                 warn!("user '{}' does not exist", user_info.user);
                 None
@@ -105,6 +115,7 @@ async fn do_get_auth_info(
             secret,
             allowed_ips,
             project_id: None,
+            client_cert_auth: None,
         })
     }
 
@@ -122,6 +133,7 @@ async fn do_wake_compute(&self) -> Result<NodeInfo, WakeComputeError> {
                 project_id: (&ProjectId::from("project")).into(),
                 branch_id: (&BranchId::from("branch")).into(),
                 cold_start_info: crate::console::messages::ColdStartInfo::Warm,
+                audit_log: None,
             },
             allow_self_signed_compute: false,
         };
@@ -150,6 +162,32 @@ async fn get_execute_postgres_query(
     Ok(Some(entry))
 }
 
+/// While the password of a role is being rotated, the role has the next password
+/// already, and the role compute_ctl creates for it keeps the current one until
+/// it expires. Returns that password and when it expires.
+async fn get_previous_password(
+    client: &Client,
+    user: &RoleName,
+) -> Result<Option<(scram::ServerSecret, DateTime<Utc>)>, GetAuthInfoError> {
+    let rows = client
+        .query(
+            "select rolpassword, extract(epoch from rolvaliduntil)::bigint as valid_until \
+             from pg_catalog.pg_authid where rolname = $1",
+            &[&previous_password_role(user).as_str()],
+        )
+        .await?;
+    let Some(row) = rows.first() else {
+        return Ok(None);
+    };
+
+    let password: Option<String> = row.try_get("rolpassword")?;
+    let valid_until: Option<i64> = row.try_get("valid_until")?;
+    Ok(password
+        .as_deref()
+        .and_then(scram::ServerSecret::parse)
+        .zip(valid_until.and_then(|t| DateTime::from_timestamp(t, 0))))
+}
+
 impl super::Api for Api {
     #[tracing::instrument(skip_all)]
     async fn get_role_secret(
@@ -175,6 +213,15 @@ async fn get_allowed_ips_and_secret(
         ))
     }
 
+    async fn get_client_cert_auth(
+        &self,
+        _ctx: &RequestMonitoring,
+        _user_info: &ComputeUserInfo,
+    ) -> Result<CachedClientCertAuth, GetAuthInfoError> {
+        // Client certificates are not supported by the mock console.
+        Ok(Cached::new_uncached(None))
+    }
+
     #[tracing::instrument(skip_all)]
     async fn wake_compute(
         &self,
@@ -3,11 +3,11 @@
 use super::{
     super::messages::{ConsoleError, GetRoleSecret, WakeCompute},
     errors::{ApiError, GetAuthInfoError, WakeComputeError},
-    ApiCaches, ApiLocks, AuthInfo, AuthSecret, CachedAllowedIps, CachedNodeInfo, CachedRoleSecret,
-    NodeInfo,
+    ApiCaches, ApiLocks, AuthInfo, AuthSecret, CachedAllowedIps, CachedClientCertAuth,
+    CachedNodeInfo, CachedRoleSecret, NodeInfo,
 };
 use crate::{
-    auth::backend::ComputeUserInfo,
+    auth::backend::{client_cert::ClientCertAuth, ComputeUserInfo},
     compute,
     console::messages::{ColdStartInfo, Reason},
     http,
@@ -105,6 +105,10 @@ async fn do_get_auth_info(
                 None
             } else {
                 let secret = scram::ServerSecret::parse(&body.role_secret)
+                    .map(|secret| match &body.next_role_secret {
+                        Some(next) => secret.with_next(&next.role_secret, next.overlap_until),
+                        None => secret,
+                    })
                     .map(AuthSecret::Scram)
                     .ok_or(GetAuthInfoError::BadSecret)?;
                 Some(secret)
@@ -114,10 +118,17 @@ async fn do_get_auth_info(
                 .proxy
                 .allowed_ips_number
                 .observe(allowed_ips.len() as f64);
+            let client_cert_auth = body
+                .client_cert_auth
+                .map(ClientCertAuth::new)
+                .transpose()
+                .map_err(GetAuthInfoError::BadClientCertAuth)?
+                .map(Arc::new);
             Ok(AuthInfo {
                 secret,
                 allowed_ips,
                 project_id: body.project_id,
+                client_cert_auth,
             })
         }
         .map_err(crate::error::log_error)
@@ -215,6 +226,11 @@ async fn get_role_secret(
                 normalized_ep_int,
                 Arc::new(auth_info.allowed_ips),
             );
+            self.caches.project_info.insert_client_cert_auth(
+                project_id,
+                normalized_ep_int,
+                auth_info.client_cert_auth,
+            );
             ctx.set_project_id(project_id);
         }
         // When we just got a secret, we don't need to invalidate it.
@@ -254,6 +270,11 @@ async fn get_allowed_ips_and_secret(
                 normalized_ep_int,
                 allowed_ips.clone(),
             );
+            self.caches.project_info.insert_client_cert_auth(
+                project_id,
+                normalized_ep_int,
+                auth_info.client_cert_auth,
+            );
             ctx.set_project_id(project_id);
         }
         Ok((
@@ -262,6 +283,41 @@ async fn get_allowed_ips_and_secret(
         ))
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn get_client_cert_auth(
+        &self,
+        ctx: &RequestMonitoring,
+        user_info: &ComputeUserInfo,
+    ) -> Result<CachedClientCertAuth, GetAuthInfoError> {
+        let normalized_ep = &user_info.endpoint.normalize();
+        if let Some(client_cert_auth) = self.caches.project_info.get_client_cert_auth(normalized_ep)
+        {
+            return Ok(client_cert_auth);
+        }
+        let auth_info = self.do_get_auth_info(ctx, user_info).await?;
+        if let Some(project_id) = auth_info.project_id {
+            let normalized_ep_int = normalized_ep.into();
+            self.caches.project_info.insert_role_secret(
+                project_id,
+                normalized_ep_int,
+                (&user_info.user).into(),
+                auth_info.secret,
+            );
+            self.caches.project_info.insert_allowed_ips(
+                project_id,
+                normalized_ep_int,
+                Arc::new(auth_info.allowed_ips),
+            );
+            self.caches.project_info.insert_client_cert_auth(
+                project_id,
+                normalized_ep_int,
+                auth_info.client_cert_auth.clone(),
+            );
+            ctx.set_project_id(project_id);
+        }
+        Ok(Cached::new_uncached(auth_info.client_cert_auth))
+    }
+
     #[tracing::instrument(skip_all)]
     async fn wake_compute(
         &self,
@@ -18,8 +18,9 @@
     DbName, EndpointId, RoleName,
 };
 
-use self::parquet::RequestData;
+use self::{audit::AuditLog, parquet::RequestData};
 
+pub mod audit;
 pub mod parquet;
 
 pub(crate) static LOG_CHAN: OnceCell<mpsc::WeakUnboundedSender<RequestData>> = OnceCell::new();
@@ -77,6 +78,7 @@ pub(crate) enum AuthMethod {
     ScramSha256,
     ScramSha256Plus,
     Cleartext,
+    ClientCertificate,
 }
 
 impl RequestMonitoring {
@@ -261,6 +263,11 @@ pub(crate) fn cold_start_info(&self) -> ColdStartInfo {
             .cold_start_info
     }
 
+    /// Audit log of the statements sent to the given compute, enabled by the endpoint settings.
+    pub(crate) fn audit_log(&self, aux: &MetricsAuxInfo) -> AuditLog {
+        AuditLog::new(&self.0.try_lock().expect("should not deadlock"), aux)
+    }
+
     pub(crate) fn latency_timer_pause(&self, waiting_for: Waiting) -> LatencyTimerPause<'_> {
         LatencyTimerPause {
             ctx: self,
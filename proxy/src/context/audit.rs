@@ -0,0 +1,650 @@
+//! Statement-level audit log.
+//!
+//! Endpoints opt in via [`AuditLogSettings`] sent by the control plane.
+//! Every statement of such endpoints is recorded with its hash (and optionally the redacted text),
+//! role, duration, number of rows and error code. The records are written into rotated
+//! parquet or JSON files and uploaded to remote storage.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use bytes::{BufMut, BytesMut};
+use futures::{Stream, StreamExt};
+use once_cell::sync::OnceCell;
+use parquet::{basic::Compression, file::properties::WriterProperties};
+use remote_storage::{GenericRemoteStorage, RemoteStorageConfig};
+use sha2::{Digest, Sha256};
+use tokio::{sync::mpsc, time};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    config::remote_storage_from_toml,
+    console::messages::{AuditLogSettings, MetricsAuxInfo},
+    metrics::Protocol,
+};
+
+use super::{
+    parquet::{upload_path, upload_with_retries, worker_inner, ParquetConfig, ParquetRow},
+    RequestMonitoringInner,
+};
+
+static AUDIT_CHAN: OnceCell<mpsc::WeakUnboundedSender<StatementData>> = OnceCell::new();
+
+/// SQLSTATE of statements that were interrupted before they completed.
+const QUERY_CANCELED: &str = "57014";
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditLogFormat {
+    Parquet,
+    /// Newline-delimited JSON.
+    Json,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct AuditLogArgs {
+    /// Storage location to upload the statement audit log to.
+    /// Encoded as toml (same format as pageservers), eg
+    /// `{bucket_name='the-bucket',bucket_region='us-east-1',prefix_in_bucket='audit',endpoint='http://minio:9000'}`
+    #[clap(long, value_parser = remote_storage_from_toml)]
+    audit_log_remote_storage: Option<RemoteStorageConfig>,
+
+    /// Format of the uploaded files
+    #[clap(long, value_enum, default_value_t = AuditLogFormat::Parquet)]
+    audit_log_format: AuditLogFormat,
+
+    /// How many rows to include in a parquet row group
+    #[clap(long, default_value_t = 8192)]
+    audit_log_row_group_size: usize,
+
+    /// How large each file should be in bytes
+    #[clap(long, default_value_t = 100_000_000)]
+    audit_log_file_size: i64,
+
+    /// How long to wait before forcing a file upload
+    #[clap(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    audit_log_maximum_duration: tokio::time::Duration,
+
+    /// What level of compression to use for parquet files
+    #[clap(long, default_value_t = Compression::UNCOMPRESSED)]
+    audit_log_compression: Compression,
+}
+
+#[derive(parquet_derive::ParquetRecordWriter, serde::Serialize)]
+pub(crate) struct StatementData {
+    region: &'static str,
+    protocol: &'static str,
+    /// When the statement started. Must be UTC.
+    #[serde(serialize_with = "serialize_timestamp")]
+    timestamp: chrono::NaiveDateTime,
+    session_id: uuid::Uuid,
+    endpoint_id: &'static str,
+    project: &'static str,
+    branch: &'static str,
+    database: Option<String>,
+    username: Option<String>,
+    /// Hex-encoded SHA-256 of the statement text, as sent by the client.
+    statement_hash: String,
+    /// Statement text with literals and comments removed, if the endpoint opted in.
+    statement: Option<String>,
+    /// Command tag of the statement, eg `INSERT`.
+    command: Option<String>,
+    /// Number of rows returned or affected, if reported by postgres.
+    rows: Option<i64>,
+    /// SQLSTATE code, if the statement failed.
+    error_code: Option<String>,
+    duration_us: u64,
+}
+
+impl ParquetRow for StatementData {
+    const FILE_PREFIX: &'static str = "statements";
+    const DESCRIPTION: &'static str = "statement audit log";
+}
+
+fn serialize_timestamp<S>(timestamp: &chrono::NaiveDateTime, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    s.collect_str(&timestamp.format("%Y-%m-%dT%H:%M:%S%.6fZ"))
+}
+
+/// Audit log of a single connection or SQL over HTTP request.
+///
+/// Cheap to clone, and does nothing if the endpoint didn't opt in.
+#[derive(Clone, Default)]
+pub(crate) struct AuditLog(Option<Arc<AuditLogInner>>);
+
+struct AuditLogInner {
+    // Keeps the channel open while the connection is active, so that no records are lost on shutdown.
+    sender: mpsc::UnboundedSender<StatementData>,
+    redacted_text: bool,
+    region: &'static str,
+    protocol: Protocol,
+    session_id: uuid::Uuid,
+    endpoint_id: &'static str,
+    project: &'static str,
+    branch: &'static str,
+    database: Option<String>,
+    username: Option<String>,
+}
+
+impl AuditLog {
+    pub(super) fn new(ctx: &RequestMonitoringInner, aux: &MetricsAuxInfo) -> Self {
+        let Some(settings) = aux.audit_log else {
+            return Self::default();
+        };
+        if !Self::audits_protocol(settings, ctx.protocol) {
+            return Self::default();
+        }
+        let Some(sender) = AUDIT_CHAN.get().and_then(|tx| tx.upgrade()) else {
+            return Self::default();
+        };
+
+        Self(Some(Arc::new(AuditLogInner {
+            sender,
+            redacted_text: settings.redacted_text,
+            region: ctx.region,
+            protocol: ctx.protocol,
+            session_id: ctx.session_id,
+            endpoint_id: aux.endpoint_id.as_str(),
+            project: aux.project_id.as_str(),
+            branch: aux.branch_id.as_str(),
+            database: ctx.dbname.as_deref().map(String::from),
+            username: ctx.user.as_deref().map(String::from),
+        })))
+    }
+
+    fn audits_protocol(settings: AuditLogSettings, protocol: Protocol) -> bool {
+        match protocol {
+            Protocol::Http => true,
+            Protocol::Tcp | Protocol::Ws => settings.tcp,
+            Protocol::SniRouter => false,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Starts recording a statement. The record is written once the returned value is dropped.
+    pub(crate) fn statement(&self, text: &str) -> Option<AuditedStatement> {
+        let log = self.0.clone()?;
+        Some(AuditedStatement {
+            statement_hash: hex::encode(Sha256::digest(text.as_bytes())),
+            statement: log.redacted_text.then(|| redact(text)),
+            log,
+            started_at: chrono::Utc::now(),
+            start: time::Instant::now(),
+            command: None,
+            rows: None,
+            error_code: Some(QUERY_CANCELED.to_owned()),
+            discarded: false,
+        })
+    }
+}
+
+/// A statement being executed.
+///
+/// Statements that are dropped without [`AuditedStatement::complete`] or [`AuditedStatement::fail`]
+/// are recorded as cancelled.
+pub(crate) struct AuditedStatement {
+    log: Arc<AuditLogInner>,
+    started_at: chrono::DateTime<chrono::Utc>,
+    start: time::Instant,
+    statement_hash: String,
+    statement: Option<String>,
+    command: Option<String>,
+    rows: Option<i64>,
+    error_code: Option<String>,
+    discarded: bool,
+}
+
+impl AuditedStatement {
+    /// Adds up the results of a query string with multiple statements.
+    pub(crate) fn add_result(&mut self, command: &str, rows: Option<i64>) {
+        // empty queries and suspended portals have no command tag
+        if !command.is_empty() {
+            self.command = Some(command.to_owned());
+        }
+        self.rows = match (self.rows, rows) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.error_code = None;
+    }
+
+    pub(crate) fn set_error(&mut self, error_code: &str) {
+        self.error_code = Some(error_code.to_owned());
+    }
+
+    pub(crate) fn complete(mut self, command: &str, rows: Option<i64>) {
+        self.add_result(command, rows);
+    }
+
+    pub(crate) fn fail(mut self, error_code: &str) {
+        self.set_error(error_code);
+    }
+
+    /// Don't record the statement, e.g. because postgres skipped it.
+    pub(crate) fn discard(mut self) {
+        self.discarded = true;
+    }
+}
+
+impl Drop for AuditedStatement {
+    fn drop(&mut self) {
+        if self.discarded {
+            return;
+        }
+        let log = &self.log;
+        let row = StatementData {
+            region: log.region,
+            protocol: log.protocol.as_str(),
+            timestamp: self.started_at.naive_utc(),
+            session_id: log.session_id,
+            endpoint_id: log.endpoint_id,
+            project: log.project,
+            branch: log.branch,
+            database: log.database.clone(),
+            username: log.username.clone(),
+            statement_hash: std::mem::take(&mut self.statement_hash),
+            statement: self.statement.take(),
+            command: self.command.take(),
+            rows: self.rows,
+            error_code: self.error_code.take(),
+            duration_us: self.start.elapsed().as_micros() as u64,
+        };
+        // the worker has shut down
+        let _ = log.sender.send(row);
+    }
+}
+
+/// Splits a command tag into the command name and the number of rows.
+pub(crate) fn parse_command_tag(tag: &str) -> (&str, Option<i64>) {
+    let mut split = tag.split(' ');
+    let command = split.next().unwrap_or_default();
+    let rows = if command == "INSERT" {
+        // INSERT returns OID first and then number of rows
+        split.nth(1)
+    } else {
+        // other commands return number of rows (if any)
+        split.next()
+    };
+    (command, rows.and_then(|s| s.parse().ok()))
+}
+
+/// Removes the literals and comments from the statement, as they might contain sensitive data.
+/// Literals are replaced with `?`, positional parameters and identifiers are kept.
+pub(crate) fn redact(query: &str) -> String {
+    fn is_ident(b: u8) -> bool {
+        b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+    }
+
+    let bytes = query.as_bytes();
+    let mut out = String::with_capacity(query.len());
+    let mut i = 0;
+    // start of the text that is copied verbatim
+    let mut copy_from = 0;
+
+    while i < bytes.len() {
+        let prev = if i > 0 { Some(bytes[i - 1]) } else { None };
+        let replacement;
+        let end;
+        match bytes[i] {
+            b'\'' => {
+                // E'...' strings support backslash escapes
+                let escapes =
+                    matches!(prev, Some(b'e' | b'E')) && (i < 2 || !is_ident(bytes[i - 2]));
+                let mut j = i + 1;
+                while j < bytes.len() {
+                    match bytes[j] {
+                        b'\\' if escapes => j += 1,
+                        b'\'' if bytes.get(j + 1) == Some(&b'\'') => j += 1,
+                        b'\'' => break,
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                replacement = "?";
+                end = (j + 1).min(bytes.len());
+            }
+            b'"' => {
+                // quoted identifier, keep as is
+                let mut j = i + 1;
+                while j < bytes.len() {
+                    if bytes[j] == b'"' {
+                        if bytes.get(j + 1) == Some(&b'"') {
+                            j += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    j += 1;
+                }
+                i = (j + 1).min(bytes.len());
+                continue;
+            }
+            b'$' if !prev.is_some_and(is_ident) => {
+                // dollar-quoted string: $tag$...$tag$, the tag can't start with a digit
+                let tag_len = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                    .unwrap_or(bytes.len() - i - 1);
+                let is_tag = bytes.get(i + 1 + tag_len) == Some(&b'$')
+                    && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                if !is_tag {
+                    // positional parameter
+                    i += 1 + tag_len;
+                    continue;
+                }
+                let tag = &query[i..i + tag_len + 2];
+                let body = i + tag.len();
+                end = query[body..]
+                    .find(tag)
+                    .map_or(bytes.len(), |pos| body + pos + tag.len());
+                replacement = "?";
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                end = query[i..].find('\n').map_or(bytes.len(), |pos| i + pos);
+                replacement = "";
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // block comments nest
+                let mut depth = 0;
+                let mut j = i;
+                while j < bytes.len() {
+                    if bytes[j] == b'/' && bytes.get(j + 1) == Some(&b'*') {
+                        depth += 1;
+                        j += 2;
+                    } else if bytes[j] == b'*' && bytes.get(j + 1) == Some(&b'/') {
+                        depth -= 1;
+                        j += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        j += 1;
+                    }
+                }
+                end = j.min(bytes.len());
+                replacement = " ";
+            }
+            b'0'..=b'9' if !prev.is_some_and(is_ident) => {
+                let mut j = i;
+                while j < bytes.len() {
+                    match bytes[j] {
+                        b'0'..=b'9' | b'.' | b'_' => j += 1,
+                        // only an exponent if digits follow, eg `1e'x'` is `1` and `e'x'`
+                        b'e' | b'E' => {
+                            let sign = usize::from(matches!(bytes.get(j + 1), Some(b'+' | b'-')));
+                            if !bytes.get(j + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                                break;
+                            }
+                            j += 1 + sign;
+                        }
+                        _ => break,
+                    }
+                }
+                end = j;
+                replacement = "?";
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+
+        let mut copy_to = i;
+        // drop the string prefix, eg E'...', unless it was already replaced
+        if replacement == "?"
+            && i > copy_from
+            && bytes[i] == b'\''
+            && matches!(
+                prev,
+                Some(b'e' | b'E' | b'b' | b'B' | b'x' | b'X' | b'n' | b'N')
+            )
+            && (i < 2 || !is_ident(bytes[i - 2]))
+        {
+            copy_to -= 1;
+        }
+        out.push_str(&query[copy_from..copy_to]);
+        out.push_str(replacement);
+        i = end;
+        copy_from = end;
+    }
+    out.push_str(&query[copy_from..]);
+    out
+}
+
+/// Statement audit log worker
+///
+/// Listens on a channel for the statements of the endpoints which enabled the audit log,
+/// and uploads them to remote storage.
+pub async fn worker(
+    cancellation_token: CancellationToken,
+    config: AuditLogArgs,
+) -> anyhow::Result<()> {
+    let Some(remote_storage_config) = config.audit_log_remote_storage else {
+        info!("statement audit log: no remote storage configured");
+        return Ok(());
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    AUDIT_CHAN.set(tx.downgrade()).unwrap();
+
+    // setup row stream that will close on cancellation
+    tokio::spawn(async move {
+        cancellation_token.cancelled().await;
+        // dropping this sender will cause the channel to close only once
+        // all the remaining active connections have been closed.
+        drop(tx);
+    });
+    let rx = futures::stream::poll_fn(move |cx| rx.poll_recv(cx));
+
+    let storage = GenericRemoteStorage::from_config(&remote_storage_config)
+        .await
+        .context("remote storage init")?;
+
+    match config.audit_log_format {
+        AuditLogFormat::Parquet => {
+            let properties = WriterProperties::builder()
+                .set_compression(config.audit_log_compression)
+                .build();
+            let parquet_config = ParquetConfig {
+                propeties: Arc::new(properties),
+                rows_per_group: config.audit_log_row_group_size,
+                file_size: config.audit_log_file_size,
+                max_duration: config.audit_log_maximum_duration,
+
+                #[cfg(any(test, feature = "testing"))]
+                test_remote_failures: 0,
+            };
+            worker_inner(storage, rx, parquet_config).await
+        }
+        AuditLogFormat::Json => {
+            json_worker_inner(
+                storage,
+                rx,
+                config.audit_log_file_size as usize,
+                config.audit_log_maximum_duration,
+            )
+            .await
+        }
+    }
+}
+
+async fn json_worker_inner(
+    storage: GenericRemoteStorage,
+    rx: impl Stream<Item = StatementData>,
+    file_size: usize,
+    max_duration: time::Duration,
+) -> anyhow::Result<()> {
+    let mut rx = std::pin::pin!(rx);
+    let mut buffer = BytesMut::new();
+    let mut rows = 0;
+    let mut last_upload = time::Instant::now();
+
+    while let Some(row) = rx.next().await {
+        serde_json::to_writer((&mut buffer).writer(), &row)?;
+        buffer.put_u8(b'\n');
+        rows += 1;
+
+        if buffer.len() > file_size || last_upload.elapsed() > max_duration {
+            last_upload = time::Instant::now();
+            upload_json(&storage, buffer.split(), rows).await?;
+            rows = 0;
+        }
+    }
+
+    if !buffer.is_empty() {
+        upload_json(&storage, buffer, rows).await?;
+    }
+
+    Ok(())
+}
+
+async fn upload_json(
+    storage: &GenericRemoteStorage,
+    buffer: BytesMut,
+    rows: usize,
+) -> anyhow::Result<()> {
+    let (id, path) = upload_path(StatementData::FILE_PREFIX, "json")?;
+    info!(%id, rows, size = buffer.len(), "uploading statement audit log file");
+    upload_with_retries(
+        storage,
+        &path,
+        buffer.freeze(),
+        id,
+        StatementData::DESCRIPTION,
+    )
+    .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+    use clap::Parser;
+    use remote_storage::RemoteStorageKind;
+    use walkdir::WalkDir;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct ProxyCliArgs {
+        #[clap(flatten)]
+        audit_log: AuditLogArgs,
+    }
+
+    #[test]
+    fn default_parser() {
+        let ProxyCliArgs { audit_log } = ProxyCliArgs::parse_from(["proxy"]);
+        assert_eq!(audit_log.audit_log_remote_storage, None);
+        assert_eq!(audit_log.audit_log_format, AuditLogFormat::Parquet);
+
+        let ProxyCliArgs { audit_log } =
+            ProxyCliArgs::parse_from(["proxy", "--audit-log-format", "json"]);
+        assert_eq!(audit_log.audit_log_format, AuditLogFormat::Json);
+    }
+
+    #[test]
+    fn redact_literals() {
+        let cases = [
+            (
+                "SELECT * FROM users WHERE email = 'alice@example.com' AND id = 42",
+                "SELECT * FROM users WHERE email = ? AND id = ?",
+            ),
+            ("SELECT 'it''s', E'\\'quoted\\''", "SELECT ?, ?"),
+            ("SELECT $1::int, $2", "SELECT $1::int, $2"),
+            ("SELECT $$secret$$, $fn$ body $$ $fn$", "SELECT ?, ?"),
+            (
+                "SELECT \"col'umn\", t1.c2 FROM t1 -- password\nWHERE x = 1.5e-3",
+                "SELECT \"col'umn\", t1.c2 FROM t1 \nWHERE x = ?",
+            ),
+            ("SELECT /* a /* nested */ comment */ 1", "SELECT   ?"),
+            (
+                "INSERT INTO t VALUES (x'ff', 'unterminated",
+                "INSERT INTO t VALUES (?, ?",
+            ),
+            ("SELECT 1.e'x'", "SELECT ??"),
+            ("SELECT 1e+e'x'", "SELECT ?e+?"),
+            ("SELECT 1e5, 2E-3", "SELECT ?, ?"),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(redact(query), expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn command_tags() {
+        assert_eq!(parse_command_tag("INSERT 0 5"), ("INSERT", Some(5)));
+        assert_eq!(parse_command_tag("SELECT 1"), ("SELECT", Some(1)));
+        assert_eq!(parse_command_tag("BEGIN"), ("BEGIN", None));
+    }
+
+    fn statement(i: usize) -> StatementData {
+        StatementData {
+            region: "us-east-1",
+            protocol: "http",
+            timestamp: chrono::DateTime::from_timestamp_millis(1703862754)
+                .unwrap()
+                .naive_utc(),
+            session_id: uuid::Uuid::nil(),
+            endpoint_id: "endpoint",
+            project: "project",
+            branch: "branch",
+            database: Some("neondb".to_owned()),
+            username: Some("alice".to_owned()),
+            statement_hash: hex::encode(Sha256::digest(i.to_string())),
+            statement: Some("SELECT ?".to_owned()),
+            command: Some("SELECT".to_owned()),
+            rows: Some(1),
+            error_code: None,
+            duration_us: 1000,
+        }
+    }
+
+    fn uploaded_files(tmpdir: &Utf8Path) -> Vec<String> {
+        let mut files = WalkDir::new(tmpdir.as_std_path())
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| std::fs::read_to_string(entry.path()).unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn json_upload() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let storage = GenericRemoteStorage::from_config(&RemoteStorageConfig {
+            storage: RemoteStorageKind::LocalFs {
+                local_path: tmpdir.path().to_path_buf(),
+            },
+            timeout: std::time::Duration::from_secs(120),
+        })
+        .await
+        .unwrap();
+
+        let rows = futures::stream::iter((0..10).map(statement));
+        let line_len = serde_json::to_string(&statement(0)).unwrap().len() + 1;
+        // rotate after 4 rows
+        json_worker_inner(storage, rows, line_len * 4 - 1, time::Duration::MAX)
+            .await
+            .unwrap();
+
+        let files = uploaded_files(tmpdir.path());
+        let lines = files.iter().map(|f| f.lines().count()).collect::<Vec<_>>();
+        assert_eq!(lines.iter().sum::<usize>(), 10);
+        assert_eq!(lines.len(), 3);
+
+        let row: serde_json::Value =
+            serde_json::from_str(files[0].lines().next().unwrap()).unwrap();
+        assert_eq!(row["endpoint_id"], "endpoint");
+        assert_eq!(row["timestamp"], "1970-01-20T17:57:42.754000Z");
+        assert_eq!(row["error_code"], serde_json::Value::Null);
+
+        tmpdir.close().unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use std::{sync::Arc, time::SystemTime};
 
 use anyhow::Context;
-use bytes::{buf::Writer, BufMut, BytesMut};
+use bytes::{buf::Writer, BufMut, Bytes, BytesMut};
 use chrono::{Datelike, Timelike};
 use futures::{Stream, StreamExt};
 use parquet::{
@@ -72,6 +72,14 @@ pub struct ParquetUploadArgs {
 // * we batch up to 1024 rows, then flush them into a 'row group'
 // * after each rowgroup write, we check the length of the file and upload to s3 if large enough
 
+/// A row type of the parquet files uploaded to remote storage.
+pub(crate) trait ParquetRow: Send + 'static {
+    /// Prefix of the uploaded file names.
+    const FILE_PREFIX: &'static str;
+    /// What the rows are, for the logs.
+    const DESCRIPTION: &'static str;
+}
+
 #[derive(parquet_derive::ParquetRecordWriter)]
 pub(crate) struct RequestData {
     region: &'static str,
@@ -101,6 +109,11 @@ pub(crate) struct RequestData {
     disconnect_timestamp: Option<chrono::NaiveDateTime>,
 }
 
+impl ParquetRow for RequestData {
+    const FILE_PREFIX: &'static str = "requests";
+    const DESCRIPTION: &'static str = "request";
+}
+
 struct Options<'a> {
     options: &'a StartupMessageParams,
 }
@@ -139,6 +152,7 @@ fn from(value: &RequestMonitoringInner) -> Self {
                 super::AuthMethod::ScramSha256 => "scram_sha_256",
                 super::AuthMethod::ScramSha256Plus => "scram_sha_256_plus",
                 super::AuthMethod::Cleartext => "cleartext",
+                super::AuthMethod::ClientCertificate => "client_certificate",
             }),
             protocol: value.protocol.as_str(),
             region: value.region,
@@ -232,22 +246,26 @@ pub async fn worker(
 }
 
 #[derive(Clone, Debug)]
-struct ParquetConfig {
-    propeties: WriterPropertiesPtr,
-    rows_per_group: usize,
-    file_size: i64,
+pub(super) struct ParquetConfig {
+    pub(super) propeties: WriterPropertiesPtr,
+    pub(super) rows_per_group: usize,
+    pub(super) file_size: i64,
 
-    max_duration: tokio::time::Duration,
+    pub(super) max_duration: tokio::time::Duration,
 
     #[cfg(any(test, feature = "testing"))]
-    test_remote_failures: u64,
+    pub(super) test_remote_failures: u64,
 }
 
-async fn worker_inner(
+pub(super) async fn worker_inner<T>(
     storage: GenericRemoteStorage,
-    rx: impl Stream<Item = RequestData>,
+    rx: impl Stream<Item = T>,
     config: ParquetConfig,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<()>
+where
+    T: ParquetRow,
+    for<'a> &'a [T]: RecordWriter<T>,
+{
     #[cfg(any(test, feature = "testing"))]
     let storage = if config.test_remote_failures > 0 {
         GenericRemoteStorage::unreliable_wrapper(storage, config.test_remote_failures)
@@ -277,7 +295,7 @@ async fn worker_inner(
         }
         if len > config.file_size || force {
             last_upload = time::Instant::now();
-            let file = upload_parquet(w, len, &storage).await?;
+            let file = upload_parquet::<T>(w, len, &storage).await?;
             w = SerializedFileWriter::new(file, schema.clone(), config.propeties.clone())?;
             len = 0;
         }
@@ -290,21 +308,19 @@ async fn worker_inner(
     }
 
     if !w.flushed_row_groups().is_empty() {
-        let _rtchk: Writer<BytesMut> = upload_parquet(w, len, &storage).await?;
+        let _rtchk: Writer<BytesMut> = upload_parquet::<T>(w, len, &storage).await?;
     }
 
     Ok(())
 }
 
-async fn flush_rows<W>(
-    rows: Vec<RequestData>,
+async fn flush_rows<T, W>(
+    rows: Vec<T>,
     mut w: SerializedFileWriter<W>,
-) -> anyhow::Result<(
-    Vec<RequestData>,
-    SerializedFileWriter<W>,
-    RowGroupMetaDataPtr,
-)>
+) -> anyhow::Result<(Vec<T>, SerializedFileWriter<W>, RowGroupMetaDataPtr)>
 where
+    T: ParquetRow,
+    for<'a> &'a [T]: RecordWriter<T>,
     W: std::io::Write + Send + 'static,
 {
     let span = Span::current();
@@ -329,7 +345,7 @@ async fn flush_rows<W>(
     Ok((rows, w, rg_meta))
 }
 
-async fn upload_parquet(
+async fn upload_parquet<T: ParquetRow>(
     mut w: SerializedFileWriter<Writer<BytesMut>>,
     len: i64,
     storage: &GenericRemoteStorage,
@@ -355,6 +371,24 @@ async fn upload_parquet(
 
     let compression = len as f64 / len_uncompressed as f64;
     let size = data.len();
+    let (id, path) = upload_path(T::FILE_PREFIX, "parquet")?;
+
+    info!(
+        %id,
+        rows = metadata.num_rows,
+        size, compression, "uploading {} parquet file", T::DESCRIPTION
+    );
+
+    upload_with_retries(storage, &path, data, id, T::DESCRIPTION).await;
+
+    Ok(buffer.writer())
+}
+
+/// Returns a unique path for a new file, segmented by the current time.
+pub(super) fn upload_path(
+    file_prefix: &str,
+    extension: &str,
+) -> anyhow::Result<(uuid::Uuid, RemotePath)> {
     let now = chrono::Utc::now();
     let id = uuid::Uuid::new_v7(uuid::Timestamp::from_unix(
         uuid::NoContext,
@@ -363,26 +397,32 @@ async fn upload_parquet(
         now.timestamp_subsec_nanos(),
     ));
 
-    info!(
-        %id,
-        rows = metadata.num_rows,
-        size, compression, "uploading request parquet file"
-    );
-
     let year = now.year();
     let month = now.month();
     let day = now.day();
     let hour = now.hour();
     // segment files by time for S3 performance
     let path = RemotePath::from_string(&format!(
-        "{year:04}/{month:02}/{day:02}/{hour:02}/requests_{id}.parquet"
+        "{year:04}/{month:02}/{day:02}/{hour:02}/{file_prefix}_{id}.{extension}"
     ))?;
+    Ok((id, path))
+}
+
+/// Uploads the file, retrying on failures. Gives up with a warning after [`FAILED_UPLOAD_MAX_RETRIES`].
+/// `description` tells what kind of data the file has, for the warning.
+pub(super) async fn upload_with_retries(
+    storage: &GenericRemoteStorage,
+    path: &RemotePath,
+    data: Bytes,
+    id: uuid::Uuid,
+    description: &str,
+) {
     let cancel = CancellationToken::new();
     let maybe_err = backoff::retry(
         || async {
             let stream = futures::stream::once(futures::future::ready(Ok(data.clone())));
             storage
-                .upload(stream, data.len(), &path, None, &cancel)
+                .upload(stream, data.len(), path, None, &cancel)
                 .await
         },
         TimeoutOrCancel::caused_by_cancel,
@@ -399,10 +439,8 @@ async fn upload_parquet(
     .err();
 
     if let Some(err) = maybe_err {
-        tracing::warn!(%id, %err, "failed to upload request data");
+        tracing::warn!(%id, %err, "failed to upload {description} data");
     }
-
-    Ok(buffer.writer())
 }
 
 #[cfg(test)]
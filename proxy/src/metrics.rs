@@ -84,6 +84,9 @@ pub struct ProxyMetrics {
     /// Number of opened connections to a database.
     pub http_pool_opened_connections: Gauge,
 
+    /// Number of open SQL over HTTP sessions.
+    pub http_sessions: Gauge,
+
     /// Number of cache hits/misses for allowed ips.
     pub allowed_ips_cache_misses: CounterVec<StaticLabelSet<CacheOutcome>>,
 
@@ -134,6 +137,9 @@ pub struct ProxyMetrics {
     #[metric(metadata = Thresholds::with_buckets([0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]))]
     pub retries_metric: HistogramVec<RetriesMetricSet, 9>,
 
+    /// Number of authentications to roles whose password is being rotated (per secret).
+    pub password_rotation_auth_total: CounterVec<StaticLabelSet<RotatedSecret>>,
+
     /// Number of events consumed from redis (per event type).
     pub redis_events_count: CounterVec<StaticLabelSet<RedisEventsCount>>,
 
@@ -226,6 +232,13 @@ pub enum CacheOutcome {
     Miss,
 }
 
+#[derive(FixedCardinalityLabel, Copy, Clone)]
+#[label(singleton = "secret")]
+pub enum RotatedSecret {
+    Current,
+    Next,
+}
+
 #[derive(LabelGroup)]
 #[label(set = ConsoleRequestSet)]
 pub struct ConsoleRequest<'a> {
@@ -545,6 +558,7 @@ pub enum RedisEventsCount {
     CancelSession,
     PasswordUpdate,
     AllowedIpsUpdate,
+    ClientCertAuthUpdate,
 }
 
 pub struct ThreadPoolWorkers(usize);
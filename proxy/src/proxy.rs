@@ -6,6 +6,7 @@
 pub(crate) mod handshake;
 pub(crate) mod passthrough;
 pub(crate) mod retry;
+pub(crate) mod statement_audit;
 pub(crate) mod wake_compute;
 pub use copy_bidirectional::copy_bidirectional_client_compute;
 pub use copy_bidirectional::ErrorSource;
@@ -39,6 +40,7 @@
 use self::{
     connect_compute::{connect_to_compute, TcpMechanism},
     passthrough::ProxyPassthrough,
+    statement_audit::StatementTracker,
 };
 
 const ERR_INSECURE_CONNECTION: &str = "connection is insecure (try using `sslmode=require`)";
@@ -336,9 +338,15 @@ pub(crate) async fn handle_client<S: AsyncRead + AsyncWrite + Unpin>(
     let (stream, read_buf) = stream.into_inner();
     node.stream.write_all(&read_buf).await?;
 
+    let audit = StatementTracker::new(ctx.audit_log(&node.aux));
+    if let Some(audit) = &audit {
+        audit.lock().frontend(&read_buf);
+    }
+
     Ok(Some(ProxyPassthrough {
         client: stream,
         aux: node.aux.clone(),
+        audit,
         compute: node,
         _req: request_gauge,
         _conn: conn_gauge,
@@ -10,7 +10,10 @@
 use tracing::info;
 use utils::measured_stream::MeasuredStream;
 
-use super::copy_bidirectional::ErrorSource;
+use super::{
+    copy_bidirectional::ErrorSource,
+    statement_audit::{AuditedStream, SharedTracker, Side},
+};
 
 /// Forward bytes in both directions (client <-> compute).
 #[tracing::instrument(skip_all)]
@@ -18,6 +21,7 @@ pub(crate) async fn proxy_pass(
     client: impl AsyncRead + AsyncWrite + Unpin,
     compute: impl AsyncRead + AsyncWrite + Unpin,
     aux: MetricsAuxInfo,
+    audit: Option<SharedTracker>,
 ) -> Result<(), ErrorSource> {
     let usage = USAGE_METRICS.register(Ids {
         endpoint_id: aux.endpoint_id,
@@ -27,7 +31,7 @@ pub(crate) async fn proxy_pass(
     let metrics = &Metrics::get().proxy.io_bytes;
     let m_sent = metrics.with_labels(Direction::Tx);
     let mut client = MeasuredStream::new(
-        client,
+        AuditedStream::new(client, audit.clone(), Side::Frontend),
         |_| {},
         |cnt| {
             // Number of bytes we sent to the client (outbound).
@@ -38,7 +42,7 @@ pub(crate) async fn proxy_pass(
 
     let m_recv = metrics.with_labels(Direction::Rx);
     let mut compute = MeasuredStream::new(
-        compute,
+        AuditedStream::new(compute, audit, Side::Backend),
         |_| {},
         |cnt| {
             // Number of bytes the client sent to the compute node (inbound).
@@ -61,6 +65,7 @@ pub(crate) struct ProxyPassthrough<P, S> {
     pub(crate) client: Stream<S>,
     pub(crate) compute: PostgresConnection,
     pub(crate) aux: MetricsAuxInfo,
+    pub(crate) audit: Option<SharedTracker>,
 
     pub(crate) _req: NumConnectionRequestsGuard<'static>,
     pub(crate) _conn: NumClientConnectionsGuard<'static>,
@@ -69,7 +74,7 @@ pub(crate) struct ProxyPassthrough<P, S> {
 
 impl<P, S: AsyncRead + AsyncWrite + Unpin> ProxyPassthrough<P, S> {
     pub(crate) async fn proxy_pass(self) -> Result<(), ErrorSource> {
-        let res = proxy_pass(self.client, self.compute.stream, self.aux).await;
+        let res = proxy_pass(self.client, self.compute.stream, self.aux, self.audit).await;
         if let Err(err) = self.compute.cancel_closure.try_cancel_query().await {
             tracing::error!(?err, "could not cancel the query in the database");
         }
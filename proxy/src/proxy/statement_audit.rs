@@ -0,0 +1,415 @@
+//! Statement audit of the postgres protocol passed through the proxy.
+//!
+//! The proxy doesn't interpret the traffic after the connection is established,
+//! so for the endpoints with the audit log enabled we follow the message stream in both directions:
+//! queries and executed portals sent by the client are matched with the command completions
+//! and errors sent back by the compute.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::warn;
+
+use crate::context::audit::{parse_command_tag, AuditLog, AuditedStatement};
+
+/// Statements longer than this are audited by their prefix.
+const MAX_STATEMENT_LEN: usize = 1024 * 1024;
+
+/// Limits the memory used to track the prepared statements and portals of a connection.
+const MAX_PREPARED_STATEMENTS: usize = 10_000;
+
+pub(crate) type SharedTracker = Arc<Mutex<StatementTracker>>;
+
+pub(crate) struct StatementTracker {
+    audit: AuditLog,
+    frontend: MessageReader,
+    backend: MessageReader,
+    /// Query text of the prepared statements, by name.
+    statements: HashMap<String, Arc<str>>,
+    /// Query text of the bound portals, by name.
+    portals: HashMap<String, Arc<str>>,
+    /// Statements sent by the client, in the order postgres responds to them.
+    pending: VecDeque<Pending>,
+    /// Set if the message stream could not be followed.
+    broken: bool,
+}
+
+enum Pending {
+    /// A simple query, which might consist of multiple statements.
+    Simple(AuditedStatement),
+    /// An executed portal of the extended query protocol.
+    Extended(AuditedStatement),
+    Sync,
+}
+
+impl StatementTracker {
+    /// Returns `None` if the audit log is disabled for the connection.
+    pub(crate) fn new(audit: AuditLog) -> Option<SharedTracker> {
+        if !audit.is_enabled() {
+            return None;
+        }
+        Some(Arc::new(Mutex::new(Self {
+            audit,
+            frontend: MessageReader::default(),
+            backend: MessageReader::default(),
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            pending: VecDeque::new(),
+            broken: false,
+        })))
+    }
+
+    /// Follows the bytes sent by the client.
+    pub(crate) fn frontend(&mut self, buf: &[u8]) {
+        let mut reader = std::mem::take(&mut self.frontend);
+        self.read(&mut reader, buf, Self::frontend_message);
+        self.frontend = reader;
+    }
+
+    /// Follows the bytes sent by the compute.
+    pub(crate) fn backend(&mut self, buf: &[u8]) {
+        let mut reader = std::mem::take(&mut self.backend);
+        self.read(&mut reader, buf, Self::backend_message);
+        self.backend = reader;
+    }
+
+    fn read(&mut self, reader: &mut MessageReader, buf: &[u8], handle: fn(&mut Self, u8, &[u8])) {
+        if self.broken {
+            return;
+        }
+        let res = reader.feed(buf, |tag, body| handle(self, tag, body));
+        if res.is_err() {
+            warn!("could not follow the postgres protocol, the statement audit log is disabled for this connection");
+            self.broken = true;
+            self.pending.clear();
+        }
+    }
+
+    fn frontend_message(&mut self, tag: u8, body: &[u8]) {
+        match tag {
+            // Query
+            b'Q' => {
+                let (query, _) = read_cstr(body);
+                if let Some(statement) = self.audit.statement(&query) {
+                    self.pending.push_back(Pending::Simple(statement));
+                }
+            }
+            // Parse
+            b'P' => {
+                let (name, rest) = read_cstr(body);
+                let (query, _) = read_cstr(rest);
+                insert_bounded(&mut self.statements, name, query.into());
+            }
+            // Bind
+            b'B' => {
+                let (portal, rest) = read_cstr(body);
+                let (name, _) = read_cstr(rest);
+                if let Some(query) = self.statements.get(&*name).cloned() {
+                    insert_bounded(&mut self.portals, portal, query);
+                }
+            }
+            // Execute
+            b'E' => {
+                let (portal, _) = read_cstr(body);
+                if let Some(query) = self.portals.get(&*portal) {
+                    if let Some(statement) = self.audit.statement(query) {
+                        self.pending.push_back(Pending::Extended(statement));
+                    }
+                }
+            }
+            // Close
+            b'C' => {
+                let (name, _) = read_cstr(body.get(1..).unwrap_or_default());
+                match body.first() {
+                    Some(b'S') => self.statements.remove(&*name),
+                    Some(b'P') => self.portals.remove(&*name),
+                    _ => None,
+                };
+            }
+            // Sync
+            b'S' => self.pending.push_back(Pending::Sync),
+            _ => {}
+        }
+    }
+
+    fn backend_message(&mut self, tag: u8, body: &[u8]) {
+        match tag {
+            // CommandComplete
+            b'C' => {
+                let (tag, _) = read_cstr(body);
+                let (command, rows) = parse_command_tag(&tag);
+                match self.pending.front_mut() {
+                    Some(Pending::Simple(statement)) => statement.add_result(command, rows),
+                    Some(Pending::Extended(_)) => {
+                        if let Some(Pending::Extended(statement)) = self.pending.pop_front() {
+                            statement.complete(command, rows);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // EmptyQueryResponse, PortalSuspended
+            b'I' | b's' => match self.pending.front_mut() {
+                Some(Pending::Simple(statement)) => statement.add_result("", None),
+                Some(Pending::Extended(_)) => {
+                    if let Some(Pending::Extended(statement)) = self.pending.pop_front() {
+                        statement.complete("", None);
+                    }
+                }
+                _ => {}
+            },
+            // ErrorResponse
+            b'E' => {
+                let code = error_code(body);
+                match self.pending.front_mut() {
+                    Some(Pending::Simple(statement)) => statement.set_error(&code),
+                    Some(Pending::Extended(_)) => {
+                        if let Some(Pending::Extended(statement)) = self.pending.pop_front() {
+                            statement.fail(&code);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // ReadyForQuery
+            b'Z' => {
+                while let Some(pending) = self.pending.pop_front() {
+                    match pending {
+                        // recorded on drop
+                        Pending::Simple(_) => break,
+                        Pending::Sync => break,
+                        // skipped after an error
+                        Pending::Extended(statement) => statement.discard(),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn insert_bounded(map: &mut HashMap<String, Arc<str>>, name: String, query: Arc<str>) {
+    if map.len() < MAX_PREPARED_STATEMENTS || map.contains_key(&name) {
+        map.insert(name, query);
+    }
+}
+
+/// Splits off a null-terminated string.
+fn read_cstr(buf: &[u8]) -> (String, &[u8]) {
+    let (s, rest) = match buf.iter().position(|&b| b == 0) {
+        Some(end) => (&buf[..end], &buf[end + 1..]),
+        None => (buf, &[][..]),
+    };
+    (String::from_utf8_lossy(s).into_owned(), rest)
+}
+
+/// Extracts the SQLSTATE code from the fields of an ErrorResponse.
+fn error_code(mut body: &[u8]) -> String {
+    while let Some((&field, rest)) = body.split_first() {
+        if field == 0 {
+            break;
+        }
+        let (value, rest) = read_cstr(rest);
+        if field == b'C' {
+            return value;
+        }
+        body = rest;
+    }
+    String::new()
+}
+
+/// Splits a byte stream into postgres messages.
+#[derive(Default)]
+struct MessageReader {
+    header: [u8; 5],
+    header_len: usize,
+    remaining: usize,
+    body: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct InvalidMessageLength;
+
+impl MessageReader {
+    fn feed(
+        &mut self,
+        mut buf: &[u8],
+        mut on_message: impl FnMut(u8, &[u8]),
+    ) -> Result<(), InvalidMessageLength> {
+        while !buf.is_empty() {
+            if self.header_len < self.header.len() {
+                let n = (self.header.len() - self.header_len).min(buf.len());
+                self.header[self.header_len..self.header_len + n].copy_from_slice(&buf[..n]);
+                self.header_len += n;
+                buf = &buf[n..];
+                if self.header_len < self.header.len() {
+                    break;
+                }
+
+                let len = u32::from_be_bytes(self.header[1..].try_into().unwrap()) as usize;
+                self.remaining = len.checked_sub(4).ok_or(InvalidMessageLength)?;
+                self.body.clear();
+            }
+
+            let n = self.remaining.min(buf.len());
+            let capture = n.min(MAX_STATEMENT_LEN.saturating_sub(self.body.len()));
+            self.body.extend_from_slice(&buf[..capture]);
+            self.remaining -= n;
+            buf = &buf[n..];
+
+            if self.remaining == 0 {
+                on_message(self.header[0], &self.body);
+                self.header_len = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Side {
+    Frontend,
+    Backend,
+}
+
+/// Feeds the bytes read from the stream to the statement tracker.
+pub(crate) struct AuditedStream<S> {
+    stream: S,
+    tracker: Option<SharedTracker>,
+    side: Side,
+}
+
+impl<S> AuditedStream<S> {
+    pub(crate) fn new(stream: S, tracker: Option<SharedTracker>, side: Side) -> Self {
+        Self {
+            stream,
+            tracker,
+            side,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for AuditedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.stream).poll_read(cx, buf))?;
+        if let Some(tracker) = &this.tracker {
+            let read = &buf.filled()[filled..];
+            match this.side {
+                Side::Frontend => tracker.lock().frontend(read),
+                Side::Backend => tracker.lock().backend(read),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for AuditedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![tag];
+        buf.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    #[test]
+    fn split_messages() {
+        let mut stream = message(b'Q', b"select 1\0");
+        stream.extend(message(b'S', b""));
+        stream.extend(message(b'C', b"SELECT 1\0"));
+
+        // byte by byte, to cover the partial headers and bodies
+        let mut reader = MessageReader::default();
+        let mut messages = vec![];
+        for b in &stream {
+            reader
+                .feed(std::slice::from_ref(b), |tag, body| {
+                    messages.push((tag, body.to_vec()));
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            messages,
+            [
+                (b'Q', b"select 1\0".to_vec()),
+                (b'S', vec![]),
+                (b'C', b"SELECT 1\0".to_vec()),
+            ]
+        );
+
+        let mut reader = MessageReader::default();
+        assert!(reader.feed(b"Q\0\0\0\x02", |_, _| {}).is_err());
+    }
+
+    #[test]
+    fn parse_error_code() {
+        let body = b"SERROR\0VERROR\0C42P01\0Mrelation \"t\" does not exist\0\0";
+        assert_eq!(error_code(body), "42P01");
+        assert_eq!(error_code(b"\0"), "");
+    }
+
+    #[test]
+    fn track_extended_protocol() {
+        // the audit log is disabled, but the statements are still tracked
+        let mut tracker = StatementTracker {
+            audit: AuditLog::default(),
+            frontend: MessageReader::default(),
+            backend: MessageReader::default(),
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            pending: VecDeque::new(),
+            broken: false,
+        };
+
+        let mut frontend = message(b'P', b"s1\0select $1\0\0\0");
+        frontend.extend(message(b'B', b"\0s1\0\0\0\0\0\0\0"));
+        frontend.extend(message(b'E', b"\0\0\0\0\0"));
+        frontend.extend(message(b'S', b""));
+        tracker.frontend(&frontend);
+        assert_eq!(&*tracker.portals[""], "select $1");
+        assert!(matches!(
+            tracker.pending.iter().collect::<Vec<_>>()[..],
+            [Pending::Sync]
+        ));
+
+        tracker.frontend(&message(b'C', b"Ss1\0"));
+        assert!(tracker.statements.is_empty());
+
+        tracker.backend(&message(b'Z', b"I"));
+        assert!(tracker.pending.is_empty());
+        assert!(!tracker.broken);
+    }
+}
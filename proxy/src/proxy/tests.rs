@@ -7,12 +7,18 @@
 use super::connect_compute::ConnectMechanism;
 use super::retry::CouldRetry;
 use super::*;
+use crate::auth::backend::client_cert::{ClientCertAuth, DeferredClientCertVerifier};
 use crate::auth::backend::{
     ComputeCredentialKeys, ComputeCredentials, ComputeUserInfo, MaybeOwned, TestBackend,
 };
 use crate::config::{CertResolver, RetryConfig};
-use crate::console::messages::{ConsoleError, Details, MetricsAuxInfo, Status};
-use crate::console::provider::{CachedAllowedIps, CachedRoleSecret, ConsoleBackend, NodeInfoCache};
+use crate::console::messages::{
+    CertIdentityKind, CertRoleMapping, ClientCertAuthSettings, ConsoleError, Details,
+    MetricsAuxInfo, Status,
+};
+use crate::console::provider::{
+    CachedAllowedIps, CachedClientCertAuth, CachedRoleSecret, ConsoleBackend, NodeInfoCache,
+};
 use crate::console::{self, CachedNodeInfo, NodeInfo};
 use crate::error::ErrorKind;
 use crate::{sasl, scram, BranchId, EndpointId, ProjectId};
@@ -119,6 +125,82 @@ fn generate_tls_config<'a>(
     Ok((client_config, tls_config))
 }
 
+/// Generate TLS configs where the server asks for client certificates,
+/// and the client optionally presents one issued by a local CA.
+fn generate_client_cert_tls_config<'a>(
+    hostname: &'a str,
+    common_name: &'a str,
+    client_common_name: Option<&str>,
+) -> anyhow::Result<(ClientConfig<'a>, TlsConfig, ClientCertAuth)> {
+    let (ca, cert, key) = generate_certs(hostname, common_name)?;
+
+    let tls_config = {
+        let mut cert_resolver = CertResolver::new();
+        cert_resolver.add_cert(key, vec![cert], true)?;
+        let cert_resolver = Arc::new(cert_resolver);
+
+        let config = rustls::ServerConfig::builder()
+            .with_client_cert_verifier(Arc::new(DeferredClientCertVerifier::new()))
+            .with_cert_resolver(cert_resolver.clone())
+            .into();
+
+        TlsConfig {
+            config,
+            common_names: cert_resolver.get_common_names(),
+            cert_resolver,
+        }
+    };
+
+    let client_ca = rcgen::Certificate::from_params({
+        let mut params = rcgen::CertificateParams::default();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params
+    })?;
+
+    let client_config = {
+        let builder = rustls::ClientConfig::builder().with_root_certificates({
+            let mut store = rustls::RootCertStore::empty();
+            store.add(ca)?;
+            store
+        });
+
+        let config = match client_common_name {
+            Some(client_common_name) => {
+                let client_cert = rcgen::Certificate::from_params({
+                    let mut params = rcgen::CertificateParams::default();
+                    params.distinguished_name = rcgen::DistinguishedName::new();
+                    params
+                        .distinguished_name
+                        .push(rcgen::DnType::CommonName, client_common_name);
+                    params
+                })?;
+                builder.with_client_auth_cert(
+                    vec![pki_types::CertificateDer::from(
+                        client_cert.serialize_der_with_signer(&client_ca)?,
+                    )],
+                    pki_types::PrivateKeyDer::Pkcs8(client_cert.serialize_private_key_der().into()),
+                )?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        ClientConfig { config, hostname }
+    };
+
+    let client_cert_auth = ClientCertAuth::new(ClientCertAuthSettings {
+        ca_bundle: client_ca.serialize_pem()?.into(),
+        required: true,
+        role_mappings: vec![CertRoleMapping {
+            identity: CertIdentityKind::CommonName,
+            value: "john_doe".into(),
+            role: "john_doe".into(),
+            skip_password: true,
+        }],
+    })?;
+
+    Ok((client_config, tls_config, client_cert_auth))
+}
+
 #[async_trait]
 trait TestAuth: Sized {
     async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Send>(
@@ -168,6 +250,27 @@ async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Send>(
     }
 }
 
+/// Authenticate `john_doe` by the client certificate only.
+struct ClientCert(ClientCertAuth);
+
+#[async_trait]
+impl TestAuth for ClientCert {
+    async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Send>(
+        self,
+        stream: &mut PqStream<Stream<S>>,
+    ) -> anyhow::Result<()> {
+        let certs = stream.get_ref().client_certificates();
+        let identity = self
+            .0
+            .authenticate(certs, &"john_doe".into())?
+            .context("client certificate should be verified")?;
+        anyhow::ensure!(identity.skip_password, "password should not be required");
+
+        stream.write_message_noflush(&Be::AuthenticationOk)?;
+        Ok(())
+    }
+}
+
 /// A dummy proxy impl which performs a handshake and reports auth success.
 async fn dummy_proxy(
     client: impl AsyncRead + AsyncWrite + Unpin + Send,
@@ -237,6 +340,64 @@ async fn handshake_tls() -> anyhow::Result<()> {
     proxy.await?
 }
 
+#[tokio::test]
+async fn handshake_tls_client_certificate() -> anyhow::Result<()> {
+    let (client, server) = tokio::io::duplex(1024);
+
+    let (client_config, server_config, client_cert_auth) = generate_client_cert_tls_config(
+        "generic-project-name.localhost",
+        "localhost",
+        Some("john_doe"),
+    )?;
+    let proxy = tokio::spawn(dummy_proxy(
+        client,
+        Some(server_config),
+        ClientCert(client_cert_auth),
+    ));
+
+    let (_client, _conn) = tokio_postgres::Config::new()
+        .user("john_doe")
+        .dbname("earth")
+        .ssl_mode(SslMode::Require)
+        .connect_raw(server, client_config.make_tls_connect()?)
+        .await?;
+
+    proxy.await?
+}
+
+#[tokio::test]
+async fn handshake_tls_client_certificate_is_enforced() -> anyhow::Result<()> {
+    for client_common_name in [None, Some("jane_doe")] {
+        let (client, server) = tokio::io::duplex(1024);
+
+        let (client_config, server_config, client_cert_auth) = generate_client_cert_tls_config(
+            "generic-project-name.localhost",
+            "localhost",
+            client_common_name,
+        )?;
+        let proxy = tokio::spawn(dummy_proxy(
+            client,
+            Some(server_config),
+            ClientCert(client_cert_auth),
+        ));
+
+        let client_res = tokio_postgres::Config::new()
+            .user("john_doe")
+            .dbname("earth")
+            .ssl_mode(SslMode::Require)
+            .connect_raw(server, client_config.make_tls_connect()?)
+            .await;
+        assert!(client_res.is_err(), "client shouldn't be able to connect");
+
+        proxy
+            .await?
+            .err()
+            .context("server shouldn't accept client")?;
+    }
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn handshake_raw() -> anyhow::Result<()> {
     let (client, server) = tokio::io::duplex(1024);
@@ -525,6 +686,12 @@ fn get_allowed_ips_and_secret(
     {
         unimplemented!("not used in tests")
     }
+
+    fn get_client_cert_auth(
+        &self,
+    ) -> Result<CachedClientCertAuth, console::errors::GetAuthInfoError> {
+        unimplemented!("not used in tests")
+    }
 }
 
 fn helper_create_cached_node_info(cache: &'static NodeInfoCache) -> CachedNodeInfo {
@@ -535,6 +702,7 @@ fn helper_create_cached_node_info(cache: &'static NodeInfoCache) -> CachedNodeIn
             project_id: (&ProjectId::from("project")).into(),
             branch_id: (&BranchId::from("branch")).into(),
             cold_start_info: crate::console::messages::ColdStartInfo::Warm,
+            audit_log: None,
         },
         allow_self_signed_compute: false,
     };
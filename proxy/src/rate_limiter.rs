@@ -3,7 +3,7 @@
 mod limiter;
 
 #[cfg(test)]
-pub(crate) use limit_algorithm::aimd::Aimd;
+pub(crate) use limit_algorithm::{aimd::Aimd, gradient::GradientConfig, vegas::VegasConfig};
 
 pub(crate) use limit_algorithm::{
     DynamicLimiter, Outcome, RateLimitAlgorithm, RateLimiterConfig, Token,
@@ -7,8 +7,14 @@
 };
 
 use self::aimd::Aimd;
+use self::gradient::{Gradient, GradientConfig};
+use self::vegas::{Vegas, VegasConfig};
 
 pub(crate) mod aimd;
+pub(crate) mod gradient;
+#[cfg(test)]
+mod simulation;
+pub(crate) mod vegas;
 
 /// Whether a job succeeded or failed as a result of congestion/overload.
 ///
@@ -46,6 +52,14 @@ pub(crate) enum RateLimitAlgorithm {
         #[serde(flatten)]
         conf: Aimd,
     },
+    Vegas {
+        #[serde(flatten)]
+        conf: VegasConfig,
+    },
+    Gradient {
+        #[serde(flatten)]
+        conf: GradientConfig,
+    },
 }
 
 pub(crate) struct Fixed;
@@ -68,6 +82,8 @@ pub(crate) fn create_rate_limit_algorithm(self) -> Box<dyn LimitAlgorithm> {
         match self.algorithm {
             RateLimitAlgorithm::Fixed => Box::new(Fixed),
             RateLimitAlgorithm::Aimd { conf } => Box::new(conf),
+            RateLimitAlgorithm::Vegas { conf } => Box::new(Vegas::new(conf)),
+            RateLimitAlgorithm::Gradient { conf } => Box::new(Gradient::new(conf)),
         }
     }
 }
@@ -0,0 +1,183 @@
+use parking_lot::Mutex;
+
+use super::{LimitAlgorithm, Outcome, Sample};
+
+/// Delay-based congestion avoidance, based on the gradient between long-term and short-term latency.
+///
+/// Tracks an exponential moving average of the latency over a long window,
+/// and compares it with the latency of the current sample:
+///
+/// ```text
+/// gradient = clamp(tolerance * long_rtt / short_rtt, 0.5, 1.0)
+/// new_limit = limit * gradient + sqrt(limit)
+/// ```
+///
+/// The `sqrt(limit)` term allows the limit to grow while latency stays flat,
+/// while a growing latency pulls the limit down proportionally.
+/// Load-based errors are treated as the steepest gradient.
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq)]
+pub(crate) struct GradientConfig {
+    /// Minimum limit for Gradient algorithm.
+    pub(crate) min: usize,
+    /// Maximum limit for Gradient algorithm.
+    pub(crate) max: usize,
+    /// How much the short-term latency may exceed the long-term one before the limit is reduced.
+    pub(crate) tolerance: f64,
+    /// Weight of a new limit estimate, between 0 and 1. Lower values change the limit slower.
+    pub(crate) smoothing: f64,
+    /// Number of samples the long-term latency is averaged over.
+    pub(crate) long_window: usize,
+}
+
+pub(crate) struct Gradient {
+    conf: GradientConfig,
+    state: Mutex<GradientState>,
+}
+
+struct GradientState {
+    /// Long-term latency, in seconds.
+    long_rtt: Option<f64>,
+    /// The limit is fractional internally, so that small adjustments are not lost to rounding.
+    estimated_limit: Option<f64>,
+}
+
+impl Gradient {
+    pub(crate) fn new(conf: GradientConfig) -> Self {
+        Self {
+            conf,
+            state: Mutex::new(GradientState {
+                long_rtt: None,
+                estimated_limit: None,
+            }),
+        }
+    }
+}
+
+impl LimitAlgorithm for Gradient {
+    fn update(&self, old_limit: usize, sample: Sample) -> usize {
+        let mut state = self.state.lock();
+
+        let estimated_limit = match state.estimated_limit {
+            // The limit was not changed by someone else in the meantime.
+            Some(limit) if limit.floor() as usize == old_limit => limit,
+            _ => old_limit as f64,
+        };
+
+        let short_rtt = sample.latency.as_secs_f64();
+        let long_rtt = match state.long_rtt {
+            Some(long_rtt) => {
+                let factor = 2.0 / (self.conf.long_window.max(1) as f64 + 1.0);
+                let mut long_rtt = long_rtt * (1.0 - factor) + short_rtt * factor;
+                // If the latency dropped a lot, the long-term average is stale.
+                // Let it catch up quickly so the limit is not inflated for a long time.
+                if long_rtt / short_rtt > 2.0 {
+                    long_rtt *= 0.95;
+                }
+                long_rtt
+            }
+            None => short_rtt,
+        };
+        state.long_rtt = Some(long_rtt);
+
+        let gradient = match sample.outcome {
+            Outcome::Overload => 0.5,
+            Outcome::Success if short_rtt == 0.0 => 1.0,
+            Outcome::Success => (self.conf.tolerance * long_rtt / short_rtt).clamp(0.5, 1.0),
+        };
+
+        let queue_size = estimated_limit.sqrt();
+        let new_limit = estimated_limit * gradient + queue_size;
+
+        // Don't grow the limit if we don't use it.
+        if new_limit > estimated_limit && sample.in_flight * 2 < old_limit {
+            return old_limit;
+        }
+
+        let smoothing = self.conf.smoothing.clamp(0.0, 1.0);
+        let new_limit = estimated_limit * (1.0 - smoothing) + new_limit * smoothing;
+        let new_limit = new_limit.clamp(self.conf.min as f64, self.conf.max as f64);
+        state.estimated_limit = Some(new_limit);
+
+        let limit = new_limit.floor() as usize;
+        if limit > old_limit {
+            tracing::debug!(limit, "limit increased");
+        } else if limit < old_limit {
+            tracing::info!(limit, gradient, "limit decreased");
+        }
+        limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::rate_limiter::limit_algorithm::{
+        DynamicLimiter, RateLimitAlgorithm, RateLimiterConfig,
+    };
+
+    use super::*;
+
+    fn config() -> RateLimiterConfig {
+        RateLimiterConfig {
+            initial_limit: 16,
+            algorithm: RateLimitAlgorithm::Gradient {
+                conf: GradientConfig {
+                    min: 1,
+                    max: 100,
+                    tolerance: 1.5,
+                    smoothing: 1.0,
+                    long_window: 100,
+                },
+            },
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_decrease_limit_on_overload() {
+        let limiter = DynamicLimiter::new(config());
+
+        let token = limiter
+            .acquire_timeout(Duration::from_millis(1))
+            .await
+            .unwrap();
+        token.release(Outcome::Overload);
+
+        // 16 * 0.5 + sqrt(16)
+        assert_eq!(limiter.state().limit(), 12, "overload: decrease");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_increase_limit_when_latency_is_stable() {
+        let limiter = DynamicLimiter::new(config());
+
+        let mut tokens = vec![];
+        for _ in 0..8 {
+            tokens.push(
+                limiter
+                    .acquire_timeout(Duration::from_millis(1))
+                    .await
+                    .unwrap(),
+            );
+        }
+        tokio::time::advance(Duration::from_millis(10)).await;
+        tokens.pop().unwrap().release(Outcome::Success);
+
+        // 16 * 1.0 + sqrt(16)
+        assert_eq!(limiter.state().limit(), 20, "no queueing: increase");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_not_increase_limit_when_underutilised() {
+        let limiter = DynamicLimiter::new(config());
+
+        let token = limiter
+            .acquire_timeout(Duration::from_millis(1))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_millis(10)).await;
+        token.release(Outcome::Success);
+
+        assert_eq!(limiter.state().limit(), 16, "underutilised: ignore");
+    }
+}
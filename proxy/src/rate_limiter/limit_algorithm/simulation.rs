@@ -0,0 +1,300 @@
+//! Drives limit algorithms with synthetic latency and error traces,
+//! to check how they converge without spinning up a real limiter.
+use std::time::Duration;
+
+use super::{
+    aimd::Aimd,
+    gradient::{Gradient, GradientConfig},
+    vegas::{Vegas, VegasConfig},
+    LimitAlgorithm, Outcome, Sample,
+};
+
+/// A simulated backend, e.g. a compute node or the control plane.
+///
+/// Serves up to `capacity` jobs concurrently with `base_latency`.
+/// Excess jobs are queued, so the latency grows linearly with the queue.
+/// Jobs that take longer than `timeout` fail with [`Outcome::Overload`].
+#[derive(Clone, Copy)]
+struct Backend {
+    capacity: usize,
+    base_latency: Duration,
+    timeout: Duration,
+}
+
+impl Backend {
+    fn serve(&self, in_flight: usize) -> Sample {
+        let queued = in_flight.saturating_sub(self.capacity);
+        let latency =
+            self.base_latency + self.base_latency * queued as u32 / self.capacity.max(1) as u32;
+
+        let outcome = if latency > self.timeout {
+            Outcome::Overload
+        } else {
+            Outcome::Success
+        };
+
+        Sample {
+            latency,
+            in_flight,
+            outcome,
+        }
+    }
+
+    /// The smallest concurrency at which jobs start to time out.
+    fn timeout_point(&self) -> usize {
+        let base = self.base_latency.as_secs_f64();
+        let queued = (self.timeout.as_secs_f64() - base) / base * self.capacity as f64;
+        self.capacity + queued.floor() as usize + 1
+    }
+}
+
+/// One phase of a synthetic trace.
+#[derive(Clone, Copy)]
+struct Phase {
+    backend: Backend,
+    /// Jobs that want to run concurrently. Only `min(demand, limit)` are let through.
+    demand: usize,
+    steps: usize,
+}
+
+#[derive(Debug)]
+struct PhaseResult {
+    /// The limit at the end of the phase.
+    limit: usize,
+    /// Share of jobs failed with overload, over the second half of the phase.
+    overload_ratio: f64,
+}
+
+fn simulate(alg: &dyn LimitAlgorithm, initial_limit: usize, trace: &[Phase]) -> Vec<PhaseResult> {
+    let mut limit = initial_limit;
+    let mut results = Vec::with_capacity(trace.len());
+    for phase in trace {
+        let mut overloads = 0;
+        for step in 0..phase.steps {
+            let in_flight = phase.demand.min(limit).max(1);
+            let sample = phase.backend.serve(in_flight);
+            if step >= phase.steps / 2 && sample.outcome == Outcome::Overload {
+                overloads += 1;
+            }
+            limit = alg.update(limit, sample);
+        }
+        results.push(PhaseResult {
+            limit,
+            overload_ratio: overloads as f64 / (phase.steps - phase.steps / 2) as f64,
+        });
+    }
+    results
+}
+
+const BACKEND: Backend = Backend {
+    capacity: 50,
+    base_latency: Duration::from_millis(10),
+    timeout: Duration::from_millis(100),
+};
+
+fn vegas() -> Vegas {
+    Vegas::new(VegasConfig {
+        min: 1,
+        max: 1000,
+        alpha: 3.0,
+        beta: 6.0,
+        // Longer than any trace, see `VegasConfig::probe_interval`.
+        probe_interval: 100_000,
+    })
+}
+
+fn gradient() -> Gradient {
+    Gradient::new(GradientConfig {
+        min: 1,
+        max: 1000,
+        tolerance: 1.5,
+        smoothing: 0.2,
+        long_window: 100,
+    })
+}
+
+fn aimd() -> Aimd {
+    Aimd {
+        min: 1,
+        max: 1000,
+        inc: 1,
+        dec: 0.9,
+        utilisation: 0.8,
+    }
+}
+
+fn warmup(steps: usize) -> Phase {
+    Phase {
+        backend: BACKEND,
+        demand: 1,
+        steps,
+    }
+}
+
+fn saturated(backend: Backend, steps: usize) -> Phase {
+    Phase {
+        backend,
+        demand: 10_000,
+        steps,
+    }
+}
+
+#[track_caller]
+fn assert_near_capacity(name: &str, result: &PhaseResult, capacity: usize) {
+    assert!(
+        result.limit >= capacity / 2 && result.limit <= capacity * 2,
+        "{name}: limit {} did not converge near capacity {capacity}",
+        result.limit
+    );
+    assert_eq!(result.overload_ratio, 0.0, "{name}: {result:?}");
+}
+
+/// The limit settles where the backend is fully used, without timing out most of the jobs.
+#[track_caller]
+fn assert_stable(name: &str, result: &PhaseResult, backend: Backend) {
+    assert!(
+        result.limit >= backend.capacity && result.limit <= backend.timeout_point() * 11 / 10,
+        "{name}: limit {} did not settle between capacity {} and timeout point {}",
+        result.limit,
+        backend.capacity,
+        backend.timeout_point()
+    );
+    assert!(result.overload_ratio < 0.25, "{name}: {result:?}");
+}
+
+#[test]
+fn vegas_converges_near_capacity() {
+    // from below
+    let results = simulate(&vegas(), 5, &[warmup(10), saturated(BACKEND, 2000)]);
+    assert_near_capacity("vegas", &results[1], BACKEND.capacity);
+
+    // from above
+    let results = simulate(&vegas(), 500, &[warmup(10), saturated(BACKEND, 2000)]);
+    assert_near_capacity("vegas", &results[1], BACKEND.capacity);
+}
+
+#[test]
+fn loss_and_gradient_based_limits_are_stable() {
+    // Both only react to sudden latency changes and errors.
+    // Under a steady load they settle right below the point where jobs start to time out.
+    for (name, alg) in [
+        ("gradient", &gradient() as &dyn LimitAlgorithm),
+        ("aimd", &aimd()),
+    ] {
+        let results = simulate(alg, 5, &[warmup(10), saturated(BACKEND, 4000)]);
+        assert_stable(name, &results[1], BACKEND);
+
+        let results = simulate(alg, 1000, &[warmup(10), saturated(BACKEND, 4000)]);
+        assert_stable(name, &results[1], BACKEND);
+    }
+}
+
+#[test]
+fn does_not_grow_when_underutilised() {
+    let trace = [Phase {
+        backend: BACKEND,
+        demand: 5,
+        steps: 1000,
+    }];
+
+    for (name, alg) in [
+        ("vegas", &vegas() as &dyn LimitAlgorithm),
+        ("gradient", &gradient()),
+        ("aimd", &aimd()),
+    ] {
+        let results = simulate(alg, 20, &trace);
+        assert_eq!(results[0].limit, 20, "{name}: limit should not change");
+    }
+}
+
+#[test]
+fn backs_off_on_latency_spike() {
+    // The backend capacity drops 5 times, but jobs don't time out yet.
+    let degraded = Backend {
+        capacity: 10,
+        timeout: Duration::from_secs(10),
+        ..BACKEND
+    };
+
+    for (name, alg) in [
+        ("vegas", &vegas() as &dyn LimitAlgorithm),
+        ("gradient", &gradient()),
+    ] {
+        let results = simulate(
+            alg,
+            5,
+            &[
+                warmup(10),
+                saturated(BACKEND, 2000),
+                saturated(degraded, 50),
+            ],
+        );
+        assert!(
+            results[2].limit < results[1].limit * 3 / 4,
+            "{name}: limit {} did not back off from {}",
+            results[2].limit,
+            results[1].limit
+        );
+    }
+
+    // AIMD doesn't see the spike at all.
+    let results = simulate(
+        &aimd(),
+        50,
+        &[saturated(BACKEND, 10), saturated(degraded, 50)],
+    );
+    assert!(results[1].limit >= results[0].limit, "aimd: {results:?}");
+}
+
+#[test]
+fn recovers_after_degradation() {
+    let degraded = Backend {
+        capacity: 10,
+        ..BACKEND
+    };
+    let trace = [
+        warmup(10),
+        saturated(BACKEND, 2000),
+        saturated(degraded, 2000),
+        saturated(BACKEND, 2000),
+    ];
+
+    let results = simulate(&vegas(), 5, &trace);
+    assert_near_capacity("vegas", &results[1], BACKEND.capacity);
+    assert_near_capacity("vegas", &results[2], degraded.capacity);
+    assert_near_capacity("vegas", &results[3], BACKEND.capacity);
+
+    for (name, alg) in [
+        ("gradient", &gradient() as &dyn LimitAlgorithm),
+        ("aimd", &aimd()),
+    ] {
+        let results = simulate(alg, 5, &trace);
+        assert_stable(name, &results[1], BACKEND);
+        assert_stable(name, &results[2], degraded);
+        assert_stable(name, &results[3], BACKEND);
+    }
+}
+
+#[test]
+fn backs_off_on_errors() {
+    // Every job times out, e.g. the backend is down.
+    let broken = Backend {
+        timeout: Duration::ZERO,
+        ..BACKEND
+    };
+    let trace = [warmup(10), saturated(BACKEND, 2000), saturated(broken, 100)];
+
+    for (name, alg) in [
+        ("vegas", &vegas() as &dyn LimitAlgorithm),
+        ("gradient", &gradient()),
+        ("aimd", &aimd()),
+    ] {
+        let results = simulate(alg, 5, &trace);
+        assert!(
+            results[2].limit < results[1].limit / 2,
+            "{name}: limit {} did not back off from {}",
+            results[2].limit,
+            results[1].limit
+        );
+    }
+}
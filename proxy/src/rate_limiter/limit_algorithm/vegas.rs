@@ -0,0 +1,196 @@
+use parking_lot::Mutex;
+use std::time::Duration;
+
+use super::{LimitAlgorithm, Outcome, Sample};
+
+/// Delay-based congestion avoidance, inspired by TCP Vegas.
+///
+/// Estimates the queue size from the ratio of the minimum observed latency (no-load latency)
+/// and the latency of the current sample:
+///
+/// ```text
+/// queue_size = limit * (1 - rtt_noload / rtt)
+/// ```
+///
+/// Increases the limit while the estimated queue is small,
+/// and reduces it once the queue grows or load-based errors are detected.
+/// Compared to [`super::aimd::Aimd`], this reacts to latency spikes before requests start failing.
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq)]
+pub(crate) struct VegasConfig {
+    /// Minimum limit for Vegas algorithm.
+    pub(crate) min: usize,
+    /// Maximum limit for Vegas algorithm.
+    pub(crate) max: usize,
+    /// The limit is increased while the estimated queue is smaller than `alpha * log10(limit)`.
+    pub(crate) alpha: f64,
+    /// The limit is decreased once the estimated queue is larger than `beta * log10(limit)`.
+    pub(crate) beta: f64,
+    /// Forget the no-load latency after this many samples,
+    /// so that the algorithm adapts when the baseline latency grows.
+    ///
+    /// If the backend is congested at the time of the probe, the congested latency
+    /// is taken as the new baseline, so this should be much larger than the limit.
+    pub(crate) probe_interval: usize,
+}
+
+pub(crate) struct Vegas {
+    conf: VegasConfig,
+    state: Mutex<VegasState>,
+}
+
+struct VegasState {
+    rtt_noload: Option<Duration>,
+    samples_since_probe: usize,
+}
+
+impl Vegas {
+    pub(crate) fn new(conf: VegasConfig) -> Self {
+        Self {
+            conf,
+            state: Mutex::new(VegasState {
+                rtt_noload: None,
+                samples_since_probe: 0,
+            }),
+        }
+    }
+}
+
+impl LimitAlgorithm for Vegas {
+    fn update(&self, old_limit: usize, sample: Sample) -> usize {
+        let mut state = self.state.lock();
+
+        state.samples_since_probe += 1;
+        if state.samples_since_probe > self.conf.probe_interval {
+            state.samples_since_probe = 0;
+            state.rtt_noload = None;
+        }
+
+        // log10 of 1 is 0, which would freeze small limits.
+        let log_limit = (old_limit.max(2) as f64).log10();
+
+        if sample.outcome == Outcome::Overload {
+            let limit = (old_limit as f64 - log_limit.max(1.0)).floor() as usize;
+            let limit = limit.clamp(self.conf.min, self.conf.max);
+            tracing::info!(limit, "limit decreased");
+            return limit;
+        }
+
+        let rtt_noload = match state.rtt_noload {
+            Some(rtt_noload) if rtt_noload <= sample.latency => rtt_noload,
+            // First sample, or a new minimum. Nothing to compare against.
+            _ => {
+                state.rtt_noload = Some(sample.latency);
+                return old_limit;
+            }
+        };
+
+        let queue_size = if sample.latency.is_zero() {
+            0.0
+        } else {
+            old_limit as f64 * (1.0 - rtt_noload.as_secs_f64() / sample.latency.as_secs_f64())
+        };
+
+        let limit = if queue_size < self.conf.alpha * log_limit {
+            // Don't grow the limit if we don't use it.
+            if sample.in_flight * 2 < old_limit {
+                return old_limit;
+            }
+            old_limit as f64 + log_limit.max(1.0)
+        } else if queue_size > self.conf.beta * log_limit {
+            old_limit as f64 - log_limit.max(1.0)
+        } else {
+            return old_limit;
+        };
+
+        let limit = (limit.floor() as usize).clamp(self.conf.min, self.conf.max);
+        if limit > old_limit {
+            tracing::debug!(limit, "limit increased");
+        } else if limit < old_limit {
+            tracing::info!(limit, queue_size, "limit decreased");
+        }
+        limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::rate_limiter::limit_algorithm::{
+        DynamicLimiter, RateLimitAlgorithm, RateLimiterConfig,
+    };
+
+    use super::*;
+
+    fn config() -> RateLimiterConfig {
+        RateLimiterConfig {
+            initial_limit: 10,
+            algorithm: RateLimitAlgorithm::Vegas {
+                conf: VegasConfig {
+                    min: 1,
+                    max: 100,
+                    alpha: 3.0,
+                    beta: 6.0,
+                    probe_interval: 1000,
+                },
+            },
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_decrease_limit_on_overload() {
+        let limiter = DynamicLimiter::new(config());
+
+        let token = limiter
+            .acquire_timeout(Duration::from_millis(1))
+            .await
+            .unwrap();
+        token.release(Outcome::Overload);
+
+        assert_eq!(limiter.state().limit(), 9, "overload: decrease");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_decrease_limit_when_latency_grows() {
+        let limiter = DynamicLimiter::new(config());
+
+        // establish the no-load latency
+        let token = limiter
+            .acquire_timeout(Duration::from_millis(1))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_millis(10)).await;
+        token.release(Outcome::Success);
+        assert_eq!(limiter.state().limit(), 10);
+
+        // latency grows 10x, which means the queue is ~90% of the limit
+        let token = limiter
+            .acquire_timeout(Duration::from_millis(1))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_millis(100)).await;
+        token.release(Outcome::Success);
+        assert_eq!(limiter.state().limit(), 9, "queueing: decrease");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_increase_limit_when_latency_is_stable() {
+        let limiter = DynamicLimiter::new(config());
+
+        let mut tokens = vec![];
+        for _ in 0..6 {
+            tokens.push(
+                limiter
+                    .acquire_timeout(Duration::from_millis(1))
+                    .await
+                    .unwrap(),
+            );
+        }
+        tokio::time::advance(Duration::from_millis(10)).await;
+        for token in tokens {
+            token.release(Outcome::Success);
+        }
+
+        assert!(limiter.state().limit() > 10, "no queueing: increase");
+    }
+}
@@ -44,6 +44,13 @@ pub(crate) enum Notification {
         deserialize_with = "deserialize_json_string"
     )]
     PasswordUpdate { password_update: PasswordUpdate },
+    #[serde(
+        rename = "/client_cert_auth_updated",
+        deserialize_with = "deserialize_json_string"
+    )]
+    ClientCertAuthUpdate {
+        client_cert_auth_update: ClientCertAuthUpdate,
+    },
     #[serde(rename = "/cancel_session")]
     Cancel(CancelSession),
 }
@@ -57,6 +64,10 @@ pub(crate) struct PasswordUpdate {
     role_name: RoleNameInt,
 }
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub(crate) struct ClientCertAuthUpdate {
+    project_id: ProjectIdInt,
+}
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub(crate) struct CancelSession {
     pub(crate) region_id: Option<String>,
     pub(crate) cancel_key_data: CancelKeyData,
@@ -150,7 +161,9 @@ async fn handle_message(&self, msg: redis::Msg) -> anyhow::Result<()> {
                     }
                 }
             }
-            Notification::AllowedIpsUpdate { .. } | Notification::PasswordUpdate { .. } => {
+            Notification::AllowedIpsUpdate { .. }
+            | Notification::PasswordUpdate { .. }
+            | Notification::ClientCertAuthUpdate { .. } => {
                 invalidate_cache(self.cache.clone(), msg.clone());
                 if matches!(msg, Notification::AllowedIpsUpdate { .. }) {
                     Metrics::get()
@@ -162,6 +175,11 @@ async fn handle_message(&self, msg: redis::Msg) -> anyhow::Result<()> {
                         .proxy
                         .redis_events_count
                         .inc(RedisEventsCount::PasswordUpdate);
+                } else if matches!(msg, Notification::ClientCertAuthUpdate { .. }) {
+                    Metrics::get()
+                        .proxy
+                        .redis_events_count
+                        .inc(RedisEventsCount::ClientCertAuthUpdate);
                 }
                 // It might happen that the invalid entry is on the way to be cached.
                 // To make sure that the entry is invalidated, let's repeat the invalidation in INVALIDATION_LAG seconds.
@@ -188,6 +206,9 @@ fn invalidate_cache<C: ProjectInfoCache>(cache: Arc<C>, msg: Notification) {
                 password_update.project_id,
                 password_update.role_name,
             ),
+        Notification::ClientCertAuthUpdate {
+            client_cert_auth_update,
+        } => cache.invalidate_client_cert_auth_for_project(client_cert_auth_update.project_id),
         Notification::Cancel(_) => unreachable!("cancel message should be handled separately"),
     }
 }
@@ -325,6 +346,30 @@ fn parse_password_updated() -> anyhow::Result<()> {
 
         Ok(())
     }
+    #[test]
+    fn parse_client_cert_auth_updated() -> anyhow::Result<()> {
+        let project_id: ProjectId = "new_project".into();
+        let data = format!("{{\"project_id\": \"{project_id}\"}}");
+        let text = json!({
+            "type": "message",
+            "topic": "/client_cert_auth_updated",
+            "data": data,
+        })
+        .to_string();
+
+        let result: Notification = serde_json::from_str(&text)?;
+        assert_eq!(
+            result,
+            Notification::ClientCertAuthUpdate {
+                client_cert_auth_update: ClientCertAuthUpdate {
+                    project_id: (&project_id).into()
+                }
+            }
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn parse_cancel_session() -> anyhow::Result<()> {
         let cancel_key_data = CancelKeyData {
@@ -17,7 +17,7 @@
 
 pub(crate) use exchange::{exchange, Exchange};
 pub(crate) use key::ScramKey;
-pub(crate) use secret::ServerSecret;
+pub(crate) use secret::{SecretVersion, ServerSecret, VerifiedKey};
 
 use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
@@ -65,7 +65,7 @@ mod tests {
         EndpointId,
     };
 
-    use super::{threadpool::ThreadPool, Exchange, ServerSecret};
+    use super::{threadpool::ThreadPool, Exchange, SecretVersion, ServerSecret};
 
     #[test]
     fn snapshot() {
@@ -110,7 +110,7 @@ fn snapshot() {
         };
 
         assert_eq!(
-            key.as_bytes(),
+            key.client_key.as_bytes(),
             [
                 74, 103, 1, 132, 12, 31, 200, 48, 28, 54, 82, 232, 207, 12, 138, 189, 40, 32, 134,
                 27, 125, 170, 232, 35, 171, 167, 166, 41, 70, 228, 182, 112,
@@ -145,4 +145,56 @@ async fn round_trip() {
     async fn failure() {
         run_round_trip_test("pencil", "eraser").await;
     }
+
+    /// A secret for `password` with a fixed salt, as Postgres would store it.
+    fn secret_with_salt(password: &str, salt: &str) -> String {
+        let iterations = 4096;
+        let mut pbkdf2 = super::pbkdf2::Pbkdf2::start(
+            password.as_bytes(),
+            &base64::decode(salt).unwrap(),
+            iterations,
+        );
+        let salted_password = loop {
+            if let std::task::Poll::Ready(x) = pbkdf2.turn() {
+                break x;
+            }
+        };
+        let client_key = super::hmac_sha256(&salted_password, [b"Client Key".as_slice()]);
+        let server_key = super::hmac_sha256(&salted_password, [b"Server Key".as_slice()]);
+        let stored_key = super::sha256([client_key.as_slice()]);
+        format!(
+            "SCRAM-SHA-256${iterations}:{salt}${}:{}",
+            base64::encode(stored_key),
+            base64::encode(server_key)
+        )
+    }
+
+    #[tokio::test]
+    async fn rotation() {
+        let pool = ThreadPool::new(1);
+        let ep = EndpointIdInt::from(EndpointId::from("foo"));
+
+        let salt = "QSXCR+Q6sek8bf92";
+        let current = ServerSecret::parse(&secret_with_salt("pencil", salt)).unwrap();
+        let later = chrono::Utc::now() + chrono::Duration::hours(1);
+        let secret = current.with_next(&secret_with_salt("eraser", salt), later);
+
+        for (password, version) in [
+            ("pencil", SecretVersion::Current),
+            ("eraser", SecretVersion::Next),
+        ] {
+            match super::exchange(&pool, ep, &secret, password.as_bytes())
+                .await
+                .unwrap()
+            {
+                crate::sasl::Outcome::Success(key) => assert_eq!(key.version, version),
+                crate::sasl::Outcome::Failure(r) => panic!("{password}: {r}"),
+            }
+        }
+
+        let outcome = super::exchange(&pool, ep, &secret, b"crayon")
+            .await
+            .unwrap();
+        assert!(matches!(outcome, crate::sasl::Outcome::Failure(_)));
+    }
 }
@@ -9,7 +9,7 @@
     ClientFinalMessage, ClientFirstMessage, OwnedServerFirstMessage, SCRAM_RAW_NONCE_LEN,
 };
 use super::pbkdf2::Pbkdf2;
-use super::secret::ServerSecret;
+use super::secret::{ServerSecret, VerifiedKey};
 use super::signature::SignatureBuilder;
 use super::threadpool::ThreadPool;
 use super::ScramKey;
@@ -105,14 +105,13 @@ pub(crate) async fn exchange(
     endpoint: EndpointIdInt,
     secret: &ServerSecret,
     password: &[u8],
-) -> sasl::Result<sasl::Outcome<super::ScramKey>> {
+) -> sasl::Result<sasl::Outcome<VerifiedKey>> {
     let salt = base64::decode(&secret.salt_base64)?;
     let client_key = derive_client_key(pool, endpoint, password, &salt, secret.iterations).await;
 
-    if secret.is_password_invalid(&client_key).into() {
-        Ok(sasl::Outcome::Failure("password doesn't match"))
-    } else {
-        Ok(sasl::Outcome::Success(client_key))
+    match secret.verify(|_| client_key.clone()) {
+        Some(key) => Ok(sasl::Outcome::Success(key)),
+        None => Ok(sasl::Outcome::Failure("password doesn't match")),
     }
 }
 
@@ -157,7 +156,7 @@ fn transition(
         secret: &ServerSecret,
         tls_server_end_point: &config::TlsServerEndPoint,
         input: &str,
-    ) -> sasl::Result<sasl::Step<Infallible, super::ScramKey>> {
+    ) -> sasl::Result<sasl::Step<Infallible, VerifiedKey>> {
         let Self {
             cbind_flag,
             client_first_message_bare,
@@ -189,24 +188,26 @@ fn transition(
             client_final_message_without_proof: client_final_message.without_proof,
         };
 
-        let client_key = signature_builder
-            .build(&secret.stored_key)
-            .derive_client_key(&client_final_message.proof);
+        let verified = secret.verify(|stored_key| {
+            signature_builder
+                .build(stored_key)
+                .derive_client_key(&client_final_message.proof)
+        });
 
         // Auth fails either if keys don't match or it's pre-determined to fail.
-        if secret.is_password_invalid(&client_key).into() {
+        let Some(key) = verified else {
             return Ok(sasl::Step::Failure("password doesn't match"));
-        }
+        };
 
-        let msg =
-            client_final_message.build_server_final_message(signature_builder, &secret.server_key);
+        let msg = client_final_message
+            .build_server_final_message(signature_builder, secret.server_key(key.version));
 
-        Ok(sasl::Step::Success(client_key, msg))
+        Ok(sasl::Step::Success(key, msg))
     }
 }
 
 impl sasl::Mechanism for Exchange<'_> {
-    type Output = super::ScramKey;
+    type Output = VerifiedKey;
 
     fn exchange(mut self, input: &str) -> sasl::Result<sasl::Step<Self, Self::Output>> {
         use {sasl::Step, ExchangeState};
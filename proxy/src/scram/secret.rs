@@ -1,6 +1,8 @@
 //! Tools for SCRAM server secret management.
 
+use chrono::{DateTime, Utc};
 use subtle::{Choice, ConstantTimeEq};
+use tracing::warn;
 
 use super::base64_decode_array;
 use super::key::ScramKey;
@@ -20,6 +22,35 @@ pub(crate) struct ServerSecret {
     /// Should auth fail no matter what?
     /// This is exactly the case for mocked secrets.
     pub(crate) doomed: bool,
+    /// The secret of the password the role is being rotated to, if any.
+    pub(crate) next: Option<Box<NextSecret>>,
+}
+
+/// The secret of the password a role is being rotated to. It shares the salt and
+/// the iteration count of the current secret: the client gets those before it
+/// proves which of the two passwords it knows.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) struct NextSecret {
+    /// Hashed `ClientKey`.
+    pub(crate) stored_key: ScramKey,
+    /// Used by client to verify server's signature.
+    pub(crate) server_key: ScramKey,
+    /// The current secret is accepted too until then.
+    pub(crate) overlap_until: DateTime<Utc>,
+}
+
+/// Which of the secrets of a role the client's key matches.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum SecretVersion {
+    Current,
+    Next,
+}
+
+/// A client key that matches one of the secrets of a role.
+#[derive(Debug)]
+pub(crate) struct VerifiedKey {
+    pub(crate) client_key: ScramKey,
+    pub(crate) version: SecretVersion,
 }
 
 impl ServerSecret {
@@ -37,14 +68,72 @@ pub(crate) fn parse(input: &str) -> Option<Self> {
             stored_key: base64_decode_array(stored_key)?.into(),
             server_key: base64_decode_array(server_key)?.into(),
             doomed: false,
+            next: None,
         };
 
         Some(secret)
     }
 
-    pub(crate) fn is_password_invalid(&self, client_key: &ScramKey) -> Choice {
+    /// Add the secret of the password the role is being rotated to.
+    /// Once `overlap_until` has passed, it's the only secret left.
+    pub(crate) fn with_next(mut self, next: &str, overlap_until: DateTime<Utc>) -> Self {
+        let Some(next) = Self::parse(next) else {
+            warn!("ignoring the next secret of the role: not a SCRAM secret");
+            return self;
+        };
+        if Utc::now() >= overlap_until {
+            return next;
+        }
+        if next.iterations != self.iterations || next.salt_base64 != self.salt_base64 {
+            warn!("ignoring the next secret of the role: the salt or iteration count differ");
+            return self;
+        }
+
+        self.next = Some(Box::new(NextSecret {
+            stored_key: next.stored_key,
+            server_key: next.server_key,
+            overlap_until,
+        }));
+        self
+    }
+
+    /// Find the secret that the client's key matches, among the ones accepted now.
+    /// The key is derived from the stored key of each secret with `client_key`,
+    /// as a SCRAM proof is.
+    pub(crate) fn verify(&self, client_key: impl Fn(&ScramKey) -> ScramKey) -> Option<VerifiedKey> {
+        let current_expired = self
+            .next
+            .as_ref()
+            .is_some_and(|next| Utc::now() >= next.overlap_until);
+
         // constant time to not leak partial key match
-        client_key.sha256().ct_ne(&self.stored_key) | Choice::from(self.doomed as u8)
+        let current_key = client_key(&self.stored_key);
+        let current = current_key.sha256().ct_eq(&self.stored_key)
+            & !Choice::from(self.doomed as u8)
+            & !Choice::from(current_expired as u8);
+
+        if let Some(next) = &self.next {
+            let next_key = client_key(&next.stored_key);
+            if next_key.sha256().ct_eq(&next.stored_key).into() {
+                return Some(VerifiedKey {
+                    client_key: next_key,
+                    version: SecretVersion::Next,
+                });
+            }
+        }
+
+        bool::from(current).then_some(VerifiedKey {
+            client_key: current_key,
+            version: SecretVersion::Current,
+        })
+    }
+
+    /// The server key of one of the secrets, to sign the server's messages with.
+    pub(crate) fn server_key(&self, version: SecretVersion) -> &ScramKey {
+        match (version, &self.next) {
+            (SecretVersion::Next, Some(next)) => &next.server_key,
+            _ => &self.server_key,
+        }
     }
 
     /// To avoid revealing information to an attacker, we use a
@@ -60,6 +149,7 @@ pub(crate) fn is_password_invalid(&self, client_key: &ScramKey) -> Choice {
             stored_key: ScramKey::default(),
             server_key: ScramKey::default(),
             doomed: true,
+            next: None,
         }
     }
 
@@ -91,4 +181,68 @@ fn parse_scram_secret() {
         assert_eq!(base64::encode(parsed.stored_key), stored_key);
         assert_eq!(base64::encode(parsed.server_key), server_key);
     }
+
+    fn rotating_secret(overlap_until: DateTime<Utc>) -> (ServerSecret, ScramKey, ScramKey) {
+        let current = ScramKey::from([1; 32]);
+        let next = ScramKey::from([2; 32]);
+        let secret = ServerSecret {
+            iterations: 4096,
+            salt_base64: "QSXCR+Q6sek8bf92".to_owned(),
+            stored_key: current.sha256(),
+            server_key: ScramKey::from([3; 32]),
+            doomed: false,
+            next: Some(Box::new(NextSecret {
+                stored_key: next.sha256(),
+                server_key: ScramKey::from([4; 32]),
+                overlap_until,
+            })),
+        };
+        (secret, current, next)
+    }
+
+    #[test]
+    fn verify_rotating_secret() {
+        let (secret, current, next) = rotating_secret(Utc::now() + chrono::Duration::hours(1));
+
+        let key = secret.verify(|_| current.clone()).unwrap();
+        assert_eq!(key.version, SecretVersion::Current);
+        assert_eq!(secret.server_key(key.version), &ScramKey::from([3; 32]));
+
+        let key = secret.verify(|_| next.clone()).unwrap();
+        assert_eq!(key.version, SecretVersion::Next);
+        assert_eq!(secret.server_key(key.version), &ScramKey::from([4; 32]));
+
+        assert!(secret.verify(|_| ScramKey::from([5; 32])).is_none());
+
+        // The current secret expires at the end of the overlap
+        let (secret, current, next) = rotating_secret(Utc::now() - chrono::Duration::hours(1));
+        assert!(secret.verify(|_| current.clone()).is_none());
+        assert!(secret.verify(|_| next.clone()).is_some());
+    }
+
+    #[test]
+    fn add_next_secret() {
+        let current = "SCRAM-SHA-256$4096:QSXCR+Q6sek8bf92$FO+9jBb3MUukt6jJnzjPZOWc5ow/Pu6JtPyju0aqaE8=:qxJ1SbmSAi5EcS0J5Ck/cKAm/+Ixa+Kwp63f4OHDgzo=";
+        let next = "SCRAM-SHA-256$4096:QSXCR+Q6sek8bf92$D5h6KTMBlUvDJk2Y8ELfC1Sjtc6k9YHjRyuRZyBNJns=:Pi3QHbcluX//NDfVkKlFl88GGzlJ5LkyPwcdlN/QBvI=";
+        let other_salt = "SCRAM-SHA-256$4096:+/tQQax7twvwTj64mjBsxQ==$D5h6KTMBlUvDJk2Y8ELfC1Sjtc6k9YHjRyuRZyBNJns=:Pi3QHbcluX//NDfVkKlFl88GGzlJ5LkyPwcdlN/QBvI=";
+        let later = Utc::now() + chrono::Duration::hours(1);
+        let earlier = Utc::now() - chrono::Duration::hours(1);
+        let current = ServerSecret::parse(current).unwrap();
+
+        let rotating = current.clone().with_next(next, later);
+        let rotating_next = rotating.next.as_ref().unwrap();
+        assert_eq!(
+            rotating_next.stored_key,
+            ServerSecret::parse(next).unwrap().stored_key
+        );
+        assert_eq!(rotating_next.overlap_until, later);
+
+        // Ignored, the client couldn't prove it knows either password with a single salt
+        assert_eq!(current.clone().with_next(other_salt, later), current);
+        // Only the next secret is left after the overlap
+        assert_eq!(
+            current.clone().with_next(next, earlier),
+            ServerSecret::parse(next).unwrap()
+        );
+    }
 }
@@ -5,6 +5,7 @@
 mod backend;
 pub mod cancel_set;
 mod conn_pool;
+mod http_session;
 mod http_util;
 mod json;
 mod sql_over_http;
@@ -69,30 +70,42 @@ pub async fn task_main(
         });
     }
 
+    let sessions = http_session::HttpSessions::new(
+        config.http_config.session_idle_timeout,
+        config.http_config.max_sessions_per_endpoint,
+    );
+    {
+        let sessions = Arc::clone(&sessions);
+        tokio::spawn(async move {
+            sessions.gc_worker().await;
+        });
+    }
+
     // shutdown the connection pool
     tokio::spawn({
         let cancellation_token = cancellation_token.clone();
         let conn_pool = conn_pool.clone();
+        let sessions = sessions.clone();
         async move {
             cancellation_token.cancelled().await;
-            tokio::task::spawn_blocking(move || conn_pool.shutdown())
-                .await
-                .unwrap();
+            tokio::task::spawn_blocking(move || {
+                // sessions hold connections detached from the pool
+                sessions.shutdown();
+                conn_pool.shutdown();
+            })
+            .await
+            .unwrap();
         }
     });
 
     let backend = Arc::new(PoolingBackend {
         pool: Arc::clone(&conn_pool),
+        sessions,
         config,
         endpoint_rate_limiter: Arc::clone(&endpoint_rate_limiter),
     });
     let tls_acceptor: Arc<dyn MaybeTlsAcceptor> = match config.tls_config.as_ref() {
-        Some(config) => {
-            let mut tls_server_config = rustls::ServerConfig::clone(&config.to_server_config());
-            // prefer http2, but support http/1.1
-            tls_server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-            Arc::new(tls_server_config)
-        }
+        Some(config) => Arc::new(config.to_http_server_config()),
         None => {
             warn!("TLS config is missing");
             Arc::new(NoTls)
@@ -387,7 +400,7 @@ async fn request_handler(
 
         // Return the response so the spawned future can continue.
         Ok(response.map(|_: http_body_util::Empty<Bytes>| Full::new(Bytes::new())))
-    } else if request.uri().path() == "/sql" && *request.method() == Method::POST {
+    } else if is_sql_path(request.uri().path()) && *request.method() == Method::POST {
         let ctx = RequestMonitoring::new(
             session_id,
             peer_addr,
@@ -399,13 +412,13 @@ async fn request_handler(
         sql_over_http::handle(config, ctx, request, backend, http_cancellation_token)
             .instrument(span)
             .await
-    } else if request.uri().path() == "/sql" && *request.method() == Method::OPTIONS {
+    } else if is_sql_path(request.uri().path()) && *request.method() == Method::OPTIONS {
         Response::builder()
             .header("Allow", "OPTIONS, POST")
             .header("Access-Control-Allow-Origin", "*")
             .header(
                 "Access-Control-Allow-Headers",
-                "Authorization, Neon-Connection-String, Neon-Raw-Text-Output, Neon-Array-Mode, Neon-Pool-Opt-In, Neon-Batch-Read-Only, Neon-Batch-Isolation-Level",
+                "Authorization, Neon-Connection-String, Neon-Raw-Text-Output, Neon-Array-Mode, Neon-Pool-Opt-In, Neon-Batch-Read-Only, Neon-Batch-Isolation-Level, Neon-Session-Id",
             )
             .header("Access-Control-Max-Age", "86400" /* 24 hours */)
             .status(StatusCode::OK) // 204 is also valid, but see: https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/OPTIONS#status_code
@@ -415,3 +428,11 @@ async fn request_handler(
         json_response(StatusCode::BAD_REQUEST, "query is not supported")
     }
 }
+
+/// `/sql` runs queries, `/sql/session/*` begins and ends stateful sessions.
+fn is_sql_path(path: &str) -> bool {
+    matches!(
+        path,
+        "/sql" | "/sql/session" | "/sql/session/commit" | "/sql/session/rollback"
+    )
+}
@@ -13,7 +13,7 @@
     console::{
         errors::{GetAuthInfoError, WakeComputeError},
         locks::ApiLocks,
-        provider::ApiLockError,
+        provider::{ApiLockError, CachedRoleSecret},
         CachedNodeInfo,
     },
     context::RequestMonitoring,
@@ -28,22 +28,24 @@
 };
 
 use super::conn_pool::{poll_client, AuthData, Client, ConnInfo, GlobalConnPool};
+use super::http_session::HttpSessions;
 
 pub(crate) struct PoolingBackend {
     pub(crate) pool: Arc<GlobalConnPool<tokio_postgres::Client>>,
+    pub(crate) sessions: Arc<HttpSessions<tokio_postgres::Client>>,
     pub(crate) config: &'static ProxyConfig,
     pub(crate) endpoint_rate_limiter: Arc<EndpointRateLimiter>,
 }
 
 impl PoolingBackend {
-    pub(crate) async fn authenticate_with_password(
+    /// Checks the IP allowlist and the endpoint rate limit. Requests to a session only
+    /// compare the credentials with the ones the session began with, so they need this too.
+    pub(crate) async fn check_access(
         &self,
         ctx: &RequestMonitoring,
         config: &AuthenticationConfig,
         user_info: &ComputeUserInfo,
-        password: &[u8],
-    ) -> Result<ComputeCredentials, AuthError> {
-        let user_info = user_info.clone();
+    ) -> Result<Option<CachedRoleSecret>, AuthError> {
         let backend = self
             .config
             .auth_backend
@@ -61,6 +63,28 @@ pub(crate) async fn authenticate_with_password(
         {
             return Err(AuthError::too_many_connections());
         }
+        Ok(maybe_secret)
+    }
+
+    pub(crate) async fn authenticate_with_password(
+        &self,
+        ctx: &RequestMonitoring,
+        config: &AuthenticationConfig,
+        user_info: &ComputeUserInfo,
+        password: &[u8],
+    ) -> Result<ComputeCredentials, AuthError> {
+        let user_info = user_info.clone();
+        let backend = self
+            .config
+            .auth_backend
+            .as_ref()
+            .map(|()| user_info.clone());
+        let maybe_secret = self.check_access(ctx, config, &user_info).await?;
+        // Client certificates are never requested over HTTP, so this only passes if the
+        // endpoint doesn't require one
+        if let Some(cert_auth) = &*backend.get_client_cert_auth(ctx).await? {
+            cert_auth.authenticate(None, &user_info.user)?;
+        }
         let cached_secret = match maybe_secret {
             Some(secret) => secret,
             None => backend.get_role_secret(ctx).await?,
@@ -81,9 +105,14 @@ pub(crate) async fn authenticate_with_password(
             }
         };
         let ep = EndpointIdInt::from(&user_info.endpoint);
-        let auth_outcome =
-            crate::auth::validate_password_and_exchange(&config.thread_pool, ep, password, secret)
-                .await?;
+        let auth_outcome = crate::auth::validate_password_and_exchange(
+            &config.thread_pool,
+            ep,
+            &user_info.user,
+            password,
+            secret,
+        )
+        .await?;
         let res = match auth_outcome {
             crate::sasl::Outcome::Success(key) => {
                 info!("user successfully authenticated");
@@ -269,8 +298,12 @@ async fn connect_once(
         let permit = self.locks.get_permit(&host).await?;
 
         let mut config = (*node_info.config).clone();
+        // The keys of a password that is being rotated may be for another role,
+        // see `NodeInfo::set_keys`
+        if config.get_user().is_none() {
+            config.user(&self.conn_info.user_info.user);
+        }
         let config = config
-            .user(&self.conn_info.user_info.user)
             .dbname(&self.conn_info.dbname)
             .connect_timeout(timeout);
 
@@ -624,6 +624,10 @@ pub(crate) fn metrics(&self) -> Arc<MetricCounter> {
             branch_id: aux.branch_id,
         })
     }
+
+    pub(crate) fn aux(&self) -> &MetricsAuxInfo {
+        &self.inner.as_ref().unwrap().aux
+    }
 }
 
 pub(crate) struct Client<C: ClientInnerExt> {
@@ -631,6 +635,8 @@ pub(crate) struct Client<C: ClientInnerExt> {
     inner: Option<ClientInner<C>>,
     conn_info: ConnInfo,
     pool: Weak<RwLock<EndpointConnPool<C>>>,
+    /// The pool to return the connection to once an HTTP session ends.
+    session_pool: Option<Weak<RwLock<EndpointConnPool<C>>>>,
 }
 
 pub(crate) struct Discard<'a, C: ClientInnerExt> {
@@ -649,6 +655,7 @@ pub(self) fn new(
             span: Span::current(),
             conn_info,
             pool,
+            session_pool: None,
         }
     }
     pub(crate) fn inner(&mut self) -> (&mut C, Discard<'_, C>) {
@@ -657,10 +664,26 @@ pub(crate) fn inner(&mut self) -> (&mut C, Discard<'_, C>) {
             pool,
             conn_info,
             span: _,
+            session_pool: _,
         } = self;
         let inner = inner.as_mut().expect("client inner should not be removed");
         (&mut inner.inner, Discard { conn_info, pool })
     }
+
+    /// Keeps the connection out of the pool while it's held by an HTTP session.
+    /// If the client is dropped before [`Client::attach`], the connection is closed.
+    pub(crate) fn detach(&mut self) {
+        self.session_pool = Some(std::mem::take(&mut self.pool));
+    }
+
+    /// Ends the HTTP session. The connection is returned to the pool on drop,
+    /// but only if the session has left it idle.
+    pub(crate) fn attach(&mut self, status: ReadyForQueryStatus) {
+        if let Some(pool) = self.session_pool.take() {
+            self.pool = pool;
+            self.inner().1.check_idle(status);
+        }
+    }
 }
 
 impl<C: ClientInnerExt> Discard<'_, C> {
@@ -756,6 +779,7 @@ fn create_inner_with(client: MockClient) -> ClientInner<MockClient> {
                 project_id: (&ProjectId::from("project")).into(),
                 branch_id: (&BranchId::from("branch")).into(),
                 cold_start_info: crate::console::messages::ColdStartInfo::Warm,
+                audit_log: None,
             },
             conn_id: uuid::Uuid::new_v4(),
         }
@@ -776,6 +800,8 @@ async fn test_pool() {
             },
             cancel_set: CancelSet::new(0),
             client_conn_threshold: u64::MAX,
+            session_idle_timeout: Duration::from_secs(1),
+            max_sessions_per_endpoint: 1,
         }));
         let pool = GlobalConnPool::new(config);
         let conn_info = ConnInfo {
@@ -870,4 +896,62 @@ async fn test_pool() {
         // Closed client should be removed from the pool.
         assert_eq!(2, pool.get_global_connections_count());
     }
+
+    #[tokio::test]
+    async fn test_session_detach() {
+        let _ = env_logger::try_init();
+        let config = Box::leak(Box::new(crate::config::HttpConfig {
+            accept_websockets: false,
+            pool_options: GlobalConnPoolOptions {
+                max_conns_per_endpoint: 10,
+                gc_epoch: Duration::from_secs(1),
+                pool_shards: 2,
+                idle_timeout: Duration::from_secs(1),
+                opt_in: false,
+                max_total_conns: 10,
+            },
+            cancel_set: CancelSet::new(0),
+            client_conn_threshold: u64::MAX,
+            session_idle_timeout: Duration::from_secs(1),
+            max_sessions_per_endpoint: 1,
+        }));
+        let pool = GlobalConnPool::new(config);
+        let conn_info = ConnInfo {
+            user_info: ComputeUserInfo {
+                user: "user".into(),
+                endpoint: "endpoint".into(),
+                options: NeonOptions::default(),
+            },
+            dbname: "dbname".into(),
+            auth: AuthData::Password("password".as_bytes().into()),
+        };
+        let ep_pool = Arc::downgrade(
+            &pool.get_or_create_endpoint_pool(&conn_info.endpoint_cache_key().unwrap()),
+        );
+        {
+            let mut client = Client::new(create_inner(), conn_info.clone(), ep_pool.clone());
+            client.detach();
+            // The session was not closed, the connection must not be reused.
+            assert!(client.do_drop().is_none());
+            mem::forget(client); // drop the client
+            assert_eq!(0, pool.get_global_connections_count());
+        }
+        {
+            let mut client = Client::new(create_inner(), conn_info.clone(), ep_pool.clone());
+            client.detach();
+            client.attach(ReadyForQueryStatus::Transaction);
+            // The session transaction is still open.
+            assert!(client.do_drop().is_none());
+            mem::forget(client); // drop the client
+            assert_eq!(0, pool.get_global_connections_count());
+        }
+        {
+            let mut client = Client::new(create_inner(), conn_info.clone(), ep_pool.clone());
+            client.detach();
+            client.attach(ReadyForQueryStatus::Idle);
+            client.do_drop().unwrap()();
+            mem::forget(client); // drop the client
+            assert_eq!(1, pool.get_global_connections_count());
+        }
+    }
 }
@@ -0,0 +1,353 @@
+//! Stateful SQL-over-HTTP sessions.
+//!
+//! A session holds a connection with an open transaction across multiple HTTP requests.
+//! The client begins a session, gets back a token, and passes it in the `Neon-Session-Id`
+//! header of the following queries, until the session is committed or rolled back.
+//!
+//! While the session is open, the connection is detached from the pool (see [`Client::detach`]),
+//! so the number of open sessions per endpoint is limited separately.
+//! Sessions that are not used for longer than the idle timeout are closed,
+//! which rolls back the transaction.
+
+use std::{sync::Arc, time::Duration};
+
+use dashmap::DashMap;
+use subtle::ConstantTimeEq;
+use tokio::time::Instant;
+use tokio_postgres::IsolationLevel;
+use tracing::{debug, info};
+
+use crate::{
+    error::{ErrorKind, ReportableError, UserFacingError},
+    intern::EndpointIdInt,
+    metrics::Metrics,
+    usage_metrics::MetricCounter,
+};
+
+use super::conn_pool::{AuthData, Client, ClientInnerExt, ConnInfo};
+
+pub(crate) type SessionToken = uuid::Uuid;
+
+pub(crate) struct Session<C: ClientInnerExt> {
+    pub(crate) client: Client<C>,
+    /// Usage metrics of the endpoint. Holding the counter keeps the endpoint
+    /// reported as active for as long as the session is open.
+    pub(crate) metrics: Arc<MetricCounter>,
+    last_used: Instant,
+    _slot: SessionSlot,
+}
+
+impl<C: ClientInnerExt> Session<C> {
+    pub(crate) fn touch(&mut self) {
+        self.last_used = Instant::now();
+    }
+}
+
+impl<C: ClientInnerExt> Drop for Session<C> {
+    fn drop(&mut self) {
+        Metrics::get().proxy.http_sessions.get_metric().dec();
+    }
+}
+
+/// Counts towards the open sessions of an endpoint. The session holds it, so that a session
+/// which was removed still counts until the requests using it complete and its connection is closed.
+pub(crate) struct SessionSlot {
+    endpoint: EndpointIdInt,
+    per_endpoint: Arc<DashMap<EndpointIdInt, usize>>,
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        self.per_endpoint.remove_if_mut(&self.endpoint, |_, count| {
+            *count -= 1;
+            *count == 0
+        });
+    }
+}
+
+/// Requests to the same session are serialized on the lock.
+pub(crate) type SharedSession<C> = Arc<tokio::sync::Mutex<Session<C>>>;
+
+struct SessionEntry<C: ClientInnerExt> {
+    /// Kept outside of the lock, so that the credentials can be checked while a query is running.
+    conn_info: ConnInfo,
+    session: SharedSession<C>,
+}
+
+pub(crate) struct HttpSessions<C: ClientInnerExt> {
+    sessions: DashMap<SessionToken, SessionEntry<C>>,
+    per_endpoint: Arc<DashMap<EndpointIdInt, usize>>,
+    idle_timeout: Duration,
+    max_sessions_per_endpoint: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum SessionError {
+    #[error("missing or invalid session id")]
+    InvalidToken,
+    #[error("session not found, it might have been closed after being idle for too long")]
+    NotFound,
+    #[error("too many open sessions for this endpoint (max is {0})")]
+    TooManySessions(usize),
+}
+
+impl ReportableError for SessionError {
+    fn get_error_kind(&self) -> ErrorKind {
+        match self {
+            SessionError::InvalidToken | SessionError::NotFound => ErrorKind::User,
+            SessionError::TooManySessions(_) => ErrorKind::RateLimit,
+        }
+    }
+}
+
+impl UserFacingError for SessionError {
+    fn to_string_client(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<C: ClientInnerExt> HttpSessions<C> {
+    pub(crate) fn new(idle_timeout: Duration, max_sessions_per_endpoint: usize) -> Arc<Self> {
+        Arc::new(Self {
+            sessions: DashMap::new(),
+            per_endpoint: Arc::new(DashMap::new()),
+            idle_timeout,
+            max_sessions_per_endpoint,
+        })
+    }
+
+    pub(crate) fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Reserves a session for the endpoint, before connecting for it.
+    pub(crate) fn reserve(&self, conn_info: &ConnInfo) -> Result<SessionSlot, SessionError> {
+        let endpoint = EndpointIdInt::from(&conn_info.user_info.endpoint);
+        let mut count = self.per_endpoint.entry(endpoint).or_insert(0);
+        if *count >= self.max_sessions_per_endpoint {
+            return Err(SessionError::TooManySessions(
+                self.max_sessions_per_endpoint,
+            ));
+        }
+        *count += 1;
+        Ok(SessionSlot {
+            endpoint,
+            per_endpoint: Arc::clone(&self.per_endpoint),
+        })
+    }
+
+    /// Takes over a client with an open transaction. The client must already be detached from the pool.
+    pub(crate) fn begin(
+        &self,
+        slot: SessionSlot,
+        conn_info: ConnInfo,
+        client: Client<C>,
+        metrics: Arc<MetricCounter>,
+    ) -> SessionToken {
+        let token = SessionToken::new_v4();
+        Metrics::get().proxy.http_sessions.get_metric().inc();
+        let session = Session {
+            client,
+            metrics,
+            last_used: Instant::now(),
+            _slot: slot,
+        };
+        self.sessions.insert(
+            token,
+            SessionEntry {
+                conn_info,
+                session: Arc::new(tokio::sync::Mutex::new(session)),
+            },
+        );
+        token
+    }
+
+    /// Finds the session, if the credentials match the ones the session was started with.
+    pub(crate) fn get(
+        &self,
+        token: &SessionToken,
+        conn_info: &ConnInfo,
+    ) -> Result<SharedSession<C>, SessionError> {
+        match self.sessions.get(token) {
+            Some(entry) if same_credentials(&entry.conn_info, conn_info) => {
+                Ok(entry.session.clone())
+            }
+            _ => Err(SessionError::NotFound),
+        }
+    }
+
+    /// Removes the session, so that no new requests can use it.
+    /// Requests that are already running will complete.
+    pub(crate) fn remove(
+        &self,
+        token: &SessionToken,
+        conn_info: &ConnInfo,
+    ) -> Result<SharedSession<C>, SessionError> {
+        self.sessions
+            .remove_if(token, |_, entry| {
+                same_credentials(&entry.conn_info, conn_info)
+            })
+            .map(|(_, entry)| entry.session)
+            .ok_or(SessionError::NotFound)
+    }
+
+    pub(crate) fn shutdown(&self) {
+        // closes the connections of all sessions that are not in use,
+        // the rest is closed once the requests complete.
+        self.sessions.clear();
+    }
+
+    pub(crate) async fn gc_worker(&self) {
+        let mut interval =
+            tokio::time::interval((self.idle_timeout / 2).max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            self.gc();
+        }
+    }
+
+    fn gc(&self) {
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, entry| match entry.session.try_lock() {
+                Ok(session) => session.last_used.elapsed() < self.idle_timeout,
+                // in use
+                Err(_) => true,
+            });
+        let removed = before.saturating_sub(self.sessions.len());
+        if removed > 0 {
+            info!(removed, "closed idle sql over http sessions");
+        } else {
+            debug!("no idle sql over http sessions");
+        }
+    }
+}
+
+/// Checks that the request authenticates as the same user as the request that began the session.
+pub(crate) fn same_credentials(session: &ConnInfo, request: &ConnInfo) -> bool {
+    // Constant time, so that the credentials can't be guessed from the response time
+    let same_auth = match (&session.auth, &request.auth) {
+        (AuthData::Password(a), AuthData::Password(b)) => bool::from(a[..].ct_eq(&b[..])),
+        (AuthData::Jwt(a), AuthData::Jwt(b)) => bool::from(a.as_bytes().ct_eq(b.as_bytes())),
+        _ => false,
+    };
+    same_auth
+        && session.user_info.endpoint == request.user_info.endpoint
+        && session.user_info.user == request.user_info.user
+        && session.dbname == request.dbname
+}
+
+/// Builds the statement that opens the session transaction.
+pub(crate) fn begin_statement(
+    isolation_level: Option<IsolationLevel>,
+    read_only: bool,
+    deferrable: bool,
+) -> String {
+    let mut modes = vec![];
+    match isolation_level {
+        Some(IsolationLevel::ReadUncommitted) => modes.push("ISOLATION LEVEL READ UNCOMMITTED"),
+        Some(IsolationLevel::ReadCommitted) => modes.push("ISOLATION LEVEL READ COMMITTED"),
+        Some(IsolationLevel::RepeatableRead) => modes.push("ISOLATION LEVEL REPEATABLE READ"),
+        Some(IsolationLevel::Serializable) => modes.push("ISOLATION LEVEL SERIALIZABLE"),
+        _ => {}
+    }
+    if read_only {
+        modes.push("READ ONLY");
+    }
+    if deferrable {
+        modes.push("DEFERRABLE");
+    }
+
+    if modes.is_empty() {
+        "BEGIN".to_string()
+    } else {
+        format!("BEGIN {}", modes.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{auth::backend::ComputeUserInfo, proxy::NeonOptions};
+
+    use super::*;
+
+    fn conn_info(user: &str, auth: AuthData) -> ConnInfo {
+        ConnInfo {
+            user_info: ComputeUserInfo {
+                user: user.into(),
+                endpoint: "endpoint".into(),
+                options: NeonOptions::default(),
+            },
+            dbname: "dbname".into(),
+            auth,
+        }
+    }
+
+    #[test]
+    fn test_same_credentials() {
+        let password = |p: &str| AuthData::Password(p.as_bytes().into());
+        let session = conn_info("user", password("password"));
+
+        assert!(same_credentials(
+            &session,
+            &conn_info("user", password("password"))
+        ));
+        assert!(!same_credentials(
+            &session,
+            &conn_info("user", password("wrong"))
+        ));
+        assert!(!same_credentials(
+            &session,
+            &conn_info("other", password("password"))
+        ));
+        assert!(!same_credentials(
+            &session,
+            &conn_info("user", AuthData::Jwt("password".to_string()))
+        ));
+    }
+
+    #[test]
+    fn test_same_credentials_jwt() {
+        let session = conn_info("user", AuthData::Jwt("token".to_string()));
+
+        assert!(same_credentials(
+            &session,
+            &conn_info("user", AuthData::Jwt("token".to_string()))
+        ));
+        assert!(!same_credentials(
+            &session,
+            &conn_info("user", AuthData::Jwt("token2".to_string()))
+        ));
+    }
+
+    #[test]
+    fn test_max_sessions_per_endpoint() {
+        let sessions = HttpSessions::<tokio_postgres::Client>::new(Duration::from_secs(1), 2);
+        let info = conn_info("user", AuthData::Jwt("token".to_string()));
+
+        let first = sessions.reserve(&info).unwrap();
+        let _second = sessions.reserve(&info).unwrap();
+        assert!(matches!(
+            sessions.reserve(&info),
+            Err(SessionError::TooManySessions(2))
+        ));
+
+        // Closing a session frees its slot
+        drop(first);
+        let _third = sessions.reserve(&info).unwrap();
+        assert!(sessions.reserve(&info).is_err());
+    }
+
+    #[test]
+    fn test_begin_statement() {
+        assert_eq!(begin_statement(None, false, false), "BEGIN");
+        assert_eq!(
+            begin_statement(Some(IsolationLevel::Serializable), true, true),
+            "BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY, DEFERRABLE"
+        );
+        assert_eq!(
+            begin_statement(Some(IsolationLevel::ReadCommitted), false, false),
+            "BEGIN ISOLATION LEVEL READ COMMITTED"
+        );
+    }
+}
@@ -29,7 +29,6 @@
 use tokio_postgres::IsolationLevel;
 use tokio_postgres::NoTls;
 use tokio_postgres::ReadyForQueryStatus;
-use tokio_postgres::Transaction;
 use tokio_util::sync::CancellationToken;
 use tracing::error;
 use tracing::info;
@@ -43,6 +42,8 @@
 use crate::auth::ComputeUserInfoParseError;
 use crate::config::ProxyConfig;
 use crate::config::TlsConfig;
+use crate::context::audit::AuditLog;
+use crate::context::audit::AuditedStatement;
 use crate::context::RequestMonitoring;
 use crate::error::ErrorKind;
 use crate::error::ReportableError;
@@ -52,6 +53,7 @@
 use crate::proxy::run_until_cancelled;
 use crate::proxy::NeonOptions;
 use crate::serverless::backend::HttpConnError;
+use crate::usage_metrics::MetricCounter;
 use crate::usage_metrics::MetricCounterRecorder;
 use crate::DbName;
 use crate::RoleName;
@@ -60,6 +62,10 @@
 use super::conn_pool::AuthData;
 use super::conn_pool::Client;
 use super::conn_pool::ConnInfo;
+use super::http_session::begin_statement;
+use super::http_session::Session;
+use super::http_session::SessionError;
+use super::http_session::SessionToken;
 use super::http_util::json_response;
 use super::json::json_to_pg_text;
 use super::json::pg_text_row_to_json;
@@ -97,6 +103,7 @@ enum Payload {
 static TXN_ISOLATION_LEVEL: HeaderName = HeaderName::from_static("neon-batch-isolation-level");
 static TXN_READ_ONLY: HeaderName = HeaderName::from_static("neon-batch-read-only");
 static TXN_DEFERRABLE: HeaderName = HeaderName::from_static("neon-batch-deferrable");
+static SESSION_ID: HeaderName = HeaderName::from_static("neon-session-id");
 
 static HEADER_VALUE_TRUE: HeaderValue = HeaderValue::from_static("true");
 
@@ -378,6 +385,8 @@ pub(crate) enum SqlOverHttpError {
     JsonConversion(#[from] JsonConversionError),
     #[error("{0}")]
     Cancelled(SqlOverHttpCancel),
+    #[error("{0}")]
+    Session(#[from] SessionError),
 }
 
 impl ReportableError for SqlOverHttpError {
@@ -392,6 +401,7 @@ fn get_error_kind(&self) -> ErrorKind {
             SqlOverHttpError::Postgres(p) => p.get_error_kind(),
             SqlOverHttpError::JsonConversion(_) => ErrorKind::Postgres,
             SqlOverHttpError::Cancelled(c) => c.get_error_kind(),
+            SqlOverHttpError::Session(s) => s.get_error_kind(),
         }
     }
 }
@@ -408,6 +418,7 @@ fn to_string_client(&self) -> String {
             SqlOverHttpError::Postgres(p) => p.to_string(),
             SqlOverHttpError::JsonConversion(_) => "could not parse postgres response".to_string(),
             SqlOverHttpError::Cancelled(_) => self.to_string(),
+            SqlOverHttpError::Session(s) => s.to_string_client(),
         }
     }
 }
@@ -483,6 +494,19 @@ fn try_parse(headers: &hyper1::http::HeaderMap) -> Result<Self, SqlOverHttpError
     }
 }
 
+fn parse_session_token(headers: &HeaderMap) -> Result<Option<SessionToken>, SessionError> {
+    headers
+        .get(&SESSION_ID)
+        .map(|token| {
+            token
+                .to_str()
+                .ok()
+                .and_then(|token| SessionToken::parse_str(token).ok())
+                .ok_or(SessionError::InvalidToken)
+        })
+        .transpose()
+}
+
 fn map_header_to_isolation_level(level: &HeaderValue) -> Option<IsolationLevel> {
     match level.as_bytes() {
         b"Serializable" => Some(IsolationLevel::Serializable),
@@ -534,6 +558,40 @@ async fn handle_inner(
         || headers.get(&ALLOW_POOL) == Some(&HEADER_VALUE_TRUE);
 
     let parsed_headers = HttpHeaders::try_parse(headers)?;
+    let session_token = parse_session_token(headers)?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json");
+
+    // Session commands don't have a payload.
+    match request.uri().path() {
+        "/sql/session" => {
+            let (json_output, metrics, token) = begin_session(
+                cancel,
+                config,
+                ctx,
+                &backend,
+                conn_info,
+                allow_pool,
+                parsed_headers,
+            )
+            .await?;
+            let response = response.header(SESSION_ID.clone(), token.to_string());
+            return Ok(build_response(response, json_output, &metrics));
+        }
+        "/sql/session/commit" => {
+            let (json_output, metrics) =
+                end_session(ctx, config, &backend, session_token, &conn_info, "COMMIT").await?;
+            return Ok(build_response(response, json_output, &metrics));
+        }
+        "/sql/session/rollback" => {
+            let (json_output, metrics) =
+                end_session(ctx, config, &backend, session_token, &conn_info, "ROLLBACK").await?;
+            return Ok(build_response(response, json_output, &metrics));
+        }
+        _ => {}
+    }
 
     let request_content_length = match request.body().size_hint().upper() {
         Some(v) => v,
@@ -561,35 +619,32 @@ async fn handle_inner(
         .map_err(SqlOverHttpError::from),
     );
 
-    let authenticate_and_connect = Box::pin(
-        async {
-            let keys = match &conn_info.auth {
-                AuthData::Password(pw) => {
-                    backend
-                        .authenticate_with_password(
-                            ctx,
-                            &config.authentication_config,
-                            &conn_info.user_info,
-                            pw,
-                        )
-                        .await?
-                }
-                AuthData::Jwt(jwt) => {
-                    backend
-                        .authenticate_with_jwt(ctx, &conn_info.user_info, jwt)
-                        .await?
-                }
-            };
+    if let Some(token) = session_token {
+        // The session has authenticated with these credentials already, but the allowlist
+        // and the rate limit might have changed since.
+        backend
+            .check_access(ctx, &config.authentication_config, &conn_info.user_info)
+            .await
+            .map_err(HttpConnError::from)?;
+        let session = backend.sessions.get(&token, &conn_info)?;
+        let payload = fetch_and_process_request.await?;
+        let (json_output, metrics) = session_query(
+            cancel,
+            ctx,
+            &backend,
+            token,
+            &conn_info,
+            &session,
+            payload,
+            parsed_headers,
+        )
+        .await?;
+        return Ok(build_response(response, json_output, &metrics));
+    }
 
-            let client = backend
-                .connect_to_compute(ctx, conn_info, keys, !allow_pool)
-                .await?;
-            // not strictly necessary to mark success here,
-            // but it's just insurance for if we forget it somewhere else
-            ctx.success();
-            Ok::<_, HttpConnError>(client)
-        }
-        .map_err(SqlOverHttpError::from),
+    let authenticate_and_connect = Box::pin(
+        authenticate_and_connect(ctx, config, &backend, conn_info, allow_pool)
+            .map_err(SqlOverHttpError::from),
     );
 
     let (payload, mut client) = match run_until_cancelled(
@@ -606,13 +661,15 @@ async fn handle_inner(
         None => return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Connect)),
     };
 
-    let mut response = Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json");
+    let mut response = response;
+    let audit = ctx.audit_log(client.aux());
 
     // Now execute the query and return the result.
     let json_output = match payload {
-        Payload::Single(stmt) => stmt.process(cancel, &mut client, parsed_headers).await?,
+        Payload::Single(stmt) => {
+            stmt.process(cancel, &mut client, parsed_headers, &audit)
+                .await?
+        }
         Payload::Batch(statements) => {
             if parsed_headers.txn_read_only {
                 response = response.header(TXN_READ_ONLY.clone(), &HEADER_VALUE_TRUE);
@@ -628,13 +685,53 @@ async fn handle_inner(
             }
 
             statements
-                .process(cancel, &mut client, parsed_headers)
+                .process(cancel, &mut client, parsed_headers, &audit)
                 .await?
         }
     };
 
-    let metrics = client.metrics();
+    Ok(build_response(response, json_output, &client.metrics()))
+}
 
+async fn authenticate_and_connect(
+    ctx: &RequestMonitoring,
+    config: &'static ProxyConfig,
+    backend: &PoolingBackend,
+    conn_info: ConnInfo,
+    allow_pool: bool,
+) -> Result<Client<tokio_postgres::Client>, HttpConnError> {
+    let keys = match &conn_info.auth {
+        AuthData::Password(pw) => {
+            backend
+                .authenticate_with_password(
+                    ctx,
+                    &config.authentication_config,
+                    &conn_info.user_info,
+                    pw,
+                )
+                .await?
+        }
+        AuthData::Jwt(jwt) => {
+            backend
+                .authenticate_with_jwt(ctx, &conn_info.user_info, jwt)
+                .await?
+        }
+    };
+
+    let client = backend
+        .connect_to_compute(ctx, conn_info, keys, !allow_pool)
+        .await?;
+    // not strictly necessary to mark success here,
+    // but it's just insurance for if we forget it somewhere else
+    ctx.success();
+    Ok(client)
+}
+
+fn build_response(
+    response: hyper1::http::response::Builder,
+    json_output: String,
+    metrics: &MetricCounter,
+) -> Response<Full<Bytes>> {
     let len = json_output.len();
     let response = response
         .body(Full::new(Bytes::from(json_output)))
@@ -650,7 +747,148 @@ async fn handle_inner(
         .http_conn_content_length_bytes
         .observe(HttpDirection::Response, len as f64);
 
-    Ok(response)
+    response
+}
+
+/// Connects and opens the session transaction.
+///
+/// The connection is taken out of the pool until the session is committed or rolled back.
+async fn begin_session(
+    cancel: CancellationToken,
+    config: &'static ProxyConfig,
+    ctx: &RequestMonitoring,
+    backend: &PoolingBackend,
+    conn_info: ConnInfo,
+    allow_pool: bool,
+    parsed_headers: HttpHeaders,
+) -> Result<(String, Arc<MetricCounter>, SessionToken), SqlOverHttpError> {
+    // Held until the session is closed, sessions don't count towards the pool limits.
+    let slot = backend.sessions.reserve(&conn_info)?;
+    let connect = authenticate_and_connect(ctx, config, backend, conn_info.clone(), allow_pool);
+    let mut client = match run_until_cancelled(connect, &cancel).await {
+        Some(client) => client?,
+        None => return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Connect)),
+    };
+
+    let statement = begin_statement(
+        parsed_headers.txn_isolation_level,
+        parsed_headers.txn_read_only,
+        parsed_headers.txn_deferrable,
+    );
+    info!(statement, "beginning session");
+    let (inner, mut discard) = client.inner();
+    inner.batch_execute(&statement).await.inspect_err(|_| {
+        // the connection is in an unknown state, don't return it to the pool
+        discard.discard();
+    })?;
+
+    client.detach();
+    let metrics = client.metrics();
+    let token = backend
+        .sessions
+        .begin(slot, conn_info, client, metrics.clone());
+
+    let json_output = serde_json::to_string(&json!({
+        "sessionId": token.to_string(),
+        "idleTimeout": backend.sessions.idle_timeout().as_secs(),
+    }))
+    .expect("json serialization should not fail");
+
+    Ok((json_output, metrics, token))
+}
+
+/// Commits or rolls back the session transaction, and returns the connection to the pool.
+async fn end_session(
+    ctx: &RequestMonitoring,
+    config: &'static ProxyConfig,
+    backend: &PoolingBackend,
+    token: Option<SessionToken>,
+    conn_info: &ConnInfo,
+    statement: &'static str,
+) -> Result<(String, Arc<MetricCounter>), SqlOverHttpError> {
+    let token = token.ok_or(SessionError::InvalidToken)?;
+    backend
+        .check_access(ctx, &config.authentication_config, &conn_info.user_info)
+        .await
+        .map_err(HttpConnError::from)?;
+    // No new requests can use the session from now on.
+    let session = backend.sessions.remove(&token, conn_info)?;
+    // Wait for the running requests to finish.
+    let mut session = session.lock().await;
+
+    info!(statement, "ending session");
+    // If this fails, the session is dropped without attaching, which closes the connection.
+    let status = session.client.batch_execute(statement).await?;
+    session.client.attach(status);
+
+    let json_output = serde_json::to_string(&json!({ "command": statement }))
+        .expect("json serialization should not fail");
+
+    Ok((json_output, session.metrics.clone()))
+}
+
+/// Runs the queries inside the session transaction.
+///
+/// Postgres errors leave the transaction aborted, but the session stays open until it's rolled back.
+/// Any other error closes the session, as the connection might be in an unknown state.
+async fn session_query(
+    cancel: CancellationToken,
+    ctx: &RequestMonitoring,
+    backend: &PoolingBackend,
+    token: SessionToken,
+    conn_info: &ConnInfo,
+    session: &tokio::sync::Mutex<Session<tokio_postgres::Client>>,
+    payload: Payload,
+    parsed_headers: HttpHeaders,
+) -> Result<(String, Arc<MetricCounter>), SqlOverHttpError> {
+    let mut session = session.lock().await;
+    session.touch();
+    let audit = ctx.audit_log(session.client.aux());
+    let client: &tokio_postgres::Client = &session.client;
+    let cancel_token = client.cancel_token();
+
+    let res = match payload {
+        Payload::Single(stmt) => {
+            let query = query_to_json(client, stmt, &mut 0, parsed_headers, &audit);
+            match run_until_cancelled(query, &cancel).await {
+                Some(Ok((_, results))) => Ok(
+                    serde_json::to_string(&results).expect("json serialization should not fail")
+                ),
+                Some(Err(e)) => Err(e),
+                None => Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Postgres)),
+            }
+        }
+        Payload::Batch(statements) => {
+            query_batch(
+                cancel.child_token(),
+                client,
+                statements,
+                parsed_headers,
+                &audit,
+            )
+            .await
+        }
+    };
+    session.touch();
+
+    match res {
+        Ok(json_output) => Ok((json_output, session.metrics.clone())),
+        Err(SqlOverHttpError::Postgres(e)) if e.as_db_error().is_some() => {
+            Err(SqlOverHttpError::Postgres(e))
+        }
+        Err(e) => {
+            if let SqlOverHttpError::Cancelled(_) = e {
+                tracing::info!("cancelling query");
+                if let Err(err) = cancel_token.cancel_query(NoTls).await {
+                    tracing::error!(?err, "could not cancel query");
+                }
+            }
+            info!("closing session");
+            // The connection is closed once the last request releases the session.
+            let _ = backend.sessions.remove(&token, conn_info);
+            Err(e)
+        }
+    }
 }
 
 impl QueryData {
@@ -659,12 +897,13 @@ async fn process(
         cancel: CancellationToken,
         client: &mut Client<tokio_postgres::Client>,
         parsed_headers: HttpHeaders,
+        audit: &AuditLog,
     ) -> Result<String, SqlOverHttpError> {
         let (inner, mut discard) = client.inner();
         let cancel_token = inner.cancel_token();
 
         let res = match select(
-            pin!(query_to_json(&*inner, self, &mut 0, parsed_headers)),
+            pin!(query_to_json(&*inner, self, &mut 0, parsed_headers, audit)),
             pin!(cancel.cancelled()),
         )
         .await
@@ -730,6 +969,7 @@ async fn process(
         cancel: CancellationToken,
         client: &mut Client<tokio_postgres::Client>,
         parsed_headers: HttpHeaders,
+        audit: &AuditLog,
     ) -> Result<String, SqlOverHttpError> {
         info!("starting transaction");
         let (inner, mut discard) = client.inner();
@@ -751,57 +991,66 @@ async fn process(
             discard.discard();
         })?;
 
-        let json_output =
-            match query_batch(cancel.child_token(), &transaction, self, parsed_headers).await {
-                Ok(json_output) => {
-                    info!("commit");
-                    let status = transaction.commit().await.inspect_err(|_| {
-                        // if we cannot commit - for now don't return connection to pool
-                        // TODO: get a query status from the error
-                        discard.discard();
-                    })?;
-                    discard.check_idle(status);
-                    json_output
-                }
-                Err(SqlOverHttpError::Cancelled(_)) => {
-                    if let Err(err) = cancel_token.cancel_query(NoTls).await {
-                        tracing::error!(?err, "could not cancel query");
-                    }
-                    // TODO: after cancelling, wait to see if we can get a status. maybe the connection is still safe.
+        let json_output = match query_batch(
+            cancel.child_token(),
+            &transaction,
+            self,
+            parsed_headers,
+            audit,
+        )
+        .await
+        {
+            Ok(json_output) => {
+                info!("commit");
+                let status = transaction.commit().await.inspect_err(|_| {
+                    // if we cannot commit - for now don't return connection to pool
+                    // TODO: get a query status from the error
                     discard.discard();
-
-                    return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Postgres));
-                }
-                Err(err) => {
-                    info!("rollback");
-                    let status = transaction.rollback().await.inspect_err(|_| {
-                        // if we cannot rollback - for now don't return connection to pool
-                        // TODO: get a query status from the error
-                        discard.discard();
-                    })?;
-                    discard.check_idle(status);
-                    return Err(err);
+                })?;
+                discard.check_idle(status);
+                json_output
+            }
+            Err(SqlOverHttpError::Cancelled(_)) => {
+                if let Err(err) = cancel_token.cancel_query(NoTls).await {
+                    tracing::error!(?err, "could not cancel query");
                 }
-            };
+                // TODO: after cancelling, wait to see if we can get a status. maybe the connection is still safe.
+                discard.discard();
+
+                return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Postgres));
+            }
+            Err(err) => {
+                info!("rollback");
+                let status = transaction.rollback().await.inspect_err(|_| {
+                    // if we cannot rollback - for now don't return connection to pool
+                    // TODO: get a query status from the error
+                    discard.discard();
+                })?;
+                discard.check_idle(status);
+                return Err(err);
+            }
+        };
 
         Ok(json_output)
     }
 }
 
-async fn query_batch(
+async fn query_batch<T: GenericClient>(
     cancel: CancellationToken,
-    transaction: &Transaction<'_>,
+    client: &T,
     queries: BatchQueryData,
     parsed_headers: HttpHeaders,
+    audit: &AuditLog,
 ) -> Result<String, SqlOverHttpError> {
     let mut results = Vec::with_capacity(queries.queries.len());
     let mut current_size = 0;
     for stmt in queries.queries {
         let query = pin!(query_to_json(
-            transaction,
+            client,
             stmt,
             &mut current_size,
             parsed_headers,
+            audit,
         ));
         let cancelled = pin!(cancel.cancelled());
         let res = select(query, cancelled).await;
@@ -825,11 +1074,42 @@ async fn query_batch(
     Ok(json_output)
 }
 
+/// Runs the query and records it in the audit log.
+///
+/// If the query is cancelled, the statement is dropped, and recorded as cancelled.
 async fn query_to_json<T: GenericClient>(
     client: &T,
     data: QueryData,
     current_size: &mut usize,
     parsed_headers: HttpHeaders,
+    audit: &AuditLog,
+) -> Result<(ReadyForQueryStatus, impl Serialize), SqlOverHttpError> {
+    let mut statement = audit.statement(&data.query);
+    let res = execute_query(
+        client,
+        data,
+        current_size,
+        parsed_headers,
+        statement.as_mut(),
+    )
+    .await;
+    if let (Some(statement), Err(e)) = (statement, &res) {
+        match e {
+            SqlOverHttpError::Postgres(e) if e.as_db_error().is_some() => {
+                statement.fail(e.code().map_or("", SqlState::code));
+            }
+            _ => statement.fail(SqlState::INTERNAL_ERROR.code()),
+        }
+    }
+    res
+}
+
+async fn execute_query<T: GenericClient>(
+    client: &T,
+    data: QueryData,
+    current_size: &mut usize,
+    parsed_headers: HttpHeaders,
+    statement: Option<&mut AuditedStatement>,
 ) -> Result<(ReadyForQueryStatus, impl Serialize), SqlOverHttpError> {
     info!("executing query");
     let query_params = data.params;
@@ -865,6 +1145,9 @@ async fn query_to_json<T: GenericClient>(
         command_tag_split.next()
     }
     .and_then(|s| s.parse::<i64>().ok());
+    if let Some(statement) = statement {
+        statement.add_result(command_tag_name, command_tag_count);
+    }
 
     info!(
         rows = rows.len(),
@@ -5,6 +5,7 @@
 
 use pq_proto::framed::{ConnectionError, Framed};
 use pq_proto::{BeMessage, FeMessage, FeStartupPacket, ProtocolError};
+use rustls::pki_types::CertificateDer;
 use rustls::ServerConfig;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -207,6 +208,14 @@ pub fn sni_hostname(&self) -> Option<&str> {
         }
     }
 
+    /// Return the certificate chain presented by the client, if any.
+    pub(crate) fn client_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        match self {
+            Stream::Raw { .. } => None,
+            Stream::Tls { tls, .. } => tls.get_ref().1.peer_certificates(),
+        }
+    }
+
     pub(crate) fn tls_server_end_point(&self) -> TlsServerEndPoint {
         match self {
             Stream::Raw { .. } => TlsServerEndPoint::Undefined,
@@ -297,6 +297,7 @@ async fn handle_timeline_status<IO: AsyncRead + AsyncWrite + Unpin>(
         pgb.write_message_noflush(&BeMessage::RowDescription(&[
             RowDescriptor::text_col(b"flush_lsn"),
             RowDescriptor::text_col(b"commit_lsn"),
+            RowDescriptor::text_col(b"wal_start_lsn"),
         ]))?;
 
         // Write row if timeline exists
@@ -304,9 +305,11 @@ async fn handle_timeline_status<IO: AsyncRead + AsyncWrite + Unpin>(
             let (inmem, _state) = tli.get_state().await;
             let flush_lsn = tli.get_flush_lsn().await;
             let commit_lsn = inmem.commit_lsn;
+            let wal_start_lsn = tli.get_wal_start_lsn().await;
             pgb.write_message_noflush(&BeMessage::DataRow(&[
                 Some(flush_lsn.to_string().as_bytes()),
                 Some(commit_lsn.to_string().as_bytes()),
+                Some(wal_start_lsn.to_string().as_bytes()),
             ]))?;
         }
 
@@ -24,6 +24,7 @@
     lsn::Lsn,
 };
 
+use postgres_ffi::v14::xlog_utils::XLogSegNoOffsetToRecPtr;
 use storage_broker::proto::SafekeeperTimelineInfo;
 use storage_broker::proto::TenantTimelineId as ProtoTenantTimelineId;
 
@@ -792,6 +793,28 @@ pub async fn get_flush_lsn(&self) -> Lsn {
         self.read_shared_state().await.sk.flush_lsn()
     }
 
+    /// Returns the oldest LSN that WAL can still be streamed from. With WAL backup enabled,
+    /// removed segments are read back from remote storage, so that's the start of the
+    /// timeline; otherwise it's the first segment that is still on disk.
+    pub async fn get_wal_start_lsn(&self) -> Lsn {
+        let (_, state) = self.get_state().await;
+        let wal_seg_size = state.server.wal_seg_size as usize;
+        if wal_seg_size == 0 {
+            return Lsn::INVALID;
+        }
+        let timeline_start_lsn = state.timeline_start_lsn.segment_lsn(wal_seg_size);
+        if GlobalTimelines::get_global_config().is_wal_backup_enabled() {
+            return timeline_start_lsn;
+        }
+        match self.last_removed_segno.load(Ordering::Relaxed) {
+            0 => timeline_start_lsn,
+            segno => max(
+                timeline_start_lsn,
+                Lsn(XLogSegNoOffsetToRecPtr(segno + 1, 0, wal_seg_size)),
+            ),
+        }
+    }
+
     /// Gather timeline data for metrics.
     pub async fn info_for_metrics(&self) -> Option<FullTimelineInfo> {
         if self.is_cancelled() {
@@ -0,0 +1 @@
+DROP TABLE rolling_operations;
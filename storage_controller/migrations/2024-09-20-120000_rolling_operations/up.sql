@@ -0,0 +1,9 @@
+CREATE TABLE rolling_operations (
+  id VARCHAR PRIMARY KEY NOT NULL,
+  request TEXT NOT NULL,
+  state VARCHAR NOT NULL,
+  nodes TEXT NOT NULL,
+  error TEXT,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE operation_events (
+  id BIGSERIAL PRIMARY KEY NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  kind VARCHAR NOT NULL,
+  actor VARCHAR NOT NULL,
+  tenant_id VARCHAR,
+  tenant_shard_id VARCHAR,
+  node_id BIGINT,
+  detail TEXT NOT NULL
+);
+
+CREATE INDEX operation_events_timestamp ON operation_events (timestamp);
+CREATE INDEX operation_events_tenant_id ON operation_events (tenant_id, timestamp);
+CREATE INDEX operation_events_node_id ON operation_events (node_id, timestamp);
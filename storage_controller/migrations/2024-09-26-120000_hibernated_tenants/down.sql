@@ -0,0 +1 @@
+DROP TABLE hibernated_tenants;
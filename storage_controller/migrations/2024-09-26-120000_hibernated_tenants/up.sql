@@ -0,0 +1,5 @@
+CREATE TABLE hibernated_tenants (
+  tenant_id VARCHAR PRIMARY KEY NOT NULL,
+  placement_policy VARCHAR NOT NULL,
+  hibernated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1 @@
+DROP TABLE compute_notifications;
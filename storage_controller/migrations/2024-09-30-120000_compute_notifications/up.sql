@@ -0,0 +1,8 @@
+CREATE TABLE compute_notifications (
+  tenant_id VARCHAR PRIMARY KEY NOT NULL,
+  request VARCHAR NOT NULL,
+  attempts INTEGER NOT NULL,
+  last_error VARCHAR,
+  first_failed_at TIMESTAMPTZ NOT NULL,
+  next_attempt_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,6 @@
+-- A single row holding the highest leader lease term that has taken over the database.  Controllers
+-- elected by lease refuse to write once a newer term is recorded here.
+CREATE TABLE leader_fence (
+  term BIGINT PRIMARY KEY NOT NULL
+);
+INSERT INTO leader_fence (term) VALUES (0);
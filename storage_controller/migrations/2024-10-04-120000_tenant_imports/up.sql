@@ -0,0 +1,9 @@
+CREATE TABLE tenant_imports (
+  tenant_id VARCHAR PRIMARY KEY NOT NULL,
+  request TEXT NOT NULL,
+  state VARCHAR NOT NULL,
+  shards TEXT NOT NULL,
+  error TEXT,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1 @@
+DROP INDEX rolling_operations_active;
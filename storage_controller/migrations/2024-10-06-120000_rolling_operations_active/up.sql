@@ -0,0 +1,2 @@
+-- At most one rolling operation may be in progress
+CREATE UNIQUE INDEX rolling_operations_active ON rolling_operations ((true)) WHERE state IN ('running', 'paused');
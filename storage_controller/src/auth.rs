@@ -1,4 +1,5 @@
 use utils::auth::{AuthError, Claims, Scope};
+use utils::id::TenantId;
 
 pub fn check_permission(claims: &Claims, required_scope: Scope) -> Result<(), AuthError> {
     if claims.scope != required_scope {
@@ -7,3 +8,14 @@ pub fn check_permission(claims: &Claims, required_scope: Scope) -> Result<(), Au
 
     Ok(())
 }
+
+/// Accepts a token of the tenant's own scope, like computes use for the pageservers and
+/// safekeepers of their tenant.
+pub fn check_tenant_permission(claims: &Claims, tenant_id: TenantId) -> Result<(), AuthError> {
+    check_permission(claims, Scope::Tenant)?;
+    if claims.tenant_id != Some(tenant_id) {
+        return Err(AuthError("Tenant id mismatch. Permission denied".into()));
+    }
+
+    Ok(())
+}
@@ -1,26 +1,52 @@
+//! The compute hook tells computes (via the control plane in production) which pageservers
+//! to use for a tenant.  Notifications may be delivered to one of several sinks: an HTTP
+//! endpoint, a local file or unix socket, a command, or neon_local's endpoints directly.
+//!
+//! Notifications that fail to deliver are persisted in the database and retried with
+//! backoff by whichever controller is the leader, until they or a newer notification for the
+//! same tenant succeed.  Notifications that fail with a non-retryable error are dropped.
+
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, time::Duration};
 
 use control_plane::endpoint::{ComputeControlPlane, EndpointStatus};
 use control_plane::local_env::LocalEnv;
 use futures::StreamExt;
+use hmac::{Hmac, Mac};
 use hyper::StatusCode;
+use pageserver_api::controller_api::PendingComputeNotification;
 use pageserver_api::shard::{ShardCount, ShardNumber, ShardStripeSize, TenantShardId};
 use postgres_connection::parse_host_port;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio_util::sync::CancellationToken;
 use tracing::{info_span, Instrument};
 use utils::{
-    backoff::{self},
+    backoff::{self, exponential_backoff_duration_seconds},
     id::{NodeId, TenantId},
 };
 
+use crate::persistence::{ComputeNotificationPersistence, DatabaseResult, Persistence};
 use crate::service::Config;
 
 const SLOWDOWN_DELAY: Duration = Duration::from_secs(5);
 
 const NOTIFY_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Backoff between attempts to deliver a persisted notification: doubles with each failure,
+/// up to the maximum.
+const RETRY_BACKOFF_BASE: f64 = 1.0;
+const RETRY_BACKOFF_MAX: f64 = 600.0;
+
+/// When signing notifications, we send the time of signing in this header, and an HMAC-SHA256 of
+/// `<timestamp>.<body>` in the signature header, as `v1=<hex>`.  Receivers should reject
+/// notifications with stale timestamps, to prevent replays.
+const SIGNATURE_TIMESTAMP_HEADER: &str = "Neon-Timestamp";
+const SIGNATURE_HEADER: &str = "Neon-Signature";
+
 pub(crate) const API_CONCURRENCY: usize = 32;
 
 struct UnshardedComputeHookTenant {
@@ -64,6 +90,38 @@ fn new(tenant_shard_id: TenantShardId, stripe_size: ShardStripeSize, node_id: No
         }
     }
 
+    /// Reconstruct a tenant's state from a notification that we persisted because it failed
+    /// to deliver.  Returns None if the notification is malformed.
+    fn from_request(request: &ComputeHookNotifyRequest) -> Option<Self> {
+        match request.stripe_size {
+            Some(stripe_size) => Some(Self::Sharded(ShardedComputeHookTenant {
+                stripe_size,
+                shard_count: ShardCount::new(u8::try_from(request.shards.len()).ok()?),
+                shards: request
+                    .shards
+                    .iter()
+                    .map(|s| (s.shard_number, s.node_id))
+                    .collect(),
+                send_lock: Arc::default(),
+            })),
+            None => Some(Self::Unsharded(UnshardedComputeHookTenant {
+                node_id: request.shards.first()?.node_id,
+                send_lock: Arc::default(),
+            })),
+        }
+    }
+
+    /// Whether we know a location for every shard, i.e. whether [`Self::maybe_send`] would
+    /// generate a request if it hadn't already been sent.
+    fn is_ready(&self) -> bool {
+        match self {
+            Self::Unsharded(_) => true,
+            Self::Sharded(sharded_tenant) => {
+                sharded_tenant.shards.len() == sharded_tenant.shard_count.count() as usize
+            }
+        }
+    }
+
     fn get_send_lock(&self) -> &Arc<tokio::sync::Mutex<Option<ComputeHookNotifyRequest>>> {
         match self {
             Self::Unsharded(unsharded_tenant) => &unsharded_tenant.send_lock,
@@ -149,6 +207,64 @@ pub(crate) enum NotifyError {
 
     #[error("neon_local error: {0}")]
     NeonLocal(anyhow::Error),
+
+    // A file, socket or command sink failed
+    #[error("Sink error: {0}")]
+    Sink(anyhow::Error),
+}
+
+/// Where compute notifications are delivered
+enum ComputeHookSink {
+    /// PUT the notification to a URL (the control plane, in production), optionally signed with
+    /// an HMAC of the body
+    Http {
+        url: String,
+        hmac_key: Option<String>,
+    },
+    /// Append the notification to a file, as a line of JSON
+    File(PathBuf),
+    /// Write the notification to a unix socket as a line of JSON, and read back a line of
+    /// acknowledgement, which must be `ok`
+    Socket(PathBuf),
+    /// Run a shell command with the notification as JSON on its stdin: a non-zero exit status
+    /// is a failure
+    Command(String),
+    /// Reconfigure neon_local's endpoints directly: for test environments
+    NeonLocal,
+}
+
+impl ComputeHookSink {
+    fn new(config: &Config) -> Self {
+        if let Some(url) = &config.compute_hook_url {
+            Self::Http {
+                url: url.clone(),
+                hmac_key: config.compute_hook_hmac_key.clone(),
+            }
+        } else if let Some(path) = &config.compute_hook_file {
+            Self::File(path.clone())
+        } else if let Some(path) = &config.compute_hook_socket {
+            Self::Socket(path.clone())
+        } else if let Some(command) = &config.compute_hook_command {
+            Self::Command(command.clone())
+        } else {
+            Self::NeonLocal
+        }
+    }
+}
+
+/// In-memory record of a tenant's row in the database's pending notifications
+struct PendingNotification {
+    attempts: u32,
+    first_failed_at: chrono::DateTime<chrono::Utc>,
+}
+
+fn sign_notification(key: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
 }
 
 enum MaybeSendResult {
@@ -240,15 +356,22 @@ fn maybe_send(
 /// the compute connection string.
 pub(super) struct ComputeHook {
     config: Config,
+    sink: ComputeHookSink,
     state: std::sync::Mutex<HashMap<TenantId, ComputeHookTenant>>,
     authorization_header: Option<String>,
 
+    persistence: Arc<Persistence>,
+
+    // Tenants for which the database holds an undelivered notification.  Refreshed from the
+    // database by [`Self::retry_pending`], so that it is accurate after a change of leader.
+    pending: std::sync::Mutex<HashMap<TenantId, PendingNotification>>,
+
     // Concurrency limiter, so that we do not overload the cloud control plane when updating
     // large numbers of tenants (e.g. when failing over after a node failure)
     api_concurrency: tokio::sync::Semaphore,
 
-    // This lock is only used in testing enviroments, to serialize calls into neon_lock
-    neon_local_lock: tokio::sync::Mutex<()>,
+    // Serializes calls into neon_local (in testing environments), and writes to a notification file
+    local_lock: tokio::sync::Mutex<()>,
 
     // We share a client across all notifications to enable connection re-use etc when
     // sending large numbers of notifications
@@ -256,7 +379,7 @@ pub(super) struct ComputeHook {
 }
 
 impl ComputeHook {
-    pub(super) fn new(config: Config) -> Self {
+    pub(super) fn new(config: Config, persistence: Arc<Persistence>) -> Self {
         let authorization_header = config
             .control_plane_jwt_token
             .clone()
@@ -269,9 +392,12 @@ pub(super) fn new(config: Config) -> Self {
 
         Self {
             state: Default::default(),
+            sink: ComputeHookSink::new(&config),
             config,
             authorization_header,
-            neon_local_lock: Default::default(),
+            persistence,
+            pending: Default::default(),
+            local_lock: Default::default(),
             api_concurrency: tokio::sync::Semaphore::new(API_CONCURRENCY),
             client,
         }
@@ -284,7 +410,7 @@ async fn do_notify_local(
     ) -> Result<(), NotifyError> {
         // neon_local updates are not safe to call concurrently, use a lock to serialize
         // all calls to this function
-        let _locked = self.neon_local_lock.lock().await;
+        let _locked = self.local_lock.lock().await;
 
         let Some(repo_dir) = self.config.neon_local_repo_dir.as_deref() else {
             tracing::warn!(
@@ -332,25 +458,158 @@ async fn do_notify_local(
         Ok(())
     }
 
+    /// Append the notification to a file
+    async fn do_notify_file(
+        &self,
+        path: &Path,
+        reconfigure_request: &ComputeHookNotifyRequest,
+    ) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(reconfigure_request)?;
+        line.push(b'\n');
+
+        // Serialize writers so that lines are never interleaved
+        let _locked = self.local_lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// Send the notification to a unix socket, and wait for it to be acknowledged
+    async fn do_notify_socket(
+        &self,
+        path: &Path,
+        reconfigure_request: &ComputeHookNotifyRequest,
+    ) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(reconfigure_request)?;
+        line.push(b'\n');
+
+        let exchange = async {
+            let mut stream = tokio::net::UnixStream::connect(path).await?;
+            stream.write_all(&line).await?;
+
+            let mut response = String::new();
+            tokio::io::BufReader::new(stream)
+                .read_line(&mut response)
+                .await?;
+            match response.trim_end() {
+                "ok" => Ok(()),
+                response => Err(anyhow::anyhow!("Peer responded {response:?}")),
+            }
+        };
+
+        tokio::time::timeout(NOTIFY_REQUEST_TIMEOUT, exchange)
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out"))?
+    }
+
+    /// Run a command with the notification on its stdin
+    async fn do_notify_command(
+        &self,
+        command: &str,
+        reconfigure_request: &ComputeHookNotifyRequest,
+    ) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(reconfigure_request)?;
+
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("NEON_TENANT_ID", reconfigure_request.tenant_id.to_string())
+            .stdin(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin.write_all(&body).await?;
+        drop(stdin);
+
+        let status = tokio::time::timeout(NOTIFY_REQUEST_TIMEOUT, child.wait())
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out"))??;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Command failed with {status}"))
+        }
+    }
+
     async fn do_notify_iteration(
         &self,
-        url: &String,
         reconfigure_request: &ComputeHookNotifyRequest,
         cancel: &CancellationToken,
     ) -> Result<(), NotifyError> {
-        let req = self.client.request(reqwest::Method::PUT, url);
+        match &self.sink {
+            ComputeHookSink::Http { url, hmac_key } => {
+                self.do_notify_http(url, hmac_key.as_deref(), reconfigure_request, cancel)
+                    .await
+            }
+            ComputeHookSink::File(path) => self
+                .do_notify_file(path, reconfigure_request)
+                .await
+                .map_err(NotifyError::Sink),
+            ComputeHookSink::Socket(path) => self
+                .do_notify_socket(path, reconfigure_request)
+                .await
+                .map_err(NotifyError::Sink),
+            ComputeHookSink::Command(command) => self
+                .do_notify_command(command, reconfigure_request)
+                .await
+                .map_err(NotifyError::Sink),
+            ComputeHookSink::NeonLocal => {
+                self.do_notify_local(reconfigure_request)
+                    .await
+                    .map_err(|e| {
+                        // This path is for testing only, so munge the error into our prod-style error type.
+                        tracing::error!("neon_local notification hook failed: {e}");
+                        NotifyError::Fatal(StatusCode::INTERNAL_SERVER_ERROR)
+                    })
+            }
+        }
+    }
+
+    async fn do_notify_http(
+        &self,
+        url: &str,
+        hmac_key: Option<&str>,
+        reconfigure_request: &ComputeHookNotifyRequest,
+        cancel: &CancellationToken,
+    ) -> Result<(), NotifyError> {
+        let body =
+            serde_json::to_vec(reconfigure_request).expect("Notification is always serializable");
+
+        let req = self
+            .client
+            .request(reqwest::Method::PUT, url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
         let req = if let Some(value) = &self.authorization_header {
             req.header(reqwest::header::AUTHORIZATION, value)
         } else {
             req
         };
+        let req = if let Some(key) = hmac_key {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            req.header(SIGNATURE_TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    SIGNATURE_HEADER,
+                    format!("v1={}", sign_notification(key, timestamp, &body)),
+                )
+        } else {
+            req
+        };
 
         tracing::info!(
             "Sending notify request to {} ({:?})",
             url,
             reconfigure_request
         );
-        let send_result = req.json(&reconfigure_request).send().await;
+        let send_result = req.body(body).send().await;
         let response = match send_result {
             Ok(r) => r,
             Err(e) => return Err(e.into()),
@@ -412,7 +671,6 @@ async fn do_notify_iteration(
 
     async fn do_notify(
         &self,
-        url: &String,
         reconfigure_request: &ComputeHookNotifyRequest,
         cancel: &CancellationToken,
     ) -> Result<(), NotifyError> {
@@ -427,11 +685,16 @@ async fn do_notify(
             .map_err(|_| NotifyError::ShuttingDown)?;
 
         backoff::retry(
-            || self.do_notify_iteration(url, reconfigure_request, cancel),
+            || self.do_notify_iteration(reconfigure_request, cancel),
             |e| {
+                // Local sinks are not retried here: failures are retried from the database by
+                // [`Self::retry_pending`], without holding up the caller.
                 matches!(
                     e,
-                    NotifyError::Fatal(_) | NotifyError::Unexpected(_) | NotifyError::Busy
+                    NotifyError::Fatal(_)
+                        | NotifyError::Unexpected(_)
+                        | NotifyError::Busy
+                        | NotifyError::Sink(_)
                 )
             },
             3,
@@ -472,7 +735,7 @@ fn notify_prepare(
     async fn notify_execute(
         &self,
         maybe_send_result: MaybeSendResult,
-        tenant_shard_id: TenantShardId,
+        tenant_id: TenantId,
         cancel: &CancellationToken,
     ) -> Result<(), NotifyError> {
         // Process result: we may get an update to send, or we may have to wait for a lock
@@ -493,10 +756,10 @@ async fn notify_execute(
                 // we have acquired the send lock and take `[Self::state]` lock.  This is safe because maybe_send only uses
                 // try_lock.
                 let state_locked = self.state.lock().unwrap();
-                let Some(tenant) = state_locked.get(&tenant_shard_id.tenant_id) else {
+                let Some(tenant) = state_locked.get(&tenant_id) else {
                     return Ok(());
                 };
-                match tenant.maybe_send(tenant_shard_id.tenant_id, Some(send_locked)) {
+                match tenant.maybe_send(tenant_id, Some(send_locked)) {
                     MaybeSendResult::AwaitLock(_) => {
                         unreachable!("We supplied lock guard")
                     }
@@ -509,15 +772,21 @@ async fn notify_execute(
             MaybeSendResult::Transmit((request, lock)) => (request, lock),
         };
 
-        let result = if let Some(notify_url) = &self.config.compute_hook_url {
-            self.do_notify(notify_url, &request, cancel).await
-        } else {
-            self.do_notify_local(&request).await.map_err(|e| {
-                // This path is for testing only, so munge the error into our prod-style error type.
-                tracing::error!("neon_local notification hook failed: {e}");
-                NotifyError::Fatal(StatusCode::INTERNAL_SERVER_ERROR)
-            })
-        };
+        let result = self.do_notify(&request, cancel).await;
+
+        // Update the persistent record while still holding the send lock, so that records of
+        // failures and successes are written in the same order as the sends.
+        match &result {
+            Ok(()) => self.clear_pending(tenant_id).await,
+            Err(NotifyError::ShuttingDown) => {}
+            Err(e @ NotifyError::Fatal(_)) => {
+                // Retrying would never succeed: don't keep it, or any older notification it
+                // supersedes, in the retry queue.
+                tracing::warn!(%tenant_id, "Dropping compute notification that can't be delivered: {e}");
+                self.clear_pending(tenant_id).await;
+            }
+            Err(e) => self.set_pending(&request, e).await,
+        }
 
         if result.is_ok() {
             // Before dropping the send lock, stash the request we just sent so that
@@ -527,6 +796,208 @@ async fn notify_execute(
         result
     }
 
+    /// Persist a notification that failed to deliver, so that it will be retried even if we
+    /// restart or lose leadership.
+    async fn set_pending(&self, request: &ComputeHookNotifyRequest, error: &NotifyError) {
+        let now = chrono::Utc::now();
+        let (attempts, first_failed_at) = {
+            let mut pending = self.pending.lock().unwrap();
+            let entry = pending
+                .entry(request.tenant_id)
+                .or_insert(PendingNotification {
+                    attempts: 0,
+                    first_failed_at: now,
+                });
+            entry.attempts += 1;
+            (entry.attempts, entry.first_failed_at)
+        };
+
+        let backoff = Duration::from_secs_f64(exponential_backoff_duration_seconds(
+            attempts,
+            RETRY_BACKOFF_BASE,
+            RETRY_BACKOFF_MAX,
+        ));
+        let next_attempt_at = now + chrono::Duration::from_std(backoff).unwrap_or_default();
+
+        let record = ComputeNotificationPersistence {
+            tenant_id: request.tenant_id.to_string(),
+            request: serde_json::to_string(request).expect("Notification is always serializable"),
+            attempts: attempts as i32,
+            last_error: Some(error.to_string()),
+            first_failed_at,
+            next_attempt_at,
+        };
+        if let Err(e) = self.persistence.upsert_compute_notification(record).await {
+            // Not fatal: the notification is still retried by reconciliation, we just lose
+            // the guarantee across restarts.
+            tracing::warn!(tenant_id=%request.tenant_id, "Failed to persist pending compute notification: {e}");
+        }
+    }
+
+    /// A notification was delivered: any persisted notification for the tenant is obsolete
+    async fn clear_pending(&self, tenant_id: TenantId) {
+        if self.pending.lock().unwrap().remove(&tenant_id).is_none() {
+            return;
+        }
+
+        if let Err(e) = self
+            .persistence
+            .delete_compute_notification(tenant_id)
+            .await
+        {
+            // Not fatal: the next call to [`Self::retry_pending`] will find that the tenant's
+            // latest state was already sent, and try to remove it again.
+            tracing::warn!(%tenant_id, "Failed to remove delivered compute notification: {e}");
+        }
+    }
+
+    /// Retry delivering persisted notifications that are due for another attempt.  This is
+    /// called periodically by the leader.  `tenant_exists` is used to clean up notifications
+    /// for deleted tenants.
+    pub(super) async fn retry_pending(
+        &self,
+        tenant_exists: impl Fn(&TenantId) -> bool,
+        cancel: &CancellationToken,
+    ) -> DatabaseResult<()> {
+        let records = self.persistence.list_compute_notifications().await?;
+
+        let now = chrono::Utc::now();
+        let mut due = Vec::new();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            pending.clear();
+            for record in records {
+                let request =
+                    match serde_json::from_str::<ComputeHookNotifyRequest>(&record.request) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            tracing::warn!(
+                                "Dropping malformed pending compute notification for {}: {e}",
+                                record.tenant_id
+                            );
+                            continue;
+                        }
+                    };
+                pending.insert(
+                    request.tenant_id,
+                    PendingNotification {
+                        attempts: record.attempts as u32,
+                        first_failed_at: record.first_failed_at,
+                    },
+                );
+                if record.next_attempt_at <= now {
+                    due.push(request);
+                }
+            }
+        }
+
+        let tenant_exists = &tenant_exists;
+        let mut stream = futures::stream::iter(due)
+            .map(|request| {
+                let tenant_id = request.tenant_id;
+                async move {
+                    if !tenant_exists(&tenant_id) {
+                        tracing::info!("Dropping pending compute notification for deleted tenant");
+                        if self.pending.lock().unwrap().remove(&tenant_id).is_some() {
+                            self.persistence
+                                .delete_compute_notification(tenant_id)
+                                .await?;
+                        }
+                        return Ok(());
+                    }
+
+                    if let Err(e) = self.retry_one(request, cancel).await {
+                        tracing::info!("Retry of compute notification failed: {e}");
+                    }
+                    Ok(())
+                }
+                .instrument(info_span!("retry_pending", %tenant_id))
+            })
+            .buffer_unordered(API_CONCURRENCY);
+
+        while let Some(result) = stream.next().await {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    async fn retry_one(
+        &self,
+        request: ComputeHookNotifyRequest,
+        cancel: &CancellationToken,
+    ) -> Result<(), NotifyError> {
+        let tenant_id = request.tenant_id;
+
+        // Usually we already have in-memory state for the tenant, which is at least as recent as
+        // the persisted request.  If not (e.g. we just became leader), start from the request.
+        let send_lock = {
+            let mut state_locked = self.state.lock().unwrap();
+            if !state_locked.contains_key(&tenant_id) {
+                let Some(tenant) = ComputeHookTenant::from_request(&request) else {
+                    return Err(NotifyError::Sink(anyhow::anyhow!(
+                        "Malformed persisted notification"
+                    )));
+                };
+                state_locked.insert(tenant_id, tenant);
+            }
+            state_locked[&tenant_id].get_send_lock().clone()
+        };
+
+        let send_locked = tokio::select! {
+            guard = send_lock.lock_owned() => {guard},
+            _ = cancel.cancelled() => {
+                return Err(NotifyError::ShuttingDown)
+            }
+        };
+
+        let (maybe_send_result, is_ready) = {
+            let state_locked = self.state.lock().unwrap();
+            let Some(tenant) = state_locked.get(&tenant_id) else {
+                return Ok(());
+            };
+            (
+                tenant.maybe_send(tenant_id, Some(send_locked)),
+                tenant.is_ready(),
+            )
+        };
+
+        match maybe_send_result {
+            MaybeSendResult::Noop if is_ready => {
+                // The tenant's latest state was delivered since this notification failed
+                self.clear_pending(tenant_id).await;
+                Ok(())
+            }
+            MaybeSendResult::Noop => {
+                // Mid-split: a notification will be sent when all the new shards are known
+                Ok(())
+            }
+            result @ MaybeSendResult::Transmit(_) => {
+                self.notify_execute(result, tenant_id, cancel).await
+            }
+            MaybeSendResult::AwaitLock(_) => unreachable!("We supplied lock guard"),
+        }
+    }
+
+    /// Notifications that failed to deliver and are waiting to be retried
+    pub(super) async fn list_pending(&self) -> DatabaseResult<Vec<PendingComputeNotification>> {
+        Ok(self
+            .persistence
+            .list_compute_notifications()
+            .await?
+            .into_iter()
+            .filter_map(|record| {
+                Some(PendingComputeNotification {
+                    tenant_id: record.tenant_id.parse().ok()?,
+                    attempts: record.attempts as u32,
+                    last_error: record.last_error,
+                    first_failed_at: record.first_failed_at,
+                    next_attempt_at: record.next_attempt_at,
+                })
+            })
+            .collect())
+    }
+
     /// Infallible synchronous fire-and-forget version of notify(), that sends its results to
     /// a channel.  Something should consume the channel and arrange to try notifying again
     /// if something failed.
@@ -557,7 +1028,7 @@ pub(super) fn notify_background(
 
                     async move {
                         this
-                            .notify_execute(maybe_send_result, tenant_shard_id, &cancel)
+                            .notify_execute(maybe_send_result, tenant_shard_id.tenant_id, &cancel)
                             .await.map_err(|e| (tenant_shard_id, e))
                     }.instrument(info_span!(
                         "notify_background", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug()
@@ -611,7 +1082,7 @@ pub(super) async fn notify(
         cancel: &CancellationToken,
     ) -> Result<(), NotifyError> {
         let maybe_send_result = self.notify_prepare(tenant_shard_id, node_id, stripe_size);
-        self.notify_execute(maybe_send_result, tenant_shard_id, cancel)
+        self.notify_execute(maybe_send_result, tenant_shard_id.tenant_id, cancel)
             .await
     }
 }
@@ -694,4 +1165,57 @@ fn tenant_updates() -> anyhow::Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn tenant_from_persisted_request() -> anyhow::Result<()> {
+        let tenant_id = TenantId::generate();
+        let request = ComputeHookNotifyRequest {
+            tenant_id,
+            stripe_size: Some(ShardStripeSize(32768)),
+            shards: vec![
+                ComputeHookNotifyRequestShard {
+                    node_id: NodeId(1),
+                    shard_number: ShardNumber(0),
+                },
+                ComputeHookNotifyRequestShard {
+                    node_id: NodeId(2),
+                    shard_number: ShardNumber(1),
+                },
+            ],
+        };
+
+        // A tenant reconstructed from a persisted request is ready to re-send that request
+        let tenant_state = ComputeHookTenant::from_request(&request).unwrap();
+        assert!(tenant_state.is_ready());
+        let MaybeSendResult::Transmit((resent, _guard)) = tenant_state.maybe_send(tenant_id, None)
+        else {
+            anyhow::bail!("Wrong send result");
+        };
+        assert_eq!(resent, request);
+
+        // Requests without any shards can't be reconstructed
+        assert!(ComputeHookTenant::from_request(&ComputeHookNotifyRequest {
+            tenant_id,
+            stripe_size: None,
+            shards: Vec::new(),
+        })
+        .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn notification_signature() {
+        let body = br#"{"tenant_id":"1f359dd625e519a1a4e8d7509690f6fc"}"#;
+        let signature = sign_notification("secret", 1727700000, body);
+
+        // Hex-encoded SHA-256 output, stable for the same inputs
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign_notification("secret", 1727700000, body));
+
+        // Anything that changes the signed content or the key changes the signature
+        assert_ne!(signature, sign_notification("secret", 1727700001, body));
+        assert_ne!(signature, sign_notification("other", 1727700000, body));
+        assert_ne!(signature, sign_notification("secret", 1727700000, b"{}"));
+    }
 }
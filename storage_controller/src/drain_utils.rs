@@ -98,7 +98,10 @@ pub(crate) fn tenant_shard_eligible_for_drain(
             return None;
         }
 
-        match scheduler.node_preferred(tenant_shard.intent.get_secondary()) {
+        match scheduler.node_preferred(
+            tenant_shard.intent.get_secondary(),
+            tenant_shard.preferred_az(),
+        ) {
             Some(node) => Some(node),
             None => {
                 tracing::warn!(
@@ -0,0 +1,278 @@
+//! Operation history: a persistent record of the scheduling decisions, reconcile outcomes, node
+//! state changes and API mutations of the storage controller, so that questions like "why did
+//! this tenant move" can be answered after the fact.
+//!
+//! Events are recorded synchronously (often while holding [`crate::service::Service`]'s locks)
+//! into a bounded queue, and written to the database in batches by a background task.  If the
+//! database can't keep up, events are dropped rather than slowing down the controller.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use pageserver_api::{controller_api::OperationEventKind, shard::TenantShardId};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use utils::id::{NodeId, TenantId};
+
+use crate::persistence::{NewOperationEventPersistence, Persistence};
+
+/// How many events may be waiting to be written before we start dropping them
+const HISTORY_QUEUE_DEPTH: usize = 16384;
+
+/// Maximum number of events written in one database transaction
+const HISTORY_BATCH_SIZE: usize = 512;
+
+/// How often events older than the retention period are removed
+const HISTORY_TRIM_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The actor for decisions that the controller takes by itself
+pub(crate) const ACTOR_CONTROLLER: &str = "controller";
+
+pub(crate) struct HistoryEvent {
+    kind: OperationEventKind,
+    actor: String,
+    tenant_id: Option<TenantId>,
+    tenant_shard_id: Option<TenantShardId>,
+    node_id: Option<NodeId>,
+    detail: String,
+}
+
+impl HistoryEvent {
+    pub(crate) fn new(kind: OperationEventKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            actor: ACTOR_CONTROLLER.to_string(),
+            tenant_id: None,
+            tenant_shard_id: None,
+            node_id: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub(crate) fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    pub(crate) fn tenant(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub(crate) fn shard(mut self, tenant_shard_id: TenantShardId) -> Self {
+        self.tenant_id = Some(tenant_shard_id.tenant_id);
+        self.tenant_shard_id = Some(tenant_shard_id);
+        self
+    }
+
+    pub(crate) fn node(mut self, node_id: NodeId) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    /// The event recording a call to the HTTP API that `request_name` handles, or `None` if such calls
+    /// aren't recorded: reads, upcalls from pageservers, whose effects show up as node state changes
+    /// and reconciles, and dry runs, which mutate nothing.
+    pub(crate) fn for_api_request(
+        method: &hyper::Method,
+        path: &str,
+        status: hyper::StatusCode,
+        request_name: &str,
+        actor: String,
+    ) -> Option<Self> {
+        if matches!(*method, hyper::Method::GET | hyper::Method::HEAD)
+            || request_name.starts_with("upcall_")
+            || request_name == "control_v1_plan"
+            || request_name == "control_v1_leader_lease"
+        {
+            return None;
+        }
+
+        let mut event = HistoryEvent::new(
+            OperationEventKind::Api,
+            format!("{method} {path} -> {status}"),
+        )
+        .actor(actor);
+
+        // Pick out the subject of the request from its path, e.g. /control/v1/node/:node_id/drain
+        let mut segments = path.split('/').peekable();
+        while let Some(segment) = segments.next() {
+            let Some(next) = segments.peek() else {
+                break;
+            };
+            match segment {
+                "tenant" => match TenantShardId::from_str(next) {
+                    Ok(tenant_shard_id) if tenant_shard_id.is_unsharded() => {
+                        event = event.tenant(tenant_shard_id.tenant_id)
+                    }
+                    Ok(tenant_shard_id) => event = event.shard(tenant_shard_id),
+                    Err(_) => {}
+                },
+                "node" => {
+                    if let Ok(node_id) = NodeId::from_str(next) {
+                        event = event.node(node_id);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Some(event)
+    }
+
+    fn into_persistent(self) -> NewOperationEventPersistence {
+        NewOperationEventPersistence {
+            timestamp: chrono::Utc::now(),
+            kind: String::from(self.kind),
+            actor: self.actor,
+            tenant_id: self.tenant_id.map(|t| t.to_string()),
+            tenant_shard_id: self.tenant_shard_id.map(|t| t.to_string()),
+            node_id: self.node_id.map(|n| n.0 as i64),
+            detail: self.detail,
+        }
+    }
+}
+
+pub(crate) struct OperationHistory {
+    tx: mpsc::Sender<NewOperationEventPersistence>,
+}
+
+impl OperationHistory {
+    pub(crate) fn new() -> (Self, mpsc::Receiver<NewOperationEventPersistence>) {
+        let (tx, rx) = mpsc::channel(HISTORY_QUEUE_DEPTH);
+        (Self { tx }, rx)
+    }
+
+    pub(crate) fn record(&self, event: HistoryEvent) {
+        if self.tx.try_send(event.into_persistent()).is_err() {
+            crate::metrics::METRICS_REGISTRY
+                .metrics_group
+                .storage_controller_history_events_dropped
+                .inc();
+        }
+    }
+
+    /// Background task writing recorded events to the database, and removing events older than
+    /// `retention`.
+    pub(crate) async fn run(
+        persistence: Arc<Persistence>,
+        mut rx: mpsc::Receiver<NewOperationEventPersistence>,
+        retention: Duration,
+        cancel: CancellationToken,
+    ) {
+        let mut trim_interval = tokio::time::interval(HISTORY_TRIM_INTERVAL);
+
+        loop {
+            let first = tokio::select! {
+                _ = cancel.cancelled() => return,
+                _ = trim_interval.tick() => {
+                    Self::trim(&persistence, retention).await;
+                    continue;
+                }
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => return,
+                },
+            };
+
+            let mut batch = vec![first];
+            while batch.len() < HISTORY_BATCH_SIZE {
+                match rx.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
+
+            let count = batch.len();
+            if let Err(err) = persistence.insert_operation_events(batch).await {
+                tracing::warn!("Failed to persist {count} operation history events: {err}");
+                crate::metrics::METRICS_REGISTRY
+                    .metrics_group
+                    .storage_controller_history_events_dropped
+                    .inc_by(count as u64);
+            }
+        }
+    }
+
+    async fn trim(persistence: &Persistence, retention: Duration) {
+        let Ok(retention) = chrono::Duration::from_std(retention) else {
+            return;
+        };
+        match persistence
+            .delete_operation_events_before(chrono::Utc::now() - retention)
+            .await
+        {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Removed {removed} expired operation history events"),
+            Err(err) => tracing::warn!("Failed to remove expired operation history events: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Method, StatusCode};
+
+    fn api_event(
+        method: Method,
+        path: &str,
+        request_name: &str,
+    ) -> Option<NewOperationEventPersistence> {
+        HistoryEvent::for_api_request(
+            &method,
+            path,
+            StatusCode::OK,
+            request_name,
+            "api:admin".to_string(),
+        )
+        .map(HistoryEvent::into_persistent)
+    }
+
+    #[test]
+    fn api_requests() {
+        let tenant_id = TenantId::generate();
+
+        let event = api_event(
+            Method::PUT,
+            &format!("/control/v1/tenant/{tenant_id}/policy"),
+            "control_v1_tenant_policy",
+        )
+        .unwrap();
+        assert_eq!(event.kind, "api");
+        assert_eq!(event.actor, "api:admin");
+        assert_eq!(event.tenant_id, Some(tenant_id.to_string()));
+        assert_eq!(event.tenant_shard_id, None);
+        assert_eq!(
+            event.detail,
+            format!("PUT /control/v1/tenant/{tenant_id}/policy -> 200 OK")
+        );
+
+        let shard = TenantShardId {
+            tenant_id,
+            shard_number: pageserver_api::shard::ShardNumber(1),
+            shard_count: pageserver_api::shard::ShardCount::new(4),
+        };
+        let event = api_event(
+            Method::PUT,
+            &format!("/control/v1/tenant/{shard}/migrate"),
+            "control_v1_tenant_migrate",
+        )
+        .unwrap();
+        assert_eq!(event.tenant_id, Some(tenant_id.to_string()));
+        assert_eq!(event.tenant_shard_id, Some(shard.to_string()));
+
+        let event = api_event(
+            Method::PUT,
+            "/control/v1/node/3/drain",
+            "control_v1_node_drain",
+        )
+        .unwrap();
+        assert_eq!(event.node_id, Some(3));
+        assert_eq!(event.tenant_id, None);
+
+        // Not recorded
+        assert!(api_event(Method::GET, "/control/v1/node", "control_v1_node").is_none());
+        assert!(api_event(Method::POST, "/upcall/v1/re-attach", "upcall_v1_reattach").is_none());
+        assert!(api_event(Method::POST, "/control/v1/plan", "control_v1_plan").is_none());
+    }
+}
@@ -1,9 +1,12 @@
+use crate::history::HistoryEvent;
+use crate::leadership::lease::{LeaseRequest, LeaseVoter};
 use crate::metrics::{
     HttpRequestLatencyLabelGroup, HttpRequestStatusLabelGroup, PageserverRequestLabelGroup,
     METRICS_REGISTRY,
 };
-use crate::persistence::SafekeeperPersistence;
+use crate::persistence::{OperationEventFilter, SafekeeperPersistence};
 use crate::reconciler::ReconcileError;
+use crate::service::import::ImportArchive;
 use crate::service::{LeadershipStatus, Service, STARTUP_RECONCILE_TIMEOUT};
 use anyhow::Context;
 use futures::Future;
@@ -14,18 +17,19 @@
 use pageserver_api::controller_api::{
     MetadataHealthListOutdatedRequest, MetadataHealthListOutdatedResponse,
     MetadataHealthListUnhealthyResponse, MetadataHealthUpdateRequest, MetadataHealthUpdateResponse,
-    ShardsPreferredAzsRequest, TenantCreateRequest,
+    ShardsPreferredAzsRequest, TenantCreateRequest, TenantImportRequest,
 };
 use pageserver_api::models::{
-    TenantConfigRequest, TenantLocationConfigRequest, TenantShardSplitRequest,
-    TenantTimeTravelRequest, TimelineArchivalConfigRequest, TimelineCreateRequest,
+    TenantConfigRequest, TenantLocationConfigRequest, TenantShardMergeRequest,
+    TenantShardSplitRequest, TenantTimeTravelRequest, TimelineArchivalConfigRequest,
+    TimelineCreateRequest,
 };
 use pageserver_api::shard::TenantShardId;
 use pageserver_client::{mgmt_api, BlockUnblock};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
-use utils::auth::{Scope, SwappableJwtAuth};
+use utils::auth::{Claims, Scope, SwappableJwtAuth};
 use utils::failpoint_support::failpoints_handler;
 use utils::http::endpoint::{auth_middleware, check_permission_with, request_span};
 use utils::http::request::{must_get_query_param, parse_query_param, parse_request_param};
@@ -42,8 +46,8 @@
 };
 
 use pageserver_api::controller_api::{
-    NodeAvailability, NodeConfigureRequest, NodeRegisterRequest, TenantPolicyRequest,
-    TenantShardMigrateRequest,
+    NodeAvailability, NodeConfigureRequest, NodeRegisterRequest, PlanRequest,
+    RollingOperationRequest, TenantPolicyRequest, TenantShardMigrateRequest,
 };
 use pageserver_api::upcall_api::{ReAttachRequest, ValidateRequest};
 
@@ -456,16 +460,39 @@ async fn handle_tenant_timeline_passthrough(
     Ok(response)
 }
 
+/// Unlike the rest of the `/debug` API, which requires the admin scope, this also accepts a token
+/// scoped to the tenant: computes call it with their own `storage_auth_token`, to find the
+/// pageservers to switch to when theirs are unreachable.  It only reveals the addresses and shard
+/// parameters of the pageservers where the tenant is attached, which its computes use anyway.
 async fn handle_tenant_locate(
     service: Arc<Service>,
     req: Request<Body>,
 ) -> Result<Response<Body>, ApiError> {
-    check_permissions(&req, Scope::Admin)?;
-
     let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    check_permission_with(&req, |claims| {
+        crate::auth::check_tenant_permission(claims, tenant_id)
+            .or_else(|_| crate::auth::check_permission(claims, Scope::Admin))
+    })?;
+
     json_response(StatusCode::OK, service.tenant_locate(tenant_id)?)
 }
 
+/// Computes (or the control plane on their behalf) call this before connecting to a tenant, to
+/// re-attach it if it was hibernated for being idle.  Like [`handle_tenant_locate`], computes may
+/// call it with their own tenant's token.
+async fn handle_tenant_wake(
+    service: Arc<Service>,
+    req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    check_permission_with(&req, |claims| {
+        crate::auth::check_tenant_permission(claims, tenant_id)
+            .or_else(|_| crate::auth::check_permission(claims, Scope::Admin))
+    })?;
+
+    json_response(StatusCode::OK, service.tenant_wake(tenant_id).await?)
+}
+
 async fn handle_tenant_describe(
     service: Arc<Service>,
     req: Request<Body>,
@@ -624,6 +651,168 @@ async fn handle_cancel_node_fill(req: Request<Body>) -> Result<Response<Body>, A
     json_response(StatusCode::ACCEPTED, ())
 }
 
+async fn handle_operation_history(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    const DEFAULT_LIMIT: i64 = 100;
+    const MAX_LIMIT: i64 = 10000;
+
+    let filter = OperationEventFilter {
+        tenant_id: parse_query_param(&req, "tenant_id")?,
+        node_id: parse_query_param(&req, "node_id")?,
+        kind: parse_query_param(&req, "kind")?,
+        since: parse_query_param(&req, "since")?,
+        until: parse_query_param(&req, "until")?,
+        limit: parse_query_param::<_, i64>(&req, "limit")?
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT),
+    };
+
+    let state = get_state(&req);
+    json_response(
+        StatusCode::OK,
+        state.service.operation_history(filter).await?,
+    )
+}
+
+async fn handle_pending_compute_notifications(
+    req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    json_response(
+        StatusCode::OK,
+        state.service.pending_compute_notifications().await?,
+    )
+}
+
+async fn handle_plan(mut req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let plan_req = json_request::<PlanRequest>(&mut req).await?;
+    let state = get_state(&req);
+    json_response(StatusCode::OK, state.service.plan(plan_req).await?)
+}
+
+async fn handle_rolling_operation_start(
+    mut req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let rolling_req = json_request::<RollingOperationRequest>(&mut req).await?;
+    let state = get_state(&req);
+
+    json_response(
+        StatusCode::CREATED,
+        state.service.start_rolling_operation(rolling_req).await?,
+    )
+}
+
+async fn handle_rolling_operation_list(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    json_response(
+        StatusCode::OK,
+        state.service.list_rolling_operations().await?,
+    )
+}
+
+async fn handle_rolling_operation_pause(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    let id: String = parse_request_param(&req, "operation_id")?;
+
+    json_response(
+        StatusCode::ACCEPTED,
+        state.service.pause_rolling_operation(&id).await?,
+    )
+}
+
+async fn handle_rolling_operation_resume(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    let id: String = parse_request_param(&req, "operation_id")?;
+
+    json_response(
+        StatusCode::ACCEPTED,
+        state.service.resume_rolling_operation(&id).await?,
+    )
+}
+
+async fn handle_rolling_operation_abort(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    let id: String = parse_request_param(&req, "operation_id")?;
+
+    json_response(
+        StatusCode::ACCEPTED,
+        state.service.abort_rolling_operation(&id).await?,
+    )
+}
+
+async fn handle_tenant_import_create(mut req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let import_req = json_request::<TenantImportRequest>(&mut req).await?;
+    let state = get_state(&req);
+
+    json_response(
+        StatusCode::CREATED,
+        state.service.tenant_import_create(import_req).await?,
+    )
+}
+
+async fn handle_tenant_import_list(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    json_response(StatusCode::OK, state.service.tenant_import_list().await?)
+}
+
+async fn handle_tenant_import_get(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+
+    json_response(
+        StatusCode::OK,
+        state.service.tenant_import_get(tenant_id).await?,
+    )
+}
+
+async fn handle_tenant_import_upload(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    let archive: ImportArchive = parse_request_param(&req, "archive")?;
+    let service = get_state(&req).service.clone();
+
+    json_response(
+        StatusCode::OK,
+        service
+            .tenant_import_upload(tenant_id, archive, req.into_body())
+            .await?,
+    )
+}
+
+async fn handle_tenant_import_resume(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let state = get_state(&req);
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+
+    json_response(
+        StatusCode::ACCEPTED,
+        state.service.tenant_import_resume(tenant_id).await?,
+    )
+}
+
 async fn handle_metadata_health_update(mut req: Request<Body>) -> Result<Response<Body>, ApiError> {
     check_permissions(&req, Scope::Scrubber)?;
 
@@ -684,6 +873,21 @@ async fn handle_tenant_shard_split(
     )
 }
 
+async fn handle_tenant_shard_merge(
+    service: Arc<Service>,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    let merge_req = json_request::<TenantShardMergeRequest>(&mut req).await?;
+
+    json_response(
+        StatusCode::OK,
+        service.tenant_shard_merge(tenant_id, merge_req).await?,
+    )
+}
+
 async fn handle_tenant_shard_migrate(
     service: Arc<Service>,
     mut req: Request<Body>,
@@ -735,6 +939,32 @@ async fn handle_step_down(req: Request<Body>) -> Result<Response<Body>, ApiError
     json_response(StatusCode::OK, state.service.step_down().await)
 }
 
+/// Another controller is campaigning for, renewing or releasing a leader lease: see [`crate::leadership::lease`]
+async fn handle_leader_lease_vote(mut req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let lease_req = json_request::<LeaseRequest>(&mut req).await?;
+    let voter = get_lease_voter(&req)?;
+    let response = tokio::task::spawn_blocking(move || voter.vote(lease_req))
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.into()))?;
+
+    json_response(StatusCode::OK, response)
+}
+
+async fn handle_leader_lease_get(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permissions(&req, Scope::Admin)?;
+
+    let voter = get_lease_voter(&req)?;
+    json_response(StatusCode::OK, voter.current())
+}
+
+fn get_lease_voter(req: &Request<Body>) -> Result<Arc<LeaseVoter>, ApiError> {
+    req.data::<Arc<LeaseVoter>>()
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound("Leader lease election is not configured".into()))
+}
+
 async fn handle_tenant_drop(req: Request<Body>) -> Result<Response<Body>, ApiError> {
     let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
     check_permissions(&req, Scope::PageServerApi)?;
@@ -927,11 +1157,19 @@ enum AllowedRoutes<'a> {
             LeadershipStatus::Leader => AllowedRoutes::All,
             LeadershipStatus::SteppedDown => {
                 // TODO: does it make sense to allow /status here?
-                AllowedRoutes::Some(["/control/v1/step_down", "/status", "/metrics"].to_vec())
-            }
-            LeadershipStatus::Candidate => {
-                AllowedRoutes::Some(["/ready", "/status", "/metrics"].to_vec())
+                AllowedRoutes::Some(
+                    [
+                        "/control/v1/step_down",
+                        "/control/v1/leader_lease",
+                        "/status",
+                        "/metrics",
+                    ]
+                    .to_vec(),
+                )
             }
+            LeadershipStatus::Candidate => AllowedRoutes::Some(
+                ["/control/v1/leader_lease", "/ready", "/status", "/metrics"].to_vec(),
+            ),
         };
 
         let uri = req.uri().to_string();
@@ -1002,6 +1240,37 @@ fn epilogue_metrics_middleware<B: hyper::body::HttpBody + Send + Sync + 'static>
     })
 }
 
+/// Record mutating API calls in the operation history, see [`HistoryEvent::for_api_request`]
+fn epilogue_history_middleware<B: hyper::body::HttpBody + Send + Sync + 'static>(
+) -> Middleware<B, ApiError> {
+    Middleware::post_with_info(move |resp, req_info| async move {
+        let Some(request_name) = req_info.context::<RequestName>() else {
+            return Ok(resp);
+        };
+        let Some(state) = req_info.data::<Arc<HttpState>>() else {
+            return Ok(resp);
+        };
+
+        let actor = match req_info.context::<Claims>() {
+            Some(claims) => format!("api:{:?}", claims.scope).to_lowercase(),
+            None => "api".to_string(),
+        };
+        let Some(event) = HistoryEvent::for_api_request(
+            req_info.method(),
+            req_info.uri().path(),
+            resp.status(),
+            request_name.0,
+            actor,
+        ) else {
+            return Ok(resp);
+        };
+
+        state.service.record_history(event);
+
+        Ok(resp)
+    })
+}
+
 pub async fn measured_metrics_handler(req: Request<Body>) -> Result<Response<Body>, ApiError> {
     pub const TEXT_FORMAT: &str = "text/plain; version=0.0.4";
 
@@ -1040,7 +1309,8 @@ pub fn make_router(
     let mut router = endpoint::make_router()
         .middleware(prologue_leadership_status_check_middleware())
         .middleware(prologue_metrics_middleware())
-        .middleware(epilogue_metrics_middleware());
+        .middleware(epilogue_metrics_middleware())
+        .middleware(epilogue_history_middleware());
     if auth.is_some() {
         router = router.middleware(auth_middleware(|request| {
             let state = get_state(request);
@@ -1052,6 +1322,10 @@ pub fn make_router(
         }));
     }
 
+    if let Some(voter) = service.lease_voter() {
+        router = router.data(voter.clone());
+    }
+
     router
         .data(Arc::new(HttpState::new(service, auth, build_info)))
         .get("/metrics", |r| {
@@ -1071,6 +1345,10 @@ pub fn make_router(
         .post("/upcall/v1/validate", |r| {
             named_request_span(r, handle_validate, RequestName("upcall_v1_validate"))
         })
+        // Upcall for computes
+        .post("/upcall/v1/tenant/:tenant_id/wake", |r| {
+            tenant_service_handler(r, handle_tenant_wake, RequestName("upcall_v1_tenant_wake"))
+        })
         // Test/dev/debug endpoints
         .post("/debug/v1/attach-hook", |r| {
             named_request_span(r, handle_attach_hook, RequestName("debug_v1_attach_hook"))
@@ -1094,6 +1372,7 @@ pub fn make_router(
         .get("/debug/v1/tenant", |r| {
             named_request_span(r, handle_tenants_dump, RequestName("debug_v1_tenant"))
         })
+        // Also accepts tenant-scoped tokens, see [`handle_tenant_locate`]
         .get("/debug/v1/tenant/:tenant_id/locate", |r| {
             tenant_service_handler(
                 r,
@@ -1167,6 +1446,95 @@ pub fn make_router(
                 RequestName("control_v1_cancel_node_fill"),
             )
         })
+        .get("/control/v1/history", |r| {
+            named_request_span(
+                r,
+                handle_operation_history,
+                RequestName("control_v1_history"),
+            )
+        })
+        .get("/control/v1/compute_notifications", |r| {
+            named_request_span(
+                r,
+                handle_pending_compute_notifications,
+                RequestName("control_v1_compute_notifications"),
+            )
+        })
+        .post("/control/v1/plan", |r| {
+            named_request_span(r, handle_plan, RequestName("control_v1_plan"))
+        })
+        // Rolling operations
+        .post("/control/v1/rolling_operation", |r| {
+            named_request_span(
+                r,
+                handle_rolling_operation_start,
+                RequestName("control_v1_rolling_operation_start"),
+            )
+        })
+        .get("/control/v1/rolling_operation", |r| {
+            named_request_span(
+                r,
+                handle_rolling_operation_list,
+                RequestName("control_v1_rolling_operation_list"),
+            )
+        })
+        .put("/control/v1/rolling_operation/:operation_id/pause", |r| {
+            named_request_span(
+                r,
+                handle_rolling_operation_pause,
+                RequestName("control_v1_rolling_operation_pause"),
+            )
+        })
+        .put("/control/v1/rolling_operation/:operation_id/resume", |r| {
+            named_request_span(
+                r,
+                handle_rolling_operation_resume,
+                RequestName("control_v1_rolling_operation_resume"),
+            )
+        })
+        .put("/control/v1/rolling_operation/:operation_id/abort", |r| {
+            named_request_span(
+                r,
+                handle_rolling_operation_abort,
+                RequestName("control_v1_rolling_operation_abort"),
+            )
+        })
+        // Tenant imports
+        .post("/control/v1/import", |r| {
+            named_request_span(
+                r,
+                handle_tenant_import_create,
+                RequestName("control_v1_import_create"),
+            )
+        })
+        .get("/control/v1/import", |r| {
+            named_request_span(
+                r,
+                handle_tenant_import_list,
+                RequestName("control_v1_import_list"),
+            )
+        })
+        .get("/control/v1/import/:tenant_id", |r| {
+            named_request_span(
+                r,
+                handle_tenant_import_get,
+                RequestName("control_v1_import_get"),
+            )
+        })
+        .put("/control/v1/import/:tenant_id/upload/:archive", |r| {
+            named_request_span(
+                r,
+                handle_tenant_import_upload,
+                RequestName("control_v1_import_upload"),
+            )
+        })
+        .put("/control/v1/import/:tenant_id/resume", |r| {
+            named_request_span(
+                r,
+                handle_tenant_import_resume,
+                RequestName("control_v1_import_resume"),
+            )
+        })
         // Metadata health operations
         .post("/control/v1/metadata_health/update", |r| {
             named_request_span(
@@ -1204,6 +1572,13 @@ pub fn make_router(
                 RequestName("control_v1_tenant_shard_split"),
             )
         })
+        .put("/control/v1/tenant/:tenant_id/shard_merge", |r| {
+            tenant_service_handler(
+                r,
+                handle_tenant_shard_merge,
+                RequestName("control_v1_tenant_shard_merge"),
+            )
+        })
         .get("/control/v1/tenant/:tenant_id", |r| {
             tenant_service_handler(
                 r,
@@ -1231,6 +1606,20 @@ pub fn make_router(
         .put("/control/v1/step_down", |r| {
             named_request_span(r, handle_step_down, RequestName("control_v1_step_down"))
         })
+        .get("/control/v1/leader_lease", |r| {
+            named_request_span(
+                r,
+                handle_leader_lease_get,
+                RequestName("control_v1_leader_lease"),
+            )
+        })
+        .put("/control/v1/leader_lease", |r| {
+            named_request_span(
+                r,
+                handle_leader_lease_vote,
+                RequestName("control_v1_leader_lease"),
+            )
+        })
         .get("/control/v1/safekeeper/:id", |r| {
             named_request_span(r, handle_get_safekeeper, RequestName("v1_safekeeper"))
         })
@@ -1348,3 +1737,48 @@ pub fn make_router(
             )
         })
 }
+
+struct StandbyState {
+    auth: Option<Arc<SwappableJwtAuth>>,
+}
+
+/// While we campaign for a leader lease there is no [`Service`] yet, but other controllers still
+/// need our vote: this router serves just enough to give it to them.
+pub fn make_standby_router(
+    voter: Arc<LeaseVoter>,
+    auth: Option<Arc<SwappableJwtAuth>>,
+) -> RouterBuilder<hyper::Body, ApiError> {
+    let mut router = endpoint::make_router();
+    if auth.is_some() {
+        router = router.middleware(auth_middleware(|request| {
+            if request.uri().path() == "/status" {
+                None
+            } else {
+                request
+                    .data::<Arc<StandbyState>>()
+                    .and_then(|state| state.auth.as_deref())
+            }
+        }));
+    }
+
+    router
+        .data(Arc::new(StandbyState { auth }))
+        .data(voter)
+        .get("/status", |r| {
+            named_request_span(r, handle_status, RequestName("status"))
+        })
+        .get("/control/v1/leader_lease", |r| {
+            named_request_span(
+                r,
+                handle_leader_lease_get,
+                RequestName("control_v1_leader_lease"),
+            )
+        })
+        .put("/control/v1/leader_lease", |r| {
+            named_request_span(
+                r,
+                handle_leader_lease_vote,
+                RequestName("control_v1_leader_lease"),
+            )
+        })
+}
@@ -6,9 +6,13 @@
 use crate::{
     peer_client::{GlobalObservedState, PeerClient},
     persistence::{ControllerPersistence, DatabaseError, DatabaseResult, Persistence},
-    service::Config,
+    service::{Config, LeaderLeaseConfig},
 };
 
+pub mod lease;
+
+use lease::LeaderLease;
+
 /// Helper for storage controller leadership acquisition
 pub(crate) struct Leadership {
     persistence: Arc<Persistence>,
@@ -20,6 +24,8 @@ pub(crate) struct Leadership {
 pub(crate) enum Error {
     #[error(transparent)]
     Database(#[from] DatabaseError),
+    #[error("Leader lease: {0}")]
+    Lease(anyhow::Error),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -59,6 +65,42 @@ pub(crate) async fn step_down_current_leader(
         Ok((leader, leader_step_down_state))
     }
 
+    /// Campaign for a leader lease (see [`lease`]) instead of relying on the database to pick a
+    /// leader.  If we start as a candidate, ask the current holder to step down rather than
+    /// waiting for its lease to expire.  Should be called early on in the start-up sequence.
+    ///
+    /// Returns the lease, along with the current leader and its observed state, like
+    /// [`Self::step_down_current_leader`].
+    pub(crate) async fn acquire_lease(
+        &self,
+        lease_config: &LeaderLeaseConfig,
+    ) -> Result<(
+        Arc<LeaderLease>,
+        Option<ControllerPersistence>,
+        Option<GlobalObservedState>,
+    )> {
+        let Some(address_for_peers) = &self.config.address_for_peers else {
+            return Err(Error::Lease(anyhow::anyhow!(
+                "address-for-peers is required to acquire a leader lease"
+            )));
+        };
+
+        let (lease, observed) = LeaderLease::acquire(
+            lease_config,
+            address_for_peers.to_string(),
+            self.config.peer_jwt_token.clone(),
+            self.config.start_as_candidate,
+            &self.cancel,
+        )
+        .await
+        .map_err(Error::Lease)?;
+
+        // Read back the leader recorded in the database, so that [`Self::become_leader`] can replace it
+        let leader = self.current_leader().await?;
+
+        Ok((lease, leader, observed))
+    }
+
     /// Mark the current storage controller instance as the leader in the database
     pub(crate) async fn become_leader(
         &self,
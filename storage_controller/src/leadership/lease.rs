@@ -0,0 +1,424 @@
+//! Leader election by lease, for running several storage controllers without relying on the
+//! database to decide which one of them leads.
+//!
+//! Every controller taking part is also a voter.  A candidate becomes leader by collecting
+//! grants of a lease from a majority of the peers (itself included), for a term higher than any
+//! that it has seen.  A voter grants each term to at most one holder, and will not grant a new
+//! term to a different holder until the lease it last granted has expired, so at most one
+//! controller holds a live lease at any time.  The leader renews its lease well before it
+//! expires, and considers it lost if it cannot renew it in time.
+//!
+//! Expiry is measured on each process's monotonic clock, from when the leader sent a request and
+//! from when a voter received it, so the leader always gives up on a lease before the voters
+//! that granted it are willing to grant it to someone else.
+//!
+//! The term doubles as a fencing token: a new leader records it in the database with
+//! [`crate::persistence::Persistence::fence`], so that a deposed leader cannot write anything
+//! once it has been replaced, even if it has not noticed yet.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use futures::future::join_all;
+use hyper::Uri;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::peer_client::{GlobalObservedState, PeerClient};
+use crate::service::LeaderLeaseConfig;
+
+/// A candidate's request to a voter, to grant or renew a lease
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct LeaseRequest {
+    /// The candidate's address for peers
+    pub(crate) candidate: String,
+    pub(crate) term: u64,
+    /// How long the lease should last.  Zero releases a lease that the candidate holds.
+    pub(crate) ttl_ms: u64,
+}
+
+/// A voter's reply to a [`LeaseRequest`], which also describes the lease that it last granted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct LeaseResponse {
+    pub(crate) granted: bool,
+    pub(crate) term: u64,
+    pub(crate) holder: Option<String>,
+    /// Whether the lease that this voter last granted is still live
+    pub(crate) live: bool,
+}
+
+/// What a voter must remember across restarts, so that it never grants a term twice
+#[derive(Serialize, Deserialize, Default)]
+struct VoterState {
+    term: u64,
+    holder: Option<String>,
+}
+
+struct VoterInner {
+    state: VoterState,
+    expires: Instant,
+}
+
+/// The voting half of lease-based leader election.  Its state is persisted locally rather than in
+/// the database: see the module documentation.
+pub struct LeaseVoter {
+    path: Utf8PathBuf,
+    inner: std::sync::Mutex<VoterInner>,
+}
+
+impl LeaseVoter {
+    /// Load the voter's state from `path`, if it exists.
+    ///
+    /// We do not know when the lease we last granted before restarting expires, so we assume it
+    /// was granted just now and will last for `ttl`.
+    pub fn load(path: Utf8PathBuf, ttl: Duration) -> anyhow::Result<Self> {
+        let state = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => VoterState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            inner: std::sync::Mutex::new(VoterInner {
+                state,
+                expires: Instant::now() + ttl,
+            }),
+        })
+    }
+
+    fn persist(path: &Utf8Path, state: &VoterState) -> anyhow::Result<()> {
+        let tmp_path = utils::crashsafe::path_with_suffix_extension(path, "___temp");
+        utils::crashsafe::overwrite(path, &tmp_path, &serde_json::to_vec(state)?)?;
+        Ok(())
+    }
+
+    /// Handle a [`LeaseRequest`].  This does blocking I/O when it grants a new term.
+    pub(crate) fn vote(&self, req: LeaseRequest) -> LeaseResponse {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let ours = inner.state.holder.as_deref() == Some(req.candidate.as_str());
+
+        if req.ttl_ms == 0 {
+            let released = ours && req.term == inner.state.term;
+            if released {
+                tracing::info!("{} released lease for term {}", req.candidate, req.term);
+                inner.expires = now;
+            }
+            return Self::describe(&inner, now, released);
+        }
+
+        let grant = if req.term < inner.state.term {
+            false
+        } else if req.term == inner.state.term {
+            ours || inner.state.holder.is_none()
+        } else {
+            ours || inner.expires <= now
+        };
+
+        if grant && (req.term != inner.state.term || !ours) {
+            let state = VoterState {
+                term: req.term,
+                holder: Some(req.candidate.clone()),
+            };
+            if let Err(e) = Self::persist(&self.path, &state) {
+                tracing::error!("Failed to persist lease vote to {}: {e}", self.path);
+                return Self::describe(&inner, now, false);
+            }
+            tracing::info!("Granted lease for term {} to {}", req.term, req.candidate);
+            inner.state = state;
+        }
+
+        if grant {
+            inner.expires = now + Duration::from_millis(req.ttl_ms);
+        }
+
+        Self::describe(&inner, now, grant)
+    }
+
+    /// Describe the lease that we last granted, without changing it
+    pub(crate) fn current(&self) -> LeaseResponse {
+        Self::describe(&self.inner.lock().unwrap(), Instant::now(), false)
+    }
+
+    fn describe(inner: &VoterInner, now: Instant, granted: bool) -> LeaseResponse {
+        LeaseResponse {
+            granted,
+            term: inner.state.term,
+            holder: inner.state.holder.clone(),
+            live: inner.expires > now,
+        }
+    }
+}
+
+/// The candidate half of lease-based leader election: a lease that we hold, and keep renewing
+/// until we lose or release it.
+pub(crate) struct LeaderLease {
+    term: u64,
+    candidate: String,
+    peers: Vec<PeerClient>,
+    ttl: Duration,
+
+    /// Fired when we stop holding the lease, whether we lost or released it
+    lost: CancellationToken,
+}
+
+impl LeaderLease {
+    pub(crate) fn term(&self) -> u64 {
+        self.term
+    }
+
+    /// Fired when we no longer hold the lease
+    pub(crate) fn lost(&self) -> &CancellationToken {
+        &self.lost
+    }
+
+    /// Campaign until we hold a lease.  If `step_down_holder` is set, ask a live holder to step
+    /// down rather than waiting for its lease to expire, and return the observed state that it
+    /// hands over.
+    pub(crate) async fn acquire(
+        config: &LeaderLeaseConfig,
+        candidate: String,
+        jwt: Option<String>,
+        step_down_holder: bool,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<(Arc<Self>, Option<GlobalObservedState>)> {
+        let peers = config
+            .peers
+            .iter()
+            .map(|uri| PeerClient::new(uri.clone(), jwt.clone()))
+            .collect::<Vec<_>>();
+
+        let mut term = 1;
+        let mut observed = None;
+        let mut stepped_down: Option<(u64, String)> = None;
+
+        loop {
+            let started_at = Instant::now();
+            let responses = Self::request_all(&peers, &candidate, term, config.ttl).await;
+            let granted = responses.iter().filter(|r| r.1.granted).count();
+            if granted * 2 > peers.len() {
+                tracing::info!("Acquired leader lease for term {term} ({granted} grants)");
+                let lease = Arc::new(Self {
+                    term,
+                    candidate,
+                    peers,
+                    ttl: config.ttl,
+                    lost: CancellationToken::new(),
+                });
+                tokio::task::spawn(lease.clone().renew_until_lost(started_at, cancel.clone()));
+                return Ok((lease, observed));
+            }
+
+            // Give back the grants we did get, so that we do not hold up other candidates, and try
+            // again with a term that none of the voters have seen yet.
+            let granted_by = responses
+                .iter()
+                .filter(|r| r.1.granted)
+                .map(|r| r.0)
+                .collect::<Vec<_>>();
+            Self::release_on(&peers, &granted_by, &candidate, term).await;
+
+            let live_holder = responses
+                .iter()
+                .filter(|r| r.1.live)
+                .max_by_key(|r| r.1.term)
+                .and_then(|r| r.1.holder.clone().map(|h| (r.1.term, h)));
+            term = responses
+                .iter()
+                .map(|r| r.1.term)
+                .max()
+                .unwrap_or(0)
+                .max(term)
+                + 1;
+
+            match live_holder {
+                Some((holder_term, holder))
+                    if step_down_holder
+                        && holder != candidate
+                        && stepped_down.as_ref() != Some(&(holder_term, holder.clone())) =>
+                {
+                    tracing::info!("Requesting step down from lease holder {holder}");
+                    match holder.parse::<Uri>() {
+                        Ok(uri) => {
+                            match PeerClient::new(uri, jwt.clone()).step_down(cancel).await {
+                                Ok(state) => observed = Some(state),
+                                Err(e) => {
+                                    tracing::warn!(
+                                    "Lease holder {holder} did not respond to step down request: {e}"
+                                );
+                                }
+                            }
+                        }
+                        Err(e) => tracing::warn!("Invalid lease holder address {holder}: {e}"),
+                    }
+                    stepped_down = Some((holder_term, holder));
+                    continue;
+                }
+                Some((_, holder)) => {
+                    tracing::info!("Waiting for {holder}'s leader lease to expire");
+                }
+                None => {
+                    tracing::info!("Failed to acquire leader lease for term {term} ({granted} grants), retrying");
+                }
+            }
+
+            // Jitter our retries, so that candidates who split the vote do not keep on doing so
+            let backoff = config.ttl.mul_f64(rand::thread_rng().gen_range(0.1..0.3));
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {},
+                _ = cancel.cancelled() => anyhow::bail!("Cancelled while acquiring leader lease"),
+            }
+        }
+    }
+
+    /// Renew the lease a few times per TTL.  If we cannot get a majority to renew it before it
+    /// expires, or a voter tells us that a newer term exists, the lease is lost.
+    async fn renew_until_lost(self: Arc<Self>, acquired_at: Instant, cancel: CancellationToken) {
+        let mut valid_until = acquired_at + self.ttl;
+        let mut interval = tokio::time::interval(self.ttl / 3);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = self.lost.cancelled() => return,
+                _ = cancel.cancelled() => return,
+            }
+
+            let started_at = Instant::now();
+            let responses =
+                Self::request_all(&self.peers, &self.candidate, self.term, self.ttl).await;
+            if let Some(newer) = responses
+                .iter()
+                .find(|r| r.1.term > self.term && r.1.live)
+                .map(|r| r.1.term)
+            {
+                tracing::error!(
+                    "Lost leader lease for term {}: a voter has granted term {newer}",
+                    self.term
+                );
+                self.lost.cancel();
+                return;
+            }
+
+            let granted = responses.iter().filter(|r| r.1.granted).count();
+            if granted * 2 > self.peers.len() {
+                valid_until = started_at + self.ttl;
+            } else {
+                tracing::warn!(
+                    "Failed to renew leader lease for term {} ({granted} grants)",
+                    self.term
+                );
+            }
+
+            if Instant::now() >= valid_until {
+                tracing::error!("Lost leader lease for term {}: expired", self.term);
+                self.lost.cancel();
+                return;
+            }
+        }
+    }
+
+    /// Stop renewing the lease, and tell the voters that it is free, so that another controller
+    /// does not have to wait for it to expire.
+    pub(crate) async fn release(&self) {
+        if self.lost.is_cancelled() {
+            return;
+        }
+        self.lost.cancel();
+
+        let all = (0..self.peers.len()).collect::<Vec<_>>();
+        Self::release_on(&self.peers, &all, &self.candidate, self.term).await;
+        tracing::info!("Released leader lease for term {}", self.term);
+    }
+
+    /// Send a lease request to every peer, returning the responses we got in time, along with the
+    /// index of the peer that sent each one.
+    async fn request_all(
+        peers: &[PeerClient],
+        candidate: &str,
+        term: u64,
+        ttl: Duration,
+    ) -> Vec<(usize, LeaseResponse)> {
+        let req = LeaseRequest {
+            candidate: candidate.to_string(),
+            term,
+            ttl_ms: ttl.as_millis() as u64,
+        };
+
+        // Responses that take a large fraction of the TTL to arrive are no use to us
+        let timeout = ttl / 4;
+        join_all(peers.iter().map(|peer| peer.leader_lease(&req, timeout)))
+            .await
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, res)| match res {
+                Ok(response) => Some((i, response)),
+                Err(e) => {
+                    tracing::warn!("Leader lease request to peer failed: {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    async fn release_on(peers: &[PeerClient], indices: &[usize], candidate: &str, term: u64) {
+        let req = LeaseRequest {
+            candidate: candidate.to_string(),
+            term,
+            ttl_ms: 0,
+        };
+        join_all(
+            indices
+                .iter()
+                .map(|i| peers[*i].leader_lease(&req, Duration::from_secs(1))),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(candidate: &str, term: u64, ttl_ms: u64) -> LeaseRequest {
+        LeaseRequest {
+            candidate: candidate.to_string(),
+            term,
+            ttl_ms,
+        }
+    }
+
+    #[test]
+    fn voter_grants_one_live_lease() -> anyhow::Result<()> {
+        let tmp = camino_tempfile::tempdir()?;
+        let path = tmp.path().join("leader_lease.json");
+
+        // A fresh voter has no grant to protect, apart from the one it may have made before a restart
+        let voter = LeaseVoter::load(path.clone(), Duration::ZERO)?;
+        assert!(voter.vote(request("a", 1, 60000)).granted);
+
+        // The holder may renew, and move to a later term, but nobody else may take over
+        assert!(voter.vote(request("a", 1, 60000)).granted);
+        assert!(!voter.vote(request("b", 2, 60000)).granted);
+        assert!(voter.vote(request("a", 3, 60000)).granted);
+        assert!(!voter.vote(request("b", 3, 60000)).granted);
+
+        // Once the holder releases it, the next term may go to someone else, but not the old one
+        assert!(voter.vote(request("a", 3, 0)).granted);
+        assert!(!voter.vote(request("b", 3, 60000)).granted);
+        let response = voter.vote(request("b", 4, 60000));
+        assert!(response.granted);
+        assert_eq!(response.holder.as_deref(), Some("b"));
+
+        // After a restart, we remember the term and wait out the lease before granting to anyone else
+        drop(voter);
+        let voter = LeaseVoter::load(path, Duration::from_secs(60))?;
+        assert_eq!(voter.current().term, 4);
+        assert!(!voter.vote(request("a", 5, 60000)).granted);
+        assert!(voter.vote(request("b", 4, 60000)).granted);
+
+        Ok(())
+    }
+}
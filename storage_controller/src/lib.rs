@@ -6,9 +6,10 @@
 mod compute_hook;
 mod drain_utils;
 mod heartbeater;
+mod history;
 pub mod http;
 mod id_lock_map;
-mod leadership;
+pub mod leadership;
 pub mod metrics;
 mod node;
 mod pageserver_client;
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Context};
+use camino::Utf8PathBuf;
 use clap::Parser;
 use hyper::Uri;
 use metrics::launch_timestamp::LaunchTimestamp;
@@ -6,13 +7,15 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use storage_controller::http::make_router;
+use storage_controller::http::{make_router, make_standby_router};
+use storage_controller::leadership::lease::LeaseVoter;
 use storage_controller::metrics::preinitialize_metrics;
 use storage_controller::persistence::Persistence;
 use storage_controller::service::chaos_injector::ChaosInjector;
 use storage_controller::service::{
-    Config, Service, HEARTBEAT_INTERVAL_DEFAULT, MAX_OFFLINE_INTERVAL_DEFAULT,
-    MAX_WARMING_UP_INTERVAL_DEFAULT, RECONCILER_CONCURRENCY_DEFAULT,
+    Config, LeaderLeaseConfig, Service, AZ_FAILOVER_PER_PASS_DEFAULT, HEARTBEAT_INTERVAL_DEFAULT,
+    HISTORY_RETENTION_DEFAULT, MAX_OFFLINE_INTERVAL_DEFAULT, MAX_WARMING_UP_INTERVAL_DEFAULT,
+    RECONCILER_CONCURRENCY_DEFAULT,
 };
 use tokio::signal::unix::SignalKind;
 use tokio_util::sync::CancellationToken;
@@ -54,6 +57,25 @@ struct Cli {
     #[arg(long)]
     compute_hook_url: Option<String>,
 
+    /// Key for signing compute notifications sent to `--compute-hook-url`, with HMAC-SHA256
+    #[arg(long)]
+    compute_hook_hmac_key: Option<String>,
+
+    /// Instead of `--compute-hook-url`: append compute notifications to this file, one JSON
+    /// object per line
+    #[arg(long, conflicts_with_all = ["compute_hook_url", "compute_hook_socket", "compute_hook_command"])]
+    compute_hook_file: Option<PathBuf>,
+
+    /// Instead of `--compute-hook-url`: write compute notifications to this unix socket, one
+    /// JSON object per line.  The peer must respond `ok` to each one.
+    #[arg(long, conflicts_with_all = ["compute_hook_url", "compute_hook_command"])]
+    compute_hook_socket: Option<PathBuf>,
+
+    /// Instead of `--compute-hook-url`: run this shell command for each compute notification,
+    /// with the notification as JSON on its stdin
+    #[arg(long, conflicts_with_all = ["compute_hook_url"])]
+    compute_hook_command: Option<String>,
+
     /// URL to connect to postgres, like postgresql://localhost:1234/storage_controller
     #[arg(long)]
     database_url: Option<String>,
@@ -79,6 +101,11 @@ struct Cli {
     #[arg(long)]
     reconciler_concurrency: Option<usize>,
 
+    /// When all the nodes in an AZ go offline, how many shards to fail over to other AZs at a
+    /// time.  The rest follow on later passes of the background reconciler.
+    #[arg(long)]
+    az_failover_per_pass: Option<usize>,
+
     /// How long to wait for the initial database connection to be available.
     #[arg(long, default_value = "5s")]
     db_connect_timeout: humantime::Duration,
@@ -108,6 +135,38 @@ struct Cli {
     // Period with which to send heartbeats to registered nodes
     #[arg(long)]
     heartbeat_interval: Option<humantime::Duration>,
+
+    /// How long to keep operation history (scheduling decisions, reconciles, node state changes
+    /// and API calls) for
+    #[arg(long)]
+    history_retention: Option<humantime::Duration>,
+
+    /// Hibernate tenants that have had no compute activity for this long, by moving them to a
+    /// secondary location.  They are re-attached when woken by their compute.
+    #[arg(long)]
+    hibernate_after: Option<humantime::Duration>,
+
+    /// Detach hibernated tenants entirely after they have spent this long in a secondary location
+    #[arg(long)]
+    hibernate_detach_after: Option<humantime::Duration>,
+
+    /// Elect a leader by acquiring a lease from a majority of these controllers, rather than
+    /// through the database.  Repeat for each controller, including this one (which must match
+    /// `--address-for-peers`).
+    #[arg(long)]
+    leader_lease_peer: Vec<Uri>,
+
+    /// How long a leader lease lasts without being renewed
+    #[arg(long, default_value = "10s")]
+    leader_lease_ttl: humantime::Duration,
+
+    /// Where to keep this controller's leader lease votes: required with `--leader-lease-peer`
+    #[arg(long)]
+    leader_lease_state: Option<Utf8PathBuf>,
+
+    /// Where to keep basebackup and WAL archives uploaded for tenant imports
+    #[arg(long)]
+    import_dir: Option<PathBuf>,
 }
 
 enum StrictMode {
@@ -134,6 +193,7 @@ struct Secrets {
     jwt_token: Option<String>,
     control_plane_jwt_token: Option<String>,
     peer_jwt_token: Option<String>,
+    compute_hook_hmac_key: Option<String>,
 }
 
 impl Secrets {
@@ -141,6 +201,7 @@ impl Secrets {
     const PAGESERVER_JWT_TOKEN_ENV: &'static str = "PAGESERVER_JWT_TOKEN";
     const CONTROL_PLANE_JWT_TOKEN_ENV: &'static str = "CONTROL_PLANE_JWT_TOKEN";
     const PEER_JWT_TOKEN_ENV: &'static str = "PEER_JWT_TOKEN";
+    const COMPUTE_HOOK_HMAC_KEY_ENV: &'static str = "COMPUTE_HOOK_HMAC_KEY";
     const PUBLIC_KEY_ENV: &'static str = "PUBLIC_KEY";
 
     /// Load secrets from, in order of preference:
@@ -168,6 +229,10 @@ async fn load(args: &Cli) -> anyhow::Result<Self> {
                 Self::CONTROL_PLANE_JWT_TOKEN_ENV,
             ),
             peer_jwt_token: Self::load_secret(&args.peer_jwt_token, Self::PEER_JWT_TOKEN_ENV),
+            compute_hook_hmac_key: Self::load_secret(
+                &args.compute_hook_hmac_key,
+                Self::COMPUTE_HOOK_HMAC_KEY_ENV,
+            ),
         };
 
         Ok(this)
@@ -255,7 +320,12 @@ async fn async_main() -> anyhow::Result<()> {
                     "Insecure config!  One or more secrets is not set.  This is only permitted in `--dev` mode"
                 );
         }
-        StrictMode::Strict if args.compute_hook_url.is_none() => {
+        StrictMode::Strict
+            if args.compute_hook_url.is_none()
+                && args.compute_hook_file.is_none()
+                && args.compute_hook_socket.is_none()
+                && args.compute_hook_command.is_none() =>
+        {
             // Production systems should always have a compute hook set, to prevent falling
             // back to trying to use neon_local.
             anyhow::bail!(
@@ -270,11 +340,40 @@ async fn async_main() -> anyhow::Result<()> {
         }
     }
 
+    let leader_lease = if args.leader_lease_peer.is_empty() {
+        None
+    } else {
+        let Some(address_for_peers) = &args.address_for_peers else {
+            anyhow::bail!("`--leader-lease-peer` requires `--address-for-peers`");
+        };
+        if !args.leader_lease_peer.contains(address_for_peers) {
+            anyhow::bail!("`--leader-lease-peer` must include `--address-for-peers`");
+        }
+        Some(LeaderLeaseConfig {
+            peers: args.leader_lease_peer.clone(),
+            ttl: args.leader_lease_ttl.into(),
+        })
+    };
+    let lease_voter = match (&leader_lease, &args.leader_lease_state) {
+        (None, _) => None,
+        (Some(lease_config), Some(path)) => Some(Arc::new(
+            LeaseVoter::load(path.clone(), lease_config.ttl)
+                .with_context(|| format!("Loading leader lease state from {path}"))?,
+        )),
+        (Some(_), None) => {
+            anyhow::bail!("`--leader-lease-peer` requires `--leader-lease-state`")
+        }
+    };
+
     let config = Config {
         jwt_token: secrets.jwt_token,
         control_plane_jwt_token: secrets.control_plane_jwt_token,
         peer_jwt_token: secrets.peer_jwt_token,
         compute_hook_url: args.compute_hook_url,
+        compute_hook_hmac_key: secrets.compute_hook_hmac_key,
+        compute_hook_file: args.compute_hook_file,
+        compute_hook_socket: args.compute_hook_socket,
+        compute_hook_command: args.compute_hook_command,
         max_offline_interval: args
             .max_offline_interval
             .map(humantime::Duration::into)
@@ -286,6 +385,9 @@ async fn async_main() -> anyhow::Result<()> {
         reconciler_concurrency: args
             .reconciler_concurrency
             .unwrap_or(RECONCILER_CONCURRENCY_DEFAULT),
+        az_failover_per_pass: args
+            .az_failover_per_pass
+            .unwrap_or(AZ_FAILOVER_PER_PASS_DEFAULT),
         split_threshold: args.split_threshold,
         neon_local_repo_dir: args.neon_local_repo_dir,
         max_secondary_lag_bytes: args.max_secondary_lag_bytes,
@@ -293,9 +395,17 @@ async fn async_main() -> anyhow::Result<()> {
             .heartbeat_interval
             .map(humantime::Duration::into)
             .unwrap_or(HEARTBEAT_INTERVAL_DEFAULT),
+        history_retention: args
+            .history_retention
+            .map(humantime::Duration::into)
+            .unwrap_or(HISTORY_RETENTION_DEFAULT),
+        hibernate_after: args.hibernate_after.map(humantime::Duration::into),
+        hibernate_detach_after: args.hibernate_detach_after.map(humantime::Duration::into),
         address_for_peers: args.address_for_peers,
         start_as_candidate: args.start_as_candidate,
         http_service_port: args.listen.port() as i32,
+        leader_lease,
+        import_dir: args.import_dir,
     };
 
     // Validate that we can connect to the database
@@ -303,13 +413,43 @@ async fn async_main() -> anyhow::Result<()> {
 
     let persistence = Arc::new(Persistence::new(secrets.database_url));
 
-    let service = Service::spawn(config, persistence.clone()).await?;
-
     let http_listener = tcp_listener::bind(args.listen)?;
 
     let auth = secrets
         .public_key
         .map(|jwt_auth| Arc::new(SwappableJwtAuth::new(jwt_auth)));
+
+    // While we campaign for a leader lease, our peers need us to vote in their campaigns too
+    let standby = match &lease_voter {
+        Some(voter) => {
+            let router = make_standby_router(voter.clone(), auth.clone())
+                .build()
+                .map_err(|err| anyhow!(err))?;
+            let shutdown = CancellationToken::new();
+            let server = hyper::Server::from_tcp(http_listener.try_clone()?)?
+                .serve(utils::http::RouterService::new(router).unwrap())
+                .with_graceful_shutdown({
+                    let shutdown = shutdown.clone();
+                    async move {
+                        shutdown.cancelled().await;
+                    }
+                });
+            tracing::info!("Serving leader lease votes on {0}", args.listen);
+            Some((tokio::task::spawn(server), shutdown))
+        }
+        None => None,
+    };
+
+    let service = Service::spawn(config, persistence.clone(), lease_voter).await?;
+
+    if let Some((standby_task, shutdown)) = standby {
+        shutdown.cancel();
+        match standby_task.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Error in leader lease HTTP server: {e}"),
+            Err(e) => tracing::error!("Error joining leader lease HTTP server task: {e}"),
+        }
+    }
     let router = make_router(service.clone(), auth, build_info)
         .build()
         .map_err(|err| anyhow!(err))?;
@@ -44,6 +44,25 @@ pub(crate) struct StorageControllerMetricGroup {
     /// Count of how many times we make an optimization change to a tenant's scheduling
     pub(crate) storage_controller_schedule_optimization: measured::Counter,
 
+    /// Count of operation history events that were not persisted, because the queue
+    /// to the database writer was full or the write failed
+    pub(crate) storage_controller_history_events_dropped: measured::Counter,
+
+    /// Count of idle tenants hibernated, broken down by the placement policy they were moved to
+    pub(crate) storage_controller_tenant_hibernate:
+        measured::CounterVec<TenantHibernateLabelGroupSet>,
+
+    /// Count of hibernated tenants woken up
+    pub(crate) storage_controller_tenant_wake: measured::Counter,
+
+    /// Time taken to wake a hibernated tenant, until its attached locations are reconciled
+    #[metric(metadata = histogram::Thresholds::exponential_buckets(0.1, 2.0))]
+    pub(crate) storage_controller_tenant_wake_latency: measured::Histogram<8>,
+
+    /// Count of times every node in an availability zone went offline, triggering a failover of
+    /// that AZ's attachments to secondaries elsewhere
+    pub(crate) storage_controller_az_failover: measured::Counter,
+
     /// HTTP request status counters for handled requests
     pub(crate) storage_controller_http_request_status:
         measured::CounterVec<HttpRequestStatusLabelGroupSet>,
@@ -108,6 +127,9 @@ fn default() -> Self {
         metrics_group
             .storage_controller_reconcile_complete
             .init_all_dense();
+        metrics_group
+            .storage_controller_tenant_hibernate
+            .init_all_dense();
 
         Self {
             metrics_group,
@@ -168,6 +190,18 @@ pub(crate) struct LeadershipStatusGroup {
     pub(crate) status: LeadershipStatus,
 }
 
+#[derive(measured::LabelGroup)]
+#[label(set = TenantHibernateLabelGroupSet)]
+pub(crate) struct TenantHibernateLabelGroup {
+    pub(crate) placement: HibernatePlacement,
+}
+
+#[derive(FixedCardinalityLabel, Clone, Copy)]
+pub(crate) enum HibernatePlacement {
+    Secondary,
+    Detached,
+}
+
 #[derive(FixedCardinalityLabel, Clone, Copy)]
 pub(crate) enum ReconcileOutcome {
     #[label(rename = "ok")]
@@ -231,6 +265,7 @@ pub(crate) enum DatabaseErrorLabel {
     ConnectionPool,
     Logical,
     Migration,
+    Fenced,
 }
 
 impl DatabaseError {
@@ -241,6 +276,7 @@ pub(crate) fn error_label(&self) -> DatabaseErrorLabel {
             Self::ConnectionPool(_) => DatabaseErrorLabel::ConnectionPool,
             Self::Logical(_) => DatabaseErrorLabel::Logical,
             Self::Migration(_) => DatabaseErrorLabel::Migration,
+            Self::Fenced(_) => DatabaseErrorLabel::Fenced,
         }
     }
 }
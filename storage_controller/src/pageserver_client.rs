@@ -1,18 +1,22 @@
 use pageserver_api::{
     models::{
         detach_ancestor::AncestorDetached, LocationConfig, LocationConfigListResponse,
-        PageserverUtilization, SecondaryProgress, TenantScanRemoteStorageResponse,
-        TenantShardSplitRequest, TenantShardSplitResponse, TimelineArchivalConfigRequest,
-        TimelineCreateRequest, TimelineInfo, TopTenantShardsRequest, TopTenantShardsResponse,
+        PageserverUtilization, SecondaryProgress, StatusResponse, TenantScanRemoteStorageResponse,
+        TenantShardMergeRequest, TenantShardMergeResponse, TenantShardSplitRequest,
+        TenantShardSplitResponse, TimelineArchivalConfigRequest, TimelineCreateRequest,
+        TimelineInfo, TopTenantShardsRequest, TopTenantShardsResponse,
     },
     shard::TenantShardId,
 };
 use pageserver_client::{
-    mgmt_api::{Client, Result},
+    mgmt_api::{Client, ReqwestBody, Result},
     BlockUnblock,
 };
 use reqwest::StatusCode;
-use utils::id::{NodeId, TenantId, TimelineId};
+use utils::{
+    id::{NodeId, TenantId, TimelineId},
+    lsn::Lsn,
+};
 
 /// Thin wrapper around [`pageserver_client::mgmt_api::Client`]. It allows the storage
 /// controller to collect metrics in a non-intrusive manner.
@@ -218,6 +222,19 @@ pub(crate) async fn tenant_shard_split(
         )
     }
 
+    pub(crate) async fn tenant_shard_merge(
+        &self,
+        tenant_shard_id: TenantShardId,
+        req: TenantShardMergeRequest,
+    ) -> Result<TenantShardMergeResponse> {
+        measured_request!(
+            "tenant_shard_merge",
+            crate::metrics::Method::Put,
+            &self.node_id_label,
+            self.inner.tenant_shard_merge(tenant_shard_id, req).await
+        )
+    }
+
     pub(crate) async fn timeline_list(
         &self,
         tenant_shard_id: &TenantShardId,
@@ -279,6 +296,52 @@ pub(crate) async fn timeline_block_unblock_gc(
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn import_basebackup(
+        &self,
+        tenant_shard_id: TenantShardId,
+        timeline_id: TimelineId,
+        base_lsn: Lsn,
+        end_lsn: Lsn,
+        pg_version: u32,
+        image_layers: bool,
+        basebackup: ReqwestBody,
+    ) -> Result<()> {
+        // measuring these makes no sense: their duration depends on the size of the archive
+        self.inner
+            .import_basebackup(
+                tenant_shard_id,
+                timeline_id,
+                base_lsn,
+                end_lsn,
+                pg_version,
+                image_layers,
+                basebackup,
+            )
+            .await
+    }
+
+    pub(crate) async fn import_wal(
+        &self,
+        tenant_shard_id: TenantShardId,
+        timeline_id: TimelineId,
+        start_lsn: Lsn,
+        end_lsn: Lsn,
+        image_layers: bool,
+        wal: ReqwestBody,
+    ) -> Result<()> {
+        self.inner
+            .import_wal(
+                tenant_shard_id,
+                timeline_id,
+                start_lsn,
+                end_lsn,
+                image_layers,
+                wal,
+            )
+            .await
+    }
+
     pub(crate) async fn get_utilization(&self) -> Result<PageserverUtilization> {
         measured_request!(
             "utilization",
@@ -288,6 +351,15 @@ pub(crate) async fn get_utilization(&self) -> Result<PageserverUtilization> {
         )
     }
 
+    pub(crate) async fn get_status(&self) -> Result<StatusResponse> {
+        measured_request!(
+            "status",
+            crate::metrics::Method::Get,
+            &self.node_id_label,
+            self.inner.get_status().await
+        )
+    }
+
     pub(crate) async fn top_tenant_shards(
         &self,
         request: TopTenantShardsRequest,
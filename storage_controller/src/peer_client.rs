@@ -1,3 +1,4 @@
+use crate::leadership::lease::{LeaseRequest, LeaseResponse};
 use crate::tenant_shard::ObservedState;
 use pageserver_api::shard::TenantShardId;
 use serde::{Deserialize, Serialize};
@@ -105,4 +106,35 @@ pub(crate) async fn step_down(
         .ok_or_else(|| StorageControllerPeerError::Cancelled)
         .and_then(|x| x)
     }
+
+    /// Ask the peer to grant, renew or release a leader lease: see [`crate::leadership::lease`].
+    /// Not retried: callers ask again on their next round of requests.
+    pub(crate) async fn leader_lease(
+        &self,
+        lease_request: &LeaseRequest,
+        timeout: Duration,
+    ) -> Result<LeaseResponse> {
+        let lease_path = format!("{}control/v1/leader_lease", self.uri);
+        let req = self.client.put(lease_path).json(lease_request);
+        let req = if let Some(jwt) = &self.jwt {
+            req.header(reqwest::header::AUTHORIZATION, format!("Bearer {jwt}"))
+        } else {
+            req
+        };
+
+        let res = req
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(StorageControllerPeerError::SendError)?;
+        let response = res.error_from_body().await?;
+
+        let status = response.status();
+        let url = response.url().to_owned();
+
+        response
+            .json()
+            .await
+            .map_err(|err| StorageControllerPeerError::DeserializationError(status, url, err))
+    }
 }
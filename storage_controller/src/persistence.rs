@@ -1,6 +1,7 @@
 pub(crate) mod split_state;
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::Duration;
 use std::time::Instant;
 
@@ -12,6 +13,10 @@
 use pageserver_api::controller_api::MetadataHealthRecord;
 use pageserver_api::controller_api::ShardSchedulingPolicy;
 use pageserver_api::controller_api::{NodeSchedulingPolicy, PlacementPolicy};
+use pageserver_api::controller_api::{
+    OperationEvent, OperationEventKind, RollingOperationState, RollingOperationStatus,
+    TenantImportState, TenantImportStatus,
+};
 use pageserver_api::models::TenantConfig;
 use pageserver_api::shard::ShardConfigError;
 use pageserver_api::shard::ShardIdentity;
@@ -56,8 +61,32 @@
 /// Database calls relating to nodes have low performance requirements, as they are very rarely
 /// updated, and reads of nodes are always from memory, not the database.  We only require that
 /// we can UPDATE a node's scheduling mode reasonably quickly to mark a bad node offline.
+///
+/// ## Fencing
+///
+/// When controllers elect a leader by lease (see [`crate::leadership::lease`]), a deposed leader
+/// may not find out that it lost its lease before it next writes.  The leader therefore records
+/// its lease term in the database with [`Persistence::fence`], after which every transaction
+/// checks that no newer term has been recorded.  Without a lease, there is no fencing.
 pub struct Persistence {
     connection_pool: diesel::r2d2::Pool<diesel::r2d2::ConnectionManager<PgConnection>>,
+
+    /// Our lease term, as recorded by [`Self::fence`], or zero if we have not fenced
+    fencing_term: AtomicI64,
+}
+
+/// Fail the transaction if a leader with a newer lease term than ours has fenced the database.
+fn check_fence(conn: &mut PgConnection, fencing_term: i64) -> DatabaseResult<()> {
+    use crate::schema::leader_fence;
+
+    let current: i64 = leader_fence::table.select(leader_fence::term).first(conn)?;
+    if current > fencing_term {
+        Err(DatabaseError::Fenced(format!(
+            "leader term {current} has replaced our term {fencing_term}"
+        )))
+    } else {
+        Ok(())
+    }
 }
 
 /// Legacy format, for use in JSON compat objects in test environment
@@ -78,6 +107,8 @@ pub(crate) enum DatabaseError {
     Logical(String),
     #[error("Migration error: {0}")]
     Migration(String),
+    #[error("Fenced: {0}")]
+    Fenced(String),
 }
 
 #[derive(measured::FixedCardinalityLabel, Copy, Clone)]
@@ -89,6 +120,8 @@ pub(crate) enum DatabaseOperation {
     BeginShardSplit,
     CompleteShardSplit,
     AbortShardSplit,
+    BeginShardMerge,
+    AbortShardMerge,
     Detach,
     ReAttach,
     IncrementGeneration,
@@ -106,6 +139,22 @@ pub(crate) enum DatabaseOperation {
     GetLeader,
     UpdateLeader,
     SetPreferredAzs,
+    InsertRollingOperation,
+    UpdateRollingOperation,
+    ListRollingOperations,
+    InsertTenantImport,
+    UpdateTenantImport,
+    ListTenantImports,
+    InsertOperationEvents,
+    ListOperationEvents,
+    DeleteOperationEvents,
+    InsertHibernatedTenant,
+    DeleteHibernatedTenant,
+    ListHibernatedTenants,
+    UpsertComputeNotification,
+    DeleteComputeNotification,
+    ListComputeNotifications,
+    Fence,
 }
 
 #[must_use]
@@ -155,7 +204,10 @@ pub fn new(database_url: String) -> Self {
             .build(manager)
             .expect("Could not build connection pool");
 
-        Self { connection_pool }
+        Self {
+            connection_pool,
+            fencing_term: AtomicI64::new(0),
+        }
     }
 
     /// A helper for use during startup, where we would like to tolerate concurrent restarts of the
@@ -233,11 +285,18 @@ async fn with_conn<F, R>(&self, func: F) -> DatabaseResult<R>
         // somehow engineer a situation where duelling transactions might otherwise live-lock.
         const MAX_RETRIES: usize = 128;
 
+        let fencing_term = self.fencing_term.load(Ordering::Relaxed);
+
         let mut conn = self.connection_pool.get()?;
         tokio::task::spawn_blocking(move || -> DatabaseResult<R> {
             let mut retry_count = 0;
             loop {
-                match conn.build_transaction().serializable().run(|c| func(c)) {
+                match conn.build_transaction().serializable().run(|c| {
+                    if fencing_term > 0 {
+                        check_fence(c, fencing_term)?;
+                    }
+                    func(c)
+                }) {
                     Ok(r) => break Ok(r),
                     Err(
                         err @ DatabaseError::Query(diesel::result::Error::DatabaseError(
@@ -269,6 +328,35 @@ async fn with_conn<F, R>(&self, func: F) -> DatabaseResult<R>
         .expect("Task panic")
     }
 
+    /// Record `term` as the newest leader lease term, so that controllers holding older terms can
+    /// no longer write, and check for newer terms in all our transactions from now on.
+    ///
+    /// Fails if a newer term has been recorded, i.e. if we have already been deposed.  The same term
+    /// may be recorded again, because a restarted controller can be granted its own term again.
+    pub(crate) async fn fence(&self, term: i64) -> DatabaseResult<()> {
+        use crate::schema::leader_fence;
+
+        self.with_measured_conn(
+            DatabaseOperation::Fence,
+            move |conn| -> DatabaseResult<()> {
+                let updated = diesel::update(leader_fence::table)
+                    .filter(leader_fence::term.le(term))
+                    .set(leader_fence::term.eq(term))
+                    .execute(conn)?;
+                if updated != 1 {
+                    return Err(DatabaseError::Fenced(format!(
+                        "term {term} is older than the recorded leader term"
+                    )));
+                }
+                Ok(())
+            },
+        )
+        .await?;
+
+        self.fencing_term.store(term, Ordering::Relaxed);
+        Ok(())
+    }
+
     /// When a node is first registered, persist it before using it for anything
     pub(crate) async fn insert_node(&self, node: &Node) -> DatabaseResult<()> {
         let np = node.to_persistent();
@@ -386,6 +474,17 @@ pub(crate) async fn delete_tenant(&self, del_tenant_id: TenantId) -> DatabaseRes
                 diesel::delete(tenant_shards)
                     .filter(tenant_id.eq(del_tenant_id.to_string()))
                     .execute(conn)?;
+                diesel::delete(crate::schema::hibernated_tenants::table)
+                    .filter(
+                        crate::schema::hibernated_tenants::tenant_id.eq(del_tenant_id.to_string()),
+                    )
+                    .execute(conn)?;
+                diesel::delete(crate::schema::compute_notifications::table)
+                    .filter(
+                        crate::schema::compute_notifications::tenant_id
+                            .eq(del_tenant_id.to_string()),
+                    )
+                    .execute(conn)?;
                 Ok(())
             },
         )
@@ -850,6 +949,109 @@ pub(crate) async fn abort_shard_split(
         .await
     }
 
+    /// The inverse of [`Self::begin_shard_split`]: mark the source shards as splitting, and insert the
+    /// merged shards.  Each merged shard takes the newest generation of the shards it is made of, as that
+    /// is the generation the pageserver will attach it with.
+    pub(crate) async fn begin_shard_merge(
+        &self,
+        old_shard_count: ShardCount,
+        merge_tenant_id: TenantId,
+        merged_to_sources: Vec<(TenantShardPersistence, Vec<TenantShardId>)>,
+    ) -> DatabaseResult<()> {
+        use crate::schema::tenant_shards::dsl::*;
+        self.with_measured_conn(DatabaseOperation::BeginShardMerge, move |conn| -> DatabaseResult<()> {
+            // Mark source shards as splitting
+            let updated = diesel::update(tenant_shards)
+                .filter(tenant_id.eq(merge_tenant_id.to_string()))
+                .filter(shard_count.eq(old_shard_count.literal() as i32))
+                .set((splitting.eq(1),))
+                .execute(conn)?;
+            if u8::try_from(updated)
+                .map_err(|_| DatabaseError::Logical(
+                    format!("Overflow existing shard count {} while merging", updated))
+                )? != old_shard_count.count() {
+                // Perhaps a deletion or a split raced with this attempt to merge: the merge request should fail.
+                return Err(DatabaseError::Logical(
+                    format!("Unexpected existing shard count {updated} when preparing tenant for merge (expected {})", old_shard_count.count())
+                ));
+            }
+
+            // Insert merged shards
+            for (merged, sources) in &merged_to_sources {
+                let mut merged = merged.clone();
+                let mut merged_generation = None;
+                for source_shard_id in sources {
+                    let source = crate::schema::tenant_shards::table
+                        .filter(tenant_id.eq(source_shard_id.tenant_id.to_string()))
+                        .filter(shard_number.eq(source_shard_id.shard_number.0 as i32))
+                        .filter(shard_count.eq(source_shard_id.shard_count.literal() as i32))
+                        .load::<TenantShardPersistence>(conn)?;
+                    let [source] = source.as_slice() else {
+                        return Err(DatabaseError::Logical(format!(
+                            "Source shard {source_shard_id} not found"
+                        )));
+                    };
+                    merged_generation = std::cmp::max(merged_generation, source.generation);
+                }
+                merged.generation = merged_generation;
+
+                debug_assert!(merged.splitting == SplitState::Merging);
+                diesel::insert_into(tenant_shards)
+                    .values(merged)
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Used when the remote part of a shard merge failed: we will revert the database state to have only
+    /// the source shards, with SplitState::Idle.
+    ///
+    /// Completing a merge is the same database operation as completing a split, see [`Self::complete_shard_split`].
+    pub(crate) async fn abort_shard_merge(
+        &self,
+        merge_tenant_id: TenantId,
+        new_shard_count: ShardCount,
+    ) -> DatabaseResult<AbortShardSplitStatus> {
+        use crate::schema::tenant_shards::dsl::*;
+        self.with_measured_conn(
+            DatabaseOperation::AbortShardMerge,
+            move |conn| -> DatabaseResult<AbortShardSplitStatus> {
+                // Clear the splitting state on source shards
+                let updated = diesel::update(tenant_shards)
+                    .filter(tenant_id.eq(merge_tenant_id.to_string()))
+                    .filter(shard_count.ne(new_shard_count.literal() as i32))
+                    .set((splitting.eq(0),))
+                    .execute(conn)?;
+
+                // Source shards are already gone: we cannot abort.
+                if updated == 0 {
+                    return Ok(AbortShardSplitStatus::Complete);
+                }
+
+                // Sanity check: if source shards were present, their cardinality should
+                // be greater than the number of merged shards.
+                if updated <= new_shard_count.count() as usize {
+                    return Err(DatabaseError::Logical(format!(
+                        "Unexpected source shard count {updated} while aborting merge to \
+                            count {new_shard_count:?} on tenant {merge_tenant_id}"
+                    )));
+                }
+
+                // Erase merged shards
+                diesel::delete(tenant_shards)
+                    .filter(tenant_id.eq(merge_tenant_id.to_string()))
+                    .filter(shard_count.eq(new_shard_count.literal() as i32))
+                    .execute(conn)?;
+
+                Ok(AbortShardSplitStatus::Aborted)
+            },
+        )
+        .await
+    }
+
     /// Stores all the latest metadata health updates durably. Updates existing entry on conflict.
     ///
     /// **Correctness:** `metadata_health_updates` should all belong the tenant shards managed by the storage controller.
@@ -1001,6 +1203,373 @@ pub(crate) async fn update_leader(
         Ok(())
     }
 
+    /// Persist a new rolling operation.  Fails with [`DatabaseError::Logical`] if another one is
+    /// already in progress: we only run one at a time, which a unique index on the active states
+    /// also enforces.
+    pub(crate) async fn insert_rolling_operation(
+        &self,
+        operation: RollingOperationPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::rolling_operations::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::InsertRollingOperation,
+            move |conn| -> DatabaseResult<()> {
+                let in_progress: i64 = rolling_operations
+                    .filter(state.eq_any([
+                        String::from(RollingOperationState::Running),
+                        String::from(RollingOperationState::Paused),
+                    ]))
+                    .count()
+                    .get_result(conn)?;
+                if in_progress > 0 {
+                    return Err(DatabaseError::Logical(
+                        "Another rolling operation is in progress".to_string(),
+                    ));
+                }
+
+                match diesel::insert_into(rolling_operations)
+                    .values(&operation)
+                    .execute(conn)
+                {
+                    Ok(_) => Ok(()),
+                    Err(diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::UniqueViolation,
+                        info,
+                    )) if info.constraint_name() == Some("rolling_operations_active") => {
+                        Err(DatabaseError::Logical(
+                            "Another rolling operation is in progress".to_string(),
+                        ))
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            },
+        )
+        .await
+    }
+
+    /// Update the state and progress of a rolling operation
+    pub(crate) async fn update_rolling_operation(
+        &self,
+        operation: RollingOperationPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::rolling_operations::dsl::*;
+
+        let updated = self
+            .with_measured_conn(
+                DatabaseOperation::UpdateRollingOperation,
+                move |conn| -> DatabaseResult<usize> {
+                    Ok(diesel::update(rolling_operations)
+                        .filter(id.eq(&operation.id))
+                        .set((
+                            state.eq(&operation.state),
+                            nodes.eq(&operation.nodes),
+                            error.eq(&operation.error),
+                            updated_at.eq(operation.updated_at),
+                        ))
+                        .execute(conn)?)
+                },
+            )
+            .await?;
+
+        if updated != 1 {
+            return Err(DatabaseError::Logical(
+                "Rolling operation not found for update".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Rolling operations, most recently created first
+    pub(crate) async fn list_rolling_operations(
+        &self,
+    ) -> DatabaseResult<Vec<RollingOperationPersistence>> {
+        use crate::schema::rolling_operations::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::ListRollingOperations,
+            move |conn| -> DatabaseResult<_> {
+                Ok(rolling_operations
+                    .order(created_at.desc())
+                    .load::<RollingOperationPersistence>(conn)?)
+            },
+        )
+        .await
+    }
+
+    /// Persist a new tenant import.  Fails if the tenant already has one.
+    pub(crate) async fn insert_tenant_import(
+        &self,
+        import: TenantImportPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::tenant_imports::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::InsertTenantImport,
+            move |conn| -> DatabaseResult<()> {
+                let existing: i64 = tenant_imports
+                    .filter(tenant_id.eq(&import.tenant_id))
+                    .count()
+                    .get_result(conn)?;
+                if existing > 0 {
+                    return Err(DatabaseError::Logical(format!(
+                        "Tenant {} already has an import",
+                        import.tenant_id
+                    )));
+                }
+
+                diesel::insert_into(tenant_imports)
+                    .values(&import)
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Update the state and progress of a tenant import
+    pub(crate) async fn update_tenant_import(
+        &self,
+        import: TenantImportPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::tenant_imports::dsl::*;
+
+        let updated = self
+            .with_measured_conn(
+                DatabaseOperation::UpdateTenantImport,
+                move |conn| -> DatabaseResult<usize> {
+                    Ok(diesel::update(tenant_imports)
+                        .filter(tenant_id.eq(&import.tenant_id))
+                        .set((
+                            state.eq(&import.state),
+                            shards.eq(&import.shards),
+                            error.eq(&import.error),
+                            updated_at.eq(import.updated_at),
+                        ))
+                        .execute(conn)?)
+                },
+            )
+            .await?;
+
+        if updated != 1 {
+            return Err(DatabaseError::Logical(
+                "Tenant import not found for update".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Tenant imports, most recently created first
+    pub(crate) async fn list_tenant_imports(&self) -> DatabaseResult<Vec<TenantImportPersistence>> {
+        use crate::schema::tenant_imports::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::ListTenantImports,
+            move |conn| -> DatabaseResult<_> {
+                Ok(tenant_imports
+                    .order(created_at.desc())
+                    .load::<TenantImportPersistence>(conn)?)
+            },
+        )
+        .await
+    }
+
+    pub(crate) async fn insert_operation_events(
+        &self,
+        events: Vec<NewOperationEventPersistence>,
+    ) -> DatabaseResult<()> {
+        use crate::schema::operation_events::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::InsertOperationEvents,
+            move |conn| -> DatabaseResult<()> {
+                diesel::insert_into(operation_events)
+                    .values(&events)
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Operation history matching the filter, most recent first
+    pub(crate) async fn list_operation_events(
+        &self,
+        filter: OperationEventFilter,
+    ) -> DatabaseResult<Vec<OperationEventPersistence>> {
+        use crate::schema::operation_events::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::ListOperationEvents,
+            move |conn| -> DatabaseResult<_> {
+                let mut query = operation_events.into_boxed();
+                if let Some(filter_tenant_id) = filter.tenant_id {
+                    query = query.filter(tenant_id.eq(filter_tenant_id.to_string()));
+                }
+                if let Some(filter_node_id) = filter.node_id {
+                    query = query.filter(node_id.eq(filter_node_id.0 as i64));
+                }
+                if let Some(filter_kind) = filter.kind {
+                    query = query.filter(kind.eq(String::from(filter_kind)));
+                }
+                if let Some(since) = filter.since {
+                    query = query.filter(timestamp.ge(since));
+                }
+                if let Some(until) = filter.until {
+                    query = query.filter(timestamp.lt(until));
+                }
+
+                Ok(query
+                    .order(id.desc())
+                    .limit(filter.limit)
+                    .load::<OperationEventPersistence>(conn)?)
+            },
+        )
+        .await
+    }
+
+    /// Remove operation history from before `cutoff`, returning how many events were removed
+    pub(crate) async fn delete_operation_events_before(
+        &self,
+        cutoff: chrono::DateTime<chrono::Utc>,
+    ) -> DatabaseResult<usize> {
+        use crate::schema::operation_events::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::DeleteOperationEvents,
+            move |conn| -> DatabaseResult<usize> {
+                Ok(diesel::delete(operation_events)
+                    .filter(timestamp.lt(cutoff))
+                    .execute(conn)?)
+            },
+        )
+        .await
+    }
+
+    /// Record that a tenant was hibernated, and the placement policy to restore when it wakes.  If
+    /// the tenant is already hibernated, the existing record is kept.
+    pub(crate) async fn insert_hibernated_tenant(
+        &self,
+        hibernated: HibernatedTenantPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::hibernated_tenants::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::InsertHibernatedTenant,
+            move |conn| -> DatabaseResult<()> {
+                diesel::insert_into(hibernated_tenants)
+                    .values(&hibernated)
+                    .on_conflict(tenant_id)
+                    .do_nothing()
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    pub(crate) async fn delete_hibernated_tenant(
+        &self,
+        del_tenant_id: TenantId,
+    ) -> DatabaseResult<()> {
+        use crate::schema::hibernated_tenants::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::DeleteHibernatedTenant,
+            move |conn| -> DatabaseResult<()> {
+                diesel::delete(hibernated_tenants)
+                    .filter(tenant_id.eq(del_tenant_id.to_string()))
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Hibernated tenants, optionally only the one with this ID
+    pub(crate) async fn list_hibernated_tenants(
+        &self,
+        filter_tenant_id: Option<TenantId>,
+    ) -> DatabaseResult<Vec<HibernatedTenantPersistence>> {
+        use crate::schema::hibernated_tenants::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::ListHibernatedTenants,
+            move |conn| -> DatabaseResult<_> {
+                let mut query = hibernated_tenants.into_boxed();
+                if let Some(filter_tenant_id) = filter_tenant_id {
+                    query = query.filter(tenant_id.eq(filter_tenant_id.to_string()));
+                }
+                Ok(query.load::<HibernatedTenantPersistence>(conn)?)
+            },
+        )
+        .await
+    }
+
+    /// Record a compute notification that failed to deliver, replacing any earlier one for the
+    /// same tenant (but keeping its `first_failed_at`, and counting on from its `attempts`, which
+    /// a newly started controller may not have loaded yet).
+    pub(crate) async fn upsert_compute_notification(
+        &self,
+        notification: ComputeNotificationPersistence,
+    ) -> DatabaseResult<()> {
+        use crate::schema::compute_notifications::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::UpsertComputeNotification,
+            move |conn| -> DatabaseResult<()> {
+                diesel::insert_into(compute_notifications)
+                    .values(&notification)
+                    .on_conflict(tenant_id)
+                    .do_update()
+                    .set((
+                        request.eq(&notification.request),
+                        attempts.eq(attempts + 1),
+                        last_error.eq(&notification.last_error),
+                        next_attempt_at.eq(notification.next_attempt_at),
+                    ))
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    pub(crate) async fn delete_compute_notification(
+        &self,
+        del_tenant_id: TenantId,
+    ) -> DatabaseResult<()> {
+        use crate::schema::compute_notifications::dsl::*;
+
+        self.with_measured_conn(
+            DatabaseOperation::DeleteComputeNotification,
+            move |conn| -> DatabaseResult<()> {
+                diesel::delete(compute_notifications)
+                    .filter(tenant_id.eq(del_tenant_id.to_string()))
+                    .execute(conn)?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// All the compute notifications that are waiting to be retried
+    pub(crate) async fn list_compute_notifications(
+        &self,
+    ) -> DatabaseResult<Vec<ComputeNotificationPersistence>> {
+        self.with_measured_conn(
+            DatabaseOperation::ListComputeNotifications,
+            move |conn| -> DatabaseResult<_> {
+                Ok(crate::schema::compute_notifications::table
+                    .order(crate::schema::compute_notifications::next_attempt_at)
+                    .load::<ComputeNotificationPersistence>(conn)?)
+            },
+        )
+        .await
+    }
+
     pub(crate) async fn safekeeper_get(
         &self,
         id: i64,
@@ -1185,6 +1754,165 @@ pub(crate) struct ControllerPersistence {
     pub(crate) started_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Filter for [`Persistence::list_operation_events`]
+pub(crate) struct OperationEventFilter {
+    pub(crate) tenant_id: Option<TenantId>,
+    pub(crate) node_id: Option<NodeId>,
+    pub(crate) kind: Option<OperationEventKind>,
+    pub(crate) since: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) until: Option<chrono::DateTime<chrono::Utc>>,
+    pub(crate) limit: i64,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::operation_events)]
+pub(crate) struct NewOperationEventPersistence {
+    pub(crate) timestamp: chrono::DateTime<chrono::Utc>,
+    pub(crate) kind: String,
+    pub(crate) actor: String,
+    pub(crate) tenant_id: Option<String>,
+    pub(crate) tenant_shard_id: Option<String>,
+    pub(crate) node_id: Option<i64>,
+    pub(crate) detail: String,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = crate::schema::operation_events)]
+pub(crate) struct OperationEventPersistence {
+    pub(crate) id: i64,
+    pub(crate) timestamp: chrono::DateTime<chrono::Utc>,
+    pub(crate) kind: String,
+    pub(crate) actor: String,
+    pub(crate) tenant_id: Option<String>,
+    pub(crate) tenant_shard_id: Option<String>,
+    pub(crate) node_id: Option<i64>,
+    pub(crate) detail: String,
+}
+
+impl OperationEventPersistence {
+    pub(crate) fn into_event(self) -> anyhow::Result<OperationEvent> {
+        Ok(OperationEvent {
+            id: self.id,
+            timestamp: self.timestamp,
+            kind: OperationEventKind::from_str(&self.kind)?,
+            actor: self.actor,
+            tenant_id: self
+                .tenant_id
+                .as_deref()
+                .map(TenantId::from_str)
+                .transpose()?,
+            tenant_shard_id: self
+                .tenant_shard_id
+                .as_deref()
+                .map(TenantShardId::from_str)
+                .transpose()?,
+            node_id: self.node_id.map(|node_id| NodeId(node_id as u64)),
+            detail: self.detail,
+        })
+    }
+}
+
+/// A [`RollingOperationStatus`], with its request and per-node progress stored as JSON
+#[derive(Queryable, Selectable, Insertable, Eq, PartialEq, Debug, Clone)]
+#[diesel(table_name = crate::schema::rolling_operations)]
+pub(crate) struct RollingOperationPersistence {
+    pub(crate) id: String,
+    pub(crate) request: String,
+    pub(crate) state: String,
+    pub(crate) nodes: String,
+    pub(crate) error: Option<String>,
+    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    pub(crate) updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl RollingOperationPersistence {
+    pub(crate) fn from_status(status: &RollingOperationStatus) -> Self {
+        Self {
+            id: status.id.clone(),
+            request: serde_json::to_string(&status.request).expect("serialization is infallible"),
+            state: String::from(status.state),
+            nodes: serde_json::to_string(&status.nodes).expect("serialization is infallible"),
+            error: status.error.clone(),
+            created_at: status.created_at,
+            updated_at: status.updated_at,
+        }
+    }
+
+    pub(crate) fn into_status(self) -> anyhow::Result<RollingOperationStatus> {
+        Ok(RollingOperationStatus {
+            id: self.id,
+            request: serde_json::from_str(&self.request)?,
+            state: RollingOperationState::from_str(&self.state)?,
+            nodes: serde_json::from_str(&self.nodes)?,
+            error: self.error,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        })
+    }
+}
+
+/// A [`TenantImportStatus`], with its request and per-shard progress stored as JSON
+#[derive(Queryable, Selectable, Insertable, Eq, PartialEq, Debug, Clone)]
+#[diesel(table_name = crate::schema::tenant_imports)]
+pub(crate) struct TenantImportPersistence {
+    pub(crate) tenant_id: String,
+    pub(crate) request: String,
+    pub(crate) state: String,
+    pub(crate) shards: String,
+    pub(crate) error: Option<String>,
+    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    pub(crate) updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl TenantImportPersistence {
+    pub(crate) fn from_status(status: &TenantImportStatus) -> Self {
+        Self {
+            tenant_id: status.request.tenant_id.to_string(),
+            request: serde_json::to_string(&status.request).expect("serialization is infallible"),
+            state: String::from(status.state),
+            shards: serde_json::to_string(&status.shards).expect("serialization is infallible"),
+            error: status.error.clone(),
+            created_at: status.created_at,
+            updated_at: status.updated_at,
+        }
+    }
+
+    pub(crate) fn into_status(self) -> anyhow::Result<TenantImportStatus> {
+        Ok(TenantImportStatus {
+            request: serde_json::from_str(&self.request)?,
+            state: TenantImportState::from_str(&self.state)?,
+            shards: serde_json::from_str(&self.shards)?,
+            error: self.error,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        })
+    }
+}
+
+/// A tenant that was moved out of its attached placement for being idle: see
+/// [`crate::service::Service::tenant_wake`]
+#[derive(Queryable, Selectable, Insertable, Eq, PartialEq, Debug, Clone)]
+#[diesel(table_name = crate::schema::hibernated_tenants)]
+pub(crate) struct HibernatedTenantPersistence {
+    pub(crate) tenant_id: String,
+    /// The placement policy the tenant had before hibernating, as JSON
+    pub(crate) placement_policy: String,
+    pub(crate) hibernated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A compute notification that has not been delivered yet: see [`crate::compute_hook`]
+#[derive(Queryable, Selectable, Insertable, Eq, PartialEq, Debug, Clone)]
+#[diesel(table_name = crate::schema::compute_notifications)]
+pub(crate) struct ComputeNotificationPersistence {
+    pub(crate) tenant_id: String,
+    /// The body of the notification, as JSON
+    pub(crate) request: String,
+    pub(crate) attempts: i32,
+    pub(crate) last_error: Option<String>,
+    pub(crate) first_failed_at: chrono::DateTime<chrono::Utc>,
+    pub(crate) next_attempt_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Serialize, Deserialize, Queryable, Selectable, Eq, PartialEq, Debug, Clone)]
 #[diesel(table_name = crate::schema::safekeepers)]
 pub(crate) struct SafekeeperPersistence {
@@ -1,8 +1,11 @@
 use crate::{node::Node, tenant_shard::TenantShard};
 use itertools::Itertools;
+use pageserver_api::models::utilization::RawScore;
 use pageserver_api::models::PageserverUtilization;
+use pageserver_api::shard::TenantShardId;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use utils::{http::error::ApiError, id::NodeId};
 
 /// Scenarios in which we cannot find a suitable location for a tenant shard
@@ -20,13 +23,13 @@ fn from(value: ScheduleError) -> Self {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub enum MaySchedule {
     Yes(PageserverUtilization),
     No,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct SchedulerNode {
     /// How many shards are currently scheduled on this node, via their [`crate::tenant_shard::IntentState`].
     shard_count: usize,
@@ -36,6 +39,9 @@ struct SchedulerNode {
     /// Whether this node is currently elegible to have new shards scheduled (this is derived
     /// from a node's availability state and scheduling policy).
     may_schedule: MaySchedule,
+
+    /// The availability zone this node is in
+    az: String,
 }
 
 impl PartialEq for SchedulerNode {
@@ -48,6 +54,7 @@ fn eq(&self, other: &Self) -> bool {
         may_schedule_matches
             && self.shard_count == other.shard_count
             && self.attached_shard_count == other.attached_shard_count
+            && self.az == other.az
     }
 }
 
@@ -58,9 +65,67 @@ impl Eq for SchedulerNode {}
 ///
 /// The type has no persistent state of its own: this is all populated at startup.  The Serialize
 /// impl is only for debug dumps.
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub(crate) struct Scheduler {
     nodes: HashMap<NodeId, SchedulerNode>,
+
+    /// When we last moved an attachment that was generating load.  Nodes' reported load takes a
+    /// heartbeat or two to reflect a migration, so we wait a while before moving anything else for
+    /// load reasons.
+    #[serde(skip)]
+    last_load_migration: Option<Instant>,
+
+    /// Set on copies made with [`Self::clone_for_planning`]
+    #[serde(skip)]
+    planning: bool,
+}
+
+/// A node's shard counts and utilization score, as seen by the [`Scheduler`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SchedulerNodeStats {
+    pub(crate) shard_count: usize,
+    pub(crate) attached_shard_count: usize,
+    /// None if the node may not have shards scheduled on it
+    pub(crate) utilization_score: Option<RawScore>,
+}
+
+/// A node's load must be at least this far above another's before we move work between them: this
+/// is the hysteresis that prevents us moving shards back and forth between similarly loaded nodes.
+const LOAD_REBALANCE_MIN_SPREAD: RawScore = PageserverUtilization::UTILIZATION_FULL / 4;
+
+/// Nodes below this load are not worth moving work away from, however imbalanced the cluster is.
+const LOAD_REBALANCE_MIN_SCORE: RawScore = PageserverUtilization::UTILIZATION_FULL / 2;
+
+/// Minimum time between migrations made for load reasons
+const LOAD_REBALANCE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A proposal to move work off a busy node, generated by [`Scheduler::plan_load_rebalance`].
+#[derive(Serialize, Debug)]
+pub(crate) struct LoadRebalance {
+    /// The most loaded node, which we would like to move an attachment away from
+    pub(crate) from_node: NodeId,
+    /// Nodes that have enough headroom to take work from `from_node`, least loaded first
+    pub(crate) to_nodes: Vec<NodeId>,
+    /// Shards attached to `from_node` which are worth moving: they carry load, but not so much
+    /// that moving them would make the destination busier than `from_node` is left.
+    pub(crate) shards: Vec<TenantShardId>,
+}
+
+/// Debug view of the scheduler, including the scores that it derives from nodes' utilization
+#[derive(Serialize)]
+pub(crate) struct SchedulerView<'a> {
+    nodes: HashMap<NodeId, SchedulerNodeView<'a>>,
+    load_rebalance: Option<LoadRebalance>,
+    last_load_migration_secs_ago: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct SchedulerNodeView<'a> {
+    #[serde(flatten)]
+    node: &'a SchedulerNode,
+    utilization_score: Option<RawScore>,
+    load_score: Option<RawScore>,
+    overloaded: bool,
 }
 
 /// Score for soft constraint scheduling: lower scores are preferred to higher scores.
@@ -143,6 +208,22 @@ pub(crate) fn get_node_attachments(&self, node_id: NodeId) -> usize {
     }
 }
 
+/// Soft constraint on which availability zone a location should be scheduled in.  Like the
+/// [`ScheduleContext`], this will never cause us to fail to schedule a shard: it only decides
+/// between otherwise eligible nodes, ahead of affinity and utilization.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AzAffinity<'a> {
+    /// No preference
+    Any,
+    /// Prefer nodes in this AZ, if set.  Used for attached locations, which should stay in the
+    /// tenant's preferred AZ so that they are close to its compute.
+    Attached(Option<&'a str>),
+    /// Prefer nodes in AZs that none of the `hard_exclude` nodes are in.  Used for secondary
+    /// locations, so that losing an AZ does not take out a shard's attached location together
+    /// with the secondaries that would take over from it.
+    Secondary,
+}
+
 pub(crate) enum RefCountUpdate {
     PromoteSecondary,
     Attach,
@@ -162,12 +243,15 @@ pub(crate) fn new<'a>(nodes: impl Iterator<Item = &'a Node>) -> Self {
                     shard_count: 0,
                     attached_shard_count: 0,
                     may_schedule: node.may_schedule(),
+                    az: node.get_availability_zone_id().to_string(),
                 },
             );
         }
 
         Self {
             nodes: scheduler_nodes,
+            last_load_migration: None,
+            planning: false,
         }
     }
 
@@ -188,6 +272,7 @@ pub(crate) fn consistency_check<'a>(
                     shard_count: 0,
                     attached_shard_count: 0,
                     may_schedule: node.may_schedule(),
+                    az: node.get_availability_zone_id().to_string(),
                 },
             );
         }
@@ -302,6 +387,15 @@ pub(crate) fn update_node_ref_counts(&mut self, node_id: NodeId, update: RefCoun
                 // value until some future heartbeat after we have physically removed this shard
                 // from the node: this prevents the scheduler over-optimistically trying to schedule
                 // more work onto the node before earlier detaches are done.
+                //
+                // When planning, there are no detaches in flight: we are interested in where
+                // things end up, so the utilization follows our shard count down as well as up.
+                if self.planning {
+                    if let MaySchedule::Yes(utilization) = &mut node.may_schedule {
+                        utilization.shard_count = node.shard_count as u32;
+                        utilization.utilization_score = None;
+                    }
+                }
             }
         }
     }
@@ -366,6 +460,7 @@ pub(crate) fn node_upsert(&mut self, node: &Node) {
                     shard_count: 0,
                     attached_shard_count: 0,
                     may_schedule: node.may_schedule(),
+                    az: node.get_availability_zone_id().to_string(),
                 });
             }
         }
@@ -377,13 +472,146 @@ pub(crate) fn node_remove(&mut self, node_id: NodeId) {
         }
     }
 
+    /// Account for the load of an attachment moving between nodes, so that the scheduler sees its
+    /// effect immediately rather than after the next heartbeats.
+    pub(crate) fn migrate_shard_load(
+        &mut self,
+        tenant_shard_id: TenantShardId,
+        from_node: NodeId,
+        to_node: NodeId,
+    ) {
+        let Some(MaySchedule::Yes(from)) =
+            self.nodes.get_mut(&from_node).map(|n| &mut n.may_schedule)
+        else {
+            return;
+        };
+        let Some(position) = from
+            .shard_loads
+            .iter()
+            .position(|l| l.id == tenant_shard_id)
+        else {
+            // We don't know of any load for this shard: nothing to adjust
+            return;
+        };
+        let load = from.shard_loads.remove(position);
+        from.adjust_load(&load, false);
+
+        if let Some(MaySchedule::Yes(to)) =
+            self.nodes.get_mut(&to_node).map(|n| &mut n.may_schedule)
+        {
+            to.adjust_load(&load, true);
+            to.shard_loads.push(load);
+        }
+
+        self.last_load_migration = Some(Instant::now());
+    }
+
+    /// Look for a node whose load is far enough above others' that it is worth moving an attachment
+    /// away from it.  This only proposes candidates: the caller decides which shard to move, subject
+    /// to its other scheduling constraints.
+    pub(crate) fn plan_load_rebalance(&self) -> Option<LoadRebalance> {
+        if !self.planning
+            && self
+                .last_load_migration
+                .is_some_and(|t| t.elapsed() < LOAD_REBALANCE_INTERVAL)
+        {
+            return None;
+        }
+
+        let mut loads: Vec<(NodeId, &PageserverUtilization, RawScore)> = self
+            .nodes
+            .iter()
+            .filter_map(|(node_id, node)| match &node.may_schedule {
+                MaySchedule::Yes(utilization) => {
+                    Some((*node_id, utilization, utilization.load_score()))
+                }
+                MaySchedule::No => None,
+            })
+            .collect();
+        // Most loaded first, node ID for determinism
+        loads.sort_by_key(|(node_id, _, load_score)| (std::cmp::Reverse(*load_score), *node_id));
+
+        let (from_node, from_utilization, from_score) = *loads.first()?;
+        if from_score < LOAD_REBALANCE_MIN_SCORE {
+            return None;
+        }
+
+        let to_nodes: Vec<(NodeId, RawScore)> = loads
+            .iter()
+            .rev()
+            .filter(|(_, utilization, load_score)| {
+                *load_score + LOAD_REBALANCE_MIN_SPREAD <= from_score
+                    && !PageserverUtilization::is_overloaded(utilization.score())
+            })
+            .map(|(node_id, _, load_score)| (*node_id, *load_score))
+            .collect();
+        let (_, to_score) = *to_nodes.first()?;
+
+        // Moving a shard with load L leaves the source at from-L and the destination at to+L: only
+        // move shards small enough that the destination stays below the source.
+        let max_shard_score = (from_score - to_score) / 2;
+        let shards = from_utilization
+            .shard_loads
+            .iter()
+            .filter(|load| {
+                let shard_score = from_utilization.shard_load_score(load);
+                shard_score > 0 && shard_score <= max_shard_score
+            })
+            .map(|load| load.id)
+            .collect::<Vec<_>>();
+        if shards.is_empty() {
+            return None;
+        }
+
+        Some(LoadRebalance {
+            from_node,
+            to_nodes: to_nodes.into_iter().map(|(node_id, _)| node_id).collect(),
+            shards,
+        })
+    }
+
+    /// For `debug/v1/scheduler`
+    pub(crate) fn view(&self) -> SchedulerView<'_> {
+        SchedulerView {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(node_id, node)| {
+                    let utilization = match &node.may_schedule {
+                        MaySchedule::Yes(utilization) => Some(utilization),
+                        MaySchedule::No => None,
+                    };
+                    let utilization_score = utilization.map(|u| u.score());
+                    (
+                        *node_id,
+                        SchedulerNodeView {
+                            node,
+                            utilization_score,
+                            load_score: utilization.map(|u| u.load_score()),
+                            overloaded: utilization_score
+                                .is_some_and(PageserverUtilization::is_overloaded),
+                        },
+                    )
+                })
+                .collect(),
+            load_rebalance: self.plan_load_rebalance(),
+            last_load_migration_secs_ago: self.last_load_migration.map(|t| t.elapsed().as_secs()),
+        }
+    }
+
     /// Where we have several nodes to choose from, for example when picking a secondary location
     /// to promote to an attached location, this method may be used to pick the best choice based
     /// on the scheduler's knowledge of utilization and availability.
     ///
     /// If the input is empty, or all the nodes are not elegible for scheduling, return None: the
     /// caller can pick a node some other way.
-    pub(crate) fn node_preferred(&self, nodes: &[NodeId]) -> Option<NodeId> {
+    ///
+    /// Amongst eligible nodes, those in `preferred_az` are picked first.
+    pub(crate) fn node_preferred(
+        &self,
+        nodes: &[NodeId],
+        preferred_az: Option<&str>,
+    ) -> Option<NodeId> {
         if nodes.is_empty() {
             return None;
         }
@@ -393,17 +621,35 @@ pub(crate) fn node_preferred(&self, nodes: &[NodeId]) -> Option<NodeId> {
         let node = nodes
             .iter()
             .map(|node_id| {
-                let may_schedule = self
+                let (may_schedule, in_preferred_az) = self
                     .nodes
                     .get(node_id)
-                    .map(|n| !matches!(n.may_schedule, MaySchedule::No))
-                    .unwrap_or(false);
-                (*node_id, may_schedule)
+                    .map(|n| {
+                        (
+                            !matches!(n.may_schedule, MaySchedule::No),
+                            preferred_az == Some(n.az.as_str()),
+                        )
+                    })
+                    .unwrap_or((false, false));
+                (*node_id, may_schedule, in_preferred_az)
             })
-            .max_by_key(|(_n, may_schedule)| *may_schedule);
+            .max_by_key(|(_n, may_schedule, in_preferred_az)| (*may_schedule, *in_preferred_az));
 
         // If even the preferred node has may_schedule==false, return None
-        node.and_then(|(node_id, may_schedule)| if may_schedule { Some(node_id) } else { None })
+        node.and_then(
+            |(node_id, may_schedule, _)| {
+                if may_schedule {
+                    Some(node_id)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// The availability zone of a node, if it is known to the scheduler
+    pub(crate) fn node_az(&self, node_id: NodeId) -> Option<&str> {
+        self.nodes.get(&node_id).map(|n| n.az.as_str())
     }
 
     /// hard_exclude: it is forbidden to use nodes in this list, typically becacuse they
@@ -415,16 +661,28 @@ pub(crate) fn node_preferred(&self, nodes: &[NodeId]) -> Option<NodeId> {
     /// to their anti-affinity score.  We use this to prefeer to avoid placing shards in
     /// the same tenant on the same node.  This is a soft constraint: the context will never
     /// cause us to fail to schedule a shard.
+    ///
+    /// az: which availability zones we prefer, see [`AzAffinity`].  This is also a soft constraint,
+    /// and takes precedence over the context.
     pub(crate) fn schedule_shard(
         &mut self,
         hard_exclude: &[NodeId],
+        az: AzAffinity,
         context: &ScheduleContext,
     ) -> Result<NodeId, ScheduleError> {
         if self.nodes.is_empty() {
             return Err(ScheduleError::NoPageservers);
         }
 
-        let mut scores: Vec<(NodeId, AffinityScore, u64, usize)> = self
+        let avoid_azs = match az {
+            AzAffinity::Secondary => hard_exclude
+                .iter()
+                .filter_map(|n| self.nodes.get(n).map(|n| n.az.clone()))
+                .collect::<Vec<_>>(),
+            AzAffinity::Any | AzAffinity::Attached(_) => Vec::new(),
+        };
+
+        let mut scores: Vec<(NodeId, bool, AffinityScore, u64, usize)> = self
             .nodes
             .iter_mut()
             .filter_map(|(k, v)| match &mut v.may_schedule {
@@ -432,6 +690,11 @@ pub(crate) fn schedule_shard(
                 MaySchedule::Yes(_) if hard_exclude.contains(k) => None,
                 MaySchedule::Yes(utilization) => Some((
                     *k,
+                    match az {
+                        AzAffinity::Any | AzAffinity::Attached(None) => false,
+                        AzAffinity::Attached(Some(preferred)) => v.az != preferred,
+                        AzAffinity::Secondary => avoid_azs.contains(&v.az),
+                    },
                     context.nodes.get(k).copied().unwrap_or(AffinityScore::FREE),
                     utilization.cached_score(),
                     v.attached_shard_count,
@@ -445,7 +708,7 @@ pub(crate) fn schedule_shard(
         // overloaded.
         let non_overloaded_scores = scores
             .iter()
-            .filter(|i| !PageserverUtilization::is_overloaded(i.2))
+            .filter(|i| !PageserverUtilization::is_overloaded(i.3))
             .copied()
             .collect::<Vec<_>>();
         if !non_overloaded_scores.is_empty() {
@@ -453,12 +716,13 @@ pub(crate) fn schedule_shard(
         }
 
         // Sort by, in order of precedence:
-        //  1st: Affinity score.  We should never pick a higher-score node if a lower-score node is available
-        //  2nd: Utilization score (this combines shard count and disk utilization)
-        //  3rd: Attached shard count.  When nodes have identical utilization (e.g. when populating some
+        //  1st: AZ mismatch.  We should never pick a node outside the AZs we want if one inside them is available
+        //  2nd: Affinity score.  We should never pick a higher-score node if a lower-score node is available
+        //  3rd: Utilization score (this combines shard count and disk utilization)
+        //  4th: Attached shard count.  When nodes have identical utilization (e.g. when populating some
         //       empty nodes), this acts as an anti-affinity between attached shards.
-        //  4th: Node ID.  This is a convenience to make selection deterministic in tests and empty systems.
-        scores.sort_by_key(|i| (i.1, i.2, i.3, i.0));
+        //  5th: Node ID.  This is a convenience to make selection deterministic in tests and empty systems.
+        scores.sort_by_key(|i| (i.1, i.2, i.3, i.4, i.0));
 
         if scores.is_empty() {
             // After applying constraints, no pageservers were left.
@@ -467,7 +731,7 @@ pub(crate) fn schedule_shard(
                 // schedule: this may help an engineer understand if some nodes are marked offline
                 // in a way that's preventing progress.
                 tracing::info!(
-                    "Scheduling failure, while excluding {hard_exclude:?}, node states:"
+                    "Scheduling failure, while excluding {hard_exclude:?} ({az:?}), node states:"
                 );
                 for (node_id, node) in &self.nodes {
                     tracing::info!(
@@ -485,7 +749,7 @@ pub(crate) fn schedule_shard(
 
         if !matches!(context.mode, ScheduleMode::Speculative) {
             tracing::info!(
-            "scheduler selected node {node_id} (elegible nodes {:?}, hard exclude: {hard_exclude:?}, soft exclude: {context:?})",
+            "scheduler selected node {node_id} (elegible nodes {:?}, hard exclude: {hard_exclude:?}, az: {az:?}, soft exclude: {context:?})",
             scores.iter().map(|i| i.0 .0).collect::<Vec<_>>()
         );
         }
@@ -496,6 +760,33 @@ pub(crate) fn schedule_shard(
         Ok(node_id)
     }
 
+    /// A copy of this scheduler for working out the consequences of hypothetical changes, without
+    /// affecting the real one.  The copy models the state that the cluster would settle into,
+    /// rather than the transition to it: utilization is not held at stale values while shards are
+    /// detached, and load is rebalanced without waiting for nodes to report the effect of the
+    /// previous migration.
+    ///
+    /// Shards' [`crate::tenant_shard::IntentState`]s referencing the copy must be cloned along
+    /// with it, and cleared against the copy once planning is done.
+    pub(crate) fn clone_for_planning(&self) -> Self {
+        Self {
+            planning: true,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn node_stats(&self, node_id: NodeId) -> Option<SchedulerNodeStats> {
+        let node = self.nodes.get(&node_id)?;
+        Some(SchedulerNodeStats {
+            shard_count: node.shard_count,
+            attached_shard_count: node.attached_shard_count,
+            utilization_score: match &node.may_schedule {
+                MaySchedule::Yes(utilization) => Some(utilization.score()),
+                MaySchedule::No => None,
+            },
+        })
+    }
+
     /// Unit test access to internal state
     #[cfg(test)]
     pub(crate) fn get_node_shard_count(&self, node_id: NodeId) -> usize {
@@ -519,6 +810,12 @@ pub(crate) mod test_utils {
     ///
     /// Node IDs start at one.
     pub(crate) fn make_test_nodes(n: u64) -> HashMap<NodeId, Node> {
+        make_test_nodes_in_azs(n, &["test-az"])
+    }
+
+    /// Test helper: like [`make_test_nodes`], but spreading the nodes round-robin across the
+    /// given availability zones.
+    pub(crate) fn make_test_nodes_in_azs(n: u64, azs: &[&str]) -> HashMap<NodeId, Node> {
         (1..n + 1)
             .map(|i| {
                 (NodeId(i), {
@@ -528,7 +825,7 @@ pub(crate) fn make_test_nodes(n: u64) -> HashMap<NodeId, Node> {
                         80 + i as u16,
                         format!("pghost-{i}"),
                         5432 + i as u16,
-                        "test-az".to_string(),
+                        azs[(i - 1) as usize % azs.len()].to_string(),
                     );
                     node.set_availability(NodeAvailability::Active(test_utilization::simple(0, 0)));
                     assert!(node.is_available());
@@ -556,9 +853,9 @@ fn scheduler_basic() -> anyhow::Result<()> {
 
         let context = ScheduleContext::default();
 
-        let scheduled = scheduler.schedule_shard(&[], &context)?;
+        let scheduled = scheduler.schedule_shard(&[], AzAffinity::Any, &context)?;
         t1_intent.set_attached(&mut scheduler, Some(scheduled));
-        let scheduled = scheduler.schedule_shard(&[], &context)?;
+        let scheduled = scheduler.schedule_shard(&[], AzAffinity::Any, &context)?;
         t2_intent.set_attached(&mut scheduler, Some(scheduled));
 
         assert_eq!(scheduler.get_node_shard_count(NodeId(1)), 1);
@@ -567,7 +864,8 @@ fn scheduler_basic() -> anyhow::Result<()> {
         assert_eq!(scheduler.get_node_shard_count(NodeId(2)), 1);
         assert_eq!(scheduler.get_node_attached_shard_count(NodeId(2)), 1);
 
-        let scheduled = scheduler.schedule_shard(&t1_intent.all_pageservers(), &context)?;
+        let scheduled =
+            scheduler.schedule_shard(&t1_intent.all_pageservers(), AzAffinity::Any, &context)?;
         t1_intent.push_secondary(&mut scheduler, scheduled);
 
         assert_eq!(scheduler.get_node_shard_count(NodeId(1)), 1);
@@ -621,7 +919,9 @@ fn assert_scheduler_chooses(
             scheduler: &mut Scheduler,
             context: &ScheduleContext,
         ) {
-            let scheduled = scheduler.schedule_shard(&[], context).unwrap();
+            let scheduled = scheduler
+                .schedule_shard(&[], AzAffinity::Any, context)
+                .unwrap();
             let mut intent = IntentState::new();
             intent.set_attached(scheduler, Some(scheduled));
             scheduled_intents.push(intent);
@@ -729,4 +1029,134 @@ fn assert_scheduler_chooses(
             intent.clear(&mut scheduler);
         }
     }
+
+    #[test]
+    /// Test that we propose moving load off busy nodes, but only when the imbalance is significant
+    fn scheduler_load_rebalance() {
+        use pageserver_api::models::utilization::ShardLoad;
+        use utils::id::TenantId;
+
+        let mut nodes = test_utils::make_test_nodes(3);
+        let mut scheduler = Scheduler::new(nodes.values());
+
+        let busy_shard = TenantShardId::unsharded(TenantId::generate());
+        let huge_shard = TenantShardId::unsharded(TenantId::generate());
+
+        fn set_load(
+            nodes: &mut HashMap<NodeId, Node>,
+            scheduler: &mut Scheduler,
+            node_id: NodeId,
+            loads: Vec<(TenantShardId, u64)>,
+        ) {
+            let mut utilization = test_utilization::simple(0, 0);
+            for (id, getpage_requests_per_second) in loads {
+                utilization.getpage_requests_per_second += getpage_requests_per_second;
+                utilization.shard_loads.push(ShardLoad {
+                    id,
+                    ingest_bytes_per_second: 0,
+                    getpage_requests_per_second,
+                    resident_size: 0,
+                });
+            }
+            let node = nodes.get_mut(&node_id).unwrap();
+            node.set_availability(NodeAvailability::Active(utilization));
+            scheduler.node_upsert(node);
+        }
+
+        // Idle system: nothing to do
+        assert!(scheduler.plan_load_rebalance().is_none());
+
+        // Busy but balanced nodes: nothing to do
+        let max_getpage = test_utilization::simple(0, 0).max_getpage_requests_per_second;
+        for node_id in [NodeId(1), NodeId(2), NodeId(3)] {
+            set_load(
+                &mut nodes,
+                &mut scheduler,
+                node_id,
+                vec![(busy_shard, max_getpage * 6 / 10)],
+            );
+        }
+        assert!(scheduler.plan_load_rebalance().is_none());
+
+        // One busy node: we should propose moving the shard which fits, but not the one that would
+        // just make its destination the busiest node.
+        set_load(
+            &mut nodes,
+            &mut scheduler,
+            NodeId(1),
+            vec![
+                (huge_shard, max_getpage * 6 / 10),
+                (busy_shard, max_getpage / 10),
+            ],
+        );
+        set_load(&mut nodes, &mut scheduler, NodeId(2), vec![]);
+        set_load(
+            &mut nodes,
+            &mut scheduler,
+            NodeId(3),
+            vec![(busy_shard, max_getpage / 10)],
+        );
+        let rebalance = scheduler.plan_load_rebalance().unwrap();
+        assert_eq!(rebalance.from_node, NodeId(1));
+        assert_eq!(rebalance.to_nodes, vec![NodeId(2), NodeId(3)]);
+        assert_eq!(rebalance.shards, vec![busy_shard]);
+
+        // Once we have moved something, we hold off on moving anything else until the effect is visible
+        scheduler.migrate_shard_load(busy_shard, NodeId(1), NodeId(2));
+        assert!(scheduler.plan_load_rebalance().is_none());
+        let view = serde_json::to_value(scheduler.view()).unwrap();
+        assert_eq!(
+            view["nodes"]["2"]["load_score"],
+            PageserverUtilization::UTILIZATION_FULL / 10
+        );
+        assert_eq!(
+            view["nodes"]["1"]["load_score"],
+            PageserverUtilization::UTILIZATION_FULL * 6 / 10
+        );
+    }
+
+    #[test]
+    /// Attached locations should land in the preferred AZ, and secondaries outside the AZs of
+    /// the shard's other locations.
+    fn scheduler_az_affinity() {
+        // Nodes 1 and 3 are in az-a, nodes 2 and 4 in az-b
+        let nodes = test_utils::make_test_nodes_in_azs(4, &["az-a", "az-b"]);
+        let mut scheduler = Scheduler::new(nodes.values());
+        let context = ScheduleContext::default();
+
+        assert_eq!(scheduler.node_az(NodeId(3)), Some("az-a"));
+
+        let mut intent = IntentState::new();
+        let attached = scheduler
+            .schedule_shard(&[], AzAffinity::Attached(Some("az-b")), &context)
+            .unwrap();
+        assert_eq!(attached, NodeId(2));
+        intent.set_attached(&mut scheduler, Some(attached));
+
+        // Node 4 is emptier than node 1 after the attachment, but shares an AZ with it
+        let secondary = scheduler
+            .schedule_shard(&intent.all_pageservers(), AzAffinity::Secondary, &context)
+            .unwrap();
+        assert_eq!(scheduler.node_az(secondary), Some("az-a"));
+        intent.push_secondary(&mut scheduler, secondary);
+
+        // With every AZ in use, we fall back to whichever node is left
+        let secondary = scheduler
+            .schedule_shard(&intent.all_pageservers(), AzAffinity::Secondary, &context)
+            .unwrap();
+        assert!(!intent.all_pageservers().contains(&secondary));
+        intent.push_secondary(&mut scheduler, secondary);
+
+        // Promoting a secondary prefers one in the preferred AZ, when it is schedulable
+        assert_eq!(
+            scheduler.node_preferred(&[NodeId(1), NodeId(4)], Some("az-b")),
+            Some(NodeId(4))
+        );
+        assert_eq!(
+            scheduler.node_preferred(&[NodeId(4), NodeId(1)], Some("az-a")),
+            Some(NodeId(1))
+        );
+
+        intent.clear(&mut scheduler);
+    }
 }
@@ -1,5 +1,16 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    compute_notifications (tenant_id) {
+        tenant_id -> Varchar,
+        request -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Varchar>,
+        first_failed_at -> Timestamptz,
+        next_attempt_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     controllers (address, started_at) {
         address -> Varchar,
@@ -7,6 +18,20 @@
     }
 }
 
+diesel::table! {
+    hibernated_tenants (tenant_id) {
+        tenant_id -> Varchar,
+        placement_policy -> Varchar,
+        hibernated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    leader_fence (term) {
+        term -> Int8,
+    }
+}
+
 diesel::table! {
     metadata_health (tenant_id, shard_number, shard_count) {
         tenant_id -> Varchar,
@@ -29,6 +54,43 @@
     }
 }
 
+diesel::table! {
+    operation_events (id) {
+        id -> Int8,
+        timestamp -> Timestamptz,
+        kind -> Varchar,
+        actor -> Varchar,
+        tenant_id -> Nullable<Varchar>,
+        tenant_shard_id -> Nullable<Varchar>,
+        node_id -> Nullable<Int8>,
+        detail -> Text,
+    }
+}
+
+diesel::table! {
+    rolling_operations (id) {
+        id -> Varchar,
+        request -> Text,
+        state -> Varchar,
+        nodes -> Text,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    tenant_imports (tenant_id) {
+        tenant_id -> Varchar,
+        request -> Text,
+        state -> Varchar,
+        shards -> Text,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     tenant_shards (tenant_id, shard_number, shard_count) {
         tenant_id -> Varchar,
@@ -45,7 +107,18 @@
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(controllers, metadata_health, nodes, tenant_shards,);
+diesel::allow_tables_to_appear_in_same_query!(
+    compute_notifications,
+    controllers,
+    hibernated_tenants,
+    leader_fence,
+    metadata_health,
+    nodes,
+    operation_events,
+    rolling_operations,
+    tenant_imports,
+    tenant_shards,
+);
 
 diesel::table! {
     safekeepers {
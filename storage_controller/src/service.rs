@@ -17,15 +17,18 @@
     compute_hook::NotifyError,
     drain_utils::{self, TenantShardDrain, TenantShardIterator},
     id_lock_map::{trace_exclusive_lock, trace_shared_lock, IdLockMap, TracingExclusiveGuard},
-    leadership::Leadership,
+    leadership::{
+        lease::{LeaderLease, LeaseVoter},
+        Leadership,
+    },
     metrics,
     peer_client::GlobalObservedState,
     persistence::{
         AbortShardSplitStatus, ControllerPersistence, DatabaseResult, MetadataHealthPersistence,
-        ShardGenerationState, TenantFilter,
+        OperationEventFilter, ShardGenerationState, TenantFilter,
     },
     reconciler::{ReconcileError, ReconcileUnits, ReconcilerConfig, ReconcilerConfigBuilder},
-    scheduler::{MaySchedule, ScheduleContext, ScheduleError, ScheduleMode},
+    scheduler::{AzAffinity, MaySchedule, ScheduleContext, ScheduleError, ScheduleMode},
     tenant_shard::{
         MigrateAttachment, ReconcileNeeded, ReconcilerStatus, ScheduleOptimization,
         ScheduleOptimizationAction,
@@ -41,7 +44,8 @@
 use pageserver_api::{
     controller_api::{
         MetadataHealthRecord, MetadataHealthUpdateRequest, NodeAvailability, NodeRegisterRequest,
-        NodeSchedulingPolicy, NodeShard, NodeShardResponse, PlacementPolicy, ShardSchedulingPolicy,
+        NodeSchedulingPolicy, NodeShard, NodeShardResponse, OperationEvent, OperationEventKind,
+        PendingComputeNotification, PlacementPolicy, ShardSchedulingPolicy,
         ShardsPreferredAzsRequest, ShardsPreferredAzsResponse, TenantCreateRequest,
         TenantCreateResponse, TenantCreateResponseShard, TenantDescribeResponse,
         TenantDescribeResponseShard, TenantLocateResponse, TenantPolicyRequest,
@@ -60,8 +64,9 @@
     models::{
         self, LocationConfig, LocationConfigListResponse, LocationConfigMode,
         PageserverUtilization, ShardParameters, TenantConfig, TenantLocationConfigRequest,
-        TenantLocationConfigResponse, TenantShardLocation, TenantShardSplitRequest,
-        TenantShardSplitResponse, TenantTimeTravelRequest, TimelineCreateRequest, TimelineInfo,
+        TenantLocationConfigResponse, TenantShardLocation, TenantShardMergeRequest,
+        TenantShardMergeResponse, TenantShardSplitRequest, TenantShardSplitResponse,
+        TenantTimeTravelRequest, TimelineCreateRequest, TimelineInfo,
     },
     shard::{ShardCount, ShardIdentity, ShardNumber, ShardStripeSize, TenantShardId},
     upcall_api::{
@@ -84,6 +89,7 @@
 use crate::{
     compute_hook::ComputeHook,
     heartbeater::{Heartbeater, PageserverState},
+    history::{HistoryEvent, OperationHistory},
     node::{AvailabilityTransition, Node},
     persistence::{split_state::SplitState, DatabaseError, Persistence, TenantShardPersistence},
     reconciler::attached_location_conf,
@@ -95,6 +101,10 @@
 };
 
 pub mod chaos_injector;
+mod hibernation;
+pub(crate) mod import;
+mod plan;
+mod rolling_operation;
 
 // For operations that should be quick, like attaching a new tenant
 const SHORT_RECONCILE_TIMEOUT: Duration = Duration::from_secs(5);
@@ -116,6 +126,9 @@
 /// This must be long enough to cover node restarts as well as normal operations: in future
 pub const MAX_OFFLINE_INTERVAL_DEFAULT: Duration = Duration::from_secs(30);
 
+/// How long operation history events are kept for by default
+pub const HISTORY_RETENTION_DEFAULT: Duration = Duration::from_secs(30 * 24 * 3600);
+
 /// How long a node may be unresponsive to heartbeats during start up before we declare it
 /// offline.
 ///
@@ -136,6 +149,7 @@ enum TenantOperations {
     Delete,
     UpdatePolicy,
     ShardSplit,
+    ShardMerge,
     SecondaryDownload,
     TimelineCreate,
     TimelineDelete,
@@ -143,6 +157,9 @@ enum TenantOperations {
     TimelineArchivalConfig,
     TimelineDetachAncestor,
     TimelineGcBlockUnblock,
+    Hibernate,
+    Wake,
+    Import,
 }
 
 #[derive(Clone, strum_macros::Display)]
@@ -180,6 +197,8 @@ pub(crate) enum LeadershipStatus {
 
 pub const RECONCILER_CONCURRENCY_DEFAULT: usize = 128;
 
+pub const AZ_FAILOVER_PER_PASS_DEFAULT: usize = 256;
+
 // Depth of the channel used to enqueue shards for reconciliation when they can't do it immediately.
 // This channel is finite-size to avoid using excessive memory if we get into a state where reconciles are finishing more slowly
 // than they're being pushed onto the queue.
@@ -200,6 +219,13 @@ struct ServiceState {
     /// hence the type choice.
     ongoing_operation: Option<OperationHandler>,
 
+    /// Rolling operation being driven by this controller, if any.  Its drains and fills show up
+    /// in [`Self::ongoing_operation`] while they run.
+    rolling_operation: Option<Arc<rolling_operation::RollingOperationHandle>>,
+
+    /// Tenant imports being driven by this controller, with the tokens that interrupt them.
+    tenant_imports: HashMap<TenantId, CancellationToken>,
+
     /// Queue of tenants who are waiting for concurrency limits to permit them to reconcile
     delayed_reconcile_rx: tokio::sync::mpsc::Receiver<TenantShardId>,
 }
@@ -260,6 +286,8 @@ fn new(
             nodes: Arc::new(nodes),
             scheduler,
             ongoing_operation: None,
+            rolling_operation: None,
+            tenant_imports: HashMap::new(),
             delayed_reconcile_rx,
         }
     }
@@ -307,6 +335,15 @@ pub struct Config {
     /// assume it is running in a test environment and try to update neon_local.
     pub compute_hook_url: Option<String>,
 
+    /// Key for signing notifications sent to [`Self::compute_hook_url`] with HMAC-SHA256
+    pub compute_hook_hmac_key: Option<String>,
+
+    /// Alternatives to [`Self::compute_hook_url`]: append notifications to a file, write them to
+    /// a unix socket, or run a command for each of them.  See [`crate::compute_hook`].
+    pub compute_hook_file: Option<PathBuf>,
+    pub compute_hook_socket: Option<PathBuf>,
+    pub compute_hook_command: Option<String>,
+
     /// Grace period within which a pageserver does not respond to heartbeats, but is still
     /// considered active. Once the grace period elapses, the next heartbeat failure will
     /// mark the pagseserver offline.
@@ -320,6 +357,11 @@ pub struct Config {
     /// How many Reconcilers may be spawned concurrently
     pub reconciler_concurrency: usize,
 
+    /// When all the nodes in an AZ go offline, how many shards are rescheduled to other AZs at
+    /// once: the rest are rescheduled by later background reconciliation passes, so that losing
+    /// an AZ doesn't start migrating every tenant in it at the same time.
+    pub az_failover_per_pass: usize,
+
     /// How large must a shard grow in bytes before we split it?
     /// None disables auto-splitting.
     pub split_threshold: Option<u64>,
@@ -335,11 +377,38 @@ pub struct Config {
 
     pub heartbeat_interval: Duration,
 
+    /// How long to keep operation history events for
+    pub history_retention: Duration,
+
+    /// Tenants with no compute activity for this long are hibernated: see [`Service::tenant_wake`].
+    /// None disables hibernation.
+    pub hibernate_after: Option<Duration>,
+
+    /// Hibernated tenants are detached after this long.  None leaves them in a secondary location.
+    pub hibernate_detach_after: Option<Duration>,
+
     pub address_for_peers: Option<Uri>,
 
     pub start_as_candidate: bool,
 
     pub http_service_port: i32,
+
+    /// If set, elect a leader by acquiring a lease from a majority of these peers, rather than by
+    /// stepping down whichever controller the database says is the leader.
+    pub leader_lease: Option<LeaderLeaseConfig>,
+
+    /// Where to keep archives uploaded for tenant imports.  None disables uploads: imports must
+    /// then read their archives from a path or URL.
+    pub import_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LeaderLeaseConfig {
+    /// Every controller that takes part in the election, including ourselves
+    pub peers: Vec<Uri>,
+
+    /// How long a lease lasts without being renewed
+    pub ttl: Duration,
 }
 
 impl From<DatabaseError> for ApiError {
@@ -353,6 +422,7 @@ fn from(err: DatabaseError) -> ApiError {
             DatabaseError::Logical(reason) | DatabaseError::Migration(reason) => {
                 ApiError::InternalServerError(anyhow::anyhow!(reason))
             }
+            DatabaseError::Fenced(reason) => ApiError::ResourceUnavailable(reason.into()),
         }
     }
 }
@@ -372,8 +442,14 @@ pub struct Service {
 
     heartbeater: Heartbeater,
 
+    /// Persistent record of what we did and why, see [`crate::history`]
+    history: OperationHistory,
+
+    /// When we last saw compute activity for each tenant, used to decide when to hibernate them
+    tenant_activity: std::sync::Mutex<hibernation::TenantActivity>,
+
     // Channel for background cleanup from failed operations that require cleanup, such as shard split
-    abort_tx: tokio::sync::mpsc::UnboundedSender<TenantShardSplitAbort>,
+    abort_tx: tokio::sync::mpsc::UnboundedSender<TenantShardAbort>,
 
     // Locking on a tenant granularity (covers all shards in the tenant):
     // - Take exclusively for rare operations that mutate the tenant's persistent state (e.g. create/delete/split)
@@ -410,6 +486,12 @@ pub struct Service {
     /// This waits for initial reconciliation with pageservers to complete.  Until this barrier
     /// passes, it isn't safe to do any actions that mutate tenants.
     pub(crate) startup_complete: Barrier,
+
+    /// The lease we hold, if leaders are elected by lease rather than through the database
+    leader_lease: Option<Arc<LeaderLease>>,
+
+    /// Our vote in other controllers' lease elections
+    lease_voter: Option<Arc<LeaseVoter>>,
 }
 
 impl From<ReconcileWaitError> for ApiError {
@@ -474,6 +556,56 @@ struct TenantShardSplitAbort {
     _tenant_lock: TracingExclusiveGuard<TenantOperations>,
 }
 
+struct ShardMergeParams {
+    old_shard_count: ShardCount,
+    new_shard_count: ShardCount,
+    targets: Vec<ShardMergeTarget>,
+    policy: PlacementPolicy,
+    config: TenantConfig,
+    shard_ident: ShardIdentity,
+}
+
+// When preparing for a shard merge, we may either choose to proceed with the merge,
+// or find that the work is already done and return NoOp.
+enum ShardMergeAction {
+    Merge(Box<ShardMergeParams>),
+    NoOp(TenantShardMergeResponse),
+}
+
+// A merged shard which will be created from its sources.  The merge is done on the node
+// where the lowest-numbered source is attached, and the other sources are migrated there first.
+struct ShardMergeTarget {
+    merged_id: TenantShardId,
+    node: Node,
+    source_ids: Vec<TenantShardId>,
+}
+
+/// Counterpart of [`TenantShardSplitAbort`] for a failed shard merge.
+struct TenantShardMergeAbort {
+    tenant_id: TenantId,
+    /// The target value from the request that failed
+    new_shard_count: ShardCount,
+    /// Merged shards that a pageserver may have written to remote storage, with the node that did it.
+    /// Only these are deleted: the prefix of any other merged shard may hold layers that the sources read.
+    merges_started: Vec<(TenantShardId, Node)>,
+    /// Until this abort op is complete, no other operations may be done on the tenant
+    _tenant_lock: TracingExclusiveGuard<TenantOperations>,
+}
+
+enum TenantShardAbort {
+    Split(TenantShardSplitAbort),
+    Merge(TenantShardMergeAbort),
+}
+
+impl TenantShardAbort {
+    fn tenant_id(&self) -> TenantId {
+        match self {
+            Self::Split(op) => op.tenant_id,
+            Self::Merge(op) => op.tenant_id,
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum TenantShardSplitAbortError {
     #[error(transparent)]
@@ -892,6 +1024,149 @@ async fn cleanup_locations(&self, cleanup: Vec<(TenantShardId, NodeId)>) {
         }
     }
 
+    /// Periodically retry compute notifications that failed to deliver, including those that
+    /// failed before a restart or under a previous leader.
+    async fn retry_compute_notifications(&self) {
+        const RETRY_PERIOD: Duration = Duration::from_secs(5);
+
+        let mut interval = tokio::time::interval(RETRY_PERIOD);
+        while !self.cancel.is_cancelled() {
+            tokio::select! {
+              _ = interval.tick() => { }
+              _ = self.cancel.cancelled() => return
+            };
+
+            if self.get_leadership_status() != LeadershipStatus::Leader {
+                continue;
+            }
+
+            let result = self
+                .compute_hook
+                .retry_pending(
+                    |tenant_id| {
+                        let locked = self.inner.read().unwrap();
+                        locked
+                            .tenants
+                            .range(TenantShardId::tenant_range(*tenant_id))
+                            .next()
+                            .is_some()
+                    },
+                    &self.cancel,
+                )
+                .await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to retry compute notifications: {e}");
+            }
+        }
+    }
+
+    /// Move a shard's attachment away from an offline node, if it is attached there.  Returns
+    /// `None` if it wasn't rescheduled, or whether it was rescheduled onto a node that has a
+    /// secondary location, which is quick to promote.
+    fn reschedule_from_offline_node(
+        &self,
+        tenant_shard: &mut TenantShard,
+        scheduler: &mut Scheduler,
+        node_id: NodeId,
+    ) -> Option<bool> {
+        let secondaries = tenant_shard.intent.get_secondary().clone();
+        if !tenant_shard.intent.demote_attached(scheduler, node_id) {
+            return None;
+        }
+        tenant_shard.sequence = tenant_shard.sequence.next();
+
+        // TODO: populate a ScheduleContext including all shards in the same tenant_id (only matters
+        // for tenants without secondary locations: if they have a secondary location, then this
+        // schedule() call is just promoting an existing secondary)
+        let mut schedule_context = ScheduleContext::default();
+
+        let tenant_shard_id = tenant_shard.tenant_shard_id;
+        match tenant_shard.schedule(scheduler, &mut schedule_context) {
+            Err(e) => {
+                // It is possible that some tenants will become unschedulable when too many pageservers
+                // go offline: in this case there isn't much we can do other than make the issue observable.
+                // TODO: give TenantShard a scheduling error attribute to be queried later.
+                tracing::warn!(%tenant_shard_id, "Scheduling error when marking pageserver {} offline: {e}", node_id);
+                None
+            }
+            Ok(()) => {
+                self.history.record(
+                    HistoryEvent::new(
+                        OperationEventKind::Schedule,
+                        format!(
+                            "Rescheduled away from offline node {node_id}: attached to {:?}",
+                            tenant_shard.intent.get_attached()
+                        ),
+                    )
+                    .shard(tenant_shard_id),
+                );
+                Some(
+                    tenant_shard
+                        .intent
+                        .get_attached()
+                        .map_or(false, |n| secondaries.contains(&n)),
+                )
+            }
+        }
+    }
+
+    /// Carry on failing over shards that are still attached in an AZ whose nodes are all
+    /// offline, [`Config::az_failover_per_pass`] at a time.  Returns how many were rescheduled.
+    fn az_failover_pass(&self) -> usize {
+        let mut locked = self.inner.write().unwrap();
+        let (nodes, tenants, scheduler) = locked.parts_mut();
+        if !nodes
+            .values()
+            .any(|n| matches!(n.may_schedule(), MaySchedule::Yes(_)))
+        {
+            return 0;
+        }
+
+        let mut promoted = Vec::new();
+        let mut cold_attaches = Vec::new();
+        for (tenant_shard_id, tenant_shard) in tenants.iter_mut() {
+            if promoted.len() + cold_attaches.len() >= self.config.az_failover_per_pass {
+                break;
+            }
+
+            let Some(node_id) = *tenant_shard.intent.get_attached() else {
+                continue;
+            };
+            let Some(node) = nodes.get(&node_id) else {
+                continue;
+            };
+            if node.is_available() || !az_unavailable(nodes, node.get_availability_zone_id()) {
+                continue;
+            }
+
+            match self.reschedule_from_offline_node(tenant_shard, scheduler, node_id) {
+                Some(true) => promoted.push(*tenant_shard_id),
+                Some(false) => cold_attaches.push(*tenant_shard_id),
+                None => {}
+            }
+        }
+
+        // As when the AZ went offline, promote warm secondaries before attaching from scratch
+        let rescheduled = promoted.len() + cold_attaches.len();
+        for tenant_shard_id in promoted.into_iter().chain(cold_attaches) {
+            if let Some(tenant_shard) = tenants.get_mut(&tenant_shard_id) {
+                self.maybe_reconcile_shard(tenant_shard, nodes);
+            }
+        }
+
+        if rescheduled > 0 {
+            tracing::info!("Failed over {rescheduled} more shards out of offline AZs");
+        }
+        rescheduled
+    }
+
+    /// Compute notifications that failed to deliver and are waiting to be retried
+    pub(crate) async fn pending_compute_notifications(
+        &self,
+    ) -> Result<Vec<PendingComputeNotification>, ApiError> {
+        Ok(self.compute_hook.list_pending().await?)
+    }
+
     /// Long running background task that periodically wakes up and looks for shards that need
     /// reconciliation.  Reconciliation is fallible, so any reconciliation tasks that fail during
     /// e.g. a tenant create/attach/migrate must eventually be retried: this task is responsible
@@ -906,6 +1181,7 @@ async fn background_reconcile(self: &Arc<Self>) {
         while !self.reconcilers_cancel.is_cancelled() {
             tokio::select! {
               _ = interval.tick() => {
+                self.az_failover_pass();
                 let reconciles_spawned = self.reconcile_all();
                 if reconciles_spawned == 0 {
                     // Run optimizer only when we didn't find any other work to do
@@ -941,6 +1217,10 @@ async fn spawn_heartbeat_driver(&self) {
                 for (node_id, state) in deltas.0 {
                     let new_availability = match state {
                         PageserverState::Available { utilization, .. } => {
+                            self.record_node_activity(
+                                node_id,
+                                utilization.active_shards.as_deref(),
+                            );
                             NodeAvailability::Active(utilization)
                         }
                         PageserverState::WarmingUp { started_at } => {
@@ -1044,6 +1324,18 @@ fn process_result(&self, mut result: ReconcileResult) {
 
                 tenant.observed = result.observed;
                 tenant.waiter.advance(result.sequence);
+
+                self.history.record(
+                    HistoryEvent::new(
+                        OperationEventKind::Reconcile,
+                        format!(
+                            "Reconciled (sequence {}, attached to {:?})",
+                            result.sequence,
+                            tenant.intent.get_attached()
+                        ),
+                    )
+                    .shard(result.tenant_shard_id),
+                );
             }
             Err(e) => {
                 match e {
@@ -1057,6 +1349,13 @@ fn process_result(&self, mut result: ReconcileResult) {
                     }
                     _ => {
                         tracing::warn!("Reconcile error: {}", e);
+                        self.history.record(
+                            HistoryEvent::new(
+                                OperationEventKind::Reconcile,
+                                format!("Reconcile failed (sequence {}): {e}", result.sequence),
+                            )
+                            .shard(result.tenant_shard_id),
+                        );
                     }
                 }
 
@@ -1129,7 +1428,7 @@ async fn process_results(
 
     async fn process_aborts(
         &self,
-        mut abort_rx: tokio::sync::mpsc::UnboundedReceiver<TenantShardSplitAbort>,
+        mut abort_rx: tokio::sync::mpsc::UnboundedReceiver<TenantShardAbort>,
     ) {
         loop {
             // Wait for the next result, or for cancellation
@@ -1149,12 +1448,16 @@ async fn process_aborts(
             // processed, as it holds a lock guard that prevents other operations trying to do things
             // to the tenant while it is in a weird part-split state.
             while !self.cancel.is_cancelled() {
-                match self.abort_tenant_shard_split(&op).await {
+                let result = match &op {
+                    TenantShardAbort::Split(op) => self.abort_tenant_shard_split(op).await,
+                    TenantShardAbort::Merge(op) => self.abort_tenant_shard_merge(op).await,
+                };
+                match result {
                     Ok(_) => break,
                     Err(e) => {
                         tracing::warn!(
-                            "Failed to abort shard split on {}, will retry: {e}",
-                            op.tenant_id
+                            "Failed to abort shard split or merge on {}, will retry: {e}",
+                            op.tenant_id()
                         );
 
                         // If a node is unavailable, we hope that it has been properly marked Offline
@@ -1170,13 +1473,26 @@ async fn process_aborts(
         }
     }
 
-    pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Result<Arc<Self>> {
+    pub async fn spawn(
+        config: Config,
+        persistence: Arc<Persistence>,
+        lease_voter: Option<Arc<LeaseVoter>>,
+    ) -> anyhow::Result<Arc<Self>> {
         let (result_tx, result_rx) = tokio::sync::mpsc::unbounded_channel();
         let (abort_tx, abort_rx) = tokio::sync::mpsc::unbounded_channel();
 
         let leadership_cancel = CancellationToken::new();
         let leadership = Leadership::new(persistence.clone(), config.clone(), leadership_cancel);
-        let (leader, leader_step_down_state) = leadership.step_down_current_leader().await?;
+        let (leader_lease, leader, leader_step_down_state) = match &config.leader_lease {
+            Some(lease_config) => {
+                let (lease, leader, observed) = leadership.acquire_lease(lease_config).await?;
+                (Some(lease), leader, observed)
+            }
+            None => {
+                let (leader, observed) = leadership.step_down_current_leader().await?;
+                (None, leader, observed)
+            }
+        };
 
         // Apply the migrations **after** the current leader has stepped down
         // (or we've given up waiting for it), but **before** reading from the
@@ -1184,6 +1500,12 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
         // migrating.
         persistence.migration_run().await?;
 
+        // A previous leader may not know yet that its lease expired: fence it off from the
+        // database before we read anything, so that it cannot overwrite what we do.
+        if let Some(lease) = &leader_lease {
+            persistence.fence(lease.term() as i64).await?;
+        }
+
         tracing::info!("Loading nodes from database...");
         let nodes = persistence
             .list_nodes()
@@ -1215,7 +1537,29 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
         }
 
         for (tenant_id, (count_min, count_max)) in tenant_shard_count_min_max {
-            if count_min != count_max {
+            let merging = tenant_shard_persistence.iter().any(|tsp| {
+                tsp.splitting == SplitState::Merging
+                    && TenantId::from_str(tsp.tenant_id.as_str()).unwrap() == tenant_id
+            });
+            if count_min != count_max && merging {
+                // Same as for a split below, but the shards to drop are the merged ones, with the lower count
+                tracing::info!("Aborting shard merge {tenant_id} {count_max:?} -> {count_min:?}");
+                let abort_status = persistence.abort_shard_merge(tenant_id, count_min).await?;
+                assert!(matches!(abort_status, AbortShardSplitStatus::Aborted));
+
+                tenant_shard_persistence.iter_mut().for_each(|tsp| {
+                    let tsp_tenant_id = TenantId::from_str(tsp.tenant_id.as_str()).unwrap();
+                    if tsp_tenant_id == tenant_id
+                        && tsp.get_shard_identity().unwrap().count == count_max
+                    {
+                        tsp.splitting = SplitState::Idle;
+                    }
+                });
+                tenant_shard_persistence.retain(|tsp| {
+                    TenantId::from_str(tsp.tenant_id.as_str()).unwrap() != tenant_id
+                        || tsp.splitting == SplitState::Idle
+                });
+            } else if count_min != count_max {
                 // Aborting the split in the database and dropping the child shards is sufficient: the reconciliation in
                 // [`Self::startup_reconcile`] will implicitly drop the child shards on remote pageservers, or they'll
                 // be dropped later in [`Self::node_activate_reconcile`] if it isn't available right now.
@@ -1321,6 +1665,10 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             cancel.clone(),
         );
 
+        let (history, history_rx) = OperationHistory::new();
+
+        let compute_hook = Arc::new(ComputeHook::new(config.clone(), persistence.clone()));
+
         let initial_leadership_status = if config.start_as_candidate {
             LeadershipStatus::Candidate
         } else {
@@ -1337,9 +1685,13 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             ))),
             config: config.clone(),
             persistence,
-            compute_hook: Arc::new(ComputeHook::new(config.clone())),
+            compute_hook,
             result_tx,
             heartbeater,
+            history,
+            tenant_activity: std::sync::Mutex::new(
+                hibernation::TenantActivity::new(Instant::now()),
+            ),
             reconciler_concurrency: Arc::new(tokio::sync::Semaphore::new(
                 config.reconciler_concurrency,
             )),
@@ -1352,6 +1704,8 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             reconcilers_gate: Gate::default(),
             tenant_op_locks: Default::default(),
             node_op_locks: Default::default(),
+            leader_lease: leader_lease.clone(),
+            lease_voter,
         });
 
         let result_task_this = this.clone();
@@ -1374,6 +1728,21 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             }
         });
 
+        tokio::task::spawn({
+            let this = this.clone();
+            async move {
+                if let Ok(_gate) = this.gate.enter() {
+                    OperationHistory::run(
+                        this.persistence.clone(),
+                        history_rx,
+                        this.config.history_retention,
+                        this.cancel.clone(),
+                    )
+                    .await
+                }
+            }
+        });
+
         tokio::task::spawn({
             let this = this.clone();
             async move {
@@ -1391,6 +1760,28 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             }
         });
 
+        if let Some(lease) = leader_lease {
+            tokio::task::spawn({
+                let this = this.clone();
+                async move {
+                    if let Ok(_gate) = this.gate.enter() {
+                        tokio::select! {
+                            _ = this.cancel.cancelled() => {},
+                            _ = lease.lost().cancelled() => {
+                                // Another controller may already be acting as leader: stop
+                                // touching pageservers.  Our database writes are fenced off
+                                // once it has started up, but in-flight reconciles are not.
+                                if this.get_leadership_status() == LeadershipStatus::Leader {
+                                    tracing::error!("Lost leader lease, stepping down");
+                                    this.step_down().await;
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
         tokio::task::spawn({
             let this = this.clone();
             // We will block the [`Service::startup_complete`] barrier until [`Self::startup_reconcile`]
@@ -1427,6 +1818,46 @@ pub async fn spawn(config: Config, persistence: Arc<Persistence>) -> anyhow::Res
             }
         });
 
+        tokio::task::spawn({
+            let this = this.clone();
+            let startup_complete = startup_complete.clone();
+            async move {
+                startup_complete.wait().await;
+                this.resume_rolling_operation_on_startup().await;
+            }
+        });
+
+        tokio::task::spawn({
+            let this = this.clone();
+            let startup_complete = startup_complete.clone();
+            async move {
+                startup_complete.wait().await;
+                this.resume_tenant_imports_on_startup().await;
+            }
+        });
+
+        tokio::task::spawn({
+            let this = this.clone();
+            let startup_complete = startup_complete.clone();
+            async move {
+                startup_complete.wait().await;
+                if let Ok(_gate) = this.gate.enter() {
+                    this.hibernate_idle_tenants().await;
+                }
+            }
+        });
+
+        tokio::task::spawn({
+            let this = this.clone();
+            let startup_complete = startup_complete.clone();
+            async move {
+                startup_complete.wait().await;
+                if let Ok(_gate) = this.gate.enter() {
+                    this.retry_compute_notifications().await;
+                }
+            }
+        });
+
         Ok(this)
     }
 
@@ -1948,6 +2379,7 @@ pub(crate) async fn tenant_create(
             TenantOperations::Create,
         )
         .await;
+        self.record_tenant_activity(tenant_id);
         let (response, waiters) = self.do_tenant_create(create_req).await?;
 
         if let Err(e) = self.await_waiters(waiters, RECONCILE_TIMEOUT).await {
@@ -2621,7 +3053,8 @@ pub(crate) async fn tenant_time_travel_remote_storage(
             let scheduler = &mut locked.scheduler;
             // Right now we only perform the operation on a single node without parallelization
             // TODO fan out the operation to multiple nodes for better performance
-            let node_id = scheduler.schedule_shard(&[], &ScheduleContext::default())?;
+            let node_id =
+                scheduler.schedule_shard(&[], AzAffinity::Any, &ScheduleContext::default())?;
             let node = locked
                 .nodes
                 .get(&node_id)
@@ -2807,7 +3240,8 @@ pub(crate) async fn tenant_delete(&self, tenant_id: TenantId) -> Result<StatusCo
 
             // Pick an arbitrary node to use for remote deletions (does not have to be where the tenant
             // was attached, just has to be able to see the S3 content)
-            let node_id = scheduler.schedule_shard(&[], &ScheduleContext::default())?;
+            let node_id =
+                scheduler.schedule_shard(&[], AzAffinity::Any, &ScheduleContext::default())?;
             let node = nodes
                 .get(&node_id)
                 .expect("Pageservers may not be deleted while lock is active");
@@ -2926,6 +3360,13 @@ pub(crate) async fn tenant_update_policy(
             )
             .await?;
 
+        if placement.is_some() {
+            // An explicit placement overrides hibernation: don't let a later wake restore the
+            // policy the tenant had when it hibernated.
+            self.persistence.delete_hibernated_tenant(tenant_id).await?;
+            self.record_tenant_activity(tenant_id);
+        }
+
         let mut schedule_context = ScheduleContext::default();
         let mut locked = self.inner.write().unwrap();
         let (nodes, tenants, scheduler) = locked.parts_mut();
@@ -4085,18 +4526,26 @@ pub(crate) async fn tenant_shard_split(
                 // Split might be part-done, we must do work to abort it.
                 tracing::warn!("Enqueuing background abort of split on {tenant_id}");
                 self.abort_tx
-                    .send(TenantShardSplitAbort {
+                    .send(TenantShardAbort::Split(TenantShardSplitAbort {
                         tenant_id,
                         new_shard_count,
                         new_stripe_size,
                         _tenant_lock,
-                    })
+                    }))
                     // Ignore error sending: that just means we're shutting down: aborts are ephemeral so it's fine to drop it.
                     .ok();
                 return Err(e);
             }
         };
 
+        self.history.record(
+            HistoryEvent::new(
+                OperationEventKind::ShardCount,
+                format!("Split into {} shards", new_shard_count.literal()),
+            )
+            .tenant(tenant_id),
+        );
+
         // The split is now complete.  As an optimization, we will trigger all the child shards to upload
         // a heatmap immediately, and all their secondary locations to start downloading: this avoids waiting
         // for the background heatmap/download interval before secondaries get warm enough to migrate shards
@@ -4529,45 +4978,730 @@ async fn do_tenant_shard_split(
         Ok((response, waiters))
     }
 
-    pub(crate) async fn tenant_shard_migrate(
+    pub(crate) async fn tenant_shard_merge(
         &self,
-        tenant_shard_id: TenantShardId,
-        migrate_req: TenantShardMigrateRequest,
-    ) -> Result<TenantShardMigrateResponse, ApiError> {
-        let waiter = {
-            let mut locked = self.inner.write().unwrap();
-            let (nodes, tenants, scheduler) = locked.parts_mut();
-
-            let Some(node) = nodes.get(&migrate_req.node_id) else {
-                return Err(ApiError::BadRequest(anyhow::anyhow!(
-                    "Node {} not found",
-                    migrate_req.node_id
-                )));
-            };
-
-            if !node.is_available() {
-                // Warn but proceed: the caller may intend to manually adjust the placement of
-                // a shard even if the node is down, e.g. if intervening during an incident.
-                tracing::warn!("Migrating to unavailable node {node}");
-            }
+        tenant_id: TenantId,
+        merge_req: TenantShardMergeRequest,
+    ) -> Result<TenantShardMergeResponse, ApiError> {
+        let _tenant_lock = trace_exclusive_lock(
+            &self.tenant_op_locks,
+            tenant_id,
+            TenantOperations::ShardMerge,
+        )
+        .await;
 
-            let Some(shard) = tenants.get_mut(&tenant_shard_id) else {
-                return Err(ApiError::NotFound(
-                    anyhow::anyhow!("Tenant shard not found").into(),
-                ));
-            };
+        let new_shard_count = ShardCount::new(merge_req.new_shard_count);
 
-            if shard.intent.get_attached() == &Some(migrate_req.node_id) {
-                // No-op case: we will still proceed to wait for reconciliation in case it is
-                // incomplete from an earlier update to the intent.
-                tracing::info!("Migrating: intent is unchanged {:?}", shard.intent);
-            } else {
-                let old_attached = *shard.intent.get_attached();
+        // Validate the request and construct parameters.  This phase is fallible, but does not require
+        // rollback on errors, as it does no I/O and mutates no state.
+        let shard_merge_params = match self.prepare_tenant_shard_merge(tenant_id, merge_req)? {
+            ShardMergeAction::NoOp(resp) => return Ok(resp),
+            ShardMergeAction::Merge(params) => params,
+        };
 
-                match shard.policy {
-                    PlacementPolicy::Attached(n) => {
-                        // If our new attached node was a secondary, it no longer should be.
-                        shard.intent.remove_secondary(scheduler, migrate_req.node_id);
+        // Execute this merge: this phase mutates state and does remote I/O on pageservers.  If it fails,
+        // we must roll back.
+        let mut merges_started = Vec::new();
+        let r = self
+            .do_tenant_shard_merge(tenant_id, shard_merge_params, &mut merges_started)
+            .await;
+
+        let (response, waiters) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                // Merge might be part-done, we must do work to abort it.
+                tracing::warn!("Enqueuing background abort of merge on {tenant_id}");
+                self.abort_tx
+                    .send(TenantShardAbort::Merge(TenantShardMergeAbort {
+                        tenant_id,
+                        new_shard_count,
+                        merges_started,
+                        _tenant_lock,
+                    }))
+                    // Ignore error sending: that just means we're shutting down: aborts are ephemeral so it's fine to drop it.
+                    .ok();
+                return Err(e);
+            }
+        };
+
+        self.history.record(
+            HistoryEvent::new(
+                OperationEventKind::ShardCount,
+                format!("Merged into {} shards", new_shard_count.literal()),
+            )
+            .tenant(tenant_id),
+        );
+
+        // As after a split, warm up secondary locations for the merged shards promptly
+        self.tenant_shard_split_start_secondaries(tenant_id, waiters)
+            .await;
+        Ok(response)
+    }
+
+    fn prepare_tenant_shard_merge(
+        &self,
+        tenant_id: TenantId,
+        merge_req: TenantShardMergeRequest,
+    ) -> Result<ShardMergeAction, ApiError> {
+        fail::fail_point!("shard-merge-validation", |_| Err(ApiError::BadRequest(
+            anyhow::anyhow!("failpoint")
+        )));
+
+        let new_shard_count = ShardCount::new(merge_req.new_shard_count);
+        if new_shard_count.literal() == 0 {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "Cannot merge into zero shards"
+            )));
+        }
+
+        let locked = self.inner.read().unwrap();
+
+        let mut shards = locked
+            .tenants
+            .range(TenantShardId::tenant_range(tenant_id))
+            .peekable();
+        let Some((_, first)) = shards.peek() else {
+            return Err(ApiError::NotFound(
+                anyhow::anyhow!("Tenant {} not found", tenant_id).into(),
+            ));
+        };
+        let old_shard_count = first.shard.count;
+        let policy = first.policy.clone();
+        let config = first.config.clone();
+        let shard_ident = first.shard;
+
+        let mut shard_ids = Vec::new();
+        for (tenant_shard_id, shard) in shards {
+            if shard.shard.count != old_shard_count || !matches!(shard.splitting, SplitState::Idle)
+            {
+                return Err(ApiError::Conflict(
+                    "Cannot merge, currently mid-split or mid-merge".to_string(),
+                ));
+            }
+            shard_ids.push(*tenant_shard_id);
+        }
+
+        match old_shard_count.count().cmp(&new_shard_count.count()) {
+            Ordering::Equal => {
+                // Already merged (this may be a retry)
+                return Ok(ShardMergeAction::NoOp(TenantShardMergeResponse {
+                    new_shards: shard_ids,
+                }));
+            }
+            Ordering::Less => {
+                return Err(ApiError::BadRequest(anyhow::anyhow!(
+                    "Requested count {} but tenant only has {} shards: use a split to increase it",
+                    new_shard_count.count(),
+                    old_shard_count.count()
+                )));
+            }
+            Ordering::Greater => {}
+        }
+
+        if old_shard_count.count() % new_shard_count.count() != 0
+            || !(old_shard_count.count() / new_shard_count.count()).is_power_of_two()
+        {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "Requested merge from {} to {} shards is not a power of two",
+                old_shard_count.count(),
+                new_shard_count.count()
+            )));
+        }
+
+        if !matches!(policy, PlacementPolicy::Attached(_)) {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "Cannot merge a tenant that is not attached"
+            )));
+        }
+
+        let mut targets = Vec::new();
+        for merged_number in 0..new_shard_count.count() {
+            let merged_id = TenantShardId {
+                tenant_id,
+                shard_number: ShardNumber(merged_number),
+                shard_count: new_shard_count,
+            };
+            let source_ids = merged_id.split(old_shard_count);
+
+            // The merge happens where the lowest-numbered source is attached
+            let lowest = locked
+                .tenants
+                .get(&source_ids[0])
+                .expect("Checked shard count above");
+            let Some(node_id) = *lowest.intent.get_attached() else {
+                return Err(ApiError::BadRequest(anyhow::anyhow!(
+                    "Cannot merge a tenant that is not attached"
+                )));
+            };
+            let node = locked
+                .nodes
+                .get(&node_id)
+                .expect("Pageservers may not be deleted while referenced");
+
+            targets.push(ShardMergeTarget {
+                merged_id,
+                node: node.clone(),
+                source_ids,
+            });
+        }
+
+        Ok(ShardMergeAction::Merge(Box::new(ShardMergeParams {
+            old_shard_count,
+            new_shard_count,
+            targets,
+            policy,
+            config,
+            shard_ident,
+        })))
+    }
+
+    /// Pushes each merged shard to `merges_started` before asking a pageserver to write it, so that
+    /// an abort knows which merged shards to clean up.
+    async fn do_tenant_shard_merge(
+        &self,
+        tenant_id: TenantId,
+        params: Box<ShardMergeParams>,
+        merges_started: &mut Vec<(TenantShardId, Node)>,
+    ) -> Result<(TenantShardMergeResponse, Vec<ReconcilerWaiter>), ApiError> {
+        let ShardMergeParams {
+            old_shard_count,
+            new_shard_count,
+            targets,
+            policy,
+            config,
+            shard_ident,
+        } = *params;
+
+        // Drop any secondary locations, and move all the sources of each merged shard onto the node
+        // that will do the merge: the pageserver can only merge shards that it has attached.
+        let waiters = {
+            let mut locked = self.inner.write().unwrap();
+            let mut waiters = Vec::new();
+            let (nodes, tenants, scheduler) = locked.parts_mut();
+            for target in &targets {
+                for source_id in &target.source_ids {
+                    let Some(shard) = tenants.get_mut(source_id) else {
+                        // Paranoia check: this shouldn't happen: we have the oplock for this tenant ID.
+                        return Err(ApiError::InternalServerError(anyhow::anyhow!(
+                            "Shard {} not found",
+                            source_id
+                        )));
+                    };
+
+                    shard.intent.clear_secondary(scheduler);
+                    if shard.intent.get_attached() != &Some(target.node.get_id()) {
+                        tracing::info!("Migrating {source_id} to {} for merge", target.node);
+                        shard
+                            .intent
+                            .set_attached(scheduler, Some(target.node.get_id()));
+                        shard.sequence = shard.sequence.next();
+                    }
+
+                    if let Some(waiter) = self.maybe_reconcile_shard(shard, nodes) {
+                        waiters.push(waiter);
+                    }
+                }
+            }
+            waiters
+        };
+        self.await_waiters(waiters, RECONCILE_TIMEOUT).await?;
+
+        fail::fail_point!("shard-merge-post-migrate", |_| Err(
+            ApiError::InternalServerError(anyhow::anyhow!("failpoint"))
+        ));
+
+        // Persist the merged shards before creating them anywhere, so that we can always clean up.  As
+        // with splits, this also protects against concurrent attempts to merge.
+        let merged_tsps = targets
+            .iter()
+            .map(|target| {
+                let tsp = TenantShardPersistence {
+                    tenant_id: target.merged_id.tenant_id.to_string(),
+                    shard_number: target.merged_id.shard_number.0 as i32,
+                    shard_count: target.merged_id.shard_count.literal() as i32,
+                    shard_stripe_size: shard_ident.stripe_size.0 as i32,
+                    // Note: this generation is a placeholder, [`Persistence::begin_shard_merge`] will
+                    // populate the correct generation as part of its transaction.
+                    generation: None,
+                    generation_pageserver: Some(target.node.get_id().0 as i64),
+                    placement_policy: serde_json::to_string(&policy).unwrap(),
+                    config: serde_json::to_string(&config).unwrap(),
+                    splitting: SplitState::Merging,
+
+                    // Scheduling policies and preferred AZ do not carry through to merged shards
+                    scheduling_policy: serde_json::to_string(&ShardSchedulingPolicy::default())
+                        .unwrap(),
+                    preferred_az_id: None,
+                };
+                (tsp, target.source_ids.clone())
+            })
+            .collect::<Vec<_>>();
+
+        if let Err(e) = self
+            .persistence
+            .begin_shard_merge(old_shard_count, tenant_id, merged_tsps)
+            .await
+        {
+            match e {
+                DatabaseError::Query(diesel::result::Error::DatabaseError(
+                    DatabaseErrorKind::UniqueViolation,
+                    _,
+                )) => {
+                    tracing::warn!("Conflicting attempt to merge {tenant_id}: {e}");
+                    return Err(ApiError::Conflict("Tenant is already merging".into()));
+                }
+                _ => return Err(ApiError::InternalServerError(e.into())),
+            }
+        }
+        fail::fail_point!("shard-merge-post-begin", |_| Err(
+            ApiError::InternalServerError(anyhow::anyhow!("failpoint"))
+        ));
+
+        // Apply the splitting state in-memory, which stops reconciliation of the sources while
+        // the pageserver is working on them.
+        {
+            let mut locked = self.inner.write().unwrap();
+            for target in &targets {
+                for source_id in &target.source_ids {
+                    if let Some(source_shard) = locked.tenants.get_mut(source_id) {
+                        source_shard.splitting = SplitState::Splitting;
+                        source_shard
+                            .observed
+                            .locations
+                            .insert(target.node.get_id(), ObservedStateLocation { conf: None });
+                    }
+                }
+            }
+        }
+
+        for target in &targets {
+            let ShardMergeTarget {
+                merged_id,
+                node,
+                source_ids,
+            } = target;
+
+            // Paranoia check: the sources must still all be attached where we put them above
+            {
+                let locked = self.inner.read().unwrap();
+                for source_id in source_ids {
+                    let attached = locked
+                        .tenants
+                        .get(source_id)
+                        .and_then(|s| *s.intent.get_attached());
+                    if attached != Some(node.get_id()) {
+                        return Err(ApiError::Conflict(format!(
+                            "Shard {} unexpectedly rescheduled during merge",
+                            source_id
+                        )));
+                    }
+                }
+            }
+
+            let client = PageserverClient::new(
+                node.get_id(),
+                node.base_url(),
+                self.config.jwt_token.as_deref(),
+            );
+            merges_started.push((*merged_id, node.clone()));
+            let response = client
+                .tenant_shard_merge(
+                    source_ids[0],
+                    TenantShardMergeRequest {
+                        new_shard_count: new_shard_count.literal(),
+                    },
+                )
+                .await
+                .map_err(|e| {
+                    if matches!(e, mgmt_api::Error::ApiError(StatusCode::CONFLICT, _)) {
+                        // The pageserver refused before writing anything: the merged shard's prefix
+                        // still holds layers of the shard that the sources were split from.
+                        merges_started.pop();
+                    }
+                    ApiError::Conflict(format!("Failed to merge into {}: {}", merged_id, e))
+                })?;
+
+            fail::fail_point!("shard-merge-post-remote", |_| Err(ApiError::Conflict(
+                "failpoint".to_string()
+            )));
+
+            tracing::info!("Merged {} shards into {}", source_ids.len(), merged_id);
+
+            if response.new_shards != vec![*merged_id] {
+                // This should never happen: the pageserver should agree with us on how shard merges work.
+                return Err(ApiError::InternalServerError(anyhow::anyhow!(
+                    "Merging into shard {} resulted in unexpected IDs: {:?}",
+                    merged_id,
+                    response.new_shards,
+                )));
+            }
+        }
+
+        // Dropping the source shards and clearing the splitting state is the same as for a split
+        self.persistence
+            .complete_shard_split(tenant_id, old_shard_count)
+            .await?;
+
+        fail::fail_point!("shard-merge-post-complete", |_| Err(
+            ApiError::InternalServerError(anyhow::anyhow!("failpoint"))
+        ));
+
+        // Replace all the sources with the merged shards: this phase is infallible.
+        let (response, merged_locations, waiters) =
+            self.tenant_shard_merge_commit_inmem(tenant_id, new_shard_count);
+
+        // The merged shards' preferred AZ is that of the pageserver they were merged on
+        let preferred_azs = {
+            let locked = self.inner.read().unwrap();
+            merged_locations
+                .iter()
+                .filter_map(|(tid, node_id, _stripe_size)| {
+                    let az_id = locked
+                        .nodes
+                        .get(node_id)
+                        .map(|n| n.get_availability_zone_id().to_string())?;
+
+                    Some((*tid, az_id))
+                })
+                .collect::<Vec<_>>()
+        };
+        match self
+            .persistence
+            .set_tenant_shard_preferred_azs(preferred_azs)
+            .await
+        {
+            Ok(updated) => {
+                let mut locked = self.inner.write().unwrap();
+                for (tid, az_id) in updated {
+                    if let Some(shard) = locked.tenants.get_mut(&tid) {
+                        shard.set_preferred_az(az_id);
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Failed to persist preferred AZs after merge: {err}");
+            }
+        }
+
+        // Send compute notifications for all the merged shards
+        let mut failed_notifications = Vec::new();
+        for (merged_id, merged_ps, stripe_size) in &merged_locations {
+            if let Err(e) = self
+                .compute_hook
+                .notify(*merged_id, *merged_ps, *stripe_size, &self.cancel)
+                .await
+            {
+                tracing::warn!("Failed to update compute of {}->{} during merge, proceeding anyway to complete merge ({e})",
+                        merged_id, merged_ps);
+                failed_notifications.push(*merged_id);
+            }
+        }
+        if !failed_notifications.is_empty() {
+            let mut locked = self.inner.write().unwrap();
+            for failed in failed_notifications {
+                if let Some(shard) = locked.tenants.get_mut(&failed) {
+                    shard.pending_compute_notification = true;
+                }
+            }
+        }
+
+        // Unlike split parents, nothing refers to the source shards' remote data once the merge is done.
+        // Now that the merge is committed, remove them from the pageserver, locally and from remote
+        // storage.  This is best-effort: leftovers are garbage, and do not affect the tenant.  If
+        // they remain attached, they are detached when the node is next reconciled.
+        for target in &targets {
+            for source_id in &target.source_ids {
+                let source_id = *source_id;
+                match target
+                    .node
+                    .with_client_retries(
+                        |client| async move { client.tenant_delete(source_id).await },
+                        &self.config.jwt_token,
+                        1,
+                        3,
+                        RECONCILE_TIMEOUT,
+                        &self.cancel,
+                    )
+                    .await
+                {
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        tracing::warn!("Failed to delete merged source shard {source_id}: {e}")
+                    }
+                    None => {
+                        tracing::warn!("Cancelled deleting merged source shard {source_id}")
+                    }
+                }
+            }
+        }
+
+        Ok((response, waiters))
+    }
+
+    /// Infallible final stage of [`Self::tenant_shard_merge`]: update the contents
+    /// of the tenant map to reflect the merged shards that exist after the merge.
+    fn tenant_shard_merge_commit_inmem(
+        &self,
+        tenant_id: TenantId,
+        new_shard_count: ShardCount,
+    ) -> (
+        TenantShardMergeResponse,
+        Vec<(TenantShardId, NodeId, ShardStripeSize)>,
+        Vec<ReconcilerWaiter>,
+    ) {
+        let mut response = TenantShardMergeResponse {
+            new_shards: Vec::new(),
+        };
+        let mut merged_locations = Vec::new();
+        let mut waiters = Vec::new();
+
+        let mut locked = self.inner.write().unwrap();
+
+        let source_ids = locked
+            .tenants
+            .range(TenantShardId::tenant_range(tenant_id))
+            .map(|(shard_id, _)| *shard_id)
+            .collect::<Vec<_>>();
+
+        let (nodes, tenants, scheduler) = locked.parts_mut();
+        let mut schedule_context = ScheduleContext::default();
+        for merged_id in source_ids
+            .iter()
+            .map(|id| id.merge(new_shard_count))
+            .unique()
+            .collect::<Vec<_>>()
+        {
+            let sources = source_ids
+                .iter()
+                .filter(|id| id.merge(new_shard_count) == merged_id)
+                .map(|id| {
+                    tenants
+                        .remove(id)
+                        .expect("It was present, we just merged it")
+                })
+                .collect::<Vec<_>>();
+
+            // The merged shard takes its location and settings from the lowest source, and the newest
+            // generation of all the sources, as the pageserver did.
+            let lowest = sources
+                .first()
+                .expect("Every merged shard has at least one source");
+            let pageserver = lowest.intent.get_attached().unwrap();
+            let policy = lowest.policy.clone();
+            let config = lowest.config.clone();
+            let mut merged_shard = lowest.shard;
+            let generation = sources
+                .iter()
+                .map(|s| s.generation.expect("Shard must have been attached"))
+                .max()
+                .unwrap();
+
+            for mut old_state in sources {
+                // As for splits, a non-splitting state is impossible because we hold the tenant's lock
+                assert!(matches!(old_state.splitting, SplitState::Splitting));
+                old_state.intent.clear(scheduler);
+            }
+
+            merged_shard.number = merged_id.shard_number;
+            merged_shard.count = merged_id.shard_count;
+
+            let mut merged_observed: HashMap<NodeId, ObservedStateLocation> = HashMap::new();
+            merged_observed.insert(
+                pageserver,
+                ObservedStateLocation {
+                    conf: Some(attached_location_conf(
+                        generation,
+                        &merged_shard,
+                        &config,
+                        &policy,
+                    )),
+                },
+            );
+
+            let mut merged_state = TenantShard::new(merged_id, merged_shard, policy.clone());
+            merged_state.intent = IntentState::single(scheduler, Some(pageserver));
+            merged_state.observed = ObservedState {
+                locations: merged_observed,
+            };
+            merged_state.generation = Some(generation);
+            merged_state.config = config;
+
+            merged_locations.push((merged_id, pageserver, merged_shard.stripe_size));
+
+            if let Err(e) = merged_state.schedule(scheduler, &mut schedule_context) {
+                // Not fatal: the merged shard is already attached, we just couldn't find a secondary.
+                tracing::warn!("Failed to schedule merged shard {merged_id}: {e}");
+            }
+            if let Some(waiter) = self.maybe_reconcile_shard(&mut merged_state, nodes) {
+                waiters.push(waiter);
+            }
+
+            tenants.insert(merged_id, merged_state);
+            response.new_shards.push(merged_id);
+        }
+
+        (response, merged_locations, waiters)
+    }
+
+    async fn abort_tenant_shard_merge(
+        &self,
+        op: &TenantShardMergeAbort,
+    ) -> Result<(), TenantShardSplitAbortError> {
+        // Cleaning up a merge is like cleaning up a split, with the roles reversed: the sources are restored,
+        // and the merged shards are removed.  The merged shards that a pageserver wrote must also be deleted
+        // from remote storage, as nothing else will ever clean them up (unlike split children, which are
+        // implicitly dropped).
+        let TenantShardMergeAbort {
+            tenant_id,
+            new_shard_count,
+            merges_started,
+            ..
+        } = op;
+
+        match self
+            .persistence
+            .abort_shard_merge(*tenant_id, *new_shard_count)
+            .await?
+        {
+            AbortShardSplitStatus::Aborted => {}
+            AbortShardSplitStatus::Complete => {
+                // The merge completed (e.g. we lost the connection after committing the transaction)
+                self.tenant_shard_merge_commit_inmem(*tenant_id, *new_shard_count);
+                return Ok(());
+            }
+        }
+
+        // Clean up in-memory state
+        {
+            let mut locked = self.inner.write().unwrap();
+            let (nodes, tenants, scheduler) = locked.parts_mut();
+
+            for (tenant_shard_id, shard) in
+                tenants.range_mut(TenantShardId::tenant_range(*tenant_id))
+            {
+                if shard.shard.count == *new_shard_count {
+                    tracing::warn!(
+                        "During merge abort, merged shard {tenant_shard_id} found in-memory"
+                    );
+                    continue;
+                }
+
+                tracing::info!("Restoring source shard {tenant_shard_id}");
+                shard.splitting = SplitState::Idle;
+                if let Err(e) = shard.schedule(scheduler, &mut ScheduleContext::default()) {
+                    tracing::warn!("Failed to schedule {tenant_shard_id} during merge abort: {e}")
+                }
+
+                self.maybe_reconcile_shard(shard, nodes);
+            }
+
+            tenants.retain(|_id, s| s.shard.count != *new_shard_count);
+        }
+
+        for (merged_id, node) in merges_started {
+            let merged_id = *merged_id;
+            // Pick up the node's current availability
+            let node = self
+                .inner
+                .read()
+                .unwrap()
+                .nodes
+                .get(&node.get_id())
+                .cloned()
+                .unwrap_or_else(|| node.clone());
+            if !node.is_available() {
+                // Unlike a split child, a merged shard's remote data would be leaked if we skipped it here,
+                // so wait for the node to come back.
+                tracing::warn!("Node {node} unavailable, can't clean up during merge abort");
+                return Err(TenantShardSplitAbortError::Unavailable);
+            }
+
+            // Detach, then delete the remote data of the merged shard.  If the pageserver merge API call is
+            // still in progress, these calls will get a 503 and retry, up to our limit.
+            tracing::info!("Deleting {merged_id} on {node}...");
+            match node
+                .with_client_retries(
+                    |client| async move {
+                        let config = LocationConfig {
+                            mode: LocationConfigMode::Detached,
+                            generation: None,
+                            secondary_conf: None,
+                            shard_number: merged_id.shard_number.0,
+                            shard_count: merged_id.shard_count.literal(),
+                            // Stripe size and tenant config don't matter when detaching
+                            shard_stripe_size: 0,
+                            tenant_conf: TenantConfig::default(),
+                        };
+
+                        client
+                            .location_config(merged_id, config, None, false)
+                            .await?;
+                        client.tenant_delete(merged_id).await
+                    },
+                    &self.config.jwt_token,
+                    1,
+                    10,
+                    Duration::from_secs(5),
+                    &self.cancel,
+                )
+                .await
+            {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    tracing::warn!(
+                        "Failed to delete merged shard {merged_id} from node {node} during abort"
+                    );
+                    return Err(e.into());
+                }
+                None => {
+                    return Err(TenantShardSplitAbortError::Unavailable);
+                }
+            };
+        }
+
+        tracing::info!("Successfully aborted merge");
+        Ok(())
+    }
+
+    pub(crate) async fn tenant_shard_migrate(
+        &self,
+        tenant_shard_id: TenantShardId,
+        migrate_req: TenantShardMigrateRequest,
+    ) -> Result<TenantShardMigrateResponse, ApiError> {
+        let waiter = {
+            let mut locked = self.inner.write().unwrap();
+            let (nodes, tenants, scheduler) = locked.parts_mut();
+
+            let Some(node) = nodes.get(&migrate_req.node_id) else {
+                return Err(ApiError::BadRequest(anyhow::anyhow!(
+                    "Node {} not found",
+                    migrate_req.node_id
+                )));
+            };
+
+            if !node.is_available() {
+                // Warn but proceed: the caller may intend to manually adjust the placement of
+                // a shard even if the node is down, e.g. if intervening during an incident.
+                tracing::warn!("Migrating to unavailable node {node}");
+            }
+
+            let Some(shard) = tenants.get_mut(&tenant_shard_id) else {
+                return Err(ApiError::NotFound(
+                    anyhow::anyhow!("Tenant shard not found").into(),
+                ));
+            };
+
+            if shard.intent.get_attached() == &Some(migrate_req.node_id) {
+                // No-op case: we will still proceed to wait for reconciliation in case it is
+                // incomplete from an earlier update to the intent.
+                tracing::info!("Migrating: intent is unchanged {:?}", shard.intent);
+            } else {
+                let old_attached = *shard.intent.get_attached();
+
+                match shard.policy {
+                    PlacementPolicy::Attached(n) => {
+                        // If our new attached node was a secondary, it no longer should be.
+                        shard.intent.remove_secondary(scheduler, migrate_req.node_id);
 
                         // If we were already attached to something, demote that to a secondary
                         if let Some(old_attached) = old_attached {
@@ -4829,7 +5963,7 @@ pub(crate) async fn consistency_check(&self) -> Result<(), ApiError> {
     pub(crate) fn scheduler_dump(&self) -> Result<hyper::Response<hyper::Body>, ApiError> {
         let serialized = {
             let locked = self.inner.read().unwrap();
-            serde_json::to_string(&locked.scheduler)
+            serde_json::to_string(&locked.scheduler.view())
                 .map_err(|e| ApiError::InternalServerError(e.into()))?
         };
 
@@ -4912,7 +6046,17 @@ pub(crate) async fn node_delete(&self, node_id: NodeId) -> Result<(), ApiError>
                     } else {
                         tracing::info!(
                             "Rescheduled shard {tenant_shard_id} away from node during deletion"
-                        )
+                        );
+                        self.history.record(
+                            HistoryEvent::new(
+                                OperationEventKind::Schedule,
+                                format!(
+                                    "Rescheduled away from deleted node {node_id}: attached to {:?}",
+                                    shard.intent.get_attached()
+                                ),
+                            )
+                            .shard(*tenant_shard_id),
+                        );
                     }
 
                     self.maybe_reconcile_shard(shard, nodes);
@@ -4946,6 +6090,8 @@ pub(crate) async fn node_delete(&self, node_id: NodeId) -> Result<(), ApiError>
         // 2. Actually delete the node from the database and from in-memory state
         tracing::info!("Deleting node from database");
         self.persistence.delete_node(node_id).await?;
+        self.history
+            .record(HistoryEvent::new(OperationEventKind::NodeState, "Deleted").node(node_id));
 
         Ok(())
     }
@@ -5127,6 +6273,16 @@ enum RegistrationStatus {
             register_req.node_id,
             locked.nodes.len()
         );
+        self.history.record(
+            HistoryEvent::new(
+                OperationEventKind::NodeState,
+                format!(
+                    "Registered in availability zone {}",
+                    locked.nodes[&register_req.node_id].get_availability_zone_id()
+                ),
+            )
+            .node(register_req.node_id),
+        );
         Ok(())
     }
 
@@ -5192,6 +6348,18 @@ pub(crate) async fn node_configure(
         }
 
         if let Some(scheduling) = scheduling {
+            if node.get_scheduling() != scheduling {
+                self.history.record(
+                    HistoryEvent::new(
+                        OperationEventKind::NodeState,
+                        format!(
+                            "Scheduling policy {:?} -> {scheduling:?}",
+                            node.get_scheduling()
+                        ),
+                    )
+                    .node(node_id),
+                );
+            }
             node.set_scheduling(scheduling);
         }
 
@@ -5200,13 +6368,61 @@ pub(crate) async fn node_configure(
 
         let new_nodes = Arc::new(new_nodes);
 
+        let transition_description = match availability_transition {
+            AvailabilityTransition::ToActive => Some("active"),
+            AvailabilityTransition::ToOffline => Some("offline"),
+            AvailabilityTransition::ToWarmingUpFromActive
+            | AvailabilityTransition::ToWarmingUpFromOffline => Some("warming up"),
+            AvailabilityTransition::Unchanged => None,
+        };
+        if let Some(description) = transition_description {
+            self.history.record(
+                HistoryEvent::new(
+                    OperationEventKind::NodeState,
+                    format!("Availability changed to {description}"),
+                )
+                .node(node_id),
+            );
+        }
+
         // Modify scheduling state for any Tenants that are affected by a change in the node's availability state.
         match availability_transition {
             AvailabilityTransition::ToOffline => {
                 tracing::info!("Node {} transition to offline", node_id);
                 let mut tenants_affected: usize = 0;
 
-                for (tenant_shard_id, tenant_shard) in tenants {
+                let az = new_nodes[&node_id].get_availability_zone_id();
+                let az_lost =
+                    az_unavailable(&new_nodes, az) && new_nodes.values().any(|n| n.is_available());
+                if az_lost {
+                    tracing::warn!(
+                        "All nodes in AZ {az} are offline: failing over its attachments to other AZs"
+                    );
+                    self.history.record(HistoryEvent::new(
+                        OperationEventKind::NodeState,
+                        format!("AZ {az} is offline: failing over to other AZs"),
+                    ));
+                    metrics::METRICS_REGISTRY
+                        .metrics_group
+                        .storage_controller_az_failover
+                        .inc();
+                }
+
+                // Shards that we could only reschedule onto a node with no secondary location on it.
+                // Attaching these from scratch is slow and expensive, so we reconcile them after
+                // the shards that can promote a warm secondary: when a whole AZ goes offline, this
+                // gets as many tenants as possible available again before the reconciler
+                // concurrency limit starts deferring the rest.
+                let mut cold_attaches = Vec::new();
+                // When a whole AZ goes offline, only this many shards fail over now: the rest are
+                // left to [`Self::az_failover_pass`].
+                let mut failovers_left = if az_lost {
+                    self.config.az_failover_per_pass
+                } else {
+                    usize::MAX
+                };
+
+                for (tenant_shard_id, tenant_shard) in tenants.iter_mut() {
                     if let Some(observed_loc) = tenant_shard.observed.locations.get_mut(&node_id) {
                         // When a node goes offline, we set its observed configuration to None, indicating unknown: we will
                         // not assume our knowledge of the node's configuration is accurate until it comes back online
@@ -5230,32 +6446,36 @@ pub(crate) async fn node_configure(
                         continue;
                     }
 
-                    if tenant_shard.intent.demote_attached(scheduler, node_id) {
-                        tenant_shard.sequence = tenant_shard.sequence.next();
+                    if failovers_left == 0 {
+                        continue;
+                    }
 
-                        // TODO: populate a ScheduleContext including all shards in the same tenant_id (only matters
-                        // for tenants without secondary locations: if they have a secondary location, then this
-                        // schedule() call is just promoting an existing secondary)
-                        let mut schedule_context = ScheduleContext::default();
+                    if let Some(promoted) =
+                        self.reschedule_from_offline_node(tenant_shard, scheduler, node_id)
+                    {
+                        failovers_left -= 1;
+                        if !promoted {
+                            cold_attaches.push(*tenant_shard_id);
+                        } else if self
+                            .maybe_reconcile_shard(tenant_shard, &new_nodes)
+                            .is_some()
+                        {
+                            tenants_affected += 1;
+                        };
+                    }
+                }
 
-                        match tenant_shard.schedule(scheduler, &mut schedule_context) {
-                            Err(e) => {
-                                // It is possible that some tenants will become unschedulable when too many pageservers
-                                // go offline: in this case there isn't much we can do other than make the issue observable.
-                                // TODO: give TenantShard a scheduling error attribute to be queried later.
-                                tracing::warn!(%tenant_shard_id, "Scheduling error when marking pageserver {} offline: {e}", node_id);
-                            }
-                            Ok(()) => {
-                                if self
-                                    .maybe_reconcile_shard(tenant_shard, &new_nodes)
-                                    .is_some()
-                                {
-                                    tenants_affected += 1;
-                                };
-                            }
+                for tenant_shard_id in cold_attaches {
+                    if let Some(tenant_shard) = tenants.get_mut(&tenant_shard_id) {
+                        if self
+                            .maybe_reconcile_shard(tenant_shard, &new_nodes)
+                            .is_some()
+                        {
+                            tenants_affected += 1;
                         }
                     }
                 }
+
                 tracing::info!(
                     "Launched {} reconciler tasks for tenants affected by node {} going offline",
                     tenants_affected,
@@ -5264,6 +6484,18 @@ pub(crate) async fn node_configure(
             }
             AvailabilityTransition::ToActive => {
                 tracing::info!("Node {} transition to active", node_id);
+
+                let az = new_nodes[&node_id].get_availability_zone_id();
+                if az_unavailable(nodes, az) {
+                    // Attachments that failed over are moved back by the optimizer, a few at a time, once
+                    // the secondaries that we kept on this AZ's nodes are usable again.
+                    tracing::info!("AZ {az} is back online");
+                    self.history.record(HistoryEvent::new(
+                        OperationEventKind::NodeState,
+                        format!("AZ {az} is back online"),
+                    ));
+                }
+
                 // When a node comes back online, we must reconcile any tenant that has a None observed
                 // location on the node.
                 for tenant_shard in locked.tenants.values_mut() {
@@ -5744,7 +6976,19 @@ async fn optimize_all(&self) -> usize {
                 // Shard was dropped between planning and execution;
                 continue;
             };
+            let description = optimization.action.to_string();
             if shard.apply_optimization(scheduler, optimization) {
+                metrics::METRICS_REGISTRY
+                    .metrics_group
+                    .storage_controller_schedule_optimization
+                    .inc();
+                self.history.record(
+                    HistoryEvent::new(
+                        OperationEventKind::Schedule,
+                        format!("Optimization: {description}"),
+                    )
+                    .shard(tenant_shard_id),
+                );
                 optimizations_applied += 1;
                 if self.maybe_reconcile_shard(shard, nodes).is_some() {
                     reconciles_spawned += 1;
@@ -5767,100 +7011,14 @@ async fn optimize_all(&self) -> usize {
     }
 
     fn optimize_all_plan(&self) -> Vec<(TenantShardId, ScheduleOptimization)> {
-        let mut schedule_context = ScheduleContext::default();
-
-        let mut tenant_shards: Vec<&TenantShard> = Vec::new();
-
         // How many candidate optimizations we will generate, before evaluating them for readniess: setting
         // this higher than the execution limit gives us a chance to execute some work even if the first
         // few optimizations we find are not ready.
         const MAX_OPTIMIZATIONS_PLAN_PER_PASS: usize = 8;
 
-        let mut work = Vec::new();
-
         let mut locked = self.inner.write().unwrap();
         let (nodes, tenants, scheduler) = locked.parts_mut();
-        for (tenant_shard_id, shard) in tenants.iter() {
-            if tenant_shard_id.is_shard_zero() {
-                // Reset accumulators on the first shard in a tenant
-                schedule_context = ScheduleContext::default();
-                schedule_context.mode = ScheduleMode::Speculative;
-                tenant_shards.clear();
-            }
-
-            if work.len() >= MAX_OPTIMIZATIONS_PLAN_PER_PASS {
-                break;
-            }
-
-            match shard.get_scheduling_policy() {
-                ShardSchedulingPolicy::Active => {
-                    // Ok to do optimization
-                }
-                ShardSchedulingPolicy::Essential
-                | ShardSchedulingPolicy::Pause
-                | ShardSchedulingPolicy::Stop => {
-                    // Policy prevents optimizing this shard.
-                    continue;
-                }
-            }
-
-            // Accumulate the schedule context for all the shards in a tenant: we must have
-            // the total view of all shards before we can try to optimize any of them.
-            schedule_context.avoid(&shard.intent.all_pageservers());
-            if let Some(attached) = shard.intent.get_attached() {
-                schedule_context.push_attached(*attached);
-            }
-            tenant_shards.push(shard);
-
-            // Once we have seen the last shard in the tenant, proceed to search across all shards
-            // in the tenant for optimizations
-            if shard.shard.number.0 == shard.shard.count.count() - 1 {
-                if tenant_shards.iter().any(|s| s.reconciler.is_some()) {
-                    // Do not start any optimizations while another change to the tenant is ongoing: this
-                    // is not necessary for correctness, but simplifies operations and implicitly throttles
-                    // optimization changes to happen in a "trickle" over time.
-                    continue;
-                }
-
-                if tenant_shards.iter().any(|s| {
-                    !matches!(s.splitting, SplitState::Idle)
-                        || matches!(s.policy, PlacementPolicy::Detached)
-                }) {
-                    // Never attempt to optimize a tenant that is currently being split, or
-                    // a tenant that is meant to be detached
-                    continue;
-                }
-
-                // TODO: optimization calculations are relatively expensive: create some fast-path for
-                // the common idle case (avoiding the search on tenants that we have recently checked)
-
-                for shard in &tenant_shards {
-                    if let Some(optimization) =
-                        // If idle, maybe ptimize attachments: if a shard has a secondary location that is preferable to
-                        // its primary location based on soft constraints, cut it over.
-                        shard.optimize_attachment(nodes, &schedule_context)
-                    {
-                        work.push((shard.tenant_shard_id, optimization));
-                        break;
-                    } else if let Some(optimization) =
-                        // If idle, maybe optimize secondary locations: if a shard has a secondary location that would be
-                        // better placed on another node, based on ScheduleContext, then adjust it.  This
-                        // covers cases like after a shard split, where we might have too many shards
-                        // in the same tenant with secondary locations on the node where they originally split.
-                        shard.optimize_secondary(scheduler, &schedule_context)
-                    {
-                        work.push((shard.tenant_shard_id, optimization));
-                        break;
-                    }
-
-                    // TODO: extend this mechanism to prefer attaching on nodes with fewer attached
-                    // tenants (i.e. extend schedule state to distinguish attached from secondary counts),
-                    // for the total number of attachments on a node (not just within a tenant.)
-                }
-            }
-        }
-
-        work
+        plan_optimizations(nodes, tenants, scheduler, MAX_OPTIMIZATIONS_PLAN_PER_PASS)
     }
 
     async fn optimize_all_validate(
@@ -6123,6 +7281,10 @@ pub async fn shutdown(&self) {
         self.stop_reconciliations(StopReconciliationsReason::ShuttingDown)
             .await;
 
+        if let Some(lease) = &self.leader_lease {
+            lease.release().await;
+        }
+
         // Background tasks hold gate guards: this notifies them of the cancellation and
         // waits for them all to complete.
         tracing::info!("Shutting down: cancelling and waiting for background tasks to exit");
@@ -6301,6 +7463,17 @@ pub(crate) async fn drain_node(
                     )?;
 
                     if let Some(tenant_shard) = rescheduled {
+                        self.history.record(
+                            HistoryEvent::new(
+                                OperationEventKind::Schedule,
+                                format!(
+                                    "Migrated to {dest_node_id} to drain node {}",
+                                    tid_drain.drained_node
+                                ),
+                            )
+                            .shard(tid_drain.tenant_shard_id)
+                            .node(tid_drain.drained_node),
+                        );
                         let waiter = self.maybe_configured_reconcile_shard(
                             tenant_shard,
                             nodes,
@@ -6381,11 +7554,19 @@ pub(crate) async fn drain_node(
     fn fill_node_plan(&self, node_id: NodeId) -> Vec<TenantShardId> {
         let mut locked = self.inner.write().unwrap();
         let fill_requirement = locked.scheduler.compute_fill_requirement(node_id);
+        let fill_az = locked.scheduler.node_az(node_id).map(|az| az.to_string());
 
         let mut tids_by_node = locked
             .tenants
             .iter_mut()
             .filter_map(|(tid, tenant_shard)| {
+                // Filling must not pull attachments out of their preferred AZ
+                if let Some(preferred_az) = tenant_shard.preferred_az() {
+                    if fill_az.as_deref() != Some(preferred_az) {
+                        return None;
+                    }
+                }
+
                 if tenant_shard.intent.get_secondary().contains(&node_id) {
                     if let Some(primary) = tenant_shard.intent.get_attached() {
                         return Some((*primary, *tid));
@@ -6539,6 +7720,16 @@ pub(crate) async fn fill_node(
                                         previously_attached_to,
                                         node_id
                                     );
+                                    self.history.record(
+                                        HistoryEvent::new(
+                                            OperationEventKind::Schedule,
+                                            format!(
+                                                "Migrated from {previously_attached_to:?} to fill node {node_id}"
+                                            ),
+                                        )
+                                        .shard(tid)
+                                        .node(node_id),
+                                    );
 
                                     if let Some(waiter) = self.maybe_configured_reconcile_shard(
                                         tenant_shard,
@@ -6665,19 +7856,56 @@ pub(crate) async fn metadata_health_list_outdated(
         Ok(result)
     }
 
+    pub(crate) fn lease_voter(&self) -> Option<&Arc<LeaseVoter>> {
+        self.lease_voter.as_ref()
+    }
+
     pub(crate) fn get_leadership_status(&self) -> LeadershipStatus {
         self.inner.read().unwrap().get_leadership_status()
     }
 
+    /// Record an event in the operation history: see [`crate::history`]
+    pub(crate) fn record_history(&self, event: HistoryEvent) {
+        self.history.record(event);
+    }
+
+    pub(crate) async fn operation_history(
+        &self,
+        filter: OperationEventFilter,
+    ) -> Result<Vec<OperationEvent>, ApiError> {
+        self.persistence
+            .list_operation_events(filter)
+            .await?
+            .into_iter()
+            .map(|event| event.into_event().map_err(ApiError::InternalServerError))
+            .collect()
+    }
+
     pub(crate) async fn step_down(&self) -> GlobalObservedState {
         tracing::info!("Received step down request from peer");
         failpoint_support::sleep_millis_async!("sleep-on-step-down-handling");
 
-        self.inner.write().unwrap().step_down();
+        {
+            let mut locked = self.inner.write().unwrap();
+            locked.step_down();
+            // The new leader resumes the rolling operation from its persisted state
+            if let Some(rolling) = locked.rolling_operation.as_ref() {
+                rolling.cancel.cancel();
+            }
+            // ...and likewise any tenant imports
+            for cancel in locked.tenant_imports.values() {
+                cancel.cancel();
+            }
+        }
         // TODO: would it make sense to have a time-out for this?
         self.stop_reconciliations(StopReconciliationsReason::SteppingDown)
             .await;
 
+        // Hand the lease back, so that whoever asked us to step down need not wait for it to expire
+        if let Some(lease) = &self.leader_lease {
+            lease.release().await;
+        }
+
         let mut global_observed = GlobalObservedState::default();
         let locked = self.inner.read().unwrap();
         for (tid, tenant_shard) in locked.tenants.iter() {
@@ -6734,3 +7962,131 @@ pub(crate) async fn update_shards_preferred_azs(
         })
     }
 }
+
+/// Whether every node in an AZ is unavailable.  AZs we have no nodes in are not considered unavailable.
+fn az_unavailable(nodes: &HashMap<NodeId, Node>, az: &str) -> bool {
+    let mut az_nodes = nodes
+        .values()
+        .filter(|n| n.get_availability_zone_id() == az)
+        .peekable();
+    az_nodes.peek().is_some() && az_nodes.all(|n| !n.is_available())
+}
+
+/// Scan shards for possible scheduling optimizations, stopping once `max_optimizations` are found.
+/// The optimizations are not applied: see [`TenantShard::apply_optimization`].
+fn plan_optimizations(
+    nodes: &HashMap<NodeId, Node>,
+    tenants: &BTreeMap<TenantShardId, TenantShard>,
+    scheduler: &mut Scheduler,
+    max_optimizations: usize,
+) -> Vec<(TenantShardId, ScheduleOptimization)> {
+    let mut schedule_context = ScheduleContext::default();
+
+    let mut tenant_shards: Vec<&TenantShard> = Vec::new();
+
+    let mut work = Vec::new();
+
+    // If some node is much busier than others, we will also look for a shard to move away from it.  At
+    // most one such move is planned per pass, as each move changes the picture for the next.
+    let mut load_rebalance = scheduler.plan_load_rebalance();
+
+    for (tenant_shard_id, shard) in tenants.iter() {
+        if tenant_shard_id.is_shard_zero() {
+            // Reset accumulators on the first shard in a tenant
+            schedule_context = ScheduleContext::default();
+            schedule_context.mode = ScheduleMode::Speculative;
+            tenant_shards.clear();
+        }
+
+        if work.len() >= max_optimizations {
+            break;
+        }
+
+        match shard.get_scheduling_policy() {
+            ShardSchedulingPolicy::Active => {
+                // Ok to do optimization
+            }
+            ShardSchedulingPolicy::Essential
+            | ShardSchedulingPolicy::Pause
+            | ShardSchedulingPolicy::Stop => {
+                // Policy prevents optimizing this shard.
+                continue;
+            }
+        }
+
+        // Accumulate the schedule context for all the shards in a tenant: we must have
+        // the total view of all shards before we can try to optimize any of them.
+        schedule_context.avoid(&shard.intent.all_pageservers());
+        if let Some(attached) = shard.intent.get_attached() {
+            schedule_context.push_attached(*attached);
+        }
+        tenant_shards.push(shard);
+
+        // Once we have seen the last shard in the tenant, proceed to search across all shards
+        // in the tenant for optimizations
+        if shard.shard.number.0 == shard.shard.count.count() - 1 {
+            if tenant_shards.iter().any(|s| s.reconciler.is_some()) {
+                // Do not start any optimizations while another change to the tenant is ongoing: this
+                // is not necessary for correctness, but simplifies operations and implicitly throttles
+                // optimization changes to happen in a "trickle" over time.
+                continue;
+            }
+
+            if tenant_shards.iter().any(|s| {
+                !matches!(s.splitting, SplitState::Idle)
+                    || matches!(s.policy, PlacementPolicy::Detached)
+            }) {
+                // Never attempt to optimize a tenant that is currently being split, or
+                // a tenant that is meant to be detached
+                continue;
+            }
+
+            // TODO: optimization calculations are relatively expensive: create some fast-path for
+            // the common idle case (avoiding the search on tenants that we have recently checked)
+
+            let work_len = work.len();
+            for shard in &tenant_shards {
+                if let Some(optimization) =
+                    // If idle, maybe ptimize attachments: if a shard has a secondary location that is preferable to
+                    // its primary location based on soft constraints, cut it over.
+                    shard.optimize_attachment(nodes, &schedule_context)
+                {
+                    work.push((shard.tenant_shard_id, optimization));
+                    break;
+                } else if let Some(optimization) =
+                    // If idle, maybe optimize secondary locations: if a shard has a secondary location that would be
+                    // better placed on another node, based on ScheduleContext, then adjust it.  This
+                    // covers cases like after a shard split, where we might have too many shards
+                    // in the same tenant with secondary locations on the node where they originally split.
+                    shard.optimize_secondary(scheduler, &schedule_context)
+                {
+                    work.push((shard.tenant_shard_id, optimization));
+                    break;
+                }
+
+                // TODO: extend this mechanism to prefer attaching on nodes with fewer attached
+                // tenants (i.e. extend schedule state to distinguish attached from secondary counts),
+                // for the total number of attachments on a node (not just within a tenant.)
+            }
+
+            // Placement within the tenant takes precedence over load: only consider moving this
+            // tenant's shards for load reasons if they are otherwise where we want them.
+            if work.len() == work_len {
+                if let Some(rebalance) = &load_rebalance {
+                    if let Some((tenant_shard_id, optimization)) =
+                        tenant_shards.iter().find_map(|shard| {
+                            shard
+                                .optimize_load(nodes, rebalance, &schedule_context)
+                                .map(|o| (shard.tenant_shard_id, o))
+                        })
+                    {
+                        work.push((tenant_shard_id, optimization));
+                        load_rebalance = None;
+                    }
+                }
+            }
+        }
+    }
+
+    work
+}
@@ -0,0 +1,625 @@
+//! Hibernation moves tenants whose computes have gone quiet out of pageservers' attached set:
+//! first to [`PlacementPolicy::Secondary`], so that a warm location remains to re-attach
+//! quickly, and later (optionally) to [`PlacementPolicy::Detached`].
+//!
+//! Activity is learned from the `active_shards` that pageservers include in their utilization
+//! reports.  The placement policy a tenant had before hibernating is persisted, so that
+//! [`Service::tenant_wake`] can restore it, even from a different controller instance.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use pageserver_api::{
+    controller_api::{
+        OperationEventKind, PlacementPolicy, ShardSchedulingPolicy, TenantLocateResponse,
+    },
+    shard::TenantShardId,
+};
+use utils::{
+    http::error::ApiError,
+    id::{NodeId, TenantId},
+};
+
+use crate::{
+    history::HistoryEvent,
+    id_lock_map::trace_exclusive_lock,
+    metrics::{self, HibernatePlacement, TenantHibernateLabelGroup},
+    persistence::{split_state::SplitState, HibernatedTenantPersistence, TenantFilter},
+    scheduler::ScheduleContext,
+    tenant_shard::{ReconcilerWaiter, TenantShard},
+};
+
+use super::{LeadershipStatus, Service, TenantOperations, RECONCILE_TIMEOUT};
+
+/// How often to look for tenants to hibernate
+const HIBERNATE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Upper bound on how many tenants change placement in one pass, so that a controller restart
+/// or a pageserver upgrade that stops reporting activity can't hibernate everything at once.
+const MAX_HIBERNATIONS_PER_PASS: usize = 64;
+
+/// When we last saw compute activity for each tenant.
+pub(super) struct TenantActivity {
+    /// Tenants we have never seen active are treated as if they were active at this time, so
+    /// that nothing is hibernated until we have observed it for a whole idle period.
+    since: Instant,
+    last_active: HashMap<TenantId, Instant>,
+    /// Nodes whose utilization reports include `active_shards`.  Tenants attached anywhere else
+    /// are never considered idle, because we can't see their activity.
+    reporting_nodes: HashSet<NodeId>,
+}
+
+impl TenantActivity {
+    pub(super) fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            last_active: HashMap::new(),
+            reporting_nodes: HashSet::new(),
+        }
+    }
+
+    fn record(&mut self, tenant_id: TenantId, now: Instant) {
+        self.last_active.insert(tenant_id, now);
+    }
+
+    fn record_node(
+        &mut self,
+        node_id: NodeId,
+        active_shards: Option<&[TenantShardId]>,
+        now: Instant,
+    ) {
+        match active_shards {
+            Some(active_shards) => {
+                self.reporting_nodes.insert(node_id);
+                for shard in active_shards {
+                    self.record(shard.tenant_id, now);
+                }
+            }
+            None => {
+                self.reporting_nodes.remove(&node_id);
+            }
+        }
+    }
+
+    fn idle_for(&self, tenant_id: &TenantId, now: Instant) -> Duration {
+        let last_active = self.last_active.get(tenant_id).unwrap_or(&self.since);
+        now.saturating_duration_since(*last_active)
+    }
+
+    fn is_reporting(&self, node_id: &NodeId) -> bool {
+        self.reporting_nodes.contains(node_id)
+    }
+
+    /// Forget tenants that no longer exist
+    fn retain(&mut self, f: impl Fn(&TenantId) -> bool) {
+        self.last_active.retain(|tenant_id, _| f(tenant_id));
+    }
+}
+
+/// Whether a tenant whose shards are `shards` may be hibernated: it must be attached, with
+/// nothing else going on, on nodes that report activity.
+fn hibernate_eligible<'a>(
+    mut shards: impl Iterator<Item = &'a TenantShard>,
+    activity: &TenantActivity,
+) -> bool {
+    shards.all(|shard| {
+        matches!(shard.policy, PlacementPolicy::Attached(_))
+            && matches!(shard.get_scheduling_policy(), ShardSchedulingPolicy::Active)
+            && matches!(shard.splitting, SplitState::Idle)
+            && shard
+                .intent
+                .get_attached()
+                .map(|node_id| activity.is_reporting(&node_id))
+                .unwrap_or(false)
+    })
+}
+
+/// What the detach pass does with a tenant that has a hibernation record
+#[derive(Debug, PartialEq, Eq)]
+enum DetachDecision {
+    /// Hibernated for long enough: detach it
+    Detach,
+    /// Leave it alone for now
+    Wait,
+    /// Woken by some other path, or deleted: the record is stale
+    Forget,
+}
+
+/// `policy` is the tenant's current placement policy (`None` if it doesn't exist), and
+/// `hibernated_at` when it was hibernated (`None` if it no longer has a hibernation record).
+fn hibernate_detach_decision(
+    policy: Option<&PlacementPolicy>,
+    hibernated_at: Option<chrono::DateTime<chrono::Utc>>,
+    now: chrono::DateTime<chrono::Utc>,
+    detach_after: Option<Duration>,
+) -> DetachDecision {
+    let Some(hibernated_at) = hibernated_at else {
+        return DetachDecision::Wait;
+    };
+
+    match policy {
+        Some(PlacementPolicy::Secondary) => {
+            let Some(detach_after) = detach_after else {
+                return DetachDecision::Wait;
+            };
+            let hibernated_for = (now - hibernated_at).to_std().unwrap_or_default();
+            if hibernated_for < detach_after {
+                DetachDecision::Wait
+            } else {
+                DetachDecision::Detach
+            }
+        }
+        Some(PlacementPolicy::Detached) => DetachDecision::Wait,
+        Some(PlacementPolicy::Attached(_)) | None => DetachDecision::Forget,
+    }
+}
+
+impl Service {
+    /// Called with each utilization report from a pageserver
+    pub(super) fn record_node_activity(
+        &self,
+        node_id: NodeId,
+        active_shards: Option<&[TenantShardId]>,
+    ) {
+        self.tenant_activity
+            .lock()
+            .unwrap()
+            .record_node(node_id, active_shards, Instant::now());
+    }
+
+    /// Called when something other than a pageserver tells us a tenant is in use
+    pub(super) fn record_tenant_activity(&self, tenant_id: TenantId) {
+        self.tenant_activity
+            .lock()
+            .unwrap()
+            .record(tenant_id, Instant::now());
+    }
+
+    /// Background task: periodically hibernate idle tenants, if configured to.
+    pub(super) async fn hibernate_idle_tenants(self: &Arc<Self>) {
+        let Some(hibernate_after) = self.config.hibernate_after else {
+            return;
+        };
+
+        let mut interval = tokio::time::interval(HIBERNATE_PERIOD);
+        while !self.cancel.is_cancelled() {
+            tokio::select! {
+              _ = interval.tick() => { }
+              _ = self.cancel.cancelled() => return
+            };
+
+            if self.get_leadership_status() != LeadershipStatus::Leader {
+                continue;
+            }
+
+            let hibernated = self.hibernate_pass(hibernate_after).await;
+            let detached = match self.hibernate_detach_pass().await {
+                Ok(detached) => detached,
+                Err(e) => {
+                    tracing::warn!("Failed to detach hibernated tenants: {e}");
+                    0
+                }
+            };
+            if hibernated > 0 || detached > 0 {
+                tracing::info!("Hibernated {hibernated} tenants, detached {detached}");
+            }
+        }
+    }
+
+    /// Move tenants that have been idle for `hibernate_after` to secondary locations.  Returns
+    /// how many were hibernated.
+    async fn hibernate_pass(&self, hibernate_after: Duration) -> usize {
+        let candidates = {
+            let locked = self.inner.read().unwrap();
+            let mut activity = self.tenant_activity.lock().unwrap();
+            activity.retain(|tenant_id| {
+                locked
+                    .tenants
+                    .range(TenantShardId::tenant_range(*tenant_id))
+                    .next()
+                    .is_some()
+            });
+
+            let now = Instant::now();
+            let mut candidates = Vec::new();
+            for (tenant_id, shards) in &locked
+                .tenants
+                .values()
+                .group_by(|s| s.tenant_shard_id.tenant_id)
+            {
+                if candidates.len() >= MAX_HIBERNATIONS_PER_PASS {
+                    break;
+                }
+
+                if activity.idle_for(&tenant_id, now) >= hibernate_after
+                    && hibernate_eligible(shards, &activity)
+                {
+                    candidates.push(tenant_id);
+                }
+            }
+            candidates
+        };
+
+        let mut hibernated = 0;
+        for tenant_id in candidates {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
+            match self.tenant_hibernate(tenant_id, hibernate_after).await {
+                Ok(true) => hibernated += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!(%tenant_id, "Failed to hibernate tenant: {e}");
+                }
+            }
+        }
+
+        hibernated
+    }
+
+    /// Returns false if the tenant changed while we were waiting for its lock, such that it is
+    /// no longer eligible for hibernation: e.g. it was woken, or saw compute activity.
+    async fn tenant_hibernate(
+        &self,
+        tenant_id: TenantId,
+        hibernate_after: Duration,
+    ) -> Result<bool, ApiError> {
+        let _tenant_lock = trace_exclusive_lock(
+            &self.tenant_op_locks,
+            tenant_id,
+            TenantOperations::Hibernate,
+        )
+        .await;
+
+        let (policy, idle_for) = {
+            let locked = self.inner.read().unwrap();
+            let activity = self.tenant_activity.lock().unwrap();
+            let idle_for = activity.idle_for(&tenant_id, Instant::now());
+            let mut shards = locked
+                .tenants
+                .range(TenantShardId::tenant_range(tenant_id))
+                .map(|(_, shard)| shard)
+                .peekable();
+            let Some(policy) = shards.peek().map(|shard| shard.policy.clone()) else {
+                return Ok(false);
+            };
+            if idle_for < hibernate_after || !hibernate_eligible(shards, &activity) {
+                return Ok(false);
+            }
+            (policy, idle_for)
+        };
+
+        // Record what to restore before changing anything, so that a wake can always find it
+        self.persistence
+            .insert_hibernated_tenant(HibernatedTenantPersistence {
+                tenant_id: tenant_id.to_string(),
+                placement_policy: serde_json::to_string(&policy).unwrap(),
+                hibernated_at: chrono::Utc::now(),
+            })
+            .await?;
+
+        self.tenant_set_placement(tenant_id, PlacementPolicy::Secondary)
+            .await?;
+
+        tracing::info!(%tenant_id, "Hibernated tenant after {}s idle", idle_for.as_secs());
+        self.history.record(
+            HistoryEvent::new(
+                OperationEventKind::Schedule,
+                format!(
+                    "Hibernated: idle for {}",
+                    humantime::format_duration(Duration::from_secs(idle_for.as_secs()))
+                ),
+            )
+            .tenant(tenant_id),
+        );
+        metrics::METRICS_REGISTRY
+            .metrics_group
+            .storage_controller_tenant_hibernate
+            .inc(TenantHibernateLabelGroup {
+                placement: HibernatePlacement::Secondary,
+            });
+
+        Ok(true)
+    }
+
+    /// Detach tenants that have been hibernated for `hibernate_detach_after`, and clean up
+    /// hibernation records for tenants that were deleted or had their policy changed by an
+    /// operator.  Returns how many tenants were detached.
+    async fn hibernate_detach_pass(&self) -> Result<usize, ApiError> {
+        let hibernated = self.persistence.list_hibernated_tenants(None).await?;
+
+        let mut detached = 0;
+        for row in hibernated {
+            if self.cancel.is_cancelled() || detached >= MAX_HIBERNATIONS_PER_PASS {
+                break;
+            }
+
+            let tenant_id = match row.tenant_id.parse::<TenantId>() {
+                Ok(tenant_id) => tenant_id,
+                Err(e) => {
+                    tracing::warn!("Bad tenant ID {} in hibernated tenants: {e}", row.tenant_id);
+                    continue;
+                }
+            };
+
+            let decision = hibernate_detach_decision(
+                self.tenant_policy(tenant_id).as_ref(),
+                Some(row.hibernated_at),
+                chrono::Utc::now(),
+                self.config.hibernate_detach_after,
+            );
+            if decision == DetachDecision::Wait {
+                continue;
+            }
+
+            // The tenant may have been woken (and perhaps hibernated again) since we listed it:
+            // decide again with its lock held, so that we can't detach a tenant that is in use,
+            // or lose the hibernation record of one that is being hibernated.
+            let _tenant_lock = trace_exclusive_lock(
+                &self.tenant_op_locks,
+                tenant_id,
+                TenantOperations::Hibernate,
+            )
+            .await;
+            let hibernated_at = self
+                .persistence
+                .list_hibernated_tenants(Some(tenant_id))
+                .await?
+                .pop()
+                .map(|row| row.hibernated_at);
+            let decision = hibernate_detach_decision(
+                self.tenant_policy(tenant_id).as_ref(),
+                hibernated_at,
+                chrono::Utc::now(),
+                self.config.hibernate_detach_after,
+            );
+
+            match decision {
+                DetachDecision::Detach => {
+                    self.tenant_set_placement(tenant_id, PlacementPolicy::Detached)
+                        .await?;
+
+                    tracing::info!(%tenant_id, "Detached hibernated tenant");
+                    self.history.record(
+                        HistoryEvent::new(OperationEventKind::Schedule, "Hibernated: detached")
+                            .tenant(tenant_id),
+                    );
+                    metrics::METRICS_REGISTRY
+                        .metrics_group
+                        .storage_controller_tenant_hibernate
+                        .inc(TenantHibernateLabelGroup {
+                            placement: HibernatePlacement::Detached,
+                        });
+                    detached += 1;
+                }
+                DetachDecision::Wait => {}
+                DetachDecision::Forget => {
+                    self.persistence.delete_hibernated_tenant(tenant_id).await?;
+                }
+            }
+        }
+
+        Ok(detached)
+    }
+
+    /// The placement policy of a tenant's shards, or `None` if it doesn't exist
+    fn tenant_policy(&self, tenant_id: TenantId) -> Option<PlacementPolicy> {
+        let locked = self.inner.read().unwrap();
+        locked
+            .tenants
+            .range(TenantShardId::tenant_range(tenant_id))
+            .next()
+            .map(|(_, shard)| shard.policy.clone())
+    }
+
+    /// Re-attach a hibernated tenant, if it is hibernated, and return where its shards are
+    /// attached.  Computes call this before connecting to a tenant that may be hibernated.
+    pub(crate) async fn tenant_wake(
+        &self,
+        tenant_id: TenantId,
+    ) -> Result<TenantLocateResponse, ApiError> {
+        self.record_tenant_activity(tenant_id);
+
+        let started_at = Instant::now();
+        let waiters = {
+            let _tenant_lock =
+                trace_exclusive_lock(&self.tenant_op_locks, tenant_id, TenantOperations::Wake)
+                    .await;
+
+            let Some(row) = self
+                .persistence
+                .list_hibernated_tenants(Some(tenant_id))
+                .await?
+                .pop()
+            else {
+                // Not hibernated: nothing to do
+                return self.tenant_locate(tenant_id);
+            };
+
+            let policy: PlacementPolicy = serde_json::from_str(&row.placement_policy)
+                .map_err(|e| ApiError::InternalServerError(anyhow::anyhow!(e)))?;
+
+            let waiters = self.tenant_set_placement(tenant_id, policy).await?;
+            self.persistence.delete_hibernated_tenant(tenant_id).await?;
+            waiters
+        };
+
+        self.await_waiters(waiters, RECONCILE_TIMEOUT).await?;
+
+        let latency = started_at.elapsed();
+        tracing::info!(%tenant_id, "Woke tenant in {}ms", latency.as_millis());
+        self.history.record(
+            HistoryEvent::new(
+                OperationEventKind::Schedule,
+                format!("Woken in {}ms", latency.as_millis()),
+            )
+            .tenant(tenant_id),
+        );
+        let metrics_group = &metrics::METRICS_REGISTRY.metrics_group;
+        metrics_group.storage_controller_tenant_wake.inc();
+        metrics_group
+            .storage_controller_tenant_wake_latency
+            .observe(latency.as_secs_f64());
+
+        self.tenant_locate(tenant_id)
+    }
+
+    /// Persist and apply a new placement policy for all of a tenant's shards.  The caller must
+    /// hold the tenant's exclusive lock.
+    async fn tenant_set_placement(
+        &self,
+        tenant_id: TenantId,
+        placement: PlacementPolicy,
+    ) -> Result<Vec<ReconcilerWaiter>, ApiError> {
+        self.persistence
+            .update_tenant_shard(
+                TenantFilter::Tenant(tenant_id),
+                Some(placement.clone()),
+                None,
+                None,
+                None,
+            )
+            .await?;
+
+        let mut waiters = Vec::new();
+        let mut schedule_context = ScheduleContext::default();
+        let mut locked = self.inner.write().unwrap();
+        let (nodes, tenants, scheduler) = locked.parts_mut();
+        for (_, shard) in tenants.range_mut(TenantShardId::tenant_range(tenant_id)) {
+            shard.policy = placement.clone();
+            if let Err(e) = shard.schedule(scheduler, &mut schedule_context) {
+                tracing::warn!(tenant_id=%shard.tenant_shard_id.tenant_id,
+                               shard_id=%shard.tenant_shard_id.shard_slug(),
+                               "Failed to schedule with policy {placement:?}: {e}");
+            }
+            if let Some(waiter) = self.maybe_reconcile_shard(shard, nodes) {
+                waiters.push(waiter);
+            }
+        }
+
+        Ok(waiters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenant_activity_idle() {
+        let start = Instant::now();
+        let mut activity = TenantActivity::new(start);
+
+        let node_id = NodeId(1);
+        let busy = TenantShardId::unsharded(TenantId::generate());
+        let quiet = TenantId::generate();
+
+        // Unknown nodes are not trusted to report activity
+        assert!(!activity.is_reporting(&node_id));
+
+        let later = start + Duration::from_secs(100);
+        activity.record_node(node_id, Some(&[busy]), later);
+        assert!(activity.is_reporting(&node_id));
+
+        // A tenant we've never seen active is idle since we started watching
+        let now = start + Duration::from_secs(150);
+        assert_eq!(activity.idle_for(&quiet, now), Duration::from_secs(150));
+        assert_eq!(
+            activity.idle_for(&busy.tenant_id, now),
+            Duration::from_secs(50)
+        );
+
+        // A node that stops reporting activity (e.g. is downgraded) is no longer trusted
+        activity.record_node(node_id, None, now);
+        assert!(!activity.is_reporting(&node_id));
+
+        activity.retain(|tenant_id| *tenant_id != busy.tenant_id);
+        assert_eq!(
+            activity.idle_for(&busy.tenant_id, now),
+            Duration::from_secs(150)
+        );
+    }
+
+    #[test]
+    fn hibernate_wake_detach() {
+        let detach_after = Some(Duration::from_secs(3600));
+        let hibernated_at = chrono::Utc::now();
+        let long_after = hibernated_at + chrono::Duration::hours(2);
+
+        // Hibernated, not yet for long enough
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Secondary),
+                Some(hibernated_at),
+                hibernated_at + chrono::Duration::minutes(10),
+                detach_after
+            ),
+            DetachDecision::Wait
+        );
+
+        // Hibernated for long enough: the detach pass lists the tenant and decides to detach it
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Secondary),
+                Some(hibernated_at),
+                long_after,
+                detach_after
+            ),
+            DetachDecision::Detach
+        );
+
+        // ...but before it gets the tenant's lock, the tenant is woken: deciding again under the
+        // lock leaves it alone, and the wake has already removed the record.
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Attached(1)),
+                None,
+                long_after,
+                detach_after
+            ),
+            DetachDecision::Wait
+        );
+
+        // ...or woken and hibernated again since: it gets a whole period before being detached
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Secondary),
+                Some(long_after),
+                long_after,
+                detach_after
+            ),
+            DetachDecision::Wait
+        );
+
+        // A record left behind by a wake, or by deleting the tenant, is removed
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Attached(1)),
+                Some(hibernated_at),
+                long_after,
+                detach_after
+            ),
+            DetachDecision::Forget
+        );
+        assert_eq!(
+            hibernate_detach_decision(None, Some(hibernated_at), long_after, detach_after),
+            DetachDecision::Forget
+        );
+
+        // Without detach_after, hibernated tenants stay secondary
+        assert_eq!(
+            hibernate_detach_decision(
+                Some(&PlacementPolicy::Secondary),
+                Some(hibernated_at),
+                long_after,
+                None
+            ),
+            DetachDecision::Wait
+        );
+    }
+}
@@ -0,0 +1,706 @@
+//! Tenant imports create a new tenant from a vanilla Postgres basebackup: the controller creates
+//! the tenant, then streams the basebackup (and optionally WAL) to the pageserver of each shard
+//! in turn.  Every shard is sent the whole archive, and keeps the pages it owns.
+//!
+//! Progress is persisted after every step, so that an import survives a restart of the storage
+//! controller or a change of leader.  Archives that were uploaded to the controller are kept on
+//! the leader's local disk: if another controller takes over, they must be uploaded again.
+
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::StreamExt;
+use hyper::body::HttpBody;
+use pageserver_api::{
+    controller_api::{
+        ImportShardStatus, ImportShardStep, ImportSource, TenantCreateRequest, TenantImportRequest,
+        TenantImportState, TenantImportStatus,
+    },
+    models::TenantConfig,
+    shard::{ShardNumber, TenantShardId},
+};
+use pageserver_client::mgmt_api::{self, ReqwestBody};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use utils::{http::error::ApiError, id::TenantId};
+
+use crate::{
+    id_lock_map::trace_shared_lock,
+    pageserver_client::PageserverClient,
+    persistence::{DatabaseError, TenantImportPersistence},
+};
+
+use super::{LeadershipStatus, Service, TenantOperations};
+
+/// How many times, and how often, to retry a step against a shard that is not attached
+const UNAVAILABLE_RETRY_LIMIT: usize = 30;
+const UNAVAILABLE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The archives that make up an import
+#[derive(Clone, Copy, Eq, PartialEq, Debug, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ImportArchive {
+    Basebackup,
+    Wal,
+}
+
+impl FromStr for ImportArchive {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "basebackup" => Ok(Self::Basebackup),
+            "wal" => Ok(Self::Wal),
+            _ => Err(anyhow::anyhow!("Unknown import archive '{s}'")),
+        }
+    }
+}
+
+impl ImportArchive {
+    fn source(self, request: &TenantImportRequest) -> Option<&ImportSource> {
+        match self {
+            Self::Basebackup => Some(&request.basebackup),
+            Self::Wal => request.wal.as_ref(),
+        }
+    }
+}
+
+enum ImportError {
+    /// Step down or shutdown: the next leader picks the import up where we left it
+    Cancelled,
+    /// An uploaded archive is not on our local disk
+    AwaitingUpload(ImportArchive),
+    /// A shard is not attached yet, e.g. just after the tenant was created: retry the step shortly
+    Unavailable(String),
+    /// A step failed.  The import stops, and resuming it retries the step.
+    Failed(anyhow::Error),
+}
+
+impl From<ApiError> for ImportError {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::ShuttingDown => ImportError::Cancelled,
+            ApiError::ResourceUnavailable(msg) => ImportError::Unavailable(msg.into_owned()),
+            err => ImportError::Failed(anyhow::anyhow!("{err}")),
+        }
+    }
+}
+
+impl From<DatabaseError> for ImportError {
+    fn from(err: DatabaseError) -> Self {
+        ImportError::Failed(anyhow::anyhow!("{err}"))
+    }
+}
+
+impl From<mgmt_api::Error> for ImportError {
+    fn from(err: mgmt_api::Error) -> Self {
+        match err {
+            mgmt_api::Error::Cancelled => ImportError::Cancelled,
+            err => ImportError::Failed(anyhow::anyhow!("{err}")),
+        }
+    }
+}
+
+impl Service {
+    pub(crate) async fn tenant_import_create(
+        self: &Arc<Self>,
+        request: TenantImportRequest,
+    ) -> Result<TenantImportStatus, ApiError> {
+        self.tenant_import_check_leader()?;
+
+        if request.end_lsn < request.base_lsn {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "end_lsn {} is before base_lsn {}",
+                request.end_lsn,
+                request.base_lsn
+            )));
+        }
+        if (request.end_lsn > request.base_lsn) != request.wal.is_some() {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "WAL must be provided if and only if end_lsn is after base_lsn"
+            )));
+        }
+        let uploads = std::iter::once(&request.basebackup)
+            .chain(request.wal.as_ref())
+            .any(|source| *source == ImportSource::Upload);
+        if uploads && self.config.import_dir.is_none() {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "Uploads are not possible: the storage controller has no import directory"
+            )));
+        }
+
+        {
+            let locked = self.inner.read().unwrap();
+            if locked
+                .tenants
+                .range(TenantShardId::tenant_range(request.tenant_id))
+                .next()
+                .is_some()
+            {
+                return Err(ApiError::Conflict(format!(
+                    "Tenant {} already exists: an import creates a new tenant",
+                    request.tenant_id
+                )));
+            }
+        }
+
+        let now = chrono::Utc::now();
+        let mut status = TenantImportStatus {
+            shards: (0..request.shard_parameters.count.count())
+                .map(|n| ImportShardStatus {
+                    shard_number: ShardNumber(n),
+                    step: ImportShardStep::Pending,
+                    bytes_sent: 0,
+                })
+                .collect(),
+            request,
+            state: TenantImportState::Running,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        if self.tenant_import_missing_upload(&status.request).is_some() {
+            status.state = TenantImportState::AwaitingUpload;
+        }
+
+        self.persistence
+            .insert_tenant_import(TenantImportPersistence::from_status(&status))
+            .await
+            .map_err(|err| match err {
+                DatabaseError::Logical(msg) => ApiError::Conflict(msg),
+                err => err.into(),
+            })?;
+
+        tracing::info!(
+            tenant_id=%status.request.tenant_id,
+            "Created tenant import ({:?})",
+            status.state
+        );
+        if status.state == TenantImportState::Running {
+            self.spawn_tenant_import(status.clone())?;
+        }
+
+        Ok(status)
+    }
+
+    /// All tenant imports, most recent first
+    pub(crate) async fn tenant_import_list(&self) -> Result<Vec<TenantImportStatus>, ApiError> {
+        self.persistence
+            .list_tenant_imports()
+            .await?
+            .into_iter()
+            .map(|import| import.into_status().map_err(ApiError::InternalServerError))
+            .collect()
+    }
+
+    pub(crate) async fn tenant_import_get(
+        &self,
+        tenant_id: TenantId,
+    ) -> Result<TenantImportStatus, ApiError> {
+        self.tenant_import_list()
+            .await?
+            .into_iter()
+            .find(|import| import.request.tenant_id == tenant_id)
+            .ok_or_else(|| {
+                ApiError::NotFound(anyhow::anyhow!("Tenant {tenant_id} has no import").into())
+            })
+    }
+
+    /// Store an archive for an import whose source for it is [`ImportSource::Upload`].  Once all
+    /// its archives are here, the import starts.
+    pub(crate) async fn tenant_import_upload(
+        self: &Arc<Self>,
+        tenant_id: TenantId,
+        archive: ImportArchive,
+        mut body: hyper::Body,
+    ) -> Result<TenantImportStatus, ApiError> {
+        self.tenant_import_check_leader()?;
+
+        let mut status = self.tenant_import_get(tenant_id).await?;
+        if archive.source(&status.request) != Some(&ImportSource::Upload) {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "The {archive} archive of this import is not uploaded"
+            )));
+        }
+        if status.state == TenantImportState::Complete {
+            return Err(ApiError::Conflict(format!(
+                "Import of tenant {tenant_id} is complete"
+            )));
+        }
+
+        let path = self
+            .tenant_import_upload_path(tenant_id, archive)
+            .ok_or_else(|| {
+                ApiError::BadRequest(anyhow::anyhow!(
+                    "The storage controller has no import directory"
+                ))
+            })?;
+        let tmp_path = path.with_extension("tar.tmp");
+        tokio::fs::create_dir_all(path.parent().expect("upload paths are in a directory"))
+            .await
+            .map_err(|e| ApiError::InternalServerError(e.into()))?;
+
+        // Write to a temporary file, so that we never import a partial upload
+        let mut bytes = 0;
+        {
+            let mut file = tokio::fs::File::create(&tmp_path)
+                .await
+                .map_err(|e| ApiError::InternalServerError(e.into()))?;
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(|e| ApiError::BadRequest(e.into()))?;
+                bytes += chunk.len();
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| ApiError::InternalServerError(e.into()))?;
+            }
+            file.sync_all()
+                .await
+                .map_err(|e| ApiError::InternalServerError(e.into()))?;
+        }
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| ApiError::InternalServerError(e.into()))?;
+        tracing::info!(%tenant_id, "Received {bytes} byte {archive} archive for import");
+
+        if status.state == TenantImportState::AwaitingUpload
+            && self.tenant_import_missing_upload(&status.request).is_none()
+        {
+            status.state = TenantImportState::Running;
+            status.error = None;
+            self.persist_tenant_import(&mut status).await?;
+            self.spawn_tenant_import(status.clone())?;
+        }
+
+        Ok(status)
+    }
+
+    /// Retry a failed import from the step that failed
+    pub(crate) async fn tenant_import_resume(
+        self: &Arc<Self>,
+        tenant_id: TenantId,
+    ) -> Result<TenantImportStatus, ApiError> {
+        self.tenant_import_check_leader()?;
+
+        let mut status = self.tenant_import_get(tenant_id).await?;
+        match status.state {
+            TenantImportState::Failed | TenantImportState::AwaitingUpload => {}
+            state => {
+                return Err(ApiError::Conflict(format!(
+                    "Import of tenant {tenant_id} is {}",
+                    String::from(state)
+                )))
+            }
+        }
+
+        if let Some(archive) = self.tenant_import_missing_upload(&status.request) {
+            return Err(ApiError::Conflict(format!(
+                "Import of tenant {tenant_id} is waiting for its {archive} archive to be uploaded"
+            )));
+        }
+
+        status.state = TenantImportState::Running;
+        status.error = None;
+        self.persist_tenant_import(&mut status).await?;
+
+        tracing::info!(%tenant_id, "Resuming tenant import");
+        self.spawn_tenant_import(status.clone())?;
+
+        Ok(status)
+    }
+
+    /// Called once on startup: if we are the leader, pick up any imports that were running
+    /// under a previous leader.
+    pub(super) async fn resume_tenant_imports_on_startup(self: &Arc<Self>) {
+        if self.get_leadership_status() != LeadershipStatus::Leader {
+            return;
+        }
+
+        let imports = match self.tenant_import_list().await {
+            Ok(imports) => imports,
+            Err(err) => {
+                tracing::error!("Failed to load tenant imports: {err}");
+                return;
+            }
+        };
+
+        for status in imports
+            .into_iter()
+            .filter(|import| import.state == TenantImportState::Running)
+        {
+            tracing::info!(tenant_id=%status.request.tenant_id, "Resuming tenant import after startup");
+            if let Err(err) = self.spawn_tenant_import(status) {
+                tracing::error!("Failed to resume tenant import: {err}");
+            }
+        }
+    }
+
+    async fn persist_tenant_import(
+        &self,
+        status: &mut TenantImportStatus,
+    ) -> Result<(), DatabaseError> {
+        status.updated_at = chrono::Utc::now();
+        self.persistence
+            .update_tenant_import(TenantImportPersistence::from_status(status))
+            .await
+    }
+
+    fn tenant_import_check_leader(&self) -> Result<(), ApiError> {
+        match self.get_leadership_status() {
+            LeadershipStatus::Leader => Ok(()),
+            status => Err(ApiError::ResourceUnavailable(
+                format!("Tenant imports are only driven by the leader (we are {status})").into(),
+            )),
+        }
+    }
+
+    fn tenant_import_upload_path(
+        &self,
+        tenant_id: TenantId,
+        archive: ImportArchive,
+    ) -> Option<PathBuf> {
+        self.config.import_dir.as_ref().map(|dir| {
+            dir.join(tenant_id.to_string())
+                .join(format!("{archive}.tar"))
+        })
+    }
+
+    /// The first archive that should be uploaded to us, but has not been
+    fn tenant_import_missing_upload(&self, request: &TenantImportRequest) -> Option<ImportArchive> {
+        [ImportArchive::Basebackup, ImportArchive::Wal]
+            .into_iter()
+            .find(|archive| {
+                archive.source(request) == Some(&ImportSource::Upload)
+                    && !self
+                        .tenant_import_upload_path(request.tenant_id, *archive)
+                        .is_some_and(|path| path.exists())
+            })
+    }
+
+    fn spawn_tenant_import(self: &Arc<Self>, status: TenantImportStatus) -> Result<(), ApiError> {
+        let gate_guard = self.gate.enter().map_err(|_| ApiError::ShuttingDown)?;
+        let tenant_id = status.request.tenant_id;
+
+        let cancel = self.cancel.child_token();
+        {
+            let mut locked = self.inner.write().unwrap();
+            if locked.tenant_imports.contains_key(&tenant_id) {
+                return Err(ApiError::Conflict(format!(
+                    "Import of tenant {tenant_id} is already running"
+                )));
+            }
+            locked.tenant_imports.insert(tenant_id, cancel.clone());
+        }
+
+        let span = tracing::info_span!(parent: None, "tenant_import", %tenant_id);
+
+        tokio::task::spawn({
+            let service = self.clone();
+            async move {
+                let _gate_guard = gate_guard;
+
+                scopeguard::defer! {
+                    service.inner.write().unwrap().tenant_imports.remove(&tenant_id);
+                }
+
+                service.drive_tenant_import(status, &cancel).await;
+            }
+            .instrument(span)
+        });
+
+        Ok(())
+    }
+
+    async fn drive_tenant_import(
+        self: &Arc<Self>,
+        mut status: TenantImportStatus,
+        cancel: &CancellationToken,
+    ) {
+        match self.tenant_import_steps(&mut status, cancel).await {
+            Ok(()) => {
+                tracing::info!("Tenant import complete");
+                status.state = TenantImportState::Complete;
+                if let Some(dir) = self
+                    .config
+                    .import_dir
+                    .as_ref()
+                    .map(|dir| dir.join(status.request.tenant_id.to_string()))
+                {
+                    if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            tracing::warn!("Failed to remove uploaded archives: {e}");
+                        }
+                    }
+                }
+            }
+            Err(ImportError::AwaitingUpload(archive)) => {
+                tracing::info!("Tenant import waiting for its {archive} archive to be uploaded");
+                status.state = TenantImportState::AwaitingUpload;
+            }
+            Err(ImportError::Unavailable(msg)) => {
+                tracing::error!("Tenant import failed: {msg}");
+                status.state = TenantImportState::Failed;
+                status.error = Some(msg);
+            }
+            Err(ImportError::Failed(err)) => {
+                tracing::error!("Tenant import failed: {err:#}");
+                status.state = TenantImportState::Failed;
+                status.error = Some(format!("{err:#}"));
+            }
+            Err(ImportError::Cancelled) => {
+                // Leave the persistent state as it is for the next leader to pick up
+                tracing::info!("Tenant import interrupted by step down or shutdown");
+                return;
+            }
+        }
+
+        if let Err(err) = self.persist_tenant_import(&mut status).await {
+            tracing::error!("Failed to persist tenant import state: {err}");
+        }
+    }
+
+    async fn tenant_import_steps(
+        self: &Arc<Self>,
+        status: &mut TenantImportStatus,
+        cancel: &CancellationToken,
+    ) -> Result<(), ImportError> {
+        self.tenant_import_checkpoint(cancel)?;
+        self.tenant_import_ensure_tenant(&status.request).await?;
+
+        for idx in 0..status.shards.len() {
+            let mut unavailable_retries = 0;
+            while status.shards[idx].step != ImportShardStep::Done {
+                self.tenant_import_checkpoint(cancel)?;
+
+                let bytes_sent = Arc::new(AtomicU64::new(0));
+                let result = self
+                    .tenant_import_shard_step(
+                        &status.request,
+                        status.shards[idx].shard_number,
+                        bytes_sent.clone(),
+                    )
+                    .await;
+                status.shards[idx].bytes_sent += bytes_sent.load(Ordering::Relaxed);
+                status.shards[idx].step = match result {
+                    Err(ImportError::Unavailable(msg))
+                        if unavailable_retries < UNAVAILABLE_RETRY_LIMIT =>
+                    {
+                        unavailable_retries += 1;
+                        tracing::info!("Shard unavailable, retrying: {msg}");
+                        tokio::select! {
+                            _ = tokio::time::sleep(UNAVAILABLE_RETRY_INTERVAL) => {},
+                            _ = cancel.cancelled() => return Err(ImportError::Cancelled),
+                        }
+                        continue;
+                    }
+                    result => result?,
+                };
+
+                tracing::info!(
+                    shard_number=%status.shards[idx].shard_number.0,
+                    "Tenant import step {:?}",
+                    status.shards[idx].step
+                );
+                self.persist_tenant_import(status).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checked between steps: is there any reason to stop here?
+    fn tenant_import_checkpoint(&self, cancel: &CancellationToken) -> Result<(), ImportError> {
+        if cancel.is_cancelled() || self.get_leadership_status() != LeadershipStatus::Leader {
+            Err(ImportError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Create the tenant, unless a previous attempt at the import already did
+    async fn tenant_import_ensure_tenant(
+        &self,
+        request: &TenantImportRequest,
+    ) -> Result<(), ImportError> {
+        let exists = {
+            let locked = self.inner.read().unwrap();
+            locked
+                .tenants
+                .range(TenantShardId::tenant_range(request.tenant_id))
+                .next()
+                .is_some()
+        };
+        if exists {
+            return Ok(());
+        }
+
+        tracing::info!("Creating tenant for import");
+        self.tenant_create(TenantCreateRequest {
+            new_tenant_id: TenantShardId::unsharded(request.tenant_id),
+            generation: None,
+            shard_parameters: request.shard_parameters.clone(),
+            placement_policy: request.placement_policy.clone(),
+            config: TenantConfig::default(),
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Do the next step of importing into one shard, returning the step it is at afterwards.
+    ///
+    /// Rather than trusting our own record of what is done, check the pageserver: a step may have
+    /// completed without us persisting that it did.
+    async fn tenant_import_shard_step(
+        &self,
+        request: &TenantImportRequest,
+        shard_number: ShardNumber,
+        bytes_sent: Arc<AtomicU64>,
+    ) -> Result<ImportShardStep, ImportError> {
+        let tenant_id = request.tenant_id;
+        let tenant_shard_id = TenantShardId {
+            tenant_id,
+            shard_number,
+            shard_count: request.shard_parameters.count,
+        };
+
+        // Exclude shard splits and tenant deletion while we write to the shard
+        let _tenant_lock =
+            trace_shared_lock(&self.tenant_op_locks, tenant_id, TenantOperations::Import).await;
+
+        let jwt = self.config.jwt_token.clone();
+        let import_dir = self.config.import_dir.clone();
+        self.tenant_remote_mutation(tenant_id, move |targets| async move {
+            let Some((_, node)) = targets.into_iter().find(|(id, _)| *id == tenant_shard_id) else {
+                return Err(ImportError::Failed(anyhow::anyhow!(
+                    "Shard {tenant_shard_id} not found: was the tenant split or deleted?"
+                )));
+            };
+            let client = PageserverClient::new(node.get_id(), node.base_url(), jwt.as_deref());
+
+            let timeline = client
+                .timeline_list(&tenant_shard_id)
+                .await?
+                .into_iter()
+                .find(|timeline| timeline.timeline_id == request.timeline_id);
+            let has_wal = request.end_lsn > request.base_lsn;
+
+            match timeline {
+                None => {
+                    tracing::info!("Importing basebackup into {tenant_shard_id} on {node}");
+                    let body = open_import_source(
+                        import_dir.as_ref(),
+                        request,
+                        ImportArchive::Basebackup,
+                        bytes_sent,
+                    )
+                    .await?;
+                    client
+                        .import_basebackup(
+                            tenant_shard_id,
+                            request.timeline_id,
+                            request.base_lsn,
+                            request.end_lsn,
+                            request.pg_version,
+                            request.image_layers && !has_wal,
+                            body,
+                        )
+                        .await?;
+                    Ok(if has_wal {
+                        ImportShardStep::BasebackupImported
+                    } else {
+                        ImportShardStep::Done
+                    })
+                }
+                Some(timeline) if timeline.last_record_lsn < request.end_lsn => {
+                    tracing::info!("Importing WAL into {tenant_shard_id} on {node}");
+                    let body = open_import_source(
+                        import_dir.as_ref(),
+                        request,
+                        ImportArchive::Wal,
+                        bytes_sent,
+                    )
+                    .await?;
+                    client
+                        .import_wal(
+                            tenant_shard_id,
+                            request.timeline_id,
+                            request.base_lsn,
+                            request.end_lsn,
+                            request.image_layers,
+                            body,
+                        )
+                        .await?;
+                    Ok(ImportShardStep::Done)
+                }
+                Some(_) => Ok(ImportShardStep::Done),
+            }
+        })
+        .await?
+    }
+}
+
+/// Open an archive of an import as a request body, counting the bytes that are read from it
+async fn open_import_source(
+    import_dir: Option<&PathBuf>,
+    request: &TenantImportRequest,
+    archive: ImportArchive,
+    bytes_sent: Arc<AtomicU64>,
+) -> Result<ReqwestBody, ImportError> {
+    let count = move |chunk: &bytes::Bytes| {
+        bytes_sent.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    };
+
+    let path = match archive.source(request) {
+        None => {
+            return Err(ImportError::Failed(anyhow::anyhow!(
+                "Import has no {archive} archive"
+            )))
+        }
+        Some(ImportSource::Url { url }) => {
+            let response = reqwest::get(url)
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| {
+                    ImportError::Failed(anyhow::anyhow!("Fetching {archive} archive: {e}"))
+                })?;
+            let stream = response
+                .bytes_stream()
+                .inspect(move |chunk| chunk.iter().for_each(&count));
+            return Ok(ReqwestBody::wrap_stream(stream));
+        }
+        Some(ImportSource::LocalPath { path }) => PathBuf::from(path),
+        Some(ImportSource::Upload) => match import_dir {
+            Some(dir) => dir
+                .join(request.tenant_id.to_string())
+                .join(format!("{archive}.tar")),
+            None => return Err(ImportError::AwaitingUpload(archive)),
+        },
+    };
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e)
+            if e.kind() == std::io::ErrorKind::NotFound
+                && archive.source(request) == Some(&ImportSource::Upload) =>
+        {
+            return Err(ImportError::AwaitingUpload(archive));
+        }
+        Err(e) => {
+            return Err(ImportError::Failed(anyhow::anyhow!(
+                "Opening {archive} archive {}: {e}",
+                path.display()
+            )))
+        }
+    };
+    let stream =
+        tokio_util::io::ReaderStream::new(file).inspect(move |chunk| chunk.iter().for_each(&count));
+    Ok(ReqwestBody::wrap_stream(stream))
+}
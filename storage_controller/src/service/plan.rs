@@ -0,0 +1,515 @@
+//! Dry-run planning: work out what the storage controller would do in response to a hypothetical
+//! change, such as draining a node, adding a node or changing a tenant's placement policy.
+//!
+//! The real scheduling code is run against copies of the nodes, shards and [`Scheduler`], which
+//! are then compared with the originals.  Nothing is persisted, reconciled or recorded in the
+//! operation history.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use pageserver_api::{
+    controller_api::{
+        NodeAvailability, NodeSchedulingPolicy, PlacementPolicy, PlanRequest, PlanResponse,
+        PlannedLocation, PlannedMigration, PlannedNodeChange, PlannedShardIssue,
+    },
+    shard::TenantShardId,
+};
+use utils::{
+    http::error::ApiError,
+    id::{NodeId, TenantId},
+};
+
+use crate::{
+    drain_utils::TenantShardDrain,
+    node::Node,
+    scheduler::{MaySchedule, ScheduleContext, Scheduler, SchedulerNodeStats},
+    tenant_shard::TenantShard,
+};
+
+use super::{plan_optimizations, Service};
+
+/// Optimizations generated and applied per pass when looking for the state that the cluster
+/// settles into after a change
+const PLAN_OPTIMIZATIONS_PER_PASS: usize = 64;
+
+/// Give up on settling after this many passes: the optimizer may take a long time to run out of
+/// work on a large cluster, and must not be allowed to flap forever.
+const PLAN_MAX_PASSES: usize = 1000;
+
+/// Where a shard's [`crate::tenant_shard::IntentState`] placed it, before the change
+struct Locations {
+    attached: Option<NodeId>,
+    secondary: Vec<NodeId>,
+}
+
+/// Copies of the scheduling state of the cluster, which a hypothetical change is applied to
+struct Plan {
+    nodes: HashMap<NodeId, Node>,
+    tenants: BTreeMap<TenantShardId, TenantShard>,
+    scheduler: Scheduler,
+
+    locations_before: BTreeMap<TenantShardId, Locations>,
+    nodes_before: BTreeMap<NodeId, SchedulerNodeStats>,
+    issues: Vec<PlannedShardIssue>,
+}
+
+impl Plan {
+    fn new(
+        nodes: &HashMap<NodeId, Node>,
+        tenants: &BTreeMap<TenantShardId, TenantShard>,
+        scheduler: &Scheduler,
+    ) -> Self {
+        let scheduler = scheduler.clone_for_planning();
+        let nodes_before = nodes
+            .keys()
+            .filter_map(|node_id| Some((*node_id, scheduler.node_stats(*node_id)?)))
+            .collect();
+
+        Self {
+            nodes: nodes.clone(),
+            tenants: tenants
+                .iter()
+                .map(|(tenant_shard_id, shard)| (*tenant_shard_id, shard.clone_for_planning()))
+                .collect(),
+            scheduler,
+            locations_before: tenants
+                .iter()
+                .map(|(tenant_shard_id, shard)| {
+                    (
+                        *tenant_shard_id,
+                        Locations {
+                            attached: *shard.intent.get_attached(),
+                            secondary: shard.intent.get_secondary().clone(),
+                        },
+                    )
+                })
+                .collect(),
+            nodes_before,
+            issues: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, req: PlanRequest) -> Result<(), ApiError> {
+        match req {
+            PlanRequest::NodeDrain { node_id } => self.drain_node(node_id),
+            PlanRequest::NodeAdd {
+                node_id,
+                availability_zone_id,
+            } => self.add_node(node_id, availability_zone_id),
+            PlanRequest::TenantPolicy {
+                tenant_id,
+                placement,
+            } => self.set_tenant_policy(tenant_id, placement),
+        }
+    }
+
+    /// Like [`Service::drain_node`], except that secondaries are assumed to be warm enough to
+    /// cut over to.
+    fn drain_node(&mut self, node_id: NodeId) -> Result<(), ApiError> {
+        let Some(node) = self.nodes.get_mut(&node_id) else {
+            return Err(ApiError::NotFound(
+                anyhow::anyhow!("Node {node_id} not registered").into(),
+            ));
+        };
+        node.set_scheduling(NodeSchedulingPolicy::Draining);
+        self.scheduler.node_upsert(node);
+
+        let tenant_shard_ids = self
+            .tenants
+            .iter()
+            .filter(|(_, shard)| *shard.intent.get_attached() == Some(node_id))
+            .map(|(tenant_shard_id, _)| *tenant_shard_id)
+            .collect::<Vec<_>>();
+        for tenant_shard_id in tenant_shard_ids {
+            let drain = TenantShardDrain {
+                drained_node: node_id,
+                tenant_shard_id,
+            };
+            let Some(destination) =
+                drain.tenant_shard_eligible_for_drain(&self.tenants, &self.scheduler)
+            else {
+                self.issues.push(PlannedShardIssue {
+                    tenant_shard_id,
+                    reason: format!("No eligible secondary to move to from node {node_id}"),
+                });
+                continue;
+            };
+
+            let shard = self.tenants.get_mut(&tenant_shard_id).unwrap();
+            if let Err(e) = shard.reschedule_to_secondary(Some(destination), &mut self.scheduler) {
+                self.issues.push(PlannedShardIssue {
+                    tenant_shard_id,
+                    reason: format!("Failed to move to secondary on node {destination}: {e}"),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A new node is modelled on the existing nodes in its AZ, if any, or else on any other node:
+    /// it has the same capacity, and no shards.
+    fn add_node(&mut self, node_id: NodeId, availability_zone_id: String) -> Result<(), ApiError> {
+        if self.nodes.contains_key(&node_id) {
+            return Err(ApiError::Conflict(format!(
+                "Node {node_id} is already registered"
+            )));
+        }
+
+        let mut peers = self
+            .nodes
+            .values()
+            .filter_map(|node| match node.may_schedule() {
+                MaySchedule::Yes(utilization) => Some((
+                    node.get_availability_zone_id() == availability_zone_id,
+                    node.get_id(),
+                    utilization,
+                )),
+                MaySchedule::No => None,
+            })
+            .collect::<Vec<_>>();
+        // Same AZ first, node ID for determinism
+        peers.sort_by_key(|(same_az, node_id, _)| (!same_az, *node_id));
+        let Some((_, _, utilization)) = peers.into_iter().next() else {
+            return Err(ApiError::PreconditionFailed(
+                "No schedulable nodes to model the new node on".into(),
+            ));
+        };
+
+        let mut node = Node::new(
+            node_id,
+            String::new(),
+            0,
+            String::new(),
+            0,
+            availability_zone_id,
+        );
+        node.set_availability(NodeAvailability::Active(utilization.empty_like()));
+        self.scheduler.node_upsert(&node);
+        self.nodes.insert(node_id, node);
+
+        Ok(())
+    }
+
+    /// Like [`Service::tenant_update_policy`]
+    fn set_tenant_policy(
+        &mut self,
+        tenant_id: TenantId,
+        placement: PlacementPolicy,
+    ) -> Result<(), ApiError> {
+        let mut schedule_context = ScheduleContext::default();
+        let mut found = false;
+        for (tenant_shard_id, shard) in self
+            .tenants
+            .range_mut(TenantShardId::tenant_range(tenant_id))
+        {
+            found = true;
+            shard.policy = placement.clone();
+            if let Err(e) = shard.schedule(&mut self.scheduler, &mut schedule_context) {
+                self.issues.push(PlannedShardIssue {
+                    tenant_shard_id: *tenant_shard_id,
+                    reason: format!("Failed to schedule: {e}"),
+                });
+            }
+        }
+
+        if !found {
+            return Err(ApiError::NotFound(
+                anyhow::anyhow!("Tenant {tenant_id} not found").into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Run the optimizer until it runs out of work.  Returns false if we gave up first.
+    fn settle(&mut self) -> bool {
+        for _ in 0..PLAN_MAX_PASSES {
+            let work = plan_optimizations(
+                &self.nodes,
+                &self.tenants,
+                &mut self.scheduler,
+                PLAN_OPTIMIZATIONS_PER_PASS,
+            );
+            if work.is_empty() {
+                return true;
+            }
+
+            for (tenant_shard_id, optimization) in work {
+                if let Some(shard) = self.tenants.get_mut(&tenant_shard_id) {
+                    shard.apply_optimization(&mut self.scheduler, optimization);
+                }
+            }
+        }
+
+        false
+    }
+
+    fn response(&self, converged: bool) -> PlanResponse {
+        let mut migrations = Vec::new();
+        let mut secondaries_created = Vec::new();
+        let mut locations_removed = Vec::new();
+
+        for (tenant_shard_id, shard) in &self.tenants {
+            let Some(before) = self.locations_before.get(tenant_shard_id) else {
+                continue;
+            };
+            let after = &shard.intent;
+
+            if before.attached != *after.get_attached() {
+                migrations.push(PlannedMigration {
+                    tenant_shard_id: *tenant_shard_id,
+                    from: before.attached,
+                    to: *after.get_attached(),
+                });
+            }
+
+            let locations_before = before
+                .attached
+                .iter()
+                .chain(before.secondary.iter())
+                .copied()
+                .collect::<HashSet<_>>();
+            let locations_after = after.all_pageservers().into_iter().collect::<HashSet<_>>();
+            for node_id in after.get_secondary() {
+                if !locations_before.contains(node_id) {
+                    secondaries_created.push(PlannedLocation {
+                        tenant_shard_id: *tenant_shard_id,
+                        node_id: *node_id,
+                    });
+                }
+            }
+            for node_id in before.attached.iter().chain(before.secondary.iter()) {
+                if !locations_after.contains(node_id) {
+                    locations_removed.push(PlannedLocation {
+                        tenant_shard_id: *tenant_shard_id,
+                        node_id: *node_id,
+                    });
+                }
+            }
+        }
+
+        let node_ids = self
+            .nodes_before
+            .keys()
+            .copied()
+            .chain(self.nodes.keys().copied())
+            .collect::<BTreeSet<_>>();
+        let nodes = node_ids
+            .into_iter()
+            .map(|node_id| {
+                let before = self.nodes_before.get(&node_id);
+                let after = self.scheduler.node_stats(node_id);
+                PlannedNodeChange {
+                    node_id,
+                    shard_count_before: before.map(|s| s.shard_count).unwrap_or(0),
+                    shard_count_after: after.map(|s| s.shard_count).unwrap_or(0),
+                    attached_shard_count_before: before
+                        .map(|s| s.attached_shard_count)
+                        .unwrap_or(0),
+                    attached_shard_count_after: after.map(|s| s.attached_shard_count).unwrap_or(0),
+                    utilization_score_before: before.and_then(|s| s.utilization_score),
+                    utilization_score_after: after.and_then(|s| s.utilization_score),
+                }
+            })
+            .collect();
+
+        PlanResponse {
+            migrations,
+            secondaries_created,
+            locations_removed,
+            issues: self.issues.clone(),
+            nodes,
+            converged,
+        }
+    }
+}
+
+impl Drop for Plan {
+    fn drop(&mut self) {
+        // The copied intents hold references in the copied scheduler, and must release them
+        // before being dropped.
+        for shard in self.tenants.values_mut() {
+            shard.intent.clear(&mut self.scheduler);
+        }
+    }
+}
+
+impl Service {
+    /// Work out the scheduling decisions that would follow from a hypothetical change, without
+    /// making any of them.
+    pub(crate) async fn plan(&self, req: PlanRequest) -> Result<PlanResponse, ApiError> {
+        let mut plan = {
+            let locked = self.inner.read().unwrap();
+            Plan::new(&locked.nodes, &locked.tenants, &locked.scheduler)
+        };
+
+        // Settling may take many passes over all the shards in the cluster: keep it off the
+        // async executor.
+        tokio::task::spawn_blocking(move || -> Result<PlanResponse, ApiError> {
+            plan.apply(req)?;
+            let converged = plan.settle();
+            Ok(plan.response(converged))
+        })
+        .await
+        .map_err(|e| ApiError::InternalServerError(anyhow::anyhow!("Planning failed: {e}")))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pageserver_api::shard::ShardCount;
+
+    use super::*;
+    use crate::{scheduler::test_utils::make_test_nodes, tenant_shard::tests::make_test_tenant};
+
+    fn schedule_tenants(
+        tenants: &mut BTreeMap<TenantShardId, TenantShard>,
+        scheduler: &mut Scheduler,
+        policy: PlacementPolicy,
+        count: usize,
+    ) {
+        for _ in 0..count {
+            let mut context = ScheduleContext::default();
+            for mut shard in make_test_tenant(policy.clone(), ShardCount::new(1)) {
+                shard.schedule(scheduler, &mut context).unwrap();
+                tenants.insert(shard.tenant_shard_id, shard);
+            }
+        }
+    }
+
+    #[test]
+    fn plan_drain_moves_attachments_to_secondaries() {
+        let nodes = make_test_nodes(3);
+        let mut scheduler = Scheduler::new(nodes.values());
+        let mut tenants = BTreeMap::new();
+        schedule_tenants(
+            &mut tenants,
+            &mut scheduler,
+            PlacementPolicy::Attached(1),
+            6,
+        );
+
+        let drained = NodeId(1);
+        let attached_before = tenants
+            .values()
+            .filter(|s| *s.intent.get_attached() == Some(drained))
+            .count();
+        assert!(attached_before > 0);
+
+        let mut plan = Plan::new(&nodes, &tenants, &scheduler);
+        plan.apply(PlanRequest::NodeDrain { node_id: drained })
+            .unwrap();
+        assert!(plan.settle());
+        let response = plan.response(true);
+
+        assert_eq!(response.migrations.len(), attached_before);
+        assert!(response
+            .migrations
+            .iter()
+            .all(|m| m.from == Some(drained) && m.to.is_some() && m.to != Some(drained)));
+        assert!(response.issues.is_empty());
+
+        let drained_stats = response
+            .nodes
+            .iter()
+            .find(|n| n.node_id == drained)
+            .unwrap();
+        assert_eq!(drained_stats.attached_shard_count_before, attached_before);
+        assert_eq!(drained_stats.attached_shard_count_after, 0);
+        assert_eq!(drained_stats.utilization_score_after, None);
+
+        // The real state is untouched
+        assert_eq!(
+            tenants
+                .values()
+                .filter(|s| *s.intent.get_attached() == Some(drained))
+                .count(),
+            attached_before
+        );
+        assert_eq!(
+            scheduler.node_stats(drained).unwrap().attached_shard_count,
+            attached_before
+        );
+
+        for shard in tenants.values_mut() {
+            shard.intent.clear(&mut scheduler);
+        }
+    }
+
+    #[test]
+    fn plan_tenant_policy_creates_secondaries() {
+        let nodes = make_test_nodes(4);
+        let mut scheduler = Scheduler::new(nodes.values());
+        let mut tenants = BTreeMap::new();
+        schedule_tenants(
+            &mut tenants,
+            &mut scheduler,
+            PlacementPolicy::Attached(0),
+            1,
+        );
+        let tenant_shard_id = *tenants.keys().next().unwrap();
+
+        let mut plan = Plan::new(&nodes, &tenants, &scheduler);
+        plan.apply(PlanRequest::TenantPolicy {
+            tenant_id: tenant_shard_id.tenant_id,
+            placement: PlacementPolicy::Attached(2),
+        })
+        .unwrap();
+        assert!(plan.settle());
+        let response = plan.response(true);
+
+        assert!(response.migrations.is_empty());
+        assert_eq!(response.secondaries_created.len(), 2);
+        assert!(response
+            .secondaries_created
+            .iter()
+            .all(|l| l.tenant_shard_id == tenant_shard_id));
+        assert!(response.locations_removed.is_empty());
+        assert_eq!(
+            response
+                .nodes
+                .iter()
+                .map(|n| n.shard_count_after)
+                .sum::<usize>(),
+            3
+        );
+
+        for shard in tenants.values_mut() {
+            shard.intent.clear(&mut scheduler);
+        }
+    }
+
+    #[test]
+    fn plan_node_add() {
+        let nodes = make_test_nodes(2);
+        let mut scheduler = Scheduler::new(nodes.values());
+        let tenants = BTreeMap::new();
+
+        let mut plan = Plan::new(&nodes, &tenants, &scheduler);
+        assert!(matches!(
+            plan.apply(PlanRequest::NodeAdd {
+                node_id: NodeId(1),
+                availability_zone_id: "test-az".to_string(),
+            }),
+            Err(ApiError::Conflict(_))
+        ));
+
+        plan.apply(PlanRequest::NodeAdd {
+            node_id: NodeId(3),
+            availability_zone_id: "test-az".to_string(),
+        })
+        .unwrap();
+        let converged = plan.settle();
+        let response = plan.response(converged);
+
+        let added = response
+            .nodes
+            .iter()
+            .find(|n| n.node_id == NodeId(3))
+            .unwrap();
+        assert_eq!(added.shard_count_before, 0);
+        assert_eq!(added.utilization_score_before, None);
+        assert_eq!(added.utilization_score_after, Some(0));
+    }
+}
@@ -0,0 +1,1179 @@
+//! Rolling operations restart a list of pageservers one at a time (or one availability zone at a
+//! time): each node is drained, restarted by a caller-provided hook, waited for until it comes
+//! back with a new version, and then filled.
+//!
+//! Progress is persisted after every step, so that an operation survives a restart of the
+//! storage controller or a change of leader: whoever is leader picks up a running operation
+//! at the step where it was interrupted.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use pageserver_api::controller_api::{
+    NodeSchedulingPolicy, RollingNodeStatus, RollingNodeStep, RollingOperationHook,
+    RollingOperationHookBody, RollingOperationRequest, RollingOperationState,
+    RollingOperationStatus,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use utils::{http::error::ApiError, id::NodeId};
+
+use crate::{
+    background_node_operations::Operation,
+    persistence::{DatabaseError, RollingOperationPersistence},
+};
+
+use super::{LeadershipStatus, Service, SHORT_RECONCILE_TIMEOUT};
+
+/// How often we check on a drain, fill or restart that a rolling operation is waiting for
+const ROLLING_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A request from the API for the driver of a rolling operation to stop
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum RollingStop {
+    /// Stop at the end of the current step, leaving the operation resumable
+    Pause,
+    /// Stop immediately, returning any node that is mid-way through back to service
+    Abort,
+}
+
+/// In-memory handle on the task driving a rolling operation
+pub(super) struct RollingOperationHandle {
+    pub(super) id: String,
+    /// Fires when the driver should stop as soon as possible: on abort, step down or shutdown
+    pub(super) cancel: CancellationToken,
+    stop: std::sync::Mutex<Option<RollingStop>>,
+}
+
+impl RollingOperationHandle {
+    fn request_stop(&self, stop: RollingStop) {
+        *self.stop.lock().unwrap() = Some(stop);
+        if stop == RollingStop::Abort {
+            self.cancel.cancel();
+        }
+    }
+
+    fn stop_requested(&self) -> Option<RollingStop> {
+        *self.stop.lock().unwrap()
+    }
+}
+
+/// A request from the API to change the state of a rolling operation
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum RollingCommand {
+    Pause,
+    Resume,
+    Abort,
+}
+
+/// The state that `command` moves an operation from `state` to, or `None` if it is in that state
+/// already.  An operation that is over can't be changed.
+fn rolling_transition(
+    id: &str,
+    state: RollingOperationState,
+    command: RollingCommand,
+) -> Result<Option<RollingOperationState>, ApiError> {
+    if state.is_terminal() {
+        return Err(ApiError::Conflict(format!(
+            "Rolling operation {id} is {}",
+            String::from(state)
+        )));
+    }
+
+    let next = match command {
+        RollingCommand::Pause => RollingOperationState::Paused,
+        RollingCommand::Resume => RollingOperationState::Running,
+        RollingCommand::Abort => RollingOperationState::Aborted,
+    };
+    Ok((next != state).then_some(next))
+}
+
+/// The next step of a rolling operation: the nodes it applies to are indices into
+/// [`RollingOperationStatus::nodes`].
+#[derive(Eq, PartialEq, Debug)]
+enum RollingAction {
+    Drain(usize),
+    /// Run the hook of all these nodes at once, they are restarted together
+    Restart(Vec<(usize, Option<String>)>),
+    AwaitRestart(Vec<(usize, Option<String>)>),
+    Fill(usize),
+}
+
+/// What to do next, given how far each node has got.  Within a batch, all nodes are drained one
+/// at a time, then restarted together, then filled one at a time, and a batch is done before the
+/// next one starts.  Since this only depends on the persisted steps, an operation that is resumed
+/// after a pause or a restart of the controller picks up where it stopped.
+fn next_rolling_action(
+    status: &RollingOperationStatus,
+    batches: &[Vec<usize>],
+) -> Option<RollingAction> {
+    for batch in batches {
+        let steps = || batch.iter().map(|idx| (*idx, &status.nodes[*idx].step));
+
+        if let Some((idx, _)) = steps()
+            .find(|(_, step)| matches!(step, RollingNodeStep::Pending | RollingNodeStep::Draining))
+        {
+            return Some(RollingAction::Drain(idx));
+        }
+
+        let restarting = steps()
+            .filter_map(|(idx, step)| match step {
+                RollingNodeStep::Restarting { version_before } => {
+                    Some((idx, version_before.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !restarting.is_empty() {
+            return Some(RollingAction::Restart(restarting));
+        }
+
+        let awaiting = steps()
+            .filter_map(|(idx, step)| match step {
+                RollingNodeStep::AwaitingVersion { version_before } => {
+                    Some((idx, version_before.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !awaiting.is_empty() {
+            return Some(RollingAction::AwaitRestart(awaiting));
+        }
+
+        if let Some((idx, _)) = steps().find(|(_, step)| **step == RollingNodeStep::Filling) {
+            return Some(RollingAction::Fill(idx));
+        }
+    }
+
+    None
+}
+
+/// Indices into `nodes`, grouped into the sets of nodes that are restarted together: each node on
+/// its own, or all the nodes of an AZ.
+fn rolling_batches(
+    nodes: &[RollingNodeStatus],
+    by_availability_zone: bool,
+    az_of: impl Fn(NodeId) -> Option<String>,
+) -> Vec<Vec<usize>> {
+    if !by_availability_zone {
+        return (0..nodes.len()).map(|idx| vec![idx]).collect();
+    }
+
+    let mut batches: Vec<(Option<String>, Vec<usize>)> = Vec::new();
+    for (idx, node_status) in nodes.iter().enumerate() {
+        let az = az_of(node_status.node_id);
+        match batches.iter_mut().find(|(batch_az, _)| *batch_az == az) {
+            Some((_, batch)) => batch.push(idx),
+            None => batches.push((az, vec![idx])),
+        }
+    }
+
+    batches.into_iter().map(|(_, batch)| batch).collect()
+}
+
+/// The operation that was running under a previous leader, if any
+fn rolling_operation_to_resume(
+    operations: Vec<RollingOperationStatus>,
+) -> Option<RollingOperationStatus> {
+    operations
+        .into_iter()
+        .find(|op| op.state == RollingOperationState::Running)
+}
+
+/// Nodes that an operation is part-way through, which an abort returns to service
+fn rolling_nodes_in_progress(status: &RollingOperationStatus) -> Vec<NodeId> {
+    status
+        .nodes
+        .iter()
+        .filter(|node_status| {
+            !matches!(
+                node_status.step,
+                RollingNodeStep::Pending | RollingNodeStep::Done
+            )
+        })
+        .map(|node_status| node_status.node_id)
+        .collect()
+}
+
+enum RollingError {
+    /// A pause was requested: the operation stopped between two steps
+    Paused,
+    /// Abort, step down or shutdown: what happens next depends on [`RollingOperationHandle::stop_requested`]
+    Cancelled,
+    /// A step failed.  The operation is paused, and resuming it retries the step.
+    Failed(anyhow::Error),
+}
+
+impl From<ApiError> for RollingError {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::ShuttingDown => RollingError::Cancelled,
+            err => RollingError::Failed(anyhow::anyhow!("{err}")),
+        }
+    }
+}
+
+impl From<DatabaseError> for RollingError {
+    fn from(err: DatabaseError) -> Self {
+        RollingError::Failed(anyhow::anyhow!("{err}"))
+    }
+}
+
+impl Service {
+    pub(crate) async fn start_rolling_operation(
+        self: &Arc<Self>,
+        request: RollingOperationRequest,
+    ) -> Result<RollingOperationStatus, ApiError> {
+        if request.nodes.is_empty() {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "A rolling operation needs at least one node"
+            )));
+        }
+
+        let mut seen = HashSet::new();
+        for node_id in &request.nodes {
+            if !seen.insert(*node_id) {
+                return Err(ApiError::BadRequest(anyhow::anyhow!(
+                    "Node {node_id} is listed more than once"
+                )));
+            }
+            self.get_node(*node_id).await?;
+        }
+
+        self.rolling_check_leader()?;
+
+        if let Some(active) = self
+            .list_rolling_operations()
+            .await?
+            .into_iter()
+            .find(|op| !op.state.is_terminal())
+        {
+            return Err(ApiError::Conflict(format!(
+                "Rolling operation {} is already {}",
+                active.id,
+                String::from(active.state)
+            )));
+        }
+
+        let now = chrono::Utc::now();
+        let status = RollingOperationStatus {
+            id: hex::encode(rand::random::<[u8; 8]>()),
+            nodes: request
+                .nodes
+                .iter()
+                .map(|node_id| RollingNodeStatus {
+                    node_id: *node_id,
+                    step: RollingNodeStep::Pending,
+                })
+                .collect(),
+            request,
+            state: RollingOperationState::Running,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.persistence
+            .insert_rolling_operation(RollingOperationPersistence::from_status(&status))
+            .await
+            .map_err(|err| match err {
+                // Another operation was started concurrently
+                DatabaseError::Logical(reason) => ApiError::Conflict(reason),
+                err => err.into(),
+            })?;
+
+        tracing::info!("Starting rolling operation {}", status.id);
+        self.spawn_rolling_operation(status.clone())?;
+
+        Ok(status)
+    }
+
+    /// All rolling operations, most recent first
+    pub(crate) async fn list_rolling_operations(
+        &self,
+    ) -> Result<Vec<RollingOperationStatus>, ApiError> {
+        self.persistence
+            .list_rolling_operations()
+            .await?
+            .into_iter()
+            .map(|op| op.into_status().map_err(ApiError::InternalServerError))
+            .collect()
+    }
+
+    pub(crate) async fn pause_rolling_operation(
+        &self,
+        id: &str,
+    ) -> Result<RollingOperationStatus, ApiError> {
+        self.rolling_check_leader()?;
+
+        let mut status = self.get_rolling_operation(id).await?;
+        let Some(paused) = rolling_transition(id, status.state, RollingCommand::Pause)? else {
+            return Ok(status);
+        };
+
+        let handle = self.inner.read().unwrap().rolling_operation.clone();
+        match handle {
+            Some(handle) if handle.id == id => {
+                // The driver persists the state change once its current step is done
+                tracing::info!("Requesting pause of rolling operation {id}");
+                handle.request_stop(RollingStop::Pause);
+            }
+            _ => {
+                // Nothing is driving the operation, so there is no step to finish
+                status.state = paused;
+                self.persist_rolling_operation(&mut status).await?;
+            }
+        }
+
+        Ok(status)
+    }
+
+    pub(crate) async fn resume_rolling_operation(
+        self: &Arc<Self>,
+        id: &str,
+    ) -> Result<RollingOperationStatus, ApiError> {
+        self.rolling_check_leader()?;
+
+        let mut status = self.get_rolling_operation(id).await?;
+        // Resuming a running operation that nothing drives is fine, e.g. if the driver failed to start
+        rolling_transition(id, status.state, RollingCommand::Resume)?;
+
+        if let Some(handle) = self.inner.read().unwrap().rolling_operation.as_ref() {
+            return Err(ApiError::Conflict(format!(
+                "Rolling operation {} is still running",
+                handle.id
+            )));
+        }
+
+        status.state = RollingOperationState::Running;
+        status.error = None;
+        self.persist_rolling_operation(&mut status).await?;
+
+        tracing::info!("Resuming rolling operation {id}");
+        self.spawn_rolling_operation(status.clone())?;
+
+        Ok(status)
+    }
+
+    pub(crate) async fn abort_rolling_operation(
+        &self,
+        id: &str,
+    ) -> Result<RollingOperationStatus, ApiError> {
+        self.rolling_check_leader()?;
+
+        let status = self.get_rolling_operation(id).await?;
+        rolling_transition(id, status.state, RollingCommand::Abort)?;
+
+        let handle = self.inner.read().unwrap().rolling_operation.clone();
+        match handle {
+            Some(handle) if handle.id == id => {
+                // The driver cleans up and persists the state change once it has stopped
+                tracing::info!("Requesting abort of rolling operation {id}");
+                handle.request_stop(RollingStop::Abort);
+                Ok(status)
+            }
+            _ => Ok(self.rolling_abort_cleanup(status).await?),
+        }
+    }
+
+    /// Called once on startup: if we are the leader, pick up any operation that was running
+    /// under a previous leader.
+    pub(super) async fn resume_rolling_operation_on_startup(self: &Arc<Self>) {
+        if self.get_leadership_status() != LeadershipStatus::Leader {
+            return;
+        }
+
+        let operations = match self.list_rolling_operations().await {
+            Ok(operations) => operations,
+            Err(err) => {
+                tracing::error!("Failed to load rolling operations: {err}");
+                return;
+            }
+        };
+
+        if let Some(status) = rolling_operation_to_resume(operations) {
+            tracing::info!("Resuming rolling operation {} after startup", status.id);
+            if let Err(err) = self.spawn_rolling_operation(status) {
+                tracing::error!("Failed to resume rolling operation: {err}");
+            }
+        }
+    }
+
+    async fn get_rolling_operation(&self, id: &str) -> Result<RollingOperationStatus, ApiError> {
+        self.list_rolling_operations()
+            .await?
+            .into_iter()
+            .find(|op| op.id == id)
+            .ok_or_else(|| {
+                ApiError::NotFound(anyhow::anyhow!("Rolling operation {id} not found").into())
+            })
+    }
+
+    async fn persist_rolling_operation(
+        &self,
+        status: &mut RollingOperationStatus,
+    ) -> Result<(), DatabaseError> {
+        status.updated_at = chrono::Utc::now();
+        self.persistence
+            .update_rolling_operation(RollingOperationPersistence::from_status(status))
+            .await
+    }
+
+    fn rolling_check_leader(&self) -> Result<(), ApiError> {
+        match self.get_leadership_status() {
+            LeadershipStatus::Leader => Ok(()),
+            status => Err(ApiError::ResourceUnavailable(
+                format!("Rolling operations are only driven by the leader (we are {status})")
+                    .into(),
+            )),
+        }
+    }
+
+    fn spawn_rolling_operation(
+        self: &Arc<Self>,
+        status: RollingOperationStatus,
+    ) -> Result<(), ApiError> {
+        let gate_guard = self.gate.enter().map_err(|_| ApiError::ShuttingDown)?;
+
+        let handle = Arc::new(RollingOperationHandle {
+            id: status.id.clone(),
+            cancel: self.cancel.child_token(),
+            stop: std::sync::Mutex::new(None),
+        });
+
+        {
+            let mut locked = self.inner.write().unwrap();
+            if let Some(existing) = locked.rolling_operation.as_ref() {
+                return Err(ApiError::Conflict(format!(
+                    "Rolling operation {} is already running",
+                    existing.id
+                )));
+            }
+            locked.rolling_operation = Some(handle.clone());
+        }
+
+        let span = tracing::info_span!(parent: None, "rolling_operation", id = %status.id);
+
+        tokio::task::spawn({
+            let service = self.clone();
+            async move {
+                let _gate_guard = gate_guard;
+
+                scopeguard::defer! {
+                    service.inner.write().unwrap().rolling_operation.take();
+                }
+
+                service.drive_rolling_operation(status, &handle).await;
+            }
+            .instrument(span)
+        });
+
+        Ok(())
+    }
+
+    async fn drive_rolling_operation(
+        self: &Arc<Self>,
+        mut status: RollingOperationStatus,
+        handle: &RollingOperationHandle,
+    ) {
+        let result = self.rolling_operation_steps(&mut status, handle).await;
+
+        match result {
+            Ok(()) => {
+                tracing::info!("Rolling operation complete");
+                status.state = RollingOperationState::Complete;
+            }
+            Err(RollingError::Paused) => {
+                tracing::info!("Rolling operation paused");
+                status.state = RollingOperationState::Paused;
+            }
+            Err(RollingError::Failed(err)) => {
+                tracing::error!("Rolling operation paused by failed step: {err:#}");
+                status.state = RollingOperationState::Paused;
+                status.error = Some(format!("{err:#}"));
+            }
+            Err(RollingError::Cancelled) => {
+                if handle.stop_requested() == Some(RollingStop::Abort) {
+                    if let Err(err) = self.rolling_abort_cleanup(status).await {
+                        tracing::error!("Failed to abort rolling operation: {err}");
+                    }
+                } else {
+                    // Leave the persistent state as it is for the next leader to pick up
+                    tracing::info!("Rolling operation interrupted by step down or shutdown");
+                }
+                return;
+            }
+        }
+
+        if let Err(err) = self.persist_rolling_operation(&mut status).await {
+            tracing::error!("Failed to persist rolling operation state: {err}");
+        }
+    }
+
+    async fn rolling_operation_steps(
+        self: &Arc<Self>,
+        status: &mut RollingOperationStatus,
+        handle: &RollingOperationHandle,
+    ) -> Result<(), RollingError> {
+        let cancel = &handle.cancel;
+
+        let batches = {
+            let locked = self.inner.read().unwrap();
+            rolling_batches(
+                &status.nodes,
+                status.request.by_availability_zone,
+                |node_id| {
+                    locked
+                        .nodes
+                        .get(&node_id)
+                        .map(|node| node.get_availability_zone_id().to_string())
+                },
+            )
+        };
+
+        while let Some(action) = next_rolling_action(status, &batches) {
+            self.rolling_checkpoint(handle)?;
+
+            match action {
+                RollingAction::Drain(idx) => {
+                    let node_id = status.nodes[idx].node_id;
+                    self.set_rolling_step(status, idx, RollingNodeStep::Draining)
+                        .await?;
+                    self.rolling_drain(node_id, cancel).await?;
+                    let version_before = self.rolling_node_version(node_id, cancel).await?;
+                    self.set_rolling_step(
+                        status,
+                        idx,
+                        RollingNodeStep::Restarting { version_before },
+                    )
+                    .await?;
+                }
+                RollingAction::Restart(restarting) => {
+                    let results = futures::future::join_all(restarting.iter().map(|(idx, _)| {
+                        self.run_rolling_hook(&status.request, status.nodes[*idx].node_id, cancel)
+                    }))
+                    .await;
+                    self.apply_rolling_results(status, restarting, results, |version_before| {
+                        RollingNodeStep::AwaitingVersion { version_before }
+                    })
+                    .await?;
+                }
+                RollingAction::AwaitRestart(awaiting) => {
+                    let results =
+                        futures::future::join_all(awaiting.iter().map(|(idx, version_before)| {
+                            self.rolling_await_restart(
+                                &status.request,
+                                status.nodes[*idx].node_id,
+                                version_before.as_deref(),
+                                cancel,
+                            )
+                        }))
+                        .await;
+                    self.apply_rolling_results(status, awaiting, results, |_| {
+                        RollingNodeStep::Filling
+                    })
+                    .await?;
+                }
+                RollingAction::Fill(idx) => {
+                    self.rolling_fill(status.nodes[idx].node_id, cancel).await?;
+                    self.set_rolling_step(status, idx, RollingNodeStep::Done)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checked between steps: is there any reason to stop here?
+    fn rolling_checkpoint(&self, handle: &RollingOperationHandle) -> Result<(), RollingError> {
+        if handle.cancel.is_cancelled() || self.get_leadership_status() != LeadershipStatus::Leader
+        {
+            return Err(RollingError::Cancelled);
+        }
+
+        match handle.stop_requested() {
+            Some(RollingStop::Pause) => Err(RollingError::Paused),
+            Some(RollingStop::Abort) => Err(RollingError::Cancelled),
+            None => Ok(()),
+        }
+    }
+
+    async fn set_rolling_step(
+        &self,
+        status: &mut RollingOperationStatus,
+        idx: usize,
+        step: RollingNodeStep,
+    ) -> Result<(), RollingError> {
+        tracing::info!(node_id=%status.nodes[idx].node_id, "Rolling operation step {step:?}");
+        status.nodes[idx].step = step;
+        Ok(self.persist_rolling_operation(status).await?)
+    }
+
+    /// Advance the nodes of a batch whose step succeeded, then report the first failure
+    async fn apply_rolling_results<F>(
+        &self,
+        status: &mut RollingOperationStatus,
+        nodes: Vec<(usize, Option<String>)>,
+        results: Vec<Result<(), RollingError>>,
+        next_step: F,
+    ) -> Result<(), RollingError>
+    where
+        F: Fn(Option<String>) -> RollingNodeStep,
+    {
+        let mut first_error = None;
+        for ((idx, version_before), result) in nodes.into_iter().zip(results) {
+            match result {
+                Ok(()) => {
+                    self.set_rolling_step(status, idx, next_step(version_before))
+                        .await?
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    async fn rolling_drain(
+        self: &Arc<Self>,
+        node_id: NodeId,
+        cancel: &CancellationToken,
+    ) -> Result<(), RollingError> {
+        self.rolling_await_background_op(node_id, cancel).await?;
+
+        match self.get_node(node_id).await?.get_scheduling() {
+            NodeSchedulingPolicy::PauseForRestart => {
+                // Already drained, e.g. by an earlier attempt at this step
+                return Ok(());
+            }
+            NodeSchedulingPolicy::Draining | NodeSchedulingPolicy::Filling => {
+                // Left over from an operation interrupted by a restart of the controller
+                self.node_configure(node_id, None, Some(NodeSchedulingPolicy::Active))
+                    .await?;
+            }
+            _ => {}
+        }
+
+        self.start_node_drain(node_id).await?;
+        self.rolling_await_background_op(node_id, cancel).await?;
+
+        match self.get_node(node_id).await?.get_scheduling() {
+            NodeSchedulingPolicy::PauseForRestart => Ok(()),
+            policy => Err(RollingError::Failed(anyhow::anyhow!(
+                "Drain of node {node_id} did not complete (scheduling policy {policy:?})"
+            ))),
+        }
+    }
+
+    async fn rolling_fill(
+        self: &Arc<Self>,
+        node_id: NodeId,
+        cancel: &CancellationToken,
+    ) -> Result<(), RollingError> {
+        self.rolling_await_background_op(node_id, cancel).await?;
+
+        match self.get_node(node_id).await?.get_scheduling() {
+            NodeSchedulingPolicy::PauseForRestart
+            | NodeSchedulingPolicy::Draining
+            | NodeSchedulingPolicy::Filling => {
+                // A node that re-attached is already active, so this is a node that was interrupted
+                // by a restart of the controller, or that came back without re-attaching.
+                self.node_configure(node_id, None, Some(NodeSchedulingPolicy::Active))
+                    .await?;
+            }
+            _ => {}
+        }
+
+        self.start_node_fill(node_id).await?;
+        self.rolling_await_background_op(node_id, cancel).await?;
+
+        match self.get_node(node_id).await?.get_scheduling() {
+            NodeSchedulingPolicy::Active => Ok(()),
+            policy => Err(RollingError::Failed(anyhow::anyhow!(
+                "Fill of node {node_id} did not complete (scheduling policy {policy:?})"
+            ))),
+        }
+    }
+
+    /// Wait for any drain or fill of this node to finish.  If we are cancelled, the drain or fill is
+    /// cancelled too, which returns the node to service.
+    async fn rolling_await_background_op(
+        &self,
+        node_id: NodeId,
+        cancel: &CancellationToken,
+    ) -> Result<(), RollingError> {
+        let ongoing_on_node = |service: &Self| {
+            let locked = service.inner.read().unwrap();
+            locked.ongoing_operation.as_ref().and_then(|handler| {
+                let op_node_id = match handler.operation {
+                    Operation::Drain(drain) => drain.node_id,
+                    Operation::Fill(fill) => fill.node_id,
+                };
+                (op_node_id == node_id).then(|| handler.cancel.clone())
+            })
+        };
+
+        while let Some(op_cancel) = ongoing_on_node(self) {
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    tracing::info!(%node_id, "Cancelling background operation");
+                    op_cancel.cancel();
+                    while ongoing_on_node(self).is_some() {
+                        tokio::time::sleep(ROLLING_POLL_INTERVAL).await;
+                    }
+                    return Err(RollingError::Cancelled);
+                },
+                _ = tokio::time::sleep(ROLLING_POLL_INTERVAL) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn rolling_node_version(
+        &self,
+        node_id: NodeId,
+        cancel: &CancellationToken,
+    ) -> Result<Option<String>, RollingError> {
+        let node = self.get_node(node_id).await?;
+        match node
+            .with_client_retries(
+                |client| async move { client.get_status().await },
+                &self.config.jwt_token,
+                1,
+                3,
+                SHORT_RECONCILE_TIMEOUT,
+                cancel,
+            )
+            .await
+        {
+            Some(Ok(status)) => Ok(status.version),
+            Some(Err(err)) => Err(RollingError::Failed(anyhow::anyhow!(
+                "Failed to get status of node {node_id}: {err}"
+            ))),
+            None if cancel.is_cancelled() => Err(RollingError::Cancelled),
+            None => Err(RollingError::Failed(anyhow::anyhow!(
+                "Node {node_id} became unavailable"
+            ))),
+        }
+    }
+
+    async fn run_rolling_hook(
+        &self,
+        request: &RollingOperationRequest,
+        node_id: NodeId,
+        cancel: &CancellationToken,
+    ) -> Result<(), RollingError> {
+        let node = self.get_node(node_id).await?;
+        let describe = node.describe();
+        let body = RollingOperationHookBody {
+            node_id,
+            listen_http_addr: describe.listen_http_addr,
+            listen_http_port: describe.listen_http_port,
+            availability_zone_id: node.get_availability_zone_id().to_string(),
+        };
+
+        tracing::info!(%node_id, "Running rolling operation hook");
+
+        let run = async {
+            match &request.hook {
+                RollingOperationHook::Command { program, args } => {
+                    let status = tokio::process::Command::new(program)
+                        .args(args)
+                        .env("NODE_ID", body.node_id.to_string())
+                        .env("NODE_HTTP_ADDR", &body.listen_http_addr)
+                        .env("NODE_HTTP_PORT", body.listen_http_port.to_string())
+                        .env("NODE_AVAILABILITY_ZONE_ID", &body.availability_zone_id)
+                        .kill_on_drop(true)
+                        .status()
+                        .await
+                        .with_context(|| format!("Failed to run hook {program}"))?;
+                    if !status.success() {
+                        anyhow::bail!("Hook {program} failed for node {node_id}: {status}");
+                    }
+                }
+                RollingOperationHook::Http { url } => {
+                    let response = reqwest::Client::new()
+                        .post(url)
+                        .json(&body)
+                        .send()
+                        .await
+                        .with_context(|| format!("Failed to call hook {url}"))?;
+                    if !response.status().is_success() {
+                        anyhow::bail!(
+                            "Hook {url} failed for node {node_id}: {}",
+                            response.status()
+                        );
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        tokio::select! {
+            result = tokio::time::timeout(request.restart_timeout, run) => match result {
+                Ok(result) => result.map_err(RollingError::Failed),
+                Err(_) => Err(RollingError::Failed(anyhow::anyhow!(
+                    "Hook timed out for node {node_id} after {}",
+                    humantime::format_duration(request.restart_timeout)
+                ))),
+            },
+            _ = cancel.cancelled() => Err(RollingError::Cancelled),
+        }
+    }
+
+    /// Wait until the node is available again and is running a new version
+    async fn rolling_await_restart(
+        &self,
+        request: &RollingOperationRequest,
+        node_id: NodeId,
+        version_before: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<(), RollingError> {
+        let deadline = tokio::time::Instant::now() + request.restart_timeout;
+
+        // If the node did not report its version before the restart, the best we can do is
+        // wait until we have seen it go away and come back.
+        let mut seen_unavailable = false;
+
+        loop {
+            let node = self.get_node(node_id).await?;
+            if !node.is_available() {
+                seen_unavailable = true;
+            } else {
+                let version = match node
+                    .with_client_retries(
+                        |client| async move { client.get_status().await },
+                        &self.config.jwt_token,
+                        1,
+                        1,
+                        SHORT_RECONCILE_TIMEOUT,
+                        cancel,
+                    )
+                    .await
+                {
+                    Some(Ok(status)) => Some(status.version),
+                    Some(Err(err)) => {
+                        tracing::debug!(%node_id, "Node status not available yet: {err}");
+                        None
+                    }
+                    None if cancel.is_cancelled() => return Err(RollingError::Cancelled),
+                    None => None,
+                };
+
+                if let Some(version) = version {
+                    let restarted = match (&request.target_version, version_before) {
+                        (Some(target), _) => version.as_ref() == Some(target),
+                        (None, Some(before)) => version.as_deref() != Some(before),
+                        (None, None) => seen_unavailable,
+                    };
+                    if restarted {
+                        tracing::info!(%node_id, "Node is back with version {version:?}");
+                        return Ok(());
+                    }
+                }
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(RollingError::Failed(anyhow::anyhow!(
+                    "Node {node_id} did not come back with a new version within {}",
+                    humantime::format_duration(request.restart_timeout)
+                )));
+            }
+
+            tokio::select! {
+                _ = cancel.cancelled() => return Err(RollingError::Cancelled),
+                _ = tokio::time::sleep(ROLLING_POLL_INTERVAL) => {}
+            }
+        }
+    }
+
+    /// Return any node that the operation is part-way through back to service, and mark
+    /// the operation aborted.
+    async fn rolling_abort_cleanup(
+        &self,
+        mut status: RollingOperationStatus,
+    ) -> Result<RollingOperationStatus, ApiError> {
+        for node_id in rolling_nodes_in_progress(&status) {
+            let Ok(node) = self.get_node(node_id).await else {
+                // Deleted while the operation was in progress
+                continue;
+            };
+            if matches!(
+                node.get_scheduling(),
+                NodeSchedulingPolicy::PauseForRestart
+                    | NodeSchedulingPolicy::Draining
+                    | NodeSchedulingPolicy::Filling
+            ) {
+                tracing::info!(%node_id, "Returning node to service after abort");
+                self.node_configure(node_id, None, Some(NodeSchedulingPolicy::Active))
+                    .await?;
+            }
+        }
+
+        tracing::info!("Rolling operation aborted");
+        status.state = RollingOperationState::Aborted;
+        self.persist_rolling_operation(&mut status).await?;
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(
+        steps: Vec<RollingNodeStep>,
+        by_availability_zone: bool,
+    ) -> RollingOperationStatus {
+        let nodes = (1..=steps.len() as u64).map(NodeId).collect::<Vec<_>>();
+        let now = chrono::Utc::now();
+        RollingOperationStatus {
+            id: "op".to_string(),
+            request: RollingOperationRequest {
+                nodes: nodes.clone(),
+                hook: RollingOperationHook::Command {
+                    program: "true".to_string(),
+                    args: Vec::new(),
+                },
+                target_version: None,
+                by_availability_zone,
+                restart_timeout: Duration::from_secs(60),
+            },
+            state: RollingOperationState::Running,
+            nodes: nodes
+                .into_iter()
+                .zip(steps)
+                .map(|(node_id, step)| RollingNodeStatus { node_id, step })
+                .collect(),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn pending(count: usize) -> Vec<RollingNodeStep> {
+        vec![RollingNodeStep::Pending; count]
+    }
+
+    fn version(v: &str) -> Option<String> {
+        Some(v.to_string())
+    }
+
+    /// Apply the steps that the driver takes when `action` succeeds
+    fn succeed(status: &mut RollingOperationStatus, action: &RollingAction) {
+        match action {
+            RollingAction::Drain(idx) => {
+                status.nodes[*idx].step = RollingNodeStep::Restarting {
+                    version_before: version("v1"),
+                }
+            }
+            RollingAction::Restart(nodes) => {
+                for (idx, version_before) in nodes {
+                    status.nodes[*idx].step = RollingNodeStep::AwaitingVersion {
+                        version_before: version_before.clone(),
+                    };
+                }
+            }
+            RollingAction::AwaitRestart(nodes) => {
+                for (idx, _) in nodes {
+                    status.nodes[*idx].step = RollingNodeStep::Filling;
+                }
+            }
+            RollingAction::Fill(idx) => status.nodes[*idx].step = RollingNodeStep::Done,
+        }
+    }
+
+    /// Run the operation to completion, assuming every step succeeds
+    fn run(status: &mut RollingOperationStatus, batches: &[Vec<usize>]) -> Vec<RollingAction> {
+        let mut actions = Vec::new();
+        while let Some(action) = next_rolling_action(status, batches) {
+            succeed(status, &action);
+            actions.push(action);
+            assert!(actions.len() < 100, "operation does not progress");
+        }
+        actions
+    }
+
+    #[test]
+    fn one_node_at_a_time() {
+        let mut status = operation(pending(2), false);
+        let batches = rolling_batches(&status.nodes, false, |_| None);
+        assert_eq!(batches, vec![vec![0], vec![1]]);
+
+        let restarted = |idx| vec![(idx, version("v1"))];
+        assert_eq!(
+            run(&mut status, &batches),
+            vec![
+                RollingAction::Drain(0),
+                RollingAction::Restart(restarted(0)),
+                RollingAction::AwaitRestart(restarted(0)),
+                RollingAction::Fill(0),
+                RollingAction::Drain(1),
+                RollingAction::Restart(restarted(1)),
+                RollingAction::AwaitRestart(restarted(1)),
+                RollingAction::Fill(1),
+            ]
+        );
+        assert!(status
+            .nodes
+            .iter()
+            .all(|node| node.step == RollingNodeStep::Done));
+    }
+
+    #[test]
+    fn by_availability_zone() {
+        let mut status = operation(pending(3), true);
+        let az_of =
+            |node_id: NodeId| Some(if node_id.0 == 2 { "az-b" } else { "az-a" }.to_string());
+        let batches = rolling_batches(&status.nodes, true, az_of);
+        // The AZs are in the order of their first node
+        assert_eq!(batches, vec![vec![0, 2], vec![1]]);
+
+        let az_a = vec![(0, version("v1")), (2, version("v1"))];
+        let actions = run(&mut status, &batches);
+        assert_eq!(
+            actions[..6],
+            [
+                RollingAction::Drain(0),
+                RollingAction::Drain(2),
+                RollingAction::Restart(az_a.clone()),
+                RollingAction::AwaitRestart(az_a),
+                RollingAction::Fill(0),
+                RollingAction::Fill(2),
+            ]
+        );
+        assert_eq!(actions[6], RollingAction::Drain(1));
+        assert_eq!(actions.len(), 10);
+    }
+
+    #[test]
+    fn failed_step_is_retried() {
+        // In a batch, one hook succeeded and the other failed: resuming runs the failed one again,
+        // before waiting for either to come back
+        let status = operation(
+            vec![
+                RollingNodeStep::AwaitingVersion {
+                    version_before: version("v1"),
+                },
+                RollingNodeStep::Restarting {
+                    version_before: version("v1"),
+                },
+            ],
+            true,
+        );
+        let batches = rolling_batches(&status.nodes, true, |_| None);
+        assert_eq!(
+            next_rolling_action(&status, &batches),
+            Some(RollingAction::Restart(vec![(1, version("v1"))]))
+        );
+
+        // A drain that was interrupted is started again
+        let status = operation(vec![RollingNodeStep::Draining], false);
+        assert_eq!(
+            next_rolling_action(&status, &[vec![0]]),
+            Some(RollingAction::Drain(0))
+        );
+    }
+
+    #[test]
+    fn resume_after_restart() {
+        let mut running = operation(
+            vec![
+                RollingNodeStep::Done,
+                RollingNodeStep::AwaitingVersion {
+                    version_before: version("v1"),
+                },
+                RollingNodeStep::Pending,
+            ],
+            false,
+        );
+        running.id = "running".to_string();
+        let mut complete = operation(vec![RollingNodeStep::Done], false);
+        complete.id = "complete".to_string();
+        complete.state = RollingOperationState::Complete;
+
+        // What the next leader loads from the database
+        let persisted = [&complete, &running]
+            .into_iter()
+            .map(|op| {
+                RollingOperationPersistence::from_status(op)
+                    .into_status()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let resumed = rolling_operation_to_resume(persisted).unwrap();
+        assert_eq!(resumed.id, "running");
+
+        // It carries on with the node it was waiting for, then the rest
+        let batches = rolling_batches(&resumed.nodes, false, |_| None);
+        assert_eq!(
+            next_rolling_action(&resumed, &batches),
+            Some(RollingAction::AwaitRestart(vec![(1, version("v1"))]))
+        );
+        let mut resumed = resumed;
+        let actions = run(&mut resumed, &batches);
+        assert_eq!(actions.len(), 5);
+        assert_eq!(actions[1], RollingAction::Drain(2));
+
+        // A paused operation waits for a resume request
+        running.state = RollingOperationState::Paused;
+        assert!(rolling_operation_to_resume(vec![running]).is_none());
+    }
+
+    #[test]
+    fn pause_resume_abort() {
+        use RollingCommand::*;
+        use RollingOperationState::*;
+
+        assert_eq!(
+            rolling_transition("op", Running, Pause).unwrap(),
+            Some(Paused)
+        );
+        assert_eq!(rolling_transition("op", Paused, Pause).unwrap(), None);
+        assert_eq!(
+            rolling_transition("op", Paused, Resume).unwrap(),
+            Some(Running)
+        );
+        assert_eq!(rolling_transition("op", Running, Resume).unwrap(), None);
+        assert_eq!(
+            rolling_transition("op", Running, Abort).unwrap(),
+            Some(Aborted)
+        );
+        assert_eq!(
+            rolling_transition("op", Paused, Abort).unwrap(),
+            Some(Aborted)
+        );
+
+        for state in [Aborted, Complete] {
+            for command in [Pause, Resume, Abort] {
+                assert!(matches!(
+                    rolling_transition("op", state, command),
+                    Err(ApiError::Conflict(_))
+                ));
+            }
+        }
+
+        // An abort returns the nodes that are part-way through to service
+        let status = operation(
+            vec![
+                RollingNodeStep::Done,
+                RollingNodeStep::Filling,
+                RollingNodeStep::Draining,
+                RollingNodeStep::Pending,
+            ],
+            false,
+        );
+        assert_eq!(
+            rolling_nodes_in_progress(&status),
+            vec![NodeId(2), NodeId(3)]
+        );
+    }
+}
@@ -8,7 +8,9 @@
     metrics::{self, ReconcileCompleteLabelGroup, ReconcileOutcome},
     persistence::TenantShardPersistence,
     reconciler::{ReconcileUnits, ReconcilerConfig},
-    scheduler::{AffinityScore, MaySchedule, RefCountUpdate, ScheduleContext},
+    scheduler::{
+        AffinityScore, AzAffinity, LoadRebalance, MaySchedule, RefCountUpdate, ScheduleContext,
+    },
     service::ReconcileResultRequest,
 };
 use pageserver_api::controller_api::{
@@ -355,6 +357,23 @@ pub(crate) enum ScheduleOptimizationAction {
     MigrateAttachment(MigrateAttachment),
 }
 
+impl std::fmt::Display for ScheduleOptimizationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::ReplaceSecondary(replace) => write!(
+                f,
+                "replace secondary {} -> {}",
+                replace.old_node_id, replace.new_node_id
+            ),
+            Self::MigrateAttachment(migrate) => write!(
+                f,
+                "migrate attachment {} -> {}",
+                migrate.old_attached_node_id, migrate.new_attached_node_id
+            ),
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub(crate) struct ScheduleOptimization {
     // What was the reconcile sequence when we generated this optimization?  The optimization
@@ -362,6 +381,10 @@ pub(crate) struct ScheduleOptimization {
     // happened between planning the optimization and applying it.
     sequence: Sequence,
 
+    // Was this optimization generated to move load off a busy node?  Only then does applying it
+    // move the shard's load in the scheduler: other migrations are not driven by utilization.
+    for_load: bool,
+
     pub(crate) action: ScheduleOptimizationAction,
 }
 
@@ -530,14 +553,20 @@ fn schedule_attached(
             return Ok((false, node_id));
         }
 
-        if let Some(promote_secondary) = scheduler.node_preferred(&self.intent.secondary) {
+        if let Some(promote_secondary) =
+            scheduler.node_preferred(&self.intent.secondary, self.preferred_az())
+        {
             // Promote a secondary
             tracing::debug!("Promoted secondary {} to attached", promote_secondary);
             self.intent.promote_attached(scheduler, promote_secondary);
             Ok((true, promote_secondary))
         } else {
             // Pick a fresh node: either we had no secondaries or none were schedulable
-            let node_id = scheduler.schedule_shard(&self.intent.secondary, context)?;
+            let node_id = scheduler.schedule_shard(
+                &self.intent.secondary,
+                AzAffinity::Attached(self.preferred_az()),
+                context,
+            )?;
             tracing::debug!("Selected {} as attached", node_id);
             self.intent.set_attached(scheduler, Some(node_id));
             Ok((true, node_id))
@@ -590,7 +619,9 @@ pub(crate) fn do_schedule(
         match self.policy {
             Attached(secondary_count) => {
                 let retain_secondaries = if self.intent.attached.is_none()
-                    && scheduler.node_preferred(&self.intent.secondary).is_some()
+                    && scheduler
+                        .node_preferred(&self.intent.secondary, self.preferred_az())
+                        .is_some()
                 {
                     // If we have no attached, and one of the secondaries is elegible to be promoted, retain
                     // one more secondary than we usually would, as one of them will become attached futher down this function.
@@ -611,9 +642,16 @@ pub(crate) fn do_schedule(
                     self.schedule_attached(scheduler, context)?;
                 modified |= modified_attached;
 
-                let mut used_pageservers = vec![attached_node_id];
+                // Secondaries go in a different AZ to the attached location and to each other where
+                // possible, so that they can take over if an AZ is lost.
+                let mut used_pageservers = self.intent.all_pageservers();
+                debug_assert!(used_pageservers.contains(&attached_node_id));
                 while self.intent.secondary.len() < secondary_count {
-                    let node_id = scheduler.schedule_shard(&used_pageservers, context)?;
+                    let node_id = scheduler.schedule_shard(
+                        &used_pageservers,
+                        AzAffinity::Secondary,
+                        context,
+                    )?;
                     self.intent.push_secondary(scheduler, node_id);
                     used_pageservers.push(node_id);
                     modified = true;
@@ -626,7 +664,7 @@ pub(crate) fn do_schedule(
                     modified = true;
                 } else if self.intent.secondary.is_empty() {
                     // Populate secondary by scheduling a fresh node
-                    let node_id = scheduler.schedule_shard(&[], context)?;
+                    let node_id = scheduler.schedule_shard(&[], AzAffinity::Any, context)?;
                     self.intent.push_secondary(scheduler, node_id);
                     modified = true;
                 }
@@ -668,12 +706,14 @@ pub(crate) fn reschedule_to_secondary(
     ) -> Result<(), ScheduleError> {
         let promote_to = match promote_to {
             Some(node) => node,
-            None => match scheduler.node_preferred(self.intent.get_secondary()) {
-                Some(node) => node,
-                None => {
-                    return Err(ScheduleError::ImpossibleConstraint);
+            None => {
+                match scheduler.node_preferred(self.intent.get_secondary(), self.preferred_az()) {
+                    Some(node) => node,
+                    None => {
+                        return Err(ScheduleError::ImpossibleConstraint);
+                    }
                 }
-            },
+            }
         };
 
         assert!(self.intent.get_secondary().contains(&promote_to));
@@ -713,6 +753,7 @@ pub(crate) fn optimize_attachment(
 
         let current_affinity_score = schedule_context.get_node_affinity(attached);
         let current_attachment_count = schedule_context.get_node_attachments(attached);
+        let current_in_preferred_az = self.in_preferred_az(nodes, attached);
 
         // Generate score for each node, dropping any un-schedulable nodes.
         let all_pageservers = self.intent.all_pageservers();
@@ -732,29 +773,53 @@ pub(crate) fn optimize_attachment(
                 } else if matches!(node.unwrap().may_schedule(), MaySchedule::No) {
                     None
                 } else {
+                    let az_mismatch = !self.in_preferred_az(nodes, *node_id);
                     let affinity_score = schedule_context.get_node_affinity(*node_id);
                     let attachment_count = schedule_context.get_node_attachments(*node_id);
-                    Some((*node_id, affinity_score, attachment_count))
+                    Some((*node_id, az_mismatch, affinity_score, attachment_count))
                 }
             })
             .collect::<Vec<_>>();
 
         // Sort precedence:
-        //  1st - prefer nodes with the lowest total affinity score
-        //  2nd - prefer nodes with the lowest number of attachments in this context
-        //  3rd - if all else is equal, sort by node ID for determinism in tests.
-        scores.sort_by_key(|i| (i.1, i.2, i.0));
-
-        if let Some((preferred_node, preferred_affinity_score, preferred_attachment_count)) =
-            scores.first()
+        //  1st - prefer nodes in the tenant's preferred AZ
+        //  2nd - prefer nodes with the lowest total affinity score
+        //  3rd - prefer nodes with the lowest number of attachments in this context
+        //  4th - if all else is equal, sort by node ID for determinism in tests.
+        scores.sort_by_key(|i| (i.1, i.2, i.3, i.0));
+
+        if let Some((
+            preferred_node,
+            preferred_az_mismatch,
+            preferred_affinity_score,
+            preferred_attachment_count,
+        )) = scores.first()
         {
             if attached != *preferred_node {
+                if !current_in_preferred_az && !preferred_az_mismatch {
+                    // We are attached outside the preferred AZ, e.g. because we failed over while
+                    // it was unavailable: move back as soon as a secondary there can take over.
+                    tracing::info!(
+                        "Identified optimization: migrate attachment {attached}->{preferred_node} to return to preferred AZ (secondaries {:?})",
+                        self.intent.get_secondary()
+                    );
+                    return Some(ScheduleOptimization {
+                        sequence: self.sequence,
+                        for_load: false,
+                        action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
+                            old_attached_node_id: attached,
+                            new_attached_node_id: *preferred_node,
+                        }),
+                    });
+                }
+
                 // The best alternative must be more than 1 better than us, otherwise we could end
                 // up flapping back next time we're called (e.g. there's no point migrating from
                 // a location with score 1 to a score zero, because on next location the situation
-                // would be the same, but in reverse).
-                if current_affinity_score > *preferred_affinity_score + AffinityScore(1)
-                    || current_attachment_count > *preferred_attachment_count + 1
+                // would be the same, but in reverse).  Never leave the preferred AZ to get a better score.
+                if !(current_in_preferred_az && *preferred_az_mismatch)
+                    && (current_affinity_score > *preferred_affinity_score + AffinityScore(1)
+                        || current_attachment_count > *preferred_attachment_count + 1)
                 {
                     tracing::info!(
                         "Identified optimization: migrate attachment {attached}->{preferred_node} (secondaries {:?})",
@@ -762,6 +827,7 @@ pub(crate) fn optimize_attachment(
                     );
                     return Some(ScheduleOptimization {
                         sequence: self.sequence,
+                        for_load: false,
                         action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
                             old_attached_node_id: attached,
                             new_attached_node_id: *preferred_node,
@@ -793,23 +859,59 @@ pub(crate) fn optimize_secondary(
             return None;
         }
 
+        let attached_az = self
+            .intent
+            .get_attached()
+            .and_then(|n| scheduler.node_az(n))
+            .map(|az| az.to_string());
+
         for secondary in self.intent.get_secondary() {
-            let Some(affinity_score) = schedule_context.nodes.get(secondary) else {
-                // We're already on a node unaffected any affinity constraints,
-                // so we won't change it.
-                continue;
+            // A secondary in the same AZ as the attached location is no use if that AZ is lost
+            let in_attached_az =
+                attached_az.is_some() && scheduler.node_az(*secondary) == attached_az.as_deref();
+
+            let affinity_score = match schedule_context.nodes.get(secondary) {
+                Some(affinity_score) => affinity_score,
+                None if in_attached_az => &AffinityScore::FREE,
+                None => {
+                    // We're already on a node unaffected any affinity constraints,
+                    // so we won't change it.
+                    continue;
+                }
             };
 
             // Let the scheduler suggest a node, where it would put us if we were scheduling afresh
             // This implicitly limits the choice to nodes that are available, and prefers nodes
             // with lower utilization.
-            let Ok(candidate_node) =
-                scheduler.schedule_shard(&self.intent.all_pageservers(), schedule_context)
-            else {
+            let Ok(candidate_node) = scheduler.schedule_shard(
+                &self.intent.all_pageservers(),
+                AzAffinity::Secondary,
+                schedule_context,
+            ) else {
                 // A scheduling error means we have no possible candidate replacements
                 continue;
             };
 
+            let candidate_in_attached_az = attached_az.is_some()
+                && scheduler.node_az(candidate_node) == attached_az.as_deref();
+            if in_attached_az && !candidate_in_attached_az {
+                tracing::info!(
+                    "Identified optimization: replace secondary {secondary}->{candidate_node} to leave attached AZ (current secondaries {:?})",
+                    self.intent.get_secondary()
+                );
+                return Some(ScheduleOptimization {
+                    sequence: self.sequence,
+                    for_load: false,
+                    action: ScheduleOptimizationAction::ReplaceSecondary(ReplaceSecondary {
+                        old_node_id: *secondary,
+                        new_node_id: candidate_node,
+                    }),
+                });
+            } else if candidate_in_attached_az && !in_attached_az {
+                // Never move into the attached location's AZ to get a better score
+                continue;
+            }
+
             let candidate_affinity_score = schedule_context
                 .nodes
                 .get(&candidate_node)
@@ -826,6 +928,7 @@ pub(crate) fn optimize_secondary(
                 );
                 return Some(ScheduleOptimization {
                     sequence: self.sequence,
+                    for_load: false,
                     action: ScheduleOptimizationAction::ReplaceSecondary(ReplaceSecondary {
                         old_node_id: *secondary,
                         new_node_id: candidate_node,
@@ -837,6 +940,75 @@ pub(crate) fn optimize_secondary(
         None
     }
 
+    /// Optimize for load: if this shard is one that the scheduler would like to move off a busy node,
+    /// either cut over to a secondary on a less busy node, or move a secondary to a less busy node
+    /// so that we can cut over to it once it is warm.
+    ///
+    /// Neither move may worsen the tenant's spread across nodes, as otherwise [`Self::optimize_attachment`]
+    /// and [`Self::optimize_secondary`] would move it straight back.  For the same reason, a shard with
+    /// a preferred AZ only moves within that AZ, and never moves its secondaries for load.
+    #[instrument(skip_all, fields(tenant_id=%self.tenant_shard_id.tenant_id, shard_id=%self.tenant_shard_id.shard_slug()))]
+    pub(crate) fn optimize_load(
+        &self,
+        nodes: &HashMap<NodeId, Node>,
+        rebalance: &LoadRebalance,
+        schedule_context: &ScheduleContext,
+    ) -> Option<ScheduleOptimization> {
+        let attached = (*self.intent.get_attached())?;
+        if attached != rebalance.from_node
+            || !rebalance.shards.contains(&self.tenant_shard_id)
+            || self.intent.secondary.is_empty()
+        {
+            return None;
+        }
+
+        let current_affinity_score = schedule_context.get_node_affinity(attached);
+        let current_attachment_count = schedule_context.get_node_attachments(attached);
+
+        for node_id in &rebalance.to_nodes {
+            if self.intent.secondary.contains(node_id)
+                && self.in_preferred_az(nodes, *node_id)
+                && schedule_context.get_node_affinity(*node_id) <= current_affinity_score
+                && schedule_context.get_node_attachments(*node_id) < current_attachment_count
+            {
+                tracing::info!(
+                    "Identified optimization for load: migrate attachment {attached}->{node_id} (secondaries {:?})",
+                    self.intent.get_secondary()
+                );
+                return Some(ScheduleOptimization {
+                    sequence: self.sequence,
+                    for_load: true,
+                    action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
+                        old_attached_node_id: attached,
+                        new_attached_node_id: *node_id,
+                    }),
+                });
+            }
+        }
+
+        if self.preferred_az().is_some() {
+            return None;
+        }
+
+        let new_node_id = rebalance
+            .to_nodes
+            .iter()
+            .find(|n| schedule_context.get_node_affinity(**n) == AffinityScore::FREE)?;
+        let old_node_id = self.intent.get_secondary().first()?;
+        tracing::info!(
+            "Identified optimization for load: replace secondary {old_node_id}->{new_node_id} (current secondaries {:?})",
+            self.intent.get_secondary()
+        );
+        Some(ScheduleOptimization {
+            sequence: self.sequence,
+            for_load: true,
+            action: ScheduleOptimizationAction::ReplaceSecondary(ReplaceSecondary {
+                old_node_id: *old_node_id,
+                new_node_id: *new_node_id,
+            }),
+        })
+    }
+
     /// Return true if the optimization was really applied: it will not be applied if the optimization's
     /// sequence is behind this tenant shard's
     pub(crate) fn apply_optimization(
@@ -848,11 +1020,6 @@ pub(crate) fn apply_optimization(
             return false;
         }
 
-        metrics::METRICS_REGISTRY
-            .metrics_group
-            .storage_controller_schedule_optimization
-            .inc();
-
         match optimization.action {
             ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
                 old_attached_node_id,
@@ -861,6 +1028,13 @@ pub(crate) fn apply_optimization(
                 self.intent.demote_attached(scheduler, old_attached_node_id);
                 self.intent
                     .promote_attached(scheduler, new_attached_node_id);
+                if optimization.for_load {
+                    scheduler.migrate_shard_load(
+                        self.tenant_shard_id,
+                        old_attached_node_id,
+                        new_attached_node_id,
+                    );
+                }
             }
             ScheduleOptimizationAction::ReplaceSecondary(ReplaceSecondary {
                 old_node_id,
@@ -1322,10 +1496,36 @@ pub(crate) fn to_persistent(&self) -> TenantShardPersistence {
         }
     }
 
+    /// A copy of this shard's scheduling state, for use with [`Scheduler::clone_for_planning`].  The
+    /// copy has no reconciler or observed state: it must never be reconciled.
+    pub(crate) fn clone_for_planning(&self) -> Self {
+        let mut shard = Self::new(self.tenant_shard_id, self.shard, self.policy.clone());
+        shard.sequence = self.sequence;
+        shard.generation = self.generation;
+        shard.intent = self.intent.clone();
+        shard.config = self.config.clone();
+        shard.splitting = self.splitting;
+        shard.scheduling_policy = self.scheduling_policy;
+        shard.preferred_az_id = self.preferred_az_id.clone();
+        shard
+    }
+
     pub(crate) fn preferred_az(&self) -> Option<&str> {
         self.preferred_az_id.as_deref()
     }
 
+    /// Whether a node is in this shard's preferred AZ.  Shards without a preferred AZ are happy
+    /// anywhere.
+    fn in_preferred_az(&self, nodes: &HashMap<NodeId, Node>, node_id: NodeId) -> bool {
+        match self.preferred_az() {
+            Some(az) => nodes
+                .get(&node_id)
+                .map(|n| n.get_availability_zone_id() == az)
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
     pub(crate) fn set_preferred_az(&mut self, preferred_az_id: String) {
         self.preferred_az_id = Some(preferred_az_id);
     }
@@ -1335,11 +1535,12 @@ pub(crate) fn set_preferred_az(&mut self, preferred_az_id: String) {
 pub(crate) mod tests {
     use pageserver_api::{
         controller_api::NodeAvailability,
+        models::utilization::test_utilization,
         shard::{ShardCount, ShardNumber},
     };
     use utils::id::TenantId;
 
-    use crate::scheduler::test_utils::make_test_nodes;
+    use crate::scheduler::test_utils::{make_test_nodes, make_test_nodes_in_azs};
 
     use super::*;
 
@@ -1365,7 +1566,10 @@ fn make_test_tenant_shard(policy: PlacementPolicy) -> TenantShard {
         )
     }
 
-    fn make_test_tenant(policy: PlacementPolicy, shard_count: ShardCount) -> Vec<TenantShard> {
+    pub(crate) fn make_test_tenant(
+        policy: PlacementPolicy,
+        shard_count: ShardCount,
+    ) -> Vec<TenantShard> {
         let tenant_id = TenantId::generate();
 
         (0..shard_count.count())
@@ -1551,6 +1755,7 @@ fn optimize_attachment() -> anyhow::Result<()> {
             optimization_a,
             Some(ScheduleOptimization {
                 sequence: shard_a.sequence,
+                for_load: false,
                 action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
                     old_attached_node_id: NodeId(1),
                     new_attached_node_id: NodeId(2)
@@ -1568,6 +1773,7 @@ fn optimize_attachment() -> anyhow::Result<()> {
             optimization_b,
             Some(ScheduleOptimization {
                 sequence: shard_b.sequence,
+                for_load: false,
                 action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
                     old_attached_node_id: NodeId(1),
                     new_attached_node_id: NodeId(3)
@@ -1589,6 +1795,62 @@ fn optimize_attachment() -> anyhow::Result<()> {
         Ok(())
     }
 
+    /// A tenant with a preferred AZ should keep its secondary in another AZ, fail over to it
+    /// when the preferred AZ is lost, and move back when the AZ recovers.
+    #[test]
+    fn az_failover() -> anyhow::Result<()> {
+        // Nodes 1 and 3 are in az-a, nodes 2 and 4 in az-b
+        let mut nodes = make_test_nodes_in_azs(4, &["az-a", "az-b"]);
+        let mut scheduler = Scheduler::new(nodes.values());
+
+        let mut shard = make_test_tenant_shard(PlacementPolicy::Attached(1));
+        shard.set_preferred_az("az-a".to_string());
+        shard.schedule(&mut scheduler, &mut ScheduleContext::default())?;
+        assert_eq!(shard.intent.get_attached(), &Some(NodeId(1)));
+        assert_eq!(shard.intent.get_secondary(), &vec![NodeId(2)]);
+
+        // Lose az-a
+        for node_id in [NodeId(1), NodeId(3)] {
+            let node = nodes.get_mut(&node_id).unwrap();
+            node.set_availability(NodeAvailability::Offline);
+            scheduler.node_upsert(node);
+        }
+        assert!(shard.intent.demote_attached(&mut scheduler, NodeId(1)));
+        shard.schedule(&mut scheduler, &mut ScheduleContext::default())?;
+        assert_eq!(shard.intent.get_attached(), &Some(NodeId(2)));
+        assert_eq!(shard.intent.get_secondary(), &vec![NodeId(1)]);
+
+        // While az-a is down, there is nowhere better to be
+        let mut schedule_context = ScheduleContext::default();
+        schedule_context.avoid(&shard.intent.all_pageservers());
+        schedule_context.push_attached(shard.intent.get_attached().unwrap());
+        assert_eq!(shard.optimize_attachment(&nodes, &schedule_context), None);
+
+        // Once az-a is back, we return to it
+        let node = nodes.get_mut(&NodeId(1)).unwrap();
+        node.set_availability(NodeAvailability::Active(test_utilization::simple(0, 0)));
+        scheduler.node_upsert(node);
+        let optimization = shard.optimize_attachment(&nodes, &schedule_context);
+        assert_eq!(
+            optimization,
+            Some(ScheduleOptimization {
+                sequence: shard.sequence,
+                for_load: false,
+                action: ScheduleOptimizationAction::MigrateAttachment(MigrateAttachment {
+                    old_attached_node_id: NodeId(2),
+                    new_attached_node_id: NodeId(1)
+                })
+            })
+        );
+        shard.apply_optimization(&mut scheduler, optimization.unwrap());
+        assert_eq!(shard.intent.get_attached(), &Some(NodeId(1)));
+        assert_eq!(shard.intent.get_secondary(), &vec![NodeId(2)]);
+
+        shard.intent.clear(&mut scheduler);
+
+        Ok(())
+    }
+
     #[test]
     fn optimize_secondary() -> anyhow::Result<()> {
         let nodes = make_test_nodes(4);
@@ -1618,6 +1880,7 @@ fn optimize_secondary() -> anyhow::Result<()> {
             optimization_a,
             Some(ScheduleOptimization {
                 sequence: shard_a.sequence,
+                for_load: false,
                 action: ScheduleOptimizationAction::ReplaceSecondary(ReplaceSecondary {
                     old_node_id: NodeId(3),
                     new_node_id: NodeId(4)
@@ -21,3 +21,18 @@ class EndpointHttpClient(requests.Session):
         res = self.get(f"http://localhost:{self.port}/database_schema?database={database}")
         res.raise_for_status()
         return res.text
+
+    def status(self):
+        res = self.get(f"http://localhost:{self.port}/status")
+        res.raise_for_status()
+        return res.json()
+
+    def metrics_json(self):
+        res = self.get(f"http://localhost:{self.port}/metrics.json")
+        res.raise_for_status()
+        return res.json()
+
+    def promote(self):
+        res = self.post(f"http://localhost:{self.port}/promote")
+        res.raise_for_status()
+        return res.json()
@@ -2539,6 +2539,18 @@ class NeonStorageController(MetricsGetter, LogUtils):
         shards: list[TenantShardId] = body["new_shards"]
         return shards
 
+    def tenant_shard_merge(self, tenant_id: TenantId, shard_count: int) -> list[TenantShardId]:
+        response = self.request(
+            "PUT",
+            f"{self.api}/control/v1/tenant/{tenant_id}/shard_merge",
+            json={"new_shard_count": shard_count},
+            headers=self.headers(TokenScope.ADMIN),
+        )
+        body = response.json()
+        log.info(f"tenant_shard_merge success: {body}")
+        shards: list[TenantShardId] = body["new_shards"]
+        return shards
+
     def tenant_shard_migrate(self, tenant_shard_id: TenantShardId, dest_ps_id: int):
         self.request(
             "PUT",
@@ -2558,6 +2570,32 @@ class NeonStorageController(MetricsGetter, LogUtils):
             headers=self.headers(TokenScope.ADMIN),
         )
 
+    def compute_notifications(self) -> list[dict[str, Any]]:
+        """
+        Compute notifications that failed to deliver and are waiting to be retried
+        """
+        response = self.request(
+            "GET",
+            f"{self.api}/control/v1/compute_notifications",
+            headers=self.headers(TokenScope.ADMIN),
+        )
+        pending: list[dict[str, Any]] = response.json()
+        return pending
+
+    def history(self, **params: Any) -> list[dict[str, Any]]:
+        """
+        Operation history, most recent first.  `params` are the filters of the history API:
+        tenant_id, node_id, kind, since, until and limit.
+        """
+        response = self.request(
+            "GET",
+            f"{self.api}/control/v1/history",
+            params={k: str(v) for k, v in params.items() if v is not None},
+            headers=self.headers(TokenScope.ADMIN),
+        )
+        events: list[dict[str, Any]] = response.json()
+        return events
+
     def tenant_import(self, tenant_id: TenantId):
         self.request(
             "POST",
@@ -2565,6 +2603,34 @@ class NeonStorageController(MetricsGetter, LogUtils):
             headers=self.headers(TokenScope.ADMIN),
         )
 
+    def tenant_import_create(self, body: dict[str, Any]) -> dict[str, Any]:
+        log.info(f"tenant_import_create({body})")
+        response = self.request(
+            "POST",
+            f"{self.api}/control/v1/import",
+            json=body,
+            headers=self.headers(TokenScope.ADMIN),
+        )
+        return response.json()
+
+    def tenant_import_get(self, tenant_id: TenantId) -> dict[str, Any]:
+        response = self.request(
+            "GET",
+            f"{self.api}/control/v1/import/{tenant_id}",
+            headers=self.headers(TokenScope.ADMIN),
+        )
+        return response.json()
+
+    def tenant_import_upload(self, tenant_id: TenantId, archive: str, path: str) -> dict[str, Any]:
+        with open(path, "rb") as f:
+            response = self.request(
+                "PUT",
+                f"{self.api}/control/v1/import/{tenant_id}/upload/{archive}",
+                data=f,
+                headers=self.headers(TokenScope.ADMIN),
+            )
+        return response.json()
+
     def reconcile_all(self):
         r = self.request(
             "POST",
@@ -20,7 +20,7 @@ from fixtures.pageserver.utils import (
     wait_for_last_record_lsn,
 )
 from fixtures.remote_storage import RemoteStorageKind
-from fixtures.utils import assert_pageserver_backups_equal, subprocess_capture
+from fixtures.utils import assert_pageserver_backups_equal, subprocess_capture, wait_until
 
 
 def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_builder):
@@ -152,6 +152,72 @@ def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_build
     vanilla_pg.stop()
 
 
+def test_storage_controller_import_from_vanilla(
+    test_output_dir, pg_bin, vanilla_pg, neon_env_builder: NeonEnvBuilder
+):
+    """
+    Import a vanilla Postgres basebackup into a sharded tenant through the storage controller:
+    the basebackup is read from a local path, and the WAL uploaded to the controller.
+    """
+    vanilla_pg.start()
+    vanilla_pg.safe_psql("create user cloud_admin with password 'postgres' superuser")
+    vanilla_pg.safe_psql(
+        """create table t as select 'long string to consume some space' || g
+     from generate_series(1,300000) g"""
+    )
+    vanilla_pg.safe_psql("CHECKPOINT")
+
+    basebackup_dir = os.path.join(test_output_dir, "basebackup")
+    os.mkdir(basebackup_dir)
+    pg_bin.run(["pg_basebackup", "-F", "tar", "-d", vanilla_pg.connstr(), "-D", basebackup_dir])
+    with open(os.path.join(basebackup_dir, "backup_manifest")) as f:
+        manifest = json.load(f)
+        start_lsn = manifest["WAL-Ranges"][0]["Start-LSN"]
+        end_lsn = manifest["WAL-Ranges"][0]["End-LSN"]
+    vanilla_pg.stop()
+
+    neon_env_builder.num_pageservers = 2
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    env = neon_env_builder.init_start()
+
+    tenant = TenantId.generate()
+    timeline = TimelineId.generate()
+    status = env.storage_controller.tenant_import_create(
+        {
+            "tenant_id": str(tenant),
+            "timeline_id": str(timeline),
+            "shard_parameters": {"count": 2, "stripe_size": 1024},
+            "pg_version": int(env.pg_version),
+            "base_lsn": start_lsn,
+            "end_lsn": end_lsn,
+            "basebackup": {
+                "type": "local_path",
+                "path": os.path.join(basebackup_dir, "base.tar"),
+            },
+            "wal": {"type": "upload"},
+        }
+    )
+    assert status["state"] == "awaiting_upload"
+    assert [s["step"] for s in status["shards"]] == ["pending", "pending"]
+
+    env.storage_controller.tenant_import_upload(
+        tenant, "wal", os.path.join(basebackup_dir, "pg_wal.tar")
+    )
+
+    def import_complete():
+        status = env.storage_controller.tenant_import_get(tenant)
+        assert status["state"] == "complete", status
+
+    wait_until(60, 1, import_complete)
+
+    status = env.storage_controller.tenant_import_get(tenant)
+    assert all(s["step"] == "done" and s["bytes_sent"] > 0 for s in status["shards"])
+
+    env.neon_cli.map_branch("imported", tenant, timeline)
+    endpoint = env.endpoints.create_start("imported", tenant_id=tenant)
+    assert endpoint.safe_psql("select count(*) from t") == [(300000,)]
+
+
 def test_import_from_pageserver_small(
     pg_bin: PgBin, neon_env_builder: NeonEnvBuilder, test_output_dir: Path
 ):
@@ -0,0 +1,132 @@
+import threading
+from pathlib import Path
+
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.utils import query_scalar
+
+
+def test_lfc_prewarm(neon_simple_env: NeonEnv):
+    """
+    Check that the local file cache can be saved with get_local_cache_state() and
+    prewarmed with prewarm_local_cache() after restart, which compute_ctl does.
+    """
+    env = neon_simple_env
+
+    cache_dir = Path(env.repo_dir) / "file_cache"
+    cache_dir.mkdir(exist_ok=True)
+
+    config_lines = [
+        "shared_buffers='1MB'",
+        f"neon.file_cache_path='{cache_dir}/file.cache'",
+        "neon.max_file_cache_size='128MB'",
+        "neon.file_cache_size_limit='64MB'",
+    ]
+    endpoint = env.endpoints.create_start("main", config_lines=config_lines)
+
+    cur = endpoint.connect().cursor()
+    cur.execute("create extension neon")
+    cur.execute("create table t(id int, payload text)")
+    cur.execute("insert into t select g, repeat('x', 100) from generate_series(1, 100000) g")
+    cur.execute("select count(*) from t")
+
+    cur.execute(
+        "select reltablespace, reldatabase, relfilenode, relforknumber, relblocknumber, nblocks "
+        "from get_local_cache_state()"
+    )
+    state = cur.fetchall()
+    saved_pages = sum(run[5] for run in state)
+    log.info(f"saved {len(state)} runs of {saved_pages} pages")
+    assert saved_pages > 0
+
+    # At most as many chunks as asked for
+    chunks = query_scalar(
+        cur,
+        "select count(distinct (relfilenode, relblocknumber / 128)) from get_local_cache_state(1)",
+    )
+    assert chunks == 1
+
+    endpoint.stop()
+    endpoint.start()
+
+    cur = endpoint.connect().cursor()
+    cached_before = query_scalar(cur, "select count(*) from local_cache")
+    log.info(f"{cached_before} pages cached after restart")
+
+    fetched = 0
+    for run in state:
+        cur.execute("select prewarm_local_cache(%s, %s, %s, %s, %s, %s)", run)
+        fetched += cur.fetchone()[0]
+    log.info(f"prewarm fetched {fetched} pages")
+    assert fetched > 0
+    assert query_scalar(cur, "select count(*) from local_cache") >= cached_before + fetched
+
+    # The pages are cached now, so another round fetches nothing
+    for run in state:
+        cur.execute("select prewarm_local_cache(%s, %s, %s, %s, %s, %s)", run)
+        assert cur.fetchone()[0] == 0
+
+
+def test_lfc_prewarm_concurrent_updates(neon_simple_env: NeonEnv):
+    """
+    Check that prewarming the local file cache while the same relation is being
+    updated doesn't overwrite newer versions of its pages in the cache with older
+    ones fetched from the page server.
+    """
+    env = neon_simple_env
+
+    cache_dir = Path(env.repo_dir) / "file_cache"
+    cache_dir.mkdir(exist_ok=True)
+
+    config_lines = [
+        "shared_buffers='1MB'",
+        f"neon.file_cache_path='{cache_dir}/file.cache'",
+        "neon.max_file_cache_size='128MB'",
+        "neon.file_cache_size_limit='64MB'",
+    ]
+    endpoint = env.endpoints.create_start("main", config_lines=config_lines)
+
+    cur = endpoint.connect().cursor()
+    cur.execute("create extension neon")
+    cur.execute("create table t(id int, n int, payload text) with (fillfactor=50)")
+    cur.execute("insert into t select g, 0, repeat('x', 100) from generate_series(1, 50000) g")
+    cur.execute("select count(*) from t")
+
+    cur.execute(
+        "select reltablespace, reldatabase, relfilenode, relforknumber, relblocknumber, nblocks "
+        "from get_local_cache_state()"
+    )
+    state = cur.fetchall()
+    assert len(state) > 0
+
+    endpoint.stop()
+    endpoint.start()
+
+    rounds = 5
+    updated = threading.Event()
+
+    def update():
+        conn = endpoint.connect()
+        conn.autocommit = True
+        ucur = conn.cursor()
+        try:
+            for _ in range(rounds):
+                ucur.execute("update t set n = n + 1")
+        finally:
+            updated.set()
+
+    thread = threading.Thread(target=update, daemon=True)
+    thread.start()
+
+    cur = endpoint.connect().cursor()
+    prewarms = 0
+    while not updated.is_set():
+        for run in state:
+            cur.execute("select prewarm_local_cache(%s, %s, %s, %s, %s, %s)", run)
+        prewarms += 1
+    thread.join()
+    log.info(f"prewarmed {prewarms} times while updating")
+
+    # With tiny shared buffers, most of these pages are read back from the cache
+    assert query_scalar(cur, f"select count(*) from t where n <> {rounds}") == 0
+    assert query_scalar(cur, "select sum(n) from t") == rounds * 50000
@@ -387,6 +387,44 @@ def test_restart_endpoint(neon_simple_env: NeonEnv, vanilla_pg):
     ) == endpoint.safe_psql("select sum(somedata) from replication_example")
 
 
+def test_logical_slots_persisted(neon_simple_env: NeonEnv):
+    """
+    Check that compute_ctl reports the logical replication slots of a primary in /status
+    after a restart, and that they are usable.
+    """
+    env = neon_simple_env
+
+    timeline_id = env.neon_cli.create_branch("test_logical_slots_persisted")
+    endpoint = env.endpoints.create_start("test_logical_slots_persisted")
+    endpoint.safe_psql("select pg_create_logical_replication_slot('persisted_slot', 'pgoutput')")
+
+    # Let compute_ctl write its record of the slot
+    time.sleep(12)
+    wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, timeline_id)
+    endpoint.stop().start()
+
+    slots = endpoint.http_client().status()["logical_slots"]
+    assert [slot["name"] for slot in slots] == ["persisted_slot"]
+    assert slots[0]["plugin"] == "pgoutput"
+    assert slots[0]["error"] is None
+
+    endpoint.safe_psql("create table t(pk integer primary key)")
+    advanced_lsn = endpoint.safe_psql(
+        "select end_lsn from pg_replication_slot_advance('persisted_slot', "
+        "pg_current_wal_insert_lsn())"
+    )[0][0]
+
+    # Postgres only saves the slot's state file at checkpoints, so after a crash the one that
+    # comes with the basebackup is older than compute_ctl's record: the slot must come back at the
+    # recorded position.
+    time.sleep(12)
+    wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, timeline_id)
+    endpoint.stop(mode="immediate").start()
+    assert endpoint.safe_psql(
+        "select confirmed_flush_lsn, restart_lsn <= confirmed_flush_lsn from pg_replication_slots"
+    ) == [(advanced_lsn, True)]
+
+
 # Test that WAL redo works for fairly large records.
 #
 # See https://github.com/neondatabase/neon/pull/6534. That wasn't a
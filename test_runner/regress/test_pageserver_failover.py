@@ -0,0 +1,88 @@
+from fixtures.compute_reconfigure import ComputeReconfigure
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnvBuilder, flush_ep_to_pageserver
+from fixtures.utils import query_scalar, wait_until
+
+
+def test_pageserver_failover(
+    neon_env_builder: NeonEnvBuilder, compute_reconfigure_listener: ComputeReconfigure
+):
+    """
+    Check that compute_ctl switches to another pageserver on its own when the one it uses
+    is killed, once the storage controller has moved the tenant there.
+    """
+    neon_env_builder.num_pageservers = 2
+    # The storage controller's notifications go to the listener, which ignores them, so
+    # only compute_ctl can reconfigure the endpoint.
+    neon_env_builder.control_plane_compute_hook_api = (
+        compute_reconfigure_listener.control_plane_compute_hook_api
+    )
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    env.storage_controller.tenant_policy_update(tenant_id, {"placement": {"Attached": 1}})
+    env.storage_controller.reconcile_until_idle()
+
+    endpoint = env.endpoints.create("main", config_lines=["shared_buffers='1MB'"])
+    endpoint.respec(pageserver_failover=True)
+    endpoint.start()
+
+    cur = endpoint.connect().cursor()
+    cur.execute("create schema neon")
+    cur.execute("create extension neon with schema neon")
+    cur.execute("create table t(id int, payload text)")
+    cur.execute("insert into t select g, repeat('x', 100) from generate_series(1, 100000) g")
+
+    attached_id = env.storage_controller.locate(tenant_id)[0]["node_id"]
+    attached = env.get_pageserver(attached_id)
+    flush_ep_to_pageserver(env, endpoint, tenant_id, timeline_id, attached_id)
+    attached.http_client().timeline_checkpoint(tenant_id, timeline_id, wait_until_uploaded=True)
+    attached.http_client().tenant_heatmap_upload(tenant_id)
+    for ps in env.pageservers:
+        if ps.id != attached_id:
+            ps.http_client().tenant_secondary_download(tenant_id)
+
+    env.storage_controller.allowed_errors.extend(
+        [".*Call to node.*management API.*failed.*", ".*Heartbeat round.*"]
+    )
+    attached.stop(immediate=True)
+
+    def moved():
+        node_id = env.storage_controller.locate(tenant_id)[0]["node_id"]
+        assert node_id != attached_id
+        return node_id
+
+    new_id = wait_until(30, 1, moved)
+    log.info(f"tenant moved from pageserver {attached_id} to {new_id}")
+
+    # Reading the table needs the pageserver, since it doesn't fit in shared buffers. The
+    # backend keeps retrying the dead one until compute_ctl switches to the new one.
+    assert query_scalar(cur, "select count(*) from t") == 100000
+
+    new_port = env.get_pageserver(new_id).service_port.pg
+    assert f":{new_port}" in query_scalar(cur, "show neon.pageserver_connstring")
+    assert endpoint.http_client().metrics_json()["pageserver_failovers"] == 1
+    assert endpoint.http_client().status()["status"] == "running"
+
+
+def test_tenant_wake_on_start(neon_env_builder: NeonEnvBuilder):
+    """
+    Check that compute_ctl asks the storage controller to wake the tenant when the compute
+    starts, and connects to the pageserver it is attached to rather than the one in the spec.
+    """
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+
+    attached_id = env.storage_controller.locate(tenant_id)[0]["node_id"]
+    other_id = next(ps.id for ps in env.pageservers if ps.id != attached_id)
+
+    endpoint = env.endpoints.create("main")
+    endpoint.respec(tenant_wake=True)
+    # Without the wake, getting the basebackup from this pageserver would fail
+    endpoint.start(pageserver_id=other_id)
+
+    attached_port = env.get_pageserver(attached_id).service_port.pg
+    assert f":{attached_port}" in endpoint.safe_psql("show neon.pageserver_connstring")[0][0]
+    assert endpoint.safe_psql("select 1")[0][0] == 1
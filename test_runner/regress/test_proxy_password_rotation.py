@@ -0,0 +1,68 @@
+import base64
+import hashlib
+import hmac
+import os
+from datetime import datetime, timedelta, timezone
+
+import psycopg2
+import pytest
+from fixtures.metrics import parse_metrics
+from fixtures.neon_fixtures import NeonProxy, VanillaPostgres
+
+
+def scram_secret(password: str, salt: bytes, iterations: int = 4096) -> str:
+    salted_password = hashlib.pbkdf2_hmac("sha256", password.encode(), salt, iterations)
+    client_key = hmac.new(salted_password, b"Client Key", "sha256").digest()
+    server_key = hmac.new(salted_password, b"Server Key", "sha256").digest()
+    stored_key = hashlib.sha256(client_key).digest()
+
+    def b64(b: bytes) -> str:
+        return base64.b64encode(b).decode()
+
+    return f"SCRAM-SHA-256${iterations}:{b64(salt)}${b64(stored_key)}:{b64(server_key)}"
+
+
+def test_proxy_password_rotation(static_proxy: NeonProxy, vanilla_pg: VanillaPostgres):
+    """
+    Check that the proxy accepts both passwords of a role while its password is being
+    rotated, and connects with the current one as the role compute_ctl keeps it in.
+    """
+    # Both secrets share the salt, as the control plane generates them
+    salt = os.urandom(16)
+    current_secret = scram_secret("pencil", salt)
+    next_secret = scram_secret("eraser", salt)
+    overlap_until = datetime.now(timezone.utc) + timedelta(hours=1)
+
+    # What compute_ctl does for a role with a next password
+    vanilla_pg.safe_psql(f"create role alice login password '{next_secret}'")
+    vanilla_pg.safe_psql(
+        f"""create role "alice/previous" login password '{current_secret}' """
+        f"valid until '{overlap_until.isoformat()}' in role alice"
+    )
+    vanilla_pg.safe_psql("""alter role "alice/previous" set role = 'alice'""")
+
+    def whoami(password: str):
+        return static_proxy.safe_psql(
+            "select current_user, session_user", user="alice", password=password
+        )[0]
+
+    assert whoami("pencil") == ("alice", "alice/previous")
+    assert whoami("eraser") == ("alice", "alice")
+    with pytest.raises(psycopg2.Error) as exprinfo:
+        whoami("crayon")
+    assert "password authentication failed for user 'alice'" in str(exprinfo.value)
+
+    metrics = parse_metrics(static_proxy.get_metrics())
+    for secret in ["current", "next"]:
+        sample = metrics.query_one("proxy_password_rotation_auth_total", {"secret": secret})
+        assert sample.value == 1
+
+    # Once the overlap is over, only the next password is accepted
+    overlap_until = datetime.now(timezone.utc) - timedelta(minutes=1)
+    vanilla_pg.safe_psql(
+        f"""alter role "alice/previous" valid until '{overlap_until.isoformat()}'"""
+    )
+    with pytest.raises(psycopg2.Error) as exprinfo:
+        whoami("pencil")
+    assert "password authentication failed for user 'alice'" in str(exprinfo.value)
+    assert whoami("eraser") == ("alice", "alice")
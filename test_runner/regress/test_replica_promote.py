@@ -0,0 +1,63 @@
+import psycopg2
+import pytest
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv, wait_replica_caughtup
+from fixtures.utils import wait_until
+from requests.exceptions import HTTPError
+
+
+def test_replica_promote(neon_simple_env: NeonEnv):
+    """
+    Check that compute_ctl promotes a running replica to a primary in place: it catches up
+    with the primary, fences it on the safekeepers, and the promoted node takes over its
+    writes. The fenced primary doesn't get elected again, but shuts down.
+    """
+    env = neon_simple_env
+
+    primary = env.endpoints.create_start("main", endpoint_id="primary")
+    primary.safe_psql("create table t(id int primary key, payload text)")
+    primary.safe_psql("insert into t select g, 'before' from generate_series(1, 1000) g")
+
+    secondary = env.endpoints.new_replica_start(origin=primary, endpoint_id="secondary")
+    wait_replica_caughtup(primary, secondary)
+    # Not replayed yet, maybe, when the promotion starts
+    primary.safe_psql("insert into t select g, 'before' from generate_series(1001, 2000) g")
+
+    # A primary can't be promoted
+    with pytest.raises(HTTPError, match="412"):
+        primary.http_client().promote()
+
+    status = secondary.http_client().promote()
+    log.info(f"status after promotion: {status}")
+    assert status["promotion"]["state"] == "completed"
+    assert status["promotion"]["target_lsn"] is not None
+    assert secondary.http_client().status()["promotion"]["state"] == "completed"
+
+    # The old primary can't commit anymore: the commit waits for the safekeepers until the
+    # timeout, or its walproposer finds out that it was fenced and takes Postgres down
+    try:
+        primary.safe_psql("insert into t values (3001, 'fenced')", options="-cstatement_timeout=5s")
+    except psycopg2.Error as e:
+        log.info(f"write on the fenced primary failed: {e}")
+
+    def fenced():
+        assert primary.log_contains("it has taken over the timeline")
+
+    wait_until(30, 1, fenced)
+    primary.check_stop_result = False
+    primary.stop(mode="immediate")
+
+    assert secondary.safe_psql("select pg_is_in_recovery()")[0][0] is False
+    assert secondary.safe_psql("select count(*) from t")[0][0] == 2000
+    secondary.safe_psql("insert into t select g, 'after' from generate_series(2001, 3000) g")
+
+    # Promoting again is refused
+    with pytest.raises(HTTPError, match="412"):
+        secondary.http_client().promote()
+
+    # What the promoted node wrote is on the safekeepers and the pageserver, for the next
+    # primary to start with, and what the fenced one wrote is not
+    secondary.stop()
+    primary.start()
+    count = "select count(*), count(*) filter (where payload = 'after') from t"
+    assert primary.safe_psql(count)[0] == (3000, 1000)
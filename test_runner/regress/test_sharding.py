@@ -1509,3 +1509,123 @@ def test_top_tenants(neon_env_builder: NeonEnvBuilder):
     )
     assert len(top["shards"]) == n_tenants - 4
     assert set(i["id"] for i in top["shards"]) == set(str(i[0]) for i in tenants[4:])
+
+
+def test_sharding_merge_smoke(neon_env_builder: NeonEnvBuilder):
+    """
+    Merge a sharded tenant down to fewer shards, and check that its data reads back the same
+    after each merge.
+    """
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start(
+        initial_tenant_shard_count=4, initial_tenant_shard_stripe_size=16
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    workload = Workload(env, tenant_id, timeline_id, branch_name="main")
+    workload.init()
+    workload.write_rows(256)
+    workload.validate()
+
+    env.storage_controller.tenant_shard_merge(tenant_id, shard_count=2)
+    for shard_number in range(0, 2):
+        assert env.storage_controller.inspect(TenantShardId(tenant_id, shard_number, 2)) is not None
+    assert len(env.storage_controller.locate(tenant_id)) == 2
+    workload.validate()
+
+    # The merged shards take writes, and merge further
+    workload.churn_rows(64)
+    workload.validate()
+    env.storage_controller.tenant_shard_merge(tenant_id, shard_count=1)
+    assert len(env.storage_controller.locate(tenant_id)) == 1
+    workload.validate()
+
+    # Nothing is left of the sources on the pageservers
+    for ps in env.pageservers:
+        for loc in ps.http_client().tenant_list_locations()["tenant_shards"]:
+            assert TenantShardId.parse(loc[0]).shard_count <= 1
+
+    env.storage_controller.reconcile_until_idle(timeout_secs=30)
+    env.storage_controller.consistency_check()
+
+
+@pytest.mark.parametrize(
+    "failpoint,on_pageserver",
+    [
+        ("shard-merge-post-migrate", False),
+        ("shard-merge-post-begin", False),
+        ("shard-merge-post-remote", False),
+        ("shard-merge-timeline", True),
+        ("shard-merge-pre-activate", True),
+    ],
+)
+def test_sharding_merge_failures(
+    neon_env_builder: NeonEnvBuilder, failpoint: str, on_pageserver: bool
+):
+    """
+    Fail a merge at each of its steps, and check that it's rolled back: the sources are
+    attached again with their data, the merged shards are gone, and merging again works.
+    """
+    initial_shard_count = 4
+    merged_shard_count = 2
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start(
+        initial_tenant_shard_count=initial_shard_count, initial_tenant_shard_stripe_size=16
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    env.storage_controller.allowed_errors.extend(
+        [
+            ".*Enqueuing background abort.*",
+            ".*failpoint.*",
+            ".*Failed to merge into.*",
+        ]
+    )
+    for ps in env.pageservers:
+        ps.allowed_errors.append(".*failpoint.*")
+
+    workload = Workload(env, tenant_id, timeline_id, branch_name="main")
+    workload.init()
+    workload.write_rows(256)
+
+    if on_pageserver:
+        for ps in env.pageservers:
+            ps.http_client().configure_failpoints((failpoint, "return(1)"))
+    else:
+        env.storage_controller.configure_failpoints((failpoint, "return(1)"))
+
+    with pytest.raises(StorageControllerApiException):
+        env.storage_controller.tenant_shard_merge(tenant_id, shard_count=merged_shard_count)
+
+    if on_pageserver:
+        for ps in env.pageservers:
+            ps.http_client().configure_failpoints((failpoint, "off"))
+    else:
+        env.storage_controller.configure_failpoints((failpoint, "off"))
+
+    def assert_shard_count(shard_count: int):
+        assert len(env.storage_controller.locate(tenant_id)) == shard_count
+        attached = 0
+        for ps in env.pageservers:
+            for loc in ps.http_client().tenant_list_locations()["tenant_shards"]:
+                tenant_shard_id = TenantShardId.parse(loc[0])
+                log.info(f"Shard {tenant_shard_id} seen on node {ps.id} in mode {loc[1]['mode']}")
+                assert tenant_shard_id.shard_count == shard_count
+                if loc[1]["mode"] != "Secondary":
+                    attached += 1
+        assert attached == shard_count
+
+    # The abort runs in the background
+    wait_until(30, 1, lambda: assert_shard_count(initial_shard_count))
+    workload.churn_rows(16)
+    workload.validate()
+
+    # Having rolled back, the merge can be done again
+    env.storage_controller.tenant_shard_merge(tenant_id, shard_count=merged_shard_count)
+    env.storage_controller.reconcile_until_idle(timeout_secs=30)
+    assert_shard_count(merged_shard_count)
+    workload.validate()
+
+    env.storage_controller.consistency_check()
@@ -1,5 +1,7 @@
 import concurrent.futures
 import json
+import os
+import signal
 import threading
 import time
 from collections import defaultdict
@@ -7,6 +9,7 @@ from datetime import datetime, timezone
 from typing import Any, Dict, List, Optional, Set, Tuple, Union
 
 import pytest
+import toml
 from fixtures.common_types import TenantId, TenantShardId, TimelineId
 from fixtures.compute_reconfigure import ComputeReconfigure
 from fixtures.log_helper import log
@@ -566,6 +569,107 @@ def test_storage_controller_compute_hook(
     env.storage_controller.consistency_check()
 
 
+def test_storage_controller_compute_hook_durable(
+    httpserver: HTTPServer,
+    neon_env_builder: NeonEnvBuilder,
+    httpserver_listen_address,
+):
+    """
+    Test that compute notifications that fail to deliver are persisted, retried after a restart of
+    the storage controller and removed once delivered, and that notifications rejected as invalid
+    are not kept for retrying.
+    """
+    neon_env_builder.num_pageservers = 2
+    (host, port) = httpserver_listen_address
+    neon_env_builder.control_plane_compute_hook_api = f"http://{host}:{port}/notify"
+
+    notifications = []
+    handle_params = {"status": 200}
+
+    def handler(request: Request):
+        status = handle_params["status"]
+        log.info(f"Notify request[{status}]: {request}")
+        notifications.append((status, request.json))
+        return Response(status=status)
+
+    httpserver.expect_request("/notify", method="PUT").respond_with_handler(handler)
+
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    tenant_shard_id = TenantShardId(tenant_id, 0, 0)
+    env.storage_controller.allowed_errors.extend(
+        [
+            ".*Failed to notify compute of attached pageserver.*",
+            ".*Reconcile error.*",
+            ".*Dropping compute notification that can't be delivered.*",
+        ]
+    )
+
+    def attached_node() -> int:
+        node_id: int = env.storage_controller.locate(tenant_id)[0]["node_id"]
+        return node_id
+
+    def other_node(node_id: int) -> int:
+        return next(ps.id for ps in env.pageservers if ps.id != node_id)
+
+    assert env.storage_controller.compute_notifications() == []
+
+    # The control plane is unavailable: the notification of a migration is persisted
+    handle_params["status"] = 503
+    dest = other_node(attached_node())
+    env.storage_controller.tenant_shard_migrate(tenant_shard_id, dest)
+
+    def pending() -> dict[str, Any]:
+        pending = env.storage_controller.compute_notifications()
+        assert len(pending) == 1
+        assert pending[0]["tenant_id"] == str(tenant_id)
+        return pending[0]
+
+    before_restart = wait_until(20, 0.5, pending)
+    assert "unavailable" in before_restart["last_error"]
+
+    # After a restart, the new instance finds the notification in the database and keeps
+    # retrying it
+    env.storage_controller.stop()
+    env.storage_controller.start()
+
+    def retried_after_restart():
+        assert pending()["attempts"] > before_restart["attempts"]
+
+    wait_until(30, 1, retried_after_restart)
+    assert pending()["first_failed_at"] == before_restart["first_failed_at"]
+
+    # Once the control plane is back, the notification is delivered and no longer pending
+    handle_params["status"] = 200
+
+    def delivered():
+        assert env.storage_controller.compute_notifications() == []
+        (status, body) = notifications[-1]
+        assert status == 200
+        assert body["shards"] == [{"node_id": dest, "shard_number": 0}]
+
+    wait_until(30, 1, delivered)
+
+    # A notification that the control plane rejects as invalid will never be delivered, so it
+    # isn't kept for retrying
+    handle_params["status"] = 400
+    notified = len(notifications)
+    dest = other_node(dest)
+    env.storage_controller.tenant_shard_migrate(tenant_shard_id, dest)
+
+    def rejected():
+        assert any(status == 400 for (status, _) in notifications[notified:])
+
+    wait_until(20, 0.5, rejected)
+    # Give a persisted notification time to show up, were it persisted
+    time.sleep(2)
+    assert env.storage_controller.compute_notifications() == []
+
+    handle_params["status"] = 200
+    env.storage_controller.reconcile_until_idle()
+    env.storage_controller.consistency_check()
+
+
 def test_storage_controller_debug_apis(neon_env_builder: NeonEnvBuilder):
     """
     Verify that occasional-use debug APIs work as expected.  This is a lightweight test
@@ -1414,11 +1518,87 @@ def test_storcon_cli(neon_env_builder: NeonEnvBuilder):
         ]
     )
 
+    # The changes above show up in the operation history
+    history_lines = storcon_cli(
+        ["history", "--tenant-id", str(env.initial_tenant), "--kind", "api", "--since", "1h"]
+    )
+    assert any("/policy" in line for line in history_lines)
+    assert not any("/node/" in line for line in history_lines)
+
     # Quiesce any background reconciliation before doing consistency check
     env.storage_controller.reconcile_until_idle(timeout_secs=10)
     env.storage_controller.consistency_check()
 
 
+def test_storage_controller_history(neon_env_builder: NeonEnvBuilder):
+    """
+    Check that mutating API calls are recorded in the operation history, that the history API's
+    filters select the right events, and that events older than the retention period are removed.
+    """
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+
+    env.storage_controller.allowed_errors.extend(
+        [".*Skipping reconcile for policy.*", ".*Scheduling is disabled by policy.*"]
+    )
+
+    def api_events(**params: Any) -> list[dict[str, Any]]:
+        return env.storage_controller.history(kind="api", **params)
+
+    before = datetime.now(timezone.utc)
+    env.storage_controller.tenant_policy_update(tenant_id, {"scheduling": "Stop"})
+    env.storage_controller.node_configure(1, {"scheduling": "Pause"})
+    # Reads are not recorded
+    env.storage_controller.tenant_list()
+    env.storage_controller.node_list()
+
+    # Events are written to the database in the background
+    def recorded():
+        assert len(api_events(since=before.isoformat())) == 2
+
+    wait_until(20, 0.5, recorded)
+    after = datetime.now(timezone.utc)
+
+    # Most recent first
+    [node_event, tenant_event] = api_events(since=before.isoformat())
+    assert node_event["actor"].startswith("api")
+    assert node_event["node_id"] == 1
+    assert node_event["tenant_id"] is None
+    assert node_event["detail"].startswith("PUT /control/v1/node/1/config")
+    assert tenant_event["actor"].startswith("api")
+    assert tenant_event["tenant_id"] == str(tenant_id)
+    assert tenant_event["node_id"] is None
+    assert tenant_event["detail"].startswith(f"PUT /control/v1/tenant/{tenant_id}/policy")
+
+    # Filters
+    assert [e["id"] for e in api_events(tenant_id=tenant_id)] == [tenant_event["id"]]
+    assert [e["id"] for e in api_events(node_id=1)] == [node_event["id"]]
+    assert api_events(node_id=2) == []
+    assert [e["id"] for e in api_events(since=before.isoformat(), limit=1)] == [node_event["id"]]
+    assert api_events(until=before.isoformat()) == []
+    assert api_events(since=after.isoformat()) == []
+    assert all(e["kind"] == "node_state" for e in env.storage_controller.history(kind="node_state"))
+    # The pageservers' registration on startup is recorded as a node state change
+    assert len(env.storage_controller.history(kind="node_state", until=before.isoformat())) > 0
+
+    # Restarting with a short retention removes everything recorded so far: expired events are
+    # removed as soon as the controller starts, and then periodically.
+    env.storage_controller.stop()
+    config_path = env.repo_dir / "config"
+    config = toml.loads(config_path.read_text())
+    config.setdefault("storage_controller", {})["history_retention"] = "1s"
+    config_path.write_text(toml.dumps(config))
+    time.sleep(2)
+    env.storage_controller.start()
+    cutoff = datetime.now(timezone.utc)
+
+    def trimmed():
+        assert env.storage_controller.history(until=cutoff.isoformat()) == []
+
+    wait_until(20, 0.5, trimmed)
+
+
 def test_lock_time_tracing(neon_env_builder: NeonEnvBuilder):
     """
     Check that when lock on resource (tenants, nodes) is held for too long it is
@@ -2231,6 +2411,118 @@ def test_storage_controller_leadership_transfer(
         )
 
 
+def test_storage_controller_leader_lease_failover(
+    neon_env_builder: NeonEnvBuilder,
+    storage_controller_proxy: StorageControllerProxy,
+    port_distributor: PortDistributor,
+):
+    """
+    Run three storage controllers that elect their leader with leases rather than through the
+    database, and check that a survivor takes over each time the leader is killed without warning.
+    """
+    neon_env_builder.auth_enabled = True
+    neon_env_builder.num_pageservers = 2
+
+    ports = {instance_id: port_distributor.get_port() for instance_id in range(1, 4)}
+    neon_env_builder.storage_controller_config = {
+        "database_url": f"127.0.0.1:{port_distributor.get_port()}",
+        "leader_lease_peers": [f"127.0.0.1:{port}" for port in ports.values()],
+        "leader_lease_ttl": "3s",
+    }
+    neon_env_builder.storage_controller_port_override = storage_controller_proxy.port()
+
+    env = neon_env_builder.init_configs()
+
+    # Until a majority is up, nobody can acquire the lease
+    for instance_id, port in ports.items():
+        env.storage_controller.start(timeout_in_seconds=30, instance_id=instance_id, base_port=port)
+
+    def lease_holder() -> int:
+        """Find the instance holding the newest live lease, according to the running voters"""
+        leases = []
+        for instance_id, port in ports.items():
+            if not env.storage_controller.instances[instance_id]["running"]:
+                continue
+            lease = env.storage_controller.request(
+                "GET",
+                f"http://127.0.0.1:{port}/control/v1/leader_lease",
+                headers=env.storage_controller.headers(TokenScope.ADMIN),
+            ).json()
+            if lease["live"]:
+                leases.append((lease["term"], lease["holder"]))
+        assert len(leases) > 0
+        _, holder = max(leases)
+        return next(i for i, port in ports.items() if holder == f"http://127.0.0.1:{port}/")
+
+    def wait_for_new_leader(previous: Optional[int]) -> int:
+        def new_lease_holder():
+            holder = lease_holder()
+            assert holder != previous
+            return holder
+
+        leader = wait_until(30, 1, new_lease_holder)
+        storage_controller_proxy.route_to(f"http://127.0.0.1:{ports[leader]}")
+
+        def becomes_leader():
+            assert (
+                env.storage_controller.get_leadership_status()
+                == StorageControllerLeadershipStatus.LEADER
+            )
+
+        wait_until(30, 1, becomes_leader)
+        env.storage_controller.wait_until_ready()
+        leader_record = env.storage_controller.get_leader()
+        assert leader_record["address"] == f"http://127.0.0.1:{ports[leader]}/"
+        return leader
+
+    def kill(instance_id: int):
+        pid_file = env.repo_dir / f"storage_controller_{instance_id}" / "storage_controller.pid"
+        os.kill(int(pid_file.read_text()), signal.SIGKILL)
+        env.storage_controller.instances[instance_id]["running"] = False
+
+    leader = wait_for_new_leader(None)
+
+    env.broker.try_start()
+    for pageserver in env.pageservers:
+        pageserver.start()
+
+    tenant_count = 2
+    shard_count = 2
+    tenants = set(TenantId.generate() for _ in range(0, tenant_count))
+    for tid in tenants:
+        env.storage_controller.tenant_create(
+            tid, shard_count=shard_count, placement_policy={"Attached": 1}
+        )
+    env.storage_controller.reconcile_until_idle()
+
+    def check_tenants():
+        env.storage_controller.consistency_check()
+        for tid in tenants:
+            assert len(env.storage_controller.locate(tid)) == shard_count
+
+    # Kill the leader: one of the other two must take over once its lease expires
+    kill(leader)
+    first_leader = leader
+    leader = wait_for_new_leader(first_leader)
+    check_tenants()
+
+    # Bring the first leader back as a voter, then kill the second one: with the second leader
+    # gone, the lease can only be acquired with the restarted instance's vote.
+    env.storage_controller.start(
+        timeout_in_seconds=30, instance_id=first_leader, base_port=ports[first_leader]
+    )
+    kill(leader)
+    leader = wait_for_new_leader(leader)
+    check_tenants()
+
+    env.storage_controller.allowed_errors.extend(
+        [
+            ".*Leader lease request to peer failed.*",
+            ".*Failed to renew leader lease.*",
+        ]
+    )
+
+
 def test_storage_controller_ps_restarted_during_drain(neon_env_builder: NeonEnvBuilder):
     # single unsharded tenant, two locations
     neon_env_builder.num_pageservers = 2
@@ -2521,6 +2813,60 @@ def eq_safekeeper_records(a: dict[str, Any], b: dict[str, Any]) -> bool:
 
 
 @run_only_on_default_postgres("this is like a 'unit test' against storcon db")
+def test_storage_controller_az_failover_per_pass(neon_env_builder: NeonEnvBuilder):
+    """
+    When all the pageservers in an AZ go offline, the storage controller fails over only a limited
+    number of shards at once, and the rest on later background passes.
+    """
+
+    def assign_az(ps_cfg):
+        ps_cfg["availability_zone"] = "az-a" if ps_cfg["id"] == 1 else "az-b"
+
+    neon_env_builder.pageserver_config_override = assign_az
+    neon_env_builder.num_pageservers = 3
+    per_pass = 2
+    neon_env_builder.storage_controller_config = {"az_failover_per_pass": per_pass}
+    env = neon_env_builder.init_configs()
+    env.start()
+
+    env.storage_controller.allowed_errors.extend(
+        [".*Call to node.*management API.*failed.*", ".*Heartbeat round.*"]
+    )
+
+    # Attach all the tenants in az-a
+    for ps in env.pageservers[1:]:
+        env.storage_controller.node_configure(ps.id, {"scheduling": "Pause"})
+    tenant_ids = [TenantId.generate() for _ in range(3 * per_pass)]
+    for tenant_id in tenant_ids:
+        env.storage_controller.tenant_create(tenant_id, placement_policy={"Attached": 0})
+    for ps in env.pageservers[1:]:
+        env.storage_controller.node_configure(ps.id, {"scheduling": "Active"})
+    env.storage_controller.reconcile_until_idle()
+
+    def attached_in_az_a() -> int:
+        return sum(
+            1
+            for tenant_id in tenant_ids
+            if env.storage_controller.locate(tenant_id)[0]["node_id"] == env.pageservers[0].id
+        )
+
+    assert attached_in_az_a() == len(tenant_ids)
+
+    env.pageservers[0].stop(immediate=True)
+    # Make it unlikely that a heartbeat response still in flight marks the node back online
+    time.sleep(2)
+    env.storage_controller.node_configure(env.pageservers[0].id, {"availability": "Offline"})
+
+    # Only one batch fails over when the AZ goes offline...
+    assert attached_in_az_a() == len(tenant_ids) - per_pass
+
+    # ...and the rest on the following background reconciliation passes
+    def all_failed_over():
+        assert attached_in_az_a() == 0
+
+    wait_until(60, 1, all_failed_over)
+
+
 def test_shard_preferred_azs(neon_env_builder: NeonEnvBuilder):
     def assign_az(ps_cfg):
         az = f"az-{ps_cfg['id']}"
@@ -0,0 +1,95 @@
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.pg_version import PgVersion
+from fixtures.utils import query_scalar
+
+
+def test_suspend_snapshot(neon_simple_env: NeonEnv):
+    """
+    Check that the buffer pool and the relation statistics that compute_ctl captures when
+    the endpoint is suspended are restored when it starts again, and only then.
+    """
+    env = neon_simple_env
+
+    endpoint = env.endpoints.create("main", config_lines=["shared_buffers='32MB'"])
+    endpoint.respec(suspend_snapshot=True)
+    endpoint.start()
+
+    with endpoint.cursor() as cur:
+        cur.execute("create schema neon")
+        cur.execute("create extension neon with schema neon")
+        cur.execute("create extension pg_buffercache")
+        cur.execute("create table t(id int, payload text)")
+        cur.execute("insert into t select g, repeat('x', 100) from generate_series(1, 100000) g")
+        cur.execute("select count(*) from t")
+    # The statistics of a backend are flushed to shared memory at the latest when it exits
+
+    buffers_query = (
+        "select count(*) from pg_buffercache "
+        "where relfilenode = pg_relation_filenode('t') and reldatabase = "
+        "(select oid from pg_database where datname = current_database())"
+    )
+    with endpoint.cursor() as cur:
+        cached_before = query_scalar(cur, buffers_query)
+    log.info(f"{cached_before} pages of t cached before suspend")
+    assert cached_before > 0
+
+    endpoint.stop(mode="suspend")
+    endpoint.start()
+
+    metrics = endpoint.http_client().metrics_json()
+    log.info(f"restore metrics: {metrics}")
+    assert metrics["suspend_restored_pages"] > 0
+    with endpoint.cursor() as cur:
+        assert query_scalar(cur, buffers_query) > 0
+        if env.pg_version != PgVersion.V14:
+            assert metrics["suspend_restored_relations"] > 0
+            assert (
+                query_scalar(cur, "select n_tup_ins from pg_stat_user_tables where relname = 't'")
+                == 100000
+            )
+
+    # The snapshot is gone once restored
+    endpoint.stop()
+    endpoint.start()
+    metrics = endpoint.http_client().metrics_json()
+    assert metrics["suspend_restored_pages"] == 0
+    assert metrics["suspend_restored_relations"] == 0
+
+
+def test_suspend_snapshot_budget(neon_simple_env: NeonEnv):
+    """
+    Check that the restore of the suspend snapshot stops when its budget runs out, and that
+    the snapshot is deleted even then.
+    """
+    env = neon_simple_env
+
+    endpoint = env.endpoints.create("main", config_lines=["shared_buffers='32MB'"])
+    endpoint.respec(suspend_snapshot=True, suspend_restore_budget_ms=1)
+    endpoint.start()
+
+    with endpoint.cursor() as cur:
+        cur.execute("create schema neon")
+        cur.execute("create extension neon with schema neon")
+        cur.execute("create table t(id int, payload text)")
+        cur.execute("insert into t select g, repeat('x', 100) from generate_series(1, 100000) g")
+        cur.execute("select count(*) from t")
+
+    endpoint.stop(mode="suspend")
+    endpoint.start()
+
+    metrics = endpoint.http_client().metrics_json()
+    log.info(f"restore metrics: {metrics}")
+    assert metrics["suspend_restored_pages"] == 0
+    assert metrics["suspend_restored_relations"] == 0
+    assert metrics["suspend_restore_ms"] < 1000
+    # Either between the queries of the restore, or during one of them
+    assert endpoint.log_contains("suspend snapshot restore is out of budget|statement timeout")
+
+    # The snapshot is gone, so a generous budget restores nothing after a restart
+    endpoint.respec(suspend_restore_budget_ms=60000)
+    endpoint.stop()
+    endpoint.start()
+    metrics = endpoint.http_client().metrics_json()
+    assert metrics["suspend_restored_pages"] == 0
+    assert metrics["suspend_restored_relations"] == 0
@@ -0,0 +1,184 @@
+import asyncio
+import json
+import subprocess
+from pathlib import Path
+from typing import Any
+
+import pytest
+import websockets
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.port_distributor import PortDistributor
+from fixtures.utils import query_scalar, wait_until
+
+GiB = 1 << 30
+# vm-monitor's sys_buffer_bytes: the agent reports the memory of the VM, the kernel gets a bit less
+SYS_BUFFER = 100 << 20
+
+
+@pytest.mark.asyncio
+async def test_vm_monitor_settings(
+    neon_simple_env: NeonEnv,
+    neon_binpath: Path,
+    port_distributor: PortDistributor,
+    test_output_dir: Path,
+):
+    """
+    Check that vm-monitor recalculates the memory-dependent settings on scale events: the ones
+    that can be reloaded are applied right away, the others are left pending a restart. The
+    memory limit of the cgroup caps the memory they're calculated with, and they're left as
+    they are when the cgroup uses too much memory to downscale.
+
+    A directory of plain files stands in for the cgroup.
+    """
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+
+    cgroup_dir = test_output_dir / "cgroup"
+    cgroup_dir.mkdir()
+
+    def set_cgroup_file(name: str, contents: str):
+        # Atomically, as vm-monitor polls the files
+        tmp = cgroup_dir / f"{name}.tmp"
+        tmp.write_text(contents)
+        tmp.rename(cgroup_dir / name)
+
+    def set_cgroup_usage(anon: int):
+        set_cgroup_file("memory.stat", f"anon {anon}\nactive_anon {anon}\ninactive_anon 0\n")
+
+    set_cgroup_file("memory.max", "max\n")
+    set_cgroup_usage(64 << 20)
+
+    policy = {
+        "settings": [
+            {"name": "work_mem", "per_gib": 4096, "min": 4096, "max": 1048576},
+            {"name": "max_connections", "per_gib": 50, "min": 25},
+        ]
+    }
+    port = port_distributor.get_port()
+    monitor_log = open(test_output_dir / "vm_monitor.log", "w")
+    monitor = subprocess.Popen(
+        [
+            str(neon_binpath / "vm-monitor"),
+            *["--pgconnstr", endpoint.connstr()],
+            *["--addr", f"127.0.0.1:{port}"],
+            *["--cgroup", str(cgroup_dir.absolute())],
+            *["--memory-scaling", json.dumps(policy)],
+        ],
+        stdout=monitor_log,
+        stderr=monitor_log,
+    )
+
+    def settings():
+        with endpoint.cursor() as cur:
+            cur.execute(
+                "select name, setting, pending_restart from pg_settings "
+                "where name in ('work_mem', 'max_connections')"
+            )
+            return {name: (setting, pending) for name, setting, pending in cur.fetchall()}
+
+    def auto_conf_value(name: str) -> str:
+        with endpoint.cursor() as cur:
+            return query_scalar(
+                cur,
+                "select setting from pg_file_settings "
+                f"where name = '{name}' and sourcefile like '%postgresql.auto.conf'",
+            )
+
+    try:
+
+        async def connect():
+            for _ in range(50):
+                try:
+                    return await websockets.connect(f"ws://127.0.0.1:{port}/monitor")
+                except OSError:
+                    await asyncio.sleep(0.2)
+            raise Exception("vm-monitor did not start")
+
+        ws = await connect()
+        async with ws:
+            await ws.send(json.dumps({"min": 1, "max": 1}))
+            log.info(f"protocol: {await ws.recv()}")
+
+            # Scale up to 4 GiB
+            await ws.send(
+                json.dumps(
+                    {
+                        "type": "UpscaleNotification",
+                        "content": {"granted": {"cpu": 1.0, "mem": 4 * GiB + SYS_BUFFER}},
+                        "id": 2,
+                    }
+                )
+            )
+            reply = json.loads(await ws.recv())
+            assert reply["type"] == "UpscaleConfirmation"
+            assert reply["id"] == 2
+
+            def scaled_up():
+                current = settings()
+                log.info(f"settings after upscale: {current}")
+                assert current["work_mem"] == ("16384", False)
+                assert current["max_connections"][1]
+
+            wait_until(20, 0.5, scaled_up)
+            assert auto_conf_value("max_connections") == "200"
+
+            # The cgroup is limited to 2 GiB, so the settings follow that rather than 4 GiB
+            set_cgroup_file("memory.max", f"{2 * GiB}\n")
+            await ws.send(
+                json.dumps(
+                    {
+                        "type": "UpscaleNotification",
+                        "content": {"granted": {"cpu": 1.0, "mem": 4 * GiB + SYS_BUFFER}},
+                        "id": 4,
+                    }
+                )
+            )
+            reply = json.loads(await ws.recv())
+            assert reply["type"] == "UpscaleConfirmation"
+
+            def limited():
+                assert settings()["work_mem"] == ("8192", False)
+
+            wait_until(20, 0.5, limited)
+            assert auto_conf_value("max_connections") == "100"
+
+            async def downscale(msg_id: int) -> dict[str, Any]:
+                await ws.send(
+                    json.dumps(
+                        {
+                            "type": "DownscaleRequest",
+                            "content": {"target": {"cpu": 0.25, "mem": GiB + SYS_BUFFER}},
+                            "id": msg_id,
+                        }
+                    )
+                )
+                reply = json.loads(await ws.recv())
+                log.info(f"downscale result: {reply}")
+                assert reply["type"] == "DownscaleResult"
+                return reply
+
+            # The cgroup uses too much memory to go down to 1 GiB, so the downscale is denied
+            # and the settings stay
+            set_cgroup_usage(512 << 20)
+            # Let vm-monitor average a few samples of the new usage
+            await asyncio.sleep(1)
+            reply = await downscale(6)
+            assert not reply["Ok"], reply["Status"]
+            assert settings()["work_mem"] == ("8192", False)
+            assert auto_conf_value("max_connections") == "100"
+
+            # Once it uses less, the downscale is approved
+            set_cgroup_usage(64 << 20)
+            await asyncio.sleep(1)
+            reply = await downscale(8)
+            assert reply["Ok"], reply["Status"]
+
+            def scaled_down():
+                assert settings()["work_mem"] == ("4096", False)
+
+            wait_until(20, 0.5, scaled_down)
+            assert auto_conf_value("max_connections") == "50"
+    finally:
+        monitor.terminate()
+        monitor.wait(timeout=10)